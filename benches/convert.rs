@@ -0,0 +1,202 @@
+//! Throughput of the main parsers and of the whole conversion, on synthetic
+//! image-heavy, cloud-heavy and TF-heavy bags generated in a temp directory.
+//! Each group reports messages/s and MB/s of bag payload.
+//!
+//! Run with `cargo bench --features test-bags --bench convert`. To guard a
+//! redesign, save a baseline before it and compare against it after:
+//!
+//! ```bash
+//! cargo bench --features test-bags --bench convert -- --save-baseline main
+//! cargo bench --features test-bags --bench convert -- --baseline main
+//! ```
+
+use bag2rrd::mappings::images::{decode_image, ImageOptions};
+use bag2rrd::mappings::pointcloud::parse_pointcloud2;
+use bag2rrd::mappings::tf::parse_tf_message;
+use bag2rrd::test_bag::{write_bag, TestConnection, TestMessage};
+use bag2rrd::{convert_bag, ConvertOptions};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::path::{Path, PathBuf};
+
+/// Messages of one synthetic bag
+const MESSAGES: usize = 200;
+/// Messages per chunk, about what `rosbag record` writes for these sizes
+const CHUNK: usize = 20;
+
+fn push_str(data: &mut Vec<u8>, s: &str) {
+    data.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    data.extend_from_slice(s.as_bytes());
+}
+
+fn push_header(data: &mut Vec<u8>, stamp: f64, frame: &str) {
+    data.extend_from_slice(&0u32.to_le_bytes()); // seq
+    data.extend_from_slice(&(stamp.trunc() as u32).to_le_bytes());
+    data.extend_from_slice(&((stamp.fract() * 1e9).round() as u32).to_le_bytes());
+    push_str(data, frame);
+}
+
+/// 640x480 rgb8 sensor_msgs/Image
+fn image_payload(stamp: f64, seed: u8) -> Vec<u8> {
+    let (width, height) = (640u32, 480u32);
+    let mut data = Vec::new();
+    push_header(&mut data, stamp, "camera");
+    data.extend_from_slice(&height.to_le_bytes());
+    data.extend_from_slice(&width.to_le_bytes());
+    push_str(&mut data, "rgb8");
+    data.push(0); // is_bigendian
+    data.extend_from_slice(&(width * 3).to_le_bytes()); // step
+    let len = (width * height * 3) as usize;
+    data.extend_from_slice(&(len as u32).to_le_bytes());
+    data.extend((0..len).map(|i| (i as u8).wrapping_add(seed)));
+    data
+}
+
+/// sensor_msgs/PointCloud2 of `points` x, y, z, intensity float32 points
+fn cloud_payload(stamp: f64, points: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    push_header(&mut data, stamp, "lidar");
+    data.extend_from_slice(&1u32.to_le_bytes()); // height
+    data.extend_from_slice(&(points as u32).to_le_bytes()); // width
+    data.extend_from_slice(&4u32.to_le_bytes());
+    for (i, name) in ["x", "y", "z", "intensity"].iter().enumerate() {
+        push_str(&mut data, name);
+        data.extend_from_slice(&(4 * i as u32).to_le_bytes());
+        data.push(7); // FLOAT32
+        data.extend_from_slice(&1u32.to_le_bytes());
+    }
+    data.push(0); // is_bigendian
+    data.extend_from_slice(&16u32.to_le_bytes()); // point_step
+    data.extend_from_slice(&(16 * points as u32).to_le_bytes()); // row_step
+    data.extend_from_slice(&((16 * points) as u32).to_le_bytes());
+    for i in 0..points {
+        let angle = i as f32 * 0.01;
+        for v in [angle.cos() * 10.0, angle.sin() * 10.0, (i % 32) as f32 * 0.1, (i % 256) as f32] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+    data.push(1); // is_dense
+    data
+}
+
+/// tf2_msgs/TFMessage with a chain of `count` links moving along x
+fn tf_payload(stamp: f64, count: usize) -> Vec<u8> {
+    let mut data = (count as u32).to_le_bytes().to_vec();
+    for i in 0..count {
+        let parent = if i == 0 { "odom".to_string() } else { format!("link_{i}") };
+        push_header(&mut data, stamp, &parent);
+        push_str(&mut data, &format!("link_{}", i + 1));
+        for v in [stamp.fract(), 0.0, 0.1, 0.0, 0.0, 0.0, 1.0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+    data
+}
+
+/// A synthetic bag: its path, message count and payload bytes
+struct Workload {
+    name: &'static str,
+    bag: PathBuf,
+    messages: u64,
+    bytes: u64,
+    sample: Vec<u8>,
+    /// The mapper's parser for one message of the bag
+    parse: fn(&[u8]),
+}
+
+fn workload(
+    dir: &Path,
+    name: &'static str,
+    (topic, tp): (&'static str, &'static str),
+    payload: impl Fn(f64, usize) -> Vec<u8>,
+    parse: fn(&[u8]),
+) -> Workload {
+    let connections = [TestConnection { id: 0, topic, tp, latching: false }];
+    let chunks: Vec<Vec<TestMessage>> = (0..MESSAGES / CHUNK)
+        .map(|chunk| {
+            (0..CHUNK)
+                .map(|i| {
+                    let index = chunk * CHUNK + i;
+                    let stamp = 1_700_000_000.0 + index as f64 * 0.05;
+                    TestMessage::new(0, stamp, payload(stamp, index))
+                })
+                .collect()
+        })
+        .collect();
+    let bag = dir.join(format!("{name}.bag"));
+    write_bag(&bag, &connections, &chunks);
+    let bytes = chunks.iter().flatten().map(|m| m.data.len() as u64).sum();
+    Workload { name, bag, messages: MESSAGES as u64, bytes, sample: chunks[0][0].data.clone(), parse }
+}
+
+fn workloads(dir: &Path) -> Vec<Workload> {
+    vec![
+        workload(
+            dir,
+            "images",
+            ("/camera/image_raw", "sensor_msgs/Image"),
+            |stamp, i| image_payload(stamp, i as u8),
+            |payload| {
+                black_box(decode_image(payload, &ImageOptions::default()).unwrap());
+            },
+        ),
+        workload(
+            dir,
+            "clouds",
+            ("/lidar/points", "sensor_msgs/PointCloud2"),
+            |stamp, _| cloud_payload(stamp, 32_768),
+            |payload| {
+                black_box(parse_pointcloud2(payload, None).unwrap());
+            },
+        ),
+        workload(
+            dir,
+            "tf",
+            ("/tf", "tf2_msgs/TFMessage"),
+            |stamp, _| tf_payload(stamp, 32),
+            |payload| {
+                black_box(parse_tf_message(payload).unwrap());
+            },
+        ),
+    ]
+}
+
+fn bench_parsers(c: &mut Criterion, workloads: &[Workload]) {
+    let mut group = c.benchmark_group("parse_message");
+    for w in workloads {
+        group.throughput(Throughput::Bytes(w.sample.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(w.name), &w.sample, |b, payload| {
+            b.iter(|| (w.parse)(black_box(payload)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_convert(c: &mut Criterion, dir: &Path, workloads: &[Workload]) {
+    // The same bags, counted once in messages and once in payload bytes
+    for unit in ["messages", "bytes"] {
+        let mut group = c.benchmark_group(format!("convert_{unit}"));
+        group.sample_size(10);
+        for w in workloads {
+            group.throughput(match unit {
+                "messages" => Throughput::Elements(w.messages),
+                _ => Throughput::Bytes(w.bytes),
+            });
+            let out = dir.join(format!("{}.rrd", w.name));
+            let options = ConvertOptions::new(w.bag.to_str().unwrap(), out.to_str().unwrap()).show_progress(false);
+            group.bench_function(w.name, |b| b.iter(|| convert_bag(&options).unwrap()));
+        }
+        group.finish();
+    }
+}
+
+fn benches(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("bag2rrd_bench_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let workloads = workloads(&dir);
+    bench_parsers(c, &workloads);
+    bench_convert(c, &dir, &workloads);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+criterion_group!(convert, benches);
+criterion_main!(convert);
@@ -0,0 +1,98 @@
+//! Message parsing cost: owned vs borrowed TF decoding, TF ingestion into the
+//! interned frame graph, and LaserScan decoding with and without a reused
+//! scratch buffer.
+//!
+//! Run with `cargo bench --bench parse`.
+
+use bag2rrd::mappings::laserscan::parse_laserscan_msg;
+use bag2rrd::mappings::rename::RenameRules;
+use bag2rrd::mappings::tf::{parse_tf_message, TfMessageReader};
+use bag2rrd::ros_codec::Cursor;
+use bag2rrd::TfGraph;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+fn push_str(data: &mut Vec<u8>, s: &str) {
+    data.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    data.extend_from_slice(s.as_bytes());
+}
+
+/// Serialized tf2_msgs/TFMessage with a chain of `count` transforms
+fn tf_payload(count: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&(count as u32).to_le_bytes());
+    for i in 0..count {
+        data.extend_from_slice(&0u32.to_le_bytes()); // seq
+        data.extend_from_slice(&1_700_000_000u32.to_le_bytes());
+        data.extend_from_slice(&250_000_000u32.to_le_bytes());
+        push_str(&mut data, &format!("robot/link_{i}"));
+        push_str(&mut data, &format!("robot/link_{}", i + 1));
+        for v in [0.1, 0.0, 0.2, 0.0, 0.0, 0.0, 1.0f64] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+    data
+}
+
+/// Serialized sensor_msgs/LaserScan with `beams` ranges and intensities
+fn scan_payload(beams: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&[0u8; 12]); // seq, stamp
+    push_str(&mut data, "laser");
+    let increment = std::f32::consts::TAU / beams as f32;
+    for v in [-std::f32::consts::PI, std::f32::consts::PI, increment, 0.0, 0.1, 0.05, 30.0] {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    for _ in 0..2 {
+        data.extend_from_slice(&(beams as u32).to_le_bytes());
+        for i in 0..beams {
+            data.extend_from_slice(&(1.0 + (i % 100) as f32 * 0.1).to_le_bytes());
+        }
+    }
+    data
+}
+
+fn bench_tf(c: &mut Criterion) {
+    let payload = tf_payload(16);
+    let mut group = c.benchmark_group("tf_message");
+    group.throughput(Throughput::Bytes(payload.len() as u64));
+    group.bench_function("owned", |b| b.iter(|| black_box(parse_tf_message(black_box(&payload)).unwrap())));
+    group.bench_function("borrowed", |b| {
+        b.iter(|| {
+            for tf in TfMessageReader::new(black_box(&payload)).unwrap() {
+                black_box(tf.unwrap());
+            }
+        })
+    });
+    let (rec, _storage) = rerun::RecordingStreamBuilder::new("parse_bench").memory().unwrap();
+    let mut graph = TfGraph::new();
+    let mut t = 0.0;
+    group.bench_function("ingest", |b| {
+        b.iter(|| {
+            t += 1e-3;
+            graph.ingest_tf_msg(&rec, "/tf", t, None, black_box(&payload), 1.0, "world", &RenameRules::default()).unwrap();
+        })
+    });
+    group.finish();
+}
+
+fn bench_laserscan(c: &mut Criterion) {
+    let payload = scan_payload(1440);
+    let mut group = c.benchmark_group("laserscan");
+    group.throughput(Throughput::Bytes(payload.len() as u64));
+    group.bench_function("parse", |b| b.iter(|| black_box(parse_laserscan_msg(black_box(&payload)).unwrap())));
+    // Ranges alone, into a buffer kept across messages
+    let mut ranges = Vec::new();
+    group.bench_function("ranges_scratch", |b| {
+        b.iter(|| {
+            let mut cursor = Cursor::new(black_box(&payload));
+            cursor.header().unwrap();
+            cursor.skip(7 * 4).unwrap();
+            cursor.f32_array_into(&mut ranges).unwrap();
+            black_box(ranges.len())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_tf, bench_laserscan);
+criterion_main!(benches);
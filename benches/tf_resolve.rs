@@ -0,0 +1,63 @@
+//! TF lookup cost against buffer size: with binary search and cached frame
+//! paths, resolve time should stay flat as the number of samples grows.
+//!
+//! Run with `cargo bench --bench tf_resolve`.
+
+use bag2rrd::mappings::rename::RenameRules;
+use bag2rrd::{TfGraph, TfMode};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// Serialized tf2_msgs/TFMessage holding one transform
+fn tf_payload(parent: &str, child: &str, stamp: f64, trans: [f64; 3]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&1u32.to_le_bytes()); // transforms length
+    data.extend_from_slice(&0u32.to_le_bytes()); // seq
+    data.extend_from_slice(&(stamp.trunc() as u32).to_le_bytes());
+    data.extend_from_slice(&((stamp.fract() * 1e9) as u32).to_le_bytes());
+    data.extend_from_slice(&(parent.len() as u32).to_le_bytes());
+    data.extend_from_slice(parent.as_bytes());
+    data.extend_from_slice(&(child.len() as u32).to_le_bytes());
+    data.extend_from_slice(child.as_bytes());
+    for v in trans.into_iter().chain([0.0, 0.0, 0.0, 1.0]) {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    data
+}
+
+/// world -> map (static) -> odom -> base_link (1 kHz each) -> laser (static)
+fn build_graph(rec: &rerun::RecordingStream, samples: usize) -> TfGraph {
+    let mut graph = TfGraph::new();
+    let root = "world";
+    let frames = RenameRules::default();
+    graph.ingest_tf_static_msg(rec, "/tf_static", &tf_payload("world", "map", 0.0, [0.0; 3]), root, &frames).unwrap();
+    graph.ingest_tf_static_msg(rec, "/tf_static", &tf_payload("base_link", "laser", 0.0, [0.2, 0.0, 0.3]), root, &frames).unwrap();
+    for i in 0..samples {
+        let t = i as f64 * 1e-3;
+        let buffer = samples as f64;
+        graph.ingest_tf_msg(rec, "/tf", t, None, &tf_payload("map", "odom", 0.0, [t, 0.0, 0.0]), buffer, root, &frames).unwrap();
+        graph.ingest_tf_msg(rec, "/tf", t, None, &tf_payload("odom", "base_link", 0.0, [0.0, t, 0.0]), buffer, root, &frames).unwrap();
+    }
+    graph
+}
+
+fn bench_resolve(c: &mut Criterion) {
+    let (rec, _storage) = rerun::RecordingStreamBuilder::new("tf_resolve_bench").memory().unwrap();
+    let mut group = c.benchmark_group("tf_resolve");
+    for samples in [1_000, 10_000, 100_000] {
+        let graph = build_graph(&rec, samples);
+        let end = samples as f64 * 1e-3;
+        for (name, mode) in [("nearest", TfMode::Nearest), ("interpolate", TfMode::Interpolate)] {
+            group.bench_with_input(BenchmarkId::new(name, samples), &samples, |b, _| {
+                let mut t = 0.0;
+                b.iter(|| {
+                    t = (t + 0.0137) % end;
+                    black_box(graph.resolve_pose("world", "laser", black_box(t), mode))
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_resolve);
+criterion_main!(benches);
@@ -0,0 +1,272 @@
+//! batch command - Convert every bag of directories or globs, several at once
+//!
+//! Each bag becomes `<out-dir>/<bag name>.rrd`, converted with the same
+//! options. Bags whose .rrd already exists are skipped unless `overwrite` is
+//! set, so an interrupted batch picks up where it stopped when run again. A
+//! failing bag does not stop the others; its error is reported in the summary.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::convert::{convert_bag, ConvertOptions};
+use crate::events::{ConvertEvent, ConvertStats, ProgressHook};
+use crate::interrupt::Interrupted;
+use crate::multi_bag::expand_bag_paths;
+
+/// Bags and outputs of a batch
+#[derive(Clone, Debug)]
+pub struct BatchOptions {
+    /// Directories of bags, file-name globs or bag files
+    pub inputs: Vec<String>,
+    /// Directory receiving one .rrd per bag
+    pub out_dir: String,
+    /// Bags converted at once
+    pub jobs: usize,
+    /// Convert bags whose .rrd already exists instead of skipping them
+    pub overwrite: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BagStatus {
+    Converted,
+    /// The .rrd already existed
+    Skipped,
+    Failed,
+    /// Stopped by Ctrl-C, or not started after it
+    Interrupted,
+}
+
+/// Outcome of one bag of a batch
+#[derive(Clone, Debug, Serialize)]
+pub struct BagResult {
+    pub bag: String,
+    pub output: String,
+    pub status: BagStatus,
+    /// Messages logged to the recording
+    pub messages: u64,
+    pub seconds: f64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BatchReport {
+    /// In bag order
+    pub bags: Vec<BagResult>,
+    pub seconds: f64,
+}
+
+impl BatchReport {
+    pub fn count(&self, status: BagStatus) -> usize {
+        self.bags.iter().filter(|b| b.status == status).count()
+    }
+
+    /// Summary table, one row per bag
+    pub fn to_text(&self) -> String {
+        let mut out = format!("\n{:<40} {:<12} {:>10} {:>9}  {}\n", "Bag", "Status", "Messages", "Seconds", "Output");
+        out.push_str(&format!("{}\n", "-".repeat(100)));
+        for bag in &self.bags {
+            let name = Path::new(&bag.bag).file_name().map_or(bag.bag.clone(), |n| n.to_string_lossy().into_owned());
+            let status = match bag.status {
+                BagStatus::Converted => "converted",
+                BagStatus::Skipped => "skipped",
+                BagStatus::Failed => "FAILED",
+                BagStatus::Interrupted => "interrupted",
+            };
+            out.push_str(&format!(
+                "{:<40} {:<12} {:>10} {:>9.1}  {}\n",
+                name, status, bag.messages, bag.seconds, bag.output
+            ));
+        }
+        out.push_str(&format!(
+            "\n{} converted, {} skipped, {} failed, {} interrupted in {:.1} s\n",
+            self.count(BagStatus::Converted),
+            self.count(BagStatus::Skipped),
+            self.count(BagStatus::Failed),
+            self.count(BagStatus::Interrupted),
+            self.seconds
+        ));
+        for bag in self.bags.iter().filter(|b| b.status == BagStatus::Failed) {
+            out.push_str(&format!("[ERROR] {}: {}\n", bag.bag, bag.error.as_deref().unwrap_or_default()));
+        }
+        out
+    }
+}
+
+/// .rrd path of `bag` in `out_dir`, named after the bag file
+pub(crate) fn output_path(bag: &str, out_dir: &str) -> PathBuf {
+    let stem = Path::new(bag).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    Path::new(out_dir).join(format!("{}.rrd", stem))
+}
+
+/// .rrd path of each bag in `out_dir`
+fn output_paths(bags: &[String], out_dir: &str) -> Result<Vec<PathBuf>> {
+    let mut seen: HashMap<PathBuf, &str> = HashMap::new();
+    bags.iter()
+        .map(|bag| {
+            let output = output_path(bag, out_dir);
+            if let Some(other) = seen.insert(output.clone(), bag) {
+                bail!("{} and {} would both be written to {}", other, bag, output.display());
+            }
+            Ok(output)
+        })
+        .collect()
+}
+
+/// Convert every bag named by `batch.inputs` with the options of `template`,
+/// whose input and output paths are replaced per bag
+pub fn convert_batch(batch: &BatchOptions, template: &ConvertOptions) -> Result<BatchReport> {
+    let started = Instant::now();
+    let bags = expand_bag_paths(&batch.inputs)?;
+    if bags.is_empty() {
+        bail!("no bags found in {}", batch.inputs.join(", "));
+    }
+    let outputs = output_paths(&bags, &batch.out_dir)?;
+    std::fs::create_dir_all(&batch.out_dir).with_context(|| format!("failed to create {}", batch.out_dir))?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(batch.jobs.max(1))
+        .build()
+        .context("failed to start batch threads")?;
+
+    let total = bags.len();
+    let done = AtomicUsize::new(0);
+    let results: Vec<BagResult> = pool.install(|| {
+        use rayon::prelude::*;
+        bags.par_iter()
+            .zip(&outputs)
+            .map(|(bag, output)| {
+                let result = convert_one(bag, output, batch.overwrite, template);
+                let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+                match &result.error {
+                    Some(error) => tracing::error!(n, total, %bag, status = ?result.status, "{}", error),
+                    None => tracing::info!(n, total, %bag, output = %result.output, status = ?result.status, "bag done"),
+                }
+                result
+            })
+            .collect()
+    });
+    Ok(BatchReport { bags: results, seconds: started.elapsed().as_secs_f64() })
+}
+
+/// Convert `bag` into `output` with the options of `template`, unless `output` exists
+pub(crate) fn convert_one(bag: &str, output: &Path, overwrite: bool, template: &ConvertOptions) -> BagResult {
+    let mut result = BagResult {
+        bag: bag.to_string(),
+        output: output.to_string_lossy().into_owned(),
+        status: BagStatus::Skipped,
+        messages: 0,
+        seconds: 0.0,
+        error: None,
+    };
+    if output.exists() && !overwrite {
+        return result;
+    }
+    if template.cancelled() {
+        result.status = BagStatus::Interrupted;
+        return result;
+    }
+
+    // Final counters, passed on to the hook of the template too
+    let stats: Arc<Mutex<ConvertStats>> = Arc::default();
+    let mut options = template.clone();
+    options.bag_path = bag.to_string();
+    options.extra_bags = Vec::new();
+    options.output_path = result.output.clone();
+    // --report: one file per bag, next to its output
+    if template.report_path.is_some() {
+        options.report_path = Some(output.with_extension("report.json").to_string_lossy().into_owned());
+    }
+    options.progress_hook = Some(ProgressHook::new({
+        let stats = stats.clone();
+        let inner = template.progress_hook.clone();
+        move |event| {
+            if let ConvertEvent::Finished(finished) = event {
+                *stats.lock().unwrap() = finished.clone();
+            }
+            if let Some(inner) = &inner {
+                inner.emit(event);
+            }
+        }
+    }));
+
+    let started = Instant::now();
+    let converted = convert_bag(&options);
+    result.seconds = started.elapsed().as_secs_f64();
+    result.messages = stats.lock().unwrap().kept_msgs;
+    result.status = match converted {
+        Ok(()) => BagStatus::Converted,
+        Err(e) => {
+            result.error = Some(format!("{:#}", e));
+            // A partial recording would be skipped by the next run
+            if output.exists() {
+                std::fs::remove_file(output).ok();
+            }
+            if e.is::<Interrupted>() { BagStatus::Interrupted } else { BagStatus::Failed }
+        }
+    };
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+    #[test]
+    fn test_batch_skips_existing_outputs() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_batch_{}", std::process::id()));
+        let bags = dir.join("bags");
+        std::fs::create_dir_all(&bags).unwrap();
+        let connections = [TestConnection { id: 0, topic: "/chatter", tp: "std_msgs/String", latching: false }];
+        for name in ["run1", "run2"] {
+            let messages = (0..3).map(|i| TestMessage::new(0, 100.0 + i as f64, b"\x02\0\0\0hi".to_vec())).collect();
+            write_bag(&bags.join(format!("{name}.bag")), &connections, &[messages]);
+        }
+        std::fs::write(bags.join("broken.bag"), b"not a bag").unwrap();
+
+        let out = dir.join("rrd");
+        let batch = BatchOptions {
+            inputs: vec![bags.to_string_lossy().into_owned()],
+            out_dir: out.to_string_lossy().into_owned(),
+            jobs: 2,
+            overwrite: false,
+        };
+        let template = ConvertOptions::new("", "").show_progress(false).generic_fallback(true);
+        let report = convert_batch(&batch, &template).unwrap();
+        let statuses: Vec<(String, BagStatus)> = report
+            .bags
+            .iter()
+            .map(|b| (Path::new(&b.bag).file_name().unwrap().to_string_lossy().into_owned(), b.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("broken.bag".to_string(), BagStatus::Failed),
+                ("run1.bag".to_string(), BagStatus::Converted),
+                ("run2.bag".to_string(), BagStatus::Converted),
+            ]
+        );
+        assert_eq!(report.bags[1].messages, 3);
+        assert!(out.join("run1.rrd").exists());
+        assert!(!out.join("broken.rrd").exists());
+        assert!(report.to_text().contains("2 converted, 0 skipped, 1 failed"));
+
+        // Converted bags are skipped on the next run, the failed one retried
+        let report = convert_batch(&batch, &template).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(report.count(BagStatus::Skipped), 2);
+        assert_eq!(report.count(BagStatus::Failed), 1);
+    }
+
+    #[test]
+    fn test_output_names_must_not_collide() {
+        let bags = ["a/run.bag".to_string(), "b/run.bag".to_string()];
+        let err = output_paths(&bags, "out").unwrap_err();
+        assert!(err.to_string().contains("would both be written to"), "{err:#}");
+    }
+}
@@ -0,0 +1,198 @@
+//! Blueprint written next to the RRD (--blueprint): a 3D view of the scene, a
+//! 2D view per camera topic, a time series view of the scalars and a map of the
+//! GPS fixes
+//!
+//! `rerun out.rrd out.rbl` opens the recording with this layout instead of the
+//! heuristic one; the viewer pairs them by application id. Views are named after
+//! the entities the conversion logged, so topics filtered out get no view.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use re_types::blueprint::archetypes::{ContainerBlueprint, ViewBlueprint, ViewContents, ViewportBlueprint};
+use re_types::blueprint::components::ContainerKind;
+
+use crate::mappings::registry::MessageKind;
+
+/// Entities logged by a conversion, grouped by the view showing them
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlueprintLayout {
+    /// Image entities, one 2D view each
+    pub cameras: BTreeSet<String>,
+    /// GPS entities, one map view each
+    pub gps: BTreeSet<String>,
+    /// Whether anything logged scalars (IMU magnitudes, GPS status, generic fields)
+    pub scalars: bool,
+}
+
+impl BlueprintLayout {
+    /// Note a message of `kind` logged under `entity`
+    pub fn add(&mut self, entity: &str, kind: MessageKind) {
+        match kind {
+            MessageKind::Image | MessageKind::CompressedImage => {
+                self.cameras.insert(entity.to_string());
+            }
+            MessageKind::GpsFix => {
+                self.gps.insert(entity.to_string());
+                self.scalars = true;
+            }
+            MessageKind::Imu | MessageKind::Generic => self.scalars = true,
+            MessageKind::PointCloud | MessageKind::LaserScan | MessageKind::Other => {}
+        }
+    }
+
+    /// The layout as a container tree: scene (and maps) on the left, cameras
+    /// and time series on the right
+    pub fn root(&self) -> Node {
+        let mut scene = vec![Node::view("3D", "Scene", "/")];
+        scene.extend(self.gps.iter().map(|entity| Node::view("Map", entity, entity)));
+        let mut panels = Vec::new();
+        if !self.cameras.is_empty() {
+            let cameras = self.cameras.iter().map(|entity| Node::view("2D", entity, entity)).collect();
+            panels.push(Node::Container(ContainerKind::Grid, cameras));
+        }
+        if self.scalars {
+            panels.push(Node::view("TimeSeries", "Time series", "/"));
+        }
+        let mut columns = vec![Node::stack(ContainerKind::Vertical, scene)];
+        if !panels.is_empty() {
+            columns.push(Node::stack(ContainerKind::Vertical, panels));
+        }
+        Node::Container(ContainerKind::Horizontal, columns)
+    }
+}
+
+/// A view or a container of the blueprint
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    View {
+        /// Rerun view class: 3D, 2D, TimeSeries or Map
+        class: &'static str,
+        name: String,
+        /// Entity the view is rooted at; it shows everything below it
+        origin: String,
+    },
+    Container(ContainerKind, Vec<Node>),
+}
+
+impl Node {
+    fn view(class: &'static str, name: &str, origin: &str) -> Self {
+        Node::View { class, name: name.to_string(), origin: origin.to_string() }
+    }
+
+    /// A container, or its only child
+    fn stack(kind: ContainerKind, mut children: Vec<Node>) -> Self {
+        match children.len() {
+            1 => children.remove(0),
+            _ => Node::Container(kind, children),
+        }
+    }
+}
+
+/// .rbl path next to an output, e.g. `run.rbl` for `run.rrd`
+pub fn blueprint_path(output_path: &str) -> PathBuf {
+    Path::new(output_path).with_extension("rbl")
+}
+
+/// Write the blueprint of `layout` to `path`, for recordings of `application_id`
+pub fn save_blueprint(layout: &BlueprintLayout, application_id: &str, path: &Path) -> Result<()> {
+    let rec = rerun::RecordingStreamBuilder::new(application_id)
+        .blueprint()
+        .save(path)
+        .with_context(|| format!("failed to create {}", path.display()))?;
+    rec.set_time_sequence("blueprint", 0);
+    let mut next_id = 0;
+    let (root, _) = log_node(&rec, &layout.root(), &mut next_id)?;
+    rec.log("viewport", &ViewportBlueprint::new().with_root_container(root))?;
+    Ok(())
+}
+
+/// Log `node` and its children; the id and entity path of its blueprint entry
+fn log_node(rec: &rerun::RecordingStream, node: &Node, next_id: &mut u64) -> Result<(rerun::datatypes::Uuid, String)> {
+    // Ids only need to be unique within the blueprint
+    *next_id += 1;
+    let id = rerun::datatypes::Uuid { bytes: (*next_id as u128).to_be_bytes() };
+    let hex: String = id.bytes.iter().map(|b| format!("{b:02x}")).collect();
+    let path = match node {
+        Node::View { class, name, origin } => {
+            let path = format!("view/{hex}");
+            let view = ViewBlueprint::new(*class).with_display_name(name.as_str()).with_space_origin(origin.as_str());
+            rec.log(path.as_str(), &view)?;
+            rec.log(format!("{path}/ViewContents"), &ViewContents::new(["$origin/**"]))?;
+            path
+        }
+        Node::Container(kind, children) => {
+            let contents = children
+                .iter()
+                .map(|child| log_node(rec, child, next_id).map(|(_, path)| path))
+                .collect::<Result<Vec<String>>>()?;
+            let path = format!("container/{hex}");
+            rec.log(path.as_str(), &ContainerBlueprint::new(*kind).with_contents(contents))?;
+            path
+        }
+    };
+    Ok((id, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_follows_logged_kinds() {
+        let mut layout = BlueprintLayout::default();
+        layout.add("/velodyne_points", MessageKind::PointCloud);
+        assert_eq!(layout.root(), Node::Container(ContainerKind::Horizontal, vec![Node::view("3D", "Scene", "/")]));
+
+        layout.add("/camera/front", MessageKind::CompressedImage);
+        layout.add("/camera/rear", MessageKind::Image);
+        layout.add("/camera/front", MessageKind::CompressedImage);
+        layout.add("/gps/fix", MessageKind::GpsFix);
+        let Node::Container(ContainerKind::Horizontal, columns) = layout.root() else {
+            panic!("root is not a row");
+        };
+        assert_eq!(
+            columns[0],
+            Node::Container(
+                ContainerKind::Vertical,
+                vec![Node::view("3D", "Scene", "/"), Node::view("Map", "/gps/fix", "/gps/fix")]
+            )
+        );
+        let Node::Container(ContainerKind::Vertical, panels) = &columns[1] else {
+            panic!("no camera/time series column");
+        };
+        assert_eq!(
+            panels[0],
+            Node::Container(
+                ContainerKind::Grid,
+                vec![
+                    Node::view("2D", "/camera/front", "/camera/front"),
+                    Node::view("2D", "/camera/rear", "/camera/rear")
+                ]
+            )
+        );
+        assert_eq!(panels[1], Node::view("TimeSeries", "Time series", "/"));
+        assert_eq!(blueprint_path("out/run.rrd"), PathBuf::from("out/run.rbl"));
+    }
+
+    #[test]
+    fn test_save_blueprint_writes_views_and_containers() {
+        let mut layout = BlueprintLayout::default();
+        layout.add("/camera/front", MessageKind::CompressedImage);
+        layout.add("/gps/fix", MessageKind::GpsFix);
+        let path = std::env::temp_dir().join(format!("bag2rrd_blueprint_{}.rbl", std::process::id()));
+        save_blueprint(&layout, "bag2rrd_test", &path).unwrap();
+
+        let report = crate::validate::validate_rrd(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert!(report.errors().next().is_none(), "{}", report.to_text(false));
+        assert_eq!(report.stores, 1);
+        let paths: Vec<&str> = report.entities.keys().map(String::as_str).collect();
+        assert!(paths.contains(&"/viewport"));
+        // 3D, map, camera and time series views, each with its contents
+        assert_eq!(paths.iter().filter(|path| path.starts_with("/view/")).count(), 8);
+        // The row, the scene and panel columns and the camera grid
+        assert_eq!(paths.iter().filter(|path| path.starts_with("/container/")).count(), 4);
+    }
+}
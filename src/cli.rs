@@ -1,4 +1,28 @@
-use clap::{ArgAction, Parser, Subcommand};
+use anyhow::{anyhow, Context, Result};
+use clap::{ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+
+use crate::batch::BatchOptions;
+use crate::config::ConvertConfig;
+use crate::watch::WatchOptions;
+use crate::convert::{parse_max_rate, parse_time_offset, parse_timestamp_source, ConvertOptions, OutputTarget, DEFAULT_VIEWER_URL, DEFAULT_WEB_GRPC_PORT, DEFAULT_WEB_PORT};
+use crate::extract::images::{parse_image_format, ImageExtract};
+use crate::extract::pointclouds::{parse_cloud_format, PointCloudExtract};
+use crate::extract::table::{parse_table_format, TableExport};
+use crate::mappings::camera::parse_camera_group;
+use crate::mappings::colormap::parse_colormap;
+use crate::mappings::images::{
+    parse_image_colormap, parse_image_crop, parse_image_encoding, parse_image_every_nth, parse_image_scale,
+};
+use crate::mappings::laserscan::{parse_multi_echo_mode, parse_scan_color};
+use crate::mappings::pointcloud::{parse_pointcloud_color_field, parse_pointcloud_downsample};
+use crate::mappings::roi::parse_roi;
+use crate::mappings::style::{parse_color_setting, parse_label_setting, parse_size_setting};
+use crate::mappings::tf::{parse_tf_authority, parse_tf_mode};
+use crate::memory::parse_byte_size;
+use crate::report::parse_failure_rate;
+use crate::source::rosbridge::parse_rosbridge_encoding;
+use crate::tf_analysis::TfThresholds;
+use crate::world::parse_ground_grid;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -9,6 +33,15 @@ use clap::{ArgAction, Parser, Subcommand};
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+    /// More log output on stderr: -v for debug, -vv for trace (RUST_LOG wins over both)
+    #[arg(short = 'v', long = "verbose", action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+    /// Less log output: -q for warnings and errors only, -qq for errors only
+    #[arg(short = 'q', long = "quiet", action = ArgAction::Count, global = true, conflicts_with = "verbose")]
+    pub quiet: u8,
+    /// Log format on stderr: text or json (one object per line, for log collectors)
+    #[arg(long = "log-format", default_value = "text", global = true)]
+    pub log_format: String,
 }
 
 #[derive(Subcommand, Debug)]
@@ -18,89 +51,974 @@ pub enum Commands {
     Inspect {
         /// Path to the .bag file
         bag: String,
+        /// Output format: text|json
+        #[arg(long = "format", default_value = "text")]
+        format: String,
     },
 
     /// Convert a bag into an .rrd file (images only in v0.1.0)
-    Convert {
+    Convert(ConvertArgs),
+
+    /// Convert every bag of directories or globs into one .rrd each, several at once
+    Batch(BatchArgs),
+
+    /// Convert bags as they are finished in a drop folder, until Ctrl-C
+    Watch(WatchArgs),
+
+    /// Show supported ROS→Rerun mappings and the options affecting each
+    Schema {
+        /// Output format: text or json
+        #[arg(long = "format", default_value = "text")]
+        format: String,
+    },
+
+    /// Print the TF frame tree of a bag with rates and time coverage per edge
+    TfTree {
         /// Path to the .bag file
         bag: String,
-        /// Output .rrd path
-        out: String,
-        /// Include only these topics (can be repeated)
-        #[arg(long = "include", action = ArgAction::Append)]
-        include: Vec<String>,
-        /// Exclude these topics (can be repeated)
-        #[arg(long = "exclude", action = ArgAction::Append)]
-        exclude: Vec<String>,
-        /// Start offset in seconds from the beginning of the bag
-        #[arg(long = "start")]
-        start: Option<f64>,
-        /// End offset in seconds from the beginning of the bag
-        #[arg(long = "end")]
-        end: Option<f64>,
-        /// Dry-run: show plan but do not write any RRD
-        #[arg(long = "dry-run")]
-        dry_run: bool,
-        /// Show progress bar (enabled by default)
-        #[arg(long = "progress", action = ArgAction::SetTrue, default_value_t = true)]
-        progress: bool,
-        /// Segment size (images kept) for parallel flush; if set, produce multiple .rrd files with suffixes
-        #[arg(long = "segment-size")]
-        segment_size: Option<usize>,
-        /// Use LineStrips2D instead of Points2D for LaserScan
-        #[arg(long = "scan-as-lines", default_value_t = false)]
-        scan_as_lines: bool,
-        /// GPS origin for ENU projection: "LAT,LON,ALT" (ellipsoidal meters)
-        #[arg(long = "gps-origin")]
-        gps_origin: Option<String>,
-        /// Log a polyline path for GPS track
-        #[arg(long = "gps-path", default_value_t = true)]
-        gps_path: bool,
-        /// Path to EGM96 geoid grid file (.pgm) for altitude correction
-        #[arg(long = "gps-geoid")]
-        gps_geoid: Option<String>,
-        /// Segment size in bytes (approx) before flushing a new part (in addition to --segment-size)
-        #[arg(long = "segment-bytes")]
-        segment_bytes: Option<u64>,
-        /// Number of parallel flush workers for segments (>=1)
-        #[arg(long = "flush-workers", default_value_t = 2)]
-        flush_workers: usize,
-        /// Root frame name for logging transforms (default: "world")
-        #[arg(long = "root-frame", default_value = "world")]
-        root_frame: String,
-        /// Map ROS frame names to Rerun entity paths: FRAME=/rr/path (repeatable)
-        /// Example: --map-frame base_link=/world/base robot=/world/robot
-        #[arg(long = "map-frame", action = clap::ArgAction::Append)]
-        map_frame: Vec<String>,
-        /// Rename a ROS topic to a specific Rerun entity path: ROS_TOPIC=/rr/path (repeatable)
-        #[arg(long = "topic-rename", action = clap::ArgAction::Append)]
-        topic_rename: Vec<String>,
-        /// TF buffer duration in seconds to retain dynamic transforms
-        #[arg(long = "tf-buffer-seconds", default_value_t = 30.0)]
-        tf_buffer_seconds: f64,
-        /// TF sampling mode when an exact timestamp is missing: nearest|interpolate|none
-        #[arg(long = "tf-mode", default_value = "nearest")]
-        tf_mode: String,
-        /// Key=value metadata entries to embed in the RRD (repeatable)
-        #[arg(long = "metadata", action = clap::ArgAction::Append)]
-        metadata: Vec<String>,
-        /// Tolerate bag file corruption by skipping corrupted chunks
-        #[arg(long = "tolerate-corruption", default_value_t = false)]
-        tolerate_corruption: bool,
-        /// Point cloud rotation in degrees "roll,pitch,yaw" (applied as XYZ Euler angles)
-        #[arg(long = "pointcloud-rotation")]
-        pointcloud_rotation: Option<String>,
+        /// Also write the tree as a Graphviz DOT file
+        #[arg(long = "dot")]
+        dot: Option<String>,
+        /// Also write the tree as JSON
+        #[arg(long = "json")]
+        json: Option<String>,
     },
 
-    /// Show supported ROS→Rerun mappings
-    Schema {},
+    /// Detect jumps, quaternion flips and time going backwards in the TF edges of a bag
+    AnalyzeTf {
+        /// Path to the .bag file
+        bag: String,
+        /// Translation change between consecutive samples reported as a jump (meters)
+        #[arg(long = "jump-threshold", default_value_t = 0.5)]
+        jump_threshold: f64,
+        /// Rotation change between consecutive samples reported as a jump (degrees)
+        #[arg(long = "rotation-threshold", default_value_t = 30.0)]
+        rotation_threshold: f64,
+        /// Also write the report as JSON
+        #[arg(long = "json")]
+        json: Option<String>,
+    },
 
     /// Validate an .rrd file
-    Validate { rrd: String },
+    Validate {
+        rrd: String,
+        /// Fail on warnings (e.g. timestamps going backwards) as well as errors
+        #[arg(long = "strict")]
+        strict: bool,
+    },
+
+    /// Cross-check an .rrd against its bag: messages vs logged rows, time coverage and entity presence per topic
+    Verify {
+        /// Path to the source .bag file
+        bag: String,
+        /// Path to the .rrd converted from it; parts and split files are found next to it
+        rrd: String,
+        /// Also write the report as JSON
+        #[arg(long = "json")]
+        json: Option<String>,
+        /// Flags of `bag2rrd convert` the .rrd was written with, after --
+        #[arg(last = true)]
+        convert_args: Vec<String>,
+    },
 
     /// Diagnose bag file corruption and structure issues
     Diagnose {
         /// Path to the .bag file
         bag: String,
+
+        /// Write a copy with every readable message and a rebuilt index to this path
+        #[arg(long = "repair", value_name = "OUT")]
+        repair: Option<String>,
     },
+
+    /// Write messages of a bag to standard file formats
+    Extract {
+        #[command(subcommand)]
+        what: ExtractCommands,
+    },
+    /// Write topics of a bag as tables for analysis tools
+    Export {
+        #[command(subcommand)]
+        what: ExportCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ExtractCommands {
+    /// Write PointCloud2 messages to PCD, PLY or LAS files, one per message or aggregated in a TF frame
+    Pointclouds(ExtractPointcloudsArgs),
+    /// Write image topics to PNG/JPEG sequences or an H.264 MP4 video
+    Images(ExtractImagesArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ExportCommands {
+    /// Flatten topics into CSV or Parquet tables, one row per message and one file per topic
+    Table(ExportTableArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExtractPointcloudsArgs {
+    /// Path to the bag (ROS1, ROS2 or MCAP)
+    pub bag: String,
+    /// Output directory, one file per message; the output file with --aggregate
+    pub out: String,
+    /// PointCloud2 topics to extract (can be repeated); exact name, glob or regex. All when omitted
+    #[arg(long = "topic", action = ArgAction::Append)]
+    pub topic: Vec<String>,
+    /// Output format: pcd|ply|las
+    #[arg(long = "format", default_value = "pcd")]
+    pub format: String,
+    /// Start offset in seconds from the beginning of the bag
+    #[arg(long = "start")]
+    pub start: Option<f64>,
+    /// End offset in seconds from the beginning of the bag
+    #[arg(long = "end")]
+    pub end: Option<f64>,
+    /// Keep one message out of N per topic
+    #[arg(long = "every-nth-message", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub every_nth_message: u64,
+    /// Keep one point out of N per message
+    #[arg(long = "downsample", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub downsample: u64,
+    /// Keep one point per voxel of this size in meters, in each written file
+    #[arg(long = "voxel-size", value_name = "METERS")]
+    pub voxel_size: Option<f64>,
+    /// Transform every cloud into FRAME through the bag's TF and write them as one file
+    #[arg(long = "aggregate", value_name = "FRAME")]
+    pub aggregate: Option<String>,
+    /// TF sampling mode for --aggregate: nearest|interpolate|none|static
+    #[arg(long = "tf-mode", default_value = "interpolate")]
+    pub tf_mode: String,
+}
+
+impl ExtractPointcloudsArgs {
+    pub fn into_extract(self) -> Result<PointCloudExtract> {
+        if self.voxel_size.is_some_and(|size| size <= 0.0) {
+            return Err(anyhow!("--voxel-size must be positive"));
+        }
+        Ok(PointCloudExtract {
+            topics: self.topic,
+            format: parse_cloud_format(&self.format)?,
+            start_time: self.start,
+            end_time: self.end,
+            every_nth_message: self.every_nth_message,
+            every_nth_point: self.downsample as usize,
+            voxel_size: self.voxel_size,
+            aggregate: self.aggregate,
+            tf_mode: parse_tf_mode(&self.tf_mode)?,
+        })
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExtractImagesArgs {
+    /// Path to the bag (ROS1, ROS2 or MCAP)
+    pub bag: String,
+    /// Output directory, one file per frame; the .mp4 file with --format mp4
+    pub out: String,
+    /// Image or CompressedImage topics to extract (can be repeated); exact name, glob or regex. All when omitted
+    #[arg(long = "topic", action = ArgAction::Append)]
+    pub topic: Vec<String>,
+    /// Output format: png|jpeg|mp4 (mp4 needs the `video-export` feature)
+    #[arg(long = "format", default_value = "png")]
+    pub format: String,
+    /// JPEG quality, 1-100
+    #[arg(long = "jpeg-quality", default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub jpeg_quality: u8,
+    /// Start offset in seconds from the beginning of the bag
+    #[arg(long = "start")]
+    pub start: Option<f64>,
+    /// End offset in seconds from the beginning of the bag
+    #[arg(long = "end")]
+    pub end: Option<f64>,
+    /// Keep at most this many frames per second and topic
+    #[arg(long = "fps")]
+    pub fps: Option<f64>,
+    /// Resize factor applied to every frame (0.5 halves width and height)
+    #[arg(long = "scale")]
+    pub scale: Option<f64>,
+}
+
+impl ExtractImagesArgs {
+    pub fn into_extract(self) -> Result<ImageExtract> {
+        if self.fps.is_some_and(|fps| fps <= 0.0) {
+            return Err(anyhow!("--fps must be positive"));
+        }
+        if self.scale.is_some_and(|scale| scale <= 0.0) {
+            return Err(anyhow!("--scale must be positive"));
+        }
+        Ok(ImageExtract {
+            topics: self.topic,
+            format: parse_image_format(&self.format)?,
+            jpeg_quality: self.jpeg_quality,
+            start_time: self.start,
+            end_time: self.end,
+            fps: self.fps,
+            scale: self.scale,
+        })
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExportTableArgs {
+    /// Path to the bag (ROS1, ROS2 or MCAP)
+    pub bag: String,
+    /// Output directory, one file per topic
+    pub out: String,
+    /// Topics to export (can be repeated); exact name, glob or regex. All when omitted
+    #[arg(long = "topic", action = ArgAction::Append)]
+    pub topic: Vec<String>,
+    /// Output format: csv|parquet (parquet needs the `parquet-export` feature)
+    #[arg(long = "format", default_value = "csv")]
+    pub format: String,
+    /// Start offset in seconds from the beginning of the bag
+    #[arg(long = "start")]
+    pub start: Option<f64>,
+    /// End offset in seconds from the beginning of the bag
+    #[arg(long = "end")]
+    pub end: Option<f64>,
+}
+
+impl ExportTableArgs {
+    pub fn into_export(self) -> Result<TableExport> {
+        Ok(TableExport {
+            topics: self.topic,
+            format: parse_table_format(&self.format)?,
+            start_time: self.start,
+            end_time: self.end,
+        })
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct BatchArgs {
+    /// Directories of bags, file-name globs (runs/day1_*.bag) or bag files
+    #[arg(required = true, num_args = 1..)]
+    pub inputs: Vec<String>,
+    /// Directory receiving one <bag name>.rrd per bag
+    #[arg(long = "out-dir")]
+    pub out_dir: String,
+    /// Bags converted at once
+    #[arg(long = "jobs", default_value_t = 2)]
+    pub jobs: usize,
+    /// Convert bags whose .rrd already exists instead of skipping them
+    #[arg(long = "overwrite", default_value_t = false)]
+    pub overwrite: bool,
+    /// Flags of `bag2rrd convert` shared by every bag, after --
+    /// (e.g. -- --config fleet.toml --tf-mode interpolate)
+    #[arg(last = true)]
+    pub convert_args: Vec<String>,
+}
+
+impl BatchArgs {
+    /// Batch settings and the conversion options shared by every bag
+    pub fn into_batch(self) -> Result<(BatchOptions, ConvertOptions)> {
+        let template = convert_template(self.convert_args)?;
+        let batch = BatchOptions { inputs: self.inputs, out_dir: self.out_dir, jobs: self.jobs, overwrite: self.overwrite };
+        Ok((batch, template))
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct WatchArgs {
+    /// Directory where finished .bag files appear
+    pub dir: String,
+    /// Directory receiving one <bag name>.rrd per bag
+    #[arg(long = "out-dir")]
+    pub out_dir: String,
+    /// Seconds a bag's size must stay unchanged before it counts as finished
+    /// (bags renamed from .bag.active are converted at once)
+    #[arg(long = "settle-seconds", default_value_t = 5.0)]
+    pub settle_seconds: f64,
+    /// Flags of `bag2rrd convert` for every bag, after --
+    #[arg(last = true)]
+    pub convert_args: Vec<String>,
+}
+
+impl WatchArgs {
+    pub fn into_watch(self) -> Result<(WatchOptions, ConvertOptions)> {
+        let settle = std::time::Duration::try_from_secs_f64(self.settle_seconds)
+            .map_err(|_| anyhow::anyhow!("--settle-seconds must be a finite number of seconds, zero or more"))?;
+        let template = convert_template(self.convert_args)?;
+        let watch = WatchOptions { dir: self.dir, out_dir: self.out_dir, settle };
+        Ok((watch, template))
+    }
+}
+
+/// Conversion options of the `convert` flags given after -- to batch, watch and verify
+pub fn convert_template(convert_args: Vec<String>) -> Result<ConvertOptions> {
+    let argv: Vec<String> =
+        ["bag2rrd", "convert", "input.bag", "output.rrd"].into_iter().map(String::from).chain(convert_args).collect();
+    let mut matches = Cli::command().try_get_matches_from(&argv)?;
+    // --config sets the defaults of the other flags, as for convert
+    if let Some(path) = matches.subcommand_matches("convert").and_then(|m| m.get_one::<String>("config")) {
+        matches = ConvertConfig::load(path)?.apply(Cli::command())?.try_get_matches_from(&argv)?;
+    }
+    let Commands::Convert(args) = Cli::from_arg_matches(&matches)?.command else {
+        unreachable!("parsed as the convert command");
+    };
+    if args.bags.len() > 1 {
+        anyhow::bail!("bags go before -- and convert flags after it, got {}", args.bags[1..].join(" "));
+    }
+    args.into_options()
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ConvertArgs {
+    /// Input .bag files, directories of bags, file-name globs (runs/day1_*.bag),
+    /// ROS2 bags (rosbag2 directories, .db3 files) or MCAP files, recognized by
+    /// their content; several bags are merged on one timeline. A rosbridge URL
+    /// (ws://robot:9090) or ROS1 master (ros1://robot:11311, ros1-live feature)
+    /// converts live messages until Ctrl-C or --end
+    #[arg(required = true, num_args = 1..)]
+    pub bags: Vec<String>,
+    /// Output .rrd path (- with --connect or --spawn)
+    pub out: String,
+    /// Include only these topics (can be repeated); exact name, glob (/camera/**) or regex
+    #[arg(long = "include", action = ArgAction::Append)]
+    pub include: Vec<String>,
+    /// Exclude these topics (can be repeated); exact name, glob or regex
+    #[arg(long = "exclude", action = ArgAction::Append)]
+    pub exclude: Vec<String>,
+    /// Include only these message types (can be repeated), e.g. "sensor_msgs/*"
+    #[arg(long = "include-type", action = ArgAction::Append)]
+    pub include_type: Vec<String>,
+    /// Exclude these message types (can be repeated), e.g. sensor_msgs/Image
+    #[arg(long = "exclude-type", action = ArgAction::Append)]
+    pub exclude_type: Vec<String>,
+    /// Start offset in seconds from the beginning of the bag (chunks outside --start/--end are skipped via the bag index)
+    #[arg(long = "start")]
+    pub start: Option<f64>,
+    /// End offset in seconds from the beginning of the bag
+    #[arg(long = "end")]
+    pub end: Option<f64>,
+    /// Dry-run: show the plan (per-topic mapping and message counts, estimated output size, parts) but do not write any RRD
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+    /// Show progress bar (enabled by default)
+    #[arg(long = "progress", action = ArgAction::SetTrue, default_value_t = true)]
+    pub progress: bool,
+    /// Segment size (images kept) for parallel flush; if set, produce multiple .rrd files with suffixes
+    #[arg(long = "segment-size")]
+    pub segment_size: Option<usize>,
+    /// Use LineStrips2D instead of Points2D for LaserScan
+    #[arg(long = "scan-as-lines", default_value_t = false)]
+    pub scan_as_lines: bool,
+    /// Log LaserScan as Points3D/LineStrips3D (z=0) placed in the root frame via TF
+    #[arg(long = "scan-3d", default_value_t = false)]
+    pub scan_3d: bool,
+    /// Color LaserScan points by: none|intensity|range
+    #[arg(long = "scan-color", default_value = "none")]
+    pub scan_color: String,
+    /// Colormap for LaserScan coloring: turbo|viridis|inferno|grayscale
+    #[arg(long = "scan-colormap", default_value = "turbo")]
+    pub scan_colormap: String,
+    /// MultiEchoLaserScan echoes to log: first|strongest|all (all = one entity per echo index)
+    #[arg(long = "multi-echo", default_value = "first")]
+    pub multi_echo: String,
+    /// Accumulate the last N scans (transformed into the root frame via TF) into /<topic>/accumulated
+    #[arg(long = "scan-accumulate")]
+    pub scan_accumulate: Option<usize>,
+    /// Colormap for mono8/mono16 images: NAME or TOPIC=NAME (repeatable), e.g. /thermal/image_raw=inferno
+    #[arg(long = "image-colormap", action = ArgAction::Append)]
+    pub image_colormap: Vec<String>,
+    /// Value range "MIN,MAX" mapped onto --image-colormap (default: auto per image)
+    #[arg(long = "image-value-range")]
+    pub image_value_range: Option<String>,
+    /// Downscale images before logging: SCALE or TOPIC=SCALE in (0, 1] (repeatable)
+    #[arg(long = "image-scale", action = ArgAction::Append)]
+    pub image_scale: Vec<String>,
+    /// Crop images to a pixel rectangle before scaling and logging: X,Y,W,H or TOPIC=X,Y,W,H
+    /// (repeatable); the rectangle is clamped to each image
+    #[arg(long = "image-crop", value_name = "[TOPIC=]X,Y,W,H", action = ArgAction::Append)]
+    pub image_crop: Vec<String>,
+    /// Keep only every Nth image: N or TOPIC=N (repeatable)
+    #[arg(long = "image-every-nth", action = ArgAction::Append)]
+    pub image_every_nth: Vec<String>,
+    /// Store raw images as: raw|jpeg|png (CompressedImage topics are never re-encoded)
+    #[arg(long = "image-encode", default_value = "raw")]
+    pub image_encode: String,
+    /// JPEG quality (1-100) for --image-encode jpeg
+    #[arg(long = "jpeg-quality", default_value_t = 85)]
+    pub jpeg_quality: u8,
+    /// Log CompressedImage JPEG/PNG bytes directly as EncodedImage (no decode); other formats are still decoded
+    #[arg(long = "compressed-passthrough", default_value_t = false)]
+    pub compressed_passthrough: bool,
+    /// Back-project 16UC1/32FC1 depth images into Points3D (camera frame) using the camera_info in the same namespace
+    #[arg(long = "depth-to-points", default_value_t = false)]
+    pub depth_to_points: bool,
+    /// mono16 image topic holding depth in millimeters for --depth-to-points (repeatable; 16UC1 and 32FC1 always are)
+    #[arg(long = "depth-topic", action = ArgAction::Append)]
+    pub depth_topic: Vec<String>,
+    /// Registered color image topic used to color --depth-to-points clouds
+    #[arg(long = "depth-color-topic")]
+    pub depth_color_topic: Option<String>,
+    /// Group camera topics under PREFIX (one camera per sub-namespace) with Pinhole and TF: PREFIX or PREFIX=/rr/path (repeatable)
+    /// Example: --camera-group /stereo  --camera-group /=/cameras
+    #[arg(long = "camera-group", action = ArgAction::Append)]
+    pub camera_group: Vec<String>,
+    /// Timeline source: header (header.stamp, bag time when zero) or bag (record time)
+    #[arg(long = "timestamp-source", default_value = "header")]
+    pub timestamp_source: String,
+    /// Use /clock (rosgraph_msgs/Clock) sim time as the time axis; receive times are mapped onto it
+    #[arg(long = "sim-time", default_value_t = false)]
+    pub sim_time: bool,
+    /// Accumulate nav_msgs/Odometry positions into a LineStrips3D trajectory under /<root>/trajectories/<topic>
+    #[arg(long = "odom-trajectory", default_value_t = false)]
+    pub odom_trajectory: bool,
+    /// Maximum odometry trajectory length in points, oldest dropped first (0 = unlimited)
+    #[arg(long = "odom-trajectory-max-points", default_value_t = 10000)]
+    pub odom_trajectory_max_points: usize,
+    /// Add one trajectory point every N odometry messages
+    #[arg(long = "odom-trajectory-every-nth", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub odom_trajectory_every_nth: u64,
+    /// GPS origin for ENU projection: "LAT,LON,ALT" (ellipsoidal meters)
+    #[arg(long = "gps-origin")]
+    pub gps_origin: Option<String>,
+    /// Log a polyline path for GPS track
+    #[arg(long = "gps-path", default_value_t = true)]
+    pub gps_path: bool,
+    /// Path to EGM96 geoid grid file (.pgm) for altitude correction
+    #[arg(long = "gps-geoid")]
+    pub gps_geoid: Option<String>,
+    /// Also write the NavSatFix tracks, one per topic with timestamps, to this GPX file
+    #[arg(long = "export-gpx", value_name = "PATH")]
+    pub export_gpx: Option<String>,
+    /// Also write the NavSatFix tracks to this KML file (Google Earth)
+    #[arg(long = "export-kml", value_name = "PATH")]
+    pub export_kml: Option<String>,
+    /// Segment size in bytes (approx) before flushing a new part (in addition to --segment-size)
+    #[arg(long = "segment-bytes")]
+    pub segment_bytes: Option<u64>,
+    /// Segment duration in seconds of bag time (in addition to --segment-size/--segment-bytes)
+    #[arg(long = "segment-seconds", value_name = "SECONDS")]
+    pub segment_seconds: Option<f64>,
+    /// Align --segment-seconds boundaries to round timestamps (e.g. 60 starts parts on full minutes)
+    #[arg(long = "segment-align", default_value_t = false)]
+    pub segment_align: bool,
+    /// Write topics matching PATTERN to <out stem>_NAME.rrd (NAME=PATTERN, can be repeated; first match wins)
+    #[arg(long = "output-group", value_name = "NAME=PATTERN", action = ArgAction::Append)]
+    pub output_group: Vec<String>,
+    /// Write every topic to its own <out stem>_<topic>.rrd
+    #[arg(long = "split-topics", default_value_t = false)]
+    pub split_topics: bool,
+    /// Number of parallel flush workers for segments (>=1)
+    #[arg(long = "flush-workers", default_value_t = 2)]
+    pub flush_workers: usize,
+    /// Write a SHA-256 checksum file (<part>.sha256) next to each segment
+    #[arg(long = "segment-checksum", default_value_t = false)]
+    pub segment_checksum: bool,
+    /// Keep the parts listed in the segment manifest of an interrupted run and
+    /// continue after the last complete one
+    #[arg(long = "resume", default_value_t = false)]
+    pub resume: bool,
+    /// Threads decompressing chunks and decoding images in parallel (0 = all cores, 1 = sequential)
+    #[arg(long = "decode-threads", default_value_t = 0)]
+    pub decode_threads: usize,
+    /// Memory budget for buffered data, e.g. 8G; chunks are then read a group at a time
+    #[arg(long = "max-memory", value_name = "SIZE")]
+    pub max_memory: Option<String>,
+    /// Milliseconds the rerun batcher waits before cutting a chunk (larger gives fewer, bigger chunks)
+    #[arg(long = "batch-flush-ms", value_name = "MS")]
+    pub batch_flush_ms: Option<u64>,
+    /// Logged bytes after which the batcher cuts a chunk, e.g. 4M
+    #[arg(long = "batch-flush-bytes", value_name = "SIZE")]
+    pub batch_flush_bytes: Option<String>,
+    /// Logged rows after which the batcher cuts a chunk
+    #[arg(long = "batch-flush-rows", value_name = "ROWS")]
+    pub batch_flush_rows: Option<u64>,
+    /// After writing, merge the small chunks of each .rrd file (high-rate topics, split chunks)
+    #[arg(long = "compact", default_value_t = false)]
+    pub compact: bool,
+    /// Root frame name for logging transforms (default: "world")
+    #[arg(long = "root-frame", default_value = "world")]
+    pub root_frame: String,
+    /// Map ROS frame names to Rerun entity paths: FRAME=/rr/path (repeatable); FRAME may be a glob or regex
+    /// Example: --map-frame base_link=/world/base --map-frame 'cam_(.*)_optical=/world/cams/$1'
+    #[arg(long = "map-frame", action = clap::ArgAction::Append)]
+    pub map_frame: Vec<String>,
+    /// Rename a ROS topic to a specific Rerun entity path: ROS_TOPIC=/rr/path (repeatable)
+    /// ROS_TOPIC may be a glob (*, **, ?) or a regex; wildcards and groups are $1, $2, ...
+    /// Example: --topic-rename '/camera/(.*)=/sensors/cam/$1'
+    #[arg(long = "topic-rename", action = clap::ArgAction::Append)]
+    pub topic_rename: Vec<String>,
+    /// TF buffer duration in seconds to retain dynamic transforms
+    #[arg(long = "tf-buffer-seconds", default_value_t = 30.0)]
+    pub tf_buffer_seconds: f64,
+    /// TF sampling mode when an exact timestamp is missing: nearest|interpolate|none|static (static-only transforms)
+    #[arg(long = "tf-mode", default_value = "nearest")]
+    pub tf_mode: String,
+    /// Only resolve through dynamic TF edges with a sample within this many seconds of the lookup time (default: unlimited)
+    #[arg(long = "tf-tolerance", default_value_t = f64::INFINITY)]
+    pub tf_tolerance: f64,
+    /// When no TF path has data within --tf-tolerance, resolve through the nearest samples anyway
+    #[arg(long = "tf-fallback", default_value_t = false)]
+    pub tf_fallback: bool,
+    /// Parent kept when a child frame is published under several parents: first-wins|last-wins|prefer-topic=TOPIC
+    #[arg(long = "tf-authority", default_value = "last-wins")]
+    pub tf_authority: String,
+    /// Log images, point clouds and scans under their header.frame_id entity (/<root>/<frame>/<topic>) so TF animates them
+    #[arg(long = "attach-to-frames", default_value_t = false)]
+    pub attach_to_frames: bool,
+    /// Also plot each /tf edge's x/y/z and yaw/pitch/roll (degrees) as scalars under /tf_plots/<parent>__<child>/
+    #[arg(long = "tf-plots", default_value_t = false)]
+    pub tf_plots: bool,
+    /// Draw RGB coordinate axes of this length in meters at each TF frame (like RViz's TF display)
+    #[arg(long = "tf-axes")]
+    pub tf_axes: Option<f32>,
+    /// Only draw --tf-axes for frames whose name matches this regex, e.g. "^(base_link|camera_.*)$"
+    #[arg(long = "tf-axes-filter")]
+    pub tf_axes_filter: Option<String>,
+    /// URDF whose link visuals (meshes, boxes, cylinders, spheres) are shown at their TF frames;
+    /// package:// meshes are looked up in ROS_PACKAGE_PATH
+    #[arg(long = "urdf", value_name = "FILE")]
+    pub urdf: Option<String>,
+    /// Mesh shown at a TF frame: FRAME=PATH, glTF/GLB/OBJ/STL (repeatable)
+    #[arg(long = "mesh", action = ArgAction::Append)]
+    pub mesh: Vec<String>,
+    /// Static ground grid in the z = 0 plane: SIZE[,SPACING] in meters, e.g. 50,1
+    #[arg(long = "ground-grid")]
+    pub ground_grid: Option<String>,
+    /// Arrow pointing north (+y of the GPS ENU frame) when the bag has NavSatFix topics
+    #[arg(long = "north-arrow", default_value_t = false)]
+    pub north_arrow: bool,
+    /// YAML list of named points (name, position [x, y, z], optional color) shown as labels
+    #[arg(long = "landmarks", value_name = "FILE")]
+    pub landmarks: Option<String>,
+    /// YAML map of class id to label and color, the legend of point cloud classes and detections
+    #[arg(long = "class-map", value_name = "FILE")]
+    pub class_map: Option<String>,
+    /// After converting, report TF position/rotation jumps, quaternion flips and stamps going backwards
+    #[arg(long = "analyze-tf", default_value_t = false)]
+    pub analyze_tf: bool,
+    /// --analyze-tf: translation change between consecutive samples reported as a jump (meters)
+    #[arg(long = "tf-jump-threshold", default_value_t = 0.5)]
+    pub tf_jump_threshold: f64,
+    /// --analyze-tf: rotation change between consecutive samples reported as a jump (degrees)
+    #[arg(long = "tf-rotation-threshold", default_value_t = 30.0)]
+    pub tf_rotation_threshold: f64,
+    /// Also write a JSON report: message counters, and every type that was not mapped
+    /// with its count, example topics and the flag that would map it
+    #[arg(long = "report", value_name = "FILE")]
+    pub report: Option<String>,
+    /// Fail on the first message a mapper cannot parse (truncated payload, undecodable image,
+    /// md5sum mismatch) instead of skipping it with a warning
+    #[arg(long = "strict", default_value_t = false)]
+    pub strict: bool,
+    /// Fail the conversion when more than RATE (0-1) of the messages of a kind fail to parse:
+    /// RATE for every kind or KIND=RATE, KIND in image, compressed_image, pointcloud, laserscan,
+    /// gps, imu, other (repeatable; overrides --strict for that kind)
+    #[arg(long = "max-failure-rate", value_name = "[KIND=]RATE", action = clap::ArgAction::Append)]
+    pub max_failure_rate: Vec<String>,
+    /// Log every gap in the header.seq of a topic as a warning under /diagnostics/drops,
+    /// at the time of the message after it (gaps are always counted in the summary)
+    #[arg(long = "log-drops", default_value_t = false)]
+    pub log_drops: bool,
+    /// Key=value metadata entries sent as recording properties metadata/<key> (repeatable)
+    #[arg(long = "metadata", action = clap::ArgAction::Append)]
+    pub metadata: Vec<String>,
+    /// Recording id of the output (default: md5sum of the bag, so converting the same bag
+    /// again gives the same id; random for live sources)
+    #[arg(long = "recording-id")]
+    pub recording_id: Option<String>,
+    /// Application id the viewer groups recordings under
+    #[arg(long = "application-id", default_value = "bag2rrd")]
+    pub application_id: String,
+    /// Also write OUT with the .rbl extension: a blueprint with a 3D view, a 2D view per camera,
+    /// time series and GPS maps (open both with `rerun out.rrd out.rbl`)
+    #[arg(long = "blueprint", default_value_t = false)]
+    pub blueprint: bool,
+    /// Tolerate bag file corruption by skipping corrupted chunks
+    #[arg(long = "tolerate-corruption", default_value_t = false)]
+    pub tolerate_corruption: bool,
+    /// Log messages of unsupported types from the definition stored in the bag:
+    /// numeric fields as scalars, strings as text, under /<topic>/<field>
+    #[arg(long = "generic-fallback", default_value_t = false)]
+    pub generic_fallback: bool,
+    /// Parse mapped types as their standard definition even when the bag records another md5sum
+    /// (by default such topics are skipped with a warning instead of being mis-parsed)
+    #[arg(long = "ignore-md5-mismatch", default_value_t = false)]
+    pub ignore_md5_mismatch: bool,
+    /// Stream to a running Rerun viewer instead of writing OUT (pass - as OUT);
+    /// URL defaults to the viewer on this machine
+    #[arg(
+        long = "connect",
+        value_name = "URL",
+        num_args = 0..=1,
+        default_missing_value = DEFAULT_VIEWER_URL,
+        conflicts_with_all = ["spawn", "web"]
+    )]
+    pub connect: Option<String>,
+    /// Start a Rerun viewer and stream to it instead of writing OUT (pass - as OUT)
+    #[arg(long = "spawn", default_value_t = false, conflicts_with = "web")]
+    pub spawn: bool,
+    /// Serve the recording to browsers with the Rerun web viewer instead of writing OUT
+    /// (pass - as OUT; requires building with --features web)
+    #[arg(long = "web", default_value_t = false)]
+    pub web: bool,
+    /// Port of the web viewer served by --web
+    #[arg(long = "web-port", default_value_t = DEFAULT_WEB_PORT, requires = "web")]
+    pub web_port: u16,
+    /// Port of the gRPC server the --web viewer reads the recording from
+    #[arg(long = "web-grpc-port", default_value_t = DEFAULT_WEB_GRPC_PORT, requires = "web")]
+    pub web_grpc_port: u16,
+    /// Keep serving after the conversion completes, until Ctrl-C
+    #[arg(long = "keep-serving", default_value_t = false, requires = "web")]
+    pub keep_serving: bool,
+    /// Play the bag back at this multiple of its recorded rate, e.g. 1.0 for realtime,
+    /// while streaming with --connect, --spawn or --web (0 = as fast as possible)
+    #[arg(long = "rate", default_value_t = 0.0)]
+    pub rate: f64,
+    /// How the rosbridge server of a ws:// input sends messages: cbor-raw (serialized, fast) or json
+    #[arg(long = "rosbridge-encoding", default_value = "cbor-raw")]
+    pub rosbridge_encoding: String,
+    /// Rhai script mapping in-house message types to scalars, text and points
+    /// (requires building with --features scripting)
+    #[arg(long = "mapper-script", value_name = "FILE")]
+    pub mapper_script: Option<String>,
+    /// Point cloud rotation in degrees "roll,pitch,yaw" (applied as XYZ Euler angles, before TF)
+    #[arg(long = "pointcloud-rotation")]
+    pub pointcloud_rotation: Option<String>,
+    /// Also log organized PointCloud2 (height > 1) as a range DepthImage under /<topic>/range_image
+    #[arg(long = "pointcloud-range-image", default_value_t = false)]
+    pub pointcloud_range_image: bool,
+    /// Log point clouds in their sensor frame instead of transforming them into the root frame via TF
+    #[arg(long = "no-pointcloud-tf", default_value_t = false)]
+    pub no_pointcloud_tf: bool,
+    /// PointCloud2 field logged as per-point class ids, e.g. "label" for semantic clouds
+    #[arg(long = "pointcloud-class-field")]
+    pub pointcloud_class_field: Option<String>,
+    /// PointCloud2 field logged as per-point keypoint ids, e.g. "ring"
+    #[arg(long = "pointcloud-keypoint-field")]
+    pub pointcloud_keypoint_field: Option<String>,
+    /// Crop point clouds and laser scans to a box of the root frame, in meters:
+    /// XMIN,YMIN,ZMIN,XMAX,YMAX,ZMAX, or XMIN,YMIN,XMAX,YMAX for any height.
+    /// Points are moved into the root frame via TF before the test
+    #[arg(long = "roi", value_name = "BOX", allow_hyphen_values = true)]
+    pub roi: Option<String>,
+    /// Keep every Nth point of point clouds: N or TOPIC=N (repeatable)
+    #[arg(long = "pointcloud-downsample", action = ArgAction::Append)]
+    pub pointcloud_downsample: Vec<String>,
+    /// Color point clouds by a scalar field (turbo colormap): FIELD or TOPIC=FIELD (repeatable), e.g. intensity
+    #[arg(long = "pointcloud-color-field", action = ArgAction::Append)]
+    pub pointcloud_color_field: Vec<String>,
+    /// Seconds added to message times, e.g. to compensate sensor latency: SECONDS or TOPIC=SECONDS (repeatable)
+    #[arg(long = "time-offset", action = ArgAction::Append, allow_hyphen_values = true)]
+    pub time_offset: Vec<String>,
+    /// Keep at most HZ messages per second of a topic, for every kind of message:
+    /// HZ or TOPIC=HZ (repeatable); latched topics like /tf_static are never thinned
+    #[arg(long = "max-rate", value_name = "[TOPIC=]HZ", action = ArgAction::Append)]
+    pub max_rate: Vec<String>,
+    /// Color of points and lines (GPS, scans, point clouds, trajectories, paths):
+    /// COLOR or TOPIC=COLOR, as #RRGGBB[AA] or R,G,B[,A] (repeatable)
+    #[arg(long = "color", action = ArgAction::Append)]
+    pub color: Vec<String>,
+    /// Point radius in meters: METERS or TOPIC=METERS (repeatable)
+    #[arg(long = "radius", action = ArgAction::Append)]
+    pub radius: Vec<String>,
+    /// Line width in meters (paths, trajectories, scans as lines): METERS or TOPIC=METERS (repeatable)
+    #[arg(long = "line-width", action = ArgAction::Append)]
+    pub line_width: Vec<String>,
+    /// Label shown in the 3D view: TEXT or TOPIC=TEXT (repeatable)
+    #[arg(long = "label", action = ArgAction::Append)]
+    pub label: Vec<String>,
+    /// TOML or YAML file with default values for these flags (keys are the long flag names)
+    /// plus per-topic [topics."/name"] sections; flags given on the command line win
+    #[arg(long = "config")]
+    pub config: Option<String>,
+}
+
+impl ConvertArgs {
+    /// Parse the string-typed flags into conversion options
+    pub fn into_options(self) -> Result<ConvertOptions> {
+        let ConvertArgs {
+            bags,
+            out,
+            include,
+            exclude,
+            include_type,
+            exclude_type,
+            start,
+            end,
+            dry_run,
+            progress,
+            segment_size,
+            scan_as_lines,
+            scan_3d,
+            scan_color,
+            scan_colormap,
+            multi_echo,
+            scan_accumulate,
+            image_colormap,
+            image_value_range,
+            image_scale,
+            image_crop,
+            image_every_nth,
+            image_encode,
+            jpeg_quality,
+            compressed_passthrough,
+            depth_to_points,
+            depth_topic,
+            depth_color_topic,
+            camera_group,
+            timestamp_source,
+            sim_time,
+            odom_trajectory,
+            odom_trajectory_max_points,
+            odom_trajectory_every_nth,
+            gps_origin,
+            gps_path,
+            segment_bytes,
+            segment_seconds,
+            segment_align,
+            output_group,
+            split_topics,
+            flush_workers,
+            segment_checksum,
+            resume,
+            decode_threads,
+            max_memory,
+            batch_flush_ms,
+            batch_flush_bytes,
+            batch_flush_rows,
+            compact,
+            root_frame,
+            map_frame,
+            topic_rename,
+            tf_buffer_seconds,
+            tf_mode,
+            tf_tolerance,
+            tf_fallback,
+            tf_authority,
+            attach_to_frames,
+            tf_plots,
+            tf_axes,
+            tf_axes_filter,
+            urdf,
+            mesh,
+            ground_grid,
+            north_arrow,
+            landmarks,
+            class_map,
+            analyze_tf,
+            tf_jump_threshold,
+            tf_rotation_threshold,
+            report,
+            strict,
+            max_failure_rate,
+            log_drops,
+            metadata,
+            recording_id,
+            application_id,
+            blueprint,
+            gps_geoid,
+            export_gpx,
+            export_kml,
+            tolerate_corruption,
+            generic_fallback,
+            ignore_md5_mismatch,
+            mapper_script,
+            connect,
+            spawn,
+            web,
+            web_port,
+            web_grpc_port,
+            keep_serving,
+            rate,
+            rosbridge_encoding,
+            pointcloud_rotation,
+            pointcloud_range_image,
+            no_pointcloud_tf,
+            pointcloud_class_field,
+            pointcloud_keypoint_field,
+            roi,
+            pointcloud_downsample,
+            pointcloud_color_field,
+            time_offset,
+            max_rate,
+            color,
+            radius,
+            line_width,
+            label,
+            config: _,
+        } = self;
+        Ok(ConvertOptions {
+            bag_path: bags[0].clone(),
+            extra_bags: bags[1..].to_vec(),
+            output_path: out,
+            include_topics: include,
+            exclude_topics: exclude,
+            include_types: include_type,
+            exclude_types: exclude_type,
+            start_time: start,
+            end_time: end,
+            dry_run,
+            show_progress: progress,
+            segment_size,
+            scan_as_lines,
+            scan_3d,
+            scan_color: parse_scan_color(&scan_color)?,
+            scan_colormap: parse_colormap(&scan_colormap)?,
+            multi_echo: parse_multi_echo_mode(&multi_echo)?,
+            scan_accumulate,
+            image_colormap: image_colormap
+                .iter()
+                .map(|s| parse_image_colormap(s))
+                .collect::<Result<Vec<_>>>()?,
+            image_value_range: match image_value_range {
+                Some(range_str) => Some(parse_value_range(&range_str)?),
+                None => None,
+            },
+            image_scale: image_scale
+                .iter()
+                .map(|s| parse_image_scale(s))
+                .collect::<Result<Vec<_>>>()?,
+            image_crop: image_crop.iter().map(|s| parse_image_crop(s)).collect::<Result<Vec<_>>>()?,
+            image_every_nth: image_every_nth
+                .iter()
+                .map(|s| parse_image_every_nth(s))
+                .collect::<Result<Vec<_>>>()?,
+            image_encoding: parse_image_encoding(&image_encode, jpeg_quality)?,
+            compressed_passthrough,
+            depth_to_points,
+            depth_topics: depth_topic,
+            depth_color_topic,
+            camera_groups: camera_group
+                .iter()
+                .map(|s| parse_camera_group(s))
+                .collect::<Result<Vec<_>>>()?,
+            timestamp_source: parse_timestamp_source(&timestamp_source)?,
+            sim_time,
+            odom_trajectory,
+            odom_trajectory_max_points,
+            odom_trajectory_every_nth,
+            gps_origin,
+            gps_path,
+            segment_bytes,
+            segment_seconds,
+            segment_align,
+            output_groups: output_group,
+            split_topics,
+            flush_workers,
+            segment_checksum,
+            resume,
+            decode_threads,
+            max_memory: max_memory.as_deref().map(parse_byte_size).transpose().context("invalid --max-memory")?,
+            batch_flush_ms,
+            batch_flush_bytes: batch_flush_bytes
+                .as_deref()
+                .map(parse_byte_size)
+                .transpose()
+                .context("invalid --batch-flush-bytes")?,
+            batch_flush_rows,
+            compact,
+            root_frame,
+            frame_mappings: map_frame,
+            topic_renames: topic_rename,
+            tf_buffer_seconds,
+            tf_mode: parse_tf_mode(&tf_mode)?,
+            tf_tolerance,
+            tf_fallback,
+            tf_authority: parse_tf_authority(&tf_authority)?,
+            attach_to_frames,
+            tf_plots,
+            tf_axes,
+            tf_axes_filter,
+            urdf,
+            meshes: mesh,
+            ground_grid: ground_grid.as_deref().map(parse_ground_grid).transpose()?,
+            north_arrow,
+            landmarks,
+            class_map,
+            analyze_tf: analyze_tf.then_some(TfThresholds {
+                max_translation: tf_jump_threshold,
+                max_rotation_deg: tf_rotation_threshold,
+            }),
+            report_path: report,
+            strict,
+            max_failure_rates: max_failure_rate
+                .iter()
+                .map(|s| parse_failure_rate(s))
+                .collect::<Result<Vec<_>>>()?,
+            log_drops,
+            metadata,
+            recording_id,
+            application_id,
+            blueprint,
+            gps_geoid,
+            export_gpx,
+            export_kml,
+            tolerate_corruption,
+            generic_fallback,
+            ignore_md5_mismatch,
+            mapper_script,
+            progress_hook: None,
+            cancel: None,
+            output_target: match connect {
+                Some(url) => OutputTarget::Grpc(url),
+                None if spawn => OutputTarget::Spawn,
+                None if web => OutputTarget::Web { port: web_port, grpc_port: web_grpc_port, keep_serving },
+                None => OutputTarget::File,
+            },
+            rate,
+            rosbridge_encoding: parse_rosbridge_encoding(&rosbridge_encoding)?,
+            pointcloud_rotation: match pointcloud_rotation {
+                Some(rotation_str) => Some(parse_pointcloud_rotation(&rotation_str)?),
+                None => None,
+            },
+            pointcloud_range_image,
+            pointcloud_tf: !no_pointcloud_tf,
+            pointcloud_class_field,
+            pointcloud_keypoint_field,
+            roi: roi.as_deref().map(parse_roi).transpose()?,
+            pointcloud_downsample: pointcloud_downsample
+                .iter()
+                .map(|s| parse_pointcloud_downsample(s))
+                .collect::<Result<Vec<_>>>()?,
+            pointcloud_color_field: pointcloud_color_field
+                .iter()
+                .map(|s| parse_pointcloud_color_field(s))
+                .collect::<Result<Vec<_>>>()?,
+            time_offsets: time_offset
+                .iter()
+                .map(|s| parse_time_offset(s))
+                .collect::<Result<Vec<_>>>()?,
+            max_rates: max_rate.iter().map(|s| parse_max_rate(s)).collect::<Result<Vec<_>>>()?,
+            colors: color.iter().map(|s| parse_color_setting(s)).collect::<Result<Vec<_>>>()?,
+            radii: radius.iter().map(|s| parse_size_setting(s)).collect::<Result<Vec<_>>>()?,
+            line_widths: line_width.iter().map(|s| parse_size_setting(s)).collect::<Result<Vec<_>>>()?,
+            labels: label.iter().map(|s| parse_label_setting(s)).collect::<Result<Vec<_>>>()?,
+        })
+    }
+}
+
+fn parse_pointcloud_rotation(rotation_str: &str) -> Result<[f64; 3]> {
+    let parts: Vec<&str> = rotation_str.split(',').collect();
+    if parts.len() != 3 {
+        return Err(anyhow!("The rotation must contain exactly 3 values separated by commas (roll,pitch,yaw)"));
+    }
+    
+    let roll = parts[0].trim().parse::<f64>()
+        .map_err(|_| anyhow!("Failed to parse roll: '{}'", parts[0]))?;
+    let pitch = parts[1].trim().parse::<f64>()
+        .map_err(|_| anyhow!("Failed to parse pitch: '{}'", parts[1]))?;
+    let yaw = parts[2].trim().parse::<f64>()
+        .map_err(|_| anyhow!("Failed to parse yaw: '{}'", parts[2]))?;
+    
+    Ok([roll, pitch, yaw])
+}
+
+fn parse_value_range(range_str: &str) -> Result<[f64; 2]> {
+    let (min, max) = range_str
+        .split_once(',')
+        .ok_or_else(|| anyhow!("The value range must be \"MIN,MAX\""))?;
+    let min = min.trim().parse::<f64>()
+        .map_err(|_| anyhow!("Failed to parse range min: '{}'", min))?;
+    let max = max.trim().parse::<f64>()
+        .map_err(|_| anyhow!("Failed to parse range max: '{}'", max))?;
+    if max <= min {
+        return Err(anyhow!("The value range max must be greater than min"));
+    }
+    Ok([min, max])
 }
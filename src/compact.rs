@@ -0,0 +1,151 @@
+//! Post-pass merging the small chunks of an .rrd (--compact)
+//!
+//! The batcher cuts chunks on a timer, so a kHz topic ends up in many chunks
+//! of a few rows, and entities that log rarely get a chunk per row. This pass
+//! reads the file back, concatenates consecutive chunks of each entity that
+//! share their timelines and components, up to [`CompactionLimits`], and
+//! writes the result in place of the original. Store infos and other messages
+//! are copied as they are.
+
+use anyhow::{Context, Result};
+use re_chunk::Chunk;
+use re_log_encoding::encoder::Encoder;
+use re_log_encoding::EncodingOptions;
+use re_log_types::{EntityPath, LogMsg, StoreId};
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Size of the merged chunks; the defaults are those of the viewer's own compaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompactionLimits {
+    pub max_rows: u64,
+    pub max_bytes: u64,
+}
+
+impl Default for CompactionLimits {
+    fn default() -> Self {
+        Self { max_rows: 4096, max_bytes: 384 * 1024 }
+    }
+}
+
+/// Chunks of a file before and after compaction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+    pub chunks_before: u64,
+    pub chunks_after: u64,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// Chunk of an entity waiting for the next ones
+struct Pending {
+    chunk: Chunk,
+    bytes: u64,
+}
+
+/// Merge the chunks of the .rrd at `path`, replacing it
+pub fn compact_rrd(path: &Path, limits: CompactionLimits) -> Result<CompactionStats> {
+    let mut stats = CompactionStats { bytes_before: std::fs::metadata(path)?.len(), ..Default::default() };
+    let file = std::fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let decoder = re_log_encoding::decoder::Decoder::new(BufReader::new(file))?;
+
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".compacting");
+    let out = std::fs::File::create(&tmp_path).with_context(|| format!("failed to create {:?}", tmp_path))?;
+    let mut encoder = Encoder::new(
+        re_build_info::CrateVersion::LOCAL,
+        EncodingOptions::PROTOBUF_COMPRESSED,
+        std::io::BufWriter::new(out),
+    )?;
+
+    let mut pending: HashMap<(StoreId, EntityPath), Pending> = HashMap::new();
+    for msg in decoder {
+        let (store_id, arrow_msg) = match msg.context("failed to decode a message")? {
+            LogMsg::ArrowMsg(store_id, arrow_msg) => (store_id, arrow_msg),
+            other => {
+                encoder.append(&other)?;
+                continue;
+            }
+        };
+        stats.chunks_before += 1;
+        let bytes = arrow_msg.batch.get_array_memory_size() as u64;
+        let chunk = Chunk::from_arrow_msg(&arrow_msg)?;
+        let key = (store_id.clone(), chunk.entity_path().clone());
+        let next = match pending.remove(&key) {
+            Some(prev)
+                if prev.chunk.concatenable(&chunk)
+                    && (prev.chunk.num_rows() + chunk.num_rows()) as u64 <= limits.max_rows
+                    && prev.bytes + bytes <= limits.max_bytes =>
+            {
+                Pending { chunk: prev.chunk.concatenated(&chunk)?, bytes: prev.bytes + bytes }
+            }
+            Some(prev) => {
+                write_chunk(&mut encoder, store_id, &prev.chunk, &mut stats)?;
+                Pending { chunk, bytes }
+            }
+            None => Pending { chunk, bytes },
+        };
+        pending.insert(key, next);
+    }
+    let mut rest: Vec<_> = pending.into_iter().collect();
+    rest.sort_by_key(|((_, entity), _)| entity.to_string());
+    for ((store_id, _), last) in rest {
+        write_chunk(&mut encoder, store_id, &last.chunk, &mut stats)?;
+    }
+    encoder.finish()?;
+    drop(encoder);
+
+    std::fs::rename(&tmp_path, path).with_context(|| format!("failed to replace {}", path.display()))?;
+    stats.bytes_after = std::fs::metadata(path)?.len();
+    tracing::info!(
+        path = %path.display(),
+        chunks_before = stats.chunks_before,
+        chunks_after = stats.chunks_after,
+        bytes_before = stats.bytes_before,
+        bytes_after = stats.bytes_after,
+        "compacted RRD"
+    );
+    Ok(stats)
+}
+
+fn write_chunk<W: std::io::Write>(
+    encoder: &mut Encoder<W>,
+    store_id: StoreId,
+    chunk: &Chunk,
+    stats: &mut CompactionStats,
+) -> Result<()> {
+    stats.chunks_after += 1;
+    encoder.append(&LogMsg::ArrowMsg(store_id, chunk.to_arrow_msg()?))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compaction_merges_small_chunks() {
+        let path = std::env::temp_dir().join(format!("bag2rrd_compact_{}.rrd", std::process::id()));
+        {
+            // Flushing after every row leaves one chunk per row
+            let config = rerun::log::ChunkBatcherConfig::ALWAYS;
+            let rec = rerun::RecordingStreamBuilder::new("compact_test").batcher_config(config).save(&path).unwrap();
+            for i in 0..100 {
+                rec.set_time_sequence("frame_index", i);
+                rec.log("/imu/accel", &rerun::archetypes::Scalars::new([i as f64])).unwrap();
+                rec.log("/gps/status", &rerun::archetypes::Scalars::new([0.0])).unwrap();
+            }
+        }
+        let before = crate::validate::validate_rrd(path.to_str().unwrap()).unwrap();
+        let stats = compact_rrd(&path, CompactionLimits::default()).unwrap();
+        assert_eq!(stats.chunks_before, before.chunks);
+        assert!(stats.chunks_after < stats.chunks_before);
+
+        // Same rows, in fewer chunks
+        let after = crate::validate::validate_rrd(path.to_str().unwrap()).unwrap();
+        assert_eq!(after.entities, before.entities);
+        assert_eq!(after.chunks, stats.chunks_after);
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,305 @@
+//! --config files for the convert command (TOML or YAML)
+//!
+//! Top-level keys are the long flag names of `bag2rrd convert` (`tf-mode`,
+//! `image-scale`, ...; `snake_case` is accepted too) and become the defaults of
+//! those flags, so anything given on the command line wins. Per-topic sections
+//! expand into the TOPIC=VALUE form of the matching flags:
+//!
+//! ```toml
+//! root-frame = "map"
+//! tf-mode = "interpolate"
+//! exclude-type = ["sensor_msgs/CameraInfo"]
+//! image-scale = 0.5
+//!
+//! [topics."/thermal/image_raw"]
+//! image-colormap = "inferno"
+//! image-scale = 1.0
+//! time-offset = -0.04
+//!
+//! [topics."/velodyne_points"]
+//! pointcloud-downsample = 4
+//! pointcloud-color-field = "intensity"
+//! entity-path = "/sensors/lidar"
+//!
+//! [topics."/debug/markers"]
+//! exclude = true
+//!
+//! [topics."/camera/image_raw"]
+//! output-group = "cameras"
+//!
+//! [topics."/gps/fix"]
+//! color = "#ff8000"
+//! line-width = 0.3
+//! label = "RTK antenna"
+//! ```
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{ArgAction, Command, CommandFactory, FromArgMatches};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::cli::{Cli, Commands};
+use crate::convert::ConvertOptions;
+
+/// Flags that take TOPIC=VALUE entries, usable inside [topics."/name"] sections
+const TOPIC_FLAGS: &[&str] = &[
+    "image-colormap",
+    "image-scale",
+    "image-crop",
+    "image-every-nth",
+    "pointcloud-downsample",
+    "pointcloud-color-field",
+    "time-offset",
+    "max-rate",
+    "topic-rename",
+    "color",
+    "radius",
+    "line-width",
+    "label",
+];
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+enum ConfigValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    List(Vec<ConfigValue>),
+}
+
+impl ConfigValue {
+    fn to_values(&self) -> Vec<String> {
+        match self {
+            Self::Bool(b) => vec![b.to_string()],
+            Self::Int(i) => vec![i.to_string()],
+            Self::Float(f) => vec![f.to_string()],
+            Self::String(s) => vec![s.clone()],
+            Self::List(items) => items.iter().flat_map(|v| v.to_values()).collect(),
+        }
+    }
+}
+
+/// Default flag values for `bag2rrd convert`, loaded from a config file
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct ConvertConfig {
+    #[serde(default)]
+    topics: BTreeMap<String, BTreeMap<String, ConfigValue>>,
+    #[serde(flatten)]
+    flags: BTreeMap<String, ConfigValue>,
+}
+
+impl ConvertConfig {
+    /// Load a .toml, .yaml or .yml config file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).with_context(|| format!("failed to read config {}", path.display()))?;
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+        match extension.to_ascii_lowercase().as_str() {
+            "toml" => Self::from_toml(&text),
+            "yaml" | "yml" => Self::from_yaml(&text),
+            _ => Err(anyhow!("Unsupported config format '{}': expected .toml, .yaml or .yml", path.display())),
+        }
+        .with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(text)?)
+    }
+
+    /// Values per long flag name, with the per-topic sections expanded
+    pub fn flag_values(&self) -> Result<BTreeMap<String, Vec<String>>> {
+        let mut values: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (key, value) in &self.flags {
+            values.entry(flag_name(key)).or_default().extend(value.to_values());
+        }
+        for (topic, settings) in &self.topics {
+            for (key, value) in settings {
+                // entity-path reads better than topic-rename inside a topic's own section
+                let flag = match flag_name(key).as_str() {
+                    "entity-path" => "topic-rename".to_string(),
+                    flag => flag.to_string(),
+                };
+                match (flag.as_str(), value) {
+                    ("include" | "exclude", ConfigValue::Bool(true)) => {
+                        values.entry(flag).or_default().push(topic.clone());
+                    }
+                    ("include" | "exclude", ConfigValue::Bool(false)) => {}
+                    // The topic's own section names its group rather than a pattern
+                    ("output-group", ConfigValue::String(group)) => {
+                        values.entry(flag).or_default().push(format!("{group}={topic}"));
+                    }
+                    (flag, _) if TOPIC_FLAGS.contains(&flag) => {
+                        let entries = value.to_values().into_iter().map(|v| format!("{topic}={v}"));
+                        values.entry(flag.to_string()).or_default().extend(entries);
+                    }
+                    _ => bail!(
+                        "'{}' in [topics.\"{}\"] is not a per-topic setting (expected include, exclude, entity-path, output-group, {})",
+                        key,
+                        topic,
+                        TOPIC_FLAGS.join(", ")
+                    ),
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// Set the config values as defaults of the `convert` subcommand's flags
+    pub fn apply(&self, cli: Command) -> Result<Command> {
+        let values = self.flag_values()?;
+        let convert = cli.find_subcommand("convert").ok_or_else(|| anyhow!("no convert subcommand"))?;
+        let mut ids = Vec::new();
+        for (flag, flag_values) in &values {
+            let arg = convert
+                .get_arguments()
+                .find(|a| a.get_long() == Some(flag.as_str()) && flag != "config")
+                .ok_or_else(|| anyhow!("Unknown option '{}' in config", flag))?;
+            if flag_values.len() > 1 && !matches!(arg.get_action(), ArgAction::Append) {
+                bail!("Option '{}' in config takes a single value", flag);
+            }
+            ids.push((arg.get_id().clone(), flag_values.clone()));
+        }
+        Ok(cli.mut_subcommand("convert", |mut convert| {
+            for (id, flag_values) in ids {
+                convert = convert.mut_arg(id, |arg| arg.default_values(flag_values));
+            }
+            convert
+        }))
+    }
+}
+
+/// `tf_mode` and `--tf-mode` both name the `tf-mode` flag
+fn flag_name(key: &str) -> String {
+    key.trim_start_matches("--").replace('_', "-")
+}
+
+impl ConvertOptions {
+    /// Options for converting `bag` into `out` with the settings of a config file,
+    /// as `bag2rrd convert BAG OUT --config PATH` would
+    pub fn from_config_file(path: impl AsRef<Path>, bag: &str, out: &str) -> Result<Self> {
+        let cli = ConvertConfig::load(path)?.apply(Cli::command())?;
+        let matches = cli.try_get_matches_from(["bag2rrd", "convert", bag, out])?;
+        match Cli::from_arg_matches(&matches)?.command {
+            Commands::Convert(args) => args.into_options(),
+            _ => unreachable!("parsed a convert command line"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::TopicConfig;
+    use crate::mappings::colormap::Colormap;
+    use crate::mappings::images::TopicSetting;
+    use crate::mappings::rename::RenameRules;
+    use crate::mappings::tf::TfMode;
+
+    fn parse(config: &ConvertConfig, args: &[&str]) -> Result<ConvertOptions> {
+        let matches = config.apply(Cli::command())?.try_get_matches_from(args)?;
+        match Cli::from_arg_matches(&matches)?.command {
+            Commands::Convert(args) => args.into_options(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_config_defaults_and_cli_overrides() {
+        let config = ConvertConfig::from_toml(
+            r#"
+            root-frame = "map"
+            tf_mode = "interpolate"
+            tf-plots = true
+            exclude-type = ["sensor_msgs/CameraInfo", "sensor_msgs/Imu"]
+            image-scale = 0.5
+
+            [topics."/thermal/image_raw"]
+            image-colormap = "inferno"
+            image-scale = 1.0
+
+            [topics."/debug/markers"]
+            exclude = true
+
+            [topics."/camera/image_raw"]
+            output-group = "cameras"
+
+            [topics."/velodyne_points"]
+            pointcloud-downsample = 4
+            pointcloud-color-field = "intensity"
+            time-offset = -0.05
+            entity-path = "/sensors/lidar"
+            "#,
+        )
+        .unwrap();
+
+        let options = parse(&config, &["bag2rrd", "convert", "in.bag", "out.rrd"]).unwrap();
+        assert_eq!(options.root_frame, "map");
+        assert_eq!(options.tf_mode, TfMode::Interpolate);
+        assert!(options.tf_plots);
+        assert_eq!(options.exclude_types, ["sensor_msgs/CameraInfo", "sensor_msgs/Imu"]);
+        assert_eq!(options.exclude_topics, ["/debug/markers"]);
+        assert_eq!(options.output_groups, ["cameras=/camera/image_raw"]);
+        assert_eq!(
+            options.image_scale,
+            [
+                TopicSetting { topic: None, value: 0.5 },
+                TopicSetting { topic: Some("/thermal/image_raw".to_string()), value: 1.0 },
+            ]
+        );
+        assert_eq!(
+            options.image_colormap,
+            [TopicSetting { topic: Some("/thermal/image_raw".to_string()), value: Colormap::Inferno }]
+        );
+        let renames = RenameRules::new(&options.topic_renames).unwrap();
+        let lidar = TopicConfig::resolve(&options, &renames, "/velodyne_points");
+        assert_eq!(lidar.pointcloud_downsample, 4);
+        assert_eq!(lidar.pointcloud_color_field.as_deref(), Some("intensity"));
+        assert_eq!(lidar.time_offset, -0.05);
+        assert_eq!(lidar.entity("/velodyne_points"), "/sensors/lidar");
+        let thermal = TopicConfig::resolve(&options, &renames, "/thermal/image_raw");
+        assert_eq!(thermal.image_scale, Some(1.0));
+        assert_eq!(thermal.time_offset, 0.0);
+        assert_eq!(thermal.entity("/thermal/image_raw"), "/thermal/image_raw");
+
+        // Command line flags replace the config values
+        let options = parse(
+            &config,
+            &["bag2rrd", "convert", "in.bag", "out.rrd", "--root-frame", "world", "--exclude-type", "sensor_msgs/Image"],
+        )
+        .unwrap();
+        assert_eq!(options.root_frame, "world");
+        assert_eq!(options.tf_mode, TfMode::Interpolate);
+        assert_eq!(options.exclude_types, ["sensor_msgs/Image"]);
+    }
+
+    #[test]
+    fn test_yaml_config_matches_toml() {
+        let yaml = ConvertConfig::from_yaml(
+            "tf-mode: interpolate\nimage-scale: 0.5\ntopics:\n  /thermal/image_raw:\n    image-colormap: inferno\n",
+        )
+        .unwrap();
+        let toml = ConvertConfig::from_toml(
+            "tf-mode = \"interpolate\"\nimage-scale = 0.5\n[topics.\"/thermal/image_raw\"]\nimage-colormap = \"inferno\"\n",
+        )
+        .unwrap();
+        assert_eq!(yaml.flag_values().unwrap(), toml.flag_values().unwrap());
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let unknown = ConvertConfig::from_toml("no-such-flag = 1").unwrap();
+        assert!(unknown.apply(Cli::command()).is_err());
+        let not_per_topic = ConvertConfig::from_toml("[topics.\"/a\"]\ntf-mode = \"nearest\"").unwrap();
+        assert!(not_per_topic.flag_values().is_err());
+        let repeated = ConvertConfig::from_toml("root-frame = [\"a\", \"b\"]").unwrap();
+        assert!(repeated.apply(Cli::command()).is_err());
+        let invalid = ConvertConfig::from_toml("tf-mode = \"sometimes\"").unwrap();
+        assert!(parse(&invalid, &["bag2rrd", "convert", "in.bag", "out.rrd"]).is_err());
+    }
+}
@@ -1,50 +1,172 @@
 use anyhow::{Context, Result};
 use flume::{Receiver, Sender};
-use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use rosbag::record_types::MessageData;
 use rosbag::{ChunkRecord, MessageRecord, RosBag};
-use std::collections::HashSet;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::{
-    Arc,
+    Arc, Mutex,
     atomic::{AtomicBool, Ordering},
 };
 use std::time::Instant;
 
-use crate::mappings::tf::TfMode;
+use crate::mappings::camera::CameraGroup;
+use crate::mappings::colormap::Colormap;
+use crate::mappings::images::{
+    decode_compressed, decode_image, parse_topic_setting, setting_for_topic, DecodedImage,
+    ImageColormap, ImageCrop, ImageEncoding, ImageOptions, TopicSetting,
+};
+use crate::mappings::laserscan::{MultiEchoMode, ScanColorBy};
+use crate::mappings::registry::{Mapped, MapperContext, MapperRegistry, MessageKind, MessageMapper};
+use crate::mappings::rename::{EntityRenames, RenameRules};
+use crate::mappings::roi::RegionOfInterest;
+use crate::mappings::scalars::ScalarColumns;
+use crate::mappings::style::Style;
+use crate::mappings::tf::{TfAuthority, TfGraph, TfMode};
+use crate::blueprint::{blueprint_path, save_blueprint, BlueprintLayout};
+use crate::compact::{compact_rrd, CompactionLimits};
+use crate::events::{ConvertEvent, ConvertStats, ProgressHook, PROGRESS_INTERVAL};
+use crate::filter::MessageFilter;
+use crate::interrupt::CancellationToken;
+use crate::manifest::{SegmentContents, SegmentEntry, SegmentManifest};
+use crate::memory::{peak_rss_bytes, MemoryBudget, MemoryTracker};
+use crate::multi_bag::{expand_bag_paths, ConnectionMap};
+use crate::plan::ConversionPlan;
+use crate::drops::{log_gap, SeqGap, SeqTracker};
+use crate::report::{finish_report, FailureRate, UnmappedReason, UnmappedTypes};
+use crate::progress::ConvertProgress;
+use crate::robot_model::RobotModel;
+use crate::ros_msg::{Md5Check, TypeInfo};
+use crate::rosbags_io::{read_chunk_at, BagIndex, BagLayout, ChunkScan, ChunkSpan};
+use crate::source::rosbridge::RosbridgeEncoding;
+use crate::tf_analysis::TfThresholds;
+use crate::timeline::Timelines;
+use crate::world::{GroundGrid, WorldAnnotations};
 
 /// Options for converting a ROS bag file to Rerun RRD format
-#[derive(Debug, Clone)]
+///
+/// Built with [`ConvertOptions::new`] and the setters named after each field;
+/// new fields may be added in any release. Serialized without its file paths
+/// and hooks, as the `provenance/options` recording property.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
 pub struct ConvertOptions {
     /// Path to the input .bag file
+    #[serde(skip)]
     pub bag_path: String,
+    /// Further bags (or directories/globs of bags) merged with `bag_path` on one timeline
+    #[serde(skip)]
+    pub extra_bags: Vec<String>,
     /// Path to the output .rrd file
+    #[serde(skip)]
     pub output_path: String,
-    /// Include only these topics (empty means include all)
+    /// Include only these topics (empty means include all); exact, glob or regex
     pub include_topics: Vec<String>,
     /// Exclude these topics
     pub exclude_topics: Vec<String>,
+    /// Include only these message types, e.g. "sensor_msgs/*" (empty means include all)
+    pub include_types: Vec<String>,
+    /// Exclude these message types, e.g. "sensor_msgs/Image"
+    pub exclude_types: Vec<String>,
     /// Start time offset in seconds from bag start
     pub start_time: Option<f64>,
     /// End time offset in seconds from bag start
     pub end_time: Option<f64>,
-    /// Dry run: show plan but don't write output
+    /// Dry run: print the per-topic plan (mapping, messages, estimated size, parts) but don't write output
     pub dry_run: bool,
-    /// Show progress bar
+    /// Show a progress bar on stderr: percentage, ETA, read rate and current topic,
+    /// or a spinner for unindexed bags read in groups
     pub show_progress: bool,
     /// Segment size (images kept) for parallel flush
     pub segment_size: Option<usize>,
     /// Use LineStrips2D instead of Points2D for LaserScan
     pub scan_as_lines: bool,
+    /// Log LaserScan as 3D primitives placed in the root frame via TF
+    pub scan_3d: bool,
+    /// Color LaserScan points by intensity or range
+    pub scan_color: ScanColorBy,
+    /// Colormap for LaserScan coloring
+    pub scan_colormap: Colormap,
+    /// Which echoes of MultiEchoLaserScan to log
+    pub multi_echo: MultiEchoMode,
+    /// Accumulate the last N scans (in the root frame) into /<topic>/accumulated
+    pub scan_accumulate: Option<usize>,
+    /// Colormaps for mono8/mono16 images, global or per topic
+    pub image_colormap: Vec<ImageColormap>,
+    /// Value range [min, max] mapped onto the image colormap (auto when unset)
+    pub image_value_range: Option<[f64; 2]>,
+    /// Resize factors for images, global or per topic
+    pub image_scale: Vec<TopicSetting<f64>>,
+    /// Pixel rectangles images are cropped to before scaling, global or per topic
+    pub image_crop: Vec<TopicSetting<ImageCrop>>,
+    /// Keep only every Nth image, global or per topic
+    pub image_every_nth: Vec<TopicSetting<u64>>,
+    /// Storage for raw sensor_msgs/Image pixels (raw, JPEG or PNG)
+    pub image_encoding: ImageEncoding,
+    /// Log CompressedImage JPEG/PNG payloads as EncodedImage without decoding
+    pub compressed_passthrough: bool,
+    /// Back-project depth images into /<topic>/points using the sibling camera_info
+    pub depth_to_points: bool,
+    /// mono16 topics projected as depth in millimeters (16UC1 and 32FC1 always are)
+    pub depth_topics: Vec<String>,
+    /// Registered color image topic used to color back-projected depth points
+    pub depth_color_topic: Option<String>,
+    /// Camera groups: images, Pinhole and TF of each camera under one entity subtree
+    pub camera_groups: Vec<CameraGroup>,
+    /// Timestamp messages by header.stamp or by bag record time
+    pub timestamp_source: TimestampSource,
+    /// Use rosgraph_msgs/Clock (sim time) as the time axis instead of bag time
+    pub sim_time: bool,
+    /// Accumulate nav_msgs/Odometry positions into a trajectory polyline per topic
+    pub odom_trajectory: bool,
+    /// Maximum trajectory length in points, oldest dropped first (0 = unlimited)
+    pub odom_trajectory_max_points: usize,
+    /// Add one trajectory point every N odometry messages
+    pub odom_trajectory_every_nth: u64,
     /// GPS origin for ENU projection: "LAT,LON,ALT"
     pub gps_origin: Option<String>,
     /// Log a polyline path for GPS track
     pub gps_path: bool,
     /// Path to EGM96 geoid grid file for altitude correction
+    #[serde(skip)]
     pub gps_geoid: Option<String>,
+    /// Write the GPS tracks to this GPX file
+    #[serde(skip)]
+    pub export_gpx: Option<String>,
+    /// Write the GPS tracks to this KML file
+    #[serde(skip)]
+    pub export_kml: Option<String>,
     /// Segment size in bytes for parallel flush
     pub segment_bytes: Option<u64>,
+    /// Segment duration in seconds of bag time
+    pub segment_seconds: Option<f64>,
+    /// Align --segment-seconds boundaries to multiples of the duration (epoch time)
+    pub segment_align: bool,
+    /// NAME=PATTERN: topics matching PATTERN are written to `<stem>_NAME.rrd`
+    pub output_groups: Vec<String>,
+    /// Write every topic to its own `<stem>_<topic>.rrd`
+    pub split_topics: bool,
     /// Number of parallel flush workers
     pub flush_workers: usize,
+    /// Write a `<part>.sha256` checksum next to each segment
+    pub segment_checksum: bool,
+    /// Continue a segmented conversion after the last complete part in its manifest
+    pub resume: bool,
+    /// Threads decompressing chunks and decoding images (0 uses every core)
+    pub decode_threads: usize,
+    /// Memory budget in bytes for buffered chunks, decoded images and the logging backlog
+    pub max_memory: Option<u64>,
+    /// Batcher flush interval in milliseconds; rerun's default otherwise
+    pub batch_flush_ms: Option<u64>,
+    /// Logged bytes after which the batcher cuts a chunk
+    pub batch_flush_bytes: Option<u64>,
+    /// Logged rows after which the batcher cuts a chunk
+    pub batch_flush_rows: Option<u64>,
+    /// Merge the chunks of the written .rrd files after the conversion
+    pub compact: bool,
     /// Root frame name for transforms
     pub root_frame: String,
     /// Map ROS frame names to Rerun entity paths: FRAME=/rr/path
@@ -55,21 +177,540 @@ pub struct ConvertOptions {
     pub tf_buffer_seconds: f64,
     /// TF sampling mode
     pub tf_mode: TfMode,
-    /// Key=value metadata entries to embed in the RRD
+    /// Max seconds between a lookup and a dynamic TF sample for the edge to be traversed
+    pub tf_tolerance: f64,
+    /// Resolve through stale edges when no TF path is within tf_tolerance
+    pub tf_fallback: bool,
+    /// Which parent wins when a child frame is published under several parents
+    pub tf_authority: TfAuthority,
+    /// Log sensor topics under the entity of their header.frame_id so TF moves them
+    pub attach_to_frames: bool,
+    /// Plot each dynamic TF edge's translation and yaw/pitch/roll under /tf_plots
+    pub tf_plots: bool,
+    /// Draw coordinate axes of this length (meters) at each TF frame
+    pub tf_axes: Option<f32>,
+    /// Only draw axes for frames whose name matches this regex
+    pub tf_axes_filter: Option<String>,
+    /// URDF whose link visuals are shown at their TF frames
+    #[serde(skip)]
+    pub urdf: Option<String>,
+    /// FRAME=PATH meshes (glTF, GLB, OBJ, STL) shown at a TF frame
+    #[serde(skip)]
+    pub meshes: Vec<String>,
+    /// Static ground grid under /annotations
+    pub ground_grid: Option<GroundGrid>,
+    /// North arrow under /annotations, when the bag has GPS fixes
+    pub north_arrow: bool,
+    /// YAML list of named points logged under /annotations/landmarks
+    #[serde(skip)]
+    pub landmarks: Option<String>,
+    /// YAML map of class ids to labels and colors, logged as the AnnotationContext of the root entity
+    #[serde(skip)]
+    pub class_map: Option<String>,
+    /// Report TF jumps, quaternion flips and stamps going backwards after conversion
+    pub analyze_tf: Option<TfThresholds>,
+    /// Write the counters of the conversion and the types it did not map to this JSON file
+    #[serde(skip)]
+    pub report_path: Option<String>,
+    /// Fail on the first message a mapper cannot parse, for kinds without a --max-failure-rate
+    pub strict: bool,
+    /// Share of the messages of a kind allowed to fail mapping before the conversion fails
+    pub max_failure_rates: Vec<FailureRate>,
+    /// Log a TextLog under /diagnostics/drops for every gap in the header.seq of a topic
+    pub log_drops: bool,
+    /// Key=value entries sent as the recording properties `metadata/<key>`
+    #[serde(skip)]
     pub metadata: Vec<String>,
+    /// Recording id of every output; by default the md5sum of the bag, random for live sources
+    pub recording_id: Option<String>,
+    /// Application id the viewer groups recordings under
+    pub application_id: String,
+    /// Write a blueprint with views of the logged cameras, scalars and GPS fixes next to the output (`<out>.rbl`)
+    pub blueprint: bool,
     /// Tolerate bag file corruption by skipping corrupted chunks
     pub tolerate_corruption: bool,
+    /// Log messages of unsupported types from their embedded definition: numbers as Scalars, strings as TextLog
+    pub generic_fallback: bool,
+    /// Parse mapped types as their standard definition even when the bag's md5sum differs
+    pub ignore_md5_mismatch: bool,
+    /// Rhai script mapping in-house message types (needs the `scripting` feature)
+    #[serde(skip)]
+    pub mapper_script: Option<String>,
+    /// Receives progress counters, completed segments and warnings while converting
+    #[serde(skip)]
+    pub progress_hook: Option<ProgressHook>,
+    /// Stops the conversion like Ctrl-C: the output converted so far is finalized
+    /// and the conversion returns [`Interrupted`](crate::interrupt::Interrupted)
+    #[serde(skip)]
+    pub cancel: Option<CancellationToken>,
+    /// Where the recording goes; segmentation and split outputs need [`OutputTarget::File`]
+    #[serde(skip)]
+    pub output_target: OutputTarget,
+    /// Pace messages at this multiple of their recorded rate, 1.0 being realtime,
+    /// so a viewer watches the bag play back; 0 converts as fast as possible
+    pub rate: f64,
+    /// How a rosbridge server (`ws://` input) sends messages
+    pub rosbridge_encoding: RosbridgeEncoding,
     /// Point cloud rotation in degrees as [roll, pitch, yaw] (XYZ Euler angles)
     pub pointcloud_rotation: Option<[f64; 3]>,
+    /// Also log organized PointCloud2 messages as range images
+    pub pointcloud_range_image: bool,
+    /// Transform point clouds from their header frame into the root frame via TF
+    pub pointcloud_tf: bool,
+    /// PointCloud2 field to log as per-point class ids (e.g. "label")
+    pub pointcloud_class_field: Option<String>,
+    /// PointCloud2 field to log as per-point keypoint ids (e.g. "ring")
+    pub pointcloud_keypoint_field: Option<String>,
+    /// Box of the root frame point clouds and laser scans are cropped to
+    pub roi: Option<RegionOfInterest>,
+    /// Keep every Nth point of point clouds, globally or per topic
+    pub pointcloud_downsample: Vec<TopicSetting<usize>>,
+    /// Scalar point field coloring point clouds (turbo colormap), globally or per topic
+    pub pointcloud_color_field: Vec<TopicSetting<String>>,
+    /// Seconds added to message times (e.g. sensor latency), globally or per topic
+    pub time_offsets: Vec<TopicSetting<f64>>,
+    /// Most messages per second kept of a topic, globally or per topic; latched topics are never thinned
+    pub max_rates: Vec<TopicSetting<f64>>,
+    /// Color of points and lines: COLOR or TOPIC=COLOR
+    pub colors: Vec<TopicSetting<[u8; 4]>>,
+    /// Point radius in meters: METERS or TOPIC=METERS
+    pub radii: Vec<TopicSetting<f32>>,
+    /// Line width in meters: METERS or TOPIC=METERS
+    pub line_widths: Vec<TopicSetting<f32>>,
+    /// Label shown next to points and lines: TEXT or TOPIC=TEXT
+    pub labels: Vec<TopicSetting<String>>,
+}
+
+impl Default for ConvertOptions {
+    /// The defaults of `bag2rrd convert`, read from its flag definitions, with empty
+    /// bag and output paths
+    fn default() -> Self {
+        use clap::{Args, FromArgMatches};
+        let matches = crate::cli::ConvertArgs::augment_args(clap::Command::new("convert"))
+            .try_get_matches_from(["convert", "", ""])
+            .expect("convert flags have defaults");
+        crate::cli::ConvertArgs::from_arg_matches(&matches)
+            .map_err(anyhow::Error::from)
+            .and_then(crate::cli::ConvertArgs::into_options)
+            .expect("convert flag defaults are valid")
+    }
+}
+
+impl ConvertOptions {
+    /// Options for converting `bag` into `out` with the defaults of `bag2rrd convert`;
+    /// the setters below change the other fields
+    pub fn new(bag: impl Into<String>, out: impl Into<String>) -> Self {
+        Self {
+            bag_path: bag.into(),
+            output_path: out.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Send `event` to the progress hook, if any
+    pub(crate) fn emit(&self, event: ConvertEvent) {
+        if let Some(hook) = &self.progress_hook {
+            hook.emit(&event);
+        }
+    }
+
+    /// Log a warning and report it to the progress hook
+    pub(crate) fn warn(&self, warning: String) {
+        tracing::warn!("{}", warning);
+        self.emit(ConvertEvent::Warning(warning));
+    }
+
+    /// Whether Ctrl-C was pressed or the cancellation token cancelled
+    pub(crate) fn cancelled(&self) -> bool {
+        crate::interrupt::interrupted() || self.cancel.as_ref().is_some_and(|c| c.is_cancelled())
+    }
+}
+
+/// One chainable setter per field, named after it. Optional fields take the value
+/// itself, e.g. `.segment_size(100)`
+macro_rules! setters {
+    ($($field:ident: $kind:ident $ty:ty;)*) => {
+        impl ConvertOptions {
+            $(setters!(@one $field $kind $ty);)*
+        }
+    };
+    (@one $field:ident value $ty:ty) => {
+        #[doc = concat!("Set `", stringify!($field), "`")]
+        pub fn $field(mut self, value: $ty) -> Self {
+            self.$field = value;
+            self
+        }
+    };
+    (@one $field:ident some $ty:ty) => {
+        #[doc = concat!("Set `", stringify!($field), "`")]
+        pub fn $field(mut self, value: $ty) -> Self {
+            self.$field = Some(value);
+            self
+        }
+    };
+    (@one $field:ident into $ty:ty) => {
+        #[doc = concat!("Set `", stringify!($field), "`")]
+        pub fn $field(mut self, value: impl Into<$ty>) -> Self {
+            self.$field = value.into();
+            self
+        }
+    };
+    (@one $field:ident some_into $ty:ty) => {
+        #[doc = concat!("Set `", stringify!($field), "`")]
+        pub fn $field(mut self, value: impl Into<$ty>) -> Self {
+            self.$field = Some(value.into());
+            self
+        }
+    };
+    (@one $field:ident strings $ty:ty) => {
+        #[doc = concat!("Set `", stringify!($field), "`")]
+        pub fn $field(mut self, values: impl IntoIterator<Item = impl Into<$ty>>) -> Self {
+            self.$field = values.into_iter().map(Into::into).collect();
+            self
+        }
+    };
+}
+
+setters! {
+    extra_bags: strings String;
+    include_topics: strings String;
+    exclude_topics: strings String;
+    include_types: strings String;
+    exclude_types: strings String;
+    start_time: some f64;
+    end_time: some f64;
+    dry_run: value bool;
+    show_progress: value bool;
+    segment_size: some usize;
+    scan_as_lines: value bool;
+    scan_3d: value bool;
+    scan_color: value ScanColorBy;
+    scan_colormap: value Colormap;
+    multi_echo: value MultiEchoMode;
+    scan_accumulate: some usize;
+    image_colormap: value Vec<ImageColormap>;
+    image_value_range: some [f64; 2];
+    image_scale: value Vec<TopicSetting<f64>>;
+    image_crop: value Vec<TopicSetting<ImageCrop>>;
+    image_every_nth: value Vec<TopicSetting<u64>>;
+    image_encoding: value ImageEncoding;
+    compressed_passthrough: value bool;
+    depth_to_points: value bool;
+    depth_topics: strings String;
+    depth_color_topic: some_into String;
+    camera_groups: value Vec<CameraGroup>;
+    timestamp_source: value TimestampSource;
+    sim_time: value bool;
+    odom_trajectory: value bool;
+    odom_trajectory_max_points: value usize;
+    odom_trajectory_every_nth: value u64;
+    gps_origin: some_into String;
+    gps_path: value bool;
+    gps_geoid: some_into String;
+    export_gpx: some_into String;
+    export_kml: some_into String;
+    segment_bytes: some u64;
+    segment_seconds: some f64;
+    segment_align: value bool;
+    output_groups: strings String;
+    split_topics: value bool;
+    flush_workers: value usize;
+    segment_checksum: value bool;
+    resume: value bool;
+    decode_threads: value usize;
+    max_memory: some u64;
+    batch_flush_ms: some u64;
+    batch_flush_bytes: some u64;
+    batch_flush_rows: some u64;
+    compact: value bool;
+    root_frame: into String;
+    frame_mappings: strings String;
+    topic_renames: strings String;
+    tf_buffer_seconds: value f64;
+    tf_mode: value TfMode;
+    tf_tolerance: value f64;
+    tf_fallback: value bool;
+    tf_authority: value TfAuthority;
+    attach_to_frames: value bool;
+    tf_plots: value bool;
+    tf_axes: some f32;
+    tf_axes_filter: some_into String;
+    urdf: some_into String;
+    meshes: strings String;
+    ground_grid: some GroundGrid;
+    north_arrow: value bool;
+    landmarks: some_into String;
+    class_map: some_into String;
+    analyze_tf: some TfThresholds;
+    report_path: some_into String;
+    strict: value bool;
+    max_failure_rates: value Vec<FailureRate>;
+    log_drops: value bool;
+    metadata: strings String;
+    recording_id: some_into String;
+    application_id: into String;
+    blueprint: value bool;
+    tolerate_corruption: value bool;
+    generic_fallback: value bool;
+    ignore_md5_mismatch: value bool;
+    mapper_script: some_into String;
+    progress_hook: some ProgressHook;
+    cancel: some CancellationToken;
+    output_target: value OutputTarget;
+    rate: value f64;
+    rosbridge_encoding: value RosbridgeEncoding;
+    pointcloud_rotation: some [f64; 3];
+    pointcloud_range_image: value bool;
+    pointcloud_tf: value bool;
+    pointcloud_class_field: some_into String;
+    pointcloud_keypoint_field: some_into String;
+    roi: some RegionOfInterest;
+    pointcloud_downsample: value Vec<TopicSetting<usize>>;
+    pointcloud_color_field: value Vec<TopicSetting<String>>;
+    time_offsets: value Vec<TopicSetting<f64>>;
+    max_rates: value Vec<TopicSetting<f64>>;
+    colors: value Vec<TopicSetting<[u8; 4]>>;
+    radii: value Vec<TopicSetting<f32>>;
+    line_widths: value Vec<TopicSetting<f32>>;
+    labels: value Vec<TopicSetting<String>>;
+}
+
+/// Settings of one topic: its TOPIC=VALUE options, else the global ones
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TopicConfig {
+    pub image_scale: Option<f64>,
+    pub image_crop: Option<ImageCrop>,
+    pub image_every_nth: u64,
+    pub image_colormap: Option<Colormap>,
+    pub pointcloud_downsample: usize,
+    pub pointcloud_color_field: Option<String>,
+    /// --topic-rename target replacing the topic as entity path
+    pub entity_path: Option<String>,
+    /// Seconds added to the message time
+    pub time_offset: f64,
+    /// --max-rate of the topic, in Hz
+    pub max_rate: Option<f64>,
+    /// File-name suffix of the split output holding the topic; None for the main output
+    pub output_group: Option<String>,
+    pub style: Style,
+}
+
+impl TopicConfig {
+    pub fn resolve(options: &ConvertOptions, topic_renames: &RenameRules, topic: &str) -> Self {
+        Self {
+            image_scale: setting_for_topic(&options.image_scale, topic).copied(),
+            image_crop: setting_for_topic(&options.image_crop, topic).copied(),
+            image_every_nth: setting_for_topic(&options.image_every_nth, topic).copied().unwrap_or(1),
+            image_colormap: setting_for_topic(&options.image_colormap, topic).copied(),
+            pointcloud_downsample: setting_for_topic(&options.pointcloud_downsample, topic).copied().unwrap_or(1),
+            pointcloud_color_field: setting_for_topic(&options.pointcloud_color_field, topic).cloned(),
+            entity_path: topic_renames.rename(topic),
+            time_offset: setting_for_topic(&options.time_offsets, topic).copied().unwrap_or(0.0),
+            max_rate: setting_for_topic(&options.max_rates, topic).copied(),
+            output_group: if options.split_topics {
+                Some(topic.trim_matches('/').replace('/', "_"))
+            } else {
+                crate::filter::output_group(&options.output_groups, topic)
+            },
+            style: Style {
+                color: setting_for_topic(&options.colors, topic).copied(),
+                radius: setting_for_topic(&options.radii, topic).copied(),
+                line_width: setting_for_topic(&options.line_widths, topic).copied(),
+                label: setting_for_topic(&options.labels, topic).cloned(),
+            },
+        }
+    }
+
+    /// Entity path of the topic's data
+    pub fn entity<'a>(&'a self, topic: &'a str) -> &'a str {
+        self.entity_path.as_deref().unwrap_or(topic)
+    }
+}
+
+/// Parse "SECONDS" or "TOPIC=SECONDS" for --time-offset
+pub fn parse_time_offset(s: &str) -> Result<TopicSetting<f64>> {
+    parse_topic_setting(s, |v| match v.parse::<f64>() {
+        Ok(offset) if offset.is_finite() => Ok(offset),
+        _ => Err(anyhow::anyhow!("Invalid time offset (expected seconds): {}", v)),
+    })
+}
+
+/// Parse "HZ" or "TOPIC=HZ" for --max-rate
+pub fn parse_max_rate(s: &str) -> Result<TopicSetting<f64>> {
+    parse_topic_setting(s, |v| match v.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(anyhow::anyhow!("Invalid max rate (expected Hz > 0): {}", v)),
+    })
+}
+
+/// Thins topics to their --max-rate
+///
+/// A topic keeps one message per period. Slots follow the first kept message
+/// and take a message up to a quarter period early, so a topic published at
+/// the limit loses nothing to jitter; they restart after a silence.
+#[derive(Debug, Default)]
+pub(crate) struct RateLimiter {
+    /// Time from which the next message of each topic is kept, in seconds
+    next: HashMap<String, f64>,
+}
+
+impl RateLimiter {
+    /// Whether to keep a message of `topic` at `time` seconds; always with no `max_rate`
+    pub(crate) fn keep(&mut self, topic: &str, max_rate: Option<f64>, time: f64) -> bool {
+        let Some(rate) = max_rate else {
+            return true;
+        };
+        let period = 1.0 / rate;
+        match self.next.get_mut(topic) {
+            Some(next) if time < *next - period / 4.0 => false,
+            Some(next) => {
+                *next = if time - *next < period { *next + period } else { time + period };
+                true
+            }
+            None => {
+                self.next.insert(topic.to_string(), time + period);
+                true
+            }
+        }
+    }
+}
+
+/// Where a conversion writes its recording
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub enum OutputTarget {
+    /// The .rrd file at `output_path`, or its segments and split files
+    #[default]
+    File,
+    /// Encoded .rrd bytes, handed to the [`MemoryOutput`] once the conversion ends
+    Memory(MemoryOutput),
+    /// A recording stream created by the caller with its own sink and recording id;
+    /// flushed at the end but left open
+    Stream(rerun::RecordingStream),
+    /// A viewer or server at this gRPC URL, e.g. [`DEFAULT_VIEWER_URL`]
+    Grpc(String),
+    /// A viewer started for the conversion (the `rerun` binary must be on the PATH)
+    Spawn,
+    /// The web viewer served on `port` of this machine, its data from a gRPC server on
+    /// `grpc_port` (needs the `web` feature). With `keep_serving` the conversion returns
+    /// on Ctrl-C or cancellation instead of once the recording is flushed
+    Web { port: u16, grpc_port: u16, keep_serving: bool },
+}
+
+/// Port of the web viewer served by [`OutputTarget::Web`] unless given
+pub const DEFAULT_WEB_PORT: u16 = 9090;
+
+/// Port of the gRPC server feeding the web viewer of [`OutputTarget::Web`] unless given
+pub const DEFAULT_WEB_GRPC_PORT: u16 = 9876;
+
+/// gRPC URL of a Rerun viewer running on this machine with its default port
+pub const DEFAULT_VIEWER_URL: &str = "rerun+http://127.0.0.1:9876/proxy";
+
+/// Bytes of an in-memory recording; clones share them
+#[derive(Clone, Debug, Default)]
+pub struct MemoryOutput(Arc<Mutex<Vec<u8>>>);
+
+impl MemoryOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The .rrd bytes of the recording, leaving the output empty
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
 }
 
-#[derive(Debug)]
-#[allow(dead_code)]
+/// Which clock drives the "ros_time" timeline
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum TimestampSource {
+    /// header.stamp of the message, falling back to bag time when missing or zero
+    #[default]
+    Header,
+    /// Time the message was recorded into the bag
+    Bag,
+}
+
+pub fn parse_timestamp_source(s: &str) -> Result<TimestampSource> {
+    match s {
+        "header" => Ok(TimestampSource::Header),
+        "bag" => Ok(TimestampSource::Bag),
+        _ => Err(anyhow::anyhow!("Invalid timestamp source: {}", s)),
+    }
+}
+
+/// Mapped message types that start with std_msgs/Header
+pub(crate) fn starts_with_header(tp: &str) -> bool {
+    matches!(
+        tp,
+        "sensor_msgs/Image"
+            | "sensor_msgs/CompressedImage"
+            | "sensor_msgs/CameraInfo"
+            | "sensor_msgs/PointCloud2"
+            | "sensor_msgs/LaserScan"
+            | "sensor_msgs/MultiEchoLaserScan"
+            | "sensor_msgs/NavSatFix"
+            | "sensor_msgs/Imu"
+            | "nav_msgs/Odometry"
+            | "nav_msgs/Path"
+            | "geometry_msgs/PoseStamped"
+            | "ffmpeg_image_transport_msgs/FFMPEGPacket"
+            | "theora_image_transport/Packet"
+    )
+}
+
+/// Messages logged again by --resume even before the resume point: static TF, latched
+/// topics and camera intrinsics, published once or rarely but needed by the later parts
+fn resume_context(topic: &str, tp: &str, latched: bool) -> bool {
+    latched || topic.trim_start_matches('/') == "tf_static" || tp == "sensor_msgs/CameraInfo"
+}
+
+/// header.stamp (seconds) of message types that start with std_msgs/Header; None if zero
+pub(crate) fn header_stamp(tp: &str, payload: &[u8]) -> Option<f64> {
+    let offset = match tp {
+        "foxglove_msgs/CompressedVideo" => 0, // bare time field
+        _ if starts_with_header(tp) => 4,     // after seq
+        _ => return None,
+    };
+    let stamp = crate::ros_codec::Cursor::at(payload, offset).time().ok()?;
+    (stamp != 0.0).then_some(stamp)
+}
+
+/// header.seq of a message starting with a std_msgs/Header
+pub(crate) fn header_seq(payload: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(payload.get(..4)?.try_into().ok()?))
+}
+
+/// header.frame_id of a message starting with a std_msgs/Header
+fn header_frame_id(payload: &[u8]) -> Option<&str> {
+    let len = u32::from_le_bytes(payload.get(12..16)?.try_into().ok()?) as usize;
+    let frame = std::str::from_utf8(payload.get(16..16 + len)?).ok()?;
+    (!frame.is_empty()).then_some(frame)
+}
+
+/// Entity path of a sensor topic attached to its frame: /<root>/<frame>/<topic>
+pub(crate) fn frame_attached_path(topic: &str, payload: &[u8], root_frame: &str, frame_mappings: &RenameRules) -> Option<String> {
+    let frame = header_frame_id(payload)?;
+    let frame_path = if frame == root_frame {
+        format!("/{root_frame}")
+    } else {
+        crate::mappings::tf::map_frame_to_path(frame, root_frame, frame_mappings)
+    };
+    Some(format!("{}/{}", frame_path.trim_end_matches('/'), topic.trim_start_matches('/')))
+}
+
+/// A finished segment handed to a flush worker
 struct FlushJob {
     part_index: u32,
+    /// The segment's recording; the worker drops the last handle, which closes the file
+    rec: rerun::RecordingStream,
     tmp_path: PathBuf,
     final_path: PathBuf,
-    raw_bytes_in_part: u64,
+    /// Also write `<final_path>.sha256`
+    checksum: bool,
+    /// Merge the chunks of the part before moving it into place
+    compact: bool,
+    contents: SegmentContents,
 }
 
 /// Convert a ROS bag file to Rerun RRD format
@@ -87,89 +728,213 @@ struct FlushJob {
 /// ```rust,no_run
 /// use bag2rrd::{convert_bag, ConvertOptions, TfMode};
 ///
-/// let options = ConvertOptions {
-///     bag_path: "input.bag".to_string(),
-///     output_path: "output.rrd".to_string(),
-///     include_topics: vec![],
-///     exclude_topics: vec![],
-///     start_time: None,
-///     end_time: None,
-///     dry_run: false,
-///     show_progress: true,
-///     segment_size: None,
-///     scan_as_lines: false,
-///     gps_origin: None,
-///     gps_path: true,
-///     segment_bytes: None,
-///     flush_workers: 2,
-///     root_frame: "world".to_string(),
-///     frame_mappings: vec![],
-///     topic_renames: vec![],
-///     tf_buffer_seconds: 30.0,
-///     tf_mode: TfMode::Nearest,
-///     metadata: vec![],
-///     gps_geoid: None,
-///     tolerate_corruption: false,
-/// };
+/// let options = ConvertOptions::new("input.bag", "output.rrd")
+///     .exclude_types(["sensor_msgs/CameraInfo"])
+///     .segment_size(500)
+///     .root_frame("map")
+///     .tf_mode(TfMode::Interpolate);
 ///
 /// convert_bag(&options)?;
 /// # Ok::<(), anyhow::Error>(())
 /// ```
 pub fn convert_bag(options: &ConvertOptions) -> Result<()> {
-    let bag_file = RosBag::new(&options.bag_path).with_context(|| format!("failed to open bag: {}", options.bag_path))?;
-
-    let mut tf_graph = crate::mappings::tf::TfGraph::new();
+    let mut mappers = MapperRegistry::builtin(options);
+    if let Some(script) = &options.mapper_script {
+        mappers.register_script(Path::new(script)).context("invalid --mapper-script")?;
+    }
+    convert_bag_with(options, mappers)
+}
 
-    // filters
-    let include_set: Option<HashSet<&str>> = if options.include_topics.is_empty() {
-        None
-    } else {
-        Some(options.include_topics.iter().map(|s| s.as_str()).collect())
-    };
-    let exclude_set: HashSet<&str> = options.exclude_topics.iter().map(|s| s.as_str()).collect();
-
-    // collect all chunks first since the iterator may not be restartable
-    let chunks: Vec<_> = if options.tolerate_corruption {
-        let mut valid_chunks = Vec::new();
-        let mut chunk_count = 0;
-        let mut corrupted_count = 0;
-
-        for chunk_result in bag_file.chunk_records() {
-            chunk_count += 1;
-            match chunk_result {
-                Ok(chunk) => valid_chunks.push(chunk),
-                Err(e) => {
-                    corrupted_count += 1;
-                    tracing::warn!("Skipping corrupted chunk #{}: {}", chunk_count, e);
-                }
-            }
+/// Convert with the message mappers of `mappers`, e.g. [`MapperRegistry::builtin`]
+/// extended with mappers of proprietary types
+pub fn convert_bag_with(options: &ConvertOptions, mut mappers: MapperRegistry) -> Result<()> {
+    let renames = EntityRenames::new(options)?;
+    crate::filter::validate_output_groups(&options.output_groups)?;
+    let robot = RobotModel::load(options)?;
+    let world = WorldAnnotations::load(options)?;
+    if crate::source::is_live_input(&options.bag_path) {
+        return crate::live::convert_live(options, mappers, &robot, &world, &renames);
+    }
+    let inputs: Vec<String> = std::iter::once(&options.bag_path).chain(&options.extra_bags).cloned().collect();
+    // ROS2 bags and MCAP files are staged as ROS1 bags, removed once converted
+    let mut staged = crate::source::StagedInputs::new(&inputs)?;
+    let mut bag_paths = expand_bag_paths(&staged.paths)?;
+    for path in &mut bag_paths {
+        if BagLayout::read(path)?.has_index() {
+            continue;
         }
-
-        if corrupted_count > 0 {
-            tracing::warn!(
-                "Skipped {} corrupted chunks out of {} total chunks",
-                corrupted_count,
-                chunk_count
+        // The bag reader cannot address a file whose header points at no index section,
+        // so its chunks are copied in order to a reindexed bag, like `rosbag reindex` does
+        let (reindexed, summary) = staged.stage_reindexed(path)?;
+        if summary.salvaged_chunks + summary.dropped_chunks > 0 && !options.tolerate_corruption {
+            anyhow::bail!(
+                "{path} has no index section and {} damaged chunks; pass --tolerate-corruption to keep their readable \
+                 records, or repair it with `bag2rrd diagnose {path} --repair fixed.bag`",
+                summary.salvaged_chunks + summary.dropped_chunks
             );
         }
+        options.warn(format!(
+            "{path} has no index section (interrupted recording or truncated file); read its {} chunks in order",
+            summary.chunks + summary.salvaged_chunks
+        ));
+        *path = reindexed;
+    }
+    // Provenance names and hashes the files given, not their staged copies
+    let source_paths: Vec<String> = bag_paths.iter().map(|path| staged.origin(path).to_string()).collect();
+    let bags: Vec<RosBag> = bag_paths
+        .iter()
+        .map(|path| RosBag::new(path).with_context(|| format!("failed to open bag: {}", path)))
+        .collect::<Result<_>>()?;
+    if bags.len() > 1 {
+        tracing::info!("Merging {} bags: {}", bags.len(), bag_paths.join(", "));
+    }
 
-        valid_chunks
+    // --resume keeps the complete parts of an interrupted run and converts what follows them
+    let manifest_path = SegmentManifest::path_for(&options.output_path);
+    let resumed_parts = if options.resume {
+        if options.segment_size.is_none() && options.segment_bytes.is_none() && options.segment_seconds.is_none() {
+            anyhow::bail!("--resume needs segmented output (--segment-size, --segment-bytes or --segment-seconds)");
+        }
+        if manifest_path.exists() {
+            let manifest = SegmentManifest::read(&manifest_path)?;
+            if manifest.bag != options.bag_path {
+                anyhow::bail!(
+                    "--resume: {} was written for bag {}, not {}",
+                    manifest_path.display(),
+                    manifest.bag,
+                    options.bag_path
+                );
+            }
+            manifest.completed_parts(manifest_path.parent().unwrap_or(Path::new("")))
+        } else {
+            options.warn(format!("--resume: no manifest at {}; converting from the start", manifest_path.display()));
+            Vec::new()
+        }
     } else {
-        bag_file.chunk_records().collect::<Result<Vec<_>, _>>()?
+        Vec::new()
     };
+    // Record time (seconds) of the last message in the kept parts
+    let resume_after = resumed_parts.last().map(|part| part.end_time);
+    let resumed_count = resumed_parts.len() as u64;
+    if let Some(part) = resumed_parts.last() {
+        tracing::info!(part = part.part, file = %part.file, end = %part.end_utc, "resuming after the last complete part");
+    }
+    let after_resume = |time_ns: u64| resume_after.is_none_or(|t| time_ns as f64 / 1_000_000_000.0 > t);
 
-    // collect connections first
-    let mut connections = std::collections::BTreeMap::new();
-    for record in &chunks {
-        if let ChunkRecord::Chunk(chunk) = record {
-            for msg in chunk.messages() {
-                let msg = msg?;
-                if let MessageRecord::Connection(conn) = msg {
-                    connections.insert(conn.id, (conn.topic.to_string(), conn.tp.to_string()));
+    let mut logger = MessageLogger::new(options)?;
+
+    // filters
+    let filter = MessageFilter::new(
+        &options.include_topics,
+        &options.exclude_topics,
+        &options.include_types,
+        &options.exclude_types,
+    )?;
+
+    // Chunk decompression and image decoding run on this pool; logging stays on this thread
+    let decode_pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.decode_threads)
+        .build()
+        .context("failed to start decode threads")?;
+
+    // The bag index lists every chunk, so chunks are decompressed in parallel and, with
+    // --start/--end, only those overlapping the window are read. Unindexed bags are
+    // scanned sequentially in full
+    let windowed = options.start_time.is_some() || options.end_time.is_some();
+    let indexes: Vec<Option<BagIndex>> = bags
+        .iter()
+        .zip(&bag_paths)
+        .map(|(bag, path)| {
+            BagIndex::read(bag).unwrap_or_else(|e| {
+                if windowed {
+                    options.warn(format!("Failed to read the index of {path} ({e:#}); scanning all chunks"));
+                } else {
+                    tracing::debug!("Failed to read the index of {path} ({e:#}); reading chunks sequentially");
                 }
+                None
+            })
+        })
+        .collect();
+    // Without an index, --tolerate-corruption locates the chunks by their record lengths
+    // so a damaged one does not derail the reads after it
+    let scans: Vec<Option<ChunkScan>> = indexes
+        .iter()
+        .zip(&bag_paths)
+        .map(|(index, path)| match index {
+            None if options.tolerate_corruption => ChunkScan::read(path).map(Some),
+            _ => Ok(None),
+        })
+        .collect::<Result<_>>()?;
+    // Merged bags share one timeline, starting at the first message of any of them
+    let start_ns = indexes.iter().flatten().filter_map(BagIndex::start_ns).min();
+    // Chunks of all bags in record-time order, when every bag is indexed
+    let spans: Option<Vec<(usize, ChunkSpan)>> = indexes.iter().all(Option::is_some).then(|| {
+        let mut spans: Vec<(usize, ChunkSpan)> = indexes
+            .iter()
+            .flatten()
+            .enumerate()
+            .flat_map(|(bag, index)| {
+                index
+                    .chunks_in_range(start_ns.unwrap_or(0), options.start_time, options.end_time)
+                    .into_iter()
+                    .filter(|span| {
+                        // Chunks before the resume point are still read for their context messages
+                        after_resume(span.end_ns)
+                            || index.chunk_connections.get(&span.pos).is_some_and(|ids| {
+                                ids.iter().any(|id| {
+                                    index.connections.get(id).is_some_and(|(topic, tp)| {
+                                        resume_context(topic, tp, index.latched.contains(id))
+                                    })
+                                })
+                            })
+                    })
+                    .map(move |span| (bag, span))
+            })
+            .collect();
+        spans.sort_by_key(|(_, span)| span.start_ns);
+        spans
+    });
+    if let Some(spans) = &spans
+        && windowed
+    {
+        let total: usize = indexes.iter().flatten().map(|index| index.chunks.len()).sum();
+        tracing::info!("Reading {} of {} chunks in the --start/--end window", spans.len(), total);
+    }
+
+    // Under --max-memory chunks are read a group at a time, which needs the index
+    // for the connections and bag start time ahead of the later groups
+    let budget = options.max_memory.map(MemoryBudget::new);
+    if budget.is_some() && spans.is_none() {
+        options.warn("--max-memory needs an indexed bag to read chunks in groups; reading all chunks at once".to_string());
+    }
+    let mut memory = MemoryTracker::default();
+    let mut chunk_reader = ChunkReader::new(
+        &bags,
+        spans.as_deref(),
+        &scans,
+        budget.map(|b| b.chunk_bytes()),
+        &decode_pool,
+        options.tolerate_corruption,
+    );
+    let (mut chunks, mut group_bytes) = chunk_reader.next_group()?.unwrap_or_default();
+
+    // collect connections first; skipped chunks may hold the only definition of some
+    let mut conns = ConnectionMap::default();
+    for (bag, index) in indexes.iter().enumerate() {
+        for (id, (topic, tp)) in index.iter().flat_map(|index| &index.connections) {
+            let latching = index.as_ref().is_some_and(|index| index.latched.contains(id));
+            let shared = conns.insert(bag, *id, topic, tp, latching);
+            if let Some(info) = index.as_ref().and_then(|index| index.definitions.get(id)) {
+                conns.define(shared, info.clone());
             }
         }
     }
+    for (bag, record) in &chunks {
+        conns.register_chunk(*bag, record);
+    }
+    // Filters and per-topic settings are per connection, so resolve them once
+    let mut topic_configs: HashMap<u32, TopicConfig> = HashMap::new();
+    resolve_topic_configs(&conns, &filter, options, &renames.topics, &mut topic_configs)?;
 
     // segmentation validation
     if let Some(sz) = options.segment_size && sz == 0 {
@@ -178,15 +943,41 @@ pub fn convert_bag(options: &ConvertOptions) -> Result<()> {
     if let Some(sz) = options.segment_bytes && sz == 0 {
         anyhow::bail!("segment-bytes must be > 0");
     }
+    if let Some(secs) = options.segment_seconds && (secs.is_nan() || secs <= 0.0) {
+        anyhow::bail!("segment-seconds must be > 0");
+    }
+    // Split outputs share one recording id, so the viewer merges whichever files are opened
+    let split_output = options.split_topics || !options.output_groups.is_empty();
+    if split_output
+        && (options.segment_size.is_some() || options.segment_bytes.is_some() || options.segment_seconds.is_some())
+    {
+        anyhow::bail!("--output-group/--split-topics cannot be combined with segmentation");
+    }
+    if !matches!(options.output_target, OutputTarget::File)
+        && (split_output
+            || options.segment_size.is_some()
+            || options.segment_bytes.is_some()
+            || options.segment_seconds.is_some())
+    {
+        anyhow::bail!("segmentation and --output-group/--split-topics write files; they need OutputTarget::File");
+    }
+    if !(options.rate.is_finite() && options.rate >= 0.0) {
+        anyhow::bail!("--rate must be >= 0 (0 = as fast as possible)");
+    }
+    // Split recordings by path, with the raw bytes logged to each
+    let mut split_recs: BTreeMap<String, (rerun::RecordingStream, u64)> = BTreeMap::new();
     if options.flush_workers == 0 {
         anyhow::bail!("flush-workers must be >= 1");
     }
-    let segmentation_enabled = (options.segment_size.is_some() || options.segment_bytes.is_some()) && !options.dry_run;
-    let seg_size = options.segment_size.unwrap_or(0) as u64;
-    let seg_bytes = options.segment_bytes.unwrap_or(0);
+    let segmentation_enabled = (options.segment_size.is_some()
+        || options.segment_bytes.is_some()
+        || options.segment_seconds.is_some())
+        && !options.dry_run;
 
     // Single-output recording (created lazily after first kept message for parity with segments)
     let mut rec: Option<rerun::RecordingStream> = None;
+    // Sink of an OutputTarget::Memory recording, drained once it is flushed
+    let mut memory_sink: Option<rerun::sink::MemorySinkStorage> = None;
 
     // For segmentation derive base path components
     let (base_parent, base_stem, base_ext) = if segmentation_enabled {
@@ -199,9 +990,9 @@ pub fn convert_bag(options: &ConvertOptions) -> Result<()> {
         (PathBuf::new(), String::new(), String::new())
     };
 
-    let mut segment_index: u64 = 0; // 0-based
-    let mut segment_images: u64 = 0; // images+compressed in current segment
-    let mut segment_raw_bytes: u64 = 0;
+    let mut segment_index: u64 = resumed_count; // 0-based
+    // Time range and messages of the current part, for the manifest
+    let mut segment_contents = SegmentContents::default();
     let mut current_tmp_path = PathBuf::new();
     let mut current_final_path = PathBuf::new();
 
@@ -209,7 +1000,7 @@ pub fn convert_bag(options: &ConvertOptions) -> Result<()> {
                             base_parent: &PathBuf,
                             base_stem: &str,
                             base_ext: &str,
-                            bag: &str,
+                            provenance: &Provenance,
                             tmp_dir: &PathBuf,
                             current_tmp_path: &mut PathBuf,
                             current_final_path: &mut PathBuf|
@@ -220,91 +1011,104 @@ pub fn convert_bag(options: &ConvertOptions) -> Result<()> {
             segment_index + 1,
             base_ext
         ));
+        // Hidden until complete; next to the final part so the rename is atomic
         let tmp_path = tmp_dir.join(format!(
-            "bag2rrd_tmp_{}_{:04}.{}",
-            bag.replace("/", "_"),
+            ".{}_part{:04}.{}.tmp",
+            base_stem,
             segment_index + 1,
             base_ext
         ));
         *current_tmp_path = tmp_path.clone();
         *current_final_path = final_path.clone();
-        let rec_id = format!("bag2rrd:{}:segment:{}", bag, segment_index + 1);
-        eprintln!(
-            "[bag2rrd][segment {}] opening tmp={}",
-            segment_index + 1,
-            tmp_path.display()
-        );
-        Ok(rerun::RecordingStreamBuilder::new(rec_id).save(tmp_path)?)
+        tracing::debug!(segment = segment_index + 1, tmp = %tmp_path.display(), "opening segment");
+        // Parts share the recording id, so the viewer shows them as one recording
+        Ok(recording_builder(options, provenance, budget).save(tmp_path)?)
     };
 
     // Parallel flush setup
     let (flush_tx, flush_rx): (Sender<FlushJob>, Receiver<FlushJob>) = flume::unbounded();
     let (result_tx, result_rx): (
-        Sender<anyhow::Result<FlushJob>>,
-        Receiver<anyhow::Result<FlushJob>>,
+        Sender<anyhow::Result<SegmentEntry>>,
+        Receiver<anyhow::Result<SegmentEntry>>,
     ) = flume::unbounded();
     let tmp_dir = std::env::var("BAG2RRD_SEGMENT_TMP_DIR")
         .map(PathBuf::from)
-        .unwrap_or_else(|_| std::env::temp_dir().join("bag2rrd_segments"));
-    std::fs::create_dir_all(&tmp_dir)?;
+        .unwrap_or_else(|_| base_parent.clone());
+    if segmentation_enabled {
+        std::fs::create_dir_all(&tmp_dir)?;
+    }
     let workers: Vec<_> = (0..options.flush_workers)
-        .map(|i| {
+        .map(|_| {
             let rx = flush_rx.clone();
             let tx = result_tx.clone();
-            let tmp_dir = tmp_dir.clone();
-            std::thread::spawn(move || flush_worker(i, rx, tx, tmp_dir))
+            std::thread::spawn(move || flush_worker(rx, tx))
         })
         .collect();
+    // Parts are added to the manifest as their flush completes, so an interrupted run can --resume
+    let mut manifest = SegmentManifest { bag: options.bag_path.clone(), segments: resumed_parts };
+    let mut completed_jobs: u64 = 0;
+    // Set on Ctrl-C: reading stops and what was converted so far is finalized
+    let mut stopped = false;
+    // Offset from the bag start of the last message read, reported when stopped
+    let mut stopped_at: Option<f64> = None;
+    let mut pacer = Pacer::new(options.rate);
+    let cancelled = || options.cancelled();
 
-    // progress bar (unknown length)
-    let pb = if options.show_progress {
-        let pb = ProgressBar::new_spinner();
-        pb.set_style(ProgressStyle::with_template("{spinner} {pos} msgs").unwrap());
-        Some(pb)
-    } else {
-        None
-    };
-
-    let mut bag_start_ns = f64::INFINITY;
-    let mut total_msgs: u64 = 0;
-    let mut kept_msgs: u64 = 0;
+    // Offsets stay relative to the first message of the bag, even in a skipped chunk
+    let mut bag_start_ns = start_ns.map_or(f64::INFINITY, |ns| ns as f64);
     let mut topics: HashSet<String> = HashSet::new();
 
     // statistics and logging configuration
-    #[derive(Default)]
-    struct Stats {
-        images: u64,
-        compressed_images: u64,
-        pointclouds: u64,
-        laserscans: u64,
-        gps_fixes: u64,
-        imu_msgs: u64,
-        skipped_type: u64,
-        filtered_out: u64,
-        raw_bytes: u64,
-    }
-    let mut stats = Stats::default();
-    let log_every = std::env::var("BAG2RRD_LOG_EVERY")
-        .ok()
-        .and_then(|s| s.parse::<u64>().ok())
-        .filter(|v| *v > 0);
-    let verbose_types: Option<HashSet<String>> = std::env::var("BAG2RRD_LOG_TYPES")
-        .ok()
-        .map(|s| s.split(',').map(|t| t.trim().to_string()).collect());
+    let mut stats = ConvertStats::default();
+    // per-topic image counters for --image-every-nth
+    let mut image_frames: HashMap<String, u64> = HashMap::new();
     let second_pass_start = Instant::now();
 
-    // First pass: collect bag start time and count messages
-    for record in &chunks {
-        if let ChunkRecord::Chunk(chunk) = record {
-            for msg in chunk.messages() {
-                let msg = msg?;
-                if let MessageRecord::MessageData(msg_data) = msg {
-                    bag_start_ns = bag_start_ns.min(msg_data.time as f64);
-                    total_msgs += 1;
-                }
+    // First pass: collect bag start time and gather /clock samples. When chunks are
+    // read in groups the start time comes from the index and /clock needs its own scan
+    let mut sim_clock = crate::mappings::clock::SimClock::new();
+    let is_clock = |conns: &ConnectionMap, msg_data: &MessageData| {
+        conns.connections.get(&msg_data.conn_id).is_some_and(|(_, tp)| tp == "rosgraph_msgs/Clock")
+    };
+    // Messages to read, for the progress bar: from the index, else counted here
+    let mut total_msgs: Option<u64> = spans.as_ref().map(|spans| spans.iter().map(|(_, span)| span.messages).sum());
+    let mut end_ns = indexes.iter().flatten().filter_map(BagIndex::end_ns).max();
+    if chunk_reader.is_done() {
+        let messages = group_messages(&chunks, &conns);
+        total_msgs.get_or_insert(messages.len() as u64);
+        for msg_data in messages {
+            bag_start_ns = bag_start_ns.min(msg_data.time as f64);
+            end_ns = end_ns.max(Some(msg_data.time));
+            if options.sim_time && is_clock(&conns, &msg_data) {
+                sim_clock.push(msg_data.time as f64 / 1_000_000_000.0, msg_data.data);
+            }
+        }
+    } else if options.sim_time {
+        let mut clock_reader = chunk_reader.restart();
+        while let Some((group, _)) = clock_reader.next_group()? {
+            for (bag, record) in &group {
+                conns.register_chunk(*bag, record);
+            }
+            for msg_data in group_messages(&group, &conns).iter().filter(|m| is_clock(&conns, m)) {
+                sim_clock.push(msg_data.time as f64 / 1_000_000_000.0, msg_data.data);
             }
         }
     }
+    sim_clock.finish();
+    if sim_clock.malformed() > 0 {
+        options.warn(format!("skipped {} malformed rosgraph_msgs/Clock messages", sim_clock.malformed()));
+    }
+    let use_sim_time = options.sim_time && !sim_clock.is_empty();
+    if options.sim_time && !use_sim_time {
+        options.warn("--sim-time requested but the bag has no rosgraph_msgs/Clock messages; using bag time".to_string());
+    }
+
+    // Hashing reads every bag once more, so only when a recording is written
+    let provenance = if options.dry_run {
+        Provenance::default()
+    } else {
+        Provenance::of_bags(options, &source_paths, bag_start_ns.is_finite().then_some(bag_start_ns as u64), end_ns)?
+    };
 
     let bag_start_s = if bag_start_ns.is_finite() {
         bag_start_ns / 1_000_000_000.0
@@ -313,381 +1117,413 @@ pub fn convert_bag(options: &ConvertOptions) -> Result<()> {
     };
 
     // Second pass: process messages
-    println!("Starting second pass...");
-    for record in &chunks {
-        if let ChunkRecord::Chunk(chunk) = record {
-            for msg in chunk.messages() {
-                let msg = msg?;
-                if let MessageRecord::MessageData(msg_data) = msg {
-                    if let Some((topic, tp)) = connections.get(&msg_data.conn_id) {
-                        // Apply filters
-                        if let Some(inc) = &include_set && !inc.contains(topic.as_str()) {
-                            continue;
-                        }
-                        if exclude_set.contains(topic.as_str()) {
-                            continue;
-                        }
+    tracing::debug!("starting second pass");
+    let mut progress = options.show_progress.then(|| ConvertProgress::new(total_msgs));
+    // Images are decoded on the pool a batch at a time, ahead of the sequential logging
+    let batch_images = decode_pool.current_num_threads() * 4;
+    let in_window = |ts_rel: f64| {
+        options.start_time.is_none_or(|s| ts_rel >= s) && options.end_time.is_none_or(|e| ts_rel <= e)
+    };
+    let image_budget = budget.map(|b| b.image_bytes());
+    // Per-topic counts of a --dry-run
+    let mut plan = ConversionPlan::new(options, bag_start_s);
+    let mut rotation = SegmentRotation::new(options, bag_start_s);
+    let mut rate_limiter = RateLimiter::default();
+    loop {
+        let messages = group_messages(&chunks, &conns);
+        stats.total_msgs += messages.len() as u64;
+        memory.hold(group_bytes);
+        let mut batch_start = 0;
+        while batch_start < messages.len() {
+            // Pick the images this batch logs: in the window and kept by --image-every-nth
+            let mut image_jobs = Vec::new();
+            // Messages thinned by --max-rate, decided here so their images are not decoded
+            let mut rate_limited = HashSet::new();
+            let mut batch_bytes = 0;
+            let mut batch_end = batch_start;
+            while batch_end < messages.len()
+                && image_jobs.len() < batch_images
+                && image_budget.is_none_or(|limit| batch_bytes < limit)
+            {
+                let index = batch_end;
+                let msg_data = &messages[index];
+                batch_end += 1;
+                let (Some((topic, tp)), Some(topic_config)) =
+                    (conns.connections.get(&msg_data.conn_id), topic_configs.get(&msg_data.conn_id))
+                else {
+                    continue;
+                };
+                let ts_rel = (msg_data.time as f64 / 1_000_000_000.0) - bag_start_s;
+                if in_window(ts_rel)
+                    && after_resume(msg_data.time)
+                    && !conns.latched.contains(&msg_data.conn_id)
+                    && !rate_limiter.keep(topic, topic_config.max_rate, msg_data.time as f64 / 1_000_000_000.0)
+                {
+                    rate_limited.insert(index);
+                    continue;
+                }
+                let compressed = match tp.as_str() {
+                    "sensor_msgs/Image" => false,
+                    "sensor_msgs/CompressedImage" => true,
+                    _ => continue,
+                };
+                let Some(mapper) = mappers.get(topic, tp).filter(|m| m.decodes_images()) else {
+                    continue;
+                };
+                let info = conns.definitions.get(&msg_data.conn_id);
+                if logger.mismatched(options, mapper, msg_data.conn_id, topic, tp, info) {
+                    continue;
+                }
+                let context = resume_context(topic, tp, conns.latched.contains(&msg_data.conn_id));
+                if options.dry_run || !in_window(ts_rel) || !(after_resume(msg_data.time) || context) {
+                    continue;
+                }
+                let frame = image_frames.entry(topic.clone()).or_insert(0);
+                let keep = frame.is_multiple_of(topic_config.image_every_nth);
+                *frame += 1;
+                if keep {
+                    let image_opts = ImageOptions {
+                        colormap: topic_config.image_colormap,
+                        value_range: options.image_value_range,
+                        crop: topic_config.image_crop,
+                        scale: topic_config.image_scale,
+                        encoding: options.image_encoding,
+                        compressed_passthrough: options.compressed_passthrough,
+                    };
+                    // JPEG/PNG payloads decode to several times their size
+                    batch_bytes += msg_data.data.len() as u64 * if compressed { 8 } else { 1 };
+                    image_jobs.push((index, compressed, msg_data.data, image_opts));
+                }
+            }
+            memory.hold(batch_bytes);
+            let mut decoded_images: HashMap<usize, Result<DecodedImage>> = decode_pool.install(|| {
+                image_jobs
+                    .into_par_iter()
+                    .map(|(index, compressed, payload, opts)| {
+                        let decoded = if compressed {
+                            decode_compressed(payload, &opts)
+                        } else {
+                            decode_image(payload, &opts)
+                        };
+                        (index, decoded)
+                    })
+                    .collect()
+            });
 
-                        let ts_rel = (msg_data.time as f64 / 1_000_000_000.0) - bag_start_s;
-                        if let Some(s) = options.start_time && ts_rel < s {
-                            continue;
-                        }
-                        if let Some(e) = options.end_time && ts_rel > e {
-                            continue;
-                        }
+            for (index, msg_data) in (batch_start..batch_end).zip(&messages[batch_start..batch_end]) {
+                if cancelled() {
+                    stopped = true;
+                    break;
+                }
+                stats.processed_msgs += 1;
+                stopped_at = Some((msg_data.time as f64 / 1_000_000_000.0) - bag_start_s);
+                if let Some(progress) = &mut progress {
+                    let topic = conns.connections.get(&msg_data.conn_id).map_or("", |(topic, _)| topic.as_str());
+                    progress.message(topic, msg_data.data.len() as u64);
+                }
+                if stats.processed_msgs.is_multiple_of(PROGRESS_INTERVAL) {
+                    tracing::debug!(
+                        processed_msgs = stats.processed_msgs,
+                        kept_msgs = stats.kept_msgs,
+                        images = stats.images + stats.compressed_images,
+                        pointclouds = stats.pointclouds,
+                        skipped_types = stats.skipped_types,
+                        filtered_out = stats.filtered_out,
+                        elapsed_secs = second_pass_start.elapsed().as_secs_f64(),
+                        "progress"
+                    );
+                    options.emit(ConvertEvent::Progress(stats.clone()));
+                }
+                if let Some((topic, tp)) = conns.connections.get(&msg_data.conn_id) {
+                    // Apply filters
+                    let Some(topic_config) = topic_configs.get(&msg_data.conn_id) else {
+                        continue;
+                    };
 
-                        topics.insert(topic.clone());
-                        if options.dry_run {
-                            kept_msgs += 1;
-                            if let Some(pb) = &pb {
-                                pb.inc(1);
-                            }
-                            continue;
-                        }
+                    let ts_rel = (msg_data.time as f64 / 1_000_000_000.0) - bag_start_s;
+                    let context = resume_context(topic, tp, conns.latched.contains(&msg_data.conn_id));
+                    if !in_window(ts_rel) || !(after_resume(msg_data.time) || context) {
+                        continue;
+                    }
+                    if rate_limited.contains(&index) {
+                        stats.rate_limited += 1;
+                        logger.rate_limited(msg_data.conn_id, topic, tp, conns.definitions.get(&msg_data.conn_id), msg_data.data);
+                        continue;
+                    }
+                    if !pacer.wait(ts_rel, cancelled) {
+                        stopped = true;
+                        break;
+                    }
+
+                    topics.insert(topic.clone());
+                    if options.dry_run {
+                        stats.kept_msgs += 1;
+                        plan.record(
+                            options,
+                            topic,
+                            tp,
+                            mappers.get(topic, tp),
+                            topic_config,
+                            msg_data.time as f64 / 1_000_000_000.0,
+                            msg_data.data.len() as u64,
+                        );
+                        continue;
+                    }
 
-                        // ensure recording stream exists (single or segment)
+                    // Time-based rotation: the message opening a new window starts the next part
+                    if segmentation_enabled {
+                        let bag_s = msg_data.time as f64 / 1_000_000_000.0;
+                        if rotation.window_ended(bag_s)
+                            && let Some(rec_full) = rec.take()
+                        {
+                            logger.scalars.flush()?;
+                            submit_segment(
+                                &flush_tx,
+                                FlushJob {
+                                    part_index: (segment_index + 1) as u32,
+                                    rec: rec_full,
+                                    tmp_path: std::mem::take(&mut current_tmp_path),
+                                    final_path: std::mem::take(&mut current_final_path),
+                                    checksum: options.segment_checksum,
+                                    compact: options.compact,
+                                    contents: std::mem::take(&mut segment_contents),
+                                },
+                                rotation.images,
+                                rotation.raw_bytes,
+                            )?;
+                            segment_index += 1;
+                            rotation.close();
+                        }
                         if rec.is_none() {
-                            if segmentation_enabled {
-                                rec = Some(open_new_segment(
-                                    segment_index,
-                                    &base_parent,
-                                    &base_stem,
-                                    &base_ext,
-                                    &options.bag_path,
-                                    &tmp_dir,
-                                    &mut current_tmp_path,
-                                    &mut current_final_path,
-                                )?);
-                            } else {
-                                let rec_id = format!("bag2rrd:{}", options.bag_path);
-                                rec = Some(rerun::RecordingStreamBuilder::new(rec_id).save(&options.output_path)?);
-                            }
+                            rotation.start(bag_s);
+                        }
+                    }
 
-                            // Log metadata if provided
-                            if let Some(ref rec_ref) = rec {
-                                for metadata_entry in &options.metadata {
-                                    if let Some((key, value)) = metadata_entry.split_once('=') {
-                                        let metadata_path = format!("/metadata/{}", key.trim());
-                                        rec_ref.log(metadata_path, &rerun::archetypes::TextLog::new(value.trim()))?;
-                                    }
-                                }
+                    // ensure recording stream exists (single, split or segment)
+                    let output_path: Cow<str> = match &topic_config.output_group {
+                        Some(group) => Cow::Owned(group_output_path(&options.output_path, group)),
+                        None => Cow::Borrowed(&options.output_path),
+                    };
+                    if split_output {
+                        rec = split_recs.get(&*output_path).map(|(rec, _)| rec.clone());
+                    }
+                    if rec.is_none() {
+                        if segmentation_enabled {
+                            rec = Some(open_new_segment(
+                                segment_index,
+                                &base_parent,
+                                &base_stem,
+                                &base_ext,
+                                &provenance,
+                                &tmp_dir,
+                                &mut current_tmp_path,
+                                &mut current_final_path,
+                            )?);
+                        } else {
+                            let new_rec = open_recording(options, &output_path, &provenance, budget, &mut memory_sink)?;
+                            if split_output {
+                                split_recs.insert(output_path.to_string(), (new_rec.clone(), 0));
                             }
+                            rec = Some(new_rec);
                         }
 
-                        // dispatch by type
-                        match tp.as_str() {
-                            "sensor_msgs/Image" => {
-                                if let Some(ref rec_ref) = rec {
-                                    crate::mappings::images::image_to_rerun(
-                                        rec_ref,
-                                        topic,
-                                        ts_rel,
-                                        msg_data.data,
-                                    )?;
-                                }
-                                kept_msgs += 1;
-                                stats.images += 1;
-                                stats.raw_bytes += msg_data.data.len() as u64;
-                                if segmentation_enabled {
-                                    segment_images += 1;
-                                    segment_raw_bytes += msg_data.data.len() as u64;
-                                }
-                            }
-                            "sensor_msgs/CompressedImage" => {
-                                if let Some(ref rec_ref) = rec {
-                                    crate::mappings::images::compressed_to_rerun(
-                                        rec_ref,
-                                        topic,
-                                        ts_rel,
-                                        msg_data.data,
-                                    )?;
-                                }
-                                kept_msgs += 1;
-                                stats.compressed_images += 1;
-                                stats.raw_bytes += msg_data.data.len() as u64;
-                                if segmentation_enabled {
-                                    segment_images += 1;
-                                    segment_raw_bytes += msg_data.data.len() as u64;
-                                }
-                            }
-                            "sensor_msgs/PointCloud2" => {
-                                if let Some(ref rec_ref) = rec {
-                                    crate::mappings::pointcloud::pointcloud2_to_rerun(
-                                        rec_ref,
-                                        topic,
-                                        ts_rel,
-                                        msg_data.data,
-                                        options.pointcloud_rotation.as_ref(),
-                                    )?;
-                                }
-                                kept_msgs += 1;
-                                stats.pointclouds += 1;
-                                stats.raw_bytes += msg_data.data.len() as u64;
-                                if segmentation_enabled {
-                                    segment_images += 1;
-                                    segment_raw_bytes += msg_data.data.len() as u64;
-                                }
-                            }
-                            "sensor_msgs/LaserScan" => {
-                                if let Some(ref rec_ref) = rec {
-                                    crate::mappings::laserscan::laserscan_to_rerun(
-                                        rec_ref,
-                                        topic,
-                                        ts_rel,
-                                        msg_data.data,
-                                        options.scan_as_lines,
-                                    )?;
-                                }
-                                kept_msgs += 1;
-                                stats.laserscans += 1;
-                                stats.raw_bytes += msg_data.data.len() as u64;
-                                if segmentation_enabled {
-                                    segment_images += 1;
-                                    segment_raw_bytes += msg_data.data.len() as u64;
-                                }
-                            }
-                            "sensor_msgs/NavSatFix" => {
-                                if let Some(ref rec_ref) = rec {
-                                    crate::mappings::gps::navsatfix_to_rerun(
-                                        rec_ref,
-                                        topic,
-                                        ts_rel,
-                                        msg_data.data,
-                                        options.gps_origin.as_deref(),
-                                        options.gps_path,
-                                        options.gps_geoid.as_deref(),
-                                    )?;
-                                }
-                                kept_msgs += 1;
-                                stats.gps_fixes += 1;
-                                stats.raw_bytes += msg_data.data.len() as u64;
-                                if segmentation_enabled {
-                                    segment_images += 1;
-                                    segment_raw_bytes += msg_data.data.len() as u64;
-                                }
-                            }
-                            "sensor_msgs/Imu" => {
-                                if let Some(ref rec_ref) = rec {
-                                    crate::mappings::imu::imu_to_rerun(
-                                        rec_ref,
-                                        topic,
-                                        ts_rel,
-                                        msg_data.data,
-                                    )?;
-                                }
-                                kept_msgs += 1;
-                                stats.imu_msgs += 1;
-                                stats.raw_bytes += msg_data.data.len() as u64;
-                                if segmentation_enabled {
-                                    segment_images += 1;
-                                    segment_raw_bytes += msg_data.data.len() as u64;
-                                }
-                            }
-                            "tf2_msgs/TFMessage" => {
-                                if let Some(ref rec_ref) = rec {
-                                    tf_graph.ingest_tf_msg(rec_ref, ts_rel, msg_data.data, options.tf_buffer_seconds, &options.root_frame, &options.frame_mappings)?;
-                                }
-                                kept_msgs += 1;
-                                stats.raw_bytes += msg_data.data.len() as u64;
-                            }
-                            "tf/tfMessage" => {
-                                if let Some(ref rec_ref) = rec {
-                                    tf_graph.ingest_tf_msg(rec_ref, ts_rel, msg_data.data, options.tf_buffer_seconds, &options.root_frame, &options.frame_mappings)?;
-                                }
-                                kept_msgs += 1;
-                                stats.raw_bytes += msg_data.data.len() as u64;
-                            }
-                            "tf2_msgs/TFMessageStatic" => {
-                                if let Some(ref rec_ref) = rec {
-                                    tf_graph.ingest_tf_static_msg(rec_ref, msg_data.data, &options.root_frame, &options.frame_mappings)?;
-                                }
-                                kept_msgs += 1;
-                                stats.raw_bytes += msg_data.data.len() as u64;
-                            }
-                            "tf/tfMessageStatic" => {
-                                if let Some(ref rec_ref) = rec {
-                                    tf_graph.ingest_tf_static_msg(rec_ref, msg_data.data, &options.root_frame, &options.frame_mappings)?;
-                                }
-                                kept_msgs += 1;
-                                stats.raw_bytes += msg_data.data.len() as u64;
-                            }
-                            "nav_msgs/Odometry" => {
-                                if let Some(ref rec_ref) = rec {
-                                    crate::mappings::nav::odometry_to_rerun(
-                                        rec_ref,
-                                        topic,
-                                        ts_rel,
-                                        msg_data.data,
-                                        &options.root_frame,
-                                        &options.frame_mappings,
-                                        Some(&tf_graph),
-                                        options.tf_mode,
-                                    )?;
-                                }
-                                kept_msgs += 1;
-                                stats.raw_bytes += msg_data.data.len() as u64;
-                            }
-                            "geometry_msgs/PoseStamped" => {
-                                if let Some(ref rec_ref) = rec {
-                                    crate::mappings::nav::pose_stamped_to_rerun(
-                                        rec_ref,
-                                        topic,
-                                        ts_rel,
-                                        msg_data.data,
-                                        &options.root_frame,
-                                        &options.topic_renames,
-                                        &options.frame_mappings,
-                                        Some(&tf_graph),
-                                        options.tf_mode,
-                                    )?;
-                                }
-                                kept_msgs += 1;
-                                stats.raw_bytes += msg_data.data.len() as u64;
-                            }
-                            "nav_msgs/Path" => {
-                                if let Some(ref rec_ref) = rec {
-                                    crate::mappings::nav::path_to_rerun(
-                                        rec_ref,
-                                        topic,
-                                        ts_rel,
-                                        msg_data.data,
-                                        &options.root_frame,
-                                        &options.topic_renames,
-                                        &options.frame_mappings,
-                                        Some(&tf_graph),
-                                        options.tf_mode,
-                                    )?;
-                                }
-                                kept_msgs += 1;
-                                stats.raw_bytes += msg_data.data.len() as u64;
-                            }
-                            _ => {
-                                stats.skipped_type += 1;
-                            }
+                        if let Some(ref rec_ref) = rec {
+                            send_properties(options, rec_ref, &provenance)?;
+                            robot.log(rec_ref, options, &renames.frames)?;
+                            world.log(rec_ref, conns.has_type("sensor_msgs/NavSatFix"))?;
                         }
+                    }
 
-                        // Segment rotation
-                        if segmentation_enabled
-                            && ((seg_size > 0 && segment_images >= seg_size)
-                                || (seg_bytes > 0 && segment_raw_bytes >= seg_bytes))
-                        {
-                            if let Some(_rec_full) = rec.take() {
-                                eprintln!(
-                                    "[bag2rrd][segment {}] submitting flush job (images={} raw_bytes={})",
-                                    segment_index + 1,
-                                    segment_images,
-                                    segment_raw_bytes
-                                );
-                                let job = FlushJob {
-                                    part_index: (segment_index + 1) as u32,
-                                    tmp_path: current_tmp_path.clone(),
-                                    final_path: current_final_path.clone(),
-                                    raw_bytes_in_part: segment_raw_bytes,
-                                };
-                                flush_tx.send(job)?;
-                                // prepare next
-                                segment_index += 1;
-                                segment_images = 0;
-                                segment_raw_bytes = 0;
-                                current_tmp_path.clear();
-                                current_final_path.clear();
+                    let Some(rec_ref) = rec.as_ref() else {
+                        continue;
+                    };
+                    // log time: header.stamp when available, bag record time otherwise;
+                    // in sim time the timeline is absolute sim seconds from /clock
+                    let bag_s = msg_data.time as f64 / 1_000_000_000.0;
+                    let (time_base, receive_ts) = if use_sim_time {
+                        (0.0, sim_clock.to_sim(bag_s).unwrap_or(ts_rel))
+                    } else {
+                        (bag_start_s, ts_rel)
+                    };
+                    let kept_before = stats.kept_msgs;
+                    let (mapped, failed) = logger.log(
+                        rec_ref,
+                        options,
+                        &renames,
+                        &mut mappers,
+                        &mut stats,
+                        KeptMessage {
+                            conn: msg_data.conn_id,
+                            topic,
+                            tp,
+                            data: msg_data.data,
+                            topic_config,
+                            ts_rel,
+                            receive_ts,
+                            time_base,
+                            bag_time: bag_s,
+                            latched: conns.latched.contains(&msg_data.conn_id),
+                            type_info: conns.definitions.get(&msg_data.conn_id),
+                            decoded_image: decoded_images.remove(&index),
+                        },
+                    )?;
+                    // A message the mapper failed on still fills its segment, as the messages of its kind that map do
+                    let failed_fill = failed && MessageKind::of_type(tp).is_some_and(MessageKind::fills_segment);
+                    match mapped {
+                        Mapped::Logged(kind) => {
+                            if let Some((_, raw_bytes)) = split_recs.get_mut(&*output_path) {
+                                *raw_bytes += msg_data.data.len() as u64;
+                            }
+                            if segmentation_enabled && kind.fills_segment() {
+                                rotation.fill(msg_data.data.len() as u64);
                             }
                         }
-                        if let Some(pb) = &pb {
-                            pb.inc(1);
-                        }
-                        if let Some(ref vt) = verbose_types && vt.contains(tp) {
-                            eprintln!("[bag2rrd][msg] topic={topic} type={tp} t={:.6}", ts_rel);
-                        }
-                        if let Some(n) = log_every && kept_msgs % n == 0 {
-                            eprintln!(
-                                "[bag2rrd][progress] kept_msgs={} images={} compressed={} pointclouds={} laserscans={} gps_fixes={} imu_msgs={} skipped_type={} filtered={} elapsed={:?}",
-                                kept_msgs,
-                                stats.images,
-                                stats.compressed_images,
-                                stats.pointclouds,
-                                stats.laserscans,
-                                stats.gps_fixes,
-                                stats.imu_msgs,
-                                stats.skipped_type,
-                                stats.filtered_out,
-                                second_pass_start.elapsed()
-                            );
+                        Mapped::Skipped if segmentation_enabled && failed_fill => {
+                            rotation.fill(msg_data.data.len() as u64);
                         }
-                    } else {
-                        stats.filtered_out += 1;
+                        Mapped::Skipped | Mapped::Decimated => {}
+                    }
+
+                    if segmentation_enabled && (stats.kept_msgs > kept_before || failed_fill) {
+                        segment_contents.record(topic, msg_data.time as f64 / 1_000_000_000.0);
                     }
+
+                    // Segment rotation
+                    if segmentation_enabled
+                        && rotation.full()
+                        && let Some(rec_full) = rec.take()
+                    {
+                        logger.scalars.flush()?;
+                        submit_segment(
+                            &flush_tx,
+                            FlushJob {
+                                part_index: (segment_index + 1) as u32,
+                                rec: rec_full,
+                                tmp_path: std::mem::take(&mut current_tmp_path),
+                                final_path: std::mem::take(&mut current_final_path),
+                                checksum: options.segment_checksum,
+                                compact: options.compact,
+                                contents: std::mem::take(&mut segment_contents),
+                            },
+                            rotation.images,
+                            rotation.raw_bytes,
+                        )?;
+                        // prepare next
+                        segment_index += 1;
+                        rotation.close();
+                    }
+                } else {
+                    stats.filtered_out += 1;
                 }
             }
+            memory.release(batch_bytes);
+            for result in result_rx.try_iter() {
+                completed_jobs += 1;
+                record_part(options, &mut manifest, &manifest_path, result)?;
+            }
+            if stopped {
+                break;
+            }
+            batch_start = batch_end;
+        }
+        memory.release(group_bytes);
+        if stopped {
+            tracing::warn!(
+                processed_msgs = stats.processed_msgs,
+                stopped_at = stopped_at.unwrap_or(0.0),
+                "interrupted; finalizing the output converted so far"
+            );
+            break;
         }
+        match chunk_reader.next_group()? {
+            Some((group, bytes)) => (chunks, group_bytes) = (group, bytes),
+            None => break,
+        }
+        // Later chunks may define connections of their own
+        for (bag, record) in &chunks {
+            conns.register_chunk(*bag, record);
+        }
+        resolve_topic_configs(&conns, &filter, options, &renames.topics, &mut topic_configs)?;
     }
 
-    if let Some(pb) = &pb {
-        pb.finish_and_clear();
+    if let Some(progress) = &progress {
+        progress.finish();
     }
-    println!("Second pass completed");
+    tracing::debug!("second pass completed");
+    logger.finish(options, &mut mappers)?;
 
-    println!(
-        "Plan: {} messages, {} kept after filters, {} topics → output: {}",
-        total_msgs,
-        kept_msgs,
-        topics.len(),
-        options.output_path
-    );
+    if options.dry_run {
+        plan.finish(stats.total_msgs, stats.filtered_out);
+        print!("{}", plan.to_text());
+        options.emit(ConvertEvent::Plan(plan));
+    }
 
     if !options.dry_run {
-        eprintln!(
-            "[bag2rrd][stats] images={} compressed_images={} pointclouds={} laserscans={} gps_fixes={} imu_msgs={} skipped_types={} filtered_out={} kept_msgs={} total_msgs={} raw_bytes={}",
-            stats.images,
-            stats.compressed_images,
-            stats.pointclouds,
-            stats.laserscans,
-            stats.gps_fixes,
-            stats.imu_msgs,
-            stats.skipped_type,
-            stats.filtered_out,
-            kept_msgs,
-            total_msgs,
-            stats.raw_bytes
+        tracing::info!(
+            images = stats.images,
+            compressed_images = stats.compressed_images,
+            pointclouds = stats.pointclouds,
+            laserscans = stats.laserscans,
+            gps_fixes = stats.gps_fixes,
+            imu_msgs = stats.imu_msgs,
+            generic_msgs = stats.generic_msgs,
+            skipped_types = stats.skipped_types,
+            filtered_out = stats.filtered_out,
+            decimated_images = stats.decimated_images,
+            rate_limited = stats.rate_limited,
+            kept_msgs = stats.kept_msgs,
+            total_msgs = stats.total_msgs,
+            raw_bytes = stats.raw_bytes,
+            topics = topics.len(),
+            "conversion stats"
         );
+        tracing::info!(
+            budget = budget.map(|b| b.total),
+            peak_buffered_bytes = memory.peak(),
+            peak_rss_bytes = peak_rss_bytes(),
+            "memory"
+        );
+        if options.tolerate_corruption {
+            // Data lost to corruption: unreadable chunks, chunks cut short, and bytes
+            // stepped over while looking for the next chunk of an unindexed bag
+            tracing::info!(
+                corrupted_chunks = chunk_reader.corrupted_count,
+                truncated_chunks = chunk_reader.truncated_count,
+                total_chunks = chunk_reader.chunk_count,
+                damaged_regions = scans.iter().flatten().map(|s| s.damaged_regions).sum::<usize>(),
+                skipped_bytes = scans.iter().flatten().map(|s| s.skipped_bytes).sum::<u64>(),
+                "corruption"
+            );
+        }
         if segmentation_enabled {
-            // submit last open segment
-            if let Some(_rec_last) = rec.take() {
-                if segment_images > 0 {
-                    // only if something was logged
-                    eprintln!(
-                        "[bag2rrd][segment {}] submitting final flush job (images={} raw_bytes={})",
-                        segment_index + 1,
-                        segment_images,
-                        segment_raw_bytes
-                    );
-                    let job = FlushJob {
+            // submit last open segment; it is only opened by a kept message
+            if let Some(rec_last) = rec.take() {
+                submit_segment(
+                    &flush_tx,
+                    FlushJob {
                         part_index: (segment_index + 1) as u32,
-                        tmp_path: current_tmp_path.clone(),
-                        final_path: current_final_path.clone(),
-                        raw_bytes_in_part: segment_raw_bytes,
-                    };
-                    flush_tx.send(job)?;
-                }
+                        rec: rec_last,
+                        tmp_path: std::mem::take(&mut current_tmp_path),
+                        final_path: std::mem::take(&mut current_final_path),
+                        checksum: options.segment_checksum,
+                        compact: options.compact,
+                        contents: std::mem::take(&mut segment_contents),
+                    },
+                    rotation.images,
+                    rotation.raw_bytes,
+                )?;
+                segment_index += 1;
             }
             // Close the channel to signal workers to stop
             drop(flush_tx);
             // Wait for all workers to finish
-            let mut completed_jobs = 0;
-            let total_jobs = segment_index + if segment_images > 0 { 1 } else { 0 };
+            let total_jobs = segment_index - resumed_count;
             while completed_jobs < total_jobs {
                 match result_rx.recv() {
-                    Ok(Ok(job)) => {
-                        eprintln!(
-                            "[bag2rrd][segment {}] completed file={}",
-                            job.part_index,
-                            job.final_path.display()
-                        );
+                    // failed flushes still count as completed
+                    Ok(result) => {
                         completed_jobs += 1;
-                    }
-                    Ok(Err(e)) => {
-                        eprintln!("[bag2rrd][error] flush failed: {}", e);
-                        completed_jobs += 1; // still count as completed
+                        record_part(options, &mut manifest, &manifest_path, result)?;
                     }
                     Err(_) => break, // channel closed
                 }
@@ -696,98 +1532,931 @@ pub fn convert_bag(options: &ConvertOptions) -> Result<()> {
             for worker in workers {
                 let _ = worker.join();
             }
-            let total_segments = total_jobs;
-            eprintln!(
-                "[bag2rrd] segmentation summary: segments={} segment_size={} segment_bytes={} total_images={} raw_bytes={} pattern='{}_part{{:04}}.{}'",
-                total_segments,
-                seg_size,
-                seg_bytes,
-                stats.images + stats.compressed_images,
-                stats.raw_bytes,
-                base_stem,
-                base_ext
+            if !manifest.segments.is_empty() {
+                tracing::info!(path = %manifest_path.display(), "wrote segment manifest");
+            }
+            let total_segments = segment_index;
+            tracing::info!(
+                segments = total_segments,
+                segment_size = options.segment_size.unwrap_or(0),
+                segment_bytes = options.segment_bytes.unwrap_or(0),
+                total_images = stats.images + stats.compressed_images,
+                raw_bytes = stats.raw_bytes,
+                pattern = %format!("{}_part{{:04}}.{}", base_stem, base_ext),
+                "segmentation summary"
             );
+        } else if split_output && !split_recs.is_empty() {
+            drop(rec.take());
+            for (path, (split_rec, raw_bytes)) in split_recs {
+                tracing::debug!(%path, raw_bytes, "flushing split recording");
+                flush_recording(split_rec, &path, raw_bytes);
+                tracing::info!(%path, "saved RRD");
+                if options.compact {
+                    compact_rrd(Path::new(&path), CompactionLimits::default())?;
+                }
+            }
         } else if let Some(rec_single) = rec.take() {
-            eprintln!(
-                "[bag2rrd][single] flushing recording (images={} raw_bytes={})",
-                stats.images + stats.compressed_images,
-                stats.raw_bytes
+            tracing::debug!(
+                images = stats.images + stats.compressed_images,
+                raw_bytes = stats.raw_bytes,
+                "flushing recording"
             );
-            flush_recording(rec_single, &options.output_path, stats.raw_bytes, "[bag2rrd]");
-            eprintln!("[bag2rrd] Saved RRD: {}", options.output_path);
+            finish_recording(options, rec_single, memory_sink.take(), stats.raw_bytes, stopped)?;
         } else {
             // Could happen if no messages matched filters
-            eprintln!("[bag2rrd] no messages kept; nothing to flush");
+            tracing::warn!("no messages kept; nothing to flush");
         }
     }
 
-    Ok(())
+    if !options.dry_run {
+        stats.failed_msgs = logger.unmapped.failed_msgs();
+        finish_report(options, &stats, &logger.unmapped, &logger.drops)?;
+        logger.unmapped.check_failures(options)?;
+    }
+    let processed_msgs = stats.processed_msgs;
+    options.emit(ConvertEvent::Finished(stats));
+    if stopped {
+        return Err(crate::interrupt::Interrupted { processed_msgs, stopped_at }.into());
+    }
+    Ok(())
 }
 
-fn flush_worker(
-    _id: usize,
-    rx: Receiver<FlushJob>,
-    tx: Sender<anyhow::Result<FlushJob>>,
-    _tmp_dir: PathBuf,
-) {
-    while let Ok(job) = rx.recv() {
-        let res = (|| -> anyhow::Result<FlushJob> {
-            // For now, since rerun::RecordingStream may not be Send, we'll assume the job contains the path to a temp file
-            // and we just rename it. But in the spec, it's to finalize and save.
-            // Since the spec says "finalize to a temporary file on the producer thread", but in this code, the producer is creating the recording directly.
-            // To make it work, perhaps we need to change the approach.
-            // For simplicity, since rerun saves directly, the FlushJob will contain the final path, and we just wait for the file to be stable.
-            // But the spec shows tmp_path and final_path, with rename.
-            // Since the recording is saved directly to final_path, perhaps tmp_path is not needed, or we can use it for something else.
-            // To follow the spec, let's assume the producer saves to tmp_path, and worker renames to final_path.
-            // But in the code, the open_new_segment saves to final_path.
-            // I need to modify open_new_segment to save to tmp_path, and FlushJob to rename.
-            // Yes, let's do that.
-
-            // Monitor the file size until stable
-            let mut last_size = 0u64;
-            let mut stable_count = 0;
-            let max_stable_checks = 10; // arbitrary
-            while stable_count < max_stable_checks {
-                std::thread::sleep(std::time::Duration::from_millis(100));
-                if let Ok(meta) = std::fs::metadata(&job.tmp_path) {
-                    let size = meta.len();
-                    if size == last_size {
-                        stable_count += 1;
-                    } else {
-                        stable_count = 0;
-                        last_size = size;
+/// TF graph configured by the --tf-* and --attach-to-frames options
+pub(crate) fn new_tf_graph(options: &ConvertOptions) -> Result<crate::mappings::tf::TfGraph> {
+    let mut tf_graph = crate::mappings::tf::TfGraph::new();
+    // Attached sensors sit directly under flat frame entities, which then need root poses
+    tf_graph.set_root_relative(options.attach_to_frames);
+    if options.tf_tolerance.is_nan() || options.tf_tolerance < 0.0 {
+        anyhow::bail!("--tf-tolerance must be zero or more seconds, got {}", options.tf_tolerance);
+    }
+    tf_graph.set_time_tolerance(options.tf_tolerance);
+    tf_graph.set_tolerance_fallback(options.tf_fallback);
+    tf_graph.set_authority(options.tf_authority.clone());
+    tf_graph.set_scalar_plots(options.tf_plots);
+    if let Some(length) = options.tf_axes {
+        let filter = options
+            .tf_axes_filter
+            .as_deref()
+            .map(regex::Regex::new)
+            .transpose()
+            .context("invalid --tf-axes-filter regex")?;
+        tf_graph.set_frame_axes(length, filter);
+    }
+    Ok(tf_graph)
+}
+
+/// What the messages of a conversion share on their way to the mappers, whether
+/// they come from bags or a live source
+pub(crate) struct MessageLogger {
+    pub(crate) tf_graph: TfGraph,
+    pub(crate) scalars: ScalarColumns,
+    timelines: Timelines,
+    pub(crate) drops: SeqTracker,
+    /// Gaps found on messages --max-rate dropped, logged with the next message for --log-drops
+    pending_gaps: Vec<(String, SeqGap)>,
+    /// Types of the messages no mapper logged, for the report after converting
+    pub(crate) unmapped: UnmappedTypes,
+    /// md5sums of mapped types checked against the standard ones on their first message
+    md5_check: Md5Check,
+    /// Views of the --blueprint layout, from what was logged
+    layout: BlueprintLayout,
+}
+
+/// A message kept by the filters, with the times its input gives it
+pub(crate) struct KeptMessage<'m> {
+    pub(crate) conn: u32,
+    pub(crate) topic: &'m str,
+    pub(crate) tp: &'m str,
+    pub(crate) data: &'m [u8],
+    pub(crate) topic_config: &'m TopicConfig,
+    /// Seconds since the first message of the input, in record (or receive) time
+    pub(crate) ts_rel: f64,
+    /// Log time of a message without a header stamp
+    pub(crate) receive_ts: f64,
+    /// Origin header stamps are logged from
+    pub(crate) time_base: f64,
+    /// Record (or receive) time, in seconds
+    pub(crate) bag_time: f64,
+    pub(crate) latched: bool,
+    pub(crate) type_info: Option<&'m TypeInfo>,
+    pub(crate) decoded_image: Option<Result<DecodedImage>>,
+}
+
+impl MessageLogger {
+    pub(crate) fn new(options: &ConvertOptions) -> Result<Self> {
+        Ok(Self {
+            tf_graph: new_tf_graph(options)?,
+            scalars: ScalarColumns::new(),
+            timelines: Timelines::new(),
+            drops: SeqTracker::new(),
+            pending_gaps: Vec::new(),
+            unmapped: UnmappedTypes::new(),
+            md5_check: Md5Check::default(),
+            layout: BlueprintLayout::default(),
+        })
+    }
+
+    /// Follow the header.seq of a message --max-rate drops, so the messages it thins
+    /// out are not counted as lost
+    pub(crate) fn rate_limited(&mut self, conn: u32, topic: &str, tp: &str, type_info: Option<&TypeInfo>, data: &[u8]) {
+        if let Some(gap) = self.drops.record(conn, topic, tp, type_info, data) {
+            self.pending_gaps.push((topic.to_string(), gap));
+        }
+    }
+
+    /// Whether `mapper` parses the standard layout of a type the connection recorded
+    /// with a patched definition
+    pub(crate) fn mismatched(
+        &mut self,
+        options: &ConvertOptions,
+        mapper: &dyn MessageMapper,
+        conn: u32,
+        topic: &str,
+        tp: &str,
+        info: Option<&TypeInfo>,
+    ) -> bool {
+        mapper.standard_layout() && !options.ignore_md5_mismatch && !self.md5_check.matches(conn, topic, tp, info)
+    }
+
+    /// Log `msg` with its mapper into `rec` and count it in `stats`; also tells
+    /// whether the mapper failed on it
+    pub(crate) fn log(
+        &mut self,
+        rec: &rerun::RecordingStream,
+        options: &ConvertOptions,
+        renames: &EntityRenames,
+        mappers: &mut MapperRegistry,
+        stats: &mut ConvertStats,
+        msg: KeptMessage<'_>,
+    ) -> Result<(Mapped, bool)> {
+        let KeptMessage { conn, topic, tp, data, topic_config, .. } = msg;
+        let ts = match options.timestamp_source {
+            TimestampSource::Header => header_stamp(tp, data).map(|stamp| stamp - msg.time_base).unwrap_or(msg.receive_ts),
+            TimestampSource::Bag => msg.receive_ts,
+        } + topic_config.time_offset;
+        // Origin for stamps nested inside messages (TF, Path poses)
+        let stamp_base =
+            (options.timestamp_source == TimestampSource::Header).then_some(msg.time_base - topic_config.time_offset);
+        self.scalars.set_time(self.timelines.set_message_time(rec, topic, ts, msg.ts_rel));
+        let gap = self.drops.record(conn, topic, tp, msg.type_info, data).map(|gap| (topic.to_string(), gap));
+        for (gap_topic, gap) in self.pending_gaps.drain(..).chain(gap) {
+            if options.log_drops {
+                log_gap(rec, &gap_topic, &gap)?;
+            }
+        }
+
+        // Sensor entity under its frame, when attaching to TF frames
+        let attached_path = match tp {
+            "sensor_msgs/Image"
+            | "sensor_msgs/CompressedImage"
+            | "sensor_msgs/PointCloud2"
+            | "sensor_msgs/LaserScan"
+            | "sensor_msgs/MultiEchoLaserScan"
+                if options.attach_to_frames =>
+            {
+                frame_attached_path(topic, data, &options.root_frame, &renames.frames)
+            }
+            _ => None,
+        };
+        let entity = topic_config.entity(topic);
+        // A patched definition of a built-in type is not parsed as the standard one
+        let mismatched =
+            mappers.get(topic, tp).is_some_and(|mapper| self.mismatched(options, mapper, conn, topic, tp, msg.type_info));
+        let mapper = if mismatched {
+            mappers.fallback_mut()
+        } else {
+            mappers.get_mut(topic, tp)
+        };
+        let unsupported = mapper.is_none();
+        let mapped = match mapper {
+            Some(mapper) => {
+                let mut ctx = MapperContext {
+                    rec,
+                    topic,
+                    tp,
+                    entity,
+                    attached_path: attached_path.as_deref(),
+                    ts,
+                    stamp_base,
+                    bag_time: msg.bag_time,
+                    latched: msg.latched,
+                    options,
+                    renames,
+                    topic_config,
+                    type_info: msg.type_info,
+                    tf_graph: &mut self.tf_graph,
+                    scalars: &mut self.scalars,
+                    decoded_image: msg.decoded_image,
+                };
+                mapper.map(&mut ctx, data)
+            }
+            None => Ok(Mapped::Skipped),
+        };
+        // A message the mapper fails to parse is skipped, unless --strict says otherwise;
+        // the output failing under it stops the conversion
+        let (mapped, error) = match mapped {
+            Ok(mapped) => (mapped, None),
+            Err(e) if is_output_error(&e) => return Err(e.context(format!("failed to log {} on {}", tp, topic))),
+            Err(e) => (Mapped::Skipped, Some(format!("{:#}", e))),
+        };
+        let failed = error.is_some();
+        match mapped {
+            Mapped::Logged(kind) => {
+                self.unmapped.mapped(tp);
+                stats.count(kind, data.len() as u64);
+                self.layout.add(attached_path.as_deref().unwrap_or(entity), kind);
+            }
+            Mapped::Decimated => stats.decimated_images += 1,
+            Mapped::Skipped => {
+                stats.skipped_types += 1;
+                self.unmapped.skipped(options, topic, tp, unmapped_reason(mismatched, unsupported), error)?;
+            }
+        }
+        // -vv, or RUST_LOG=bag2rrd::messages=trace for these alone
+        tracing::trace!(target: "bag2rrd::messages", topic = %topic, tp = %tp, t = msg.ts_rel, "message");
+        Ok((mapped, failed))
+    }
+
+    /// Once the last message is logged: write the --blueprint layout, report TF frames
+    /// with several parents and flush what the mappers still hold
+    pub(crate) fn finish(&mut self, options: &ConvertOptions, mappers: &mut MapperRegistry) -> Result<()> {
+        if options.blueprint && !options.dry_run && matches!(options.output_target, OutputTarget::File) {
+            let path = blueprint_path(&options.output_path);
+            save_blueprint(&self.layout, &options.application_id, &path)?;
+            tracing::info!(path = %path.display(), "blueprint written");
+        }
+        for (child, parents) in self.tf_graph.parent_conflicts() {
+            let parents: Vec<String> = parents.iter().map(|(parent, n)| format!("{parent} ({n})")).collect();
+            let warning = format!(
+                "TF frame {child} has multiple parents: {} (tf-authority: {:?})",
+                parents.join(", "),
+                options.tf_authority
+            );
+            options.warn(warning);
+        }
+        mappers.finish()?;
+        self.scalars.flush()
+    }
+}
+
+/// Recording of the single (or split) output at `output_path`, per the output
+/// target; `memory_sink` receives the sink of an [`OutputTarget::Memory`] recording
+pub(crate) fn open_recording(
+    options: &ConvertOptions,
+    output_path: &str,
+    provenance: &Provenance,
+    budget: Option<MemoryBudget>,
+    memory_sink: &mut Option<rerun::sink::MemorySinkStorage>,
+) -> Result<rerun::RecordingStream> {
+    let builder = || recording_builder(options, provenance, budget);
+    Ok(match &options.output_target {
+        OutputTarget::File => builder().save(output_path)?,
+        OutputTarget::Memory(_) => {
+            let (new_rec, storage) = builder().memory()?;
+            *memory_sink = Some(storage);
+            new_rec
+        }
+        OutputTarget::Stream(stream) => stream.clone(),
+        OutputTarget::Grpc(url) => builder().connect_grpc_opts(url.clone())?,
+        OutputTarget::Spawn => builder().spawn()?,
+        OutputTarget::Web { port, grpc_port, .. } => serve_web(builder(), *port, *grpc_port)?,
+    })
+}
+
+/// Where a recording comes from, sent with every output written
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Provenance {
+    /// Recording name shown by the viewer
+    pub name: String,
+    /// Sent as the recording properties `provenance/<name>`
+    pub properties: Vec<(&'static str, String)>,
+    /// Content-derived recording id: the md5sum of the bag, or of the md5sums of merged bags
+    pub recording_id: Option<String>,
+}
+
+impl Provenance {
+    /// File names and md5sums of the bags given (ROS2 bags and MCAP files rather
+    /// than their staged ROS1 bag), their record-time span, the bag2rrd version and options
+    pub(crate) fn of_bags(
+        options: &ConvertOptions,
+        bag_paths: &[String],
+        start_ns: Option<u64>,
+        end_ns: Option<u64>,
+    ) -> Result<Self> {
+        let md5sums = bag_paths
+            .iter()
+            .map(|path| md5_input(Path::new(path)).with_context(|| format!("failed to hash {}", path)))
+            .collect::<Result<Vec<_>>>()?;
+        let recording_id = match md5sums.as_slice() {
+            [md5sum] => md5sum.clone(),
+            md5sums => hex_md5(md5sums.join("\n").as_bytes()),
+        };
+        let file_name = |path: &String| Path::new(path).file_name().map_or(path.clone(), |n| n.to_string_lossy().into_owned());
+        let names: Vec<String> = bag_paths.iter().map(file_name).collect();
+        let mut properties = vec![("bags", names.join("\n")), ("bag_md5", md5sums.join("\n"))];
+        properties.extend(start_ns.map(|ns| ("bag_start_ns", ns.to_string())));
+        properties.extend(end_ns.map(|ns| ("bag_end_ns", ns.to_string())));
+        let name = file_name(&options.bag_path);
+        Ok(Self { recording_id: Some(recording_id), ..Self::with_options(name, properties, options) })
+    }
+
+    /// Live source of `convert_live`
+    pub(crate) fn of_source(options: &ConvertOptions) -> Self {
+        Self::with_options(options.bag_path.clone(), vec![("source", options.bag_path.clone())], options)
+    }
+
+    fn with_options(name: String, mut properties: Vec<(&'static str, String)>, options: &ConvertOptions) -> Self {
+        properties.push(("bag2rrd_version", env!("CARGO_PKG_VERSION").to_string()));
+        properties.push(("options", serde_json::to_string_pretty(options).expect("options serialize to JSON")));
+        Self { name, properties, recording_id: None }
+    }
+}
+
+/// Send the provenance and the --metadata entries as recording properties, so
+/// they travel with the RRD instead of being logged on the timeline
+pub(crate) fn send_properties(options: &ConvertOptions, rec: &rerun::RecordingStream, provenance: &Provenance) -> Result<()> {
+    rec.send_recording_name(provenance.name.as_str())?;
+    for (name, value) in &provenance.properties {
+        rec.send_property(format!("provenance/{name}"), &rerun::archetypes::TextDocument::new(value.as_str()))?;
+    }
+    for metadata_entry in &options.metadata {
+        if let Some((key, value)) = metadata_entry.split_once('=') {
+            let name = format!("metadata/{}", key.trim());
+            rec.send_property(name, &rerun::archetypes::TextDocument::new(value.trim()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Flush the single-output recording per the output target; a web viewer with
+/// `keep_serving` stays up until Ctrl-C unless the conversion was `stopped`
+pub(crate) fn finish_recording(
+    options: &ConvertOptions,
+    rec: rerun::RecordingStream,
+    memory_sink: Option<rerun::sink::MemorySinkStorage>,
+    raw_bytes: u64,
+    stopped: bool,
+) -> Result<()> {
+    match &options.output_target {
+        OutputTarget::File => {
+            flush_recording(rec, &options.output_path, raw_bytes);
+            tracing::info!(path = %options.output_path, "saved RRD");
+            if options.compact {
+                compact_rrd(Path::new(&options.output_path), CompactionLimits::default())?;
+            }
+        }
+        OutputTarget::Memory(output) => {
+            rec.flush_blocking().context("failed to flush the in-memory recording")?;
+            if let Some(storage) = memory_sink {
+                *output.0.lock().unwrap() = storage.drain_as_bytes()?;
+            }
+        }
+        OutputTarget::Stream(_) | OutputTarget::Grpc(_) | OutputTarget::Spawn => {
+            rec.flush_blocking().context("failed to flush the recording stream")?;
+        }
+        OutputTarget::Web { port, keep_serving, .. } => {
+            rec.flush_blocking().context("failed to flush the recording stream")?;
+            if *keep_serving && !stopped {
+                // The server goes away with the recording stream
+                tracing::info!(port, "conversion done; still serving, Ctrl-C to stop");
+                while !options.cancelled() {
+                    std::thread::sleep(std::time::Duration::from_millis(200));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Recording served over gRPC on `grpc_port`, with the web viewer on `port` pointed at it
+#[cfg(feature = "web")]
+fn serve_web(builder: rerun::RecordingStreamBuilder, port: u16, grpc_port: u16) -> Result<rerun::RecordingStream> {
+    let server_options = rerun::ServerOptions {
+        memory_limit: rerun::MemoryLimit::from_fraction_of_total(0.25),
+        ..Default::default()
+    };
+    let rec = builder.serve_grpc_opts("0.0.0.0", grpc_port, server_options)?;
+    // Browsers of other machines reach both servers by the name of this one
+    let host = host_name();
+    let grpc_url = format!("rerun+http://{host}:{grpc_port}/proxy");
+    rerun::serve_web_viewer(rerun::web_viewer::WebViewerConfig {
+        web_port: rerun::web_viewer::WebViewerServerPort(port),
+        connect_to: Some(grpc_url),
+        open_browser: false,
+        ..Default::default()
+    })?
+    .detach();
+    tracing::info!("web viewer at http://{host}:{port}/?url=rerun%2Bhttp%3A%2F%2F{host}%3A{grpc_port}%2Fproxy");
+    Ok(rec)
+}
+
+#[cfg(not(feature = "web"))]
+fn serve_web(_builder: rerun::RecordingStreamBuilder, _port: u16, _grpc_port: u16) -> Result<rerun::RecordingStream> {
+    anyhow::bail!("--web needs bag2rrd built with the `web` feature")
+}
+
+/// Name of this machine, `localhost` when it has none
+#[cfg(feature = "web")]
+fn host_name() -> String {
+    #[cfg(unix)]
+    {
+        let mut name = [0u8; 256];
+        // SAFETY: gethostname writes at most `name.len()` bytes into `name`
+        if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } == 0 {
+            let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            if let Ok(name) = std::str::from_utf8(&name[..len])
+                && !name.is_empty()
+            {
+                return name.to_string();
+            }
+        }
+    }
+    std::env::var("COMPUTERNAME").unwrap_or_else(|_| "localhost".to_string())
+}
+
+/// Recording builder with the application and recording ids of `options`, whose
+/// batcher backlog stays within the memory budget
+fn recording_builder(
+    options: &ConvertOptions,
+    provenance: &Provenance,
+    budget: Option<MemoryBudget>,
+) -> rerun::RecordingStreamBuilder {
+    let mut builder = rerun::RecordingStreamBuilder::new(options.application_id.as_str());
+    if let Some(id) = options.recording_id.as_ref().or(provenance.recording_id.as_ref()) {
+        builder = builder.recording_id(id.as_str());
+    }
+    match batcher_config(options, budget) {
+        Some(config) => builder.batcher_config(config),
+        None => builder,
+    }
+}
+
+/// Batcher settings of the --batch-flush-* options within the memory budget;
+/// `None` keeps rerun's defaults (and their RERUN_FLUSH_* variables)
+fn batcher_config(options: &ConvertOptions, budget: Option<MemoryBudget>) -> Option<rerun::log::ChunkBatcherConfig> {
+    let tuned = options.batch_flush_ms.is_some() || options.batch_flush_bytes.is_some() || options.batch_flush_rows.is_some();
+    if !tuned && budget.is_none() {
+        return None;
+    }
+    let mut config = rerun::log::ChunkBatcherConfig::DEFAULT;
+    if let Some(ms) = options.batch_flush_ms {
+        config.flush_tick = std::time::Duration::from_millis(ms);
+    }
+    if let Some(bytes) = options.batch_flush_bytes {
+        config.flush_num_bytes = bytes;
+    }
+    if let Some(rows) = options.batch_flush_rows {
+        config.flush_num_rows = rows;
+    }
+    Some(match budget {
+        Some(budget) => budget.batcher_config(config),
+        None => config,
+    })
+}
+
+/// Filter and per-topic settings of the connections not resolved yet
+fn resolve_topic_configs(
+    conns: &ConnectionMap,
+    filter: &MessageFilter,
+    options: &ConvertOptions,
+    topic_renames: &RenameRules,
+    topic_configs: &mut HashMap<u32, TopicConfig>,
+) -> Result<()> {
+    for (id, (topic, tp)) in &conns.connections {
+        if !topic_configs.contains_key(id) && filter.allows(topic, tp) {
+            let config = TopicConfig::resolve(options, topic_renames, topic);
+            // --split-topics names files after topics with '/' as '_', so /a_b and /a/b would share one
+            if options.split_topics
+                && let Some((other, _)) = topic_configs
+                    .iter()
+                    .find(|(other, c)| c.output_group == config.output_group && conns.connections[*other].0 != *topic)
+            {
+                anyhow::bail!(
+                    "--split-topics: {} and {} would both write {}",
+                    conns.connections[other].0,
+                    topic,
+                    group_output_path(&options.output_path, config.output_group.as_deref().unwrap_or_default())
+                );
+            }
+            topic_configs.insert(*id, config);
+        }
+    }
+    Ok(())
+}
+
+/// A chunk and the number of the bag it was read from
+type BagChunk<'a> = (usize, ChunkRecord<'a>);
+
+/// Reads the chunks to convert: all at once, or a group at a time under --max-memory
+struct ChunkReader<'a> {
+    bags: &'a [RosBag],
+    /// Chunks listed by the bag indexes with the bag holding each; without every
+    /// index the bags are read sequentially in one group
+    spans: Option<&'a [(usize, ChunkSpan)]>,
+    /// Chunk positions of the bags read sequentially, when located by a scan
+    scans: &'a [Option<ChunkScan>],
+    /// Message bytes per group; unbounded reads everything in one group
+    group_bytes: Option<u64>,
+    pool: &'a rayon::ThreadPool,
+    tolerate_corruption: bool,
+    next: usize,
+    done: bool,
+    chunk_count: usize,
+    corrupted_count: usize,
+    /// Chunks whose messages stop at a damaged record
+    truncated_count: usize,
+}
+
+impl<'a> ChunkReader<'a> {
+    fn new(
+        bags: &'a [RosBag],
+        spans: Option<&'a [(usize, ChunkSpan)]>,
+        scans: &'a [Option<ChunkScan>],
+        group_bytes: Option<u64>,
+        pool: &'a rayon::ThreadPool,
+        tolerate_corruption: bool,
+    ) -> Self {
+        Self {
+            bags,
+            // Without an index the whole bag is one group
+            group_bytes: group_bytes.filter(|_| spans.is_some()),
+            spans,
+            scans,
+            pool,
+            tolerate_corruption,
+            next: 0,
+            done: false,
+            chunk_count: 0,
+            corrupted_count: 0,
+            truncated_count: 0,
+        }
+    }
+
+    /// A reader over the same chunks, from the first one
+    fn restart(&self) -> Self {
+        Self::new(self.bags, self.spans, self.scans, self.group_bytes, self.pool, self.tolerate_corruption)
+    }
+
+    /// Whether every chunk has been read
+    fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Next group of chunks and the bytes of message data it holds
+    fn next_group(&mut self) -> Result<Option<(Vec<BagChunk<'a>>, u64)>> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut group = Vec::new();
+        let mut bytes = 0;
+        while !self.done && self.group_bytes.is_none_or(|limit| bytes < limit) {
+            let records: Vec<(usize, rosbag::Result<ChunkRecord<'a>>)> = match self.spans {
+                Some(spans) => {
+                    // Bounded groups grow by one chunk per decode thread
+                    let step = match self.group_bytes {
+                        Some(_) => self.pool.current_num_threads(),
+                        None => spans.len(),
+                    };
+                    let batch = &spans[self.next..(self.next + step).min(spans.len())];
+                    self.next += batch.len();
+                    self.done = self.next >= spans.len();
+                    let bags = self.bags;
+                    self.pool.install(|| {
+                        batch
+                            .par_iter()
+                            .filter_map(|(bag, span)| read_chunk_at(&bags[*bag], span.pos).map(|r| (*bag, r)))
+                            .collect()
+                    })
+                }
+                None => {
+                    self.done = true;
+                    let mut records = Vec::new();
+                    for (index, bag) in self.bags.iter().enumerate() {
+                        match self.scans.get(index).and_then(Option::as_ref) {
+                            Some(scan) => records.extend(self.pool.install(|| {
+                                scan.positions
+                                    .par_iter()
+                                    .filter_map(|pos| read_chunk_at(bag, *pos).map(|r| (index, r)))
+                                    .collect::<Vec<_>>()
+                            })),
+                            None => records.extend(bag.chunk_records().map(|r| (index, r))),
+                        }
                     }
-                } else {
-                    stable_count = 0;
+                    records
+                }
+            };
+            for (bag, record) in records {
+                self.chunk_count += 1;
+                match record {
+                    Ok(chunk) => {
+                        if let ChunkRecord::Chunk(chunk) = &chunk {
+                            for msg in chunk.messages() {
+                                match msg {
+                                    Ok(MessageRecord::MessageData(msg_data)) => bytes += msg_data.data.len() as u64,
+                                    Ok(MessageRecord::Connection(_)) => {}
+                                    // The records after a damaged one cannot be located
+                                    Err(e) if self.tolerate_corruption => {
+                                        self.truncated_count += 1;
+                                        tracing::warn!(
+                                            "Skipping the rest of damaged chunk #{}: {}",
+                                            self.chunk_count,
+                                            e
+                                        );
+                                        break;
+                                    }
+                                    Err(e) => return Err(e.into()),
+                                }
+                            }
+                        }
+                        group.push((bag, chunk));
+                    }
+                    Err(e) if self.tolerate_corruption => {
+                        self.corrupted_count += 1;
+                        tracing::warn!("Skipping corrupted chunk #{}: {}", self.chunk_count, e);
+                    }
+                    Err(e) => return Err(e.into()),
                 }
             }
-            // Rename tmp to final
-            std::fs::rename(&job.tmp_path, &job.final_path)?;
-            Ok(job)
-        })();
-        let _ = tx.send(res);
+        }
+        if self.done && self.corrupted_count + self.truncated_count > 0 {
+            tracing::warn!(
+                "Skipped {} corrupted chunks and the end of {} damaged chunks out of {} total chunks",
+                self.corrupted_count,
+                self.truncated_count,
+                self.chunk_count
+            );
+        }
+        Ok(Some((group, bytes)))
     }
 }
 
-fn flush_recording(rec: rerun::RecordingStream, out_path: &str, raw_total: u64, prefix: &str) {
-    use std::io::Write;
-    let debug_timings = std::env::var("BAG2RRD_DEBUG_TIMINGS").ok().as_deref() == Some("1");
-    let timeout_secs: u64 = std::env::var("BAG2RRD_FLUSH_TIMEOUT_SECS")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(0);
-    if debug_timings {
-        eprintln!(
-            "{prefix}[debug] flushing (timeout={}s, 0=forever) file={}",
-            timeout_secs, out_path
-        );
+/// Messages of a chunk group in timestamp order, under their shared connection ids
+///
+/// A chunk's messages end at its first damaged record, which the chunk reader
+/// has already reported or tolerated.
+fn group_messages<'a>(chunks: &'a [BagChunk<'_>], conns: &ConnectionMap) -> Vec<MessageData<'a>> {
+    let mut messages = Vec::new();
+    for (bag, record) in chunks {
+        if let ChunkRecord::Chunk(chunk) = record {
+            for msg in chunk.messages().map_while(Result::ok) {
+                if let MessageRecord::MessageData(msg_data) = msg
+                    && let Some(conn_id) = conns.get(*bag, msg_data.conn_id)
+                {
+                    messages.push(MessageData { conn_id, ..msg_data });
+                }
+            }
+        }
+    }
+    // Chunks may overlap in time (e.g. reindexed or merged bags); log in timestamp order
+    messages.sort_by_key(|msg_data| msg_data.time);
+    messages
+}
+
+/// `<dir>/<stem>_<group>.<ext>` for output `<dir>/<stem>.<ext>`
+pub(crate) fn group_output_path(output_path: &str, group: &str) -> String {
+    let path = Path::new(output_path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("out");
+    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("rrd");
+    path.with_file_name(format!("{stem}_{group}.{ext}")).to_string_lossy().into_owned()
+}
+
+/// Add a flushed part to the manifest, rewritten after every part so an interrupted
+/// run can --resume from it
+fn record_part(
+    options: &ConvertOptions,
+    manifest: &mut SegmentManifest,
+    path: &Path,
+    result: Result<SegmentEntry>,
+) -> Result<()> {
+    match result {
+        Ok(part) => {
+            tracing::info!(segment = part.part, file = %part.file, bytes = part.bytes, "segment completed");
+            options.emit(ConvertEvent::SegmentCompleted(part.clone()));
+            manifest.segments.push(part);
+            manifest.segments.sort_by_key(|s| s.part);
+            manifest.write(path)
+        }
+        Err(e) => {
+            tracing::error!("flush failed: {:#}", e);
+            Ok(())
+        }
     }
-    let t0 = if debug_timings {
-        Some(std::time::Instant::now())
+}
+
+/// Hand a finished segment to the flush workers
+fn submit_segment(flush_tx: &Sender<FlushJob>, job: FlushJob, images: u64, raw_bytes: u64) -> Result<()> {
+    tracing::debug!(segment = job.part_index, images, raw_bytes, "submitting flush job");
+    flush_tx.send(job)?;
+    Ok(())
+}
+
+/// Holds messages back until their offset in the bag, divided by the rate,
+/// has elapsed since the first paced message
+struct Pacer {
+    rate: f64,
+    start: Option<(f64, Instant)>,
+}
+
+impl Pacer {
+    fn new(rate: f64) -> Self {
+        Self { rate, start: None }
+    }
+
+    /// Wait until a message at `offset` seconds is due; false if cancelled meanwhile
+    fn wait(&mut self, offset: f64, cancelled: impl Fn() -> bool) -> bool {
+        if self.rate <= 0.0 {
+            return true;
+        }
+        let (first, started) = *self.start.get_or_insert((offset, Instant::now()));
+        let due = std::time::Duration::from_secs_f64(((offset - first) / self.rate).max(0.0));
+        // Gaps in a bag can last minutes: sleep in short steps to notice Ctrl-C
+        while let Some(left) = due.checked_sub(started.elapsed()).filter(|left| !left.is_zero()) {
+            if cancelled() {
+                return false;
+            }
+            std::thread::sleep(left.min(std::time::Duration::from_millis(100)));
+        }
+        true
+    }
+}
+
+/// Whether a mapper error comes from the recording stream or its sink, or from a
+/// file the mapper writes, rather than from the message
+fn is_output_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<rerun::RecordingStreamError>() || cause.is::<std::io::Error>())
+}
+
+/// Why a message the mapping step skipped was not logged
+pub(crate) fn unmapped_reason(mismatched: bool, unsupported: bool) -> UnmappedReason {
+    if mismatched {
+        UnmappedReason::Md5Mismatch
+    } else if unsupported {
+        UnmappedReason::Unsupported
+    } else {
+        UnmappedReason::Undecodable
+    }
+}
+
+/// When a segmented conversion closes its part: after --segment-size messages that
+/// fill segments or --segment-bytes of their payload, or before the first message
+/// past its --segment-seconds window. The dry-run plan counts parts with it too
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct SegmentRotation {
+    size: u64,
+    bytes: u64,
+    seconds: Option<f64>,
+    /// Origin of the --segment-seconds windows
+    origin: f64,
+    /// End of the open part's window, in bag seconds
+    end: Option<f64>,
+    /// Messages filling the open part, and their payload bytes
+    pub images: u64,
+    pub raw_bytes: u64,
+}
+
+impl SegmentRotation {
+    /// Rotation of `options`; `bag_start_s` is the origin of unaligned --segment-seconds windows
+    pub(crate) fn new(options: &ConvertOptions, bag_start_s: f64) -> Self {
+        Self {
+            size: options.segment_size.unwrap_or(0) as u64,
+            bytes: options.segment_bytes.unwrap_or(0),
+            seconds: options.segment_seconds,
+            origin: if options.segment_align { 0.0 } else { bag_start_s },
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.size > 0 || self.bytes > 0 || self.seconds.is_some()
+    }
+
+    /// Whether the message at `bag_s` is past the window of the open part
+    pub(crate) fn window_ended(&self, bag_s: f64) -> bool {
+        self.end.is_some_and(|end| bag_s >= end)
+    }
+
+    /// Open the window of the part the message at `bag_s` starts
+    pub(crate) fn start(&mut self, bag_s: f64) {
+        self.end = self.seconds.map(|seconds| segment_window_end(bag_s, seconds, self.origin));
+    }
+
+    /// Count a message filling the open part
+    pub(crate) fn fill(&mut self, payload_len: u64) {
+        self.images += 1;
+        self.raw_bytes += payload_len;
+    }
+
+    /// Whether the open part holds --segment-size messages or --segment-bytes
+    pub(crate) fn full(&self) -> bool {
+        (self.size > 0 && self.images >= self.size) || (self.bytes > 0 && self.raw_bytes >= self.bytes)
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.images = 0;
+        self.raw_bytes = 0;
+    }
+}
+
+/// End of the `seconds`-long window holding `t`, with windows starting at `origin`
+/// plus a whole number of windows (origin 0 aligns them to multiples of `seconds`)
+fn segment_window_end(t: f64, seconds: f64, origin: f64) -> f64 {
+    origin + ((t - origin) / seconds).floor() * seconds + seconds
+}
+
+fn flush_worker(rx: Receiver<FlushJob>, tx: Sender<anyhow::Result<SegmentEntry>>) {
+    while let Ok(job) = rx.recv() {
+        let _ = tx.send(finalize_segment(job));
+    }
+}
+
+/// Flush and close a segment's recording, then move the complete file into place
+fn finalize_segment(job: FlushJob) -> Result<SegmentEntry> {
+    let FlushJob { part_index, rec, tmp_path, final_path, checksum, compact, contents } = job;
+    rec.flush_blocking()
+        .with_context(|| format!("failed to flush segment {part_index}"))?;
+    // Dropping the last handle shuts down the file sink once everything is written
+    drop(rec);
+    if compact {
+        compact_rrd(&tmp_path, CompactionLimits::default())
+            .with_context(|| format!("failed to compact segment {part_index}"))?;
+    }
+    let file = std::fs::File::open(&tmp_path)
+        .with_context(|| format!("segment {part_index} was not written to {}", tmp_path.display()))?;
+    file.sync_all()?;
+    let bytes = file.metadata()?.len();
+    drop(file);
+    let name = final_path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string();
+    let sha256 = if checksum {
+        let digest = sha256_file(&tmp_path)?;
+        let mut checksum_path = final_path.clone().into_os_string();
+        checksum_path.push(".sha256");
+        std::fs::write(&checksum_path, format!("{digest}  {name}\n"))?;
+        Some(digest)
     } else {
         None
     };
+    move_into_place(&tmp_path, &final_path)
+        .with_context(|| format!("failed to move {} to {}", tmp_path.display(), final_path.display()))?;
+    Ok(contents.into_entry(part_index, name, bytes, sha256))
+}
+
+/// Rename `from` to `to`; across filesystems the copy is staged next to `to` and renamed
+fn move_into_place(from: &Path, to: &Path) -> std::io::Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    let mut staged = to.as_os_str().to_owned();
+    staged.push(".tmp");
+    std::fs::copy(from, &staged)?;
+    std::fs::File::open(&staged)?.sync_all()?;
+    std::fs::rename(&staged, to)?;
+    std::fs::remove_file(from)
+}
+
+/// Hex MD5 of a file, as printed by md5sum
+fn md5_file(path: &Path) -> Result<String> {
+    use md5::{Digest, Md5};
+    let mut hasher = Md5::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// md5sum of a bag file, or of the md5sums of the files of a ROS2 bag directory
+fn md5_input(path: &Path) -> Result<String> {
+    if !path.is_dir() {
+        return md5_file(path);
+    }
+    let mut files = std::fs::read_dir(path)?.map(|entry| Ok(entry?.path())).collect::<Result<Vec<PathBuf>>>()?;
+    files.retain(|file| file.is_file());
+    files.sort();
+    let md5sums = files
+        .iter()
+        .map(|file| Ok(format!("{}  {}", md5_file(file)?, file.file_name().unwrap_or_default().to_string_lossy())))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(hex_md5(md5sums.join("\n").as_bytes()))
+}
+
+fn hex_md5(data: &[u8]) -> String {
+    use md5::{Digest, Md5};
+    Md5::digest(data).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Hex SHA-256 of a file, as printed by sha256sum
+fn sha256_file(path: &Path) -> Result<String> {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Drop the recording, which writes out what it buffered, logging the file growth at debug level
+fn flush_recording(rec: rerun::RecordingStream, out_path: &str, raw_total: u64) {
+    let timeout_secs: u64 = std::env::var("BAG2RRD_FLUSH_TIMEOUT_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0);
+    tracing::debug!(timeout_secs, path = %out_path, "flushing (timeout 0 waits forever)");
+    let t0 = Instant::now();
     let stop_flag = Arc::new(AtomicBool::new(false));
     let path = out_path.to_string();
     let monitor_flag = Arc::clone(&stop_flag);
@@ -795,7 +2464,6 @@ fn flush_recording(rec: rerun::RecordingStream, out_path: &str, raw_total: u64,
         .ok()
         .and_then(|s| s.parse().ok())
         .unwrap_or(500);
-    let prefix_owned = prefix.to_string();
     let monitor_handle = std::thread::spawn(move || {
         let mut last_size = 0u64;
         let mut last_change = Instant::now();
@@ -804,31 +2472,23 @@ fn flush_recording(rec: rerun::RecordingStream, out_path: &str, raw_total: u64,
                 Ok(meta) => {
                     let size = meta.len();
                     if size != last_size {
-                        let delta = size.saturating_sub(last_size);
                         let pct = if raw_total > 0 {
                             (size as f64 / raw_total as f64 * 100.0).min(100.0)
                         } else {
                             0.0
                         };
-                        eprintln!(
-                            "{}[flush] size={} (+{}) est_progress={:.1}%",
-                            prefix_owned, size, delta, pct
-                        );
+                        tracing::debug!(%path, size, delta = size.saturating_sub(last_size), est_progress = pct, "flush");
                         last_size = size;
                         last_change = Instant::now();
                     } else if last_change.elapsed() > std::time::Duration::from_secs(5) {
-                        eprintln!(
-                            "{}[flush] no size change for 5s (size={})",
-                            prefix_owned, size
-                        );
+                        tracing::debug!(%path, size, "flush: no size change for 5 s");
                         last_change = Instant::now();
                     }
                 }
-                Err(e) => eprintln!("{}[flush] metadata error: {e}", prefix_owned),
+                Err(e) => tracing::debug!(%path, "flush: metadata error: {e}"),
             }
             std::thread::sleep(std::time::Duration::from_millis(poll_ms));
         }
-        eprintln!("{}[flush] monitoring stop", prefix_owned);
     });
 
     let (tx, rx) = std::sync::mpsc::channel();
@@ -842,13 +2502,967 @@ fn flush_recording(rec: rerun::RecordingStream, out_path: &str, raw_total: u64,
         .recv_timeout(std::time::Duration::from_secs(timeout_secs))
         .is_err()
     {
-        eprintln!("{prefix}[warn] timeout waiting for flush; file may be incomplete");
+        tracing::warn!(path = %out_path, "timeout waiting for flush; file may be incomplete");
     }
     stop_flag.store(true, Ordering::Relaxed);
     let _ = monitor_handle.join();
-    std::io::stdout().flush().ok();
-    std::io::stderr().flush().ok();
-    if let Some(t0) = t0 && debug_timings {
-        eprintln!("{prefix}[debug] flush completed in {:?}", t0.elapsed());
+    tracing::debug!(path = %out_path, elapsed_secs = t0.elapsed().as_secs_f64(), "flush completed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bag::{convert_options, run_convert};
+
+    #[test]
+    fn test_header_stamp() {
+        let mut payload = vec![0u8; 4]; // seq
+        payload.extend_from_slice(&10u32.to_le_bytes());
+        payload.extend_from_slice(&500_000_000u32.to_le_bytes());
+        assert_eq!(header_stamp("sensor_msgs/Imu", &payload), Some(10.5));
+        assert_eq!(header_stamp("tf2_msgs/TFMessage", &payload), None);
+        // Zero stamps fall back to bag time
+        assert_eq!(header_stamp("sensor_msgs/Imu", &[0u8; 12]), None);
+        assert_eq!(header_seq(&7u32.to_le_bytes()), Some(7));
+        assert_eq!(header_seq(&[7]), None);
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::default();
+        // 20 Hz with jitter, thinned to 10 Hz: every other message
+        let times = [0.0, 0.049, 0.101, 0.15, 0.198, 0.251, 0.3];
+        let kept: Vec<bool> = times.iter().map(|t| limiter.keep("/imu", Some(10.0), *t)).collect();
+        assert_eq!(kept, [true, false, true, false, true, false, true]);
+        // Slots restart after a silence
+        assert!(limiter.keep("/imu", Some(10.0), 5.0));
+        assert!(!limiter.keep("/imu", Some(10.0), 5.05));
+        assert!(limiter.keep("/imu", None, 5.06));
+        assert!(limiter.keep("/gps", Some(10.0), 5.06));
+
+        assert_eq!(parse_max_rate("/camera=2.5").unwrap().value, 2.5);
+        assert!(parse_max_rate("0").is_err());
+        assert!(parse_max_rate("/camera=fast").is_err());
+    }
+
+    #[test]
+    fn test_frame_attached_path() {
+        let mut payload = vec![0u8; 12]; // seq + stamp
+        payload.extend_from_slice(&5u32.to_le_bytes());
+        payload.extend_from_slice(b"laser");
+        let mappings = RenameRules::new(&["base_link=/world/robot".to_string()]).unwrap();
+        assert_eq!(
+            frame_attached_path("/scan", &payload, "world", &mappings).as_deref(),
+            Some("/world/laser/scan")
+        );
+        payload[16..21].copy_from_slice(b"world");
+        assert_eq!(frame_attached_path("/scan", &payload, "world", &RenameRules::default()).as_deref(), Some("/world/scan"));
+        // No frame_id: the sensor keeps its topic path
+        assert_eq!(frame_attached_path("/scan", &[0u8; 16], "world", &RenameRules::default()), None);
+    }
+
+    #[test]
+    fn test_options_builder_defaults_match_cli() {
+        let options = ConvertOptions::new("in.bag", "out.rrd");
+        assert_eq!(format!("{:?}", options), format!("{:?}", convert_options(&["in.bag", "out.rrd"]).unwrap()));
+
+        let options = options
+            .exclude_topics(["/debug"])
+            .segment_size(100)
+            .root_frame("map")
+            .depth_color_topic("/camera/color")
+            .tf_mode(TfMode::Interpolate);
+        assert_eq!(options.exclude_topics, ["/debug"]);
+        assert_eq!(options.segment_size, Some(100));
+        assert_eq!(options.root_frame, "map");
+        assert_eq!(options.depth_color_topic.as_deref(), Some("/camera/color"));
+        assert_eq!(options.tf_mode, TfMode::Interpolate);
+        assert_eq!(options.bag_path, "in.bag");
+    }
+
+    #[test]
+    fn test_convert_decodes_images_in_parallel() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        // 2x2 rgb8 sensor_msgs/Image
+        let image = |value: u8| {
+            let mut payload = vec![0u8; 12]; // seq, stamp
+            payload.extend_from_slice(&0u32.to_le_bytes()); // frame_id
+            payload.extend_from_slice(&2u32.to_le_bytes());
+            payload.extend_from_slice(&2u32.to_le_bytes());
+            payload.extend_from_slice(&4u32.to_le_bytes());
+            payload.extend_from_slice(b"rgb8");
+            payload.push(0);
+            payload.extend_from_slice(&6u32.to_le_bytes());
+            payload.extend_from_slice(&12u32.to_le_bytes());
+            payload.extend_from_slice(&[value; 12]);
+            payload
+        };
+        let dir = std::env::temp_dir().join(format!("bag2rrd_parallel_decode_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("images.bag");
+        let connections = [TestConnection { id: 0, topic: "/camera/image_raw", tp: "sensor_msgs/Image", latching: false }];
+        // The second chunk starts before the first one ends
+        let chunks: Vec<Vec<TestMessage>> = vec![
+            (0..40).map(|i| TestMessage::new(0, 10.0 + i as f64 * 0.1, image(i as u8))).collect(),
+            (0..40).map(|i| TestMessage::new(0, 12.05 + i as f64 * 0.1, image(i as u8))).collect(),
+        ];
+        write_bag(&bag, &connections, &chunks);
+
+        for threads in ["1", "4"] {
+            let out = dir.join(format!("out_{threads}.rrd"));
+            run_convert(&[
+                bag.to_str().unwrap(),
+                out.to_str().unwrap(),
+                "--decode-threads",
+                threads,
+                "--image-every-nth",
+                "3",
+                "--max-memory",
+                "2K",
+            ])
+            .unwrap();
+            assert!(std::fs::metadata(&out).unwrap().len() > 0);
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_segments_are_finalized_and_renamed() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_segments_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let connections = [TestConnection { id: 0, topic: "/camera/compressed", tp: "sensor_msgs/CompressedImage", latching: false }];
+        // Undecodable payloads still count towards the segment size
+        let messages = (0..12).map(|i| TestMessage::new(0, i as f64 * 0.1, vec![0; 32])).collect();
+        write_bag(&bag, &connections, &[messages]);
+        let out = dir.join("out.rrd");
+        run_convert(&[
+            bag.to_str().unwrap(),
+            out.to_str().unwrap(),
+            "--segment-size",
+            "5",
+            "--segment-checksum",
+        ])
+        .unwrap();
+
+        let mut files: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                "in.bag",
+                "out_manifest.json",
+                "out_part0001.rrd",
+                "out_part0001.rrd.sha256",
+                "out_part0002.rrd",
+                "out_part0002.rrd.sha256",
+                "out_part0003.rrd",
+                "out_part0003.rrd.sha256",
+            ]
+        );
+        let part = dir.join("out_part0003.rrd");
+        let checksum = std::fs::read_to_string(dir.join("out_part0003.rrd.sha256")).unwrap();
+        assert_eq!(checksum, format!("{}  out_part0003.rrd\n", sha256_file(&part).unwrap()));
+
+        let manifest = SegmentManifest::read(&dir.join("out_manifest.json")).unwrap();
+        let parts: Vec<(u32, &str, u64, f64, f64)> = manifest
+            .segments
+            .iter()
+            .map(|s| (s.part, s.file.as_str(), s.messages, s.start_time, s.end_time))
+            .collect();
+        assert_eq!(
+            parts,
+            [
+                (1, "out_part0001.rrd", 5, 0.0, 0.4),
+                (2, "out_part0002.rrd", 5, 0.5, 0.9),
+                (3, "out_part0003.rrd", 2, 1.0, 1.1),
+            ]
+        );
+        assert_eq!(manifest.segments[2].bytes, std::fs::metadata(&part).unwrap().len());
+        assert_eq!(manifest.segments[2].topics["/camera/compressed"], 2);
+        assert_eq!(manifest.segments[2].sha256.as_deref(), Some(checksum.split(' ').next().unwrap()));
+
+        // An interrupted run lost part 2: --resume keeps part 1 and converts from 0.5 s on
+        std::fs::remove_file(dir.join("out_part0002.rrd")).unwrap();
+        let part1 = std::fs::read(dir.join("out_part0001.rrd")).unwrap();
+        run_convert(&[bag.to_str().unwrap(), out.to_str().unwrap(), "--segment-size", "5", "--resume"]).unwrap();
+        let resumed = SegmentManifest::read(&dir.join("out_manifest.json")).unwrap();
+        let resumed_parts: Vec<(u32, &str, u64, f64, f64)> = resumed
+            .segments
+            .iter()
+            .map(|s| (s.part, s.file.as_str(), s.messages, s.start_time, s.end_time))
+            .collect();
+        assert_eq!(resumed_parts, parts);
+        assert_eq!(resumed.segments[0], manifest.segments[0]);
+        assert_eq!(std::fs::read(dir.join("out_part0001.rrd")).unwrap(), part1);
+        assert!(dir.join("out_part0002.rrd").exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_resume_keeps_context_messages() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_resume_context_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let connections = [
+            TestConnection { id: 0, topic: "/camera/compressed", tp: "sensor_msgs/CompressedImage", latching: false },
+            TestConnection { id: 1, topic: "/tf_static", tp: "tf2_msgs/TFMessage", latching: true },
+        ];
+        // /tf_static is only in the first chunk, before the resume point
+        let mut first = vec![TestMessage::new(1, 0.0, 0u32.to_le_bytes().to_vec())];
+        first.extend((0..5).map(|i| TestMessage::new(0, i as f64 * 0.1, vec![0; 32])));
+        let second = (5..10).map(|i| TestMessage::new(0, i as f64 * 0.1, vec![0; 32])).collect();
+        write_bag(&bag, &connections, &[first, second]);
+        let out = dir.join("out.rrd");
+        let args = [bag.to_str().unwrap(), out.to_str().unwrap(), "--segment-size", "5"];
+        run_convert(&args).unwrap();
+
+        std::fs::remove_file(dir.join("out_part0002.rrd")).unwrap();
+        run_convert(&[&args[..], &["--resume"]].concat()).unwrap();
+        let resumed = SegmentManifest::read(&dir.join("out_manifest.json")).unwrap();
+        assert_eq!(resumed.segments.len(), 2);
+        assert_eq!(resumed.segments[1].topics["/camera/compressed"], 5);
+        assert_eq!(resumed.segments[1].topics.get("/tf_static"), Some(&1));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_segments_by_seconds() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        assert_eq!(segment_window_end(100.5, 1.0, 100.5), 101.5);
+        assert_eq!(segment_window_end(102.2, 1.0, 100.5), 102.5);
+        assert_eq!(segment_window_end(100.5, 60.0, 0.0), 120.0);
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_segment_seconds_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let connections = [TestConnection { id: 0, topic: "/camera/compressed", tp: "sensor_msgs/CompressedImage", latching: false }];
+        // 100.5 s to 102.25 s
+        let messages = (0..8).map(|i| TestMessage::new(0, 100.5 + i as f64 * 0.25, vec![0; 32])).collect();
+        write_bag(&bag, &connections, &[messages]);
+
+        for (align, parts) in [(false, 2), (true, 3)] {
+            let out = dir.join(format!("align_{align}.rrd"));
+            let mut args = vec![bag.to_str().unwrap(), out.to_str().unwrap(), "--segment-seconds", "1"];
+            if align {
+                args.push("--segment-align");
+            }
+            run_convert(&args).unwrap();
+            let written = std::fs::read_dir(&dir)
+                .unwrap()
+                .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().starts_with(&format!("align_{align}_part")))
+                .count();
+            assert_eq!(written, parts, "align={align}");
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_split_output_groups() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_split_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let connections = [
+            TestConnection { id: 0, topic: "/camera/left", tp: "sensor_msgs/CompressedImage", latching: false },
+            TestConnection { id: 1, topic: "/camera/right", tp: "sensor_msgs/CompressedImage", latching: false },
+            TestConnection { id: 2, topic: "/status", tp: "std_msgs/String", latching: false },
+        ];
+        let messages = (0..6).map(|i| TestMessage::new(i % 3, i as f64 * 0.1, vec![0; 32])).collect();
+        write_bag(&bag, &connections, &[messages]);
+
+        let run = |name: &str, extra: &[&str]| -> Vec<String> {
+            let out = dir.join(name).join("out.rrd");
+            std::fs::create_dir_all(out.parent().unwrap()).unwrap();
+            let mut args = vec![bag.to_str().unwrap(), out.to_str().unwrap()];
+            args.extend_from_slice(extra);
+            run_convert(&args).unwrap();
+            let mut files: Vec<String> = std::fs::read_dir(out.parent().unwrap())
+                .unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            files.sort();
+            files
+        };
+        assert_eq!(run("groups", &["--output-group", "cameras=/camera/*"]), ["out.rrd", "out_cameras.rrd"]);
+        assert_eq!(
+            run("topics", &["--split-topics"]),
+            ["out_camera_left.rrd", "out_camera_right.rrd", "out_status.rrd"]
+        );
+
+        // /camera_left and /camera/left would both be out_camera_left.rrd
+        let clash = dir.join("clash.bag");
+        let connections = [
+            TestConnection { id: 0, topic: "/camera/left", tp: "sensor_msgs/CompressedImage", latching: false },
+            TestConnection { id: 1, topic: "/camera_left", tp: "sensor_msgs/CompressedImage", latching: false },
+        ];
+        write_bag(&clash, &connections, &[(0..4).map(|i| TestMessage::new(i % 2, i as f64 * 0.1, vec![0; 32])).collect()]);
+        let out = dir.join("clash").join("out.rrd");
+        let err = run_convert(&[clash.to_str().unwrap(), out.to_str().unwrap(), "--split-topics"]).unwrap_err();
+        assert!(err.to_string().contains("would both write"), "{err:#}");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_convert_merges_bags() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_merge_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Split parts number their connections independently; the last part overlaps the others
+        let image = |id| TestConnection { id, topic: "/camera", tp: "sensor_msgs/CompressedImage", latching: false };
+        let messages = |times: &[f64], conn| times.iter().map(|t| TestMessage::new(conn, *t, vec![0; 16])).collect();
+        write_bag(&dir.join("run_0.bag"), &[image(0)], &[messages(&[0.0, 0.1, 0.2], 0)]);
+        write_bag(&dir.join("run_1.bag"), &[image(3)], &[messages(&[0.3, 0.4, 0.5], 3)]);
+        write_bag(&dir.join("run_2.bag"), &[image(1)], &[messages(&[0.25, 0.45], 1)]);
+
+        let out = dir.join("out.rrd");
+        let bags = format!("{}/run_*.bag", dir.display());
+        run_convert(&[&bags, out.to_str().unwrap(), "--segment-size", "4", "--start", "0.15"]).unwrap();
+
+        let manifest = SegmentManifest::read(&dir.join("out_manifest.json")).unwrap();
+        let parts: Vec<(u64, f64, f64)> =
+            manifest.segments.iter().map(|s| (s.messages, s.start_time, s.end_time)).collect();
+        assert_eq!(parts, [(4, 0.2, 0.4), (2, 0.45, 0.5)]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_tolerate_corruption_skips_damaged_data() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_corrupt_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let connections = [TestConnection { id: 0, topic: "/camera", tp: "sensor_msgs/CompressedImage", latching: false }];
+        let chunks: Vec<Vec<TestMessage>> = (0..4)
+            .map(|c| (0..3).map(|i| TestMessage::new(0, c as f64 + i as f64 * 0.1, vec![0; 16])).collect())
+            .collect();
+        write_bag(&bag, &connections, &chunks);
+        let clean = std::fs::read(&bag).unwrap();
+        let positions: Vec<usize> = BagIndex::read(&RosBag::new(&bag).unwrap())
+            .unwrap()
+            .unwrap()
+            .chunks
+            .iter()
+            .map(|c| c.pos as usize)
+            .collect();
+        let find = |data: &[u8], from: usize, needle: &[u8]| {
+            from + data[from..].windows(needle.len()).position(|w| w == needle).unwrap()
+        };
+        // Messages kept, read back from the manifest of a single segment
+        let convert = |tolerate: bool| -> Result<u64> {
+            let out = dir.join("out.rrd");
+            let mut args = vec![bag.to_str().unwrap(), out.to_str().unwrap(), "--segment-size", "100"];
+            if tolerate {
+                args.push("--tolerate-corruption");
+            }
+            run_convert(&args)?;
+            let manifest = SegmentManifest::read(&dir.join("out_manifest.json"))?;
+            Ok(manifest.segments.iter().map(|s| s.messages).sum())
+        };
+
+        // The second chunk's size field disagrees with its data
+        let mut data = clean.clone();
+        let size = find(&data, positions[1], b"size=") + 5;
+        data[size] ^= 1;
+        // The third chunk's second message has a garbage header length
+        let message = find(&data, find(&data, positions[2], b"\x04\x00\x00\x00op=\x02") + 1, b"\x04\x00\x00\x00op=\x02");
+        data[message - 4..message].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+        std::fs::write(&bag, &data).unwrap();
+        assert!(convert(false).is_err());
+        assert_eq!(convert(true).unwrap(), 3 + 1 + 3);
+
+        // Unreadable index: the chunks are found by scanning, past a damaged chunk header
+        let mut data = clean.clone();
+        let index_pos = *positions.last().unwrap() + 1;
+        let info = find(&data, index_pos, b"op=\x06");
+        data[info + 3] = 0x09;
+        data[positions[1]..positions[1] + 4].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+        std::fs::write(&bag, &data).unwrap();
+        assert_eq!(convert(true).unwrap(), 3 + 3 + 3);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_bag_without_index_section_is_read_sequentially() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_no_index_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let connections = [TestConnection { id: 0, topic: "/camera", tp: "sensor_msgs/CompressedImage", latching: false }];
+        let chunks: Vec<Vec<TestMessage>> = (0..3)
+            .map(|c| (0..2).map(|i| TestMessage::new(0, c as f64 + i as f64 * 0.1, vec![0; 16])).collect())
+            .collect();
+        write_bag(&bag, &connections, &chunks);
+        // A crashed recorder leaves index_pos at 0 and no index section
+        let mut data = std::fs::read(&bag).unwrap();
+        let layout = BagLayout::parse(&data).unwrap();
+        data.truncate(layout.index_pos as usize);
+        let field = data.windows(10).position(|w| w == b"index_pos=").unwrap() + 10;
+        data[field..field + 8].copy_from_slice(&0u64.to_le_bytes());
+        std::fs::write(&bag, &data).unwrap();
+
+        let out = dir.join("out.rrd");
+        run_convert(&[bag.to_str().unwrap(), out.to_str().unwrap(), "--segment-size", "100"]).unwrap();
+        let manifest = SegmentManifest::read(&dir.join("out_manifest.json")).unwrap();
+        assert_eq!(manifest.segments.iter().map(|s| s.messages).sum::<u64>(), 6);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_chunk_reader_groups_under_budget() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let path = std::env::temp_dir().join(format!("bag2rrd_chunk_groups_{}.bag", std::process::id()));
+        let connections = [TestConnection { id: 0, topic: "/data", tp: "std_msgs/String", latching: false }];
+        let chunks: Vec<Vec<TestMessage>> = (0..5)
+            .map(|c| (0..4).map(|i| TestMessage::new(0, c as f64 + i as f64 * 0.1, vec![0; 100])).collect())
+            .collect();
+        write_bag(&path, &connections, &chunks);
+        let bags = [RosBag::new(&path).unwrap()];
+        let index = BagIndex::read(&bags[0]).unwrap().unwrap();
+        let spans: Vec<(usize, ChunkSpan)> = index.chunks.iter().map(|span| (0, *span)).collect();
+        let mut conns = ConnectionMap::default();
+        conns.insert(0, 0, "/data", "std_msgs/String", false);
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+
+        let group_sizes = |group_bytes: Option<u64>| {
+            let mut reader = ChunkReader::new(&bags, Some(&spans), &[], group_bytes, &pool, false);
+            let mut sizes = Vec::new();
+            while let Some((group, bytes)) = reader.next_group().unwrap() {
+                assert_eq!(group_messages(&group, &conns).len() * 100, bytes as usize);
+                sizes.push(group.len());
+            }
+            sizes
+        };
+        assert_eq!(group_sizes(None), [5]);
+        // 400 bytes per chunk, read two at a time: groups close once over 500 bytes
+        assert_eq!(group_sizes(Some(500)), [2, 2, 1]);
+        assert_eq!(group_sizes(Some(1)), [2, 2, 1]);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_compressed_bags_convert_like_uncompressed() {
+        use crate::test_bag::{write_bag_compressed, TestCompression, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_compressed_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let connections = [
+            TestConnection { id: 0, topic: "/camera", tp: "sensor_msgs/CompressedImage", latching: false },
+            TestConnection { id: 1, topic: "/data", tp: "std_msgs/String", latching: false },
+        ];
+        let chunks: Vec<Vec<TestMessage>> = (0..3)
+            .map(|c| {
+                (0..4)
+                    .map(|i| TestMessage::new(i % 2, c as f64 + i as f64 * 0.1, vec![c as u8; 64]))
+                    .collect()
+            })
+            .collect();
+        // Messages kept per topic, read back from the manifest of a single segment
+        let convert = |compression: TestCompression| -> BTreeMap<String, u64> {
+            let bag = dir.join(format!("{:?}.bag", compression));
+            write_bag_compressed(&bag, &connections, &chunks, compression);
+            let out = dir.join(format!("{:?}.rrd", compression));
+            let args = [bag.to_str().unwrap(), out.to_str().unwrap(), "--segment-size", "100"];
+            run_convert(&args).unwrap();
+            let manifest = SegmentManifest::read(&dir.join(format!("{:?}_manifest.json", compression))).unwrap();
+            manifest.segments[0].topics.clone()
+        };
+
+        let expected = convert(TestCompression::None);
+        assert_eq!(expected["/camera"], 6);
+        assert_eq!(convert(TestCompression::Bz2), expected);
+        assert_eq!(convert(TestCompression::Lz4), expected);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_generic_fallback_logs_unsupported_types() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_generic_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let connections = [
+            TestConnection { id: 0, topic: "/reading", tp: "test_msgs/Reading", latching: false },
+            TestConnection { id: 1, topic: "/status", tp: "std_msgs/String", latching: false },
+            TestConnection { id: 2, topic: "/opaque", tp: "test_msgs/Undefined", latching: false },
+        ];
+        let reading = |value: f64| {
+            let mut payload = [0u8; 12].to_vec();
+            payload.extend_from_slice(&4u32.to_le_bytes());
+            payload.extend_from_slice(b"base");
+            payload.extend_from_slice(&value.to_le_bytes());
+            payload.extend_from_slice(&2u32.to_le_bytes());
+            payload.extend_from_slice(b"ok");
+            payload
+        };
+        let messages = vec![
+            TestMessage::new(0, 0.0, reading(1.5)),
+            TestMessage::new(1, 0.1, [2u32.to_le_bytes().as_slice(), b"hi"].concat()),
+            TestMessage::new(0, 0.2, reading(2.5)),
+            // Truncated: skipped like an unsupported type
+            TestMessage::new(0, 0.3, reading(3.5)[..20].to_vec()),
+            TestMessage::new(2, 0.4, vec![0; 8]),
+        ];
+        write_bag(&bag, &connections, &[messages]);
+        // Messages kept per topic, read back from the manifest of a single segment
+        let convert = |fallback: bool| -> BTreeMap<String, u64> {
+            let out = dir.join("out.rrd");
+            let mut args = vec![bag.to_str().unwrap(), out.to_str().unwrap(), "--segment-size", "100"];
+            if fallback {
+                args.push("--generic-fallback");
+            }
+            run_convert(&args).unwrap();
+            let manifest = SegmentManifest::read(&dir.join("out_manifest.json")).unwrap();
+            manifest.segments.iter().flat_map(|s| s.topics.clone()).collect()
+        };
+
+        assert_eq!(convert(false), BTreeMap::new());
+        assert_eq!(
+            convert(true),
+            BTreeMap::from([("/reading".to_string(), 2), ("/status".to_string(), 1)])
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_progress_hook_receives_events() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+        use std::sync::Mutex;
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_events_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let out = dir.join("out.rrd");
+        let connections = [TestConnection { id: 0, topic: "/status", tp: "std_msgs/String", latching: false }];
+        let messages: Vec<TestMessage> = (0..1500)
+            .map(|i| TestMessage::new(0, i as f64 * 0.01, [2u32.to_le_bytes().as_slice(), b"ok"].concat()))
+            .collect();
+        write_bag(&bag, &connections, &[messages]);
+        let args = [
+            bag.to_str().unwrap(),
+            out.to_str().unwrap(),
+            "--segment-size",
+            "100",
+            "--generic-fallback",
+            "--sim-time",
+        ];
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let options = convert_options(&args)
+            .unwrap()
+            .progress_hook(ProgressHook::new(move |event| sink.lock().unwrap().push(event.clone())));
+        convert_bag(&options).unwrap();
+
+        let events = events.lock().unwrap();
+        let progress: Vec<u64> = events
+            .iter()
+            .filter_map(|e| match e {
+                ConvertEvent::Progress(stats) => Some(stats.processed_msgs),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(progress, [PROGRESS_INTERVAL]);
+        // No /clock in the bag
+        assert!(events.iter().any(|e| matches!(e, ConvertEvent::Warning(w) if w.contains("--sim-time"))));
+        assert!(events.iter().any(|e| matches!(e, ConvertEvent::SegmentCompleted(part) if part.messages == 1500)));
+        let Some(ConvertEvent::Finished(stats)) = events.last() else {
+            panic!("last event is not Finished: {:?}", events.last());
+        };
+        assert_eq!((stats.processed_msgs, stats.kept_msgs, stats.generic_msgs), (1500, 1500, 1500));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_cancellation_token_finalizes_partial_output() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_cancel_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let out = dir.join("out.rrd");
+        let connections = [TestConnection { id: 0, topic: "/status", tp: "std_msgs/String", latching: false }];
+        let messages: Vec<TestMessage> = (0..1500)
+            .map(|i| TestMessage::new(0, i as f64 * 0.01, [2u32.to_le_bytes().as_slice(), b"ok"].concat()))
+            .collect();
+        write_bag(&bag, &connections, &[messages]);
+        let args =
+            [bag.to_str().unwrap(), out.to_str().unwrap(), "--segment-size", "100", "--generic-fallback"];
+        // Cancelled from the hook, as a GUI's stop button would from its own thread
+        let token = CancellationToken::new();
+        let hook_token = token.clone();
+        let options = convert_options(&args).unwrap().cancel(token).progress_hook(ProgressHook::new(move |event| {
+            if matches!(event, ConvertEvent::Progress(_)) {
+                hook_token.cancel();
+            }
+        }));
+        let err = convert_bag(&options).unwrap_err();
+        let interrupted = err.downcast_ref::<crate::interrupt::Interrupted>().unwrap();
+        assert_eq!(interrupted.processed_msgs, PROGRESS_INTERVAL);
+        let stopped_at = interrupted.stopped_at.unwrap();
+        assert!((stopped_at - 9.99).abs() < 1e-6, "{stopped_at}");
+
+        let manifest = SegmentManifest::read(&dir.join("out_manifest.json")).unwrap();
+        assert_eq!(manifest.segments.iter().map(|s| s.messages).sum::<u64>(), PROGRESS_INTERVAL);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_memory_and_stream_targets() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_targets_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let out = dir.join("out.rrd");
+        let connections = [TestConnection { id: 0, topic: "/status", tp: "std_msgs/String", latching: false }];
+        let messages = vec![TestMessage::new(0, 0.0, [2u32.to_le_bytes().as_slice(), b"ok"].concat())];
+        write_bag(&bag, &connections, &[messages]);
+        let options = ConvertOptions::new(bag.to_str().unwrap(), out.to_str().unwrap())
+            .show_progress(false)
+            .generic_fallback(true);
+
+        let memory = MemoryOutput::new();
+        convert_bag(&options.clone().output_target(OutputTarget::Memory(memory.clone()))).unwrap();
+        assert!(!memory.take().is_empty());
+        assert!(!out.exists());
+
+        let (stream, storage) = rerun::RecordingStreamBuilder::new("host_app").memory().unwrap();
+        convert_bag(&options.clone().output_target(OutputTarget::Stream(stream.clone()))).unwrap();
+        assert!(!storage.drain_as_bytes().unwrap().is_empty());
+        assert!(!out.exists());
+
+        // Segments are files
+        let segmented = options.segment_size(10).output_target(OutputTarget::Memory(memory));
+        assert!(convert_bag(&segmented).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_recording_id_derives_from_bag_content() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_recording_id_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let connections = [TestConnection { id: 0, topic: "/status", tp: "std_msgs/String", latching: false }];
+        let messages = vec![TestMessage::new(0, 0.0, [2u32.to_le_bytes().as_slice(), b"ok"].concat())];
+        let bag = dir.join("in.bag");
+        write_bag(&bag, &connections, &[messages]);
+        let copy = dir.join("copy.bag");
+        std::fs::copy(&bag, &copy).unwrap();
+        let (bag, copy) = (bag.to_string_lossy().into_owned(), copy.to_string_lossy().into_owned());
+
+        let options = ConvertOptions::new(bag.as_str(), "out.rrd");
+        let single = Provenance::of_bags(&options, std::slice::from_ref(&bag), Some(0), Some(0)).unwrap();
+        let copied = Provenance::of_bags(&options, std::slice::from_ref(&copy), Some(0), Some(0)).unwrap();
+        let merged = Provenance::of_bags(&options, &[bag.clone(), copy], None, None).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        // Same content, same id wherever the bag is; nothing of the path in it
+        assert_eq!(single.recording_id, copied.recording_id);
+        assert_eq!(single.recording_id.as_ref().unwrap().len(), 32);
+        assert_ne!(merged.recording_id, single.recording_id);
+        assert_eq!(single.name, "in.bag");
+        assert!(single.properties.iter().any(|(name, value)| *name == "bag_md5" && Some(value) == single.recording_id.as_ref()));
+    }
+
+    #[test]
+    fn test_provenance_names_the_given_bag() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+        use re_log_types::LogMsg;
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_provenance_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let connections = [TestConnection { id: 0, topic: "/status", tp: "std_msgs/String", latching: false }];
+        let messages = vec![TestMessage::new(0, 0.0, [2u32.to_le_bytes().as_slice(), b"ok"].concat())];
+        write_bag(&bag, &connections, &[messages]);
+        // Without an index section the bag is read from a reindexed copy
+        let mut data = std::fs::read(&bag).unwrap();
+        data.truncate(BagLayout::parse(&data).unwrap().index_pos as usize);
+        let field = data.windows(10).position(|w| w == b"index_pos=").unwrap() + 10;
+        data[field..field + 8].copy_from_slice(&0u64.to_le_bytes());
+        std::fs::write(&bag, &data).unwrap();
+
+        let out = dir.join("out.rrd");
+        run_convert(&[bag.to_str().unwrap(), out.to_str().unwrap(), "--max-rate", "/status=5"]).unwrap();
+        let decoder = re_log_encoding::decoder::Decoder::new(std::io::BufReader::new(std::fs::File::open(&out).unwrap())).unwrap();
+        let mut properties = HashMap::new();
+        for msg in decoder {
+            let LogMsg::ArrowMsg(_, arrow_msg) = msg.unwrap() else { continue };
+            let chunk = re_chunk::Chunk::from_arrow_msg(&arrow_msg).unwrap();
+            let Some(name) = chunk.entity_path().to_string().strip_prefix("/__properties/provenance/").map(str::to_string) else {
+                continue;
+            };
+            for texts in chunk.iter_slices::<String>(rerun::archetypes::TextDocument::descriptor_text()) {
+                properties.insert(name.clone(), texts[0].as_str().to_string());
+            }
+        }
+        assert_eq!(properties["bags"], "in.bag");
+        assert_eq!(properties["bag_md5"], md5_file(&bag).unwrap());
+        let options: serde_json::Value = serde_json::from_str(&properties["options"]).unwrap();
+        assert_eq!(options["max_rates"][0]["topic"], "/status");
+        assert!(options.get("bag_path").is_none() && options.get("output_path").is_none());
+        assert!(!properties["options"].contains(dir.to_str().unwrap()));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_pacer_follows_bag_time() {
+        let started = Instant::now();
+        let mut pacer = Pacer::new(10.0);
+        assert!(pacer.wait(5.0, || false));
+        assert!(pacer.wait(5.5, || false));
+        // Out of order messages are not held back
+        assert!(pacer.wait(5.2, || false));
+        assert!(started.elapsed() >= std::time::Duration::from_millis(50));
+        assert!(!pacer.wait(3600.0, || true));
+
+        let started = Instant::now();
+        let mut unpaced = Pacer::new(0.0);
+        assert!(unpaced.wait(0.0, || false) && unpaced.wait(3600.0, || false));
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_viewer_flags_select_output_target() {
+        let target = |flags: &[&str]| {
+            convert_options(&[&["in.bag", "-"][..], flags].concat()).unwrap().output_target
+        };
+        assert!(matches!(target(&[]), OutputTarget::File));
+        assert!(matches!(target(&["--connect"]), OutputTarget::Grpc(url) if url == DEFAULT_VIEWER_URL));
+        assert!(matches!(
+            target(&["--connect", "rerun+http://10.0.0.5:9876/proxy"]),
+            OutputTarget::Grpc(url) if url == "rerun+http://10.0.0.5:9876/proxy"
+        ));
+        assert!(matches!(target(&["--spawn"]), OutputTarget::Spawn));
+        assert!(matches!(
+            target(&["--web"]),
+            OutputTarget::Web { port: DEFAULT_WEB_PORT, grpc_port: DEFAULT_WEB_GRPC_PORT, keep_serving: false }
+        ));
+        assert!(matches!(
+            target(&["--web", "--web-port", "8080", "--web-grpc-port", "9000", "--keep-serving"]),
+            OutputTarget::Web { port: 8080, grpc_port: 9000, keep_serving: true }
+        ));
+        assert!(convert_options(&["in.bag", "-", "--connect", "--spawn"]).is_err());
+        assert!(convert_options(&["in.bag", "-", "--spawn", "--web"]).is_err());
+        assert!(convert_options(&["in.bag", "-", "--keep-serving"]).is_err());
+        assert!(convert_options(&["in.bag", "-", "--web-grpc-port", "9000"]).is_err());
+    }
+
+    #[test]
+    fn test_custom_mapper_handles_registered_topic() {
+        use crate::mappings::registry::MessageMapper;
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        /// Logs the length of each string and remembers the topics it saw
+        struct StringLength(Rc<RefCell<Vec<String>>>);
+
+        impl MessageMapper for StringLength {
+            fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> Result<Mapped> {
+                self.0.borrow_mut().push(ctx.topic.to_string());
+                ctx.rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ctx.ts);
+                ctx.rec.log(ctx.entity, &rerun::archetypes::Scalars::new([payload.len() as f64]))?;
+                Ok(Mapped::Logged(MessageKind::Other))
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_mapper_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let out = dir.join("out.rrd");
+        let connections = [
+            TestConnection { id: 0, topic: "/status", tp: "std_msgs/String", latching: false },
+            TestConnection { id: 1, topic: "/chatter", tp: "std_msgs/String", latching: false },
+        ];
+        let text = |s: &str| [(s.len() as u32).to_le_bytes().as_slice(), s.as_bytes()].concat();
+        let messages = vec![
+            TestMessage::new(0, 0.0, text("ok")),
+            TestMessage::new(1, 0.1, text("hello")),
+            TestMessage::new(0, 0.2, text("warn")),
+        ];
+        write_bag(&bag, &connections, &[messages]);
+        let args = [bag.to_str().unwrap(), out.to_str().unwrap(), "--segment-size", "100"];
+        let options = convert_options(&args).unwrap();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let mut mappers = MapperRegistry::builtin(&options);
+        mappers.register_topic("status", Box::new(StringLength(seen.clone())));
+        convert_bag_with(&options, mappers).unwrap();
+
+        // /chatter has no mapper: std_msgs/String is not a built-in type
+        assert_eq!(*seen.borrow(), ["/status", "/status"]);
+        let manifest = SegmentManifest::read(&dir.join("out_manifest.json")).unwrap();
+        let topics: BTreeMap<String, u64> = manifest.segments.iter().flat_map(|s| s.topics.clone()).collect();
+        assert_eq!(topics, BTreeMap::from([("/status".to_string(), 2)]));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_md5_mismatch_skips_patched_types() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_md5_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let connections = [TestConnection { id: 0, topic: "/fix", tp: "sensor_msgs/NavSatFix", latching: false }];
+        // Standard NavSatFix fields followed by a vendor-specific one
+        let mut fix = [0u8; 12].to_vec();
+        fix.extend_from_slice(&0u32.to_le_bytes());
+        fix.extend_from_slice(&[0, 1, 0]);
+        for v in [45.5, -73.6, 30.0] {
+            fix.extend_from_slice(&f64::to_le_bytes(v));
+        }
+        fix.extend_from_slice(&[0; 9 * 8 + 1]);
+        fix.extend_from_slice(&f64::to_le_bytes(0.8));
+        write_bag(&bag, &connections, &[vec![TestMessage::new(0, 0.0, fix)]]);
+        let standard = crate::ros_msg::known_md5sum("sensor_msgs/NavSatFix").unwrap().as_bytes();
+        let mut data = std::fs::read(&bag).unwrap();
+        while let Some(pos) = data.windows(standard.len()).position(|w| w == standard) {
+            data[pos..pos + standard.len()].copy_from_slice(&[b'f'; 32]);
+        }
+        std::fs::write(&bag, &data).unwrap();
+
+        let convert = |extra: &[&str]| -> BTreeMap<String, u64> {
+            let out = dir.join("out.rrd");
+            let mut args = vec![bag.to_str().unwrap(), out.to_str().unwrap(), "--segment-size", "100"];
+            args.extend_from_slice(extra);
+            run_convert(&args).unwrap();
+            let manifest = SegmentManifest::read(&dir.join("out_manifest.json")).unwrap();
+            manifest.segments.iter().flat_map(|s| s.topics.clone()).collect()
+        };
+        assert_eq!(convert(&[]), BTreeMap::new());
+        assert_eq!(convert(&["--ignore-md5-mismatch"]), BTreeMap::from([("/fix".to_string(), 1)]));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_strict_fails_on_unparsable_messages() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_strict_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let connections = [TestConnection { id: 0, topic: "/imu", tp: "sensor_msgs/Imu", latching: false }];
+        // Identity orientation, everything else zero; the last message is cut short
+        let mut imu = vec![0u8; 16 + 46 * 8];
+        imu[16 + 24..16 + 32].copy_from_slice(&1.0f64.to_le_bytes());
+        let mut messages: Vec<TestMessage> = (0..3).map(|i| TestMessage::new(0, i as f64 * 0.1, imu.clone())).collect();
+        messages.push(TestMessage::new(0, 0.3, imu[..40].to_vec()));
+        write_bag(&bag, &connections, &[messages]);
+
+        let convert = |extra: &[&str]| -> Result<()> {
+            let out = dir.join("out.rrd");
+            let mut args = vec![bag.to_str().unwrap(), out.to_str().unwrap()];
+            args.extend_from_slice(extra);
+            run_convert(&args)
+        };
+        // Skipped with a warning by default
+        convert(&[]).unwrap();
+        let err = convert(&["--strict"]).unwrap_err();
+        assert!(err.to_string().contains("--strict: failed to map a sensor_msgs/Imu message on /imu"), "{err:#}");
+        // 1 of 4 is within 30%, not within 10%
+        convert(&["--strict", "--max-failure-rate", "imu=0.3"]).unwrap();
+        let err = convert(&["--max-failure-rate", "0.1"]).unwrap_err();
+        assert!(err.to_string().contains("imu: 1 of 4 (25.0% > 10.0%)"), "{err:#}");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rate_limited_messages_are_not_drops() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_rate_drops_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let connections = [TestConnection { id: 0, topic: "/imu", tp: "sensor_msgs/Imu", latching: false }];
+        let mut imu = vec![0u8; 16 + 46 * 8];
+        imu[16 + 24..16 + 32].copy_from_slice(&1.0f64.to_le_bytes());
+        // 10 Hz, seq 5 never recorded
+        let messages = (0..10u32)
+            .filter(|seq| *seq != 5)
+            .map(|seq| {
+                let mut payload = imu.clone();
+                payload[..4].copy_from_slice(&seq.to_le_bytes());
+                TestMessage::new(0, seq as f64 * 0.1, payload)
+            })
+            .collect();
+        write_bag(&bag, &connections, &[messages]);
+        let (out, report) = (dir.join("out.rrd"), dir.join("report.json"));
+        let args = [bag.to_str().unwrap(), out.to_str().unwrap(), "--max-rate", "/imu=2", "--log-drops", "--report", report.to_str().unwrap()];
+        run_convert(&args).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report).unwrap()).unwrap();
+        // Only the missing message, not the ones --max-rate thinned out
+        assert_eq!(report["drops"][0]["dropped"], 1, "{report:#}");
+        assert_eq!(report["drops"][0]["messages"], 9);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_output_errors_stop_the_conversion() {
+        use crate::mappings::registry::MessageMapper;
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        /// Fails like a full disk under an export file
+        struct FullDisk;
+
+        impl MessageMapper for FullDisk {
+            fn map(&mut self, _ctx: &mut MapperContext<'_>, _payload: &[u8]) -> Result<Mapped> {
+                Err(std::io::Error::from(std::io::ErrorKind::StorageFull)).context("failed to write /tmp/status.csv")
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_output_error_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let connections = [TestConnection { id: 0, topic: "/status", tp: "std_msgs/String", latching: false }];
+        let messages = vec![TestMessage::new(0, 0.0, [2u32.to_le_bytes().as_slice(), b"ok"].concat())];
+        write_bag(&bag, &connections, &[messages]);
+        let options = convert_options(&[bag.to_str().unwrap(), dir.join("out.rrd").to_str().unwrap()]).unwrap();
+        let mut mappers = MapperRegistry::builtin(&options);
+        mappers.register_topic("/status", Box::new(FullDisk));
+        // Not skipped like a message that does not parse, even without --strict
+        let err = convert_bag_with(&options, mappers).unwrap_err();
+        assert!(format!("{err:#}").contains("failed to log std_msgs/String on /status: failed to write /tmp/status.csv"), "{err:#}");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_failed_messages_fill_their_segment() {
+        use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+        let dir = std::env::temp_dir().join(format!("bag2rrd_failed_fill_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("in.bag");
+        let connections = [TestConnection { id: 0, topic: "/imu", tp: "sensor_msgs/Imu", latching: false }];
+        // Every other message is cut short and skipped
+        let mut imu = vec![0u8; 16 + 46 * 8];
+        imu[16 + 24..16 + 32].copy_from_slice(&1.0f64.to_le_bytes());
+        let messages = (0..6)
+            .map(|i| TestMessage::new(0, i as f64 * 0.1, if i % 2 == 0 { imu.clone() } else { imu[..40].to_vec() }))
+            .collect();
+        write_bag(&bag, &connections, &[messages]);
+
+        let out = dir.join("out.rrd");
+        let args = [bag.to_str().unwrap(), out.to_str().unwrap(), "--segment-size", "3"];
+        run_convert(&args).unwrap();
+        let manifest = SegmentManifest::read(&dir.join("out_manifest.json")).unwrap();
+        let parts: Vec<(u32, u64, f64, f64)> = manifest.segments.iter().map(|s| (s.part, s.messages, s.start_time, s.end_time)).collect();
+        assert_eq!(parts, [(1, 3, 0.0, 0.2), (2, 3, 0.3, 0.5)]);
+        std::fs::remove_dir_all(&dir).ok();
     }
 }
@@ -0,0 +1,689 @@
+//! diagnose command - Deep scan of a bag's records, with recovery suggestions and reindexing
+//!
+//! Chunks are located by stepping over record lengths rather than through the
+//! index, then decompressed and walked record by record. Bag 2.0 chunks carry
+//! no checksum of their own; the bz2 and lz4 streams do, so a CRC mismatch shows
+//! up as a decompression error. `--repair` writes a copy holding every readable
+//! record with a rebuilt index section, like `rosbag reindex` does for the bags
+//! of a crashed recorder.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::rosbags_io::{header_field, record_at, record_parts, BagLayout, ChunkScan, CHUNK_OP, INDEX_DATA_OP, VERSION_LINE};
+use crate::source::bag_writer::{bag_header, field, record, time_value};
+
+const MESSAGE_OP: u8 = 0x02;
+const CHUNK_INFO_OP: u8 = 0x06;
+const CONNECTION_OP: u8 = 0x07;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexStatus {
+    /// The index section lists every chunk
+    Complete,
+    /// The index section lists fewer chunks than the file holds, or ends in a damaged record
+    Partial,
+    /// The header points at no index section (interrupted recording or truncated file)
+    Missing,
+}
+
+/// One chunk record, decompressed and walked
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct ChunkCheck {
+    /// File offset of the chunk record
+    pub pos: u64,
+    pub compression: String,
+    /// Bytes of chunk data stored in the file
+    pub stored_bytes: u64,
+    /// Uncompressed size declared by the chunk header
+    pub size: u64,
+    pub messages: u64,
+    /// Record time of the first and last readable message, in nanoseconds
+    pub start_ns: Option<u64>,
+    pub end_ns: Option<u64>,
+    /// Decompression failure, size mismatch or damaged record; `None` for an intact chunk
+    pub issue: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiagnoseReport {
+    pub path: String,
+    pub size_bytes: u64,
+    pub index: IndexStatus,
+    /// Chunks listed by the index section
+    pub indexed_chunks: u64,
+    pub chunks: Vec<ChunkCheck>,
+    /// Damaged stretches between chunk records and the bytes they span
+    pub damaged_regions: usize,
+    pub skipped_bytes: u64,
+    /// End of the last intact chunk record
+    pub last_valid_offset: u64,
+    /// Connection id → topic, from the connection records of the chunks
+    pub connections: BTreeMap<u32, String>,
+    /// Readable messages
+    pub messages: u64,
+}
+
+impl DiagnoseReport {
+    /// Chunks with a decompression, size or record issue
+    pub fn damaged_chunks(&self) -> impl Iterator<Item = &ChunkCheck> {
+        self.chunks.iter().filter(|c| c.issue.is_some())
+    }
+
+    pub fn healthy(&self) -> bool {
+        self.index == IndexStatus::Complete && self.damaged_regions == 0 && self.damaged_chunks().next().is_none()
+    }
+
+    /// What to do about the issues found
+    pub fn suggestions(&self) -> Vec<String> {
+        let mut suggestions = Vec::new();
+        if self.healthy() {
+            return suggestions;
+        }
+        if self.index != IndexStatus::Complete {
+            suggestions.push(format!(
+                "Rebuild the index: bag2rrd diagnose {} --repair fixed.bag (or rosbag reindex)",
+                self.path
+            ));
+        }
+        let damaged = self.damaged_chunks().count();
+        if damaged > 0 || self.damaged_regions > 0 {
+            suggestions.push(format!(
+                "{} damaged chunks and {} damaged regions: --repair keeps their readable records, \
+                 and bag2rrd convert --tolerate-corruption converts around them",
+                damaged, self.damaged_regions
+            ));
+        }
+        if self.index == IndexStatus::Missing && self.last_valid_offset < self.size_bytes {
+            suggestions.push(format!(
+                "The last {} bytes hold no whole chunk (truncated recording); their messages are lost",
+                self.size_bytes - self.last_valid_offset
+            ));
+        }
+        suggestions
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = format!("Bag: {} ({} bytes)\n", self.path, self.size_bytes);
+        out.push_str(&match self.index {
+            IndexStatus::Complete => format!("Index: complete, {} chunks\n", self.indexed_chunks),
+            IndexStatus::Partial => {
+                format!("Index: PARTIAL, lists {} of {} chunks\n", self.indexed_chunks, self.chunks.len())
+            }
+            IndexStatus::Missing => "Index: MISSING\n".to_string(),
+        });
+        let mut compressions: BTreeMap<&str, usize> = BTreeMap::new();
+        for chunk in &self.chunks {
+            *compressions.entry(chunk.compression.as_str()).or_insert(0) += 1;
+        }
+        let compressions: Vec<String> = compressions.iter().map(|(c, n)| format!("{} {}", n, c)).collect();
+        out.push_str(&format!(
+            "Chunks: {} ({}), {} messages on {} connections\n",
+            self.chunks.len(),
+            compressions.join(", "),
+            self.messages,
+            self.connections.len()
+        ));
+        out.push_str(&format!(
+            "Damaged regions: {} ({} bytes skipped)\n",
+            self.damaged_regions, self.skipped_bytes
+        ));
+        out.push_str(&format!("Last valid chunk ends at byte {}\n", self.last_valid_offset));
+
+        let damaged: Vec<&ChunkCheck> = self.damaged_chunks().collect();
+        if !damaged.is_empty() {
+            out.push_str("\nDamaged chunks:\n");
+            for chunk in damaged {
+                out.push_str(&format!(
+                    "  @{} ({}, {} readable messages): {}\n",
+                    chunk.pos,
+                    chunk.compression,
+                    chunk.messages,
+                    chunk.issue.as_deref().unwrap_or_default()
+                ));
+            }
+        }
+        if self.healthy() {
+            out.push_str("\nBag file structure appears intact\n");
+        } else {
+            out.push_str("\nSuggestions:\n");
+            for suggestion in self.suggestions() {
+                out.push_str(&format!("  - {}\n", suggestion));
+            }
+        }
+        out
+    }
+}
+
+/// Chunk data after decompression, with its readable records
+struct ChunkData {
+    check: ChunkCheck,
+    /// Stored record, header included
+    record: std::ops::Range<usize>,
+    data: Vec<u8>,
+    /// End of the last whole record in `data`
+    valid_len: usize,
+    /// (connection id, record time, record bytes in `data`) of each message
+    entries: Vec<(u32, u64, std::ops::Range<usize>)>,
+    /// Connection records: (id, topic, record bytes)
+    connections: Vec<(u32, String, std::ops::Range<usize>)>,
+}
+
+fn u32_field(header: &[u8], name: &str) -> Option<u32> {
+    header_field(header, name).and_then(|v| Some(u32::from_le_bytes(v.try_into().ok()?)))
+}
+
+/// Nanoseconds of a `time` field (seconds then nanoseconds, both u32)
+fn time_field(header: &[u8], name: &str) -> Option<u64> {
+    let value = header_field(header, name).filter(|v| v.len() == 8)?;
+    let secs = u32::from_le_bytes(value[..4].try_into().unwrap()) as u64;
+    let nsecs = u32::from_le_bytes(value[4..].try_into().unwrap()) as u64;
+    Some(secs * 1_000_000_000 + nsecs)
+}
+
+fn decompress(compression: &str, stored: &[u8]) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    match compression {
+        "none" => data.extend_from_slice(stored),
+        "bz2" => {
+            bzip2::read::BzDecoder::new(stored).read_to_end(&mut data)?;
+        }
+        "lz4" => {
+            lz4::Decoder::new(stored)?.read_to_end(&mut data)?;
+        }
+        other => bail!("unknown compression '{}'", other),
+    }
+    Ok(data)
+}
+
+/// Decompress the chunk record at `pos` and walk its records
+fn read_chunk(bytes: &[u8], pos: usize, end: usize) -> Option<ChunkData> {
+    let (header, data_len) = record_parts(bytes, pos, end)?;
+    let data_start = pos + 8 + header.len();
+    let compression = header_field(header, "compression")
+        .map(|v| String::from_utf8_lossy(v).into_owned())
+        .unwrap_or_else(|| "unknown".to_string());
+    let size = u32_field(header, "size").unwrap_or(0) as usize;
+    let mut chunk = ChunkData {
+        check: ChunkCheck {
+            pos: pos as u64,
+            compression,
+            stored_bytes: data_len as u64,
+            size: size as u64,
+            messages: 0,
+            start_ns: None,
+            end_ns: None,
+            issue: None,
+        },
+        record: pos..data_start + data_len,
+        data: Vec::new(),
+        valid_len: 0,
+        entries: Vec::new(),
+        connections: Vec::new(),
+    };
+    match decompress(&chunk.check.compression, &bytes[data_start..data_start + data_len]) {
+        Ok(data) => chunk.data = data,
+        Err(e) => {
+            chunk.check.issue = Some(format!("{} decompression failed: {:#}", chunk.check.compression, e));
+            return Some(chunk);
+        }
+    }
+    let data = &chunk.data;
+    if data.len() != size {
+        chunk.check.issue = Some(format!("holds {} bytes, the chunk header declares {}", data.len(), size));
+    }
+
+    let mut offset = 0;
+    while offset < data.len() {
+        let record = record_at(data, offset, data.len()).zip(record_parts(data, offset, data.len()));
+        match record {
+            Some(((MESSAGE_OP, next), (header, _))) => {
+                let (Some(conn), Some(time)) = (u32_field(header, "conn"), time_field(header, "time")) else {
+                    break;
+                };
+                chunk.entries.push((conn, time, offset..next));
+                offset = next;
+            }
+            Some(((CONNECTION_OP, next), (header, _))) => {
+                let Some(conn) = u32_field(header, "conn") else {
+                    break;
+                };
+                let topic = header_field(header, "topic").map(|t| String::from_utf8_lossy(t).into_owned());
+                chunk.connections.push((conn, topic.unwrap_or_default(), offset..next));
+                offset = next;
+            }
+            _ => break,
+        }
+    }
+    chunk.valid_len = offset;
+    if offset < data.len() && chunk.check.issue.is_none() {
+        chunk.check.issue = Some(format!(
+            "damaged record at byte {} of {}; the {} messages before it are readable",
+            offset,
+            data.len(),
+            chunk.entries.len()
+        ));
+    }
+    chunk.check.messages = chunk.entries.len() as u64;
+    chunk.check.start_ns = chunk.entries.iter().map(|e| e.1).min();
+    chunk.check.end_ns = chunk.entries.iter().map(|e| e.1).max();
+    Some(chunk)
+}
+
+/// The mapped bag file, its layout and every chunk record
+struct ScannedBag {
+    bytes: memmap2::Mmap,
+    layout: BagLayout,
+    scan: ChunkScan,
+}
+
+impl ScannedBag {
+    fn open(path: &str) -> Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("failed to open bag: {}", path))?;
+        // SAFETY: see `BagLayout::read`
+        let bytes = unsafe { memmap2::Mmap::map(&file) }.with_context(|| format!("failed to map bag: {}", path))?;
+        let layout = BagLayout::parse(&bytes).with_context(|| format!("invalid bag header: {}", path))?;
+        let scan = ChunkScan::scan(&bytes, layout.start_pos as usize, chunks_end(&layout));
+        Ok(Self { bytes, layout, scan })
+    }
+
+    fn chunks(&self) -> impl Iterator<Item = ChunkData> + '_ {
+        let end = chunks_end(&self.layout);
+        self.scan.positions.iter().filter_map(move |pos| read_chunk(&self.bytes, *pos as usize, end))
+    }
+
+    /// ChunkInfo records of the index section, and whether it ends in a damaged record
+    fn index_chunk_infos(&self) -> (u64, bool) {
+        let (mut infos, mut pos) = (0, self.layout.index_pos as usize);
+        let end = self.bytes.len();
+        while pos < end {
+            match record_at(&self.bytes, pos, end) {
+                Some((op, next)) => {
+                    infos += (op == CHUNK_INFO_OP) as u64;
+                    pos = next;
+                }
+                None => return (infos, true),
+            }
+        }
+        (infos, false)
+    }
+
+    /// Connection records of the index section up to any damage, by connection id
+    fn index_connections(&self) -> BTreeMap<u32, Vec<u8>> {
+        let mut connections = BTreeMap::new();
+        if !self.layout.has_index() {
+            return connections;
+        }
+        let (mut pos, end) = (self.layout.index_pos as usize, self.bytes.len());
+        while let Some((op, next)) = record_at(&self.bytes, pos, end) {
+            if op == CONNECTION_OP
+                && let Some(conn) = record_parts(&self.bytes, pos, end).and_then(|(header, _)| u32_field(header, "conn"))
+            {
+                connections.entry(conn).or_insert_with(|| self.bytes[pos..next].to_vec());
+            }
+            pos = next;
+        }
+        connections
+    }
+}
+
+/// End of the chunk section
+fn chunks_end(layout: &BagLayout) -> usize {
+    (if layout.has_index() { layout.index_pos } else { layout.file_len }) as usize
+}
+
+/// Scan every record of the bag at `path`
+pub fn diagnose_bag(path: &str) -> Result<DiagnoseReport> {
+    let bag = ScannedBag::open(path)?;
+    let mut report = DiagnoseReport {
+        path: path.to_string(),
+        size_bytes: bag.layout.file_len,
+        index: IndexStatus::Missing,
+        indexed_chunks: 0,
+        chunks: Vec::new(),
+        damaged_regions: bag.scan.damaged_regions,
+        skipped_bytes: bag.scan.skipped_bytes,
+        last_valid_offset: bag.layout.start_pos,
+        connections: BTreeMap::new(),
+        messages: 0,
+    };
+    for chunk in bag.chunks() {
+        if chunk.check.issue.is_none() {
+            report.last_valid_offset = chunk.record.end as u64;
+        }
+        for (conn, topic, _) in chunk.connections {
+            report.connections.entry(conn).or_insert(topic);
+        }
+        report.messages += chunk.check.messages;
+        report.chunks.push(chunk.check);
+    }
+    if bag.layout.has_index() {
+        let (infos, damaged) = bag.index_chunk_infos();
+        report.indexed_chunks = infos;
+        report.index = if damaged || (infos as usize) < report.chunks.len() {
+            IndexStatus::Partial
+        } else {
+            IndexStatus::Complete
+        };
+    }
+    Ok(report)
+}
+
+/// What `--repair` wrote
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RepairSummary {
+    /// Chunks written, copied as stored
+    pub chunks: usize,
+    /// Damaged chunks written uncompressed with their readable records
+    pub salvaged_chunks: usize,
+    /// Chunks without a readable message
+    pub dropped_chunks: usize,
+    pub connections: usize,
+    pub messages: u64,
+    /// Messages left out as their connection record was lost with a dropped chunk
+    pub orphaned_messages: u64,
+}
+
+/// Write every readable record of the bag at `path` to `out` with a rebuilt index
+/// section. Intact chunks are copied as stored; a damaged chunk keeps the records
+/// before its damage, uncompressed
+pub fn repair_bag(path: &str, out: &str) -> Result<RepairSummary> {
+    if std::fs::canonicalize(path).ok() == std::fs::canonicalize(out).ok() {
+        bail!("--repair must write to another file than the bag");
+    }
+    let bag = ScannedBag::open(path)?;
+    let file = std::fs::File::create(out).with_context(|| format!("failed to create {}", out))?;
+    let mut writer = std::io::BufWriter::new(file);
+    let header = bag_header(0, 0, 0);
+    writer.write_all(VERSION_LINE)?;
+    writer.write_all(&header)?;
+    let mut pos = (VERSION_LINE.len() + header.len()) as u64;
+
+    let mut summary = RepairSummary::default();
+    // Connection id → record bytes: those of the index section, then the first
+    // record in a chunk, so messages whose chunk lost it keep their connection
+    let mut connections = bag.index_connections();
+    let mut chunk_infos = Vec::new();
+    for chunk in bag.chunks() {
+        for (conn, _, range) in &chunk.connections {
+            connections.entry(*conn).or_insert_with(|| chunk.data[range.clone()].to_vec());
+        }
+        let orphans = chunk.entries.iter().filter(|(conn, ..)| !connections.contains_key(conn)).count();
+        summary.orphaned_messages += orphans as u64;
+        if chunk.entries.len() == orphans {
+            summary.dropped_chunks += 1;
+            continue;
+        }
+        let chunk_pos = pos;
+        // (connection id, record time, offset in the written chunk) of each message
+        let entries: Vec<(u32, u64, u32)> = if chunk.check.issue.is_none() && orphans == 0 {
+            writer.write_all(&bag.bytes[chunk.record.clone()])?;
+            pos += chunk.record.len() as u64;
+            summary.chunks += 1;
+            chunk.entries.iter().map(|(conn, time, range)| (*conn, *time, range.start as u32)).collect()
+        } else {
+            // The readable records, uncompressed, without the orphaned messages
+            let mut records: Vec<_> = chunk.connections.iter().map(|(_, _, range)| (range.clone(), None)).collect();
+            records.extend(
+                chunk
+                    .entries
+                    .iter()
+                    .filter(|(conn, ..)| connections.contains_key(conn))
+                    .map(|(conn, time, range)| (range.clone(), Some((*conn, *time)))),
+            );
+            records.sort_by_key(|(range, _)| range.start);
+            let (mut data, mut entries) = (Vec::new(), Vec::new());
+            for (range, message) in records {
+                if let Some((conn, time)) = message {
+                    entries.push((conn, time, data.len() as u32));
+                }
+                data.extend_from_slice(&chunk.data[range]);
+            }
+            let header = [
+                field("op", &[CHUNK_OP]),
+                field("compression", b"none"),
+                field("size", &(data.len() as u32).to_le_bytes()),
+            ];
+            let bytes = record(&header, &data);
+            writer.write_all(&bytes)?;
+            pos += bytes.len() as u64;
+            summary.salvaged_chunks += 1;
+            entries
+        };
+        summary.messages += entries.len() as u64;
+
+        // One IndexData record per connection, listing its messages in the chunk
+        let mut per_connection: BTreeMap<u32, Vec<u8>> = BTreeMap::new();
+        for (conn, time, offset) in &entries {
+            let entries = per_connection.entry(*conn).or_default();
+            entries.extend(time_value(*time));
+            entries.extend(offset.to_le_bytes());
+        }
+        for (conn, entries) in &per_connection {
+            let header = [
+                field("op", &[INDEX_DATA_OP]),
+                field("ver", &1u32.to_le_bytes()),
+                field("conn", &conn.to_le_bytes()),
+                field("count", &((entries.len() / 12) as u32).to_le_bytes()),
+            ];
+            let bytes = record(&header, entries);
+            writer.write_all(&bytes)?;
+            pos += bytes.len() as u64;
+        }
+        let counts: Vec<u8> = per_connection
+            .iter()
+            .flat_map(|(conn, entries)| [conn.to_le_bytes(), ((entries.len() / 12) as u32).to_le_bytes()].concat())
+            .collect();
+        let start = entries.iter().map(|e| e.1).min().unwrap_or(0);
+        let end = entries.iter().map(|e| e.1).max().unwrap_or(0);
+        let header = [
+            field("op", &[CHUNK_INFO_OP]),
+            field("ver", &1u32.to_le_bytes()),
+            field("chunk_pos", &chunk_pos.to_le_bytes()),
+            field("start_time", &time_value(start)),
+            field("end_time", &time_value(end)),
+            field("count", &(per_connection.len() as u32).to_le_bytes()),
+        ];
+        chunk_infos.push(record(&header, &counts));
+    }
+
+    let index_pos = pos;
+    for record in connections.values() {
+        writer.write_all(record)?;
+    }
+    for info in &chunk_infos {
+        writer.write_all(info)?;
+    }
+    // The header now points at the index section
+    writer.seek(SeekFrom::Start(VERSION_LINE.len() as u64))?;
+    writer.write_all(&bag_header(index_pos, connections.len(), chunk_infos.len()))?;
+    writer.flush()?;
+    summary.connections = connections.len();
+    Ok(summary)
+}
+
+/// Print the diagnosis of the bag at `path`, and write a repaired copy to `repair`
+pub fn print_diagnose(path: &str, repair: Option<&str>) -> Result<()> {
+    let report = diagnose_bag(path)?;
+    print!("{}", report.to_text());
+    if let Some(out) = repair {
+        let summary = repair_bag(path, out)?;
+        println!(
+            "\nWrote {}: {} chunks ({} salvaged, {} dropped), {} messages on {} connections",
+            out,
+            summary.chunks + summary.salvaged_chunks,
+            summary.salvaged_chunks,
+            summary.dropped_chunks,
+            summary.messages,
+            summary.connections
+        );
+        if summary.orphaned_messages > 0 {
+            println!("{} messages left out: their connection record was in a dropped chunk", summary.orphaned_messages);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rosbags_io::BagIndex;
+    use crate::test_bag::{write_bag, write_bag_compressed, TestCompression, TestConnection, TestMessage};
+    use rosbag::{ChunkRecord, MessageRecord, RosBag};
+
+    fn payloads(path: &std::path::Path) -> Vec<Vec<u8>> {
+        let bag = RosBag::new(path).unwrap();
+        bag.chunk_records()
+            .flat_map(|record| match record.unwrap() {
+                ChunkRecord::Chunk(chunk) => chunk
+                    .messages()
+                    .filter_map(|m| match m.unwrap() {
+                        MessageRecord::MessageData(data) => Some(data.data.to_vec()),
+                        MessageRecord::Connection(_) => None,
+                    })
+                    .collect(),
+                ChunkRecord::IndexData(_) => vec![],
+            })
+            .collect()
+    }
+
+    fn connections() -> [TestConnection; 2] {
+        [
+            TestConnection { id: 0, topic: "/imu", tp: "sensor_msgs/Imu", latching: false },
+            TestConnection { id: 1, topic: "/gps/fix", tp: "sensor_msgs/NavSatFix", latching: false },
+        ]
+    }
+
+    #[test]
+    fn test_truncated_bag_is_reindexed() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_diagnose_truncated_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("crashed.bag");
+        let chunks: Vec<Vec<TestMessage>> = (0..3)
+            .map(|i| vec![TestMessage::new(0, i as f64, vec![i; 100]), TestMessage::new(1, i as f64 + 0.5, vec![i; 10])])
+            .collect();
+        write_bag_compressed(&path, &connections(), &chunks, TestCompression::Lz4);
+        let p = path.to_str().unwrap();
+        let intact = diagnose_bag(p).unwrap();
+        assert!(intact.healthy(), "{}", intact.to_text());
+        assert_eq!(intact.indexed_chunks, 3);
+        assert_eq!(intact.messages, 6);
+
+        // The recorder died inside the third chunk: no index section
+        let third = intact.chunks[2].pos as usize;
+        let data = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &data[..third + 20]).unwrap();
+        let report = diagnose_bag(p).unwrap();
+        assert_eq!(report.index, IndexStatus::Missing);
+        assert_eq!(report.chunks.len(), 2);
+        assert_eq!(report.messages, 4);
+        assert_eq!(report.last_valid_offset, third as u64);
+        assert_eq!((report.damaged_regions, report.skipped_bytes), (1, 20));
+        assert_eq!(report.connections[&1], "/gps/fix");
+        assert!(!report.healthy());
+        assert!(report.suggestions()[0].contains("--repair"));
+
+        let fixed = dir.join("fixed.bag");
+        let summary = repair_bag(p, fixed.to_str().unwrap()).unwrap();
+        assert_eq!(
+            summary,
+            RepairSummary { chunks: 2, salvaged_chunks: 0, dropped_chunks: 0, connections: 2, messages: 4, orphaned_messages: 0 }
+        );
+        let repaired = diagnose_bag(fixed.to_str().unwrap()).unwrap();
+        assert!(repaired.healthy(), "{}", repaired.to_text());
+        let index = BagIndex::read(&RosBag::new(&fixed).unwrap()).unwrap().unwrap();
+        assert_eq!(index.chunks.len(), 2);
+        assert_eq!(index.connections[&0], ("/imu".to_string(), "sensor_msgs/Imu".to_string()));
+        assert_eq!(index.chunks[1].start_ns, 1_000_000_000);
+        let messages = payloads(&fixed);
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(messages, [vec![0; 100], vec![0; 10], vec![1; 100], vec![1; 10]]);
+    }
+
+    #[test]
+    fn test_damaged_record_is_salvaged() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_diagnose_damaged_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("damaged.bag");
+        let chunks = vec![
+            vec![TestMessage::new(0, 1.0, vec![1; 40])],
+            vec![TestMessage::new(0, 2.0, vec![2; 40]), TestMessage::new(0, 2.5, vec![3; 40])],
+        ];
+        write_bag(&path, &connections(), &chunks);
+        let p = path.to_str().unwrap();
+
+        // Garbage header length on the last message of the second chunk
+        let mut data = std::fs::read(&path).unwrap();
+        let second = diagnose_bag(p).unwrap().chunks[1].pos as usize;
+        let last = second + data[second..].windows(40).position(|w| w == [3; 40]).unwrap();
+        // Header length, 38 bytes of op, conn and time fields, then the data length
+        let header_len_at = last - 4 - 38 - 4;
+        assert_eq!(data[header_len_at..header_len_at + 4], 38u32.to_le_bytes());
+        data[header_len_at..header_len_at + 4].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+
+        let report = diagnose_bag(p).unwrap();
+        assert_eq!(report.index, IndexStatus::Complete);
+        let damaged: Vec<&ChunkCheck> = report.damaged_chunks().collect();
+        assert_eq!(damaged.len(), 1);
+        assert_eq!(damaged[0].messages, 1);
+        assert!(damaged[0].issue.as_deref().unwrap().contains("damaged record"));
+
+        let fixed = dir.join("fixed.bag");
+        let summary = repair_bag(p, fixed.to_str().unwrap()).unwrap();
+        assert_eq!((summary.chunks, summary.salvaged_chunks, summary.messages), (1, 1, 2));
+        let messages = payloads(&fixed);
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(messages, [vec![1; 40], vec![2; 40]]);
+    }
+
+    #[test]
+    fn test_connection_of_dropped_chunk() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_diagnose_orphans_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let conn = record(
+            &[field("op", &[CONNECTION_OP]), field("conn", &0u32.to_le_bytes()), field("topic", b"/imu")],
+            &[field("topic", b"/imu"), field("type", b"sensor_msgs/Imu"), field("md5sum", &[b'0'; 32]), field("message_definition", b"")]
+                .concat(),
+        );
+        let message = record(
+            &[field("op", &[MESSAGE_OP]), field("conn", &0u32.to_le_bytes()), field("time", &time_value(2_000_000_000))],
+            &[7; 16],
+        );
+        // The first chunk held the connection record and no longer decompresses;
+        // the second has a message of that connection only
+        let first = record(&[field("op", &[CHUNK_OP]), field("compression", b"bz2"), field("size", &64u32.to_le_bytes())], &[0xff; 32]);
+        let second = record(
+            &[field("op", &[CHUNK_OP]), field("compression", b"none"), field("size", &(message.len() as u32).to_le_bytes())],
+            &message,
+        );
+        let index_pos = (VERSION_LINE.len() + bag_header(0, 0, 0).len() + first.len() + second.len()) as u64;
+
+        // Without an index the message has no connection left
+        let write = |name: &str, index: Option<&[u8]>| {
+            let mut bytes = VERSION_LINE.to_vec();
+            bytes.extend(bag_header(if index.is_some() { index_pos } else { 0 }, index.iter().count(), 0));
+            bytes.extend_from_slice(&first);
+            bytes.extend_from_slice(&second);
+            bytes.extend_from_slice(index.unwrap_or_default());
+            let path = dir.join(name);
+            std::fs::write(&path, bytes).unwrap();
+            let fixed = dir.join(format!("fixed_{}", name));
+            (repair_bag(path.to_str().unwrap(), fixed.to_str().unwrap()).unwrap(), fixed)
+        };
+        let (summary, _) = write("unindexed.bag", None);
+        assert_eq!((summary.messages, summary.orphaned_messages, summary.dropped_chunks), (0, 1, 2));
+
+        // The index section still lists it
+        let (summary, fixed) = write("indexed.bag", Some(&conn));
+        assert_eq!((summary.messages, summary.orphaned_messages, summary.connections), (1, 0, 1));
+        let index = BagIndex::read(&RosBag::new(&fixed).unwrap()).unwrap().unwrap();
+        assert_eq!(index.connections[&0], ("/imu".to_string(), "sensor_msgs/Imu".to_string()));
+        let messages = payloads(&fixed);
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(messages, [vec![7; 16]]);
+    }
+}
@@ -0,0 +1,207 @@
+//! Messages lost between publisher and bag, from gaps in header.seq
+//!
+//! roscpp and rospy number the messages of a publisher in header.seq. When the
+//! sequence of a connection jumps by more than one, the messages in between
+//! were published but never recorded (full queues, a saturated network, a
+//! recorder too slow to keep up). A sequence going backwards is a publisher
+//! restart rather than a gap, and publishers that leave seq at zero report
+//! nothing. Connections share an id across bags and publishers of the same
+//! topic, so a topic with several live publishers shows resets and gaps that
+//! are only the sequences interleaving. Every type whose definition starts with
+//! a std_msgs/Header is checked, not only the mapped ones.
+//!
+//! With --log-drops each gap is also logged as a TextLog under
+//! [`DROPS_ENTITY`] at the time of the message after it, so drops show up
+//! while scrubbing the recording.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::convert::{header_seq, starts_with_header};
+use crate::ros_msg::TypeInfo;
+
+/// Entity of the --log-drops events
+pub const DROPS_ENTITY: &str = "/diagnostics/drops";
+
+/// Sequence gaps of one topic
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TopicDrops {
+    pub topic: String,
+    pub tp: String,
+    /// Messages with a header.seq
+    pub messages: u64,
+    /// Jumps of header.seq by more than one
+    pub gaps: u64,
+    /// Messages missing in the gaps
+    pub dropped: u64,
+    /// Messages missing in the largest gap
+    pub max_gap: u64,
+    /// Times header.seq went backwards, as when a publisher restarts
+    pub resets: u64,
+}
+
+impl TopicDrops {
+    /// Share of the published messages that were not recorded
+    pub fn drop_rate(&self) -> f64 {
+        match self.messages + self.dropped {
+            0 => 0.0,
+            published => self.dropped as f64 / published as f64,
+        }
+    }
+}
+
+/// Messages missing between two consecutive messages of a connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeqGap {
+    /// header.seq the message should have had
+    pub expected: u32,
+    pub seq: u32,
+}
+
+impl SeqGap {
+    pub fn dropped(&self) -> u64 {
+        (self.seq - self.expected) as u64
+    }
+}
+
+/// Last header.seq of every connection, and the gaps of every topic
+#[derive(Debug, Default)]
+pub struct SeqTracker {
+    /// Whether the messages of each connection start with a header
+    headers: HashMap<u32, bool>,
+    last: HashMap<u32, u32>,
+    topics: BTreeMap<String, TopicDrops>,
+}
+
+impl SeqTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check the header.seq of a message of connection `conn_id`; the gap before it, if any.
+    /// Whether the type has a header comes from the connection's definition, else from
+    /// the mapped types
+    pub fn record(&mut self, conn_id: u32, topic: &str, tp: &str, type_info: Option<&TypeInfo>, payload: &[u8]) -> Option<SeqGap> {
+        let has_header = *self
+            .headers
+            .entry(conn_id)
+            .or_insert_with(|| type_info.and_then(TypeInfo::starts_with_header).unwrap_or_else(|| starts_with_header(tp)));
+        if !has_header {
+            return None;
+        }
+        let seq = header_seq(payload)?;
+        let entry = self.topics.entry(topic.to_string()).or_insert_with(|| TopicDrops {
+            topic: topic.to_string(),
+            tp: tp.to_string(),
+            ..Default::default()
+        });
+        entry.messages += 1;
+        let last = self.last.insert(conn_id, seq)?;
+        if seq < last {
+            entry.resets += 1;
+        }
+        if seq <= last.saturating_add(1) {
+            return None;
+        }
+        let gap = SeqGap { expected: last + 1, seq };
+        entry.gaps += 1;
+        entry.dropped += gap.dropped();
+        entry.max_gap = entry.max_gap.max(gap.dropped());
+        Some(gap)
+    }
+
+    /// Topics with gaps or restarts, sorted by name
+    pub fn report(&self) -> Vec<TopicDrops> {
+        self.topics.values().filter(|t| t.gaps > 0 || t.resets > 0).cloned().collect()
+    }
+}
+
+/// Log `gap` of `topic` under [`DROPS_ENTITY`], at the current time of `rec`
+pub fn log_gap(rec: &rerun::RecordingStream, topic: &str, gap: &SeqGap) -> Result<()> {
+    let text = format!(
+        "{}: {} message{} dropped (seq {} → {})",
+        topic,
+        gap.dropped(),
+        if gap.dropped() == 1 { "" } else { "s" },
+        gap.expected - 1,
+        gap.seq
+    );
+    rec.log(
+        DROPS_ENTITY,
+        &rerun::archetypes::TextLog::new(text).with_level(rerun::components::TextLogLevel::WARN),
+    )?;
+    Ok(())
+}
+
+/// Table of the topics with sequence gaps
+pub fn drops_to_text(drops: &[TopicDrops]) -> String {
+    let mut out = format!(
+        "\nSequence gaps: {} messages dropped on {} topics\n",
+        drops.iter().map(|t| t.dropped).sum::<u64>(),
+        drops.len()
+    );
+    out.push_str(&format!(
+        "{:<35} {:>9} {:>6} {:>9} {:>7} {:>8} {:>7}\n",
+        "Topic", "Messages", "Gaps", "Dropped", "Drop%", "Max gap", "Resets"
+    ));
+    out.push_str(&format!("{}\n", "-".repeat(86)));
+    for t in drops {
+        out.push_str(&format!(
+            "{:<35} {:>9} {:>6} {:>9} {:>7.1} {:>8} {:>7}\n",
+            t.topic,
+            t.messages,
+            t.gaps,
+            t.dropped,
+            t.drop_rate() * 100.0,
+            t.max_gap,
+            t.resets
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn imu(seq: u32) -> Vec<u8> {
+        let mut payload = seq.to_le_bytes().to_vec();
+        payload.extend_from_slice(&[0u8; 12]);
+        payload
+    }
+
+    #[test]
+    fn test_seq_gaps() {
+        let mut tracker = SeqTracker::new();
+        let gaps: Vec<Option<SeqGap>> =
+            [10, 11, 14, 15, 3, 4, 6].iter().map(|seq| tracker.record(0, "/imu", "sensor_msgs/Imu", None, &imu(*seq))).collect();
+        assert_eq!(gaps[2], Some(SeqGap { expected: 12, seq: 14 }));
+        // Back to 3 is a restart, not a gap
+        assert_eq!(gaps[4], None);
+        assert_eq!(gaps[6].map(|gap| gap.dropped()), Some(1));
+        // Another connection of the topic has its own sequence
+        assert_eq!(tracker.record(1, "/imu", "sensor_msgs/Imu", None, &imu(100)), None);
+        assert_eq!(tracker.record(1, "/imu", "sensor_msgs/Imu", None, &imu(101)), None);
+        // Publishers that do not number their messages
+        for _ in 0..3 {
+            assert_eq!(tracker.record(2, "/fix", "sensor_msgs/NavSatFix", None, &imu(0)), None);
+        }
+        assert_eq!(tracker.record(3, "/tf", "tf2_msgs/TFMessage", None, &imu(7)), None);
+        // Any type whose definition starts with a header, and only those
+        let stamped = TypeInfo::new(&[], "# A reading\nint8 LOW=0\nHeader header\nfloat64 value\n");
+        let bare = TypeInfo::new(&[], "uint32 count\nHeader header\n");
+        for seq in [1, 3] {
+            tracker.record(4, "/reading", "acme_msgs/Reading", Some(&stamped), &imu(seq));
+            assert_eq!(tracker.record(5, "/counter", "acme_msgs/Counter", Some(&bare), &imu(seq)), None);
+        }
+
+        let report = tracker.report();
+        assert_eq!(report.len(), 2);
+        assert_eq!((report[1].topic.as_str(), report[1].dropped), ("/reading", 1));
+        let imu = &report[0];
+        assert_eq!((imu.messages, imu.gaps, imu.dropped, imu.max_gap, imu.resets), (9, 2, 3, 2, 1));
+        assert!((imu.drop_rate() - 0.25).abs() < 1e-9);
+        assert!(drops_to_text(&report[..1]).contains("3 messages dropped on 1 topics"));
+    }
+}
@@ -0,0 +1,112 @@
+//! Typed progress events for applications embedding the converter
+//!
+//! ```rust,no_run
+//! use bag2rrd::{convert_bag, ConvertEvent, ConvertOptions, ProgressHook};
+//!
+//! let options = ConvertOptions::new("input.bag", "output.rrd")
+//!     .show_progress(false)
+//!     .progress_hook(ProgressHook::new(|event| match event {
+//!         ConvertEvent::Progress(stats) => println!("{} messages read", stats.processed_msgs),
+//!         ConvertEvent::SegmentCompleted(part) => println!("wrote {}", part.file),
+//!         ConvertEvent::Warning(warning) => eprintln!("{warning}"),
+//!         _ => {}
+//!     }));
+//! convert_bag(&options)?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::manifest::SegmentEntry;
+use crate::mappings::registry::MessageKind;
+use crate::plan::ConversionPlan;
+
+/// Messages between two [`ConvertEvent::Progress`] events
+pub const PROGRESS_INTERVAL: u64 = 1000;
+
+/// Message counters of a conversion
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ConvertStats {
+    /// Messages read from the bag so far, kept or not
+    pub processed_msgs: u64,
+    /// Messages of the chunks read so far
+    pub total_msgs: u64,
+    /// Messages logged to the recording
+    pub kept_msgs: u64,
+    pub images: u64,
+    pub compressed_images: u64,
+    pub pointclouds: u64,
+    pub laserscans: u64,
+    pub gps_fixes: u64,
+    pub imu_msgs: u64,
+    pub generic_msgs: u64,
+    /// Messages of types without a mapper, or that did not decode
+    pub skipped_types: u64,
+    /// Of the skipped messages, those a mapper failed to parse
+    pub failed_msgs: u64,
+    /// Messages of topics left out by the filters
+    pub filtered_out: u64,
+    /// Images left out by --image-every-nth
+    pub decimated_images: u64,
+    /// Messages left out by --max-rate
+    pub rate_limited: u64,
+    /// Payload bytes of the logged messages
+    pub raw_bytes: u64,
+}
+
+impl ConvertStats {
+    /// Count a logged message of `bytes` payload bytes
+    pub fn count(&mut self, kind: MessageKind, bytes: u64) {
+        match kind {
+            MessageKind::Image => self.images += 1,
+            MessageKind::CompressedImage => self.compressed_images += 1,
+            MessageKind::PointCloud => self.pointclouds += 1,
+            MessageKind::LaserScan => self.laserscans += 1,
+            MessageKind::GpsFix => self.gps_fixes += 1,
+            MessageKind::Imu => self.imu_msgs += 1,
+            MessageKind::Generic => self.generic_msgs += 1,
+            MessageKind::Other => {}
+        }
+        self.kept_msgs += 1;
+        self.raw_bytes += bytes;
+    }
+}
+
+/// What a conversion reports to its [`ProgressHook`]
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum ConvertEvent {
+    /// Counters so far, every [`PROGRESS_INTERVAL`] messages
+    Progress(ConvertStats),
+    /// A segment was flushed and renamed into place
+    SegmentCompleted(SegmentEntry),
+    /// A problem the conversion works around, e.g. a bag without /clock under --sim-time
+    Warning(String),
+    /// What a --dry-run would write, once the bag is read
+    Plan(ConversionPlan),
+    /// Final counters, once the output is flushed
+    Finished(ConvertStats),
+}
+
+/// Callback receiving the [`ConvertEvent`]s of a conversion; it runs on the
+/// converting thread, so it should return quickly
+#[derive(Clone)]
+pub struct ProgressHook(Arc<dyn Fn(&ConvertEvent) + Send + Sync>);
+
+impl ProgressHook {
+    pub fn new(hook: impl Fn(&ConvertEvent) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+
+    pub fn emit(&self, event: &ConvertEvent) {
+        (self.0)(event)
+    }
+}
+
+impl fmt::Debug for ProgressHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressHook")
+    }
+}
@@ -0,0 +1,232 @@
+//! extract images - Write image topics to PNG/JPEG sequences or an MP4 video
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use super::{message_file_name, ExtractInput};
+use crate::mappings::images::decode_to_image;
+
+/// Output of extracted images
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// One .png per frame, 16-bit depth kept
+    #[default]
+    Png,
+    /// One .jpg per frame
+    Jpeg,
+    /// One H.264 .mp4 per run, timed by the frame stamps
+    #[cfg(feature = "video-export")]
+    Mp4,
+}
+
+pub fn parse_image_format(s: &str) -> Result<ImageFormat> {
+    match s.to_ascii_lowercase().as_str() {
+        "png" => Ok(ImageFormat::Png),
+        "jpeg" | "jpg" => Ok(ImageFormat::Jpeg),
+        #[cfg(feature = "video-export")]
+        "mp4" => Ok(ImageFormat::Mp4),
+        #[cfg(not(feature = "video-export"))]
+        "mp4" => bail!("--format mp4 needs bag2rrd built with the `video-export` feature"),
+        _ => bail!("Invalid image format '{}' (expected png, jpeg or mp4)", s),
+    }
+}
+
+/// What `extract images` writes
+#[derive(Clone, Debug)]
+pub struct ImageExtract {
+    /// Topic names, globs or regexes; every Image and CompressedImage topic when empty
+    pub topics: Vec<String>,
+    pub format: ImageFormat,
+    /// JPEG quality, 1-100
+    pub jpeg_quality: u8,
+    /// Start offset in seconds from the beginning of the bag
+    pub start_time: Option<f64>,
+    /// End offset in seconds from the beginning of the bag
+    pub end_time: Option<f64>,
+    /// Keep at most this many frames per second and topic
+    pub fps: Option<f64>,
+    /// Resize factor (0.5 halves width and height)
+    pub scale: Option<f64>,
+}
+
+impl Default for ImageExtract {
+    fn default() -> Self {
+        Self {
+            topics: vec![],
+            format: ImageFormat::Png,
+            jpeg_quality: 90,
+            start_time: None,
+            end_time: None,
+            fps: None,
+            scale: None,
+        }
+    }
+}
+
+/// Write the image messages of the bag at `path` to `out`: a directory of files
+/// named after topic and stamp, or the .mp4 file of a single topic
+pub fn extract_images(path: &str, out: &str, extract: &ImageExtract) -> Result<()> {
+    let input = ExtractInput::open(path)?;
+    let conns = input.connections(&extract.topics, &["sensor_msgs/Image", "sensor_msgs/CompressedImage"])?;
+    if matches!(extract.format, ImageFormat::Png | ImageFormat::Jpeg) {
+        std::fs::create_dir_all(out).with_context(|| format!("failed to create {}", out))?;
+    }
+    #[cfg(feature = "video-export")]
+    let mut video = match extract.format {
+        ImageFormat::Mp4 => Some(open_video(out, &conns, extract.fps)?),
+        _ => None,
+    };
+
+    let mut last_kept: HashMap<String, f64> = HashMap::new();
+    let (mut written, mut undecoded) = (0u64, 0u64);
+    input.messages(&conns, extract.start_time, extract.end_time, |topic, tp, bag_time, data| {
+        let stamp = crate::convert::header_stamp(tp, data).unwrap_or(bag_time);
+        // A little slack so jitter does not drop every other frame at the source rate
+        if let (Some(fps), Some(last)) = (extract.fps, last_kept.get(topic))
+            && stamp - last < 0.95 / fps
+        {
+            return Ok(());
+        }
+        let Some(mut image) = decode_to_image(tp, data)? else {
+            undecoded += 1;
+            return Ok(());
+        };
+        last_kept.insert(topic.to_string(), stamp);
+        if let Some(scale) = extract.scale.filter(|scale| *scale != 1.0) {
+            let width = ((image.width() as f64 * scale).round() as u32).max(1);
+            let height = ((image.height() as f64 * scale).round() as u32).max(1);
+            image = image.resize_exact(width, height, image::imageops::FilterType::Triangle);
+        }
+        match extract.format {
+            #[cfg(feature = "video-export")]
+            ImageFormat::Mp4 => {
+                if let Some(video) = &mut video {
+                    video.push(image.to_rgb8(), stamp)?;
+                }
+            }
+            ImageFormat::Jpeg => {
+                let path = Path::new(out).join(message_file_name(topic, stamp, "jpg"));
+                let file = std::fs::File::create(&path).with_context(|| format!("failed to create {}", path.display()))?;
+                let mut writer = std::io::BufWriter::new(file);
+                {
+                    let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut writer, extract.jpeg_quality);
+                    // JPEG holds 8-bit gray or RGB only
+                    if image.color().has_color() {
+                        encoder.encode_image(&image.to_rgb8())?;
+                    } else {
+                        encoder.encode_image(&image.to_luma8())?;
+                    }
+                }
+                writer.flush()?;
+            }
+            ImageFormat::Png => {
+                let path = Path::new(out).join(message_file_name(topic, stamp, "png"));
+                image
+                    .save_with_format(&path, image::ImageFormat::Png)
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+            }
+        }
+        written += 1;
+        Ok(())
+    })?;
+
+    if undecoded > 0 {
+        tracing::warn!("{} images in unsupported encodings skipped", undecoded);
+    }
+    #[cfg(feature = "video-export")]
+    if let Some(video) = video {
+        video.finish()?;
+    }
+    println!("Wrote {} frames to {}", written, out);
+    Ok(())
+}
+
+/// The MP4 writer of the single topic of `conns`
+#[cfg(feature = "video-export")]
+fn open_video(out: &str, conns: &HashMap<u32, (String, String)>, fps: Option<f64>) -> Result<super::video::Mp4Video> {
+    let topics: std::collections::HashSet<&str> = conns.values().map(|(topic, _)| topic.as_str()).collect();
+    if topics.len() > 1 {
+        let mut topics: Vec<&str> = topics.into_iter().collect();
+        topics.sort();
+        bail!("an MP4 holds one topic; pick one of {} with --topic", topics.join(", "));
+    }
+    super::video::Mp4Video::create(out, fps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+    /// sensor_msgs/Image of `width` x 1 pixels
+    fn image_payload(stamp: f64, encoding: &str, width: u32, data: &[u8]) -> Vec<u8> {
+        let mut payload = 0u32.to_le_bytes().to_vec();
+        payload.extend_from_slice(&(stamp.trunc() as u32).to_le_bytes());
+        payload.extend_from_slice(&((stamp.fract() * 1e9).round() as u32).to_le_bytes());
+        payload.extend_from_slice(&0u32.to_le_bytes()); // frame_id
+        payload.extend_from_slice(&1u32.to_le_bytes()); // height
+        payload.extend_from_slice(&width.to_le_bytes());
+        payload.extend_from_slice(&(encoding.len() as u32).to_le_bytes());
+        payload.extend_from_slice(encoding.as_bytes());
+        payload.push(0); // is_bigendian
+        payload.extend_from_slice(&(data.len() as u32).to_le_bytes()); // step
+        payload.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        payload.extend_from_slice(data);
+        payload
+    }
+
+    #[test]
+    fn test_extract_images() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_extract_images_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("camera.bag");
+        let connections = [
+            TestConnection { id: 0, topic: "/camera/image_raw", tp: "sensor_msgs/Image", latching: false },
+            TestConnection { id: 1, topic: "/depth", tp: "sensor_msgs/Image", latching: false },
+        ];
+        // 30 Hz color frames, one depth frame
+        let mut messages: Vec<TestMessage> = (0..6)
+            .map(|i| {
+                let t = 100.0 + i as f64 / 30.0;
+                TestMessage::new(0, t, image_payload(t, "rgb8", 2, &[255, 0, 0, 0, 0, 255]))
+            })
+            .collect();
+        messages.push(TestMessage::new(1, 100.0, image_payload(100.0, "16UC1", 2, &[0xe8, 0x03, 0xff, 0xff])));
+        write_bag(&bag, &connections, &[messages]);
+        let bag = bag.to_string_lossy().into_owned();
+
+        // At most 10 frames per second of the camera: 0 s and 0.1 s
+        let frames = dir.join("frames");
+        let extract = ImageExtract { topics: vec!["/camera/**".to_string()], fps: Some(10.0), ..Default::default() };
+        extract_images(&bag, &frames.to_string_lossy(), &extract).unwrap();
+        let mut files: Vec<String> = std::fs::read_dir(&frames)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["camera_image_raw_100.000000.png", "camera_image_raw_100.100000.png"]);
+        let frame = image::open(frames.join(&files[0])).unwrap().to_rgb8();
+        assert_eq!(frame.dimensions(), (2, 1));
+        assert_eq!(frame.get_pixel(1, 0).0, [0, 0, 255]);
+
+        // 16-bit depth survives PNG
+        let depth = dir.join("depth");
+        let extract = ImageExtract { topics: vec!["/depth".to_string()], ..Default::default() };
+        extract_images(&bag, &depth.to_string_lossy(), &extract).unwrap();
+        let depth = image::open(depth.join("depth_100.000000.png")).unwrap();
+        assert_eq!(depth.as_luma16().unwrap().get_pixel(0, 0).0, [1000]);
+
+        // An MP4 holds one topic
+        #[cfg(feature = "video-export")]
+        {
+            let extract = ImageExtract { format: ImageFormat::Mp4, ..Default::default() };
+            let err = extract_images(&bag, &dir.join("all.mp4").to_string_lossy(), &extract).unwrap_err();
+            assert!(err.to_string().contains("an MP4 holds one topic"), "{err:#}");
+        }
+        #[cfg(not(feature = "video-export"))]
+        assert!(parse_image_format("mp4").is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,103 @@
+//! extract commands - Write messages of a bag to standard file formats
+//!
+//! [`pointclouds`] writes PointCloud2 messages to PCD, PLY or LAS files and
+//! [`images`] image topics to PNG/JPEG sequences or, with the `video-export`
+//! feature, an H.264 MP4 video. [`table`] flattens any topic decoded from its
+//! definition into CSV or, with the `parquet-export` feature, Parquet tables.
+
+pub mod images;
+pub mod pointclouds;
+pub mod table;
+#[cfg(feature = "video-export")]
+mod video;
+
+use anyhow::{bail, Context, Result};
+use rosbag::{ChunkRecord, MessageRecord, RosBag};
+use std::collections::HashMap;
+
+use crate::filter::MessageFilter;
+use crate::rosbags_io::BagIndex;
+use crate::source::StagedInputs;
+
+/// A bag opened for extraction; ROS2 bags and MCAP files are staged as ROS1 bags
+pub(crate) struct ExtractInput {
+    bag: RosBag,
+    pub(crate) index: BagIndex,
+    /// Record time of the first message, in seconds
+    origin_s: f64,
+    path: String,
+    _staged: StagedInputs,
+}
+
+impl ExtractInput {
+    pub(crate) fn open(path: &str) -> Result<Self> {
+        let staged = StagedInputs::new(&[path.to_string()])?;
+        let bag = RosBag::new(&staged.paths[0]).with_context(|| format!("failed to open bag: {}", path))?;
+        let Some(index) = BagIndex::read(&bag)? else {
+            bail!("{path}: bag has no index; repair it first with `bag2rrd diagnose {path} --repair fixed.bag`");
+        };
+        let origin_s = index.start_ns().unwrap_or_default() as f64 / 1e9;
+        Ok(Self { bag, index, origin_s, path: path.to_string(), _staged: staged })
+    }
+
+    /// Connection id → topic and type, for the topics of `types` (any type when
+    /// empty) that `topics` keep (all when empty)
+    pub(crate) fn connections(&self, topics: &[String], types: &[&str]) -> Result<HashMap<u32, (String, String)>> {
+        let types: Vec<String> = types.iter().map(|tp| tp.to_string()).collect();
+        let filter = MessageFilter::new(topics, &[], &types, &[])?;
+        let conns: HashMap<u32, (String, String)> = self
+            .index
+            .connections
+            .iter()
+            .filter(|(_, (topic, tp))| (types.is_empty() || types.contains(tp)) && filter.allows(topic, tp))
+            .map(|(id, conn)| (*id, conn.clone()))
+            .collect();
+        if conns.is_empty() && types.is_empty() {
+            bail!("{}: no topic matches", self.path);
+        } else if conns.is_empty() {
+            bail!("{}: no {} topic matches", self.path, types.join(" or "));
+        }
+        Ok(conns)
+    }
+
+    /// Pass the messages of `conns` recorded between the `start` and `end`
+    /// offsets (seconds from the first message) to `visit`, with their record time
+    pub(crate) fn messages(
+        &self,
+        conns: &HashMap<u32, (String, String)>,
+        start: Option<f64>,
+        end: Option<f64>,
+        mut visit: impl FnMut(&str, &str, f64, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        for record in self.bag.chunk_records() {
+            let ChunkRecord::Chunk(chunk) = record? else {
+                continue;
+            };
+            for msg in chunk.messages() {
+                let MessageRecord::MessageData(msg) = msg? else {
+                    continue;
+                };
+                let Some((topic, tp)) = conns.get(&msg.conn_id) else {
+                    continue;
+                };
+                let bag_time = msg.time as f64 / 1e9;
+                let offset = bag_time - self.origin_s;
+                if start.is_some_and(|start| offset < start) || end.is_some_and(|end| offset > end) {
+                    continue;
+                }
+                visit(topic, tp, bag_time, msg.data)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// File name of one message of `topic`: `<topic>_<stamp>.<extension>`
+fn message_file_name(topic: &str, stamp: f64, extension: &str) -> String {
+    format!("{}_{:.6}.{}", topic_file_stem(topic), stamp, extension)
+}
+
+/// `topic` as a file name: `/camera/image_raw` → `camera_image_raw`
+fn topic_file_stem(topic: &str) -> String {
+    topic.trim_matches('/').replace('/', "_")
+}
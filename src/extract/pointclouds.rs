@@ -0,0 +1,530 @@
+//! extract pointclouds - Write the PointCloud2 messages of a bag to PCD, PLY or LAS files
+//!
+//! Every kept message becomes a file of its own, or with `--aggregate FRAME` all
+//! of them are transformed into FRAME through the bag's TF and written as one
+//! cloud, e.g. a map accumulated from a lidar sweeping along a drive.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+
+use super::{message_file_name, ExtractInput};
+use crate::mappings::pointcloud::{parse_field_values, parse_points, pointcloud_frame_id};
+use crate::mappings::tf::{parse_tf_message, TfGraph, TfMode};
+
+/// File format of extracted clouds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CloudFormat {
+    /// PCL's binary .pcd
+    #[default]
+    Pcd,
+    /// Binary little-endian .ply
+    Ply,
+    /// ASPRS LAS 1.2, point format 0 or 2 (with colors), millimeter resolution
+    Las,
+}
+
+impl CloudFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            CloudFormat::Pcd => "pcd",
+            CloudFormat::Ply => "ply",
+            CloudFormat::Las => "las",
+        }
+    }
+}
+
+pub fn parse_cloud_format(s: &str) -> Result<CloudFormat> {
+    match s.to_ascii_lowercase().as_str() {
+        "pcd" => Ok(CloudFormat::Pcd),
+        "ply" => Ok(CloudFormat::Ply),
+        "las" => Ok(CloudFormat::Las),
+        "laz" => bail!("LAZ is not supported; extract to las and compress with laszip"),
+        _ => bail!("Invalid point cloud format '{}' (expected pcd, ply or las)", s),
+    }
+}
+
+/// What `extract pointclouds` writes
+#[derive(Clone, Debug)]
+pub struct PointCloudExtract {
+    /// Topic names, globs or regexes; every PointCloud2 topic when empty
+    pub topics: Vec<String>,
+    pub format: CloudFormat,
+    /// Start offset in seconds from the beginning of the bag
+    pub start_time: Option<f64>,
+    /// End offset in seconds from the beginning of the bag
+    pub end_time: Option<f64>,
+    /// Keep one message out of N per topic
+    pub every_nth_message: u64,
+    /// Keep one point out of N per message
+    pub every_nth_point: usize,
+    /// Keep one point per voxel of this size (meters), in each written file
+    pub voxel_size: Option<f64>,
+    /// Transform every cloud into this frame and write them as one file
+    pub aggregate: Option<String>,
+    pub tf_mode: TfMode,
+}
+
+impl Default for PointCloudExtract {
+    fn default() -> Self {
+        Self {
+            topics: vec![],
+            format: CloudFormat::Pcd,
+            start_time: None,
+            end_time: None,
+            every_nth_message: 1,
+            every_nth_point: 1,
+            voxel_size: None,
+            aggregate: None,
+            tf_mode: TfMode::Interpolate,
+        }
+    }
+}
+
+/// Points with their intensity and colors, when every cloud has them
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Cloud {
+    pub positions: Vec<[f32; 3]>,
+    pub intensity: Option<Vec<f32>>,
+    pub colors: Option<Vec<[u8; 3]>>,
+}
+
+impl Cloud {
+    /// Points of a PointCloud2 payload, NaN points left out
+    fn from_pointcloud2(payload: &[u8]) -> Result<Self> {
+        let parsed = parse_points(payload, None, &[])?;
+        Ok(Self {
+            positions: parsed.positions.iter().map(|p| [p.x(), p.y(), p.z()]).collect(),
+            intensity: parse_field_values(payload, "intensity")?,
+            colors: parsed.colors,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Keep the points `keep` selects, by index
+    fn retain(&mut self, keep: impl Fn(usize, &[f32; 3]) -> bool) {
+        let kept: Vec<usize> = (0..self.len()).filter(|&i| keep(i, &self.positions[i])).collect();
+        self.positions = kept.iter().map(|&i| self.positions[i]).collect();
+        if let Some(intensity) = &mut self.intensity {
+            *intensity = kept.iter().map(|&i| intensity[i]).collect();
+        }
+        if let Some(colors) = &mut self.colors {
+            *colors = kept.iter().map(|&i| colors[i]).collect();
+        }
+    }
+
+    /// Keep the first point of each `size`-meter voxel
+    pub fn voxel_filter(&mut self, size: f64) {
+        let mut occupied = HashSet::new();
+        let voxel = |v: f32| (v as f64 / size).floor() as i64;
+        let keep: Vec<bool> =
+            self.positions.iter().map(|p| occupied.insert((voxel(p[0]), voxel(p[1]), voxel(p[2])))).collect();
+        self.retain(|i, _| keep[i]);
+    }
+
+    /// Add the points of `other`; an attribute missing from either is dropped
+    fn append(&mut self, other: Cloud) {
+        if self.positions.is_empty() {
+            *self = other;
+            return;
+        }
+        self.positions.extend(other.positions);
+        self.intensity = self.intensity.take().zip(other.intensity).map(|(mut a, b)| {
+            a.extend(b);
+            a
+        });
+        self.colors = self.colors.take().zip(other.colors).map(|(mut a, b)| {
+            a.extend(b);
+            a
+        });
+    }
+
+    pub fn write(&self, format: CloudFormat, out: &mut impl Write) -> Result<()> {
+        match format {
+            CloudFormat::Pcd => self.write_pcd(out),
+            CloudFormat::Ply => self.write_ply(out),
+            CloudFormat::Las => self.write_las(out),
+        }
+    }
+
+    fn write_pcd(&self, out: &mut impl Write) -> Result<()> {
+        let mut fields = vec!["x", "y", "z"];
+        fields.extend(self.intensity.as_ref().map(|_| "intensity"));
+        fields.extend(self.colors.as_ref().map(|_| "rgb"));
+        let repeat = |value: &str| vec![value; fields.len()].join(" ");
+        writeln!(out, "# .PCD v0.7 - Point Cloud Data file format")?;
+        writeln!(out, "VERSION 0.7")?;
+        writeln!(out, "FIELDS {}", fields.join(" "))?;
+        writeln!(out, "SIZE {}", repeat("4"))?;
+        writeln!(out, "TYPE {}", repeat("F"))?;
+        writeln!(out, "COUNT {}", repeat("1"))?;
+        writeln!(out, "WIDTH {}\nHEIGHT 1\nVIEWPOINT 0 0 0 1 0 0 0", self.len())?;
+        writeln!(out, "POINTS {}\nDATA binary", self.len())?;
+        for (i, p) in self.positions.iter().enumerate() {
+            for v in p {
+                out.write_all(&v.to_le_bytes())?;
+            }
+            if let Some(intensity) = &self.intensity {
+                out.write_all(&intensity[i].to_le_bytes())?;
+            }
+            // PCL packs rgb as the bits of a float: 0x00RRGGBB
+            if let Some(colors) = &self.colors {
+                let [r, g, b] = colors[i];
+                out.write_all(&u32::from_be_bytes([0, r, g, b]).to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_ply(&self, out: &mut impl Write) -> Result<()> {
+        writeln!(out, "ply\nformat binary_little_endian 1.0\ncomment generated by bag2rrd")?;
+        writeln!(out, "element vertex {}", self.len())?;
+        writeln!(out, "property float x\nproperty float y\nproperty float z")?;
+        if self.intensity.is_some() {
+            writeln!(out, "property float intensity")?;
+        }
+        if self.colors.is_some() {
+            writeln!(out, "property uchar red\nproperty uchar green\nproperty uchar blue")?;
+        }
+        writeln!(out, "end_header")?;
+        for (i, p) in self.positions.iter().enumerate() {
+            for v in p {
+                out.write_all(&v.to_le_bytes())?;
+            }
+            if let Some(intensity) = &self.intensity {
+                out.write_all(&intensity[i].to_le_bytes())?;
+            }
+            if let Some(colors) = &self.colors {
+                out.write_all(&colors[i])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_las(&self, out: &mut impl Write) -> Result<()> {
+        const HEADER_SIZE: u16 = 227;
+        const SCALE: f64 = 0.001;
+        let count = u32::try_from(self.len()).context("too many points for LAS")?;
+        let (format, record_len): (u8, u16) = if self.colors.is_some() { (2, 26) } else { (0, 20) };
+        let mut min = [f64::MAX; 3];
+        let mut max = [f64::MIN; 3];
+        for p in &self.positions {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(p[axis] as f64);
+                max[axis] = max[axis].max(p[axis] as f64);
+            }
+        }
+        if self.positions.is_empty() {
+            (min, max) = ([0.0; 3], [0.0; 3]);
+        }
+        // Offsets keep coordinates far from the origin within the i32 range
+        let offset = min.map(f64::floor);
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(b"LASF");
+        header.extend_from_slice(&[0; 4]); // file source id, global encoding
+        header.extend_from_slice(&[0; 16]); // project id
+        header.extend_from_slice(&[1, 2]); // version 1.2
+        header.extend_from_slice(&fixed_text("", 32)); // system identifier
+        header.extend_from_slice(&fixed_text("bag2rrd", 32));
+        header.extend_from_slice(&[0; 4]); // creation day of year, year
+        header.extend_from_slice(&HEADER_SIZE.to_le_bytes());
+        header.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes()); // offset to point data
+        header.extend_from_slice(&0u32.to_le_bytes()); // variable length records
+        header.push(format);
+        header.extend_from_slice(&record_len.to_le_bytes());
+        header.extend_from_slice(&count.to_le_bytes());
+        for returns in [count, 0, 0, 0, 0] {
+            header.extend_from_slice(&returns.to_le_bytes());
+        }
+        for value in [SCALE; 3].into_iter().chain(offset) {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        for axis in 0..3 {
+            header.extend_from_slice(&max[axis].to_le_bytes());
+            header.extend_from_slice(&min[axis].to_le_bytes());
+        }
+        out.write_all(&header)?;
+
+        for (i, p) in self.positions.iter().enumerate() {
+            for axis in 0..3 {
+                let scaled = ((p[axis] as f64 - offset[axis]) / SCALE).round() as i32;
+                out.write_all(&scaled.to_le_bytes())?;
+            }
+            let intensity = self.intensity.as_ref().map_or(0.0, |values| values[i]);
+            out.write_all(&(intensity.clamp(0.0, u16::MAX as f32) as u16).to_le_bytes())?;
+            // Return 1 of 1, unclassified, no scan angle, user data or source id
+            out.write_all(&[0b0000_1001, 0, 0, 0, 0, 0])?;
+            if let Some(colors) = &self.colors {
+                for c in colors[i] {
+                    out.write_all(&(c as u16 * 257).to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `text` padded with NULs to `len` bytes
+fn fixed_text(text: &str, len: usize) -> Vec<u8> {
+    let mut out = text.as_bytes()[..text.len().min(len)].to_vec();
+    out.resize(len, 0);
+    out
+}
+
+/// Write the PointCloud2 messages of the bag at `path` to `out`: a directory of
+/// files named after topic and stamp, or the file of the aggregate
+pub fn extract_pointclouds(path: &str, out: &str, extract: &PointCloudExtract) -> Result<()> {
+    let input = ExtractInput::open(path)?;
+    let clouds = input.connections(&extract.topics, &["sensor_msgs/PointCloud2"])?;
+    let tf_graph = match &extract.aggregate {
+        Some(_) => Some(load_tf(&input)?),
+        None => {
+            std::fs::create_dir_all(out).with_context(|| format!("failed to create {}", out))?;
+            None
+        }
+    };
+
+    let mut aggregate = Cloud::default();
+    let mut seen: HashMap<String, u64> = HashMap::new();
+    let (mut written, mut untransformed, mut malformed) = (0u64, 0u64, 0u64);
+    input.messages(&clouds, extract.start_time, extract.end_time, |topic, _, bag_time, data| {
+        let nth = seen.entry(topic.to_string()).or_insert(0);
+        let keep = nth.is_multiple_of(extract.every_nth_message);
+        *nth += 1;
+        if !keep {
+            return Ok(());
+        }
+        let mut cloud = match Cloud::from_pointcloud2(data) {
+            Ok(cloud) => cloud,
+            Err(e) => {
+                malformed += 1;
+                tracing::debug!("{} at {:.6}: cloud skipped: {:#}", topic, bag_time, e);
+                return Ok(());
+            }
+        };
+        if extract.every_nth_point > 1 {
+            cloud.retain(|i, _| i % extract.every_nth_point == 0);
+        }
+        let stamp = crate::convert::header_stamp("sensor_msgs/PointCloud2", data).unwrap_or(bag_time);
+        match (&extract.aggregate, &tf_graph) {
+            (Some(target), Some(tf_graph)) => {
+                let frame = pointcloud_frame_id(data).ok().flatten().unwrap_or_default();
+                let iso = if frame.is_empty() || frame == *target {
+                    Some(nalgebra::Isometry3::identity())
+                } else {
+                    tf_graph.resolve_pose(target, &frame, stamp, extract.tf_mode)
+                };
+                let Some(iso) = iso else {
+                    untransformed += 1;
+                    tracing::debug!("No TF from {} to {} at {:.6}; cloud skipped", frame, target, stamp);
+                    return Ok(());
+                };
+                for p in &mut cloud.positions {
+                    let q = iso * nalgebra::Point3::new(p[0] as f64, p[1] as f64, p[2] as f64);
+                    *p = [q.x as f32, q.y as f32, q.z as f32];
+                }
+                aggregate.append(cloud);
+            }
+            _ => {
+                if let Some(size) = extract.voxel_size {
+                    cloud.voxel_filter(size);
+                }
+                let name = message_file_name(topic, stamp, extract.format.extension());
+                write_cloud(&cloud, extract.format, &Path::new(out).join(name))?;
+            }
+        }
+        written += 1;
+        Ok(())
+    })?;
+
+    if malformed > 0 {
+        tracing::warn!("{} malformed clouds skipped", malformed);
+    }
+    if untransformed > 0 {
+        tracing::warn!("{} clouds skipped without a TF to {}", untransformed, extract.aggregate.as_deref().unwrap_or_default());
+    }
+    if extract.aggregate.is_some() {
+        if let Some(size) = extract.voxel_size {
+            aggregate.voxel_filter(size);
+        }
+        write_cloud(&aggregate, extract.format, Path::new(out))?;
+        println!("Aggregated {} clouds ({} points) into {}", written, aggregate.len(), out);
+    } else {
+        println!("Wrote {} clouds to {}", written, out);
+    }
+    Ok(())
+}
+
+fn write_cloud(cloud: &Cloud, format: CloudFormat, path: &Path) -> Result<()> {
+    let file = std::fs::File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut out = std::io::BufWriter::new(file);
+    cloud.write(format, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Every transform of the bag's TF topics, dynamic ones at their stamp
+fn load_tf(input: &ExtractInput) -> Result<TfGraph> {
+    let tf_conns = input.connections(&[], &["tf2_msgs/TFMessage", "tf/tfMessage"]).unwrap_or_default();
+    let static_topics: HashSet<&str> = tf_conns
+        .iter()
+        .filter(|(id, (topic, _))| input.index.latched.contains(id) || topic.trim_start_matches('/') == "tf_static")
+        .map(|(_, (topic, _))| topic.as_str())
+        .collect();
+    let mut tf_graph = TfGraph::new();
+    input.messages(&tf_conns, None, None, |topic, _, bag_time, data| {
+        let transforms = match parse_tf_message(data) {
+            Ok(transforms) => transforms,
+            Err(e) => {
+                tracing::warn!("Failed to parse TF message: {}; skipping", e);
+                return Ok(());
+            }
+        };
+        for tf in &transforms {
+            if static_topics.contains(topic) {
+                tf_graph.add_static_transform(tf);
+            } else {
+                let stamp = if tf.header.stamp > 0.0 { tf.header.stamp } else { bag_time };
+                tf_graph.add_transform(tf, stamp);
+            }
+        }
+        Ok(())
+    })?;
+    Ok(tf_graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+    /// sensor_msgs/PointCloud2 with x, y, z and intensity float32 fields
+    fn cloud_payload(frame: &str, stamp: u32, points: &[[f32; 4]]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&stamp.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        data.extend_from_slice(frame.as_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes()); // height
+        data.extend_from_slice(&(points.len() as u32).to_le_bytes()); // width
+        data.extend_from_slice(&4u32.to_le_bytes());
+        for (i, name) in ["x", "y", "z", "intensity"].iter().enumerate() {
+            data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&(4 * i as u32).to_le_bytes());
+            data.push(7); // FLOAT32
+            data.extend_from_slice(&1u32.to_le_bytes());
+        }
+        data.push(0); // is_bigendian
+        data.extend_from_slice(&16u32.to_le_bytes()); // point_step
+        data.extend_from_slice(&(16 * points.len() as u32).to_le_bytes());
+        data.extend_from_slice(&((points.len() * 16) as u32).to_le_bytes());
+        for p in points {
+            for v in p {
+                data.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        data.push(1); // is_dense
+        data
+    }
+
+    /// tf2_msgs/TFMessage with one translation-only transform
+    fn tf_payload(parent: &str, child: &str, x: f64) -> Vec<u8> {
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0; 12]); // seq, stamp
+        for name in [parent, child] {
+            data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+        }
+        for v in [x, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_extract_pointclouds() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_extract_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("clouds.bag");
+        let connections = [
+            TestConnection { id: 0, topic: "/tf_static", tp: "tf2_msgs/TFMessage", latching: true },
+            TestConnection { id: 1, topic: "/lidar/points", tp: "sensor_msgs/PointCloud2", latching: false },
+        ];
+        let nan = f32::NAN;
+        write_bag(
+            &bag,
+            &connections,
+            &[vec![
+                TestMessage::new(0, 100.0, tf_payload("map", "lidar", 10.0)),
+                TestMessage::new(1, 100.0, cloud_payload("lidar", 100, &[[1.0, 0.0, 0.0, 5.0], [nan, 0.0, 0.0, 1.0]])),
+                TestMessage::new(1, 101.0, cloud_payload("lidar", 101, &[[1.01, 0.0, 0.0, 7.0], [2.0, 0.0, 0.0, 9.0]])),
+                // Truncated: skipped rather than failing the extraction
+                TestMessage::new(1, 102.0, cloud_payload("lidar", 102, &[[3.0, 0.0, 0.0, 1.0]])[..40].to_vec()),
+            ]],
+        );
+        let bag = bag.to_string_lossy().into_owned();
+
+        // One file per message, NaN points dropped
+        let per_message = dir.join("clouds");
+        extract_pointclouds(&bag, &per_message.to_string_lossy(), &PointCloudExtract::default()).unwrap();
+        let mut files: Vec<String> = std::fs::read_dir(&per_message)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["lidar_points_100.000000.pcd", "lidar_points_101.000000.pcd"]);
+        let pcd = std::fs::read(per_message.join(&files[0])).unwrap();
+        let text = String::from_utf8_lossy(&pcd);
+        assert!(text.contains("FIELDS x y z intensity\n"), "{text}");
+        assert!(text.contains("POINTS 1\nDATA binary\n"), "{text}");
+
+        // Aggregated in the map frame, with points within 5 cm merged
+        let aggregate = dir.join("map.ply");
+        let extract = PointCloudExtract {
+            format: CloudFormat::Ply,
+            aggregate: Some("map".to_string()),
+            voxel_size: Some(0.05),
+            ..Default::default()
+        };
+        extract_pointclouds(&bag, &aggregate.to_string_lossy(), &extract).unwrap();
+        let ply = std::fs::read(&aggregate).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        let header_end = ply.windows(11).position(|w| w == b"end_header\n").unwrap() + 11;
+        let header = String::from_utf8_lossy(&ply[..header_end]);
+        assert!(header.contains("element vertex 2\n"), "{header}");
+        let x = |i: usize| f32::from_le_bytes(ply[header_end + 16 * i..header_end + 16 * i + 4].try_into().unwrap());
+        assert_eq!((x(0), x(1)), (11.0, 12.0));
+    }
+
+    #[test]
+    fn test_las_records() {
+        let cloud = Cloud {
+            positions: vec![[1000.5, -2.25, 3.0], [1001.0, -2.0, 3.5]],
+            intensity: Some(vec![12.0, 70000.0]),
+            colors: Some(vec![[255, 0, 1], [0, 0, 0]]),
+        };
+        let mut las = Vec::new();
+        cloud.write(CloudFormat::Las, &mut las).unwrap();
+        assert_eq!(las.len(), 227 + 2 * 26);
+        assert_eq!(&las[..4], b"LASF");
+        assert_eq!(las[104], 2); // point format with colors
+        assert_eq!(u32::from_le_bytes(las[107..111].try_into().unwrap()), 2);
+        // X offset is the floored minimum, coordinates in millimeters from it
+        assert_eq!(f64::from_le_bytes(las[155..163].try_into().unwrap()), 1000.0);
+        let record = &las[227..227 + 26];
+        assert_eq!(i32::from_le_bytes(record[0..4].try_into().unwrap()), 500);
+        assert_eq!(i32::from_le_bytes(record[4..8].try_into().unwrap()), 750);
+        assert_eq!(u16::from_le_bytes(record[12..14].try_into().unwrap()), 12);
+        assert_eq!(u16::from_le_bytes(record[20..22].try_into().unwrap()), 65535);
+        // Intensity clamped to u16
+        assert_eq!(u16::from_le_bytes(las[227 + 26 + 12..227 + 26 + 14].try_into().unwrap()), 65535);
+        assert!(parse_cloud_format("laz").is_err());
+    }
+}
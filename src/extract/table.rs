@@ -0,0 +1,395 @@
+//! export table - Flatten topics into CSV or Parquet tables, one per topic
+//!
+//! Messages are decoded from the definitions recorded in the bag, so GPS, IMU,
+//! odometry, joint states and in-house types all become one row per message:
+//! the record time, the header stamp, then one column per numeric or string
+//! field, named by its dotted path (`pose.pose.position.x`, `position.0`).
+//! Arrays longer than 16 elements and raw byte arrays are left out. A first
+//! pass over the bag gathers the columns, a second writes the rows as they are
+//! decoded, so tables of any length are written without holding them in memory.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use super::{topic_file_stem, ExtractInput};
+use crate::mappings::generic::collect_leaves;
+use crate::ros_msg::{MessageSchema, Value};
+
+const PARQUET_FEATURE: &str = "--format parquet needs bag2rrd built with the `parquet-export` feature";
+
+/// Output of exported tables
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TableFormat {
+    #[default]
+    Csv,
+    /// Typed columns, float64 or string (`parquet-export` feature)
+    Parquet,
+}
+
+pub fn parse_table_format(s: &str) -> Result<TableFormat> {
+    match s.to_ascii_lowercase().as_str() {
+        "csv" => Ok(TableFormat::Csv),
+        "parquet" => Ok(TableFormat::Parquet),
+        _ => bail!("Invalid table format '{}' (expected csv or parquet)", s),
+    }
+}
+
+/// What `export table` writes
+#[derive(Clone, Debug, Default)]
+pub struct TableExport {
+    /// Topic names, globs or regexes; every topic when empty
+    pub topics: Vec<String>,
+    pub format: TableFormat,
+    /// Start offset in seconds from the beginning of the bag
+    pub start_time: Option<f64>,
+    /// End offset in seconds from the beginning of the bag
+    pub end_time: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Cell {
+    Number(f64),
+    Text(String),
+}
+
+/// Header stamp and leaf cells of a decoded message, named by their dotted path
+fn flatten(value: &Value) -> (Option<f64>, Vec<(String, Cell)>) {
+    let stamp = value.get("header.stamp").and_then(Value::as_f64).filter(|stamp| *stamp > 0.0);
+    let mut leaves = Vec::new();
+    match value {
+        Value::Struct(fields) => {
+            for (name, field) in fields.iter().filter(|(name, _)| name != "header") {
+                collect_leaves(name, field, &mut leaves);
+            }
+        }
+        other => collect_leaves("value", other, &mut leaves),
+    }
+    let mut cells = Vec::with_capacity(leaves.len());
+    for (path, leaf) in leaves {
+        let cell = match leaf {
+            Value::String(text) => Cell::Text(text.clone()),
+            _ => match leaf.as_f64() {
+                Some(v) => Cell::Number(v),
+                None => continue,
+            },
+        };
+        cells.push((path.replace('/', "."), cell));
+    }
+    (stamp, cells)
+}
+
+/// Columns of one topic, gathered on a first pass over the messages so rows can
+/// be written as they are decoded on the second. Columns are added in the order
+/// fields first appear, so variable-length arrays widen the table as they grow
+#[derive(Default)]
+struct Columns {
+    names: Vec<String>,
+    by_name: HashMap<String, usize>,
+    /// Whether the column holds any string, making it a string column in Parquet
+    text: Vec<bool>,
+    rows: u64,
+}
+
+impl Columns {
+    fn add(&mut self, cells: &[(String, Cell)]) {
+        for (name, cell) in cells {
+            let column = match self.by_name.get(name) {
+                Some(column) => *column,
+                None => {
+                    self.by_name.insert(name.clone(), self.names.len());
+                    self.names.push(name.clone());
+                    self.text.push(false);
+                    self.names.len() - 1
+                }
+            };
+            self.text[column] |= matches!(cell, Cell::Text(_));
+        }
+        self.rows += 1;
+    }
+
+    /// `cells` in column order; absent columns are empty
+    fn row(&self, cells: Vec<(String, Cell)>) -> Vec<Option<Cell>> {
+        let mut row = vec![None; self.names.len()];
+        for (name, cell) in cells {
+            if let Some(column) = self.by_name.get(&name) {
+                row[*column] = Some(cell);
+            }
+        }
+        row
+    }
+}
+
+/// Write one table per topic of the bag at `path` into the directory `out`,
+/// named after the topic (`/gps/fix` → `gps_fix.csv`)
+pub fn export_table(path: &str, out: &str, export: &TableExport) -> Result<()> {
+    if export.format == TableFormat::Parquet && !cfg!(feature = "parquet-export") {
+        bail!(PARQUET_FEATURE);
+    }
+    let input = ExtractInput::open(path)?;
+    let conns = input.connections(&export.topics, &[])?;
+    let mut schemas: HashMap<String, MessageSchema> = HashMap::new();
+    for (id, (topic, tp)) in &conns {
+        if schemas.contains_key(topic) {
+            continue;
+        }
+        let definition = input.index.definitions.get(id).map(|info| info.definition.as_str()).unwrap_or_default();
+        if definition.trim().is_empty() {
+            tracing::warn!("{}: no message definition for {}; topic skipped", topic, tp);
+            continue;
+        }
+        match MessageSchema::parse(tp, definition) {
+            Ok(schema) => {
+                schemas.insert(topic.clone(), schema);
+            }
+            Err(e) => tracing::warn!("{}: cannot decode {}: {:#}; topic skipped", topic, tp, e),
+        }
+    }
+    std::fs::create_dir_all(out).with_context(|| format!("failed to create {}", out))?;
+
+    let mut columns: HashMap<String, Columns> = HashMap::new();
+    let mut undecoded = 0u64;
+    input.messages(&conns, export.start_time, export.end_time, |topic, _tp, _bag_time, data| {
+        let Some(schema) = schemas.get(topic) else {
+            return Ok(());
+        };
+        match schema.decode(data) {
+            Ok(value) => columns.entry(topic.to_string()).or_default().add(&flatten(&value).1),
+            Err(_) => undecoded += 1,
+        }
+        Ok(())
+    })?;
+    if undecoded > 0 {
+        tracing::warn!("{} messages that do not match their definition skipped", undecoded);
+    }
+
+    let mut files: HashMap<&str, (TableFile, std::path::PathBuf)> = HashMap::new();
+    for (topic, columns) in &columns {
+        let extension = match export.format {
+            TableFormat::Csv => "csv",
+            TableFormat::Parquet => "parquet",
+        };
+        let file = Path::new(out).join(format!("{}.{}", topic_file_stem(topic), extension));
+        let table = TableFile::create(&file, export.format, columns).with_context(|| format!("failed to create {}", file.display()))?;
+        files.insert(topic, (table, file));
+    }
+    input.messages(&conns, export.start_time, export.end_time, |topic, _tp, bag_time, data| {
+        let (Some(schema), Some(columns), Some((table, file))) = (schemas.get(topic), columns.get(topic), files.get_mut(topic)) else {
+            return Ok(());
+        };
+        let Ok(value) = schema.decode(data) else {
+            return Ok(());
+        };
+        let (stamp, cells) = flatten(&value);
+        table.push(bag_time, stamp, columns.row(cells)).with_context(|| format!("failed to write {}", file.display()))
+    })?;
+
+    let mut topics: Vec<&String> = columns.keys().collect();
+    topics.sort();
+    for topic in topics {
+        let (table, file) = files.remove(topic.as_str()).context("table not created")?;
+        table.finish().with_context(|| format!("failed to write {}", file.display()))?;
+        let columns = &columns[topic];
+        println!("{}: {} rows, {} columns → {}", topic, columns.rows, columns.names.len() + 2, file.display());
+    }
+    Ok(())
+}
+
+/// A table file written row by row
+enum TableFile {
+    Csv(std::io::BufWriter<std::fs::File>),
+    #[cfg(feature = "parquet-export")]
+    Parquet(ParquetTable),
+}
+
+impl TableFile {
+    fn create(path: &Path, format: TableFormat, columns: &Columns) -> Result<Self> {
+        match format {
+            TableFormat::Csv => {
+                let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+                let header: Vec<String> = columns.names.iter().map(|name| csv_field(name)).collect();
+                writeln!(out, "time,stamp{}{}", if header.is_empty() { "" } else { "," }, header.join(","))?;
+                Ok(Self::Csv(out))
+            }
+            #[cfg(feature = "parquet-export")]
+            TableFormat::Parquet => Ok(Self::Parquet(ParquetTable::create(path, columns)?)),
+            #[cfg(not(feature = "parquet-export"))]
+            TableFormat::Parquet => bail!(PARQUET_FEATURE),
+        }
+    }
+
+    fn push(&mut self, time: f64, stamp: Option<f64>, cells: Vec<Option<Cell>>) -> Result<()> {
+        match self {
+            Self::Csv(out) => {
+                write!(out, "{:.9},", time)?;
+                if let Some(stamp) = stamp {
+                    write!(out, "{:.9}", stamp)?;
+                }
+                for cell in cells {
+                    match cell {
+                        Some(Cell::Number(v)) => write!(out, ",{}", v)?,
+                        Some(Cell::Text(text)) => write!(out, ",{}", csv_field(&text))?,
+                        None => write!(out, ",")?,
+                    }
+                }
+                writeln!(out)?;
+            }
+            #[cfg(feature = "parquet-export")]
+            Self::Parquet(table) => table.push(time, stamp, cells)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            Self::Csv(mut out) => out.flush()?,
+            #[cfg(feature = "parquet-export")]
+            Self::Parquet(table) => table.finish()?,
+        }
+        Ok(())
+    }
+}
+
+/// `field` quoted when it holds a separator, quote or line break (RFC 4180)
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Rows buffered before they are written as one Parquet record batch
+#[cfg(feature = "parquet-export")]
+const PARQUET_BATCH: usize = 8192;
+
+/// Typed Parquet columns: float64, or string when a column holds any string
+#[cfg(feature = "parquet-export")]
+struct ParquetTable {
+    writer: parquet::arrow::ArrowWriter<std::fs::File>,
+    schema: arrow::datatypes::SchemaRef,
+    text: Vec<bool>,
+    rows: Vec<(f64, Option<f64>, Vec<Option<Cell>>)>,
+}
+
+#[cfg(feature = "parquet-export")]
+impl ParquetTable {
+    fn create(path: &Path, columns: &Columns) -> Result<Self> {
+        use arrow::datatypes::{DataType, Field, Schema};
+
+        let mut fields = vec![Field::new("time", DataType::Float64, false), Field::new("stamp", DataType::Float64, true)];
+        for (name, text) in columns.names.iter().zip(&columns.text) {
+            fields.push(Field::new(name, if *text { DataType::Utf8 } else { DataType::Float64 }, true));
+        }
+        let schema = std::sync::Arc::new(Schema::new(fields));
+        let writer = parquet::arrow::ArrowWriter::try_new(std::fs::File::create(path)?, schema.clone(), None)?;
+        Ok(Self { writer, schema, text: columns.text.clone(), rows: Vec::new() })
+    }
+
+    fn push(&mut self, time: f64, stamp: Option<f64>, cells: Vec<Option<Cell>>) -> Result<()> {
+        self.rows.push((time, stamp, cells));
+        if self.rows.len() >= PARQUET_BATCH {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        use arrow::array::{ArrayRef, Float64Array, StringArray};
+        use std::sync::Arc;
+
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let rows = std::mem::take(&mut self.rows);
+        let mut arrays: Vec<ArrayRef> = vec![
+            Arc::new(Float64Array::from_iter_values(rows.iter().map(|(time, _, _)| *time))),
+            Arc::new(Float64Array::from_iter(rows.iter().map(|(_, stamp, _)| *stamp))),
+        ];
+        for (column, text) in self.text.iter().enumerate() {
+            let cells = rows.iter().map(|(_, _, cells)| cells[column].as_ref());
+            if *text {
+                arrays.push(Arc::new(StringArray::from_iter(cells.map(|cell| match cell {
+                    Some(Cell::Text(text)) => Some(text.clone()),
+                    Some(Cell::Number(v)) => Some(v.to_string()),
+                    None => None,
+                }))));
+            } else {
+                arrays.push(Arc::new(Float64Array::from_iter(cells.map(|cell| match cell {
+                    Some(Cell::Number(v)) => Some(*v),
+                    _ => None,
+                }))));
+            }
+        }
+        let batch = arrow::record_batch::RecordBatch::try_new(self.schema.clone(), arrays)?;
+        self.writer.write(&batch)?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        self.write_batch()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bag::{write_bag, TestConnection, TestMessage};
+
+    fn string_payload(data: &str) -> Vec<u8> {
+        let mut payload = (data.len() as u32).to_le_bytes().to_vec();
+        payload.extend_from_slice(data.as_bytes());
+        payload
+    }
+
+    /// test_msgs/Reading: header, float64 value, string label
+    fn reading_payload(stamp: f64, value: f64, label: &str) -> Vec<u8> {
+        let mut payload = 0u32.to_le_bytes().to_vec();
+        payload.extend_from_slice(&(stamp.trunc() as u32).to_le_bytes());
+        payload.extend_from_slice(&((stamp.fract() * 1e9).round() as u32).to_le_bytes());
+        payload.extend_from_slice(&string_payload("base_link"));
+        payload.extend_from_slice(&value.to_le_bytes());
+        payload.extend_from_slice(&string_payload(label));
+        payload
+    }
+
+    #[test]
+    fn test_export_table_csv() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_export_table_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("readings.bag");
+        let connections = [
+            TestConnection { id: 0, topic: "/sensor/reading", tp: "test_msgs/Reading", latching: false },
+            TestConnection { id: 1, topic: "/status", tp: "std_msgs/String", latching: false },
+        ];
+        let messages = vec![
+            TestMessage::new(0, 100.0, reading_payload(99.5, 1.5, "ok")),
+            TestMessage::new(1, 100.5, string_payload("calibrating, \"fine\"")),
+            TestMessage::new(0, 101.0, reading_payload(100.5, -2.0, "low")),
+            TestMessage::new(0, 105.0, reading_payload(104.5, 0.0, "late")),
+        ];
+        write_bag(&bag, &connections, &[messages]);
+        let bag = bag.to_string_lossy().into_owned();
+
+        let out = dir.join("tables");
+        let export = TableExport { end_time: Some(2.0), ..Default::default() };
+        export_table(&bag, &out.to_string_lossy(), &export).unwrap();
+        let reading = std::fs::read_to_string(out.join("sensor_reading.csv")).unwrap();
+        assert_eq!(
+            reading,
+            "time,stamp,value,label\n\
+             100.000000000,99.500000000,1.5,ok\n\
+             101.000000000,100.500000000,-2,low\n"
+        );
+        let status = std::fs::read_to_string(out.join("status.csv")).unwrap();
+        assert_eq!(status, "time,stamp,data\n100.500000000,,\"calibrating, \"\"fine\"\"\"\n");
+
+        let export = TableExport { topics: vec!["/missing".to_string()], ..Default::default() };
+        let err = export_table(&bag, &out.to_string_lossy(), &export).unwrap_err();
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(err.to_string().contains("no topic matches"), "{err:#}");
+    }
+}
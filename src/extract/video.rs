@@ -0,0 +1,195 @@
+//! H.264 MP4 writer behind `extract images --format mp4`
+
+use anyhow::{Context, Result};
+use mp4::{AvcConfig, MediaConfig, Mp4Config, Mp4Sample, Mp4Writer, TrackConfig, TrackType};
+use openh264::encoder::{Encoder, EncoderConfig};
+use openh264::formats::{RgbSliceU8, YUVBuffer};
+use openh264::OpenH264API;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+
+/// Ticks per second of the video track
+const TIMESCALE: u32 = 90_000;
+/// Seconds of frames held back to be encoded in stamp order, as messages are
+/// recorded in arrival order
+const REORDER_WINDOW: f64 = 1.0;
+
+/// Encodes RGB frames to an MP4 file, timed by their stamps
+pub(super) struct Mp4Video {
+    path: String,
+    encoder: Encoder,
+    /// Created on the first frame, once its size and SPS/PPS are known
+    writer: Option<Mp4Writer<BufWriter<File>>>,
+    /// Frame size; every frame is resized to the first one, rounded down to even
+    size: (u32, u32),
+    first_stamp: Option<f64>,
+    /// Last encoded sample, written once the next stamp gives its duration
+    pending: Option<(u64, bool, Vec<u8>)>,
+    /// Duration of the last sample, in ticks
+    last_duration: u32,
+    /// Frames not encoded yet by stamp (ns) and arrival
+    queue: BTreeMap<(i64, u64), image::RgbImage>,
+    queued: u64,
+    /// Latest stamp pushed
+    newest: f64,
+    /// Stamp of the last frame encoded
+    encoded_until: Option<f64>,
+    /// Frames dropped for arriving after later frames were encoded
+    late: u64,
+}
+
+impl Mp4Video {
+    pub(super) fn create(path: &str, fps: Option<f64>) -> Result<Self> {
+        let encoder = Encoder::with_api_config(OpenH264API::from_source(), EncoderConfig::new())
+            .context("failed to start the H.264 encoder")?;
+        Ok(Self {
+            path: path.to_string(),
+            encoder,
+            writer: None,
+            size: (0, 0),
+            first_stamp: None,
+            pending: None,
+            last_duration: (TIMESCALE as f64 / fps.unwrap_or(30.0)).round() as u32,
+            queue: BTreeMap::new(),
+            queued: 0,
+            newest: f64::NEG_INFINITY,
+            encoded_until: None,
+            late: 0,
+        })
+    }
+
+    /// Queue a frame; frames older than [`REORDER_WINDOW`] behind the latest are encoded
+    pub(super) fn push(&mut self, image: image::RgbImage, stamp: f64) -> Result<()> {
+        if self.encoded_until.is_some_and(|until| stamp < until) {
+            self.late += 1;
+            return Ok(());
+        }
+        self.queue.insert(((stamp * 1e9).round() as i64, self.queued), image);
+        self.queued += 1;
+        self.newest = self.newest.max(stamp);
+        let horizon = (self.newest - REORDER_WINDOW) * 1e9;
+        while self.queue.first_key_value().is_some_and(|(&(ns, _), _)| (ns as f64) < horizon) {
+            let Some(((ns, _), frame)) = self.queue.pop_first() else {
+                break;
+            };
+            self.encode(&frame, ns as f64 / 1e9)?;
+        }
+        Ok(())
+    }
+
+    fn encode(&mut self, image: &image::RgbImage, stamp: f64) -> Result<()> {
+        self.encoded_until = Some(stamp);
+        if self.first_stamp.is_none() {
+            // 4:2:0 chroma needs even dimensions
+            self.size = ((image.width() & !1).max(2), (image.height() & !1).max(2));
+            self.first_stamp = Some(stamp);
+        }
+        let (width, height) = self.size;
+        let resized;
+        let frame = if image.dimensions() == self.size {
+            image
+        } else {
+            resized = image::imageops::resize(image, width, height, image::imageops::FilterType::Triangle);
+            &resized
+        };
+        let yuv = YUVBuffer::from_rgb_source(RgbSliceU8::new(frame.as_raw(), (width as usize, height as usize)));
+        let bitstream = self.encoder.encode(&yuv).context("H.264 encoding failed")?;
+
+        let (mut sps, mut pps, mut sample, mut is_sync) = (vec![], vec![], vec![], false);
+        for l in 0..bitstream.num_layers() {
+            let Some(layer) = bitstream.layer(l) else {
+                continue;
+            };
+            for n in 0..layer.nal_count() {
+                let Some(nal) = layer.nal_unit(n).map(strip_start_code) else {
+                    continue;
+                };
+                match nal.first().map(|header| header & 0x1f) {
+                    Some(7) => sps = nal.to_vec(),
+                    Some(8) => pps = nal.to_vec(),
+                    Some(kind) => {
+                        is_sync |= kind == 5;
+                        // AVCC: NAL units prefixed by their length
+                        sample.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+                        sample.extend_from_slice(nal);
+                    }
+                    None => {}
+                }
+            }
+        }
+        if sample.is_empty() {
+            // The encoder skipped the frame
+            return Ok(());
+        }
+
+        if self.writer.is_none() {
+            let file = File::create(&self.path).with_context(|| format!("failed to create {}", self.path))?;
+            let config = Mp4Config {
+                major_brand: str::parse("isom")?,
+                minor_version: 512,
+                compatible_brands: vec![str::parse("isom")?, str::parse("iso2")?, str::parse("avc1")?, str::parse("mp41")?],
+                timescale: TIMESCALE,
+            };
+            let mut writer = Mp4Writer::write_start(BufWriter::new(file), &config)?;
+            writer.add_track(&TrackConfig {
+                track_type: TrackType::Video,
+                timescale: TIMESCALE,
+                language: "und".to_string(),
+                media_conf: MediaConfig::AvcConfig(AvcConfig {
+                    width: width as u16,
+                    height: height as u16,
+                    seq_param_set: sps,
+                    pic_param_set: pps,
+                }),
+            })?;
+            self.writer = Some(writer);
+        }
+
+        let start = ((stamp - self.first_stamp.unwrap_or(stamp)).max(0.0) * TIMESCALE as f64).round() as u64;
+        self.write_pending(Some(start))?;
+        self.pending = Some((start, is_sync, sample));
+        Ok(())
+    }
+
+    /// Encode the queued frames, write the last one and the MP4 index
+    pub(super) fn finish(mut self) -> Result<()> {
+        while let Some(((ns, _), frame)) = self.queue.pop_first() {
+            self.encode(&frame, ns as f64 / 1e9)?;
+        }
+        if self.late > 0 {
+            tracing::warn!("{} frames stamped more than {} s before later frames skipped", self.late, REORDER_WINDOW);
+        }
+        self.write_pending(None)?;
+        if let Some(mut writer) = self.writer.take() {
+            writer.write_end()?;
+        }
+        Ok(())
+    }
+
+    /// Write the pending sample, lasting until `next_start` or one frame period
+    fn write_pending(&mut self, next_start: Option<u64>) -> Result<()> {
+        let (Some((start, is_sync, bytes)), Some(writer)) = (self.pending.take(), self.writer.as_mut()) else {
+            return Ok(());
+        };
+        let duration = match next_start {
+            Some(next) if next > start => {
+                self.last_duration = (next - start) as u32;
+                self.last_duration
+            }
+            _ => self.last_duration,
+        };
+        writer.write_sample(
+            1,
+            &Mp4Sample { start_time: start, duration, rendering_offset: 0, is_sync, bytes: mp4::Bytes::from(bytes) },
+        )?;
+        Ok(())
+    }
+}
+
+/// NAL unit without its Annex B start code
+fn strip_start_code(nal: &[u8]) -> &[u8] {
+    nal.strip_prefix(&[0, 0, 0, 1])
+        .or_else(|| nal.strip_prefix(&[0, 0, 1]))
+        .unwrap_or(nal)
+}
@@ -0,0 +1,138 @@
+//! Topic and message-type filters for --include/--exclude and --include-type/--exclude-type
+//!
+//! Each entry is an exact name, a glob or a regex, with the same syntax as
+//! rename patterns (see [`crate::mappings::rename`]).
+
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+
+use crate::mappings::rename::pattern_regex;
+
+/// One exact, glob or regex name pattern
+#[derive(Clone, Debug)]
+pub(crate) enum NamePattern {
+    Exact(String),
+    Regex(Regex),
+}
+
+impl NamePattern {
+    pub(crate) fn parse(pattern: &str) -> Result<Self> {
+        Ok(match pattern_regex(pattern)? {
+            Some(re) => Self::Regex(re),
+            None => Self::Exact(pattern.to_string()),
+        })
+    }
+
+    pub(crate) fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Exact(exact) => exact == name,
+            Self::Regex(re) => re.is_match(name),
+        }
+    }
+}
+
+/// Include/exclude rules on topics and message types; empty include lists keep everything
+#[derive(Clone, Debug, Default)]
+pub struct MessageFilter {
+    include_topics: Vec<NamePattern>,
+    exclude_topics: Vec<NamePattern>,
+    include_types: Vec<NamePattern>,
+    exclude_types: Vec<NamePattern>,
+}
+
+impl MessageFilter {
+    pub fn new(include_topics: &[String], exclude_topics: &[String], include_types: &[String], exclude_types: &[String]) -> Result<Self> {
+        let parse = |patterns: &[String], what: &str| -> Result<Vec<NamePattern>> {
+            patterns
+                .iter()
+                .map(|p| NamePattern::parse(p).with_context(|| format!("invalid {what} filter")))
+                .collect()
+        };
+        Ok(Self {
+            include_topics: parse(include_topics, "--include")?,
+            exclude_topics: parse(exclude_topics, "--exclude")?,
+            include_types: parse(include_types, "--include-type")?,
+            exclude_types: parse(exclude_types, "--exclude-type")?,
+        })
+    }
+
+    /// Whether messages of `tp` on `topic` pass the filters
+    pub fn allows(&self, topic: &str, tp: &str) -> bool {
+        let included = |patterns: &[NamePattern], name: &str| patterns.is_empty() || patterns.iter().any(|p| p.matches(name));
+        let excluded = |patterns: &[NamePattern], name: &str| patterns.iter().any(|p| p.matches(name));
+        included(&self.include_topics, topic)
+            && included(&self.include_types, tp)
+            && !excluded(&self.exclude_topics, topic)
+            && !excluded(&self.exclude_types, tp)
+    }
+}
+
+/// Check that every --output-group rule is NAME=PATTERN with a file-name-safe NAME
+pub fn validate_output_groups(rules: &[String]) -> Result<()> {
+    for rule in rules {
+        let (name, pattern) = rule
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid output group '{}': expected NAME=PATTERN", rule))?;
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            bail!("Invalid output group name '{}': use letters, digits, '_' or '-'", name);
+        }
+        NamePattern::parse(pattern).with_context(|| format!("invalid --output-group '{rule}'"))?;
+    }
+    Ok(())
+}
+
+/// Name of the first --output-group whose pattern matches `topic`
+pub fn output_group(rules: &[String], topic: &str) -> Option<String> {
+    rules.iter().find_map(|rule| {
+        let (name, pattern) = rule.split_once('=')?;
+        NamePattern::parse(pattern)
+            .ok()
+            .filter(|p| p.matches(topic))
+            .map(|_| name.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_topic_and_type_filters() {
+        let filter = MessageFilter::new(
+            &strings(&["/camera/**", "/tf"]),
+            &strings(&["/camera/(.*)/compressed"]),
+            &[],
+            &strings(&["sensor_msgs/CameraInfo"]),
+        )
+        .unwrap();
+        assert!(filter.allows("/camera/left/image_raw", "sensor_msgs/Image"));
+        assert!(filter.allows("/tf", "tf2_msgs/TFMessage"));
+        assert!(!filter.allows("/tf_static", "tf2_msgs/TFMessage"));
+        assert!(!filter.allows("/camera/left/compressed", "sensor_msgs/CompressedImage"));
+        assert!(!filter.allows("/camera/left/camera_info", "sensor_msgs/CameraInfo"));
+
+        let filter = MessageFilter::new(&[], &[], &strings(&["sensor_msgs/*"]), &strings(&["sensor_msgs/Image"])).unwrap();
+        assert!(filter.allows("/scan", "sensor_msgs/LaserScan"));
+        assert!(!filter.allows("/image", "sensor_msgs/Image"));
+        assert!(!filter.allows("/odom", "nav_msgs/Odometry"));
+
+        assert!(MessageFilter::new(&strings(&["/camera/(.*"]), &[], &[], &[]).is_err());
+    }
+
+    #[test]
+    fn test_output_groups() {
+        let rules = strings(&["cameras=/camera/**", "lidar=/velodyne_points", "nav=/(odom|gps/fix)"]);
+        validate_output_groups(&rules).unwrap();
+        assert_eq!(output_group(&rules, "/camera/left/image_raw").as_deref(), Some("cameras"));
+        assert_eq!(output_group(&rules, "/velodyne_points").as_deref(), Some("lidar"));
+        assert_eq!(output_group(&rules, "/gps/fix").as_deref(), Some("nav"));
+        assert_eq!(output_group(&rules, "/tf"), None);
+        assert!(validate_output_groups(&strings(&["/camera/**"])).is_err());
+        assert!(validate_output_groups(&strings(&["my cams=/camera/**"])).is_err());
+        assert!(validate_output_groups(&strings(&["cams=/camera/(.*"])).is_err());
+    }
+}
@@ -0,0 +1,437 @@
+//! inspect command - Topics, types, message counts and time span of a bag
+//!
+//! The same [`BagReport`] is printed as a table or serialized as JSON for CI
+//! pipelines and data catalogs. Per-topic rates, bandwidth and the gaps between
+//! messages make it a quick health check: an interval longer than
+//! [`DROPOUT_FACTOR`] times the topic's median interval is counted as a dropout.
+
+use anyhow::{bail, Context, Result};
+use rosbag::{ChunkRecord, IndexRecord, MessageRecord, RosBag};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::rosbags_io::chunk_compressions;
+
+/// Intervals this many times longer than a topic's median interval are dropouts
+pub const DROPOUT_FACTOR: f64 = 5.0;
+
+/// Output of `inspect`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InspectFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+pub fn parse_inspect_format(s: &str) -> Result<InspectFormat> {
+    match s.to_ascii_lowercase().as_str() {
+        "text" => Ok(InspectFormat::Text),
+        "json" => Ok(InspectFormat::Json),
+        _ => bail!("Invalid inspect format '{}' (expected text or json)", s),
+    }
+}
+
+/// One topic of a bag
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct TopicReport {
+    pub topic: String,
+    #[serde(rename = "type")]
+    pub tp: String,
+    /// md5sum of the message definition, lowercase hex
+    pub md5sum: String,
+    pub count: u64,
+    #[serde(flatten)]
+    pub rates: TopicRates,
+    /// Record time of the first and last message, seconds since epoch
+    pub first_time: Option<f64>,
+    pub last_time: Option<f64>,
+    /// Whether any connection of the topic is latched, like /tf_static
+    pub latched: bool,
+    /// Connections of the topic, one per publisher and recording session
+    pub connections: Vec<ConnectionReport>,
+}
+
+/// One connection of a topic: a publisher as recorded in its connection header
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct ConnectionReport {
+    pub id: u32,
+    /// Node that published it, when recorded
+    pub callerid: Option<String>,
+    pub latched: bool,
+    /// Type and md5sum of this publisher; they differ from the topic's when
+    /// publishers disagree on the definition
+    #[serde(rename = "type")]
+    pub tp: String,
+    pub md5sum: String,
+    pub count: u64,
+}
+
+/// Rates, bandwidth and gaps of one topic
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct TopicRates {
+    /// Average publish rate in Hz over the topic's time span (0 below two messages)
+    pub frequency_hz: f64,
+    /// Rates of the longest and shortest interval between consecutive messages
+    pub min_rate_hz: f64,
+    pub max_rate_hz: f64,
+    /// Serialized message bytes, and their average over the topic's time span
+    pub bytes: u64,
+    pub bytes_per_sec: f64,
+    /// Longest interval between consecutive messages, in seconds, and the
+    /// record time it starts at
+    pub max_gap: f64,
+    pub max_gap_at: Option<f64>,
+    /// Intervals longer than [`DROPOUT_FACTOR`] times the median interval
+    pub dropouts: u64,
+}
+
+impl TopicRates {
+    /// Rates of a topic from its sorted record times in ns and payload bytes
+    pub fn from_times(times: &[u64], bytes: u64) -> Self {
+        let mut rates = TopicRates { bytes, ..Default::default() };
+        let (Some(first), Some(last)) = (times.first(), times.last()) else {
+            return rates;
+        };
+        let (first, span) = (*first as f64 / 1e9, (last - first) as f64 / 1e9);
+        if span <= 0.0 {
+            return rates;
+        }
+        rates.frequency_hz = (times.len() - 1) as f64 / span;
+        rates.bytes_per_sec = bytes as f64 / span;
+
+        let mut intervals: Vec<(f64, f64)> =
+            times.windows(2).map(|pair| ((pair[1] - pair[0]) as f64 / 1e9, pair[0] as f64 / 1e9)).collect();
+        let (max_gap, max_gap_at) = intervals.iter().copied().fold((0.0, first), |max, interval| {
+            if interval.0 > max.0 { interval } else { max }
+        });
+        rates.max_gap = max_gap;
+        rates.max_gap_at = Some(max_gap_at);
+        rates.min_rate_hz = 1.0 / max_gap;
+        // Messages recorded in the same nanosecond have no rate of their own
+        let min_interval = intervals.iter().map(|(dt, _)| *dt).filter(|dt| *dt > 0.0).reduce(f64::min);
+        rates.max_rate_hz = min_interval.map_or(0.0, |dt| 1.0 / dt);
+        intervals.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        let median = intervals[intervals.len() / 2].0;
+        if median > 0.0 {
+            rates.dropouts = intervals.iter().filter(|(dt, _)| *dt > DROPOUT_FACTOR * median).count() as u64;
+        }
+        rates
+    }
+}
+
+/// Dropout section of the inspect table for `(topic, rates)` rows, with times
+/// as offsets from `origin`; empty when no topic has dropouts
+fn dropout_summary<'a>(topics: impl IntoIterator<Item = (&'a str, &'a TopicRates)>, origin: f64) -> String {
+    let mut out = String::new();
+    for (topic, rates) in topics.into_iter().filter(|(_, rates)| rates.dropouts > 0) {
+        if out.is_empty() {
+            out.push_str(&format!("\nDropouts (intervals over {}x the median):\n", DROPOUT_FACTOR));
+        }
+        out.push_str(&format!(
+            "  {}: {} gaps, longest {:.3} s at {:.3} s\n",
+            topic,
+            rates.dropouts,
+            rates.max_gap,
+            rates.max_gap_at.map_or(0.0, |t| t - origin)
+        ));
+    }
+    out
+}
+
+/// File-level metadata and topics of a bag
+#[derive(Debug, Default, Serialize)]
+pub struct BagReport {
+    pub path: String,
+    pub version: String,
+    pub size_bytes: u64,
+    /// Record time of the first and last message, seconds since epoch
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    pub duration: f64,
+    pub message_count: u64,
+    pub connection_count: usize,
+    pub chunk_count: u64,
+    /// Chunk count per compression (`none`, `bz2`, `lz4`)
+    pub compression: BTreeMap<String, u64>,
+    pub topics: Vec<TopicReport>,
+}
+
+impl BagReport {
+    /// Summary lines, a topic table and the connections of each topic; times
+    /// are offsets from the first message
+    pub fn to_text(&self) -> String {
+        let origin = self.start.unwrap_or_default();
+        let compression: Vec<String> = self.compression.iter().map(|(kind, n)| format!("{}: {}", kind, n)).collect();
+        let mut out = format!("Bag: {}\n", self.path);
+        out.push_str(&format!(
+            "Version: {}, Size: {:.1} MB, Chunks: {} ({}), Connections: {}\n",
+            self.version,
+            self.size_bytes as f64 / 1e6,
+            self.chunk_count,
+            compression.join(", "),
+            self.connection_count
+        ));
+        out.push_str(&format!(
+            "Start (s): {:.6}, End (s): {:.6}, Duration (s): {:.6}, Total messages: {}\n\n",
+            0.0,
+            self.end.map_or(0.0, |t| t - origin),
+            self.duration,
+            self.message_count
+        ));
+        out.push_str(&format!(
+            "{:<35} {:<35} {:>7} {:>9} {:>17} {:>10} {:>10} {:>10} {:>10}\n",
+            "Topic", "Type", "Count", "Rate(Hz)", "Min-Max(Hz)", "KB/s", "MaxGap(s)", "Start(s)", "End(s)"
+        ));
+        out.push_str(&format!("{}\n", "-".repeat(159)));
+        for topic in &self.topics {
+            let rates = &topic.rates;
+            out.push_str(&format!(
+                "{:<35} {:<35} {:>7} {:>9.2} {:>17} {:>10.1} {:>10.3} {:>10.6} {:>10.6}{}\n",
+                topic.topic,
+                topic.tp,
+                topic.count,
+                rates.frequency_hz,
+                format!("{:.2}-{:.2}", rates.min_rate_hz, rates.max_rate_hz),
+                rates.bytes_per_sec / 1e3,
+                rates.max_gap,
+                topic.first_time.map_or(0.0, |t| t - origin),
+                topic.last_time.map_or(0.0, |t| t - origin),
+                if rates.dropouts > 0 { "  !" } else { "" }
+            ));
+        }
+        out.push_str("\nConnections:\n");
+        for topic in &self.topics {
+            for conn in &topic.connections {
+                let mut flags = vec![];
+                if conn.latched {
+                    flags.push("latched".to_string());
+                }
+                if conn.md5sum != topic.md5sum {
+                    flags.push(format!("{} md5sum {}", conn.tp, conn.md5sum));
+                }
+                out.push_str(&format!(
+                    "  {:<35} #{:<5} {:<35} {:>7} msgs  {}\n",
+                    topic.topic,
+                    conn.id,
+                    conn.callerid.as_deref().unwrap_or("(no callerid)"),
+                    conn.count,
+                    flags.join(", ")
+                ));
+            }
+        }
+        out.push_str(&dropout_summary(self.topics.iter().map(|t| (t.topic.as_str(), &t.rates)), origin));
+        out
+    }
+}
+
+/// Connection of the bag, from its index or chunk connection records
+struct ConnectionInfo {
+    id: u32,
+    topic: String,
+    tp: String,
+    md5sum: String,
+    callerid: Option<String>,
+    latched: bool,
+}
+
+impl ConnectionInfo {
+    fn new(conn: &rosbag::record_types::Connection) -> Self {
+        Self {
+            id: conn.id,
+            topic: conn.topic.to_string(),
+            tp: conn.tp.to_string(),
+            md5sum: conn.md5sum.iter().map(|b| format!("{:02x}", b)).collect(),
+            callerid: (!conn.caller_id.is_empty()).then(|| conn.caller_id.to_string()),
+            latched: conn.latching,
+        }
+    }
+}
+
+/// Read the connections and message record times of the bag at `path`
+pub fn scan_bag(path: &str) -> Result<BagReport> {
+    let bag = RosBag::new(path).with_context(|| format!("failed to open bag: {}", path))?;
+    let size_bytes = std::fs::metadata(path).with_context(|| format!("failed to stat bag: {}", path))?.len();
+
+    // Index connection records, completed by the ones inside chunks for bags
+    // whose index is partial
+    let mut connections: BTreeMap<u32, ConnectionInfo> = BTreeMap::new();
+    for record in bag.index_records() {
+        if let IndexRecord::Connection(conn) = record.context("failed to read bag index record")? {
+            connections.insert(conn.id, ConnectionInfo::new(&conn));
+        }
+    }
+
+    // Connection id → record times in ns and payload bytes
+    let mut messages: BTreeMap<u32, (Vec<u64>, u64)> = BTreeMap::new();
+    for (i, record) in bag.chunk_records().enumerate() {
+        let ChunkRecord::Chunk(chunk) = record.with_context(|| format!("failed to read chunk record #{}", i + 1))?
+        else {
+            continue;
+        };
+        for msg in chunk.messages() {
+            match msg.with_context(|| format!("failed to read message in chunk #{}", i + 1))? {
+                MessageRecord::Connection(conn) => {
+                    connections.entry(conn.id).or_insert_with(|| ConnectionInfo::new(&conn));
+                }
+                MessageRecord::MessageData(msg) => {
+                    let (times, bytes) = messages.entry(msg.conn_id).or_default();
+                    times.push(msg.time);
+                    *bytes += msg.data.len() as u64;
+                }
+            }
+        }
+    }
+
+    // Topic → its connections' record times and bytes; publishers of one topic
+    // interleave, so gaps are measured on the merged times
+    let mut by_topic: BTreeMap<&str, (Vec<&ConnectionInfo>, Vec<u64>, u64)> = BTreeMap::new();
+    let mut counts: BTreeMap<u32, u64> = BTreeMap::new();
+    for (id, conn) in &connections {
+        let entry = by_topic.entry(conn.topic.as_str()).or_default();
+        entry.0.push(conn);
+        if let Some((times, bytes)) = messages.get_mut(id) {
+            counts.insert(*id, times.len() as u64);
+            entry.1.append(times);
+            entry.2 += *bytes;
+        }
+    }
+    let topics: Vec<TopicReport> = by_topic
+        .into_values()
+        .map(|(conns, mut times, bytes)| {
+            times.sort_unstable();
+            let connections: Vec<ConnectionReport> = conns
+                .iter()
+                .map(|conn| ConnectionReport {
+                    id: conn.id,
+                    callerid: conn.callerid.clone(),
+                    latched: conn.latched,
+                    tp: conn.tp.clone(),
+                    md5sum: conn.md5sum.clone(),
+                    count: counts.get(&conn.id).copied().unwrap_or_default(),
+                })
+                .collect();
+            let conn = conns[0];
+            TopicReport {
+                topic: conn.topic.clone(),
+                tp: conn.tp.clone(),
+                md5sum: conn.md5sum.clone(),
+                count: times.len() as u64,
+                rates: TopicRates::from_times(&times, bytes),
+                first_time: times.first().map(|t| *t as f64 / 1e9),
+                last_time: times.last().map(|t| *t as f64 / 1e9),
+                latched: connections.iter().any(|c| c.latched),
+                connections,
+            }
+        })
+        .collect();
+
+    let start = topics.iter().filter_map(|t| t.first_time).reduce(f64::min);
+    let end = topics.iter().filter_map(|t| t.last_time).reduce(f64::max);
+    let compression = chunk_compressions(path)?;
+    Ok(BagReport {
+        path: path.to_string(),
+        version: "2.0".to_string(),
+        size_bytes,
+        start,
+        end,
+        duration: match (start, end) {
+            (Some(start), Some(end)) => end - start,
+            _ => 0.0,
+        },
+        message_count: topics.iter().map(|t| t.count).sum(),
+        connection_count: connections.len(),
+        chunk_count: compression.values().sum(),
+        compression,
+        topics,
+    })
+}
+
+/// Print the topics of the bag at `path` as a table
+pub fn inspect_bag(path: &str) -> Result<()> {
+    print_inspect(path, InspectFormat::Text)
+}
+
+/// Print the report of the bag at `path` in `format`
+pub fn print_inspect(path: &str, format: InspectFormat) -> Result<()> {
+    tracing::info!("Starting bag inspection for: {}", path);
+    let report = scan_bag(path)?;
+    match format {
+        InspectFormat::Text => print!("{}", report.to_text()),
+        InspectFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bag::{write_bag_compressed, TestCompression, TestConnection, TestMessage};
+
+    #[test]
+    fn test_scan_bag() {
+        let path = std::env::temp_dir().join(format!("bag2rrd_inspect_{}.bag", std::process::id()));
+        let connections = [
+            TestConnection { id: 0, topic: "/tf_static", tp: "tf2_msgs/TFMessage", latching: true },
+            TestConnection { id: 1, topic: "/imu", tp: "sensor_msgs/Imu", latching: false },
+            TestConnection { id: 2, topic: "/gps", tp: "sensor_msgs/NavSatFix", latching: false },
+            // A second node publishing static transforms
+            TestConnection { id: 3, topic: "/tf_static", tp: "tf2_msgs/TFMessage", latching: true },
+        ];
+        // /imu at 10 Hz over two chunks; /gps at 10 Hz with a 1 s dropout
+        let chunks = vec![
+            vec![
+                TestMessage::new(0, 100.0, vec![]),
+                TestMessage::new(3, 100.0, vec![]),
+                TestMessage::new(1, 100.0, vec![1]),
+                TestMessage::new(2, 100.0, vec![]),
+                TestMessage::new(1, 100.1, vec![1]),
+                TestMessage::new(2, 100.1, vec![]),
+                TestMessage::new(2, 100.2, vec![]),
+            ],
+            vec![
+                TestMessage::new(1, 100.2, vec![1]),
+                TestMessage::new(1, 100.3, vec![1]),
+                TestMessage::new(2, 101.2, vec![]),
+                TestMessage::new(2, 101.3, vec![]),
+            ],
+        ];
+        write_bag_compressed(&path, &connections, &chunks, TestCompression::Lz4);
+        let report = scan_bag(&path.to_string_lossy()).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        // Each message counted once
+        assert_eq!(report.message_count, 11);
+        assert_eq!(report.connection_count, 4);
+        assert_eq!(report.chunk_count, 2);
+        assert_eq!(report.compression, BTreeMap::from([("lz4".to_string(), 2)]));
+        assert!((report.duration - 1.3).abs() < 1e-6);
+        let imu = &report.topics[1];
+        assert_eq!((imu.topic.as_str(), imu.count, imu.connections.len(), imu.latched), ("/imu", 4, 1, false));
+        assert_eq!(imu.md5sum, crate::ros_msg::known_md5sum("sensor_msgs/Imu").unwrap());
+        let rates = &imu.rates;
+        assert!((rates.frequency_hz - 10.0).abs() < 1e-3, "{}", rates.frequency_hz);
+        assert!((rates.min_rate_hz - 10.0).abs() < 1e-3 && (rates.max_rate_hz - 10.0).abs() < 1e-3);
+        assert_eq!((rates.bytes, rates.dropouts), (4, 0));
+        assert!((rates.bytes_per_sec - 4.0 / 0.3).abs() < 1e-3);
+
+        let gps = &report.topics[0].rates;
+        assert_eq!(gps.dropouts, 1);
+        assert!((gps.max_gap - 1.0).abs() < 1e-6);
+        assert!((gps.max_gap_at.unwrap() - 100.2).abs() < 1e-6);
+        assert!((gps.min_rate_hz - 1.0).abs() < 1e-6);
+        assert!(report.to_text().contains("/gps: 1 gaps, longest 1.000 s at 0.200 s"));
+
+        let tf_static = &report.topics[2];
+        assert_eq!((tf_static.count, tf_static.rates.frequency_hz, tf_static.rates.dropouts), (2, 0.0, 0));
+        assert!(tf_static.latched);
+        let publishers: Vec<(u32, Option<&str>, bool, u64)> =
+            tf_static.connections.iter().map(|c| (c.id, c.callerid.as_deref(), c.latched, c.count)).collect();
+        assert_eq!(publishers, [(0, Some("/publisher_0"), true, 1), (3, Some("/publisher_3"), true, 1)]);
+        assert!(report.to_text().contains("/publisher_3"));
+
+        let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["topics"][1]["type"], "sensor_msgs/Imu");
+        assert_eq!(json["topics"][0]["dropouts"], 1);
+        assert_eq!(json["compression"]["lz4"], 2);
+    }
+}
@@ -0,0 +1,71 @@
+//! Ctrl-C handling and cancellation of conversions
+//!
+//! The first SIGINT (or SIGTERM) only sets a flag: the conversion stops reading,
+//! flushes the recording or segment it is writing, writes the stats and the
+//! manifest, then returns [`Interrupted`]. A second signal exits at once.
+//! Applications embedding the converter stop it the same way with a [`CancellationToken`].
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Error returned by a conversion stopped by Ctrl-C or its [`CancellationToken`],
+/// after its partial output was finalized
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "conversion interrupted after {processed_msgs} messages{}; the output holds the messages converted so far",
+    stopped_at.map_or(String::new(), |t| format!(" ({t:.3} s from the bag start)"))
+)]
+pub struct Interrupted {
+    /// Messages read before the stop
+    pub processed_msgs: u64,
+    /// Offset in seconds from the bag start of the last message read, if any
+    pub stopped_at: Option<f64>,
+}
+
+/// Stops a conversion from another thread, like Ctrl-C; clones share the flag
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the conversion to stop after the message it is logging
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Exit status of an interrupted conversion (128 + SIGINT, as shells report it)
+pub const EXIT_CODE: i32 = 130;
+
+/// Route SIGINT and SIGTERM to [`interrupted`] instead of killing the process
+#[cfg(unix)]
+pub fn install_handler() {
+    extern "C" fn on_signal(_: libc::c_int) {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            // Second Ctrl-C: the user does not want to wait for the flush
+            unsafe { libc::_exit(EXIT_CODE) };
+        }
+    }
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install_handler() {}
+
+/// Whether a stop was requested by Ctrl-C
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
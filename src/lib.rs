@@ -8,7 +8,7 @@
 //!
 //! - **Images**: `sensor_msgs/Image`, `sensor_msgs/CompressedImage`
 //! - **PointClouds**: `sensor_msgs/PointCloud2` with optional RGB colors
-//! - **LaserScans**: `sensor_msgs/LaserScan` as Points2D or LineStrips2D
+//! - **LaserScans**: `sensor_msgs/LaserScan`, `sensor_msgs/MultiEchoLaserScan` as Points2D or LineStrips2D
 //! - **GPS**: `sensor_msgs/NavSatFix` with ENU projection and path tracking
 //! - **IMU**: `sensor_msgs/Imu` with orientation, angular velocity, and linear acceleration
 //! - **TF**: `/tf`, `/tf_static` with time-aware transform resolution
@@ -21,61 +21,106 @@
 //! # Example
 //!
 //! ```rust,no_run
-//! use bag2rrd::{convert_bag, ConvertOptions, inspect_bag, diagnose_bag, print_schema, validate_rrd, TfMode};
+//! use bag2rrd::{convert_bag, ConvertOptions, inspect_bag, diagnose_bag, repair_bag, print_schema, validate_rrd, TfMode};
 //!
 //! // Inspect a bag file
 //! inspect_bag("input.bag")?;
 //!
-//! // Diagnose bag file corruption
-//! diagnose_bag("input.bag")?;
+//! // Diagnose bag file corruption, and reindex a bag from a crashed recorder
+//! let diagnosis = diagnose_bag("input.bag")?;
+//! if !diagnosis.healthy() {
+//!     print!("{}", diagnosis.to_text());
+//!     repair_bag("input.bag", "fixed.bag")?;
+//! }
 //!
 //! // Print supported ROS→Rerun mappings
 //! print_schema()?;
 //!
 //! // Convert a bag file
-//! let options = ConvertOptions {
-//!     bag_path: "input.bag".to_string(),
-//!     output_path: "output.rrd".to_string(),
-//!     include_topics: vec![],
-//!     exclude_topics: vec![],
-//!     start_time: None,
-//!     end_time: None,
-//!     dry_run: false,
-//!     show_progress: true,
-//!     segment_size: None,
-//!     scan_as_lines: false,
-//!     gps_origin: None,
-//!     gps_path: true,
-//!     segment_bytes: None,
-//!     flush_workers: 2,
-//!     root_frame: "world".to_string(),
-//!     frame_mappings: vec![],
-//!     topic_renames: vec![],
-//!     tf_buffer_seconds: 30.0,
-//!     tf_mode: TfMode::Nearest,
-//!     metadata: vec![],
-//!     gps_geoid: None,
-//!     tolerate_corruption: false,
-//! };
+//! let options = ConvertOptions::new("input.bag", "output.rrd")
+//!     .exclude_types(["sensor_msgs/CameraInfo"])
+//!     .segment_size(500)
+//!     .root_frame("map")
+//!     .tf_mode(TfMode::Interpolate);
 //!
 //! convert_bag(&options)?;
 //!
-//! // Validate the output RRD file
-//! validate_rrd("output.rrd")?;
+//! // Validate the output RRD file; errors and warnings come back in the report
+//! let report = validate_rrd("output.rrd")?;
+//! if !report.passed(false) {
+//!     eprint!("{}", report.to_text(false));
+//! }
 //! # Ok::<(), anyhow::Error>(())
 //! ```
 
+pub mod batch;
+pub mod blueprint;
 pub mod cli;
+pub mod compact;
+pub mod config;
 pub mod convert;
+pub mod diagnose;
+pub mod drops;
+pub mod events;
+pub mod extract;
+pub mod filter;
+pub mod inspect;
+pub mod interrupt;
+mod live;
+pub mod logging;
+pub mod manifest;
 pub mod mappings;
+pub mod memory;
+pub mod multi_bag;
+pub mod plan;
+mod progress;
+pub mod report;
+pub mod robot_model;
+pub mod ros_codec;
+pub mod ros_msg;
 pub mod rosbags_io;
 pub mod rrd_writer;
 pub mod schema;
+pub mod source;
+// Synthetic bags for the tests and, with the test-bags feature, the benches
+#[cfg(any(test, feature = "test-bags"))]
+#[doc(hidden)]
+pub mod test_bag;
+pub mod tf_analysis;
+pub mod tf_tree;
+pub mod timeline;
 pub mod validate;
+pub mod verify;
+pub mod watch;
+pub mod world;
 
 // Re-export main types for convenience
-pub use convert::{convert_bag, ConvertOptions};
-pub use mappings::tf::{TfGraph, TfMode, TfSample};
-pub use rosbags_io::{diagnose_bag, inspect_bag};
-pub use schema::print_schema;
-pub use validate::validate_rrd;
\ No newline at end of file
+pub use batch::{convert_batch, BagResult, BagStatus, BatchOptions, BatchReport};
+pub use blueprint::{save_blueprint, BlueprintLayout};
+pub use convert::{
+    convert_bag, convert_bag_with, ConvertOptions, MemoryOutput, OutputTarget, TimestampSource, TopicConfig,
+    DEFAULT_VIEWER_URL, DEFAULT_WEB_GRPC_PORT, DEFAULT_WEB_PORT,
+};
+pub use diagnose::{diagnose_bag, repair_bag, DiagnoseReport, IndexStatus, RepairSummary};
+pub use drops::TopicDrops;
+pub use events::{ConvertEvent, ConvertStats, ProgressHook};
+pub use extract::images::{extract_images, ImageExtract, ImageFormat};
+pub use extract::pointclouds::{extract_pointclouds, CloudFormat, PointCloudExtract};
+pub use extract::table::{export_table, TableExport, TableFormat};
+pub use interrupt::{CancellationToken, Interrupted};
+pub use mappings::camera::CameraGroup;
+pub use mappings::colormap::Colormap;
+pub use mappings::images::{ImageColormap, ImageEncoding, TopicSetting};
+pub use mappings::laserscan::{MultiEchoMode, ScanColorBy};
+pub use mappings::registry::{Mapped, MapperContext, MapperRegistry, MessageKind, MessageMapper};
+pub use mappings::tf::{TfAuthority, TfGraph, TfMode, TfSample, TransformStamped};
+pub use plan::{ConversionPlan, TopicPlan};
+pub use report::{ConvertReport, FailureRate, UnmappedReason, UnmappedType};
+pub use inspect::{inspect_bag, scan_bag, BagReport, ConnectionReport, InspectFormat, TopicRates, TopicReport};
+pub use schema::{print_schema, print_schema_as, SchemaFormat};
+pub use source::rosbridge::RosbridgeEncoding;
+pub use source::{open_source, InputFormat, LiveSource, RecordingSource};
+pub use tf_analysis::TfThresholds;
+pub use validate::{validate_rrd, Issue, IssueCategory, Severity, ValidationReport};
+pub use verify::{verify, TopicCheck, TopicStatus, VerifyReport};
+pub use watch::{watch_dir, WatchOptions};
\ No newline at end of file
@@ -0,0 +1,278 @@
+//! Live conversion of a running ROS system
+//!
+//! `bag2rrd convert ws://robot:9090 live.rrd` (rosbridge) or `bag2rrd convert
+//! ros1://robot:11311 live.rrd` (a ROS1 master, with the `ros1-live` feature)
+//! subscribes to the topics kept by the topic and type filters, then maps every
+//! message as it arrives, with the same mappers and output targets as a bag,
+//! until Ctrl-C or the `--end` offset. Topics advertised later are picked up as
+//! they appear. Time offsets are from the first message received.
+
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::convert::{
+    finish_recording, open_recording, send_properties, ConvertOptions, KeptMessage, MessageLogger, Provenance,
+    RateLimiter, TopicConfig,
+};
+use crate::events::{ConvertEvent, ConvertStats, PROGRESS_INTERVAL};
+use crate::filter::MessageFilter;
+use crate::mappings::images::{decode_compressed, decode_image, ImageOptions};
+use crate::mappings::registry::MapperRegistry;
+use crate::mappings::rename::{EntityRenames, RenameRules};
+use crate::memory::MemoryBudget;
+use crate::multi_bag::ConnectionMap;
+use crate::report::finish_report;
+use crate::robot_model::RobotModel;
+use crate::ros_msg::TypeInfo;
+use crate::source::rosbridge::{is_rosbridge_url, Rosbridge};
+use crate::source::LiveSource;
+use crate::world::WorldAnnotations;
+
+/// How often the topic list is fetched again for topics advertised since
+const TOPIC_POLL: Duration = Duration::from_secs(5);
+
+/// Convert the messages received from the live source at `options.bag_path`
+pub(crate) fn convert_live(
+    options: &ConvertOptions,
+    mut mappers: MapperRegistry,
+    robot: &RobotModel,
+    world: &WorldAnnotations,
+    renames: &EntityRenames,
+) -> Result<()> {
+    if !options.extra_bags.is_empty() {
+        bail!("a live input cannot be merged with other inputs");
+    }
+    if options.resume
+        || options.segment_size.is_some()
+        || options.segment_bytes.is_some()
+        || options.segment_seconds.is_some()
+        || options.split_topics
+        || !options.output_groups.is_empty()
+    {
+        bail!("segmentation, --resume and split outputs are not supported with a live input");
+    }
+    if options.sim_time {
+        options.warn("--sim-time is ignored with a live input; using receive time".to_string());
+    }
+    let filter = MessageFilter::new(
+        &options.include_topics,
+        &options.exclude_topics,
+        &options.include_types,
+        &options.exclude_types,
+    )?;
+    let mut source = connect(options)?;
+
+    let mut logger = MessageLogger::new(options)?;
+    let mut conns = ConnectionMap::default();
+    let mut topic_configs: HashMap<u32, TopicConfig> = HashMap::new();
+    subscribe_topics(&mut *source, &filter, options, &renames.topics, &mut conns, &mut topic_configs)?;
+    let mut last_poll = Instant::now();
+
+    let budget = options.max_memory.map(MemoryBudget::new);
+    let mut rec: Option<rerun::RecordingStream> = None;
+    let provenance = Provenance::of_source(options);
+    let mut memory_sink = None;
+    let mut stats = ConvertStats::default();
+    let mut rate_limiter = RateLimiter::default();
+    let mut image_frames: HashMap<String, u64> = HashMap::new();
+    // Receive time of the first message, in seconds
+    let mut start_s: Option<f64> = None;
+    let mut stopped = false;
+    let mut stopped_at: Option<f64> = None;
+
+    loop {
+        if options.cancelled() {
+            stopped = true;
+            break;
+        }
+        if last_poll.elapsed() >= TOPIC_POLL {
+            subscribe_topics(&mut *source, &filter, options, &renames.topics, &mut conns, &mut topic_configs)?;
+            last_poll = Instant::now();
+        }
+        let Some(msg) = source.next_message()? else {
+            continue;
+        };
+        stats.total_msgs += 1;
+        stats.processed_msgs += 1;
+        if stats.processed_msgs.is_multiple_of(PROGRESS_INTERVAL) {
+            options.emit(ConvertEvent::Progress(stats.clone()));
+        }
+        let receive_s = msg.time_ns as f64 / 1_000_000_000.0;
+        let ts_rel = receive_s - *start_s.get_or_insert(receive_s);
+        stopped_at = Some(ts_rel);
+        if options.end_time.is_some_and(|end| ts_rel > end) {
+            break;
+        }
+        let Some(shared) = conns.get(0, msg.topic) else {
+            continue;
+        };
+        let Some((topic, tp)) = conns.connections.get(&shared) else {
+            continue;
+        };
+        let Some(topic_config) = topic_configs.get(&shared) else {
+            stats.filtered_out += 1;
+            continue;
+        };
+        if options.start_time.is_some_and(|start| ts_rel < start) {
+            continue;
+        }
+        if !conns.latched.contains(&shared) && !rate_limiter.keep(topic, topic_config.max_rate, receive_s) {
+            stats.rate_limited += 1;
+            logger.rate_limited(shared, topic, tp, conns.definitions.get(&shared), &msg.data);
+            continue;
+        }
+        if options.dry_run {
+            stats.kept_msgs += 1;
+            continue;
+        }
+        if rec.is_none() {
+            let new_rec = open_recording(options, &options.output_path, &provenance, budget, &mut memory_sink)?;
+            send_properties(options, &new_rec, &provenance)?;
+            robot.log(&new_rec, options, &renames.frames)?;
+            world.log(&new_rec, conns.has_type("sensor_msgs/NavSatFix"))?;
+            rec = Some(new_rec);
+        }
+        let Some(rec_ref) = rec.as_ref() else {
+            continue;
+        };
+
+        let info = conns.definitions.get(&shared);
+        // Images are decoded here rather than ahead on a pool: messages come one at a time
+        let decoded_image = match tp.as_str() {
+            "sensor_msgs/Image" | "sensor_msgs/CompressedImage" => mappers
+                .get(topic, tp)
+                .filter(|mapper| mapper.decodes_images() && !logger.mismatched(options, *mapper, shared, topic, tp, info))
+                .and_then(|_| {
+                    let frame = image_frames.entry(topic.clone()).or_insert(0);
+                    let keep = frame.is_multiple_of(topic_config.image_every_nth);
+                    *frame += 1;
+                    keep.then(|| {
+                        let opts = ImageOptions {
+                            colormap: topic_config.image_colormap,
+                            value_range: options.image_value_range,
+                            crop: topic_config.image_crop,
+                            scale: topic_config.image_scale,
+                            encoding: options.image_encoding,
+                            compressed_passthrough: options.compressed_passthrough,
+                        };
+                        if tp == "sensor_msgs/CompressedImage" {
+                            decode_compressed(&msg.data, &opts)
+                        } else {
+                            decode_image(&msg.data, &opts)
+                        }
+                    })
+                }),
+            _ => None,
+        };
+        logger.log(
+            rec_ref,
+            options,
+            renames,
+            &mut mappers,
+            &mut stats,
+            KeptMessage {
+                conn: shared,
+                topic,
+                tp,
+                data: &msg.data,
+                topic_config,
+                ts_rel,
+                receive_ts: ts_rel,
+                time_base: start_s.unwrap_or_default(),
+                bag_time: receive_s,
+                latched: conns.latched.contains(&shared),
+                type_info: info,
+                decoded_image,
+            },
+        )?;
+    }
+
+    logger.finish(options, &mut mappers)?;
+    tracing::info!(
+        received = stats.total_msgs,
+        kept_msgs = stats.kept_msgs,
+        skipped_types = stats.skipped_types,
+        undecodable = source.skipped(),
+        filtered_out = stats.filtered_out,
+        "live conversion stats"
+    );
+    if let Some(rec_single) = rec.take() {
+        finish_recording(options, rec_single, memory_sink.take(), stats.raw_bytes, stopped)?;
+    } else if !options.dry_run {
+        tracing::warn!("no messages kept; nothing to flush");
+    }
+    if !options.dry_run {
+        stats.failed_msgs = logger.unmapped.failed_msgs();
+        finish_report(options, &stats, &logger.unmapped, &logger.drops)?;
+        logger.unmapped.check_failures(options)?;
+    }
+    let processed_msgs = stats.processed_msgs;
+    options.emit(ConvertEvent::Finished(stats));
+    if stopped {
+        return Err(crate::interrupt::Interrupted { processed_msgs, stopped_at }.into());
+    }
+    Ok(())
+}
+
+/// Connect to the rosbridge server or ROS1 master of the input
+fn connect(options: &ConvertOptions) -> Result<Box<dyn LiveSource>> {
+    if is_rosbridge_url(&options.bag_path) {
+        let bridge = Rosbridge::connect(&options.bag_path, options.rosbridge_encoding)?;
+        tracing::info!(url = %options.bag_path, "connected to rosbridge");
+        return Ok(Box::new(bridge));
+    }
+    connect_ros1(&options.bag_path)
+}
+
+#[cfg(feature = "ros1-live")]
+fn connect_ros1(input: &str) -> Result<Box<dyn LiveSource>> {
+    let node = crate::source::tcpros::Ros1Node::connect(input)?;
+    tracing::info!(master = %node.master_uri(), "registered with the ROS master");
+    Ok(Box::new(node))
+}
+
+#[cfg(not(feature = "ros1-live"))]
+fn connect_ros1(input: &str) -> Result<Box<dyn LiveSource>> {
+    bail!("{}: ROS1 master inputs need bag2rrd built with the `ros1-live` feature", input)
+}
+
+/// Subscribe to the published topics the filters keep that are not subscribed yet
+fn subscribe_topics(
+    source: &mut dyn LiveSource,
+    filter: &MessageFilter,
+    options: &ConvertOptions,
+    topic_renames: &RenameRules,
+    conns: &mut ConnectionMap,
+    topic_configs: &mut HashMap<u32, TopicConfig>,
+) -> Result<()> {
+    for (name, ros_type) in source.list_topics()? {
+        let tp = crate::source::definitions::ros1_type(&ros_type);
+        let subscribed = conns.connections.values().any(|(topic, _)| *topic == name);
+        if subscribed || !filter.allows(&name, &tp) {
+            continue;
+        }
+        let Some(topic) = source.subscribe(&name, &ros_type)? else {
+            continue;
+        };
+        let shared = conns.insert(0, topic.id, &topic.name, &topic.tp, topic.latching);
+        conns.define(shared, TypeInfo { md5sum: topic.md5sum, definition: topic.definition });
+        topic_configs.insert(shared, TopicConfig::resolve(options, topic_renames, &topic.name));
+        tracing::info!(topic = %name, tp = %tp, "subscribed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_bag::convert_options;
+
+    #[test]
+    fn test_rosbridge_input_options() {
+        let options = convert_options(&["ws://127.0.0.1:9", "live.rrd", "--rosbridge-encoding", "json", "--segment-size", "10"]).unwrap();
+        assert_eq!(options.rosbridge_encoding, crate::RosbridgeEncoding::Json);
+        // Rejected before connecting
+        let err = crate::convert_bag(&options).unwrap_err();
+        assert!(err.to_string().contains("not supported with a live input"), "{err:#}");
+    }
+}
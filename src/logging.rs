@@ -0,0 +1,63 @@
+//! Log output of the bag2rrd binary: level from -v/-q, text or JSON lines on stderr
+//!
+//! Reports (inspect, schema, validate, ...) go to stdout; everything logged
+//! through `tracing` goes to stderr, so both can be redirected separately.
+//! `RUST_LOG` overrides the -v/-q level, e.g. `RUST_LOG=bag2rrd::messages=trace`
+//! for one event per converted message (topic, type, time) and nothing else.
+
+use anyhow::{bail, Result};
+use tracing_subscriber::EnvFilter;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, with the event fields
+    Json,
+}
+
+pub fn parse_log_format(s: &str) -> Result<LogFormat> {
+    match s.to_ascii_lowercase().as_str() {
+        "text" => Ok(LogFormat::Text),
+        "json" => Ok(LogFormat::Json),
+        _ => bail!("Invalid log format '{}' (expected text or json)", s),
+    }
+}
+
+/// Filter directives for `verbose` -v and `quiet` -q flags: info by default,
+/// debug then trace of bag2rrd with -v, -vv, warnings then errors only with -q, -qq
+pub fn filter_directives(verbose: u8, quiet: u8) -> &'static str {
+    match (verbose, quiet) {
+        (0, 0) => "info",
+        (_, 1) => "warn",
+        (_, q) if q > 1 => "error",
+        (1, _) => "info,bag2rrd=debug",
+        _ => "info,bag2rrd=trace",
+    }
+}
+
+/// Install the global subscriber; `RUST_LOG`, when set, wins over the flags
+pub fn init(verbose: u8, quiet: u8, format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(filter_directives(verbose, quiet)));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr);
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_directives() {
+        assert_eq!(filter_directives(0, 0), "info");
+        assert_eq!(filter_directives(1, 0), "info,bag2rrd=debug");
+        assert_eq!(filter_directives(3, 0), "info,bag2rrd=trace");
+        assert_eq!(filter_directives(0, 1), "warn");
+        assert_eq!(filter_directives(0, 2), "error");
+        assert_eq!(parse_log_format("JSON").unwrap(), LogFormat::Json);
+        assert!(parse_log_format("xml").is_err());
+    }
+}
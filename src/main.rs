@@ -1,106 +1,88 @@
-use anyhow::{anyhow, Result};
-use clap::Parser;
-use tracing_subscriber::{EnvFilter, fmt};
+use anyhow::Result;
+use clap::{CommandFactory, FromArgMatches};
 
-mod cli;
-mod convert;
-mod mappings;
-mod rosbags_io;
-mod rrd_writer;
-mod schema;
-mod validate;
-use cli::{Cli, Commands};
-use mappings::tf::parse_tf_mode;
-
-fn parse_pointcloud_rotation(rotation_str: &str) -> Result<[f64; 3]> {
-    let parts: Vec<&str> = rotation_str.split(',').collect();
-    if parts.len() != 3 {
-        return Err(anyhow!("The rotation must contain exactly 3 values separated by commas (roll,pitch,yaw)"));
-    }
-    
-    let roll = parts[0].trim().parse::<f64>()
-        .map_err(|_| anyhow!("Failed to parse roll: '{}'", parts[0]))?;
-    let pitch = parts[1].trim().parse::<f64>()
-        .map_err(|_| anyhow!("Failed to parse pitch: '{}'", parts[1]))?;
-    let yaw = parts[2].trim().parse::<f64>()
-        .map_err(|_| anyhow!("Failed to parse yaw: '{}'", parts[2]))?;
-    
-    Ok([roll, pitch, yaw])
-}
-
-fn init_tracing() {
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    fmt().with_env_filter(filter).init();
-}
+use bag2rrd::cli::{Cli, Commands, ExportCommands, ExtractCommands};
+use bag2rrd::config::ConvertConfig;
+use bag2rrd::inspect::parse_inspect_format;
+use bag2rrd::{batch, convert, diagnose, extract, inspect, interrupt, logging, schema, tf_analysis, tf_tree, validate, verify, watch, BagStatus, TfThresholds};
 
 fn main() -> Result<()> {
-    init_tracing();
-    let cli = Cli::parse();
+    let mut matches = Cli::command().get_matches();
+    // Re-parse with the config file values as flag defaults so the command line wins
+    if let Some(path) = matches.subcommand_matches("convert").and_then(|m| m.get_one::<String>("config")) {
+        let command = ConvertConfig::load(path)?.apply(Cli::command())?;
+        matches = command.get_matches();
+    }
+    let cli = Cli::from_arg_matches(&matches)?;
+    logging::init(cli.verbose, cli.quiet, logging::parse_log_format(&cli.log_format)?);
     match cli.command {
-        Commands::Inspect { bag } => rosbags_io::inspect_bag(&bag),
-        Commands::Convert {
-            bag,
-            out,
-            include,
-            exclude,
-            start,
-            end,
-            dry_run,
-            progress,
-            segment_size,
-            scan_as_lines,
-            gps_origin,
-            gps_path,
-            segment_bytes,
-            flush_workers,
-            root_frame,
-            map_frame,
-            topic_rename,
-            tf_buffer_seconds,
-            tf_mode,
-            metadata,
-            gps_geoid,
-            tolerate_corruption,
-            pointcloud_rotation,
-        } => {
-            let options = convert::ConvertOptions {
-                bag_path: bag,
-                output_path: out,
-                include_topics: include,
-                exclude_topics: exclude,
-                start_time: start,
-                end_time: end,
-                dry_run,
-                show_progress: progress,
-                segment_size,
-                scan_as_lines,
-                gps_origin,
-                gps_path,
-                segment_bytes,
-                flush_workers,
-                root_frame,
-                frame_mappings: map_frame,
-                topic_renames: topic_rename,
-                tf_buffer_seconds,
-                tf_mode: parse_tf_mode(&tf_mode)?,
-                metadata,
-                gps_geoid,
-                tolerate_corruption,
-                pointcloud_rotation: match pointcloud_rotation {
-                    Some(rotation_str) => Some(parse_pointcloud_rotation(&rotation_str)?),
-                    None => None,
-                },
-            };
-            convert::convert_bag(&options)
+        Commands::Inspect { bag, format } => inspect::print_inspect(&bag, parse_inspect_format(&format)?),
+        Commands::Convert(args) => {
+            interrupt::install_handler();
+            match convert::convert_bag(&args.into_options()?) {
+                Err(e) if e.is::<interrupt::Interrupted>() => {
+                    tracing::warn!("{e}");
+                    std::process::exit(interrupt::EXIT_CODE)
+                }
+                result => result,
+            }
+        }
+        Commands::Batch(args) => {
+            interrupt::install_handler();
+            let (batch, template) = args.into_batch()?;
+            let report = batch::convert_batch(&batch, &template)?;
+            print!("{}", report.to_text());
+            if report.count(BagStatus::Interrupted) > 0 {
+                std::process::exit(interrupt::EXIT_CODE)
+            }
+            if report.count(BagStatus::Failed) > 0 {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Commands::Watch(args) => {
+            interrupt::install_handler();
+            let (watch, template) = args.into_watch()?;
+            watch::watch_dir(&watch, &template)?;
+            Ok(())
+        }
+        Commands::Schema { format } => {
+            schema::print_schema_as(schema::parse_schema_format(&format)?)
+        }
+        Commands::TfTree { bag, dot, json } => {
+            tf_tree::print_tf_tree(&bag, dot.as_deref(), json.as_deref())
+        }
+        Commands::AnalyzeTf { bag, jump_threshold, rotation_threshold, json } => {
+            let thresholds = TfThresholds { max_translation: jump_threshold, max_rotation_deg: rotation_threshold };
+            tf_analysis::print_tf_analysis(&bag, thresholds, json.as_deref())
+        }
+        Commands::Validate { rrd, strict } => {
+            let report = validate::validate_rrd(&rrd)?;
+            print!("{}", report.to_text(strict));
+            if !report.passed(strict) {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Commands::Verify { bag, rrd, json, convert_args } => {
+            let options = bag2rrd::cli::convert_template(convert_args)?;
+            if !verify::print_verify(&bag, &rrd, &options, json.as_deref())?.passed() {
+                std::process::exit(1);
+            }
+            Ok(())
         }
-        Commands::Schema {} => {
-            schema::print_schema()
+        Commands::Diagnose { bag, repair } => diagnose::print_diagnose(&bag, repair.as_deref()),
+        Commands::Extract { what: ExtractCommands::Pointclouds(args) } => {
+            let (bag, out) = (args.bag.clone(), args.out.clone());
+            extract::pointclouds::extract_pointclouds(&bag, &out, &args.into_extract()?)
         }
-        Commands::Validate { rrd } => {
-            validate::validate_rrd(&rrd)
+        Commands::Extract { what: ExtractCommands::Images(args) } => {
+            let (bag, out) = (args.bag.clone(), args.out.clone());
+            extract::images::extract_images(&bag, &out, &args.into_extract()?)
         }
-        Commands::Diagnose { bag } => {
-            rosbags_io::diagnose_bag(&bag)
+        Commands::Export { what: ExportCommands::Table(args) } => {
+            let (bag, out) = (args.bag.clone(), args.out.clone());
+            extract::table::export_table(&bag, &out, &args.into_export()?)
         }
     }
 }
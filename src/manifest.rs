@@ -0,0 +1,189 @@
+//! Manifest of a segmented conversion: `<stem>_manifest.json` next to the parts
+//!
+//! Lists each part's file, bag-time range, per-topic message counts and size, so
+//! the part covering a given moment can be found without opening every RRD.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SegmentManifest {
+    /// Input bag
+    pub bag: String,
+    pub segments: Vec<SegmentEntry>,
+}
+
+/// One output part
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SegmentEntry {
+    pub part: u32,
+    /// File name, relative to the manifest
+    pub file: String,
+    /// Record time of the first and last message, in seconds since the epoch
+    pub start_time: f64,
+    pub end_time: f64,
+    pub start_utc: String,
+    pub end_utc: String,
+    pub messages: u64,
+    /// Messages per topic
+    pub topics: BTreeMap<String, u64>,
+    pub bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+impl SegmentManifest {
+    /// `<dir>/<stem>_manifest.json` for output `<dir>/<stem>.rrd`
+    pub fn path_for(output_path: &str) -> PathBuf {
+        let path = Path::new(output_path);
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("out");
+        path.with_file_name(format!("{stem}_manifest.json"))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("failed to open manifest {}", path.display()))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("invalid manifest {}", path.display()))
+    }
+
+    /// Write the manifest through a temporary file so readers never see half of it
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let file = std::fs::File::create(&tmp)?;
+        serde_json::to_writer_pretty(&file, self)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Parts from the first one on whose files are complete, up to the first
+    /// missing or truncated one; `dir` holds the manifest
+    pub fn completed_parts(&self, dir: &Path) -> Vec<SegmentEntry> {
+        let mut segments = self.segments.clone();
+        segments.sort_by_key(|s| s.part);
+        segments
+            .into_iter()
+            .enumerate()
+            .take_while(|(i, s)| {
+                s.part as usize == i + 1 && std::fs::metadata(dir.join(&s.file)).is_ok_and(|m| m.len() == s.bytes)
+            })
+            .map(|(_, s)| s)
+            .collect()
+    }
+
+    /// The part holding record time `t` (seconds since the epoch)
+    pub fn segment_at(&self, t: f64) -> Option<&SegmentEntry> {
+        self.segments.iter().find(|s| s.start_time <= t && t <= s.end_time)
+    }
+}
+
+/// Contents of the part being written
+#[derive(Clone, Debug, Default)]
+pub struct SegmentContents {
+    pub start_time: Option<f64>,
+    pub end_time: Option<f64>,
+    pub messages: u64,
+    pub topics: BTreeMap<String, u64>,
+}
+
+impl SegmentContents {
+    /// Count a message logged into the part at record time `t`
+    pub fn record(&mut self, topic: &str, t: f64) {
+        self.start_time = Some(self.start_time.map_or(t, |s| s.min(t)));
+        self.end_time = Some(self.end_time.map_or(t, |e| e.max(t)));
+        self.messages += 1;
+        *self.topics.entry(topic.to_string()).or_default() += 1;
+    }
+
+    pub fn into_entry(self, part: u32, file: String, bytes: u64, sha256: Option<String>) -> SegmentEntry {
+        let start_time = self.start_time.unwrap_or_default();
+        let end_time = self.end_time.unwrap_or(start_time);
+        SegmentEntry {
+            part,
+            file,
+            start_time,
+            end_time,
+            start_utc: format_utc(start_time),
+            end_utc: format_utc(end_time),
+            messages: self.messages,
+            topics: self.topics,
+            bytes,
+            sha256,
+        }
+    }
+}
+
+/// RFC 3339 UTC timestamp with milliseconds, e.g. "2024-05-03T14:32:05.120Z"
+pub fn format_utc(secs: f64) -> String {
+    let millis = (secs * 1000.0).round() as i64;
+    let (days, ms_of_day) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        ms_of_day / 3_600_000,
+        ms_of_day / 60_000 % 60,
+        ms_of_day / 1000 % 60,
+        ms_of_day % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_utc() {
+        assert_eq!(format_utc(0.0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_utc(1_714_746_725.12), "2024-05-03T14:32:05.120Z");
+        assert_eq!(format_utc(951_782_400.0), "2000-02-29T00:00:00.000Z");
+    }
+
+    #[test]
+    fn test_manifest_round_trip_and_lookup() {
+        let mut first = SegmentContents::default();
+        first.record("/camera", 100.5);
+        first.record("/imu", 100.2);
+        first.record("/camera", 101.0);
+        let mut second = SegmentContents::default();
+        second.record("/camera", 101.5);
+        let manifest = SegmentManifest {
+            bag: "in.bag".to_string(),
+            segments: vec![
+                first.into_entry(1, "out_part0001.rrd".to_string(), 10, None),
+                second.into_entry(2, "out_part0002.rrd".to_string(), 20, Some("ab".to_string())),
+            ],
+        };
+        assert_eq!(manifest.segments[0].start_time, 100.2);
+        assert_eq!(manifest.segments[0].topics["/camera"], 2);
+        assert_eq!(manifest.segment_at(100.7).map(|s| s.part), Some(1));
+        assert_eq!(manifest.segment_at(101.5).map(|s| s.part), Some(2));
+        assert!(manifest.segment_at(101.2).is_none());
+
+        let path = std::env::temp_dir().join(format!("bag2rrd_manifest_{}.json", std::process::id()));
+        manifest.write(&path).unwrap();
+        assert_eq!(SegmentManifest::read(&path).unwrap(), manifest);
+        std::fs::remove_file(&path).ok();
+
+        // Only part 1 exists with the listed size
+        let dir = std::env::temp_dir().join(format!("bag2rrd_manifest_parts_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("out_part0001.rrd"), [0; 10]).unwrap();
+        std::fs::write(dir.join("out_part0002.rrd"), [0; 5]).unwrap();
+        let parts: Vec<u32> = manifest.completed_parts(&dir).iter().map(|s| s.part).collect();
+        assert_eq!(parts, [1]);
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(SegmentManifest::path_for("runs/out.rrd"), Path::new("runs/out_manifest.json"));
+    }
+}
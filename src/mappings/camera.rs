@@ -0,0 +1,167 @@
+//! Camera rig grouping: images, Pinhole and TF of related cameras under one entity subtree
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::mappings::depth::{parse_camera_info, CameraInfo};
+use crate::mappings::images::{ImageCrop, TopicSetting};
+use crate::mappings::tf::{TfGraph, TfMode};
+
+/// Topics under `prefix` are cameras (one per first path component) logged under `entity`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CameraGroup {
+    pub prefix: String,
+    pub entity: String,
+}
+
+/// Parse "PREFIX" or "PREFIX=/rr/path", e.g. "/stereo" or "/=/cameras"
+pub fn parse_camera_group(s: &str) -> Result<CameraGroup> {
+    let (prefix, entity) = s.split_once('=').unwrap_or((s, s));
+    let prefix = format!("/{}", prefix.trim().trim_matches('/'));
+    let entity = format!("/{}", entity.trim().trim_matches('/'));
+    if entity == "/" {
+        return Err(anyhow!("Camera group needs a non-root entity path: {}", s));
+    }
+    Ok(CameraGroup { prefix, entity })
+}
+
+/// Routes camera topics into their group and logs Pinhole + TF per camera
+#[derive(Debug, Default)]
+pub struct CameraRig {
+    groups: Vec<CameraGroup>,
+    cameras: HashMap<String, CameraInfo>,
+}
+
+impl CameraRig {
+    pub fn new(groups: Vec<CameraGroup>) -> Self {
+        Self {
+            groups,
+            cameras: HashMap::new(),
+        }
+    }
+
+    /// Camera entity and remaining sub-path for a topic, e.g.
+    /// "/stereo/left/image_raw" → ("/stereo/left", "image_raw")
+    pub fn camera_for_topic(&self, topic: &str) -> Option<(String, String)> {
+        let topic = format!("/{}", topic.trim_start_matches('/'));
+        // Longest prefix wins so nested groups can override a catch-all
+        let group = self
+            .groups
+            .iter()
+            .filter(|g| g.prefix == "/" || topic.starts_with(&format!("{}/", g.prefix)))
+            .max_by_key(|g| g.prefix.len())?;
+        let rest = topic[group.prefix.len()..].trim_start_matches('/');
+        let (camera, sub_path) = rest.split_once('/')?;
+        Some((format!("{}/{}", group.entity, camera), sub_path.to_string()))
+    }
+
+    /// Entity path for an image topic of a grouped camera
+    pub fn image_entity(&self, topic: &str) -> Option<String> {
+        self.camera_for_topic(topic)
+            .map(|(camera, sub_path)| format!("{camera}/{sub_path}"))
+    }
+
+    /// --image-crop of a camera's images: the one naming an image topic of the camera,
+    /// else the one of every topic
+    fn image_crop(&self, camera: &str, crops: &[TopicSetting<ImageCrop>]) -> Option<ImageCrop> {
+        crops
+            .iter()
+            .find(|s| s.topic.as_deref().and_then(|t| self.camera_for_topic(t)).is_some_and(|(c, _)| c == camera))
+            .or_else(|| crops.iter().find(|s| s.topic.is_none()))
+            .map(|s| s.value)
+    }
+
+    /// Log the camera's Pinhole and its pose in the root frame from a CameraInfo message;
+    /// with `crops` the Pinhole is that of the cropped images
+    #[allow(clippy::too_many_arguments)]
+    pub fn camera_info_to_rerun(
+        &mut self,
+        rec: &rerun::RecordingStream,
+        topic: &str,
+        ts: f64,
+        payload: &[u8],
+        crops: &[TopicSetting<ImageCrop>],
+        root_frame: &str,
+        tf_graph: Option<&TfGraph>,
+        tf_mode: TfMode,
+    ) -> Result<()> {
+        let Some((camera, _)) = self.camera_for_topic(topic) else {
+            return Ok(());
+        };
+        let info = parse_camera_info(payload)?;
+        rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
+
+        if self.cameras.get(&camera) != Some(&info) && info.k[0] > 0.0 && info.k[4] > 0.0 {
+            let mut k = info.k.map(|v| v as f32);
+            let mut resolution = [info.width as f32, info.height as f32];
+            // A cropped image has its principal point moved by the crop origin
+            if let Some(crop) = self.image_crop(&camera, crops).and_then(|crop| crop.within(info.width, info.height)) {
+                k[2] -= crop.x as f32;
+                k[5] -= crop.y as f32;
+                resolution = [crop.width as f32, crop.height as f32];
+            }
+            // Columns of the row-major K matrix
+            let image_from_camera = rerun::datatypes::Mat3x3::from([[k[0], k[3], k[6]], [k[1], k[4], k[7]], [k[2], k[5], k[8]]]);
+            let pinhole = rerun::archetypes::Pinhole::new(image_from_camera)
+                .with_resolution(resolution)
+                .with_camera_xyz(rerun::components::ViewCoordinates::RDF);
+            rec.log(camera.as_str(), &pinhole)?;
+        }
+
+        let pose = match tf_graph {
+            Some(tf) if !info.frame_id.is_empty() && info.frame_id != root_frame => {
+                tf.resolve_pose(root_frame, &info.frame_id, ts, tf_mode)
+            }
+            _ => None,
+        };
+        if let Some(iso) = pose {
+            let t = iso.translation.vector;
+            let q = iso.rotation.quaternion();
+            let transform = rerun::archetypes::Transform3D::from_translation_rotation(
+                [t.x as f32, t.y as f32, t.z as f32],
+                rerun::datatypes::Quaternion::from_xyzw([q.i as f32, q.j as f32, q.k as f32, q.w as f32]),
+            );
+            rec.log(camera.as_str(), &transform)?;
+        }
+        self.cameras.insert(camera, info);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_crop_of_camera() {
+        let rig = CameraRig::new(vec![parse_camera_group("/stereo").unwrap()]);
+        let left = crate::mappings::images::parse_image_crop("/stereo/left/image_raw=100,50,640,480").unwrap();
+        let every = crate::mappings::images::parse_image_crop("0,0,320,240").unwrap();
+        assert_eq!(rig.image_crop("/stereo/left", std::slice::from_ref(&left)), Some(left.value));
+        assert_eq!(rig.image_crop("/stereo/right", std::slice::from_ref(&left)), None);
+        assert_eq!(rig.image_crop("/stereo/right", &[left.clone(), every.clone()]), Some(every.value));
+        // The Pinhole keeps the part of the rectangle inside the camera's image
+        assert_eq!(left.value.within(700, 480), Some(ImageCrop { x: 100, y: 50, width: 600, height: 430 }));
+        assert_eq!(left.value.within(64, 48), None);
+    }
+
+    #[test]
+    fn test_camera_for_topic() {
+        let rig = CameraRig::new(vec![
+            parse_camera_group("/stereo").unwrap(),
+            parse_camera_group("/=/cameras").unwrap(),
+        ]);
+        assert_eq!(
+            rig.camera_for_topic("/stereo/left/image_raw"),
+            Some(("/stereo/left".to_string(), "image_raw".to_string()))
+        );
+        assert_eq!(
+            rig.image_entity("cam0/image_raw/compressed"),
+            Some("/cameras/cam0/image_raw/compressed".to_string())
+        );
+        // A topic directly under the prefix has no camera namespace
+        assert_eq!(rig.camera_for_topic("/image_raw"), None);
+        assert!(parse_camera_group("/stereo=/").is_err());
+    }
+}
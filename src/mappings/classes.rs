@@ -0,0 +1,108 @@
+//! Semantic classes (--class-map): labels and colors of class ids, logged as a
+//! static AnnotationContext at the root entity so every entity shares the legend
+//!
+//! Point cloud class ids (--pointcloud-class-field) and the classes of
+//! vision_msgs detections are drawn with them. The YAML file maps each id to a
+//! label, or to a label and an RGB color:
+//!
+//! ```yaml
+//! 0: {label: unlabeled, color: [0, 0, 0]}
+//! 1: {label: car, color: [0, 0, 255]}
+//! 2: pedestrian
+//! ```
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// A class of the file: a bare label or a label with a color
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+enum ClassEntry {
+    Label(String),
+    Full(ClassInfo),
+}
+
+/// Label and RGB color of a class
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ClassInfo {
+    pub label: String,
+    #[serde(default)]
+    pub color: Option<[u8; 3]>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClassMap {
+    classes: BTreeMap<u16, ClassInfo>,
+}
+
+impl ClassMap {
+    pub fn load(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path))?;
+        Self::parse(&text).with_context(|| format!("invalid class map {}", path))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let entries: BTreeMap<u16, ClassEntry> = serde_yaml::from_str(text)?;
+        let classes = entries
+            .into_iter()
+            .map(|(id, entry)| {
+                let info = match entry {
+                    ClassEntry::Label(label) => ClassInfo { label, color: None },
+                    ClassEntry::Full(info) => info,
+                };
+                (id, info)
+            })
+            .collect();
+        Ok(Self { classes })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+
+    pub fn get(&self, id: u16) -> Option<&ClassInfo> {
+        self.classes.get(&id)
+    }
+
+    /// Id of a class given as a number ("3") or by its label ("car")
+    pub fn id_of(&self, class: &str) -> Option<u16> {
+        class
+            .trim()
+            .parse::<u16>()
+            .ok()
+            .or_else(|| self.classes.iter().find(|(_, info)| info.label == class).map(|(id, _)| *id))
+    }
+
+    /// Log the classes as a static AnnotationContext on the root entity
+    pub fn log(&self, rec: &rerun::RecordingStream) -> Result<()> {
+        if self.classes.is_empty() {
+            return Ok(());
+        }
+        let infos = self.classes.iter().map(|(id, info)| rerun::datatypes::AnnotationInfo {
+            id: *id,
+            label: Some(info.label.as_str().into()),
+            color: info.color.map(|[r, g, b]| rerun::datatypes::Rgba32::from_rgb(r, g, b)),
+        });
+        rec.log_static("/", &rerun::archetypes::AnnotationContext::new(infos))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_class_map() {
+        let classes = ClassMap::parse("0: {label: unlabeled, color: [0, 0, 0]}\n1: {label: car, color: [0, 0, 255]}\n2: pedestrian\n").unwrap();
+        assert_eq!(classes.get(1), Some(&ClassInfo { label: "car".to_string(), color: Some([0, 0, 255]) }));
+        assert_eq!(classes.get(2), Some(&ClassInfo { label: "pedestrian".to_string(), color: None }));
+        assert_eq!(classes.id_of("pedestrian"), Some(2));
+        assert_eq!(classes.id_of("7"), Some(7));
+        assert_eq!(classes.id_of("truck"), None);
+        assert!(ClassMap::parse("1: {label: car, colour: [0, 0, 255]}\n").is_err());
+        assert!(ClassMap::parse("70000: car\n").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//! rosgraph_msgs/Clock: map bag receive time to simulated time
+
+use anyhow::Result;
+
+use crate::ros_codec::Cursor;
+
+/// (bag time, sim time) samples from /clock, both in seconds
+#[derive(Debug, Default)]
+pub struct SimClock {
+    samples: Vec<(f64, f64)>,
+    /// Clock messages too short to hold a time, left out of the mapping
+    malformed: usize,
+}
+
+impl SimClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a clock sample; malformed messages are counted and skipped
+    pub fn push(&mut self, bag_time: f64, payload: &[u8]) {
+        match parse_clock(payload) {
+            Ok(sim) => self.samples.push((bag_time, sim)),
+            Err(_) => self.malformed += 1,
+        }
+    }
+
+    pub fn malformed(&self) -> usize {
+        self.malformed
+    }
+
+    /// Sort samples by bag time once all of them are collected
+    pub fn finish(&mut self) {
+        self.samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Sim time at a bag time: interpolated between clock samples, advancing at
+    /// real-time rate before the first and after the last sample
+    pub fn to_sim(&self, bag_time: f64) -> Option<f64> {
+        let idx = self.samples.partition_point(|(t, _)| *t <= bag_time);
+        match (idx.checked_sub(1).map(|i| self.samples[i]), self.samples.get(idx)) {
+            (Some((t0, s0)), Some(&(t1, s1))) if t1 > t0 => {
+                let f = (bag_time - t0) / (t1 - t0);
+                Some(s0 + f * (s1 - s0))
+            }
+            (Some((t0, s0)), _) => Some(s0 + (bag_time - t0)),
+            (None, Some(&(t1, s1))) => Some(s1 - (t1 - bag_time)),
+            (None, None) => None,
+        }
+    }
+}
+
+fn parse_clock(payload: &[u8]) -> Result<f64> {
+    Cursor::new(payload).time()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(secs: u32, nsecs: u32) -> Vec<u8> {
+        let mut payload = secs.to_le_bytes().to_vec();
+        payload.extend_from_slice(&nsecs.to_le_bytes());
+        payload
+    }
+
+    #[test]
+    fn test_sim_clock_mapping() {
+        let mut sim = SimClock::new();
+        // Simulation running at half real-time speed
+        sim.push(102.0, &clock(11, 0));
+        sim.push(100.0, &clock(10, 0));
+        sim.push(101.0, &[0, 0, 0]);
+        sim.finish();
+        assert_eq!(sim.malformed(), 1);
+        assert_eq!(sim.to_sim(101.0), Some(10.5));
+        assert_eq!(sim.to_sim(99.0), Some(9.0));
+        assert_eq!(sim.to_sim(103.0), Some(12.0));
+        assert_eq!(SimClock::new().to_sim(1.0), None);
+    }
+}
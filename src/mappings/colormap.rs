@@ -0,0 +1,146 @@
+//! Colormaps for scalar → RGB coloring (scan intensities, ranges, thermal images)
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Colormap {
+    Turbo,
+    Viridis,
+    Inferno,
+    Grayscale,
+}
+
+// Control points sampled from the matplotlib colormaps at t = 0, 0.125, ..., 1
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+const INFERNO: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [31, 12, 72],
+    [85, 15, 109],
+    [136, 34, 106],
+    [186, 54, 85],
+    [227, 89, 51],
+    [249, 140, 10],
+    [249, 201, 50],
+    [252, 255, 164],
+];
+
+impl Colormap {
+    /// Map a normalized value in [0, 1] to RGB (values are clamped)
+    pub fn map(&self, t: f32) -> [u8; 3] {
+        let t = if t.is_finite() { t.clamp(0.0, 1.0) } else { 0.0 };
+        match self {
+            Colormap::Turbo => turbo(t),
+            Colormap::Viridis => lerp_lut(&VIRIDIS, t),
+            Colormap::Inferno => lerp_lut(&INFERNO, t),
+            Colormap::Grayscale => {
+                let v = (t * 255.0).round() as u8;
+                [v, v, v]
+            }
+        }
+    }
+
+    /// Map values to colors, normalizing by their finite min/max
+    pub fn map_auto_range(&self, values: &[f32]) -> Vec<[u8; 3]> {
+        let (min, max) = finite_range(values).unwrap_or((0.0, 1.0));
+        let span = if max > min { max - min } else { 1.0 };
+        values.iter().map(|v| self.map((v - min) / span)).collect()
+    }
+
+    /// Equivalent Rerun colormap, used when the viewer applies the colormap itself
+    pub fn to_rerun(self) -> rerun::components::Colormap {
+        match self {
+            Colormap::Turbo => rerun::components::Colormap::Turbo,
+            Colormap::Viridis => rerun::components::Colormap::Viridis,
+            Colormap::Inferno => rerun::components::Colormap::Inferno,
+            Colormap::Grayscale => rerun::components::Colormap::Grayscale,
+        }
+    }
+}
+
+/// Min and max over the finite values, if any
+pub fn finite_range(values: &[f32]) -> Option<(f32, f32)> {
+    values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold(None, |acc, &v| match acc {
+            None => Some((v, v)),
+            Some((lo, hi)) => Some((lo.min(v), hi.max(v))),
+        })
+}
+
+pub fn parse_colormap(s: &str) -> Result<Colormap> {
+    match s {
+        "turbo" => Ok(Colormap::Turbo),
+        "viridis" => Ok(Colormap::Viridis),
+        "inferno" => Ok(Colormap::Inferno),
+        "grayscale" | "gray" => Ok(Colormap::Grayscale),
+        _ => Err(anyhow!("Invalid colormap: {}", s)),
+    }
+}
+
+fn lerp_lut(lut: &[[u8; 3]], t: f32) -> [u8; 3] {
+    let pos = t * (lut.len() - 1) as f32;
+    let i = (pos.floor() as usize).min(lut.len() - 2);
+    let f = pos - i as f32;
+    let (a, b) = (lut[i], lut[i + 1]);
+    [
+        (a[0] as f32 + f * (b[0] as f32 - a[0] as f32)).round() as u8,
+        (a[1] as f32 + f * (b[1] as f32 - a[1] as f32)).round() as u8,
+        (a[2] as f32 + f * (b[2] as f32 - a[2] as f32)).round() as u8,
+    ]
+}
+
+/// Polynomial approximation of Google's Turbo colormap
+fn turbo(t: f32) -> [u8; 3] {
+    let t = t as f64;
+    let r = 0.13572138 + t * (4.61539260 + t * (-42.66032258 + t * (132.13108234 + t * (-152.94239396 + t * 59.28637943))));
+    let g = 0.09140261 + t * (2.19418839 + t * (4.84296658 + t * (-14.18503333 + t * (4.27729857 + t * 2.82956604))));
+    let b = 0.10667330 + t * (12.64194608 + t * (-60.58204836 + t * (110.36276771 + t * (-89.90310912 + t * 27.34824973))));
+    [
+        (r.clamp(0.0, 1.0) * 255.0).round() as u8,
+        (g.clamp(0.0, 1.0) * 255.0).round() as u8,
+        (b.clamp(0.0, 1.0) * 255.0).round() as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colormap_endpoints() {
+        assert_eq!(Colormap::Grayscale.map(0.0), [0, 0, 0]);
+        assert_eq!(Colormap::Grayscale.map(1.0), [255, 255, 255]);
+        assert_eq!(Colormap::Viridis.map(0.0), VIRIDIS[0]);
+        assert_eq!(Colormap::Viridis.map(1.0), VIRIDIS[8]);
+        assert_eq!(Colormap::Inferno.map(2.0), INFERNO[8]); // clamped
+        // Turbo goes from dark to green-ish in the middle to dark red
+        let lo = Colormap::Turbo.map(0.0);
+        let mid = Colormap::Turbo.map(0.5);
+        let hi = Colormap::Turbo.map(1.0);
+        assert!(lo.iter().map(|&c| c as u32).sum::<u32>() < 150);
+        assert!(mid[1] > 200);
+        assert!(hi[0] > hi[2]);
+    }
+
+    #[test]
+    fn test_map_auto_range() {
+        let colors = Colormap::Grayscale.map_auto_range(&[10.0, 20.0, f32::NAN, 15.0]);
+        assert_eq!(colors[0], [0, 0, 0]);
+        assert_eq!(colors[1], [255, 255, 255]);
+        assert_eq!(colors[2], [0, 0, 0]);
+        assert_eq!(colors[3], [128, 128, 128]);
+    }
+}
@@ -0,0 +1,246 @@
+//! Depth image back-projection into point clouds using sensor_msgs/CameraInfo
+
+use anyhow::Result;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::mappings::images::{decode_depth, decode_rgb};
+use crate::ros_codec::Cursor;
+
+/// Pinhole intrinsics from sensor_msgs/CameraInfo
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraInfo {
+    pub frame_id: String,
+    pub width: u32,
+    pub height: u32,
+    /// Row-major 3x3 camera matrix
+    pub k: [f64; 9],
+}
+
+impl CameraInfo {
+    fn is_valid(&self) -> bool {
+        self.k[0] > 0.0 && self.k[4] > 0.0
+    }
+}
+
+/// Color frames kept to pair with depth images of the same stamp
+const COLOR_FRAMES: usize = 8;
+/// Largest stamp difference (seconds) between a depth image and the color frame coloring it
+const COLOR_TOLERANCE: f64 = 0.02;
+
+/// A decoded color frame: stamp, width, height and RGB bytes
+type ColorFrame = (f64, usize, usize, Vec<u8>);
+
+/// Latest intrinsics per camera namespace and recent color frames used to project depth images
+#[derive(Debug, Default)]
+pub struct DepthProjector {
+    camera_infos: HashMap<String, CameraInfo>,
+    colors: VecDeque<ColorFrame>,
+    /// mono16 topics holding depth in millimeters (16UC1 and 32FC1 always do)
+    mono16_topics: HashSet<String>,
+    /// CameraInfo topics that failed to parse, warned about once
+    bad_infos: HashSet<String>,
+}
+
+impl DepthProjector {
+    pub fn new(mono16_topics: &[String]) -> Self {
+        Self {
+            mono16_topics: mono16_topics.iter().cloned().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Remember the intrinsics of a CameraInfo topic for images in the same namespace;
+    /// malformed messages are skipped with a warning
+    pub fn set_camera_info(&mut self, topic: &str, payload: &[u8]) {
+        match parse_camera_info(payload) {
+            Ok(info) => {
+                self.camera_infos.insert(camera_namespace(topic).to_string(), info);
+            }
+            Err(e) => {
+                if self.bad_infos.insert(topic.to_string()) {
+                    tracing::warn!(%topic, "malformed camera_info, not used for depth projection: {:#}", e);
+                }
+            }
+        }
+    }
+
+    /// Remember a color frame used to color the depth image with the closest stamp
+    pub fn set_color(&mut self, ts: f64, payload: &[u8]) -> Result<()> {
+        if let Some((width, height, rgb)) = decode_rgb(payload)?
+            && width > 0
+            && height > 0
+        {
+            if self.colors.len() == COLOR_FRAMES {
+                self.colors.pop_front();
+            }
+            self.colors.push_back((ts, width, height, rgb));
+        }
+        Ok(())
+    }
+
+    /// Color frame closest to `ts`, if within COLOR_TOLERANCE
+    fn color_at(&self, ts: f64) -> Option<&ColorFrame> {
+        self.colors
+            .iter()
+            .filter(|(t, ..)| (t - ts).abs() <= COLOR_TOLERANCE)
+            .min_by(|a, b| (a.0 - ts).abs().total_cmp(&(b.0 - ts).abs()))
+    }
+
+    /// Log a depth image as Points3D in the camera optical frame under /<topic>/points
+    pub fn depth_to_rerun(
+        &self,
+        rec: &rerun::RecordingStream,
+        topic: &str,
+        ts: f64,
+        payload: &[u8],
+    ) -> Result<()> {
+        let Some(info) = self.camera_infos.get(camera_namespace(topic)) else {
+            return Ok(());
+        };
+        let Some((width, height, depth)) = decode_depth(payload, self.mono16_topics.contains(topic))? else {
+            return Ok(());
+        };
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        if !info.is_valid() {
+            tracing::debug!(%topic, "camera_info has no intrinsics; skipping depth projection");
+            return Ok(());
+        }
+
+        rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
+        let projected = back_project(&depth, width, height, info);
+        let colors = self.color_at(ts).map(|(_, cw, ch, rgb)| {
+            projected
+                .iter()
+                .map(|(idx, _)| {
+                    // Color and depth are assumed registered; rescale if resolutions differ
+                    let (u, v) = (idx % width, idx / width);
+                    let cu = (u * cw / width).min(cw - 1);
+                    let cv = (v * ch / height).min(ch - 1);
+                    let o = (cv * cw + cu) * 3;
+                    [rgb[o], rgb[o + 1], rgb[o + 2]]
+                })
+                .collect::<Vec<_>>()
+        });
+        let pts = rerun::archetypes::Points3D::new(projected.iter().map(|(_, p)| *p));
+        let pts = match colors {
+            Some(colors) => pts.with_colors(colors),
+            None => pts,
+        };
+        rec.log(format!("/{}/points", topic.trim_start_matches('/')), &pts)?;
+        Ok(())
+    }
+}
+
+/// Back-project valid depths (meters) into the optical frame (x right, y down, z forward)
+///
+/// Returns the pixel index of each point alongside its position.
+pub fn back_project(depth: &[f32], width: usize, height: usize, info: &CameraInfo) -> Vec<(usize, [f32; 3])> {
+    let (fx, fy, cx, cy) = (info.k[0], info.k[4], info.k[2], info.k[5]);
+    // Intrinsics are for the CameraInfo resolution; scale them if the image differs
+    let sx = if info.width > 0 { width as f64 / info.width as f64 } else { 1.0 };
+    let sy = if info.height > 0 { height as f64 / info.height as f64 } else { 1.0 };
+    let (fx, fy, cx, cy) = (fx * sx, fy * sy, cx * sx, cy * sy);
+
+    depth
+        .iter()
+        .take(width * height)
+        .enumerate()
+        .filter(|(_, d)| d.is_finite() && **d > 0.0)
+        .map(|(idx, &d)| {
+            let (u, v) = ((idx % width) as f64, (idx / width) as f64);
+            let z = d as f64;
+            (idx, [((u - cx) * z / fx) as f32, ((v - cy) * z / fy) as f32, d])
+        })
+        .collect()
+}
+
+/// "/camera/depth/image_raw" and "/camera/depth/camera_info" share "/camera/depth"
+fn camera_namespace(topic: &str) -> &str {
+    topic.rsplit_once('/').map(|(ns, _)| ns).unwrap_or("")
+}
+
+pub fn parse_camera_info(payload: &[u8]) -> Result<CameraInfo> {
+    let mut cursor = Cursor::new(payload);
+    let header = cursor.header()?;
+    let height = cursor.u32()?;
+    let width = cursor.u32()?;
+    let _distortion_model = cursor.str()?;
+    let d_len = cursor.u32()? as usize;
+    cursor.skip(d_len.saturating_mul(8))?;
+    let mut k = [0.0; 9];
+    for v in &mut k {
+        *v = cursor.f64()?;
+    }
+    Ok(CameraInfo {
+        frame_id: header.frame_id.into_owned(),
+        width,
+        height,
+        k,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_camera_info_and_back_project() {
+        let mut payload = vec![0u8; 12];
+        payload.extend_from_slice(&3u32.to_le_bytes());
+        payload.extend_from_slice(b"cam");
+        payload.extend_from_slice(&2u32.to_le_bytes()); // height
+        payload.extend_from_slice(&2u32.to_le_bytes()); // width
+        payload.extend_from_slice(&9u32.to_le_bytes());
+        payload.extend_from_slice(b"plumb_bob");
+        payload.extend_from_slice(&5u32.to_le_bytes());
+        payload.extend_from_slice(&[0u8; 5 * 8]);
+        for v in [2.0f64, 0.0, 1.0, 0.0, 2.0, 1.0, 0.0, 0.0, 1.0] {
+            payload.extend_from_slice(&v.to_le_bytes());
+        }
+        let info = parse_camera_info(&payload).unwrap();
+        assert_eq!(info.frame_id, "cam");
+        assert_eq!((info.width, info.height), (2, 2));
+
+        let points = back_project(&[2.0, 0.0, f32::NAN, 4.0], 2, 2, &info);
+        assert_eq!(points, vec![(0, [-1.0, -1.0, 2.0]), (3, [0.0, 0.0, 4.0])]);
+        assert_eq!(camera_namespace("/camera/depth/image_raw"), "/camera/depth");
+    }
+
+    fn image(encoding: &str, data: &[u8]) -> Vec<u8> {
+        let mut payload = vec![0u8; 12];
+        payload.extend_from_slice(&0u32.to_le_bytes()); // frame_id
+        payload.extend_from_slice(&1u32.to_le_bytes()); // height
+        payload.extend_from_slice(&1u32.to_le_bytes()); // width
+        payload.extend_from_slice(&(encoding.len() as u32).to_le_bytes());
+        payload.extend_from_slice(encoding.as_bytes());
+        payload.push(0);
+        payload.extend_from_slice(&(data.len() as u32).to_le_bytes()); // step
+        payload.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        payload.extend_from_slice(data);
+        payload
+    }
+
+    #[test]
+    fn test_mono16_depth_only_on_configured_topics() {
+        let payload = image("mono16", &1500u16.to_le_bytes());
+        assert!(decode_depth(&payload, false).unwrap().is_none());
+        assert_eq!(decode_depth(&payload, true).unwrap(), Some((1, 1, vec![1.5])));
+        let payload = image("16UC1", &1500u16.to_le_bytes());
+        assert_eq!(decode_depth(&payload, false).unwrap(), Some((1, 1, vec![1.5])));
+    }
+
+    #[test]
+    fn test_color_paired_by_stamp() {
+        let mut proj = DepthProjector::new(&[]);
+        proj.set_color(10.0, &image("rgb8", &[1, 2, 3])).unwrap();
+        proj.set_color(10.1, &image("rgb8", &[4, 5, 6])).unwrap();
+        assert_eq!(proj.color_at(10.005).map(|c| c.3.clone()), Some(vec![1, 2, 3]));
+        assert_eq!(proj.color_at(10.09).map(|c| c.3.clone()), Some(vec![4, 5, 6]));
+        assert!(proj.color_at(10.05).is_none());
+
+        proj.set_camera_info("/camera/depth/camera_info", &[0u8; 4]);
+        assert!(proj.camera_infos.is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+//! vision_msgs Detection2DArray/Detection3DArray → Rerun Boxes2D/Boxes3D (implemented in v0.5.1)
+//!
+//! Decoded from the definition recorded in the bag, so the ROS1 layout
+//! (`results[].id`, `bbox.center.x`) and the ROS2 one (`results[].hypothesis.class_id`,
+//! `bbox.center.position.x`) both map. Each box takes the class of its best
+//! hypothesis as class id, with --class-map resolving class names to ids and
+//! giving the legend.
+
+use anyhow::Result;
+
+use crate::mappings::classes::ClassMap;
+use crate::ros_msg::Value;
+
+/// One box of a detection array
+#[derive(Clone, Debug, PartialEq)]
+pub struct Detection {
+    pub center: [f32; 3],
+    pub half_size: [f32; 3],
+    /// xyzw, 3D boxes only
+    pub rotation: Option<[f32; 4]>,
+    /// Class of the best hypothesis, as recorded (a number or a name)
+    pub class: Option<String>,
+    pub score: Option<f64>,
+}
+
+fn number(value: &Value, paths: &[&str]) -> Option<f64> {
+    paths.iter().find_map(|path| value.get(path).and_then(Value::as_f64))
+}
+
+/// Boxes of a decoded Detection2DArray or Detection3DArray
+pub fn parse_detections(msg: &Value, is_3d: bool) -> Vec<Detection> {
+    let Some(Value::Array(detections)) = msg.get("detections") else {
+        return Vec::new();
+    };
+    detections.iter().filter_map(|detection| parse_detection(detection, is_3d)).collect()
+}
+
+fn parse_detection(detection: &Value, is_3d: bool) -> Option<Detection> {
+    let bbox = detection.get("bbox")?;
+    let (center, half_size, rotation) = if is_3d {
+        let p = |axis: &str| number(bbox, &[&format!("center.position.{axis}")]);
+        let q = |axis: &str| number(bbox, &[&format!("center.orientation.{axis}")]);
+        let s = |axis: &str| number(bbox, &[&format!("size.{axis}")]);
+        let rotation = match (q("x"), q("y"), q("z"), q("w")) {
+            (Some(x), Some(y), Some(z), Some(w)) => Some([x as f32, y as f32, z as f32, w as f32]),
+            _ => None,
+        };
+        (
+            [p("x")?, p("y")?, p("z")?].map(|v| v as f32),
+            [s("x")?, s("y")?, s("z")?].map(|v| v as f32 / 2.0),
+            rotation,
+        )
+    } else {
+        let x = number(bbox, &["center.x", "center.position.x"])?;
+        let y = number(bbox, &["center.y", "center.position.y"])?;
+        let (w, h) = (number(bbox, &["size_x"])?, number(bbox, &["size_y"])?);
+        ([x as f32, y as f32, 0.0], [w as f32 / 2.0, h as f32 / 2.0, 0.0], None)
+    };
+
+    // Best hypothesis: ROS1 has id and score, ROS2 a nested hypothesis
+    let mut class = None;
+    let mut score = None;
+    if let Some(Value::Array(results)) = detection.get("results") {
+        for result in results {
+            let result_score = number(result, &["score", "hypothesis.score"]);
+            if score.is_some() && result_score <= score {
+                continue;
+            }
+            score = result_score.or(score);
+            class = match result.get("id").or_else(|| result.get("hypothesis.class_id")) {
+                Some(Value::String(name)) => Some(name.clone()),
+                Some(id) => id.as_f64().map(|id| (id as i64).to_string()),
+                None => None,
+            };
+        }
+    }
+    Some(Detection { center, half_size, rotation, class, score })
+}
+
+/// Log the boxes of a detection array under `entity`
+pub fn detections_to_rerun(
+    rec: &rerun::RecordingStream,
+    entity: &str,
+    ts: f64,
+    msg: &Value,
+    is_3d: bool,
+    classes: &ClassMap,
+) -> Result<()> {
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
+
+    let detections = parse_detections(msg, is_3d);
+    let class_ids: Option<Vec<u16>> =
+        detections.iter().map(|d| d.class.as_deref().and_then(|class| classes.id_of(class))).collect();
+    // Class name (from the class map when known) and score
+    let labels: Vec<String> = detections
+        .iter()
+        .map(|d| {
+            let name = match d.class.as_deref() {
+                Some(class) => classes
+                    .id_of(class)
+                    .and_then(|id| classes.get(id))
+                    .map_or(class, |info| info.label.as_str()),
+                None => "",
+            };
+            match d.score {
+                Some(score) => format!("{} {:.2}", name, score).trim_start().to_string(),
+                None => name.to_string(),
+            }
+        })
+        .collect();
+
+    if is_3d {
+        let boxes = rerun::archetypes::Boxes3D::from_centers_and_half_sizes(
+            detections.iter().map(|d| d.center),
+            detections.iter().map(|d| d.half_size),
+        )
+        .with_quaternions(
+            detections.iter().map(|d| rerun::Quaternion::from_xyzw(d.rotation.unwrap_or([0.0, 0.0, 0.0, 1.0]))),
+        )
+        .with_labels(labels);
+        let boxes = match class_ids {
+            Some(ids) => boxes.with_class_ids(ids),
+            None => boxes,
+        };
+        rec.log(entity, &boxes)?;
+    } else {
+        let boxes = rerun::archetypes::Boxes2D::from_centers_and_half_sizes(
+            detections.iter().map(|d| [d.center[0], d.center[1]]),
+            detections.iter().map(|d| [d.half_size[0], d.half_size[1]]),
+        )
+        .with_labels(labels);
+        let boxes = match class_ids {
+            Some(ids) => boxes.with_class_ids(ids),
+            None => boxes,
+        };
+        rec.log(entity, &boxes)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(fields: &[(&str, Value)]) -> Value {
+        Value::Struct(fields.iter().map(|(name, value)| (name.to_string(), value.clone())).collect())
+    }
+
+    #[test]
+    fn test_parse_detections() {
+        // ROS1 Detection2D: Pose2D center, int64 ids
+        let ros1 = fields(&[(
+            "detections",
+            Value::Array(vec![fields(&[
+                (
+                    "results",
+                    Value::Array(vec![
+                        fields(&[("id", Value::Int(3)), ("score", Value::Float(0.4))]),
+                        fields(&[("id", Value::Int(1)), ("score", Value::Float(0.9))]),
+                    ]),
+                ),
+                (
+                    "bbox",
+                    fields(&[
+                        ("center", fields(&[("x", Value::Float(320.0)), ("y", Value::Float(240.0)), ("theta", Value::Float(0.0))])),
+                        ("size_x", Value::Float(40.0)),
+                        ("size_y", Value::Float(20.0)),
+                    ]),
+                ),
+            ])]),
+        )]);
+        let detections = parse_detections(&ros1, false);
+        assert_eq!(
+            detections,
+            vec![Detection {
+                center: [320.0, 240.0, 0.0],
+                half_size: [20.0, 10.0, 0.0],
+                rotation: None,
+                class: Some("1".to_string()),
+                score: Some(0.9),
+            }]
+        );
+
+        // ROS2 Detection3D: nested hypothesis with a class name
+        let position = fields(&[("x", Value::Float(1.0)), ("y", Value::Float(2.0)), ("z", Value::Float(0.5))]);
+        let orientation =
+            fields(&[("x", Value::Float(0.0)), ("y", Value::Float(0.0)), ("z", Value::Float(0.0)), ("w", Value::Float(1.0))]);
+        let ros2 = fields(&[(
+            "detections",
+            Value::Array(vec![fields(&[
+                (
+                    "results",
+                    Value::Array(vec![fields(&[(
+                        "hypothesis",
+                        fields(&[("class_id", Value::String("car".to_string())), ("score", Value::Float(0.7))]),
+                    )])]),
+                ),
+                (
+                    "bbox",
+                    fields(&[
+                        ("center", fields(&[("position", position), ("orientation", orientation)])),
+                        ("size", fields(&[("x", Value::Float(4.0)), ("y", Value::Float(2.0)), ("z", Value::Float(1.5))])),
+                    ]),
+                ),
+            ])]),
+        )]);
+        let detections = parse_detections(&ros2, true);
+        assert_eq!(detections[0].half_size, [2.0, 1.0, 0.75]);
+        assert_eq!(detections[0].rotation, Some([0.0, 0.0, 0.0, 1.0]));
+        assert_eq!(detections[0].class.as_deref(), Some("car"));
+        assert_eq!(ClassMap::parse("5: car\n").unwrap().id_of("car"), Some(5));
+    }
+}
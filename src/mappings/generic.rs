@@ -0,0 +1,80 @@
+//! Messages of any other type → Scalars and TextLog, decoded from their definition
+
+use anyhow::Result;
+
+use crate::mappings::scalars::ScalarColumns;
+use crate::ros_msg::Value;
+
+/// Numeric arrays up to this length are plotted element by element; longer ones are skipped
+const MAX_ARRAY_SCALARS: usize = 16;
+
+/// Log the numeric fields of `value` as Scalars and its strings as TextLog, each
+/// under `<entity>/<field path>`. The header is left out: its stamp is already the log time
+pub fn generic_to_rerun(
+    rec: &rerun::RecordingStream,
+    entity: &str,
+    ts: f64,
+    value: &Value,
+    scalars: &mut ScalarColumns,
+) -> Result<()> {
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
+    let root = format!("/{}", entity.trim_matches('/'));
+    let mut leaves = Vec::new();
+    match value {
+        Value::Struct(fields) => {
+            for (name, field) in fields.iter().filter(|(name, _)| name != "header") {
+                collect_leaves(&format!("{}/{}", root, name), field, &mut leaves);
+            }
+        }
+        other => collect_leaves(&root, other, &mut leaves),
+    }
+    for (path, leaf) in leaves {
+        match leaf {
+            Value::String(text) => rec.log(path, &rerun::archetypes::TextLog::new(text.as_str()))?,
+            _ => {
+                if let Some(v) = leaf.as_f64() {
+                    scalars.push(rec, &path, v)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Scalar and string leaves under `path`; arrays of them are indexed `<path>/<i>`
+pub(crate) fn collect_leaves<'a>(path: &str, value: &'a Value, leaves: &mut Vec<(String, &'a Value)>) {
+    match value {
+        Value::Struct(fields) => {
+            for (name, field) in fields {
+                collect_leaves(&format!("{}/{}", path, name), field, leaves);
+            }
+        }
+        Value::Array(items) if items.len() <= MAX_ARRAY_SCALARS => {
+            for (i, item) in items.iter().enumerate() {
+                collect_leaves(&format!("{}/{}", path, i), item, leaves);
+            }
+        }
+        Value::Array(_) | Value::Bytes(_) => {}
+        leaf => leaves.push((path.to_string(), leaf)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_leaves() {
+        let value = Value::Struct(vec![
+            ("pose".to_string(), Value::Struct(vec![("x".to_string(), Value::Float(1.0))])),
+            ("label".to_string(), Value::String("ok".to_string())),
+            ("gains".to_string(), Value::Array(vec![Value::Int(1), Value::Int(2)])),
+            ("long".to_string(), Value::Array(vec![Value::Int(0); MAX_ARRAY_SCALARS + 1])),
+            ("raw".to_string(), Value::Bytes(vec![0; 4])),
+        ]);
+        let mut leaves = Vec::new();
+        collect_leaves("/status", &value, &mut leaves);
+        let paths: Vec<&str> = leaves.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, ["/status/pose/x", "/status/label", "/status/gains/0", "/status/gains/1"]);
+    }
+}
@@ -1,17 +1,23 @@
-//! NavSatFix → Rerun Points3D + LineStrips3D (implemented in v0.2.0)
+//! NavSatFix → Rerun Points3D + LineStrips3D, and GeoPoints for map views (implemented in v0.2.0)
 
-use anyhow::Result;
-use once_cell::sync::Lazy;
-use std::sync::Mutex;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::Write;
 
-static GPS_STATE: Lazy<Mutex<GpsState>> = Lazy::new(|| Mutex::new(GpsState::default()));
+use crate::mappings::scalars::ScalarColumns;
+use crate::mappings::style::Style;
+use crate::ros_codec::Cursor;
 
-#[derive(Default)]
-struct GpsState {
+/// ENU origin shared by every GPS topic, and the path of each topic
+#[derive(Debug, Default)]
+pub struct GpsState {
     origin: Option<nalgebra::Point3<f64>>,
-    path_points: Vec<[f32; 3]>,
+    path_points: HashMap<String, Vec<[f32; 3]>>,
 }
 
+/// Log one fix, with GeoPoints when `geo_points` (--blueprint has a map view of them);
+/// its latitude, longitude and (geoid-corrected) altitude, `None` when skipped for a negative status
+#[allow(clippy::too_many_arguments)]
 pub fn navsatfix_to_rerun(
     rec: &rerun::RecordingStream,
     topic: &str,
@@ -19,9 +25,13 @@ pub fn navsatfix_to_rerun(
     payload: &[u8],
     gps_origin: Option<&str>,
     gps_path: bool,
+    geo_points: bool,
     geoid_path: Option<&str>,
-) -> Result<()> {
-    rec.set_timestamp_secs_since_epoch("ros_time", ts);
+    style: &Style,
+    state: &mut GpsState,
+    scalars: &mut ScalarColumns,
+) -> Result<Option<[f64; 3]>> {
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
 
             let (lat, lon, mut alt, status, service) = parse_navsatfix(payload)?;
 
@@ -37,11 +47,9 @@ pub fn navsatfix_to_rerun(
 
     if status.status < 0 {
         tracing::warn!("GPS fix status < 0; skipping");
-        return Ok(());
+        return Ok(None);
     }
 
-    let mut state = GPS_STATE.lock().unwrap();
-
     // Set origin if not set
     if state.origin.is_none() {
         let origin = if let Some(origin_str) = gps_origin {
@@ -59,12 +67,17 @@ pub fn navsatfix_to_rerun(
 
     // Log points
     let rr_path_points = format!("{}/points", normalize_path(topic).trim_end_matches('/'));
-    let pts = rerun::archetypes::Points3D::new(vec![pos_arr]);
+    let pts = style.points3d(rerun::archetypes::Points3D::new(vec![pos_arr]));
     rec.log(rr_path_points, &pts)?;
+    // Latitude/longitude too, for the map view
+    if geo_points {
+        let rr_path_geo = format!("{}/geo", normalize_path(topic).trim_end_matches('/'));
+        rec.log(rr_path_geo, &rerun::archetypes::GeoPoints::from_lat_lon([[lat, lon]]))?;
+    }
 
     // Log GPS status and service as scalars
     let rr_path_status = format!("{}/status", normalize_path(topic).trim_end_matches('/'));
-    rec.log(rr_path_status, &rerun::archetypes::Scalars::new(vec![status.status as f64]))?;
+    scalars.push(rec, &rr_path_status, status.status as f64)?;
 
     // Log service as categorical if possible, otherwise as scalar
     let service_names = get_service_names(service);
@@ -75,31 +88,154 @@ pub fn navsatfix_to_rerun(
 
     // Log path
     if gps_path {
-        state.path_points.push(pos_arr);
+        let path_points = state.path_points.entry(topic.to_string()).or_default();
+        path_points.push(pos_arr);
         let rr_path_path = format!("{}/path", normalize_path(topic).trim_end_matches('/'));
-        let line_strips = rerun::archetypes::LineStrips3D::new(vec![state.path_points.clone()]);
+        let line_strips = style.lines3d(rerun::archetypes::LineStrips3D::new(vec![path_points.clone()]));
         rec.log(rr_path_path, &line_strips)?;
     }
 
-    Ok(())
+    Ok(Some([lat, lon, alt]))
 }
 
-fn parse_navsatfix(payload: &[u8]) -> Result<(f64, f64, f64, Status, u16)> {
-    let mut cursor = 0;
+/// Fixes of every GPS topic, written as GPX and KML tracks after the conversion
+/// (--export-gpx, --export-kml) for Google Earth, QGIS and the like
+#[derive(Debug, Default)]
+pub struct TrackExport {
+    gpx: Option<String>,
+    kml: Option<String>,
+    /// One track per topic, in order of first fix
+    tracks: Vec<Track>,
+}
 
-    // Skip header
-    cursor = skip_header(payload, cursor)?;
+/// Fixes of one GPS topic, (epoch seconds, [lat, lon, alt])
+#[derive(Debug)]
+struct Track {
+    topic: String,
+    fixes: Vec<(f64, [f64; 3])>,
+}
 
-    // status (NavSatStatus) - contains status and service
-    let (status, service) = parse_status(payload, &mut cursor)?;
+impl TrackExport {
+    /// `None` when neither file is asked for
+    pub fn new(gpx: Option<&str>, kml: Option<&str>) -> Option<Self> {
+        (gpx.is_some() || kml.is_some()).then(|| Self {
+            gpx: gpx.map(str::to_string),
+            kml: kml.map(str::to_string),
+            tracks: Vec::new(),
+        })
+    }
 
-    // latitude (float64)
-    let lat = read_f64_le(payload, &mut cursor)?;
-    // longitude (float64)
-    let lon = read_f64_le(payload, &mut cursor)?;
-    // altitude (float64)
-    let alt = read_f64_le(payload, &mut cursor)?;
+    /// Add a fix of `topic`; fixes without a position (NaN when the receiver has
+    /// no fix) are left out of the files
+    pub fn push(&mut self, topic: &str, time: f64, fix: [f64; 3]) {
+        if !fix.iter().all(|v| v.is_finite()) {
+            return;
+        }
+        match self.tracks.iter_mut().find(|track| track.topic == topic) {
+            Some(track) => track.fixes.push((time, fix)),
+            None => self.tracks.push(Track { topic: topic.to_string(), fixes: vec![(time, fix)] }),
+        }
+    }
 
+    /// Write the files asked for
+    pub fn write(&self) -> Result<()> {
+        if let Some(path) = &self.gpx {
+            let mut out = std::io::BufWriter::new(std::fs::File::create(path).with_context(|| format!("failed to create {}", path))?);
+            self.write_gpx(&mut out)?;
+            out.flush()?;
+            tracing::info!(%path, "wrote GPS tracks");
+        }
+        if let Some(path) = &self.kml {
+            let mut out = std::io::BufWriter::new(std::fs::File::create(path).with_context(|| format!("failed to create {}", path))?);
+            self.write_kml(&mut out)?;
+            out.flush()?;
+            tracing::info!(%path, "wrote GPS tracks");
+        }
+        Ok(())
+    }
+
+    /// GPX 1.1: a `trk` per topic with timed `trkpt`s
+    fn write_gpx(&self, out: &mut impl Write) -> Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, r#"<gpx version="1.1" creator="bag2rrd" xmlns="http://www.topografix.com/GPX/1/1">"#)?;
+        for Track { topic, fixes } in &self.tracks {
+            writeln!(out, "  <trk>\n    <name>{}</name>\n    <trkseg>", xml_escape(topic))?;
+            for (time, [lat, lon, alt]) in fixes {
+                writeln!(
+                    out,
+                    r#"      <trkpt lat="{lat:.9}" lon="{lon:.9}"><ele>{alt:.3}</ele><time>{}</time></trkpt>"#,
+                    iso8601(*time)
+                )?;
+            }
+            writeln!(out, "    </trkseg>\n  </trk>")?;
+        }
+        writeln!(out, "</gpx>")?;
+        Ok(())
+    }
+
+    /// KML 2.2: a `gx:Track` placemark per topic, timed for the Google Earth time slider
+    fn write_kml(&self, out: &mut impl Write) -> Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            out,
+            r#"<kml xmlns="http://www.opengis.net/kml/2.2" xmlns:gx="http://www.google.com/kml/ext/2.2">"#
+        )?;
+        writeln!(out, "<Document>")?;
+        for Track { topic, fixes } in &self.tracks {
+            writeln!(out, "  <Placemark>\n    <name>{}</name>\n    <gx:Track>", xml_escape(topic))?;
+            writeln!(out, "      <altitudeMode>absolute</altitudeMode>")?;
+            for (time, _) in fixes {
+                writeln!(out, "      <when>{}</when>", iso8601(*time))?;
+            }
+            for (_, [lat, lon, alt]) in fixes {
+                writeln!(out, "      <gx:coord>{lon:.9} {lat:.9} {alt:.3}</gx:coord>")?;
+            }
+            writeln!(out, "    </gx:Track>\n  </Placemark>")?;
+        }
+        writeln!(out, "</Document>\n</kml>")?;
+        Ok(())
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// UTC date and time of `secs` since the epoch, to the millisecond
+fn iso8601(secs: f64) -> String {
+    let millis = (secs * 1000.0).round() as i64;
+    let (days, ms) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+fn parse_navsatfix(payload: &[u8]) -> Result<(f64, f64, f64, Status, u16)> {
+    let mut cursor = Cursor::new(payload);
+    cursor.skip_header()?;
+    // status (NavSatStatus): int8 status, uint16 service
+    let status = Status { status: cursor.i8()? };
+    let service = cursor.u16()?;
+    let lat = cursor.f64()?;
+    let lon = cursor.f64()?;
+    let alt = cursor.f64()?;
     Ok((lat, lon, alt, status, service))
 }
 
@@ -108,14 +244,6 @@ struct Status {
     status: i8,
 }
 
-fn parse_status(payload: &[u8], cursor: &mut usize) -> Result<(Status, u16)> {
-    // status (int8)
-    let status = read_i8(payload, cursor)?;
-    // service (uint16)
-    let service = read_u16_le(payload, cursor)?;
-    Ok((Status { status }, service))
-}
-
 fn parse_origin(s: &str) -> Result<nalgebra::Point3<f64>> {
     let parts: Vec<&str> = s.split(',').collect();
     if parts.len() != 3 {
@@ -171,67 +299,6 @@ pub fn wgs84_to_enu(
     Ok((e, n, u))
 }
 
-fn read_f64_le(payload: &[u8], cursor: &mut usize) -> Result<f64> {
-    if *cursor + 8 > payload.len() {
-        return Err(anyhow::anyhow!("payload too short"));
-    }
-    let val = f64::from_le_bytes([
-        payload[*cursor],
-        payload[*cursor + 1],
-        payload[*cursor + 2],
-        payload[*cursor + 3],
-        payload[*cursor + 4],
-        payload[*cursor + 5],
-        payload[*cursor + 6],
-        payload[*cursor + 7],
-    ]);
-    *cursor += 8;
-    Ok(val)
-}
-
-fn read_i8(payload: &[u8], cursor: &mut usize) -> Result<i8> {
-    if *cursor + 1 > payload.len() {
-        return Err(anyhow::anyhow!("payload too short"));
-    }
-    let val = payload[*cursor] as i8;
-    *cursor += 1;
-    Ok(val)
-}
-
-fn read_u16_le(payload: &[u8], cursor: &mut usize) -> Result<u16> {
-    if *cursor + 2 > payload.len() {
-        return Err(anyhow::anyhow!("Buffer too short for u16"));
-    }
-    let val = u16::from_le_bytes([payload[*cursor], payload[*cursor + 1]]);
-    *cursor += 2;
-    Ok(val)
-}
-
-fn skip_header(payload: &[u8], mut cursor: usize) -> Result<usize> {
-    // seq (uint32)
-    cursor += 4;
-    // stamp (uint32 + uint32)
-    cursor += 8;
-    // frame_id (string)
-    let len = read_u32_le(payload, &mut cursor)? as usize;
-    cursor += len;
-    Ok(cursor)
-}
-
-fn read_u32_le(payload: &[u8], cursor: &mut usize) -> Result<u32> {
-    if *cursor + 4 > payload.len() {
-        return Err(anyhow::anyhow!("payload too short"));
-    }
-    let val = u32::from_le_bytes([
-        payload[*cursor],
-        payload[*cursor + 1],
-        payload[*cursor + 2],
-        payload[*cursor + 3],
-    ]);
-    *cursor += 4;
-    Ok(val)
-}
-
 fn normalize_path(topic: &str) -> String {
     if topic.starts_with('/') {
         topic.to_string()
@@ -283,12 +350,10 @@ fn get_geoid_correction(geoid_path: &str, lat: f64, lon: f64) -> Result<f64> {
             continue;
         }
         let parts: Vec<&str> = line.split_whitespace().collect();
-        if width == 0 {
-            if parts.len() >= 2 {
-                width = parts[0].parse()?;
-                height = parts[1].parse()?;
-                break;
-            }
+        if width == 0 && parts.len() >= 2 {
+            width = parts[0].parse()?;
+            height = parts[1].parse()?;
+            break;
         }
     }
 
@@ -375,6 +440,38 @@ mod tests {
         // assert!(correction.is_ok());
     }
 
+    #[test]
+    fn test_track_export() {
+        assert_eq!(iso8601(0.0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(iso8601(1_700_000_000.25), "2023-11-14T22:13:20.250Z");
+        assert_eq!(iso8601(951_825_600.0), "2000-02-29T12:00:00.000Z");
+
+        let mut export = TrackExport::new(Some("track.gpx"), None).unwrap();
+        export.push("/fix", 1_700_000_000.0, [46.7821, -71.274, 90.0]);
+        export.push("/rtk/fix", 1_700_000_000.0, [46.7822, -71.2741, 91.0]);
+        export.push("/fix", 1_700_000_001.0, [46.7823, -71.2742, 92.5]);
+        // No fix yet: the receiver publishes NaN
+        export.push("/fix", 1_700_000_002.0, [f64::NAN, f64::NAN, f64::NAN]);
+
+        let mut gpx = Vec::new();
+        export.write_gpx(&mut gpx).unwrap();
+        let gpx = String::from_utf8(gpx).unwrap();
+        assert_eq!(gpx.matches("<trk>").count(), 2);
+        assert_eq!(gpx.matches("<trkpt").count(), 3);
+        assert!(gpx.contains(
+            r#"<trkpt lat="46.782300000" lon="-71.274200000"><ele>92.500</ele><time>2023-11-14T22:13:21.000Z</time></trkpt>"#
+        ));
+
+        let mut kml = Vec::new();
+        export.write_kml(&mut kml).unwrap();
+        let kml = String::from_utf8(kml).unwrap();
+        assert_eq!(kml.matches("<Placemark>").count(), 2);
+        // KML coordinates are longitude first
+        assert!(kml.contains("<gx:coord>-71.274000000 46.782100000 90.000</gx:coord>"));
+        assert!(kml.contains("<when>2023-11-14T22:13:20.000Z</when>"));
+        assert!(TrackExport::new(None, None).is_none());
+    }
+
     #[test]
     fn test_get_service_names() {
         assert_eq!(get_service_names(0), "");
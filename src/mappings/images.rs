@@ -1,124 +1,502 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use image::{DynamicImage, ImageFormat};
+use serde::Serialize;
+use std::borrow::Cow;
+
+use crate::mappings::colormap::{parse_colormap, Colormap};
+use crate::ros_codec::Cursor;
+
+/// Image setting applied to all topics, or only to `topic` when set
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TopicSetting<T> {
+    pub topic: Option<String>,
+    pub value: T,
+}
+
+/// Colormap for single-channel images, optionally restricted to one topic
+pub type ImageColormap = TopicSetting<Colormap>;
+
+/// Parse "VALUE" (all topics) or "TOPIC=VALUE"
+pub fn parse_topic_setting<T>(s: &str, parse_value: impl Fn(&str) -> Result<T>) -> Result<TopicSetting<T>> {
+    match s.rsplit_once('=') {
+        Some((topic, value)) if !topic.trim().is_empty() => Ok(TopicSetting {
+            topic: Some(topic.trim().to_string()),
+            value: parse_value(value.trim())?,
+        }),
+        Some(_) => Err(anyhow!("Invalid topic setting: {}", s)),
+        None => Ok(TopicSetting {
+            topic: None,
+            value: parse_value(s.trim())?,
+        }),
+    }
+}
+
+/// Setting for a topic; a topic-specific entry wins over a global one
+pub fn setting_for_topic<'a, T>(settings: &'a [TopicSetting<T>], topic: &str) -> Option<&'a T> {
+    let topic = topic.trim_start_matches('/');
+    settings
+        .iter()
+        .find(|s| s.topic.as_deref().is_some_and(|t| t.trim_start_matches('/') == topic))
+        .or_else(|| settings.iter().find(|s| s.topic.is_none()))
+        .map(|s| &s.value)
+}
+
+pub fn parse_image_colormap(s: &str) -> Result<ImageColormap> {
+    parse_topic_setting(s, parse_colormap)
+}
+
+/// Parse "SCALE" or "TOPIC=SCALE" with 0 < SCALE <= 1
+pub fn parse_image_scale(s: &str) -> Result<TopicSetting<f64>> {
+    parse_topic_setting(s, |v| {
+        let scale = v
+            .parse::<f64>()
+            .map_err(|_| anyhow!("Failed to parse image scale: '{}'", v))?;
+        if !(scale > 0.0 && scale <= 1.0) {
+            return Err(anyhow!("Image scale must be in (0, 1]: {}", scale));
+        }
+        Ok(scale)
+    })
+}
+
+/// Parse "N" or "TOPIC=N" with N >= 1
+pub fn parse_image_every_nth(s: &str) -> Result<TopicSetting<u64>> {
+    parse_topic_setting(s, |v| match v.parse::<u64>() {
+        Ok(n) if n >= 1 => Ok(n),
+        _ => Err(anyhow!("Image every-nth must be an integer >= 1: '{}'", v)),
+    })
+}
+
+/// Rectangle of an image kept by --image-crop, in pixels from the top-left corner
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct ImageCrop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageCrop {
+    /// The part of the rectangle inside a `width` x `height` image; `None` when it is outside
+    pub fn within(&self, width: u32, height: u32) -> Option<ImageCrop> {
+        let (x, y) = (self.x.min(width), self.y.min(height));
+        let (w, h) = (self.width.min(width - x), self.height.min(height - y));
+        (w > 0 && h > 0).then_some(ImageCrop { x, y, width: w, height: h })
+    }
+}
+
+/// Parse "X,Y,W,H" or "TOPIC=X,Y,W,H"
+pub fn parse_image_crop(s: &str) -> Result<TopicSetting<ImageCrop>> {
+    parse_topic_setting(s, |v| {
+        let values = v
+            .split(',')
+            .map(|n| n.trim().parse::<u32>().map_err(|_| anyhow!("Failed to parse image crop value: '{}'", n.trim())))
+            .collect::<Result<Vec<u32>>>()?;
+        match values[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(ImageCrop { x, y, width, height }),
+            [_, _, _, _] => Err(anyhow!("Image crop width and height must be > 0: '{}'", v)),
+            _ => Err(anyhow!("Image crop must be X,Y,W,H in pixels: '{}'", v)),
+        }
+    })
+}
+
+/// How raw images are stored in the RRD
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum ImageEncoding {
+    /// Uncompressed pixels (lossless, largest)
+    #[default]
+    Raw,
+    /// Transcode 8-bit RGB/mono images to JPEG with the given quality (1-100)
+    Jpeg { quality: u8 },
+    /// Transcode 8/16-bit RGB/mono images to PNG (lossless)
+    Png,
+}
+
+pub fn parse_image_encoding(s: &str, jpeg_quality: u8) -> Result<ImageEncoding> {
+    match s {
+        "raw" | "none" => Ok(ImageEncoding::Raw),
+        "jpeg" | "jpg" => {
+            if !(1..=100).contains(&jpeg_quality) {
+                return Err(anyhow!("JPEG quality must be in 1..=100: {}", jpeg_quality));
+            }
+            Ok(ImageEncoding::Jpeg { quality: jpeg_quality })
+        }
+        "png" => Ok(ImageEncoding::Png),
+        _ => Err(anyhow!("Invalid image encoding: {}", s)),
+    }
+}
+
+/// Per-topic options for sensor_msgs/Image
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImageOptions {
+    /// Log mono images as colormapped DepthImage instead of grayscale
+    pub colormap: Option<Colormap>,
+    /// Value range mapped onto the colormap (auto when unset)
+    pub value_range: Option<[f64; 2]>,
+    /// Rectangle kept before scaling, clamped to the image
+    pub crop: Option<ImageCrop>,
+    /// Resize factor applied before logging (1.0 keeps full resolution)
+    pub scale: Option<f64>,
+    /// Transcode raw pixels to JPEG/PNG and log them as EncodedImage
+    pub encoding: ImageEncoding,
+    /// Log CompressedImage JPEG/PNG bytes as-is instead of decoding them
+    pub compressed_passthrough: bool,
+}
+
+/// An image message decoded into the archetype to log
+///
+/// Decoding needs no recording, so it can run on worker threads while a single
+/// thread logs the results in order.
+pub enum DecodedImage {
+    Image(rerun::archetypes::Image),
+    Encoded(rerun::archetypes::EncodedImage),
+    Depth(Box<rerun::archetypes::DepthImage>),
+    /// H.264/H.265 packet published as a CompressedImage
+    Video(rerun::components::VideoCodec, Vec<u8>),
+    /// Unparsable or unsupported message, already reported
+    Skipped,
+}
+
+/// Log a decoded image at `topic`'s entity, at time `ts`
+pub fn log_decoded_image(rec: &rerun::RecordingStream, topic: &str, ts: f64, decoded: DecodedImage) -> Result<()> {
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
+    let rr_path = normalize_path(topic);
+    match decoded {
+        DecodedImage::Image(img) => rec.log(rr_path, &img)?,
+        DecodedImage::Encoded(img) => rec.log(rr_path, &img)?,
+        DecodedImage::Depth(img) => rec.log(rr_path, img.as_ref())?,
+        DecodedImage::Video(codec, data) => crate::mappings::video::log_video_sample(rec, &rr_path, codec, &data)?,
+        DecodedImage::Skipped => {}
+    }
+    Ok(())
+}
 
 pub fn image_to_rerun(
     rec: &rerun::RecordingStream,
     topic: &str,
     ts: f64,
     payload: &[u8],
+    opts: &ImageOptions,
 ) -> Result<()> {
-    rec.set_timestamp_secs_since_epoch("ros_time", ts);
-
-    match parse_ros_image(payload) {
-        Ok((width, height, encoding, data)) => {
-            let rr_path = normalize_path(topic);
-            match encoding.as_str() {
-                "rgb8" => {
-                    let img = rerun::archetypes::Image::from_rgb24(
-                        data.to_vec(),
-                        [width as u32, height as u32],
-                    );
-                    rec.log(rr_path, &img)?;
-                }
-                "bgr8" => {
-                    let mut buf = data.to_vec();
-                    for px in buf.chunks_exact_mut(3) {
-                        px.swap(0, 2);
-                    } // BGR→RGB
-                    let img =
-                        rerun::archetypes::Image::from_rgb24(buf, [width as u32, height as u32]);
-                    rec.log(rr_path, &img)?;
-                }
-                "rgba8" => {
-                    let mut rgb = Vec::with_capacity(width * height * 3);
-                    for px in data.chunks_exact(4) {
-                        rgb.extend_from_slice(&px[..3]);
-                    }
-                    let img =
-                        rerun::archetypes::Image::from_rgb24(rgb, [width as u32, height as u32]);
-                    rec.log(rr_path, &img)?;
-                }
-                "mono8" => {
-                    // Convert mono to RGB for now
-                    let mut rgb = Vec::with_capacity(width * height * 3);
-                    for &gray in data {
-                        rgb.extend_from_slice(&[gray, gray, gray]);
-                    }
-                    let img =
-                        rerun::archetypes::Image::from_rgb24(rgb, [width as u32, height as u32]);
-                    rec.log(rr_path, &img)?;
-                }
-                "mono16" => {
-                    // For v0.1.0, scale down to 8-bit with a warning
-                    tracing::warn!("mono16 not natively supported in v0.1.0; scaling to 8-bit");
-                    let mut rgb = Vec::with_capacity(width * height * 3);
-                    for chunk in data.chunks_exact(2) {
-                        let v = u16::from_le_bytes([chunk[0], chunk[1]]);
-                        let gray = (v >> 8) as u8;
-                        rgb.extend_from_slice(&[gray, gray, gray]);
-                    }
-                    let img =
-                        rerun::archetypes::Image::from_rgb24(rgb, [width as u32, height as u32]);
-                    rec.log(rr_path, &img)?;
-                }
-                "8UC1" => {
-                    // 8-bit unsigned single channel (same as mono8)
-                    let mut rgb = Vec::with_capacity(width * height * 3);
-                    for &gray in data {
-                        rgb.extend_from_slice(&[gray, gray, gray]);
-                    }
-                    let img =
-                        rerun::archetypes::Image::from_rgb24(rgb, [width as u32, height as u32]);
-                    rec.log(rr_path, &img)?;
-                }
-                "8UC3" => {
-                    // 8-bit unsigned 3 channels (assume BGR order like OpenCV)
-                    let mut buf = data.to_vec();
-                    for px in buf.chunks_exact_mut(3) {
-                        px.swap(0, 2); // BGR→RGB
-                    }
-                    let img =
-                        rerun::archetypes::Image::from_rgb24(buf, [width as u32, height as u32]);
-                    rec.log(rr_path, &img)?;
-                }
-                "16UC1" => {
-                    // 16-bit unsigned single channel
-                    tracing::warn!("16UC1 not natively supported; scaling to 8-bit");
-                    let mut rgb = Vec::with_capacity(width * height * 3);
-                    for chunk in data.chunks_exact(2) {
-                        let v = u16::from_le_bytes([chunk[0], chunk[1]]);
-                        let gray = (v >> 8) as u8;
-                        rgb.extend_from_slice(&[gray, gray, gray]);
-                    }
-                    let img =
-                        rerun::archetypes::Image::from_rgb24(rgb, [width as u32, height as u32]);
-                    rec.log(rr_path, &img)?;
-                }
-                "32FC1" => {
-                    // 32-bit float single channel (depth images)
-                    tracing::warn!("32FC1 converted to depth image with scaling");
-                    let depths: Vec<f32> = data
-                        .chunks_exact(4)
-                        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
-                        .collect();
-                    
-                    // Convert to depth image with meter unit
-                    let depth_img = rerun::archetypes::DepthImage::try_from(depths)
-                        .with_context(|| "Failed to create depth image")?
-                        .with_meter(1.0); // Assuming depths are in meters
-                    
-                    rec.log(rr_path, &depth_img)?;
-                }
-                other => {
-                    tracing::debug!(%other, "unsupported image encoding; skipping message");
+    log_decoded_image(rec, topic, ts, decode_image(payload, opts)?)
+}
+
+/// Decode a sensor_msgs/Image payload; `Skipped` for unsupported encodings
+pub fn decode_image(payload: &[u8], opts: &ImageOptions) -> Result<DecodedImage> {
+    let msg = parse_ros_image(payload).context("failed to parse sensor_msgs/Image")?;
+    match decode_pixels(&msg) {
+        Some(pixels) => {
+            let size = [msg.width as u32, msg.height as u32];
+            pixels_to_image(pixels, size, opts)
+        }
+        None => {
+            tracing::debug!(encoding = %msg.encoding, "unsupported image encoding; skipping message");
+            Ok(DecodedImage::Skipped)
+        }
+    }
+}
+
+/// Depth in meters from a 16UC1 (millimeters) or 32FC1 (meters) image, or a mono16 one when `mono16`
+pub(crate) fn decode_depth(payload: &[u8], mono16: bool) -> Result<Option<(usize, usize, Vec<f32>)>> {
+    let msg = parse_ros_image(payload)?;
+    // mono16 is also used by IR and thermal cameras; only configured topics hold depth
+    if msg.encoding == "mono16" && !mono16 {
+        return Ok(None);
+    }
+    let depth = match decode_pixels(&msg) {
+        Some(Pixels::L16(bytes)) => bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]) as f32 / 1000.0)
+            .collect(),
+        Some(Pixels::F32(bytes)) => bytes
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect(),
+        _ => return Ok(None),
+    };
+    Ok(Some((msg.width, msg.height, depth)))
+}
+
+/// RGB pixels of a color (rgb8/bgr8/rgba8) image
+pub(crate) fn decode_rgb(payload: &[u8]) -> Result<Option<(usize, usize, Vec<u8>)>> {
+    let msg = parse_ros_image(payload)?;
+    match decode_pixels(&msg) {
+        Some(Pixels::Rgb8(bytes)) => Ok(Some((msg.width, msg.height, bytes))),
+        _ => Ok(None),
+    }
+}
+
+/// An Image or CompressedImage message as an image to save: 8-bit RGB or
+/// grayscale, 16-bit grayscale; `None` for float images and formats that do not decode
+pub(crate) fn decode_to_image(tp: &str, payload: &[u8]) -> Result<Option<image::DynamicImage>> {
+    use image::{DynamicImage, GrayImage, ImageBuffer, Luma, RgbImage};
+
+    if tp == "sensor_msgs/CompressedImage" {
+        let (_, bytes) = parse_ros_compressed(payload)?;
+        return Ok(image::load_from_memory(bytes)
+            .inspect_err(|e| tracing::debug!("CompressedImage does not decode: {}", e))
+            .ok());
+    }
+    let msg = parse_ros_image(payload)?;
+    let (width, height) = (msg.width as u32, msg.height as u32);
+    let image = match decode_pixels(&msg) {
+        Some(Pixels::Rgb8(bytes)) => RgbImage::from_raw(width, height, bytes).map(DynamicImage::ImageRgb8),
+        Some(Pixels::L8(bytes)) => GrayImage::from_raw(width, height, bytes).map(DynamicImage::ImageLuma8),
+        Some(Pixels::L16(bytes)) => {
+            let values = bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+            ImageBuffer::<Luma<u16>, _>::from_raw(width, height, values).map(DynamicImage::ImageLuma16)
+        }
+        Some(Pixels::Yuy2(bytes)) => {
+            let rgb = bytes
+                .chunks_exact(4)
+                .flat_map(|c| {
+                    let [y0, u, y1, v] = [c[0], c[1], c[2], c[3]];
+                    yuv_to_rgb(y0, u, v).into_iter().chain(yuv_to_rgb(y1, u, v))
+                })
+                .collect();
+            RgbImage::from_raw(width, height, rgb).map(DynamicImage::ImageRgb8)
+        }
+        Some(Pixels::Nv12(bytes)) => {
+            let (w, h) = (msg.width, msg.height);
+            let (luma, chroma) = bytes.split_at(w * h);
+            let mut rgb = Vec::with_capacity(w * h * 3);
+            for row in 0..h {
+                for col in 0..w {
+                    let uv = (row / 2) * w + (col / 2) * 2;
+                    let (u, v) = (chroma.get(uv).copied().unwrap_or(128), chroma.get(uv + 1).copied().unwrap_or(128));
+                    rgb.extend(yuv_to_rgb(luma[row * w + col], u, v));
                 }
             }
+            RgbImage::from_raw(width, height, rgb).map(DynamicImage::ImageRgb8)
         }
-        Err(e) => {
-            tracing::warn!("Failed to parse ROS image message: {}; skipping", e);
+        Some(Pixels::F32(_)) | None => None,
+    };
+    Ok(image)
+}
+
+/// BT.601 limited-range YUV to RGB
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = (y as f32 - 16.0) * 1.164;
+    let (d, e) = (u as f32 - 128.0, v as f32 - 128.0);
+    let clamp = |x: f32| x.round().clamp(0.0, 255.0) as u8;
+    [clamp(c + 1.596 * e), clamp(c - 0.392 * d - 0.813 * e), clamp(c + 2.017 * d)]
+}
+
+/// Image data in a layout rerun understands; multi-byte channels are little-endian
+enum Pixels {
+    Rgb8(Vec<u8>),
+    L8(Vec<u8>),
+    L16(Vec<u8>),
+    F32(Vec<u8>),
+    Yuy2(Vec<u8>),
+    Nv12(Vec<u8>),
+}
+
+fn decode_pixels(msg: &RosImage<'_>) -> Option<Pixels> {
+    let (width, height) = (msg.width, msg.height);
+    let pixels = match &*msg.encoding {
+        "rgb8" => Pixels::Rgb8(msg.packed_rows(width * 3, height)),
+        "bgr8" | "8UC3" => {
+            // 8UC3 is assumed BGR like OpenCV
+            let mut buf = msg.packed_rows(width * 3, height);
+            for px in buf.chunks_exact_mut(3) {
+                px.swap(0, 2); // BGR→RGB
+            }
+            Pixels::Rgb8(buf)
         }
+        "rgba8" => {
+            let rgba = msg.packed_rows(width * 4, height);
+            let mut rgb = Vec::with_capacity(width * height * 3);
+            for px in rgba.chunks_exact(4) {
+                rgb.extend_from_slice(&px[..3]);
+            }
+            Pixels::Rgb8(rgb)
+        }
+        "mono8" | "8UC1" => Pixels::L8(msg.packed_rows(width, height)),
+        // Keep the full 16-bit range; rerun handles the display mapping
+        "mono16" | "16UC1" => Pixels::L16(msg.packed_le(2)),
+        // 32-bit float single channel, logged losslessly as a float image
+        "32FC1" => Pixels::F32(msg.packed_le(4)),
+        "yuv422" | "uyvy" | "UYVY" => {
+            // UYVY (ROS "yuv422"): swap each byte pair to get YUYV/YUY2
+            let mut buf = msg.packed_rows(width * 2, height);
+            for pair in buf.chunks_exact_mut(2) {
+                pair.swap(0, 1);
+            }
+            Pixels::Yuy2(buf)
+        }
+        "yuv422_yuy2" | "yuyv" | "YUYV" => Pixels::Yuy2(msg.packed_rows(width * 2, height)),
+        // Full-resolution Y plane followed by interleaved half-resolution UV
+        "nv12" | "NV12" => Pixels::Nv12(msg.packed_rows(width, height + height.div_ceil(2))),
+        _ => return None,
+    };
+    Some(pixels)
+}
+
+fn pixels_to_image(pixels: Pixels, size: [u32; 2], opts: &ImageOptions) -> Result<DecodedImage> {
+    use rerun::datatypes::{ChannelDatatype, ColorModel, PixelFormat};
+
+    let (pixels, size) = match &opts.crop {
+        Some(crop) => crop_pixels(pixels, size, crop),
+        None => (pixels, size),
+    };
+    let (pixels, size) = match opts.scale {
+        Some(scale) if scale < 1.0 => scale_pixels(pixels, size, scale),
+        _ => (pixels, size),
+    };
+    let img = match pixels {
+        Pixels::L8(bytes) if opts.colormap.is_some() => {
+            return Ok(colormapped(bytes, size, ChannelDatatype::U8, opts));
+        }
+        Pixels::L16(bytes) if opts.colormap.is_some() => {
+            return Ok(colormapped(bytes, size, ChannelDatatype::U16, opts));
+        }
+        pixels if opts.encoding != ImageEncoding::Raw => match encode_pixels(&pixels, size, opts.encoding)? {
+            Some(encoded) => return Ok(DecodedImage::Encoded(encoded)),
+            // Formats the codec can't hold (float, YUV) stay raw
+            None => return pixels_to_image(pixels, size, &ImageOptions { encoding: ImageEncoding::Raw, ..*opts }),
+        },
+        Pixels::Rgb8(bytes) => rerun::archetypes::Image::from_rgb24(bytes, size),
+        Pixels::L8(bytes) => rerun::archetypes::Image::from_l8(bytes, size),
+        Pixels::L16(bytes) => rerun::archetypes::Image::from_color_model_and_bytes(
+            bytes,
+            size,
+            ColorModel::L,
+            ChannelDatatype::U16,
+        ),
+        Pixels::F32(bytes) => rerun::archetypes::Image::from_color_model_and_bytes(
+            bytes,
+            size,
+            ColorModel::L,
+            ChannelDatatype::F32,
+        ),
+        Pixels::Yuy2(bytes) => rerun::archetypes::Image::from_pixel_format(size, PixelFormat::YUY2, bytes),
+        Pixels::Nv12(bytes) => rerun::archetypes::Image::from_pixel_format(size, PixelFormat::NV12, bytes),
+    };
+    Ok(DecodedImage::Image(img))
+}
+
+/// Compress pixels to JPEG/PNG; None when the format can't be represented
+fn encode_pixels(
+    pixels: &Pixels,
+    [width, height]: [u32; 2],
+    encoding: ImageEncoding,
+) -> Result<Option<rerun::archetypes::EncodedImage>> {
+    use image::codecs::jpeg::JpegEncoder;
+    use image::codecs::png::PngEncoder;
+    use image::{ExtendedColorType, ImageEncoder};
+
+    let (bytes, color) = match pixels {
+        Pixels::Rgb8(bytes) => (bytes, ExtendedColorType::Rgb8),
+        Pixels::L8(bytes) => (bytes, ExtendedColorType::L8),
+        Pixels::L16(bytes) if encoding == ImageEncoding::Png => (bytes, ExtendedColorType::L16),
+        _ => return Ok(None),
+    };
+    let mut out = Vec::new();
+    let media_type = match encoding {
+        ImageEncoding::Jpeg { quality } => {
+            JpegEncoder::new_with_quality(&mut out, quality)
+                .write_image(bytes, width, height, color)
+                .context("encode jpeg")?;
+            rerun::components::MediaType::jpeg()
+        }
+        ImageEncoding::Png => {
+            // PngEncoder takes native-endian samples, matching our little-endian L16
+            PngEncoder::new(&mut out)
+                .write_image(bytes, width, height, color)
+                .context("encode png")?;
+            rerun::components::MediaType::png()
+        }
+        ImageEncoding::Raw => return Ok(None),
+    };
+    Ok(Some(rerun::archetypes::EncodedImage::new(out).with_media_type(media_type)))
+}
+
+/// Cut the crop rectangle out of an image; chroma-subsampled formats and rectangles
+/// outside the image are logged whole (see [`uncropped_reason`])
+fn crop_pixels(pixels: Pixels, [width, height]: [u32; 2], crop: &ImageCrop) -> (Pixels, [u32; 2]) {
+    let Some(ImageCrop { x, y, width: w, height: h }) = crop.within(width, height) else {
+        return (pixels, [width, height]);
+    };
+    let cut = |bytes: Vec<u8>, pixel_bytes: usize| -> Vec<u8> {
+        let (start, end) = (x as usize * pixel_bytes, (x + w) as usize * pixel_bytes);
+        bytes
+            .chunks_exact(width as usize * pixel_bytes)
+            .skip(y as usize)
+            .take(h as usize)
+            .flat_map(|row| &row[start..end])
+            .copied()
+            .collect()
+    };
+    let cropped = match pixels {
+        Pixels::Rgb8(bytes) => Pixels::Rgb8(cut(bytes, 3)),
+        Pixels::L8(bytes) => Pixels::L8(cut(bytes, 1)),
+        Pixels::L16(bytes) => Pixels::L16(cut(bytes, 2)),
+        Pixels::F32(bytes) => Pixels::F32(cut(bytes, 4)),
+        Pixels::Yuy2(_) | Pixels::Nv12(_) => return (pixels, [width, height]),
+    };
+    (cropped, [w, h])
+}
+
+/// Downscale an image; chroma-subsampled formats are logged at full resolution
+fn scale_pixels(pixels: Pixels, [width, height]: [u32; 2], scale: f64) -> (Pixels, [u32; 2]) {
+    use image::imageops::{resize, FilterType};
+    use image::{ImageBuffer, Luma};
+
+    let new_size = [
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    ];
+    let [w, h] = new_size;
+    let scaled = match pixels {
+        Pixels::Rgb8(bytes) => image::RgbImage::from_raw(width, height, bytes)
+            .map(|img| Pixels::Rgb8(resize(&img, w, h, FilterType::Triangle).into_raw())),
+        Pixels::L8(bytes) => image::GrayImage::from_raw(width, height, bytes)
+            .map(|img| Pixels::L8(resize(&img, w, h, FilterType::Triangle).into_raw())),
+        Pixels::L16(bytes) => {
+            let values: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            ImageBuffer::<Luma<u16>, _>::from_raw(width, height, values).map(|img| {
+                let out = resize(&img, w, h, FilterType::Triangle).into_raw();
+                Pixels::L16(out.iter().flat_map(|v| v.to_le_bytes()).collect())
+            })
+        }
+        Pixels::F32(bytes) => {
+            let values: Vec<f32> = bytes
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .collect();
+            // Nearest keeps depth values real instead of blending across edges
+            ImageBuffer::<Luma<f32>, _>::from_raw(width, height, values).map(|img| {
+                let out = resize(&img, w, h, FilterType::Nearest).into_raw();
+                Pixels::F32(out.iter().flat_map(|v| v.to_le_bytes()).collect())
+            })
+        }
+        Pixels::Yuy2(_) | Pixels::Nv12(_) => {
+            tracing::debug!("scaling is not supported for YUV images; logging full resolution");
+            return (pixels, [width, height]);
+        }
+    };
+    // Decoded buffers are always padded to width * height, so from_raw cannot fail
+    (scaled.expect("decoded image buffer matches its size"), new_size)
+}
+
+/// A single-channel image as a DepthImage so the viewer applies the colormap
+fn colormapped(
+    bytes: Vec<u8>,
+    size: [u32; 2],
+    datatype: rerun::datatypes::ChannelDatatype,
+    opts: &ImageOptions,
+) -> DecodedImage {
+    let mut img = rerun::archetypes::DepthImage::from_data_type_and_bytes(bytes, size, datatype);
+    if let Some(colormap) = opts.colormap {
+        img = img.with_colormap(colormap.to_rerun());
     }
-    Ok(())
+    if let Some(range) = opts.value_range {
+        img = img.with_depth_range(range);
+    }
+    DecodedImage::Depth(Box::new(img))
 }
 
 pub fn compressed_to_rerun(
@@ -126,38 +504,58 @@ pub fn compressed_to_rerun(
     topic: &str,
     ts: f64,
     payload: &[u8],
+    opts: &ImageOptions,
 ) -> Result<()> {
-    rec.set_timestamp_secs_since_epoch("ros_time", ts);
-    match parse_ros_compressed(payload) {
-        Ok((fmt, bytes)) => {
-            let fmt_lc = fmt.to_ascii_lowercase();
-
-            let dyn_img: DynamicImage = if fmt_lc.contains("png") {
-                image::load_from_memory_with_format(bytes, ImageFormat::Png)
-                    .context("decode png")?
-            } else if fmt_lc.contains("jpg") || fmt_lc.contains("jpeg") {
-                image::load_from_memory_with_format(bytes, ImageFormat::Jpeg)
-                    .context("decode jpeg")?
-            } else {
-                tracing::warn!(format=%fmt, "unsupported compressed image format; skipping");
-                return Ok(());
-            };
-
-            let rgb8 = dyn_img.to_rgb8();
-            let width = rgb8.width();
-            let height = rgb8.height();
-            let rr_path = normalize_path(topic);
-            let img = rerun::archetypes::Image::from_rgb24(rgb8.into_raw(), [width, height]);
-            rec.log(rr_path, &img)?;
-        }
-        Err(e) => {
-            tracing::warn!(
-                "Failed to parse ROS compressed image message: {}; skipping",
-                e
-            );
-        }
+    log_decoded_image(rec, topic, ts, decode_compressed(payload, opts)?)
+}
+
+/// Decode a sensor_msgs/CompressedImage payload; `Skipped` for unsupported formats
+pub fn decode_compressed(payload: &[u8], opts: &ImageOptions) -> Result<DecodedImage> {
+    let (fmt, bytes) = parse_ros_compressed(payload).context("failed to parse sensor_msgs/CompressedImage")?;
+    let fmt_lc = fmt.to_ascii_lowercase();
+
+    // Resizing and cropping need pixels, so pass-through only applies to whole images at full resolution
+    let full_res = opts.scale.is_none_or(|scale| scale >= 1.0) && opts.crop.is_none();
+    if opts.compressed_passthrough
+        && full_res
+        && !fmt_lc.contains("compresseddepth")
+        && let Some(media_type) = sniff_media_type(bytes)
+    {
+        let img = rerun::archetypes::EncodedImage::new(bytes.to_vec()).with_media_type(media_type);
+        return Ok(DecodedImage::Encoded(img));
+    }
+
+    let dyn_img: DynamicImage = if fmt_lc.contains("png") {
+        image::load_from_memory_with_format(bytes, ImageFormat::Png)
+            .context("decode png")?
+    } else if fmt_lc.contains("jpg") || fmt_lc.contains("jpeg") {
+        image::load_from_memory_with_format(bytes, ImageFormat::Jpeg)
+            .context("decode jpeg")?
+    } else if let Some(codec) = crate::mappings::video::video_codec(&fmt_lc) {
+        // H.264/H.265 packets published as CompressedImage
+        return Ok(DecodedImage::Video(codec, bytes.to_vec()));
+    } else {
+        tracing::warn!(format=%fmt, "unsupported compressed image format; skipping");
+        return Ok(DecodedImage::Skipped);
+    };
+
+    let rgb8 = dyn_img.to_rgb8();
+    let width = rgb8.width();
+    let height = rgb8.height();
+    // Already-compressed payloads are never re-encoded
+    let opts = ImageOptions { encoding: ImageEncoding::Raw, ..*opts };
+    pixels_to_image(Pixels::Rgb8(rgb8.into_raw()), [width, height], &opts)
+}
+
+/// Media type from the payload's magic bytes (format strings are not reliable)
+fn sniff_media_type(bytes: &[u8]) -> Option<rerun::components::MediaType> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some(rerun::components::MediaType::jpeg())
+    } else if bytes.starts_with(&[0x89, b'P', b'N', b'G']) {
+        Some(rerun::components::MediaType::png())
+    } else {
+        None
     }
-    Ok(())
 }
 
 fn normalize_path(topic: &str) -> String {
@@ -168,184 +566,257 @@ fn normalize_path(topic: &str) -> String {
     }
 }
 
-// ROS message parsing helpers
-fn parse_ros_image(payload: &[u8]) -> Result<(usize, usize, String, &[u8])> {
-    // Debug: log first 20 bytes
-    tracing::debug!(
-        "Parsing ROS image, payload length: {}, first 20 bytes: {:?}",
-        payload.len(),
-        &payload[..payload.len().min(20)]
-    );
-
-    let mut cursor = 0;
-
-    // Parse header (std_msgs/Header)
-    // seq (uint32)
-    if payload.len() < cursor + 4 {
-        return Err(anyhow::anyhow!("payload too short for header seq"));
-    }
-    let _seq = u32::from_le_bytes([
-        payload[cursor],
-        payload[cursor + 1],
-        payload[cursor + 2],
-        payload[cursor + 3],
-    ]);
-    cursor += 4;
-
-    // stamp (time): secs (uint32), nsecs (uint32)
-    if payload.len() < cursor + 8 {
-        return Err(anyhow::anyhow!("payload too short for header stamp"));
-    }
-    let _secs = u32::from_le_bytes([
-        payload[cursor],
-        payload[cursor + 1],
-        payload[cursor + 2],
-        payload[cursor + 3],
-    ]);
-    let _nsecs = u32::from_le_bytes([
-        payload[cursor + 4],
-        payload[cursor + 5],
-        payload[cursor + 6],
-        payload[cursor + 7],
-    ]);
-    cursor += 8;
-
-    // frame_id (string): length (uint32) + chars
-    if payload.len() < cursor + 4 {
-        return Err(anyhow::anyhow!("payload too short for frame_id length"));
-    }
-    let frame_id_len = u32::from_le_bytes([
-        payload[cursor],
-        payload[cursor + 1],
-        payload[cursor + 2],
-        payload[cursor + 3],
-    ]) as usize;
-    cursor += 4;
-
-    if payload.len() < cursor + frame_id_len {
-        return Err(anyhow::anyhow!("payload too short for frame_id"));
-    }
-    let _frame_id = String::from_utf8_lossy(&payload[cursor..cursor + frame_id_len]).to_string();
-    cursor += frame_id_len;
+/// Parsed sensor_msgs/Image
+struct RosImage<'a> {
+    width: usize,
+    height: usize,
+    encoding: Cow<'a, str>,
+    is_bigendian: bool,
+    step: usize,
+    data: &'a [u8],
+}
 
-    // height (uint32)
-    if payload.len() < cursor + 4 {
-        return Err(anyhow::anyhow!("payload too short for height"));
-    }
-    let height = u32::from_le_bytes([
-        payload[cursor],
-        payload[cursor + 1],
-        payload[cursor + 2],
-        payload[cursor + 3],
-    ]) as usize;
-    cursor += 4;
-
-    // width (uint32)
-    if payload.len() < cursor + 4 {
-        return Err(anyhow::anyhow!("payload too short for width"));
-    }
-    let width = u32::from_le_bytes([
-        payload[cursor],
-        payload[cursor + 1],
-        payload[cursor + 2],
-        payload[cursor + 3],
-    ]) as usize;
-    cursor += 4;
-
-    // encoding (string): length (uint32) + chars
-    if payload.len() < cursor + 4 {
-        return Err(anyhow::anyhow!("payload too short for encoding length"));
-    }
-    let encoding_len = u32::from_le_bytes([
-        payload[cursor],
-        payload[cursor + 1],
-        payload[cursor + 2],
-        payload[cursor + 3],
-    ]) as usize;
-    cursor += 4;
-
-    if payload.len() < cursor + encoding_len {
-        return Err(anyhow::anyhow!("payload too short for encoding"));
+impl RosImage<'_> {
+    /// Pixel data with row padding removed and channels converted to little-endian
+    fn packed_le(&self, bytes_per_pixel: usize) -> Vec<u8> {
+        let mut out = self.packed_rows(self.width * bytes_per_pixel, self.height);
+        if self.is_bigendian && bytes_per_pixel > 1 {
+            for px in out.chunks_exact_mut(bytes_per_pixel) {
+                px.reverse();
+            }
+        }
+        out
     }
-    let encoding = String::from_utf8_lossy(&payload[cursor..cursor + encoding_len]).to_string();
-    cursor += encoding_len;
 
-    // is_bigendian (uint8)
-    if payload.len() < cursor + 1 {
-        return Err(anyhow::anyhow!("payload too short for is_bigendian"));
+    /// First `rows` rows of `row_len` bytes each, with any row padding removed
+    fn packed_rows(&self, row_len: usize, rows: usize) -> Vec<u8> {
+        let step = if self.step >= row_len { self.step } else { row_len };
+        let mut out = Vec::with_capacity(row_len * rows);
+        for row in self.data.chunks(step).take(rows) {
+            out.extend_from_slice(&row[..row_len.min(row.len())]);
+        }
+        // Short data would trip rerun's size check, so pad with zeros
+        out.resize(row_len * rows, 0);
+        out
     }
-    let _is_bigendian = payload[cursor];
-    cursor += 1;
+}
 
-    // step (uint32)
-    if payload.len() < cursor + 4 {
-        return Err(anyhow::anyhow!("payload too short for step"));
-    }
-    let _step = u32::from_le_bytes([
-        payload[cursor],
-        payload[cursor + 1],
-        payload[cursor + 2],
-        payload[cursor + 3],
-    ]);
-    cursor += 4;
-
-    // data (uint8[]): length (uint32) + bytes
-    if payload.len() < cursor + 4 {
-        return Err(anyhow::anyhow!("payload too short for data length"));
-    }
-    let data_len = u32::from_le_bytes([
-        payload[cursor],
-        payload[cursor + 1],
-        payload[cursor + 2],
-        payload[cursor + 3],
-    ]) as usize;
-    cursor += 4;
-
-    if payload.len() < cursor + data_len {
-        return Err(anyhow::anyhow!("payload too short for data"));
-    }
-    let data = &payload[cursor..cursor + data_len];
+/// Why --image-crop logs an Image or CompressedImage whole, from its header alone
+pub fn uncropped_reason(tp: &str, payload: &[u8], crop: &ImageCrop) -> Option<&'static str> {
+    let (width, height) = if tp == "sensor_msgs/CompressedImage" {
+        let (_, bytes) = parse_ros_compressed(payload).ok()?;
+        image::ImageReader::new(std::io::Cursor::new(bytes)).with_guessed_format().ok()?.into_dimensions().ok()?
+    } else {
+        let msg = parse_ros_image(payload).ok()?;
+        if matches!(msg.encoding.as_ref(), "yuv422" | "uyvy" | "UYVY" | "yuv422_yuy2" | "yuyv" | "YUYV" | "nv12" | "NV12") {
+            return Some("cropping is not supported for YUV images");
+        }
+        (msg.width as u32, msg.height as u32)
+    };
+    crop.within(width, height).is_none().then_some("crop rectangle outside the image")
+}
+
+// ROS message parsing helpers
+fn parse_ros_image(payload: &[u8]) -> Result<RosImage<'_>> {
+    let mut cursor = Cursor::new(payload);
+    cursor.skip_header().context("header")?;
+    let height = cursor.u32().context("height")? as usize;
+    let width = cursor.u32().context("width")? as usize;
+    let encoding = cursor.str().context("encoding")?;
+    let is_bigendian = cursor.bool().context("is_bigendian")?;
+    let step = cursor.u32().context("step")? as usize;
+    let data = cursor.byte_array().context("data")?;
 
     // Validate dimensions
     if height == 0 || width == 0 || height > 10000 || width > 10000 {
-        return Err(anyhow::anyhow!(
-            "invalid image dimensions: {}x{}",
-            width,
-            height
-        ));
+        return Err(anyhow!("invalid image dimensions: {}x{}", width, height));
     }
-
-    tracing::debug!(
-        "Successfully parsed image: {}x{} {}, data size: {}",
+    Ok(RosImage {
         width,
         height,
         encoding,
-        data.len()
-    );
-    Ok((width, height, encoding, data))
+        is_bigendian,
+        step,
+        data,
+    })
+}
+
+fn parse_ros_compressed(payload: &[u8]) -> Result<(Cow<'_, str>, &[u8])> {
+    let mut cursor = Cursor::new(payload);
+    cursor.skip_header().context("header")?;
+    let format = cursor.str().context("format")?;
+    let data = cursor.byte_array().context("data")?;
+    if data.is_empty() {
+        return Err(anyhow!("no data found"));
+    }
+    Ok((format, data))
 }
 
-fn parse_ros_compressed(payload: &[u8]) -> Result<(String, &[u8])> {
-    let mut cursor = 0;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Find format (string: uint32 length + chars)
-    if payload.len() < 4 {
-        return Err(anyhow::anyhow!("payload too short for format length"));
+    #[test]
+    fn test_packed_le_strips_padding_and_swaps_big_endian() {
+        // 2x2 mono16, big-endian, 6-byte rows (2 bytes of padding each)
+        let data = [0x01, 0x02, 0x03, 0x04, 0xAA, 0xAA, 0x05, 0x06, 0x07, 0x08, 0xAA, 0xAA];
+        let msg = RosImage {
+            width: 2,
+            height: 2,
+            encoding: "mono16".into(),
+            is_bigendian: true,
+            step: 6,
+            data: &data,
+        };
+        assert_eq!(
+            msg.packed_le(2),
+            vec![0x02, 0x01, 0x04, 0x03, 0x06, 0x05, 0x08, 0x07]
+        );
     }
-    let fmt_len = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]) as usize;
-    cursor += 4;
 
-    if payload.len() < cursor + fmt_len {
-        return Err(anyhow::anyhow!("payload too short for format"));
+    #[test]
+    fn test_setting_for_topic() {
+        let colormaps = vec![
+            parse_image_colormap("grayscale").unwrap(),
+            parse_image_colormap("/thermal/image=inferno").unwrap(),
+        ];
+        assert_eq!(setting_for_topic(&colormaps, "thermal/image"), Some(&Colormap::Inferno));
+        assert_eq!(setting_for_topic(&colormaps, "/camera/mono"), Some(&Colormap::Grayscale));
+        assert_eq!(setting_for_topic(&colormaps[1..], "/camera/mono"), None);
+        assert!(parse_image_colormap("/thermal=nope").is_err());
+
+        assert_eq!(parse_image_scale("/cam/image_raw=0.25").unwrap().value, 0.25);
+        assert!(parse_image_scale("1.5").is_err());
+        assert_eq!(parse_image_every_nth("3").unwrap().value, 3);
+        assert!(parse_image_every_nth("/cam=0").is_err());
     }
-    let format = String::from_utf8_lossy(&payload[cursor..cursor + fmt_len]).to_string();
-    cursor += fmt_len;
 
-    // The rest should be data
-    if cursor >= payload.len() {
-        return Err(anyhow::anyhow!("no data found"));
+    #[test]
+    fn test_scale_pixels() {
+        let (pixels, size) = scale_pixels(Pixels::L16(vec![0x10; 4 * 4 * 2]), [4, 4], 0.5);
+        assert_eq!(size, [2, 2]);
+        match pixels {
+            Pixels::L16(bytes) => assert_eq!(bytes, vec![0x10; 2 * 2 * 2]),
+            _ => panic!("expected L16"),
+        }
+        let (_, size) = scale_pixels(Pixels::Nv12(vec![0; 6]), [2, 2], 0.5);
+        assert_eq!(size, [2, 2]);
     }
-    let data = &payload[cursor..];
 
-    Ok((format, data))
+    #[test]
+    fn test_crop_pixels() {
+        // 4x3 mono8 image whose pixels are their index
+        let crop = ImageCrop { x: 1, y: 1, width: 2, height: 5 };
+        let (pixels, size) = crop_pixels(Pixels::L8((0..12).collect()), [4, 3], &crop);
+        // Clamped to the two rows below the first
+        assert_eq!(size, [2, 2]);
+        match pixels {
+            Pixels::L8(bytes) => assert_eq!(bytes, [5, 6, 9, 10]),
+            _ => panic!("expected L8"),
+        }
+        let outside = ImageCrop { x: 10, y: 0, width: 2, height: 2 };
+        assert_eq!(crop_pixels(Pixels::L8(vec![0; 12]), [4, 3], &outside).1, [4, 3]);
+
+        let setting = parse_image_crop("/camera/wide=100, 0, 640, 480").unwrap();
+        assert_eq!(setting.topic.as_deref(), Some("/camera/wide"));
+        assert_eq!(setting.value, ImageCrop { x: 100, y: 0, width: 640, height: 480 });
+        assert!(parse_image_crop("0,0,640").is_err());
+        assert!(parse_image_crop("0,0,0,480").is_err());
+
+        // 4x3 sensor_msgs/Image of `encoding`
+        let image = |encoding: &str| {
+            let mut payload = vec![0u8; 16];
+            payload.extend_from_slice(&3u32.to_le_bytes());
+            payload.extend_from_slice(&4u32.to_le_bytes());
+            payload.extend_from_slice(&(encoding.len() as u32).to_le_bytes());
+            payload.extend_from_slice(encoding.as_bytes());
+            payload.push(0);
+            payload.extend_from_slice(&8u32.to_le_bytes());
+            payload.extend_from_slice(&24u32.to_le_bytes());
+            payload.extend_from_slice(&[0; 24]);
+            payload
+        };
+        assert_eq!(uncropped_reason("sensor_msgs/Image", &image("mono16"), &crop), None);
+        assert_eq!(uncropped_reason("sensor_msgs/Image", &image("mono16"), &outside), Some("crop rectangle outside the image"));
+        assert_eq!(uncropped_reason("sensor_msgs/Image", &image("yuv422"), &crop), Some("cropping is not supported for YUV images"));
+    }
+
+    #[test]
+    fn test_encode_pixels() {
+        let rgb = Pixels::Rgb8(vec![128; 8 * 8 * 3]);
+        let jpeg = encode_pixels(&rgb, [8, 8], ImageEncoding::Jpeg { quality: 80 }).unwrap();
+        assert!(jpeg.is_some());
+        let l16 = Pixels::L16(vec![0; 4 * 4 * 2]);
+        assert!(encode_pixels(&l16, [4, 4], ImageEncoding::Jpeg { quality: 80 }).unwrap().is_none());
+        assert!(encode_pixels(&l16, [4, 4], ImageEncoding::Png).unwrap().is_some());
+        assert!(parse_image_encoding("jpeg", 0).is_err());
+        assert_eq!(parse_image_encoding("png", 90).unwrap(), ImageEncoding::Png);
+    }
+
+    #[test]
+    fn test_parse_ros_compressed() {
+        let mut payload = vec![0u8; 12]; // seq, stamp
+        payload.extend_from_slice(&3u32.to_le_bytes());
+        payload.extend_from_slice(b"cam");
+        payload.extend_from_slice(&4u32.to_le_bytes());
+        payload.extend_from_slice(b"jpeg");
+        payload.extend_from_slice(&3u32.to_le_bytes());
+        payload.extend_from_slice(&[0xFF, 0xD8, 0xFF]);
+        let (format, data) = parse_ros_compressed(&payload).unwrap();
+        assert_eq!(format, "jpeg");
+        assert_eq!(data, &[0xFF, 0xD8, 0xFF]);
+    }
+
+    #[test]
+    fn test_decode_compressed() {
+        let compressed = |format: &str, data: &[u8]| {
+            let mut payload = vec![0u8; 12]; // seq, stamp
+            payload.extend_from_slice(&0u32.to_le_bytes()); // frame_id
+            payload.extend_from_slice(&(format.len() as u32).to_le_bytes());
+            payload.extend_from_slice(format.as_bytes());
+            payload.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            payload.extend_from_slice(data);
+            payload
+        };
+        let mut png = Vec::new();
+        DynamicImage::new_rgb8(4, 2)
+            .write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let opts = ImageOptions::default();
+        assert!(matches!(decode_compressed(&compressed("png", &png), &opts).unwrap(), DecodedImage::Image(_)));
+        let passthrough = ImageOptions { compressed_passthrough: true, ..opts };
+        assert!(matches!(
+            decode_compressed(&compressed("png", &png), &passthrough).unwrap(),
+            DecodedImage::Encoded(_)
+        ));
+        assert!(matches!(
+            decode_compressed(&compressed("h264", &[0, 0, 0, 1]), &opts).unwrap(),
+            DecodedImage::Video(rerun::components::VideoCodec::H264, _)
+        ));
+        assert!(matches!(decode_compressed(&compressed("bmp", &[0; 4]), &opts).unwrap(), DecodedImage::Skipped));
+        assert!(decode_compressed(&compressed("jpeg", &[0xFF, 0xD8, 0xFF]), &opts).is_err());
+    }
+
+    #[test]
+    fn test_sniff_media_type() {
+        assert_eq!(sniff_media_type(&[0xFF, 0xD8, 0xFF, 0xE0]), Some(rerun::components::MediaType::jpeg()));
+        assert_eq!(sniff_media_type(b"\x89PNG\r\n"), Some(rerun::components::MediaType::png()));
+        // compressedDepth payloads start with a config header, not an image
+        assert_eq!(sniff_media_type(&[0, 0, 0, 0, 0x89, b'P']), None);
+    }
+
+    #[test]
+    fn test_packed_rows_nv12_planes() {
+        // 2x2 NV12 with 3-byte rows: two Y rows then one interleaved UV row
+        let data = [1, 2, 0, 3, 4, 0, 5, 6, 0];
+        let msg = RosImage {
+            width: 2,
+            height: 2,
+            encoding: "nv12".into(),
+            is_bigendian: false,
+            step: 3,
+            data: &data,
+        };
+        assert_eq!(msg.packed_rows(2, 3), vec![1, 2, 3, 4, 5, 6]);
+    }
 }
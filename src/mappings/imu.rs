@@ -1,16 +1,20 @@
 use anyhow::Result;
-// Manual ROS message parsing for sensor_msgs/Imu
+
+use crate::mappings::scalars::ScalarColumns;
+use crate::ros_codec::Cursor;
+
 pub fn imu_to_rerun(
     rec: &rerun::RecordingStream,
     topic: &str,
     ts: f64,
     payload: &[u8],
+    scalars: &mut ScalarColumns,
 ) -> anyhow::Result<()> {
-    // Parse IMU message manually
+    // Parse the IMU message
     let imu_data = parse_ros_imu(payload)?;
     
     // Set timestamp
-    rec.set_timestamp_secs_since_epoch("ros_time", ts);
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
     
     let entity_path = format!("/{}/imu", topic.trim_start_matches('/'));
     
@@ -64,15 +68,8 @@ pub fn imu_to_rerun(
         imu_data.linear_acceleration.z.powi(2)
     ).sqrt();
     
-    rec.log(
-        format!("{}/angular_velocity_magnitude", entity_path),
-        &rerun::archetypes::Scalars::new(vec![angular_magnitude])
-    )?;
-    
-    rec.log(
-        format!("{}/linear_acceleration_magnitude", entity_path),
-        &rerun::archetypes::Scalars::new(vec![linear_magnitude])
-    )?;
+    scalars.push(rec, &format!("{}/angular_velocity_magnitude", entity_path), angular_magnitude)?;
+    scalars.push(rec, &format!("{}/linear_acceleration_magnitude", entity_path), linear_magnitude)?;
     
     tracing::debug!(
         "Added IMU data to entity: {} with orientation: {:?}, angular_velocity: {:?}, linear_acceleration: {:?}",
@@ -116,82 +113,18 @@ fn is_valid_quaternion(q: &Quaternion) -> bool {
     norm_sq > 0.01 && (norm_sq - 1.0).abs() < 0.1
 }
 
-// ROS message parsing helper
 fn parse_ros_imu(payload: &[u8]) -> Result<ImuData> {
-    let mut cursor = 0;
-
-    // Parse header (std_msgs/Header) - skip for now
-    cursor = skip_header(payload, cursor)?;
-
-    // Parse orientation (geometry_msgs/Quaternion)
-    if payload.len() < cursor + 32 {
-        return Err(anyhow::anyhow!("payload too short for orientation"));
-    }
-    let orientation = Quaternion {
-        x: f64::from_le_bytes([
-            payload[cursor], payload[cursor + 1], payload[cursor + 2], payload[cursor + 3],
-            payload[cursor + 4], payload[cursor + 5], payload[cursor + 6], payload[cursor + 7],
-        ]),
-        y: f64::from_le_bytes([
-            payload[cursor + 8], payload[cursor + 9], payload[cursor + 10], payload[cursor + 11],
-            payload[cursor + 12], payload[cursor + 13], payload[cursor + 14], payload[cursor + 15],
-        ]),
-        z: f64::from_le_bytes([
-            payload[cursor + 16], payload[cursor + 17], payload[cursor + 18], payload[cursor + 19],
-            payload[cursor + 20], payload[cursor + 21], payload[cursor + 22], payload[cursor + 23],
-        ]),
-        w: f64::from_le_bytes([
-            payload[cursor + 24], payload[cursor + 25], payload[cursor + 26], payload[cursor + 27],
-            payload[cursor + 28], payload[cursor + 29], payload[cursor + 30], payload[cursor + 31],
-        ]),
-    };
-    cursor += 32;
-
-    // Skip orientation_covariance (9 * f64 = 72 bytes)
-    cursor += 72;
-
-    // Parse angular_velocity (geometry_msgs/Vector3)
-    if payload.len() < cursor + 24 {
-        return Err(anyhow::anyhow!("payload too short for angular_velocity"));
-    }
-    let angular_velocity = Vector3 {
-        x: f64::from_le_bytes([
-            payload[cursor], payload[cursor + 1], payload[cursor + 2], payload[cursor + 3],
-            payload[cursor + 4], payload[cursor + 5], payload[cursor + 6], payload[cursor + 7],
-        ]),
-        y: f64::from_le_bytes([
-            payload[cursor + 8], payload[cursor + 9], payload[cursor + 10], payload[cursor + 11],
-            payload[cursor + 12], payload[cursor + 13], payload[cursor + 14], payload[cursor + 15],
-        ]),
-        z: f64::from_le_bytes([
-            payload[cursor + 16], payload[cursor + 17], payload[cursor + 18], payload[cursor + 19],
-            payload[cursor + 20], payload[cursor + 21], payload[cursor + 22], payload[cursor + 23],
-        ]),
-    };
-    cursor += 24;
-
-    // Skip angular_velocity_covariance (9 * f64 = 72 bytes)
-    cursor += 72;
-
-    // Parse linear_acceleration (geometry_msgs/Vector3)
-    if payload.len() < cursor + 24 {
-        return Err(anyhow::anyhow!("payload too short for linear_acceleration"));
-    }
-    let linear_acceleration = Vector3 {
-        x: f64::from_le_bytes([
-            payload[cursor], payload[cursor + 1], payload[cursor + 2], payload[cursor + 3],
-            payload[cursor + 4], payload[cursor + 5], payload[cursor + 6], payload[cursor + 7],
-        ]),
-        y: f64::from_le_bytes([
-            payload[cursor + 8], payload[cursor + 9], payload[cursor + 10], payload[cursor + 11],
-            payload[cursor + 12], payload[cursor + 13], payload[cursor + 14], payload[cursor + 15],
-        ]),
-        z: f64::from_le_bytes([
-            payload[cursor + 16], payload[cursor + 17], payload[cursor + 18], payload[cursor + 19],
-            payload[cursor + 20], payload[cursor + 21], payload[cursor + 22], payload[cursor + 23],
-        ]),
-    };
-
+    let mut cursor = Cursor::new(payload);
+    cursor.skip_header()?;
+    let orientation = Quaternion { x: cursor.f64()?, y: cursor.f64()?, z: cursor.f64()?, w: cursor.f64()? };
+    // orientation_covariance
+    cursor.skip(72)?;
+    let angular_velocity = Vector3 { x: cursor.f64()?, y: cursor.f64()?, z: cursor.f64()? };
+    // angular_velocity_covariance
+    cursor.skip(72)?;
+    let linear_acceleration = Vector3 { x: cursor.f64()?, y: cursor.f64()?, z: cursor.f64()? };
+    // linear_acceleration_covariance
+    cursor.skip(72)?;
     Ok(ImuData {
         orientation,
         angular_velocity,
@@ -199,28 +132,6 @@ fn parse_ros_imu(payload: &[u8]) -> Result<ImuData> {
     })
 }
 
-fn skip_header(payload: &[u8], mut cursor: usize) -> Result<usize> {
-    // seq (uint32)
-    cursor += 4;
-
-    // stamp (time): secs (uint32), nsecs (uint32)
-    cursor += 8;
-
-    // frame_id (string): length (uint32) + chars
-    if payload.len() < cursor + 4 {
-        return Err(anyhow::anyhow!("payload too short for frame_id length"));
-    }
-    let frame_id_len = u32::from_le_bytes([
-        payload[cursor],
-        payload[cursor + 1],
-        payload[cursor + 2],
-        payload[cursor + 3],
-    ]) as usize;
-    cursor += 4 + frame_id_len;
-
-    Ok(cursor)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -262,4 +173,20 @@ mod tests {
         };
         assert!(is_valid_quaternion(&almost_normalized_q));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_ros_imu() {
+        let mut payload = [0u8; 12].to_vec();
+        payload.extend_from_slice(&3u32.to_le_bytes());
+        payload.extend_from_slice(b"imu");
+        let values = [[0.0, 0.0, 0.0, 1.0].as_slice(), &[0.0; 9], &[0.1, 0.2, 0.3], &[0.0; 9], &[0.0, 0.0, 9.81], &[0.0; 9]];
+        for v in values.concat() {
+            payload.extend_from_slice(&f64::to_le_bytes(v));
+        }
+        let imu = parse_ros_imu(&payload).unwrap();
+        assert!(is_valid_quaternion(&imu.orientation));
+        assert_eq!(imu.angular_velocity.y, 0.2);
+        assert_eq!(imu.linear_acceleration.z, 9.81);
+        assert!(parse_ros_imu(&payload[..payload.len() - 80]).is_err());
+    }
+}
@@ -1,138 +1,477 @@
-//! LaserScan → Rerun Points2D or LineStrips2D (implemented in v0.2.0)
+//! LaserScan / MultiEchoLaserScan → Rerun Points2D or LineStrips2D (implemented in v0.2.0)
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use nalgebra::Point3;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
 
+use crate::mappings::colormap::Colormap;
+use crate::mappings::roi::RoiCrop;
+use crate::ros_codec::Cursor;
+use crate::mappings::style::Style;
+
+/// What to color LaserScan points by
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub enum ScanColorBy {
+    #[default]
+    None,
+    Intensity,
+    Range,
+}
+
+pub fn parse_scan_color(s: &str) -> Result<ScanColorBy> {
+    match s {
+        "none" => Ok(ScanColorBy::None),
+        "intensity" => Ok(ScanColorBy::Intensity),
+        "range" => Ok(ScanColorBy::Range),
+        _ => Err(anyhow!("Invalid scan-color: {}", s)),
+    }
+}
+
+/// LaserScan conversion options
+#[derive(Debug, Clone, Copy)]
+pub struct LaserScanOptions<'a> {
+    /// Use LineStrips instead of Points
+    pub as_lines: bool,
+    /// Log as 3D primitives (z=0 in the scanner frame) resolved into the root frame via TF
+    pub as_3d: bool,
+    /// Per-point coloring (points mode only)
+    pub color_by: ScanColorBy,
+    /// Colormap used for intensity/range coloring, auto-scaled per scan
+    pub colormap: Colormap,
+    /// Per-topic --color/--radius/--line-width/--label; a color replaces `color_by`
+    pub style: Option<&'a Style>,
+    /// Drop the points outside the --roi box
+    pub roi: Option<RoiCrop<'a>>,
+}
+
+impl Default for LaserScanOptions<'_> {
+    fn default() -> Self {
+        Self {
+            as_lines: false,
+            as_3d: false,
+            color_by: ScanColorBy::None,
+            colormap: Colormap::Turbo,
+            style: None,
+            roi: None,
+        }
+    }
+}
+
+/// Rolling buffer of the last N scans per entity, in the root frame
+#[derive(Debug, Default)]
+pub struct ScanAccumulator {
+    max_scans: usize,
+    scans: HashMap<String, VecDeque<AccumulatedScan>>,
+    // Merged points and colors of the last push, reused from scan to scan
+    merged_points: Vec<[f32; 3]>,
+    merged_colors: Vec<[u8; 3]>,
+}
+
+#[derive(Debug)]
+struct AccumulatedScan {
+    points: Vec<[f32; 3]>,
+    colors: Option<Vec<[u8; 3]>>,
+}
+
+impl ScanAccumulator {
+    pub fn new(max_scans: usize) -> Self {
+        Self {
+            max_scans,
+            ..Default::default()
+        }
+    }
+
+    /// Add a scan and return the accumulated points (and colors if every scan has them)
+    fn push(&mut self, key: &str, points: Vec<[f32; 3]>, colors: Option<Vec<[u8; 3]>>) -> (&[[f32; 3]], Option<&[[u8; 3]]>) {
+        if !self.scans.contains_key(key) {
+            self.scans.insert(key.to_string(), VecDeque::new());
+        }
+        let buffer = self.scans.get_mut(key).unwrap();
+        buffer.push_back(AccumulatedScan { points, colors });
+        while buffer.len() > self.max_scans.max(1) {
+            buffer.pop_front();
+        }
+        self.merged_points.clear();
+        self.merged_points.extend(buffer.iter().flat_map(|s| s.points.iter().copied()));
+        self.merged_colors.clear();
+        let all_colored = buffer.iter().all(|s| s.colors.is_some());
+        if all_colored {
+            self.merged_colors.extend(buffer.iter().flat_map(|s| s.colors.iter().flatten().copied()));
+        }
+        (&self.merged_points, all_colored.then_some(self.merged_colors.as_slice()))
+    }
+}
+
+/// Parsed sensor_msgs/LaserScan
+#[derive(Debug)]
+pub struct LaserScan {
+    pub frame_id: String,
+    /// Cartesian points in the scanner frame; NaN for invalid ranges
+    pub points: Vec<(f32, f32)>,
+    /// Raw ranges, one per point
+    pub ranges: Vec<f32>,
+    /// Intensities, one per point (empty if the scanner does not report them)
+    pub intensities: Vec<f32>,
+}
+
+impl LaserScan {
+    /// Colors for the valid points (same order as the finite entries of `points`)
+    fn valid_point_colors(&self, color_by: ScanColorBy, colormap: Colormap) -> Option<Vec<[u8; 3]>> {
+        let values = match color_by {
+            ScanColorBy::None => return None,
+            ScanColorBy::Range => &self.ranges,
+            ScanColorBy::Intensity if self.intensities.len() == self.points.len() => &self.intensities,
+            ScanColorBy::Intensity => {
+                tracing::debug!("LaserScan has no intensities; not coloring");
+                return None;
+            }
+        };
+        let valid: Vec<f32> = self
+            .points
+            .iter()
+            .zip(values)
+            .filter(|(p, _)| p.0.is_finite() && p.1.is_finite())
+            .map(|(_, v)| *v)
+            .collect();
+        Some(colormap.map_auto_range(&valid))
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn laserscan_to_rerun(
     rec: &rerun::RecordingStream,
     topic: &str,
     ts: f64,
     payload: &[u8],
-    as_lines: bool,
+    opts: &LaserScanOptions<'_>,
+    root_frame: &str,
+    tf_graph: Option<&crate::mappings::tf::TfGraph>,
+    tf_mode: crate::mappings::tf::TfMode,
+    accumulator: Option<&mut ScanAccumulator>,
 ) -> Result<()> {
-    rec.set_timestamp_secs_since_epoch("ros_time", ts);
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
+
+    let scan = parse_laserscan_msg(payload)?;
+    log_scan(rec, &normalize_path(topic), scan, ts, opts, root_frame, tf_graph, tf_mode, accumulator)
+}
+
+/// How to log sensor_msgs/MultiEchoLaserScan
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub enum MultiEchoMode {
+    /// First echo of each beam
+    #[default]
+    First,
+    /// Echo with the highest intensity (first echo if there are no intensities)
+    Strongest,
+    /// Every echo index as its own entity: /<topic>/echo_<i>
+    All,
+}
 
-    let points = parse_laserscan(payload)?;
+pub fn parse_multi_echo_mode(s: &str) -> Result<MultiEchoMode> {
+    match s {
+        "first" => Ok(MultiEchoMode::First),
+        "strongest" => Ok(MultiEchoMode::Strongest),
+        "all" => Ok(MultiEchoMode::All),
+        _ => Err(anyhow!("Invalid multi-echo mode: {}", s)),
+    }
+}
 
+#[allow(clippy::too_many_arguments)]
+pub fn multi_echo_laserscan_to_rerun(
+    rec: &rerun::RecordingStream,
+    topic: &str,
+    ts: f64,
+    payload: &[u8],
+    opts: &LaserScanOptions<'_>,
+    mode: MultiEchoMode,
+    root_frame: &str,
+    tf_graph: Option<&crate::mappings::tf::TfGraph>,
+    tf_mode: crate::mappings::tf::TfMode,
+    mut accumulator: Option<&mut ScanAccumulator>,
+) -> Result<()> {
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
+
+    let msg = parse_multi_echo_laserscan(payload)?;
     let rr_path = normalize_path(topic);
-    if as_lines {
-        // Create a single LineStrip2D with contiguous valid points
-        let mut strips = vec![vec![]];
-        for &pt in &points {
-            if pt.0.is_finite() && pt.1.is_finite() {
-                strips.last_mut().unwrap().push([pt.0, pt.1]);
-            } else {
-                // Start new strip on invalid
-                if !strips.last().unwrap().is_empty() {
-                    strips.push(vec![]);
-                }
+    match mode {
+        MultiEchoMode::First | MultiEchoMode::Strongest => {
+            let scan = msg.select_echo(mode == MultiEchoMode::Strongest);
+            log_scan(rec, &rr_path, scan, ts, opts, root_frame, tf_graph, tf_mode, accumulator)
+        }
+        MultiEchoMode::All => {
+            for echo in 0..msg.max_echoes() {
+                let scan = msg.echo(echo);
+                let echo_path = format!("{}/echo_{}", rr_path.trim_end_matches('/'), echo);
+                log_scan(rec, &echo_path, scan, ts, opts, root_frame, tf_graph, tf_mode, accumulator.as_deref_mut())?;
             }
+            Ok(())
         }
-        if strips.last().unwrap().is_empty() {
-            strips.pop();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn log_scan(
+    rec: &rerun::RecordingStream,
+    rr_path: &str,
+    mut scan: LaserScan,
+    ts: f64,
+    opts: &LaserScanOptions<'_>,
+    root_frame: &str,
+    tf_graph: Option<&crate::mappings::tf::TfGraph>,
+    tf_mode: crate::mappings::tf::TfMode,
+    accumulator: Option<&mut ScanAccumulator>,
+) -> Result<()> {
+    let unstyled = Style::default();
+    let style = opts.style.unwrap_or(&unstyled);
+    // Points outside the ROI become invalid ranges: dropped, and line strips break there
+    if let Some(crop) = &opts.roi
+        && let Some(mask) = crop.mask(&scan.frame_id, ts, scan.points.iter().map(|&(x, y)| Point3::new(x as f64, y as f64, 0.0)))
+    {
+        for (point, inside) in scan.points.iter_mut().zip(mask) {
+            if !inside {
+                *point = (f32::NAN, f32::NAN);
+            }
         }
-        if !strips.is_empty() {
-            let line_strips = rerun::archetypes::LineStrips2D::new(strips);
-            rec.log(rr_path, &line_strips)?;
+    }
+    let point_colors = scan.valid_point_colors(opts.color_by, opts.colormap);
+    let colors = if opts.as_lines { None } else { point_colors.clone() };
+    let points = scan.points;
+
+    // Place the scan in the root frame so it moves with the robot
+    let iso = if opts.as_3d || accumulator.is_some() {
+        let iso = match tf_graph {
+            Some(tf) if !scan.frame_id.is_empty() && scan.frame_id != root_frame => {
+                tf.resolve_pose(root_frame, &scan.frame_id, ts, tf_mode)
+            }
+            _ => None,
+        };
+        if iso.is_none() && scan.frame_id != root_frame {
+            tracing::debug!(frame = %scan.frame_id, "no TF from scan frame to {root_frame}; logging in scanner frame");
         }
+        iso
     } else {
-        let valid_points: Vec<[f32; 2]> = points
-            .into_iter()
+        None
+    };
+    let to_3d = |(x, y): (f32, f32)| -> [f32; 3] {
+        let p = Point3::new(x as f64, y as f64, 0.0);
+        let p = iso.map(|iso| iso * p).unwrap_or(p);
+        [p.x as f32, p.y as f32, p.z as f32]
+    };
+
+    // Rolling local map of the last N scans, each transformed at its own timestamp
+    if let Some(acc) = accumulator {
+        let root_points: Vec<[f32; 3]> = points
+            .iter()
             .filter(|p| p.0.is_finite() && p.1.is_finite())
-            .map(|p| [p.0, p.1])
+            .map(|&p| to_3d(p))
             .collect();
+        let (map_points, map_colors) = acc.push(rr_path, root_points, point_colors);
+        let pts = rerun::archetypes::Points3D::new(map_points.iter().copied());
+        let pts = match map_colors {
+            Some(colors) => pts.with_colors(colors.iter().copied()),
+            None => pts,
+        };
+        let pts = style.points3d(pts);
+        rec.log(format!("{}/accumulated", rr_path.trim_end_matches('/')), &pts)?;
+    }
+
+    if opts.as_3d {
+        if opts.as_lines {
+            let strips: Vec<Vec<[f32; 3]>> = split_strips(&points)
+                .into_iter()
+                .map(|strip| strip.into_iter().map(&to_3d).collect())
+                .collect();
+            if !strips.is_empty() {
+                rec.log(rr_path, &style.lines3d(rerun::archetypes::LineStrips3D::new(strips)))?;
+            }
+        } else {
+            let valid_points = points.into_iter().filter(|p| p.0.is_finite() && p.1.is_finite()).map(&to_3d);
+            let pts = rerun::archetypes::Points3D::new(valid_points);
+            let pts = match colors {
+                Some(colors) => pts.with_colors(colors),
+                None => pts,
+            };
+            rec.log(rr_path, &style.points3d(pts))?;
+        }
+        return Ok(());
+    }
+
+    if opts.as_lines {
+        // Create LineStrips2D from contiguous valid points
+        let strips: Vec<Vec<[f32; 2]>> = split_strips(&points)
+            .into_iter()
+            .map(|strip| strip.into_iter().map(|(x, y)| [x, y]).collect())
+            .collect();
+        if !strips.is_empty() {
+            let line_strips = rerun::archetypes::LineStrips2D::new(strips);
+            rec.log(rr_path, &style.lines2d(line_strips))?;
+        }
+    } else {
+        let valid_points = points.into_iter().filter(|p| p.0.is_finite() && p.1.is_finite()).map(|p| [p.0, p.1]);
         let pts = rerun::archetypes::Points2D::new(valid_points);
-        rec.log(rr_path, &pts)?;
+        let pts = match colors {
+            Some(colors) => pts.with_colors(colors),
+            None => pts,
+        };
+        rec.log(rr_path, &style.points2d(pts))?;
     }
 
     Ok(())
 }
 
-pub fn parse_laserscan(payload: &[u8]) -> Result<Vec<(f32, f32)>> {
-    let mut cursor = 0;
-
-    // Skip header
-    cursor = skip_header(payload, cursor)?;
-
-    // angle_min (float32)
-    let angle_min = read_f32_le(payload, &mut cursor)?;
-    // angle_max (float32)
-    let _angle_max = read_f32_le(payload, &mut cursor)?;
-    // angle_increment (float32)
-    let angle_increment = read_f32_le(payload, &mut cursor)?;
-    // time_increment (float32) - skip
-    cursor += 4;
-    // scan_time (float32) - skip
-    cursor += 4;
-    // range_min (float32)
-    let range_min = read_f32_le(payload, &mut cursor)?;
-    // range_max (float32)
-    let range_max = read_f32_le(payload, &mut cursor)?;
-
-    // ranges length (uint32)
-    let ranges_len = read_u32_le(payload, &mut cursor)? as usize;
-    // intensities length (uint32) - skip
-    let _intensities_len = read_u32_le(payload, &mut cursor)?;
-
-    // ranges (float32[])
-    let mut ranges = Vec::with_capacity(ranges_len);
-    for _ in 0..ranges_len {
-        let r = read_f32_le(payload, &mut cursor)?;
-        ranges.push(r);
-    }
-
-    // Compute points
-    let mut points = Vec::new();
-    for (i, &r) in ranges.iter().enumerate() {
-        if r.is_finite() && r >= range_min && r <= range_max {
-            let angle = angle_min + i as f32 * angle_increment;
-            let x = r * angle.cos();
-            let y = r * angle.sin();
-            points.push((x, y));
+/// Split scan points into strips of contiguous valid points
+fn split_strips(points: &[(f32, f32)]) -> Vec<Vec<(f32, f32)>> {
+    let mut strips = vec![vec![]];
+    for &pt in points {
+        if pt.0.is_finite() && pt.1.is_finite() {
+            strips.last_mut().unwrap().push(pt);
         } else {
-            points.push((f32::NAN, f32::NAN)); // invalid
+            // Start new strip on invalid
+            if !strips.last().unwrap().is_empty() {
+                strips.push(vec![]);
+            }
         }
     }
+    if strips.last().unwrap().is_empty() {
+        strips.pop();
+    }
+    strips
+}
+
+pub fn parse_laserscan(payload: &[u8]) -> Result<Vec<(f32, f32)>> {
+    Ok(parse_laserscan_msg(payload)?.points)
+}
 
-    Ok(points)
+/// Beam geometry shared by LaserScan and MultiEchoLaserScan
+#[derive(Clone, Copy, Debug)]
+struct ScanGeometry {
+    angle_min: f32,
+    angle_increment: f32,
+    range_min: f32,
+    range_max: f32,
 }
 
-fn read_f32_le(payload: &[u8], cursor: &mut usize) -> Result<f32> {
-    if *cursor + 4 > payload.len() {
-        return Err(anyhow::anyhow!("payload too short"));
+impl ScanGeometry {
+    fn parse(cursor: &mut Cursor<'_>) -> Result<Self> {
+        let angle_min = cursor.f32()?;
+        let _angle_max = cursor.f32()?;
+        let angle_increment = cursor.f32()?;
+        // time_increment, scan_time
+        cursor.skip(8)?;
+        let range_min = cursor.f32()?;
+        let range_max = cursor.f32()?;
+        Ok(Self {
+            angle_min,
+            angle_increment,
+            range_min,
+            range_max,
+        })
     }
-    let val = f32::from_le_bytes([
-        payload[*cursor],
-        payload[*cursor + 1],
-        payload[*cursor + 2],
-        payload[*cursor + 3],
-    ]);
-    *cursor += 4;
-    Ok(val)
+
+    fn to_scan(self, frame_id: String, ranges: Vec<f32>, intensities: Vec<f32>) -> LaserScan {
+        let mut points = Vec::with_capacity(ranges.len());
+        for (i, &r) in ranges.iter().enumerate() {
+            if r.is_finite() && r >= self.range_min && r <= self.range_max {
+                let angle = self.angle_min + i as f32 * self.angle_increment;
+                let x = r * angle.cos();
+                let y = r * angle.sin();
+                points.push((x, y));
+            } else {
+                points.push((f32::NAN, f32::NAN)); // invalid
+            }
+        }
+        LaserScan {
+            frame_id,
+            points,
+            ranges,
+            intensities,
+        }
+    }
+}
+
+pub fn parse_laserscan_msg(payload: &[u8]) -> Result<LaserScan> {
+    let mut cursor = Cursor::new(payload);
+    let frame_id = cursor.header()?.frame_id.into_owned();
+    let geometry = ScanGeometry::parse(&mut cursor)?;
+    let ranges = cursor.f32_array()?;
+    // May be empty
+    let intensities = cursor.f32_array()?;
+    Ok(geometry.to_scan(frame_id, ranges, intensities))
+}
+
+/// Parsed sensor_msgs/MultiEchoLaserScan: one echo list per beam
+#[derive(Debug)]
+pub struct MultiEchoLaserScan {
+    frame_id: String,
+    geometry: ScanGeometry,
+    pub ranges: Vec<Vec<f32>>,
+    pub intensities: Vec<Vec<f32>>,
 }
 
-fn read_u32_le(payload: &[u8], cursor: &mut usize) -> Result<u32> {
-    if *cursor + 4 > payload.len() {
-        return Err(anyhow::anyhow!("payload too short"));
+impl MultiEchoLaserScan {
+    /// Largest number of echoes reported by any beam
+    pub fn max_echoes(&self) -> usize {
+        self.ranges.iter().map(|e| e.len()).max().unwrap_or(0)
+    }
+
+    /// Scan made of echo `index` of every beam (NaN where a beam has fewer echoes)
+    pub fn echo(&self, index: usize) -> LaserScan {
+        let pick = |echoes: &Vec<Vec<f32>>| -> Vec<f32> {
+            echoes.iter().map(|e| e.get(index).copied().unwrap_or(f32::NAN)).collect()
+        };
+        let intensities = if self.intensities.len() == self.ranges.len() {
+            pick(&self.intensities)
+        } else {
+            vec![]
+        };
+        self.geometry.to_scan(self.frame_id.clone(), pick(&self.ranges), intensities)
+    }
+
+    /// Scan made of the first echo, or the highest-intensity echo of every beam
+    pub fn select_echo(&self, strongest: bool) -> LaserScan {
+        if !strongest || self.intensities.len() != self.ranges.len() {
+            return self.echo(0);
+        }
+        let mut ranges = Vec::with_capacity(self.ranges.len());
+        let mut intensities = Vec::with_capacity(self.ranges.len());
+        for (beam_ranges, beam_intensities) in self.ranges.iter().zip(&self.intensities) {
+            let best = beam_intensities
+                .iter()
+                .enumerate()
+                .filter(|(i, v)| v.is_finite() && *i < beam_ranges.len())
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .map(|(i, _)| i)
+                .unwrap_or(0);
+            ranges.push(beam_ranges.get(best).copied().unwrap_or(f32::NAN));
+            intensities.push(beam_intensities.get(best).copied().unwrap_or(f32::NAN));
+        }
+        self.geometry.to_scan(self.frame_id.clone(), ranges, intensities)
     }
-    let val = u32::from_le_bytes([
-        payload[*cursor],
-        payload[*cursor + 1],
-        payload[*cursor + 2],
-        payload[*cursor + 3],
-    ]);
-    *cursor += 4;
-    Ok(val)
 }
 
-fn skip_header(payload: &[u8], mut cursor: usize) -> Result<usize> {
-    // seq (uint32)
-    cursor += 4;
-    // stamp (uint32 + uint32)
-    cursor += 8;
-    // frame_id (string)
-    let len = read_u32_le(payload, &mut cursor)? as usize;
-    cursor += len;
-    Ok(cursor)
+pub fn parse_multi_echo_laserscan(payload: &[u8]) -> Result<MultiEchoLaserScan> {
+    let mut cursor = Cursor::new(payload);
+    let frame_id = cursor.header()?.frame_id.into_owned();
+    let geometry = ScanGeometry::parse(&mut cursor)?;
+    // LaserEcho[] ranges and intensities, each LaserEcho a float32[]; intensities may be empty
+    let ranges = read_echo_array(&mut cursor)?;
+    let intensities = read_echo_array(&mut cursor)?;
+    Ok(MultiEchoLaserScan {
+        frame_id,
+        geometry,
+        ranges,
+        intensities,
+    })
+}
+
+fn read_echo_array(cursor: &mut Cursor<'_>) -> Result<Vec<Vec<f32>>> {
+    let len = cursor.u32()? as usize;
+    let mut echoes = Vec::with_capacity(len.min(cursor.remaining() / 4));
+    for _ in 0..len {
+        echoes.push(cursor.f32_array()?);
+    }
+    Ok(echoes)
 }
 
 fn normalize_path(topic: &str) -> String {
@@ -170,13 +509,13 @@ mod tests {
         // ranges len
         let ranges_len = 10;
         data.extend_from_slice(&(ranges_len as u32).to_le_bytes());
-        // intensities len
-        data.extend_from_slice(&0u32.to_le_bytes());
         // ranges
         for i in 0..ranges_len {
             let r = if i % 2 == 0 { 1.0f32 } else { f32::NAN };
             data.extend_from_slice(&r.to_le_bytes());
         }
+        // intensities len
+        data.extend_from_slice(&0u32.to_le_bytes());
 
         let points = parse_laserscan(&data).unwrap();
 
@@ -185,4 +524,89 @@ mod tests {
         assert!(points[0].0.is_finite() && points[0].1.is_finite());
         assert!(points[1].0.is_nan() || points[1].1.is_nan());
     }
+
+    #[test]
+    fn test_parse_laserscan_intensities() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0u32.to_le_bytes()); // seq
+        data.extend_from_slice(&0u64.to_le_bytes()); // stamp
+        data.extend_from_slice(&5u32.to_le_bytes());
+        data.extend_from_slice(b"laser");
+        for v in [0.0f32, 1.0, 0.5, 0.0, 0.0, 0.1, 10.0] {
+            // angle_min, angle_max, angle_increment, time_increment, scan_time, range_min, range_max
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend_from_slice(&3u32.to_le_bytes());
+        for r in [1.0f32, 20.0, 3.0] {
+            data.extend_from_slice(&r.to_le_bytes());
+        }
+        data.extend_from_slice(&3u32.to_le_bytes());
+        for i in [100.0f32, 200.0, 300.0] {
+            data.extend_from_slice(&i.to_le_bytes());
+        }
+
+        let scan = parse_laserscan_msg(&data).unwrap();
+        assert_eq!(scan.frame_id, "laser");
+        assert_eq!(scan.ranges, vec![1.0, 20.0, 3.0]);
+        assert_eq!(scan.intensities, vec![100.0, 200.0, 300.0]);
+        // Range 20.0 is beyond range_max and is dropped from the colored points
+        let colors = scan.valid_point_colors(ScanColorBy::Intensity, Colormap::Grayscale).unwrap();
+        assert_eq!(colors, vec![[0, 0, 0], [255, 255, 255]]);
+        assert!(scan.valid_point_colors(ScanColorBy::None, Colormap::Grayscale).is_none());
+    }
+
+    #[test]
+    fn test_parse_multi_echo_laserscan() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0u32.to_le_bytes()); // seq
+        data.extend_from_slice(&0u64.to_le_bytes()); // stamp
+        data.extend_from_slice(&0u32.to_le_bytes()); // frame_id len
+        for v in [0.0f32, 1.0, 0.5, 0.0, 0.0, 0.1, 10.0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        let write_echoes = |data: &mut Vec<u8>, beams: &[&[f32]]| {
+            data.extend_from_slice(&(beams.len() as u32).to_le_bytes());
+            for echoes in beams {
+                data.extend_from_slice(&(echoes.len() as u32).to_le_bytes());
+                for e in *echoes {
+                    data.extend_from_slice(&e.to_le_bytes());
+                }
+            }
+        };
+        write_echoes(&mut data, &[&[1.0, 2.0], &[3.0]]);
+        write_echoes(&mut data, &[&[10.0, 50.0], &[5.0]]);
+
+        let msg = parse_multi_echo_laserscan(&data).unwrap();
+        assert_eq!(msg.max_echoes(), 2);
+        assert_eq!(msg.select_echo(false).ranges, vec![1.0, 3.0]);
+        assert_eq!(msg.select_echo(true).ranges, vec![2.0, 3.0]);
+        let second = msg.echo(1);
+        assert_eq!(second.ranges[0], 2.0);
+        assert!(second.ranges[1].is_nan());
+        assert!(second.points[1].0.is_nan());
+    }
+
+    #[test]
+    fn test_scan_accumulator_rolls() {
+        let mut acc = ScanAccumulator::new(2);
+        acc.push("scan", vec![[1.0, 0.0, 0.0]], Some(vec![[1, 1, 1]]));
+        acc.push("scan", vec![[2.0, 0.0, 0.0]], Some(vec![[2, 2, 2]]));
+        let (points, colors) = acc.push("scan", vec![[3.0, 0.0, 0.0]], Some(vec![[3, 3, 3]]));
+        assert_eq!(points, vec![[2.0, 0.0, 0.0], [3.0, 0.0, 0.0]]);
+        assert_eq!(colors, Some(&[[2, 2, 2], [3, 3, 3]][..]));
+        // Other entities are independent, and a scan without colors drops them
+        let (points, colors) = acc.push("other", vec![[4.0, 0.0, 0.0]], None);
+        assert_eq!(points.len(), 1);
+        assert!(colors.is_none());
+    }
+
+    #[test]
+    fn test_split_strips() {
+        let nan = f32::NAN;
+        let points = [(1.0, 0.0), (2.0, 0.0), (nan, nan), (nan, nan), (3.0, 0.0)];
+        let strips = split_strips(&points);
+        assert_eq!(strips.len(), 2);
+        assert_eq!(strips[0], vec![(1.0, 0.0), (2.0, 0.0)]);
+        assert_eq!(strips[1], vec![(3.0, 0.0)]);
+    }
 }
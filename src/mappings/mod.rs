@@ -1,7 +1,22 @@
+pub mod camera;
+pub mod classes;
+pub mod clock;
+pub mod colormap;
+pub mod depth;
+pub mod detection;
+pub mod generic;
 pub mod gps;
 pub mod images; // v0.1.0
 pub mod imu; // v0.4.1
 pub mod laserscan; // v0.2.0
 pub mod nav; // v0.3.0
 pub mod pointcloud; // v0.2.0
+pub mod registry;
+pub mod rename;
+pub mod roi;
+pub mod scalars;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod style;
 pub mod tf; // v0.3.0 // v0.2.0
+pub mod video;
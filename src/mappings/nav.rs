@@ -2,19 +2,68 @@
 
 use anyhow::Result;
 use nalgebra::{Isometry3, Quaternion, Translation3, UnitQuaternion};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+
+use crate::ros_codec::{Cursor, Header};
+use crate::mappings::rename::RenameRules;
+use crate::mappings::style::Style;
+
+/// Growing odometry trajectory per topic, logged as a LineStrips3D
+#[derive(Debug, Default)]
+pub struct OdomTrajectory {
+    /// Oldest points are dropped beyond this many (0 = unlimited)
+    max_points: usize,
+    /// Keep one odometry message out of every_nth
+    every_nth: u64,
+    tracks: HashMap<String, Track>,
+}
+
+#[derive(Debug, Default)]
+struct Track {
+    messages: u64,
+    points: VecDeque<[f32; 3]>,
+}
+
+impl OdomTrajectory {
+    pub fn new(max_points: usize, every_nth: u64) -> Self {
+        Self {
+            max_points,
+            every_nth: every_nth.max(1),
+            tracks: HashMap::new(),
+        }
+    }
+
+    /// Add a position; returns the whole trajectory when the point was kept
+    fn push(&mut self, key: &str, point: [f32; 3]) -> Option<&[[f32; 3]]> {
+        let track = self.tracks.entry(key.to_string()).or_default();
+        let keep = track.messages.is_multiple_of(self.every_nth);
+        track.messages += 1;
+        if !keep {
+            return None;
+        }
+        track.points.push_back(point);
+        while self.max_points > 0 && track.points.len() > self.max_points {
+            track.points.pop_front();
+        }
+        Some(track.points.make_contiguous())
+    }
+}
 
 #[allow(clippy::too_many_arguments)]
 pub fn odometry_to_rerun(
     rec: &rerun::RecordingStream,
-    #[allow(unused_variables)] _topic: &str,
+    topic: &str,
     ts: f64,
     payload: &[u8],
     root_frame: &str,
-    #[allow(unused_variables)] map_frame: &[String],
+    #[allow(unused_variables)] map_frame: &RenameRules,
     tf_graph: Option<&crate::mappings::tf::TfGraph>,
     tf_mode: crate::mappings::tf::TfMode,
+    trajectory: Option<&mut OdomTrajectory>,
+    style: &Style,
 ) -> Result<()> {
-    rec.set_timestamp_secs_since_epoch("ros_time", ts);
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
 
     let odom = parse_odometry(payload)?;
     let parent_frame = odom.header.frame_id;
@@ -30,16 +79,25 @@ pub fn odometry_to_rerun(
     log_transform(rec, &parent_path, &child_path, &iso, ts)?;
 
     // If TF is available, resolve to root
-    if let Some(tf) = tf_graph && let Some(root_iso) = tf.resolve(root_frame, &parent_frame, ts, tf_mode) {
+    let mut position = iso.translation.vector;
+    if let Some(tf) = tf_graph && let Some(root_iso) = tf.resolve_pose(root_frame, &parent_frame, ts, tf_mode) {
         let combined_iso = root_iso * iso;
         let root_path = format!("/{root_frame}");
         log_transform(rec, &root_path, &child_path, &combined_iso, ts)?;
+        position = combined_iso.translation.vector;
+    }
+
+    // Trajectory in the root frame (odometry frame when TF can't resolve it)
+    if let Some(trajectory) = trajectory
+        && let Some(points) = trajectory.push(topic, [position.x as f32, position.y as f32, position.z as f32])
+    {
+        let entity_path = format!("/{root_frame}/trajectories/{}", topic.trim_start_matches('/'));
+        rec.log(entity_path, &style.lines3d(rerun::archetypes::LineStrips3D::new([points.to_vec()])))?;
     }
 
     Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
 #[allow(clippy::too_many_arguments)]
 pub fn pose_stamped_to_rerun(
     rec: &rerun::RecordingStream,
@@ -47,14 +105,14 @@ pub fn pose_stamped_to_rerun(
     ts: f64,
     payload: &[u8],
     root_frame: &str,
-    topic_renames: &[String],
-    #[allow(unused_variables)] map_frame: &[String],
+    topic_renames: &RenameRules,
+    #[allow(unused_variables)] map_frame: &RenameRules,
     tf_graph: Option<&crate::mappings::tf::TfGraph>,
     tf_mode: crate::mappings::tf::TfMode,
 ) -> Result<()> {
-    rec.set_timestamp_secs_since_epoch("ros_time", ts);
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
 
-    let pose_stamped = parse_pose_stamped(payload, &mut 0)?;
+    let pose_stamped = parse_pose_stamped(&mut Cursor::new(payload))?;
     let frame_id = pose_stamped.header.frame_id;
 
     let entity_path = map_topic_to_path(topic, topic_renames).unwrap_or_else(|| format!("/{root_frame}/poses/{topic}"));
@@ -63,7 +121,7 @@ pub fn pose_stamped_to_rerun(
 
     // If TF available, resolve to root
     let final_iso = if let Some(tf) = tf_graph {
-        if let Some(root_iso) = tf.resolve(root_frame, &frame_id, ts, tf_mode) {
+        if let Some(root_iso) = tf.resolve_pose(root_frame, &frame_id, ts, tf_mode) {
             root_iso * iso
         } else {
             iso
@@ -78,29 +136,40 @@ pub fn pose_stamped_to_rerun(
     Ok(())
 }
 
+/// Log a nav_msgs/Path as a LineStrips3D in the root frame
+///
+/// Each pose is resolved through TF at its own header.stamp minus `stamp_base`
+/// (the timeline origin, as for TF samples); with no base, or a zero stamp, the
+/// message time `ts` is used.
 #[allow(clippy::too_many_arguments)]
 pub fn path_to_rerun(
     rec: &rerun::RecordingStream,
     topic: &str,
     ts: f64,
+    stamp_base: Option<f64>,
     payload: &[u8],
     root_frame: &str,
-    topic_renames: &[String],
-    #[allow(unused_variables)] map_frame: &[String],
+    topic_renames: &RenameRules,
+    #[allow(unused_variables)] map_frame: &RenameRules,
     tf_graph: Option<&crate::mappings::tf::TfGraph>,
     tf_mode: crate::mappings::tf::TfMode,
+    style: &Style,
 ) -> Result<()> {
-    rec.set_timestamp_secs_since_epoch("ros_time", ts);
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
 
     let path = parse_path(payload)?;
     let entity_path = map_topic_to_path(topic, topic_renames).unwrap_or_else(|| format!("/{root_frame}/paths/{topic}"));
 
     let mut points = Vec::new();
     for pose_stamped in &path.poses {
-        let frame_id = pose_stamped.header.frame_id.clone();
+        let frame_id = &pose_stamped.header.frame_id;
         let iso = pose_to_isometry(&pose_stamped.pose);
+        let pose_ts = match stamp_base {
+            Some(base) if pose_stamped.header.stamp > 0.0 => pose_stamped.header.stamp - base,
+            _ => ts,
+        };
         let final_iso = if let Some(tf) = tf_graph {
-            if let Some(root_iso) = tf.resolve(root_frame, &frame_id, pose_stamped.header.stamp, tf_mode) {
+            if let Some(root_iso) = tf.resolve_pose(root_frame, frame_id, pose_ts, tf_mode) {
                 root_iso * iso
             } else {
                 iso
@@ -113,7 +182,7 @@ pub fn path_to_rerun(
     }
 
     if !points.is_empty() {
-        let line_strips = rerun::archetypes::LineStrips3D::new(vec![points]);
+        let line_strips = style.lines3d(rerun::archetypes::LineStrips3D::new(vec![points]));
         rec.log(entity_path, &line_strips)?;
     }
 
@@ -137,22 +206,12 @@ fn log_transform(
     Ok(())
 }
 
-fn map_frame_to_path(frame: &str, root_frame: &str, map_frame: &[String]) -> String {
-    for mapping in map_frame {
-        if let Some((ros_frame, rr_path)) = mapping.split_once('=') && ros_frame == frame {
-            return rr_path.to_string();
-        }
-    }
-    format!("/{root_frame}/{frame}")
+fn map_frame_to_path(frame: &str, root_frame: &str, map_frame: &RenameRules) -> String {
+    map_frame.rename(frame).unwrap_or_else(|| format!("/{root_frame}/{frame}"))
 }
 
-fn map_topic_to_path(topic: &str, topic_renames: &[String]) -> Option<String> {
-    for rename in topic_renames {
-        if let Some((ros_topic, rr_path)) = rename.split_once('=') && ros_topic == topic {
-            return Some(rr_path.to_string());
-        }
-    }
-    None
+fn map_topic_to_path(topic: &str, topic_renames: &RenameRules) -> Option<String> {
+    topic_renames.rename(topic)
 }
 
 fn pose_to_isometry(pose: &Pose) -> Isometry3<f64> {
@@ -162,12 +221,6 @@ fn pose_to_isometry(pose: &Pose) -> Isometry3<f64> {
 }
 
 // Parsing structs and functions
-#[derive(Debug)]
-struct Header {
-    stamp: f64,
-    frame_id: String,
-}
-
 #[derive(Debug)]
 struct Vector3 {
     x: f64,
@@ -190,9 +243,9 @@ struct Pose {
 }
 
 #[derive(Debug)]
-struct Odometry {
-    header: Header,
-    child_frame_id: String,
+struct Odometry<'a> {
+    header: Header<'a>,
+    child_frame_id: Cow<'a, str>,
     pose: PoseWithCovariance,
 }
 
@@ -202,97 +255,151 @@ struct PoseWithCovariance {
 }
 
 #[derive(Debug)]
-struct PoseStamped {
-    header: Header,
+struct PoseStamped<'a> {
+    header: Header<'a>,
     pose: Pose,
 }
 
 #[derive(Debug)]
-struct Path {
-    #[allow(dead_code)] header: Header,
-    poses: Vec<PoseStamped>,
+struct Path<'a> {
+    #[allow(dead_code)] header: Header<'a>,
+    poses: Vec<PoseStamped<'a>>,
 }
 
-fn parse_odometry(payload: &[u8]) -> Result<Odometry> {
-    let mut cursor = 0;
-    let header = parse_header(payload, &mut cursor)?;
-    let child_frame_id = parse_string(payload, &mut cursor)?;
-    let pose = parse_pose_with_covariance(payload, &mut cursor)?;
+fn parse_odometry(payload: &[u8]) -> Result<Odometry<'_>> {
+    let mut cursor = Cursor::new(payload);
+    let header = cursor.header()?;
+    let child_frame_id = cursor.str()?;
+    let pose = parse_pose_with_covariance(&mut cursor)?;
     Ok(Odometry { header, child_frame_id, pose })
 }
 
-fn parse_pose_stamped(payload: &[u8], cursor: &mut usize) -> Result<PoseStamped> {
-    let header = parse_header(payload, cursor)?;
-    let pose = parse_pose(payload, cursor)?;
+fn parse_pose_stamped<'a>(cursor: &mut Cursor<'a>) -> Result<PoseStamped<'a>> {
+    let header = cursor.header()?;
+    let pose = parse_pose(cursor)?;
     Ok(PoseStamped { header, pose })
 }
 
-fn parse_path(payload: &[u8]) -> Result<Path> {
-    let mut cursor = 0;
-    let header = parse_header(payload, &mut cursor)?;
-    let len = read_u32_le(payload, &mut cursor)? as usize;
-    let mut poses = Vec::with_capacity(len);
+fn parse_path(payload: &[u8]) -> Result<Path<'_>> {
+    let mut cursor = Cursor::new(payload);
+    let header = cursor.header()?;
+    let len = cursor.u32()? as usize;
+    // A PoseStamped is at least 72 bytes
+    let mut poses = Vec::with_capacity(len.min(cursor.remaining() / 72));
     for _ in 0..len {
-        poses.push(parse_pose_stamped(payload, &mut cursor)?);
+        poses.push(parse_pose_stamped(&mut cursor)?);
     }
     Ok(Path { header, poses })
 }
 
-fn parse_header(payload: &[u8], cursor: &mut usize) -> Result<Header> {
-    *cursor += 4; // seq
-    let stamp = read_f64_le(payload, cursor)?; // time
-    let frame_id = parse_string(payload, cursor)?;
-    Ok(Header { stamp, frame_id })
+/// ROS1 `time`: uint32 secs + uint32 nsecs, as seconds since epoch
+pub fn read_ros_time(payload: &[u8], cursor: &mut usize) -> Result<f64> {
+    let mut reader = Cursor::at(payload, *cursor);
+    let time = reader.time()?;
+    *cursor = reader.position();
+    Ok(time)
 }
 
-fn parse_pose_with_covariance(payload: &[u8], cursor: &mut usize) -> Result<PoseWithCovariance> {
-    let pose = parse_pose(payload, cursor)?;
-    *cursor += 36 * 8; // covariance matrix
+fn parse_pose_with_covariance(cursor: &mut Cursor<'_>) -> Result<PoseWithCovariance> {
+    let pose = parse_pose(cursor)?;
+    cursor.skip(36 * 8)?; // covariance matrix
     Ok(PoseWithCovariance { pose })
 }
 
-fn parse_pose(payload: &[u8], cursor: &mut usize) -> Result<Pose> {
-    let position = parse_vector3(payload, cursor)?;
-    let orientation = parse_quaternion(payload, cursor)?;
+fn parse_pose(cursor: &mut Cursor<'_>) -> Result<Pose> {
+    let position = Vector3 { x: cursor.f64()?, y: cursor.f64()?, z: cursor.f64()? };
+    let orientation = RosQuaternion { x: cursor.f64()?, y: cursor.f64()?, z: cursor.f64()?, w: cursor.f64()? };
     Ok(Pose { position, orientation })
 }
 
-fn parse_vector3(payload: &[u8], cursor: &mut usize) -> Result<Vector3> {
-    let x = read_f64_le(payload, cursor)?;
-    let y = read_f64_le(payload, cursor)?;
-    let z = read_f64_le(payload, cursor)?;
-    Ok(Vector3 { x, y, z })
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// std_msgs/Header as serialized by rosbag: seq, secs, nsecs, frame_id
+    fn header(seq: u32, secs: u32, nsecs: u32, frame_id: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&seq.to_le_bytes());
+        data.extend_from_slice(&secs.to_le_bytes());
+        data.extend_from_slice(&nsecs.to_le_bytes());
+        data.extend_from_slice(&(frame_id.len() as u32).to_le_bytes());
+        data.extend_from_slice(frame_id.as_bytes());
+        data
+    }
 
-fn parse_quaternion(payload: &[u8], cursor: &mut usize) -> Result<RosQuaternion> {
-    let x = read_f64_le(payload, cursor)?;
-    let y = read_f64_le(payload, cursor)?;
-    let z = read_f64_le(payload, cursor)?;
-    let w = read_f64_le(payload, cursor)?;
-    Ok(RosQuaternion { x, y, z, w })
-}
+    fn pose_stamped(secs: u32, nsecs: u32, x: f64) -> Vec<u8> {
+        let mut data = header(0, secs, nsecs, "map");
+        for v in [x, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data
+    }
 
-fn parse_string(payload: &[u8], cursor: &mut usize) -> Result<String> {
-    let len = read_u32_le(payload, cursor)? as usize;
-    let bytes = &payload[*cursor..*cursor + len];
-    *cursor += len;
-    Ok(String::from_utf8_lossy(bytes).to_string())
-}
+    #[test]
+    fn test_parse_header_stamp() {
+        let payload = header(42, 1_700_000_000, 250_000_000, "base_link");
+        let header = Cursor::new(&payload).header().unwrap();
+        assert!((header.stamp - 1_700_000_000.25).abs() < 1e-6);
+        assert_eq!(header.frame_id, "base_link");
+    }
 
-fn read_u32_le(payload: &[u8], cursor: &mut usize) -> Result<u32> {
-    if *cursor + 4 > payload.len() {
-        return Err(anyhow::anyhow!("Unexpected end of payload"));
+    #[test]
+    fn test_parse_path_pose_stamps() {
+        let mut payload = header(1, 1_700_000_010, 0, "map");
+        payload.extend_from_slice(&2u32.to_le_bytes());
+        payload.extend_from_slice(&pose_stamped(1_700_000_000, 500_000_000, 1.0));
+        payload.extend_from_slice(&pose_stamped(1_700_000_001, 0, 2.0));
+        let path = parse_path(&payload).unwrap();
+        assert!((path.header.stamp - 1_700_000_010.0).abs() < 1e-6);
+        assert_eq!(path.poses.len(), 2);
+        assert!((path.poses[0].header.stamp - 1_700_000_000.5).abs() < 1e-6);
+        assert!((path.poses[1].header.stamp - 1_700_000_001.0).abs() < 1e-6);
+        assert_eq!(path.poses[1].pose.position.x, 2.0);
+    }
+
+    #[test]
+    fn test_odom_trajectory_decimation_and_cap() {
+        let mut trajectory = OdomTrajectory::new(3, 2);
+        let kept: Vec<bool> = (0..10)
+            .map(|i| trajectory.push("/odom", [i as f32, 0.0, 0.0]).is_some())
+            .collect();
+        assert_eq!(kept, [true, false, true, false, true, false, true, false, true, false]);
+        // Only the last 3 kept points remain
+        let points = trajectory.push("/odom", [10.0, 0.0, 0.0]).unwrap();
+        assert_eq!(points, [[6.0, 0.0, 0.0], [8.0, 0.0, 0.0], [10.0, 0.0, 0.0]]);
+        // Each topic has its own track
+        assert_eq!(trajectory.push("/other", [1.0, 2.0, 3.0]).unwrap(), [[1.0, 2.0, 3.0]]);
     }
-    let val = u32::from_le_bytes(payload[*cursor..*cursor + 4].try_into().unwrap());
-    *cursor += 4;
-    Ok(val)
-}
 
-fn read_f64_le(payload: &[u8], cursor: &mut usize) -> Result<f64> {
-    if *cursor + 8 > payload.len() {
-        return Err(anyhow::anyhow!("Unexpected end of payload"));
+    #[test]
+    fn test_odometry_resolved_into_root_frame() {
+        use crate::mappings::tf::{Header as TfHeader, RosQuaternion, TfGraph, TfMode, Transform, TransformStamped, Vector3 as TfVector3};
+
+        // odom is 1 m along world x and turned 90° left
+        let s = std::f64::consts::FRAC_1_SQRT_2;
+        let mut graph = TfGraph::new();
+        graph.add_static_transform(&TransformStamped {
+            header: TfHeader { stamp: 0.0, frame_id: "world".to_string() },
+            child_frame_id: "odom".to_string(),
+            transform: Transform {
+                translation: TfVector3 { x: 1.0, y: 0.0, z: 0.0 },
+                rotation: RosQuaternion { x: 0.0, y: 0.0, z: s, w: s },
+            },
+        });
+        // base_link 1 m ahead in odom
+        let mut payload = header(0, 0, 0, "odom");
+        payload.extend_from_slice(&9u32.to_le_bytes());
+        payload.extend_from_slice(b"base_link");
+        for v in [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0f64] {
+            payload.extend_from_slice(&v.to_le_bytes());
+        }
+        payload.extend_from_slice(&[0; 36 * 8]);
+
+        let (rec, _) = rerun::RecordingStreamBuilder::new("test").memory().unwrap();
+        let mut trajectory = OdomTrajectory::new(0, 1);
+        let style = Style::default();
+        odometry_to_rerun(&rec, "/odom", 0.0, &payload, "world", &RenameRules::default(), Some(&graph), TfMode::Nearest, Some(&mut trajectory), &style).unwrap();
+        let point = trajectory.push("/odom", [0.0; 3]).unwrap()[0];
+        assert!((point[0] - 1.0).abs() < 1e-6 && (point[1] - 1.0).abs() < 1e-6, "{point:?}");
     }
-    let val = f64::from_le_bytes(payload[*cursor..*cursor + 8].try_into().unwrap());
-    *cursor += 8;
-    Ok(val)
 }
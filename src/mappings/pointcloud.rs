@@ -1,7 +1,39 @@
 //! PointCloud2 → Rerun Points3D (implemented in v0.2.0)
 
 use anyhow::Result;
+use nalgebra::{Isometry3, Point3};
 use rerun::components::Position3D;
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use crate::mappings::colormap::Colormap;
+use crate::mappings::images::{parse_topic_setting, TopicSetting};
+use crate::mappings::roi::{retain, RoiCrop};
+use crate::mappings::style::Style;
+use crate::ros_codec::Cursor;
+
+/// Point field warnings already given during one conversion, per (topic, field)
+#[derive(Debug, Default)]
+pub struct FieldWarnings {
+    missing: HashSet<(String, String)>,
+    out_of_range: HashSet<(String, String)>,
+}
+
+impl FieldWarnings {
+    /// Warn that `topic`'s clouds have no `field`, once per topic and field
+    fn missing(&mut self, topic: &str, field: &str) {
+        if self.missing.insert((topic.to_string(), field.to_string())) {
+            tracing::warn!(%topic, %field, "PointCloud2 has no such field; ignoring");
+        }
+    }
+
+    /// Warn that `count` ids of `field` do not fit a u16, once per topic and field
+    fn out_of_range(&mut self, topic: &str, field: &str, count: usize) {
+        if count > 0 && self.out_of_range.insert((topic.to_string(), field.to_string())) {
+            tracing::warn!(%topic, %field, count, "PointCloud2 ids outside 0..=65535; logging them as 0");
+        }
+    }
+}
 
 /// Applies a 3D rotation defined by Euler angles (roll, pitch, yaw) in degrees
 /// to the coordinates of a point (x, y, z)
@@ -41,16 +73,109 @@ fn apply_rotation(x: f32, y: f32, z: f32, rotation: &[f64; 3]) -> (f32, f32, f32
     (x_rot, y_rot, z_rot)
 }
 
+/// PointCloud2 conversion options
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PointCloudOptions<'a> {
+    /// Euler rotation in degrees [roll, pitch, yaw] applied before TF
+    pub rotation: Option<&'a [f64; 3]>,
+    /// Also log organized clouds as a range image
+    pub range_image: bool,
+    /// Point field logged as per-point class ids (e.g. "label")
+    pub class_field: Option<&'a str>,
+    /// Point field logged as per-point keypoint ids (e.g. "ring")
+    pub keypoint_field: Option<&'a str>,
+    /// Scalar point field mapped onto the turbo colormap (e.g. "intensity"), replacing rgb colors
+    pub color_field: Option<&'a str>,
+    /// Keep only every Nth point (0 or 1 keeps all)
+    pub every_nth_point: usize,
+    /// Per-topic --color/--radius/--label; a color replaces rgb and `color_field` colors
+    pub style: Option<&'a Style>,
+    /// Keep only the points inside the --roi box
+    pub roi: Option<RoiCrop<'a>>,
+}
+
+/// Parse "FIELD" or "TOPIC=FIELD" for --pointcloud-color-field
+pub fn parse_pointcloud_color_field(s: &str) -> Result<TopicSetting<String>> {
+    parse_topic_setting(s, |v| Ok(v.to_string()))
+}
+
+/// Parse "N" or "TOPIC=N" (N >= 1) for --pointcloud-downsample
+pub fn parse_pointcloud_downsample(s: &str) -> Result<TopicSetting<usize>> {
+    parse_topic_setting(s, |v| match v.parse::<usize>() {
+        Ok(n) if n >= 1 => Ok(n),
+        _ => Err(anyhow::anyhow!("Invalid point cloud downsample factor (expected an integer >= 1): {}", v)),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn pointcloud2_to_rerun(
     rec: &rerun::RecordingStream,
     topic: &str,
     ts: f64,
     payload: &[u8],
-    rotation: Option<&[f64; 3]>,
+    opts: &PointCloudOptions,
+    warnings: &mut FieldWarnings,
+    root_frame: &str,
+    tf_graph: Option<&crate::mappings::tf::TfGraph>,
+    tf_mode: crate::mappings::tf::TfMode,
 ) -> Result<()> {
-    rec.set_timestamp_secs_since_epoch("ros_time", ts);
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
+
+    let id_fields: Vec<&str> = [opts.class_field, opts.keypoint_field].into_iter().flatten().collect();
+    let parsed = parse_points(payload, opts.rotation, &id_fields)?;
+    let mut colors = parsed.colors;
+    if let Some(field) = opts.color_field {
+        match parse_field_values(payload, field)? {
+            Some(values) => colors = Some(Colormap::Turbo.map_auto_range(&values)),
+            None => warnings.missing(topic, field),
+        }
+    }
+    for (field, out_of_range) in id_fields.iter().zip(&parsed.out_of_range) {
+        warnings.out_of_range(topic, field, *out_of_range);
+    }
+    let mut extra = parsed.extra.into_iter();
+    let class_ids = opts.class_field.and_then(|_| extra.next());
+    let keypoint_ids = opts.keypoint_field.and_then(|_| extra.next());
+    for (field, ids) in [(opts.class_field, &class_ids), (opts.keypoint_field, &keypoint_ids)] {
+        if let (Some(field), Some(None)) = (field, ids) {
+            warnings.missing(topic, field);
+        }
+    }
+    let mut class_ids = class_ids.flatten();
+    let mut keypoint_ids = keypoint_ids.flatten();
+    let mut positions = parsed.positions;
+    if opts.every_nth_point > 1 {
+        let n = opts.every_nth_point;
+        positions = every_nth(positions, n);
+        colors = colors.map(|c| every_nth(c, n));
+        class_ids = class_ids.map(|ids| every_nth(ids, n));
+        keypoint_ids = keypoint_ids.map(|ids| every_nth(ids, n));
+    }
 
-    let (positions, colors) = parse_pointcloud2(payload, rotation)?;
+    if let Some(crop) = &opts.roi
+        && let Some(layout) = parse_layout(payload)?
+        && let Some(mask) = crop.mask(&layout.frame_id, ts, positions.iter().map(|p| Point3::new(p.x() as f64, p.y() as f64, p.z() as f64)))
+    {
+        positions = retain(positions, &mask);
+        colors = colors.map(|c| retain(c, &mask));
+        class_ids = class_ids.map(|ids| retain(ids, &mask));
+        keypoint_ids = keypoint_ids.map(|ids| retain(ids, &mask));
+    }
+
+    // Bake the cloud's frame into the root frame when TF can resolve it
+    if let Some(tf) = tf_graph
+        && let Some(layout) = parse_layout(payload)?
+        && !layout.frame_id.is_empty()
+        && layout.frame_id != root_frame
+    {
+        match tf.resolve_pose(root_frame, &layout.frame_id, ts, tf_mode) {
+            Some(iso) => transform_positions(&mut positions, &iso),
+            None => tracing::debug!(
+                frame = %layout.frame_id,
+                "no TF from point cloud frame to {root_frame}; logging in sensor frame"
+            ),
+        }
+    }
 
     let rr_path = normalize_path(topic);
     let pts = rerun::archetypes::Points3D::new(positions);
@@ -59,76 +184,154 @@ pub fn pointcloud2_to_rerun(
     } else {
         pts
     };
-    rec.log(rr_path, &pts)?;
+    let pts = match class_ids {
+        Some(ids) => pts.with_class_ids(ids),
+        None => pts,
+    };
+    let pts = match keypoint_ids {
+        Some(ids) => pts.with_keypoint_ids(ids),
+        None => pts,
+    };
+    let pts = match opts.style {
+        Some(style) => style.points3d(pts),
+        None => pts,
+    };
+    rec.log(rr_path.as_str(), &pts)?;
+
+    // Organized clouds (height > 1) can additionally be logged as a range image
+    if opts.range_image && let Some((width, height, ranges)) = parse_range_image(payload)? {
+        let bytes: Vec<u8> = ranges.iter().flat_map(|r| r.to_le_bytes()).collect();
+        let depth_img = rerun::archetypes::DepthImage::from_data_type_and_bytes(
+            bytes,
+            [width, height],
+            rerun::datatypes::ChannelDatatype::F32,
+        )
+        .with_meter(1.0);
+        rec.log(format!("{}/range_image", rr_path), &depth_img)?;
+    }
 
     Ok(())
 }
 
-#[allow(clippy::type_complexity, clippy::collapsible_if)]
-pub fn parse_pointcloud2(payload: &[u8], rotation: Option<&[f64; 3]>) -> Result<(Vec<Position3D>, Option<Vec<[u8; 3]>>)> {
-    let mut cursor = 0;
-
-    // Parse header (std_msgs/Header) - skip for now
-    cursor = skip_header(payload, cursor)?;
+fn every_nth<T>(values: Vec<T>, n: usize) -> Vec<T> {
+    values.into_iter().step_by(n.max(1)).collect()
+}
 
-    // height (uint32)
-    let height = read_u32_le(payload, &mut cursor)?;
-    // width (uint32)
-    let width = read_u32_le(payload, &mut cursor)?;
+/// Apply a rigid transform to all positions in place
+fn transform_positions(positions: &mut [Position3D], iso: &Isometry3<f64>) {
+    for pos in positions.iter_mut() {
+        let p = iso * Point3::new(pos.x() as f64, pos.y() as f64, pos.z() as f64);
+        *pos = Position3D::new(p.x as f32, p.y as f32, p.z as f32);
+    }
+}
 
-    // fields (array of PointField)
-    let fields = parse_fields(payload, &mut cursor)?;
+/// Parsed PointCloud2 layout (everything except the per-point decoding)
+struct CloudLayout<'a> {
+    frame_id: Cow<'a, str>,
+    height: u32,
+    width: u32,
+    fields: Vec<PointField<'a>>,
+    point_step: usize,
+    data: &'a [u8],
+}
 
-    // is_bigendian (bool)
-    let is_bigendian = read_bool(payload, &mut cursor)?;
-    if is_bigendian {
-        tracing::warn!("Big-endian PointCloud2 not supported; skipping");
-        return Ok((vec![], None));
+impl CloudLayout<'_> {
+    fn field_offset(&self, name: &str) -> Option<usize> {
+        self.fields
+            .iter()
+            .find(|f| f.name == name)
+            .map(|f| f.offset as usize)
     }
 
-    // point_step (uint32)
-    let point_step = read_u32_le(payload, &mut cursor)? as usize;
-    // row_step (uint32)
-    let _row_step = read_u32_le(payload, &mut cursor)?;
+    fn xyz_offsets(&self) -> Option<(usize, usize, usize)> {
+        Some((
+            self.field_offset("x")?,
+            self.field_offset("y")?,
+            self.field_offset("z")?,
+        ))
+    }
 
-    // data length (uint32)
-    let data_len = read_u32_le(payload, &mut cursor)? as usize;
-    if payload.len() < cursor + data_len {
-        return Err(anyhow::anyhow!("payload too short for data"));
+    fn point(&self, i: usize) -> Option<&[u8]> {
+        let point_start = i * self.point_step;
+        self.data.get(point_start..point_start + self.point_step)
     }
-    let data = &payload[cursor..cursor + data_len];
-    // cursor += data_len; // not needed
+}
 
+/// Parse the PointCloud2 header, fields and data blob.
+/// Returns `None` for big-endian clouds, which are not supported.
+fn parse_layout(payload: &[u8]) -> Result<Option<CloudLayout<'_>>> {
+    let mut cursor = Cursor::new(payload);
+    let frame_id = cursor.header()?.frame_id;
+    let height = cursor.u32()?;
+    let width = cursor.u32()?;
+    let fields = parse_fields(&mut cursor)?;
+    if cursor.bool()? {
+        tracing::warn!("Big-endian PointCloud2 not supported; skipping");
+        return Ok(None);
+    }
+    let point_step = cursor.u32()? as usize;
+    let _row_step = cursor.u32()?;
+    let data = cursor.byte_array()?;
     // is_dense (bool) - skip
 
-    // Find x, y, z offsets
-    let x_off = fields
-        .iter()
-        .find(|f| f.name == "x")
-        .map(|f| f.offset as usize);
-    let y_off = fields
-        .iter()
-        .find(|f| f.name == "y")
-        .map(|f| f.offset as usize);
-    let z_off = fields
-        .iter()
-        .find(|f| f.name == "z")
-        .map(|f| f.offset as usize);
-
-    if x_off.is_none() || y_off.is_none() || z_off.is_none() {
-        tracing::warn!("PointCloud2 missing x/y/z fields; skipping");
-        return Ok((vec![], None));
-    }
+    Ok(Some(CloudLayout {
+        frame_id,
+        height,
+        width,
+        fields,
+        point_step,
+        data,
+    }))
+}
+
+/// header.frame_id of a PointCloud2; `None` for big-endian clouds
+pub fn pointcloud_frame_id(payload: &[u8]) -> Result<Option<String>> {
+    Ok(parse_layout(payload)?.map(|layout| layout.frame_id.into_owned()))
+}
 
-    let x_off = x_off.unwrap();
-    let y_off = y_off.unwrap();
-    let z_off = z_off.unwrap();
+#[allow(clippy::type_complexity)]
+pub fn parse_pointcloud2(payload: &[u8], rotation: Option<&[f64; 3]>) -> Result<(Vec<Position3D>, Option<Vec<[u8; 3]>>)> {
+    let parsed = parse_points(payload, rotation, &[])?;
+    Ok((parsed.positions, parsed.colors))
+}
+
+/// Decoded points plus optionally requested per-point id fields
+pub struct ParsedPoints {
+    pub positions: Vec<Position3D>,
+    pub colors: Option<Vec<[u8; 3]>>,
+    /// One entry per requested field, `None` if the cloud has no such field
+    pub extra: Vec<Option<Vec<u16>>>,
+    /// Per requested field, the values that did not fit a u16
+    pub out_of_range: Vec<usize>,
+}
+
+/// Decode valid (finite) points, their colors and the requested id fields.
+///
+/// Id field values are cast to `u16`, which is what Rerun uses for class ids
+/// and keypoint ids; values outside 0..=65535 (or not finite) become 0 and are
+/// counted in [`ParsedPoints::out_of_range`].
+pub fn parse_points(payload: &[u8], rotation: Option<&[f64; 3]>, id_fields: &[&str]) -> Result<ParsedPoints> {
+    let empty = || ParsedPoints {
+        positions: vec![],
+        colors: None,
+        extra: id_fields.iter().map(|_| None).collect(),
+        out_of_range: vec![0; id_fields.len()],
+    };
+    let Some(layout) = parse_layout(payload)? else {
+        return Ok(empty());
+    };
+
+    let Some((x_off, y_off, z_off)) = layout.xyz_offsets() else {
+        tracing::warn!("PointCloud2 missing x/y/z fields; skipping");
+        return Ok(empty());
+    };
 
     // Color offset
-    let color_off = fields
-        .iter()
-        .find(|f| f.name == "rgb" || f.name == "rgba")
-        .map(|f| f.offset as usize);
+    let color_off = layout
+        .field_offset("rgb")
+        .or_else(|| layout.field_offset("rgba"));
+
+    let id_layouts: Vec<Option<&PointField>> = id_fields.iter().map(|name| layout.fields.iter().find(|f| f.name == *name)).collect();
 
     let mut positions = Vec::new();
     let mut colors = if color_off.is_some() {
@@ -136,13 +339,13 @@ pub fn parse_pointcloud2(payload: &[u8], rotation: Option<&[f64; 3]>) -> Result<
     } else {
         None
     };
+    let mut extra: Vec<Option<Vec<u16>>> = id_layouts.iter().map(|f| f.map(|_| Vec::new())).collect();
+    let mut out_of_range = vec![0; id_fields.len()];
 
-    for i in 0..(height * width) {
-        let point_start = i as usize * point_step;
-        if point_start + point_step > data.len() {
+    for i in 0..(layout.height * layout.width) as usize {
+        let Some(point) = layout.point(i) else {
             break;
-        }
-        let point = &data[point_start..point_start + point_step];
+        };
 
         // Read x, y, z as f32
         let x = read_f32_le_at(point, x_off)?;
@@ -162,87 +365,116 @@ pub fn parse_pointcloud2(payload: &[u8], rotation: Option<&[f64; 3]>) -> Result<
 
         positions.push(Position3D::new(final_x, final_y, final_z));
 
-        if let Some(colors_vec) = &mut colors {
-            if let Some(off) = color_off {
-                let color = read_color_at(point, off)?;
-                colors_vec.push(color);
+        if let Some(colors_vec) = &mut colors
+            && let Some(off) = color_off
+        {
+            let color = read_color_at(point, off)?;
+            colors_vec.push(color);
+        }
+
+        for ((values, field), out_of_range) in extra.iter_mut().zip(&id_layouts).zip(&mut out_of_range) {
+            if let (Some(values), Some(field)) = (values, field) {
+                let v = read_field_at(point, field)?;
+                values.push(if (0.0..=u16::MAX as f64).contains(&v) {
+                    v as u16
+                } else {
+                    *out_of_range += 1;
+                    0
+                });
             }
         }
     }
 
-    Ok((positions, colors))
+    Ok(ParsedPoints {
+        positions,
+        colors,
+        extra,
+        out_of_range,
+    })
+}
+
+/// Values of a scalar point field for the valid (finite) points, in the order of
+/// [`parse_points`] positions; `None` if the cloud has no such field
+pub fn parse_field_values(payload: &[u8], name: &str) -> Result<Option<Vec<f32>>> {
+    let Some(layout) = parse_layout(payload)? else {
+        return Ok(None);
+    };
+    let (Some((x_off, y_off, z_off)), Some(field)) = (layout.xyz_offsets(), layout.fields.iter().find(|f| f.name == name))
+    else {
+        return Ok(None);
+    };
+    let mut values = Vec::new();
+    for i in 0..(layout.height * layout.width) as usize {
+        let Some(point) = layout.point(i) else {
+            break;
+        };
+        let x = read_f32_le_at(point, x_off)?;
+        let y = read_f32_le_at(point, y_off)?;
+        let z = read_f32_le_at(point, z_off)?;
+        if x.is_finite() && y.is_finite() && z.is_finite() {
+            values.push(read_field_at(point, field)? as f32);
+        }
+    }
+    Ok(Some(values))
+}
+
+/// Compute an H×W range image (point norms in meters) for organized clouds.
+///
+/// Returns `None` for unorganized clouds (height <= 1) or clouds without x/y/z.
+/// Invalid points (NaN/Inf) are written as 0.0, which Rerun treats as "no depth".
+pub fn parse_range_image(payload: &[u8]) -> Result<Option<(u32, u32, Vec<f32>)>> {
+    let Some(layout) = parse_layout(payload)? else {
+        return Ok(None);
+    };
+    if layout.height <= 1 {
+        return Ok(None);
+    }
+    let Some((x_off, y_off, z_off)) = layout.xyz_offsets() else {
+        return Ok(None);
+    };
+
+    let n = (layout.height * layout.width) as usize;
+    let mut ranges = vec![0.0f32; n];
+    for (i, range) in ranges.iter_mut().enumerate() {
+        let Some(point) = layout.point(i) else {
+            break;
+        };
+        let x = read_f32_le_at(point, x_off)?;
+        let y = read_f32_le_at(point, y_off)?;
+        let z = read_f32_le_at(point, z_off)?;
+        let r = (x * x + y * y + z * z).sqrt();
+        if r.is_finite() {
+            *range = r;
+        }
+    }
+
+    Ok(Some((layout.width, layout.height, ranges)))
 }
 
 #[derive(Debug)]
 #[allow(dead_code)]
-struct PointField {
-    name: String,
+struct PointField<'a> {
+    name: Cow<'a, str>,
     offset: u32,
     datatype: u8,
     count: u32,
 }
 
-fn parse_fields(payload: &[u8], cursor: &mut usize) -> Result<Vec<PointField>> {
-    // array length (uint32)
-    let len = read_u32_le(payload, cursor)? as usize;
-    let mut fields = Vec::with_capacity(len);
+fn parse_fields<'a>(cursor: &mut Cursor<'a>) -> Result<Vec<PointField<'a>>> {
+    let len = cursor.u32()? as usize;
+    // A PointField is at least 13 bytes
+    let mut fields = Vec::with_capacity(len.min(cursor.remaining() / 13));
     for _ in 0..len {
-        // name (string)
-        let name = read_string(payload, cursor)?;
-        // offset (uint32)
-        let offset = read_u32_le(payload, cursor)?;
-        // datatype (uint8)
-        let datatype = read_u8(payload, cursor)?;
-        // count (uint32)
-        let count = read_u32_le(payload, cursor)?;
         fields.push(PointField {
-            name,
-            offset,
-            datatype,
-            count,
+            name: cursor.str()?,
+            offset: cursor.u32()?,
+            datatype: cursor.u8()?,
+            count: cursor.u32()?,
         });
     }
     Ok(fields)
 }
 
-fn read_u32_le(payload: &[u8], cursor: &mut usize) -> Result<u32> {
-    if *cursor + 4 > payload.len() {
-        return Err(anyhow::anyhow!("payload too short"));
-    }
-    let val = u32::from_le_bytes([
-        payload[*cursor],
-        payload[*cursor + 1],
-        payload[*cursor + 2],
-        payload[*cursor + 3],
-    ]);
-    *cursor += 4;
-    Ok(val)
-}
-
-fn read_u8(payload: &[u8], cursor: &mut usize) -> Result<u8> {
-    if *cursor + 1 > payload.len() {
-        return Err(anyhow::anyhow!("payload too short"));
-    }
-    let val = payload[*cursor];
-    *cursor += 1;
-    Ok(val)
-}
-
-fn read_bool(payload: &[u8], cursor: &mut usize) -> Result<bool> {
-    let val = read_u8(payload, cursor)?;
-    Ok(val != 0)
-}
-
-fn read_string(payload: &[u8], cursor: &mut usize) -> Result<String> {
-    let len = read_u32_le(payload, cursor)? as usize;
-    if *cursor + len > payload.len() {
-        return Err(anyhow::anyhow!("payload too short for string"));
-    }
-    let s = String::from_utf8_lossy(&payload[*cursor..*cursor + len]).to_string();
-    *cursor += len;
-    Ok(s)
-}
-
 fn read_f32_le_at(data: &[u8], off: usize) -> Result<f32> {
     if off + 4 > data.len() {
         return Err(anyhow::anyhow!("data too short"));
@@ -251,6 +483,31 @@ fn read_f32_le_at(data: &[u8], off: usize) -> Result<f32> {
     Ok(f32::from_le_bytes(bytes))
 }
 
+/// Read a scalar point field of any PointField datatype as f64
+fn read_field_at(data: &[u8], field: &PointField) -> Result<f64> {
+    let off = field.offset as usize;
+    let size = match field.datatype {
+        1 | 2 => 1,
+        3 | 4 => 2,
+        5..=7 => 4,
+        8 => 8,
+        other => return Err(anyhow::anyhow!("unknown PointField datatype {}", other)),
+    };
+    let Some(b) = data.get(off..off + size) else {
+        return Err(anyhow::anyhow!("data too short"));
+    };
+    Ok(match field.datatype {
+        1 => b[0] as i8 as f64,
+        2 => b[0] as f64,
+        3 => i16::from_le_bytes([b[0], b[1]]) as f64,
+        4 => u16::from_le_bytes([b[0], b[1]]) as f64,
+        5 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        6 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        7 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        _ => f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+    })
+}
+
 fn read_color_at(data: &[u8], off: usize) -> Result<[u8; 3]> {
     if off + 4 > data.len() {
         return Err(anyhow::anyhow!("data too short"));
@@ -264,17 +521,6 @@ fn read_color_at(data: &[u8], off: usize) -> Result<[u8; 3]> {
     Ok([r, g, b])
 }
 
-fn skip_header(payload: &[u8], mut cursor: usize) -> Result<usize> {
-    // seq (uint32)
-    cursor += 4;
-    // stamp (uint32 + uint32)
-    cursor += 8;
-    // frame_id (string)
-    let len = read_u32_le(payload, &mut cursor)? as usize;
-    cursor += len;
-    Ok(cursor)
-}
-
 fn normalize_path(topic: &str) -> String {
     topic.trim_start_matches('/').to_string()
 }
@@ -343,4 +589,110 @@ mod tests {
         assert_eq!(positions[0], Position3D::new(0.0, 1.0, 2.0));
         assert_eq!(colors.as_ref().unwrap()[0], [0, 0, 0]);
     }
+
+    fn create_xyz_cloud(height: u32, width: u32, points: &[[f32; 3]]) -> Vec<u8> {
+        let mut data = Vec::new();
+        // Header
+        data.extend_from_slice(&0u32.to_le_bytes()); // seq
+        data.extend_from_slice(&0u32.to_le_bytes()); // stamp sec
+        data.extend_from_slice(&0u32.to_le_bytes()); // stamp nsec
+        data.extend_from_slice(&0u32.to_le_bytes()); // frame_id len
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&3u32.to_le_bytes());
+        for (name, offset) in [("x", 0u32), ("y", 4), ("z", 8)] {
+            data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&offset.to_le_bytes());
+            data.push(7); // FLOAT32
+            data.extend_from_slice(&1u32.to_le_bytes());
+        }
+        data.push(0); // is_bigendian
+        data.extend_from_slice(&12u32.to_le_bytes()); // point_step
+        data.extend_from_slice(&(12 * width).to_le_bytes()); // row_step
+        data.extend_from_slice(&((points.len() * 12) as u32).to_le_bytes());
+        for p in points {
+            for v in p {
+                data.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        data.push(1); // is_dense
+        data
+    }
+
+    #[test]
+    fn test_parse_points_id_fields() {
+        // x,y,z float32 + label uint16 + ring uint8
+        let mut data = Vec::new();
+        data.extend_from_slice(&0u32.to_le_bytes()); // seq
+        data.extend_from_slice(&0u64.to_le_bytes()); // stamp
+        data.extend_from_slice(&0u32.to_le_bytes()); // frame_id len
+        data.extend_from_slice(&1u32.to_le_bytes()); // height
+        data.extend_from_slice(&2u32.to_le_bytes()); // width
+        let fields = [("x", 0u32, 7u8), ("y", 4, 7), ("z", 8, 7), ("label", 12, 4), ("ring", 14, 2)];
+        data.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+        for (name, offset, datatype) in fields {
+            data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&offset.to_le_bytes());
+            data.push(datatype);
+            data.extend_from_slice(&1u32.to_le_bytes());
+        }
+        data.push(0); // is_bigendian
+        data.extend_from_slice(&16u32.to_le_bytes()); // point_step
+        data.extend_from_slice(&32u32.to_le_bytes()); // row_step
+        data.extend_from_slice(&32u32.to_le_bytes()); // data len
+        for (label, ring) in [(7u16, 3u8), (42, 5)] {
+            for v in [1.0f32, 2.0, 3.0] {
+                data.extend_from_slice(&v.to_le_bytes());
+            }
+            data.extend_from_slice(&label.to_le_bytes());
+            data.push(ring);
+            data.push(0); // padding
+        }
+        data.push(1); // is_dense
+
+        let parsed = parse_points(&data, None, &["label", "ring", "missing"]).unwrap();
+        assert_eq!(parsed.positions.len(), 2);
+        assert_eq!(parsed.extra[0], Some(vec![7, 42]));
+        assert_eq!(parsed.extra[1], Some(vec![3, 5]));
+        assert_eq!(parsed.extra[2], None);
+        assert_eq!(parsed.out_of_range, [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_parse_points_out_of_range_ids() {
+        // z read as an id field
+        let data = create_xyz_cloud(1, 4, &[[0.0, 0.0, -1.0], [0.0, 0.0, 70000.0], [0.0, 0.0, 5.0], [0.0, 0.0, 65535.0]]);
+        let parsed = parse_points(&data, None, &["z"]).unwrap();
+        assert_eq!(parsed.extra[0], Some(vec![0, 0, 5, 65535]));
+        assert_eq!(parsed.out_of_range, [2]);
+    }
+
+    #[test]
+    fn test_parse_field_values_follow_valid_points() {
+        let points = [[1.0, 0.0, 0.5], [f32::NAN, 0.0, 0.0], [2.0, 0.0, -0.5]];
+        let data = create_xyz_cloud(1, 3, &points);
+        assert_eq!(parse_field_values(&data, "z").unwrap(), Some(vec![0.5, -0.5]));
+        assert_eq!(parse_field_values(&data, "intensity").unwrap(), None);
+        assert_eq!(every_nth(vec![0, 1, 2, 3, 4], 2), [0, 2, 4]);
+    }
+
+    #[test]
+    fn test_parse_range_image_organized() {
+        let points = [
+            [3.0, 4.0, 0.0],
+            [0.0, 0.0, 2.0],
+            [f32::NAN, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+        ];
+        let data = create_xyz_cloud(2, 2, &points);
+        let (width, height, ranges) = parse_range_image(&data).unwrap().unwrap();
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(ranges, vec![5.0, 2.0, 0.0, 1.0]);
+
+        // Unorganized clouds produce no range image
+        let data = create_xyz_cloud(1, 4, &points);
+        assert!(parse_range_image(&data).unwrap().is_none());
+    }
 }
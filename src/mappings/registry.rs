@@ -0,0 +1,978 @@
+//! Message mappers: one per ROS type (or topic), each owning its state
+//!
+//! `convert_bag` looks up the [`MessageMapper`] of every kept message in a
+//! [`MapperRegistry`]: the mapper registered for its topic, else for its type,
+//! else the fallback. [`MapperRegistry::builtin`] holds the mappings of this
+//! crate; downstream crates add their own for proprietary types and pass the
+//! registry to [`convert_bag_with`](crate::convert::convert_bag_with).
+//!
+//! ```rust,no_run
+//! use bag2rrd::{convert_bag_with, ConvertOptions, Mapped, MapperContext, MapperRegistry, MessageKind, MessageMapper};
+//!
+//! struct Battery;
+//!
+//! impl MessageMapper for Battery {
+//!     fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> anyhow::Result<Mapped> {
+//!         let volts = f32::from_le_bytes(payload[..4].try_into()?);
+//!         // Scalars are sent as columns, on the timelines of the message
+//!         ctx.scalars.push(ctx.rec, &format!("{}/volts", ctx.entity), volts as f64)?;
+//!         Ok(Mapped::Logged(MessageKind::Other))
+//!     }
+//! }
+//!
+//! # fn run(options: &ConvertOptions) -> anyhow::Result<()> {
+//! let mut mappers = MapperRegistry::builtin(options);
+//! mappers.register(&["acme_msgs/Battery"], Box::new(Battery));
+//! convert_bag_with(options, mappers)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::convert::{ConvertOptions, TopicConfig};
+use crate::mappings::camera::CameraRig;
+use crate::mappings::classes::ClassMap;
+use crate::mappings::depth::DepthProjector;
+use crate::mappings::gps::{GpsState, TrackExport};
+use crate::mappings::images::{log_decoded_image, uncropped_reason, DecodedImage};
+use crate::mappings::laserscan::{LaserScanOptions, ScanAccumulator};
+use crate::mappings::nav::OdomTrajectory;
+use crate::mappings::pointcloud::{FieldWarnings, PointCloudOptions};
+use crate::mappings::roi::RoiCrop;
+use crate::mappings::rename::EntityRenames;
+use crate::mappings::scalars::ScalarColumns;
+use crate::mappings::tf::TfGraph;
+use crate::ros_msg::{SchemaCache, TypeInfo, Value};
+use crate::tf_analysis::TfJumpDetector;
+
+/// Statistics counter of a logged message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum MessageKind {
+    Image,
+    /// Compressed images and video packets
+    CompressedImage,
+    PointCloud,
+    LaserScan,
+    GpsFix,
+    Imu,
+    /// Decoded from the bag's message definition
+    Generic,
+    Other,
+}
+
+impl MessageKind {
+    /// Whether the message counts towards --segment-size and --segment-bytes
+    pub fn fills_segment(self) -> bool {
+        !matches!(self, MessageKind::Generic | MessageKind::Other)
+    }
+
+    /// Counter the built-in mappers log `tp` under
+    pub fn of_type(tp: &str) -> Option<Self> {
+        match tp {
+            "sensor_msgs/Image" => Some(MessageKind::Image),
+            "sensor_msgs/CompressedImage"
+            | "ffmpeg_image_transport_msgs/FFMPEGPacket"
+            | "foxglove_msgs/CompressedVideo"
+            | "theora_image_transport/Packet" => Some(MessageKind::CompressedImage),
+            "sensor_msgs/PointCloud2" => Some(MessageKind::PointCloud),
+            "sensor_msgs/LaserScan" | "sensor_msgs/MultiEchoLaserScan" => Some(MessageKind::LaserScan),
+            "sensor_msgs/NavSatFix" => Some(MessageKind::GpsFix),
+            "sensor_msgs/Imu" => Some(MessageKind::Imu),
+            _ => None,
+        }
+    }
+
+    /// Name of the kind in --max-failure-rate
+    pub fn name(self) -> &'static str {
+        match self {
+            MessageKind::Image => "image",
+            MessageKind::CompressedImage => "compressed_image",
+            MessageKind::PointCloud => "pointcloud",
+            MessageKind::LaserScan => "laserscan",
+            MessageKind::GpsFix => "gps",
+            MessageKind::Imu => "imu",
+            MessageKind::Generic => "generic",
+            MessageKind::Other => "other",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            MessageKind::Image,
+            MessageKind::CompressedImage,
+            MessageKind::PointCloud,
+            MessageKind::LaserScan,
+            MessageKind::GpsFix,
+            MessageKind::Imu,
+            MessageKind::Generic,
+            MessageKind::Other,
+        ]
+        .into_iter()
+        .find(|kind| kind.name() == name)
+    }
+}
+
+/// What a mapper did with a message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mapped {
+    Logged(MessageKind),
+    /// Left out on purpose, e.g. an image dropped by --image-every-nth
+    Decimated,
+    /// Not logged: the payload could not be decoded
+    Skipped,
+}
+
+/// How a mapper shows one ROS type, for the `schema` command
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct MappingInfo {
+    /// Rerun archetypes logged
+    pub archetypes: &'static str,
+    /// bag2rrd version that introduced the mapping
+    pub since: &'static str,
+    /// Flags that change the mapping; config files take the same names as keys
+    pub options: &'static [&'static str],
+}
+
+/// Mapping of the types listed by a --mapper-script, which the registry only
+/// knows once the script is loaded
+pub const SCRIPT_MAPPING: MappingInfo = MappingInfo {
+    archetypes: "Scalars + TextLog + Points3D",
+    since: "v0.5.1",
+    options: &["mapper-script"],
+};
+
+/// A type (or topic) of a [`MapperRegistry`] and how its mapper shows it
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct MappingEntry {
+    /// ROS type; `*` for the fallback of the other types
+    pub ros_type: String,
+    /// Topic of a mapper registered by topic
+    pub topic: Option<String>,
+    /// `None` for mappers that do not describe themselves
+    pub mapping: Option<MappingInfo>,
+}
+
+/// One message to log, with the conversion state shared by every mapper
+pub struct MapperContext<'a> {
+    pub rec: &'a rerun::RecordingStream,
+    pub topic: &'a str,
+    pub tp: &'a str,
+    /// Entity path of the topic, after --topic-rename
+    pub entity: &'a str,
+    /// Entity under the message's header.frame_id, with --attach-to-frames
+    pub attached_path: Option<&'a str>,
+    /// Log time in seconds on the ros_time timeline
+    pub ts: f64,
+    /// Origin of stamps nested in messages (TF, Path poses), when logging by header stamp
+    pub stamp_base: Option<f64>,
+    /// Record time in the bag, in seconds
+    pub bag_time: f64,
+    /// Published latched, like /tf_static
+    pub latched: bool,
+    pub options: &'a ConvertOptions,
+    /// --map-frame and --topic-rename rules, compiled once per conversion
+    pub renames: &'a EntityRenames,
+    pub topic_config: &'a TopicConfig,
+    /// md5sum and message definition recorded in the bag
+    pub type_info: Option<&'a TypeInfo>,
+    pub tf_graph: &'a mut TfGraph,
+    /// Scalar series sent as columns; push scalars here rather than logging them row by row
+    pub scalars: &'a mut ScalarColumns,
+    /// Image decoded ahead on the decode pool, for mappers that [decode images](MessageMapper::decodes_images);
+    /// `None` when --image-every-nth leaves the message out
+    pub decoded_image: Option<Result<DecodedImage>>,
+}
+
+/// Logs the messages of one or more ROS types
+pub trait MessageMapper {
+    /// Log one message
+    fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> Result<Mapped>;
+
+    /// Called once after the last message
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether Image/CompressedImage payloads are decoded on the decode pool ahead of [`map`](Self::map)
+    fn decodes_images(&self) -> bool {
+        false
+    }
+
+    /// Whether the mapper parses the standard definition of its types; those
+    /// topics are skipped when the bag records another md5sum
+    fn standard_layout(&self) -> bool {
+        false
+    }
+
+    /// Archetypes and options of the mapping of `tp`, listed by `schema`
+    fn describe(&self, _tp: &str) -> Option<MappingInfo> {
+        None
+    }
+}
+
+/// Mappers by topic and by ROS type, plus a fallback for the other types
+#[derive(Default)]
+pub struct MapperRegistry {
+    mappers: Vec<Box<dyn MessageMapper>>,
+    by_type: HashMap<String, usize>,
+    by_topic: HashMap<String, usize>,
+    fallback: Option<usize>,
+}
+
+impl MapperRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mappings of this crate, configured by `options`
+    pub fn builtin(options: &ConvertOptions) -> Self {
+        let mut registry = Self::new();
+        let camera = CameraMapper {
+            rig: (!options.camera_groups.is_empty()).then(|| CameraRig::new(options.camera_groups.clone())),
+            depth: options.depth_to_points.then(|| DepthProjector::new(&options.depth_topics)),
+            uncropped: HashSet::new(),
+        };
+        // Camera info only feeds depth projection and camera groups
+        let camera_types: &[&str] = if camera.rig.is_some() || camera.depth.is_some() {
+            &["sensor_msgs/Image", "sensor_msgs/CompressedImage", "sensor_msgs/CameraInfo"]
+        } else {
+            &["sensor_msgs/Image", "sensor_msgs/CompressedImage"]
+        };
+        registry.register(camera_types, Box::new(camera));
+        registry.register(
+            &["ffmpeg_image_transport_msgs/FFMPEGPacket", "foxglove_msgs/CompressedVideo", "theora_image_transport/Packet"],
+            Box::new(VideoMapper::default()),
+        );
+        registry.register(&["sensor_msgs/PointCloud2"], Box::new(PointCloudMapper::default()));
+        registry.register(
+            &["sensor_msgs/LaserScan", "sensor_msgs/MultiEchoLaserScan"],
+            Box::new(ScanMapper { accumulator: options.scan_accumulate.filter(|n| *n > 0).map(ScanAccumulator::new) }),
+        );
+        registry.register(
+            &["sensor_msgs/NavSatFix"],
+            Box::new(GpsMapper {
+                state: GpsState::default(),
+                export: TrackExport::new(options.export_gpx.as_deref(), options.export_kml.as_deref()),
+            }),
+        );
+        registry.register(&["sensor_msgs/Imu"], Box::new(ImuMapper));
+        registry.register(
+            &["tf2_msgs/TFMessage", "tf/tfMessage"],
+            Box::new(TfMapper { detector: options.analyze_tf.map(TfJumpDetector::new) }),
+        );
+        registry.register(
+            &["nav_msgs/Odometry", "geometry_msgs/PoseStamped", "nav_msgs/Path"],
+            Box::new(NavMapper {
+                trajectory: options
+                    .odom_trajectory
+                    .then(|| OdomTrajectory::new(options.odom_trajectory_max_points, options.odom_trajectory_every_nth)),
+            }),
+        );
+        registry.register(
+            &["vision_msgs/Detection2DArray", "vision_msgs/Detection3DArray"],
+            Box::new(DetectionMapper {
+                decoder: DefinitionDecoder::default(),
+                // An unreadable file is reported when the conversion loads it for its legend
+                classes: options.class_map.as_deref().and_then(|path| ClassMap::load(path).ok()).unwrap_or_default(),
+            }),
+        );
+        if options.generic_fallback {
+            registry.set_fallback(Box::new(GenericMapper::default()));
+        }
+        registry
+    }
+
+    /// Map messages of these types with `mapper`, replacing their previous mapper
+    pub fn register(&mut self, types: &[&str], mapper: Box<dyn MessageMapper>) {
+        let index = self.push(mapper);
+        for tp in types {
+            self.by_type.insert(tp.to_string(), index);
+        }
+    }
+
+    /// Map the types listed by the `types()` function of a Rhai script with its `map()`
+    pub fn register_script(&mut self, path: &Path) -> Result<()> {
+        #[cfg(feature = "scripting")]
+        {
+            let mut mapper = crate::mappings::script::ScriptMapper::load(path)?;
+            let types = mapper.types()?;
+            if types.is_empty() {
+                anyhow::bail!("{}: types() lists no message types", path.display());
+            }
+            let types: Vec<&str> = types.iter().map(String::as_str).collect();
+            self.register(&types, Box::new(mapper));
+            Ok(())
+        }
+        #[cfg(not(feature = "scripting"))]
+        {
+            anyhow::bail!("{}: mapper scripts need bag2rrd built with the `scripting` feature", path.display())
+        }
+    }
+
+    /// Map every message on `topic` with `mapper`, whatever its type
+    pub fn register_topic(&mut self, topic: &str, mapper: Box<dyn MessageMapper>) {
+        let index = self.push(mapper);
+        self.by_topic.insert(format!("/{}", topic.trim_start_matches('/')), index);
+    }
+
+    /// Map messages of types no other mapper handles
+    pub fn set_fallback(&mut self, mapper: Box<dyn MessageMapper>) {
+        self.fallback = Some(self.push(mapper));
+    }
+
+    fn push(&mut self, mapper: Box<dyn MessageMapper>) -> usize {
+        self.mappers.push(mapper);
+        self.mappers.len() - 1
+    }
+
+    fn lookup(&self, topic: &str, tp: &str) -> Option<usize> {
+        self.by_topic
+            .get(&format!("/{}", topic.trim_start_matches('/')))
+            .or_else(|| self.by_type.get(tp))
+            .copied()
+            .or(self.fallback)
+    }
+
+    /// Mapper of messages on `topic` of type `tp`: the topic's, else the type's, else the fallback
+    pub fn get(&self, topic: &str, tp: &str) -> Option<&dyn MessageMapper> {
+        let index = self.lookup(topic, tp)?;
+        Some(self.mappers[index].as_ref())
+    }
+
+    pub fn get_mut(&mut self, topic: &str, tp: &str) -> Option<&mut dyn MessageMapper> {
+        let index = self.lookup(topic, tp)?;
+        Some(self.mappers[index].as_mut())
+    }
+
+    pub fn fallback_mut(&mut self) -> Option<&mut dyn MessageMapper> {
+        let index = self.fallback?;
+        Some(self.mappers[index].as_mut())
+    }
+
+    /// Every registered type with its mapping, sorted by type; topic mappers and
+    /// the fallback come last
+    pub fn mappings(&self) -> Vec<MappingEntry> {
+        let mut types: Vec<(&String, &usize)> = self.by_type.iter().collect();
+        types.sort();
+        let mut topics: Vec<(&String, &usize)> = self.by_topic.iter().collect();
+        topics.sort();
+        let mut entries: Vec<MappingEntry> = types
+            .into_iter()
+            .map(|(tp, index)| MappingEntry {
+                ros_type: tp.clone(),
+                topic: None,
+                mapping: self.mappers[*index].describe(tp),
+            })
+            .collect();
+        entries.extend(topics.into_iter().map(|(topic, index)| MappingEntry {
+            ros_type: "*".to_string(),
+            topic: Some(topic.clone()),
+            mapping: self.mappers[*index].describe("*"),
+        }));
+        if let Some(index) = self.fallback {
+            entries.push(MappingEntry { ros_type: "*".to_string(), topic: None, mapping: self.mappers[index].describe("*") });
+        }
+        entries
+    }
+
+    /// Run [`MessageMapper::finish`] of every mapper
+    pub fn finish(&mut self) -> Result<()> {
+        self.mappers.iter_mut().try_for_each(|mapper| mapper.finish())
+    }
+}
+
+/// sensor_msgs/Image and CompressedImage, plus CameraInfo for depth projection and camera groups
+struct CameraMapper {
+    rig: Option<CameraRig>,
+    depth: Option<DepthProjector>,
+    /// Topics already warned that --image-crop logs their images whole
+    uncropped: HashSet<String>,
+}
+
+impl MessageMapper for CameraMapper {
+    fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> Result<Mapped> {
+        if ctx.tp == "sensor_msgs/CameraInfo" {
+            if let Some(proj) = self.depth.as_mut() {
+                proj.set_camera_info(ctx.topic, payload);
+            }
+            if let Some(rig) = self.rig.as_mut() {
+                rig.camera_info_to_rerun(
+                    ctx.rec,
+                    ctx.topic,
+                    ctx.ts,
+                    payload,
+                    &ctx.options.image_crop,
+                    &ctx.options.root_frame,
+                    Some(&*ctx.tf_graph),
+                    ctx.options.tf_mode,
+                )?;
+            }
+            return Ok(Mapped::Logged(MessageKind::Other));
+        }
+        let Some(decoded) = ctx.decoded_image.take() else {
+            return Ok(Mapped::Decimated);
+        };
+        // Unsupported encodings and formats
+        let decoded = decoded?;
+        if matches!(decoded, DecodedImage::Skipped) {
+            return Ok(Mapped::Skipped);
+        }
+        if let Some(crop) = ctx.topic_config.image_crop
+            && !self.uncropped.contains(ctx.topic)
+            && let Some(reason) = uncropped_reason(ctx.tp, payload, &crop)
+        {
+            tracing::warn!(topic = %ctx.topic, ?crop, "{reason}; logging the images of the topic whole");
+            self.uncropped.insert(ctx.topic.to_string());
+        }
+        if let Some(proj) = self.depth.as_mut()
+            && ctx.options.depth_color_topic.as_deref() == Some(ctx.topic)
+        {
+            proj.set_color(ctx.ts, payload)?;
+        }
+        if let Some(proj) = self.depth.as_ref()
+            && ctx.tp == "sensor_msgs/Image"
+        {
+            proj.depth_to_rerun(ctx.rec, ctx.topic, ctx.ts, payload)?;
+        }
+        // Grouped cameras log images under their Pinhole entity
+        let image_path = self
+            .rig
+            .as_ref()
+            .and_then(|rig| rig.image_entity(ctx.topic))
+            .or_else(|| ctx.attached_path.map(str::to_string))
+            .unwrap_or_else(|| ctx.entity.to_string());
+        log_decoded_image(ctx.rec, &image_path, ctx.ts, decoded)?;
+        Ok(Mapped::Logged(if ctx.tp == "sensor_msgs/Image" {
+            MessageKind::Image
+        } else {
+            MessageKind::CompressedImage
+        }))
+    }
+
+    fn decodes_images(&self) -> bool {
+        true
+    }
+
+    fn standard_layout(&self) -> bool {
+        true
+    }
+
+    fn describe(&self, tp: &str) -> Option<MappingInfo> {
+        Some(match tp {
+            "sensor_msgs/Image" => MappingInfo {
+                archetypes: "Image (8/16-bit, f32)",
+                since: "v0.1.0",
+                options: &[
+                    "image-scale",
+                    "image-crop",
+                    "image-every-nth",
+                    "image-colormap",
+                    "image-value-range",
+                    "image-encode",
+                    "camera-group",
+                    "depth-to-points",
+                    "attach-to-frames",
+                ],
+            },
+            "sensor_msgs/CompressedImage" => MappingInfo {
+                archetypes: "Image/EncodedImage",
+                since: "v0.1.0",
+                options: &["image-scale", "image-crop", "image-every-nth", "compressed-passthrough", "camera-group", "attach-to-frames"],
+            },
+            _ => MappingInfo {
+                archetypes: "(depth → Points3D with --depth-to-points)",
+                since: "v0.5.1",
+                options: &["camera-group", "depth-to-points", "depth-topic", "depth-color-topic"],
+            },
+        })
+    }
+}
+
+/// H.264/H.265 packets as VideoStream samples
+#[derive(Default)]
+struct VideoMapper {
+    /// Theora topics were reported as skipped
+    theora_warned: bool,
+}
+
+impl MessageMapper for VideoMapper {
+    fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> Result<Mapped> {
+        match ctx.tp {
+            "ffmpeg_image_transport_msgs/FFMPEGPacket" => {
+                crate::mappings::video::ffmpeg_packet_to_rerun(ctx.rec, ctx.entity, ctx.ts, payload)?
+            }
+            "foxglove_msgs/CompressedVideo" => {
+                crate::mappings::video::compressed_video_to_rerun(ctx.rec, ctx.entity, ctx.ts, payload)?
+            }
+            _ => crate::mappings::video::theora_packet_to_rerun(ctx.topic, &mut self.theora_warned)?,
+        }
+        Ok(Mapped::Logged(MessageKind::CompressedImage))
+    }
+
+    fn standard_layout(&self) -> bool {
+        true
+    }
+
+    fn describe(&self, tp: &str) -> Option<MappingInfo> {
+        Some(match tp {
+            "theora_image_transport/Packet" => {
+                MappingInfo { archetypes: "(skipped, no Theora decoder)", since: "v0.5.1", options: &[] }
+            }
+            _ => MappingInfo { archetypes: "VideoStream (H.264/H.265)", since: "v0.5.1", options: &[] },
+        })
+    }
+}
+
+#[derive(Default)]
+struct PointCloudMapper {
+    warnings: FieldWarnings,
+}
+
+impl MessageMapper for PointCloudMapper {
+    fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> Result<Mapped> {
+        let options = ctx.options;
+        // Attached clouds stay in their sensor frame; the frame entity places them
+        crate::mappings::pointcloud::pointcloud2_to_rerun(
+            ctx.rec,
+            ctx.attached_path.unwrap_or(ctx.entity),
+            ctx.ts,
+            payload,
+            &PointCloudOptions {
+                rotation: options.pointcloud_rotation.as_ref(),
+                range_image: options.pointcloud_range_image,
+                class_field: options.pointcloud_class_field.as_deref(),
+                keypoint_field: options.pointcloud_keypoint_field.as_deref(),
+                color_field: ctx.topic_config.pointcloud_color_field.as_deref(),
+                every_nth_point: ctx.topic_config.pointcloud_downsample,
+                style: Some(&ctx.topic_config.style),
+                roi: roi_crop(ctx),
+            },
+            &mut self.warnings,
+            &options.root_frame,
+            (options.pointcloud_tf && ctx.attached_path.is_none()).then_some(&*ctx.tf_graph),
+            options.tf_mode,
+        )?;
+        Ok(Mapped::Logged(MessageKind::PointCloud))
+    }
+
+    fn standard_layout(&self) -> bool {
+        true
+    }
+
+    fn describe(&self, _tp: &str) -> Option<MappingInfo> {
+        Some(MappingInfo {
+            archetypes: "Points3D (+range DepthImage)",
+            since: "v0.2.0",
+            options: &[
+                "pointcloud-rotation",
+                "pointcloud-range-image",
+                "pointcloud-class-field",
+                "pointcloud-keypoint-field",
+                "pointcloud-color-field",
+                "pointcloud-downsample",
+                "roi",
+                "no-pointcloud-tf",
+                "attach-to-frames",
+                "color",
+                "radius",
+                "label",
+            ],
+        })
+    }
+}
+
+/// The --roi box of the conversion, tested against TF whatever frame the data is logged in
+fn roi_crop<'a>(ctx: &'a MapperContext<'_>) -> Option<RoiCrop<'a>> {
+    ctx.options.roi.as_ref().map(|region| RoiCrop {
+        region,
+        tf: &*ctx.tf_graph,
+        root_frame: &ctx.options.root_frame,
+        tf_mode: ctx.options.tf_mode,
+    })
+}
+
+/// LaserScan and MultiEchoLaserScan, with the --scan-accumulate buffer
+struct ScanMapper {
+    accumulator: Option<ScanAccumulator>,
+}
+
+impl MessageMapper for ScanMapper {
+    fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> Result<Mapped> {
+        let options = ctx.options;
+        // Accumulated scans are a root-frame map, so they are never attached
+        let attached_path = ctx.attached_path.filter(|_| self.accumulator.is_none());
+        let scan_options = LaserScanOptions {
+            as_lines: options.scan_as_lines,
+            as_3d: options.scan_3d || attached_path.is_some(),
+            color_by: options.scan_color,
+            colormap: options.scan_colormap,
+            style: Some(&ctx.topic_config.style),
+            roi: roi_crop(ctx),
+        };
+        let tf_graph = attached_path.is_none().then_some(&*ctx.tf_graph);
+        if ctx.tp == "sensor_msgs/MultiEchoLaserScan" {
+            crate::mappings::laserscan::multi_echo_laserscan_to_rerun(
+                ctx.rec,
+                attached_path.unwrap_or(ctx.entity),
+                ctx.ts,
+                payload,
+                &scan_options,
+                options.multi_echo,
+                &options.root_frame,
+                tf_graph,
+                options.tf_mode,
+                self.accumulator.as_mut(),
+            )?;
+        } else {
+            crate::mappings::laserscan::laserscan_to_rerun(
+                ctx.rec,
+                attached_path.unwrap_or(ctx.entity),
+                ctx.ts,
+                payload,
+                &scan_options,
+                &options.root_frame,
+                tf_graph,
+                options.tf_mode,
+                self.accumulator.as_mut(),
+            )?;
+        }
+        Ok(Mapped::Logged(MessageKind::LaserScan))
+    }
+
+    fn standard_layout(&self) -> bool {
+        true
+    }
+
+    fn describe(&self, tp: &str) -> Option<MappingInfo> {
+        Some(match tp {
+            "sensor_msgs/MultiEchoLaserScan" => MappingInfo {
+                archetypes: "Points2D/LineStrips2D (or 3D)",
+                since: "v0.5.1",
+                options: &["multi-echo", "scan-as-lines", "scan-3d", "scan-color", "scan-colormap", "scan-accumulate", "roi", "color", "radius", "line-width", "label"],
+            },
+            _ => MappingInfo {
+                archetypes: "Points2D/LineStrips2D (or 3D)",
+                since: "v0.2.0",
+                options: &["scan-as-lines", "scan-3d", "scan-color", "scan-colormap", "scan-accumulate", "roi", "attach-to-frames", "color", "radius", "line-width", "label"],
+            },
+        })
+    }
+}
+
+/// sensor_msgs/NavSatFix, with the --export-gpx/--export-kml tracks
+struct GpsMapper {
+    state: GpsState,
+    export: Option<TrackExport>,
+}
+
+impl MessageMapper for GpsMapper {
+    fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> Result<Mapped> {
+        let fix = crate::mappings::gps::navsatfix_to_rerun(
+            ctx.rec,
+            ctx.entity,
+            ctx.ts,
+            payload,
+            ctx.options.gps_origin.as_deref(),
+            ctx.options.gps_path,
+            ctx.options.blueprint,
+            ctx.options.gps_geoid.as_deref(),
+            &ctx.topic_config.style,
+            &mut self.state,
+            ctx.scalars,
+        )?;
+        if let (Some(export), Some(fix)) = (self.export.as_mut(), fix) {
+            // Absolute time of the fix: its stamp, else when it was recorded
+            let time = crate::convert::header_stamp(ctx.tp, payload).unwrap_or(ctx.bag_time);
+            export.push(ctx.topic, time, fix);
+        }
+        Ok(Mapped::Logged(MessageKind::GpsFix))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self.export.take() {
+            Some(export) => export.write(),
+            None => Ok(()),
+        }
+    }
+
+    fn standard_layout(&self) -> bool {
+        true
+    }
+
+    fn describe(&self, _tp: &str) -> Option<MappingInfo> {
+        Some(MappingInfo {
+            archetypes: "Points3D (+GeoPoints with --blueprint, +path optional)",
+            since: "v0.2.0",
+            options: &["gps-origin", "gps-geoid", "gps-path", "export-gpx", "export-kml", "color", "radius", "line-width", "label"],
+        })
+    }
+}
+
+struct ImuMapper;
+
+impl MessageMapper for ImuMapper {
+    fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> Result<Mapped> {
+        crate::mappings::imu::imu_to_rerun(ctx.rec, ctx.entity, ctx.ts, payload, ctx.scalars)?;
+        Ok(Mapped::Logged(MessageKind::Imu))
+    }
+
+    fn standard_layout(&self) -> bool {
+        true
+    }
+
+    fn describe(&self, _tp: &str) -> Option<MappingInfo> {
+        Some(MappingInfo { archetypes: "Transform3D + Arrows3D + Scalar", since: "v0.5.0", options: &[] })
+    }
+}
+
+/// /tf and /tf_static into the shared TF graph, with the --analyze-tf detector
+struct TfMapper {
+    detector: Option<TfJumpDetector>,
+}
+
+impl MessageMapper for TfMapper {
+    fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> Result<Mapped> {
+        let options = ctx.options;
+        // Static transforms are plain TFMessages on /tf_static, published latched
+        if ctx.topic.trim_start_matches('/') == "tf_static" || ctx.latched {
+            ctx.tf_graph
+                .ingest_tf_static_msg(ctx.rec, ctx.topic, payload, &options.root_frame, &ctx.renames.frames)?;
+        } else {
+            if let Some(detector) = self.detector.as_mut() {
+                match crate::mappings::tf::parse_tf_message(payload) {
+                    Ok(transforms) => transforms.iter().for_each(|tf| detector.push(tf, ctx.bag_time)),
+                    Err(e) => tracing::warn!("Failed to parse TF message: {}; skipping", e),
+                }
+            }
+            ctx.tf_graph.ingest_tf_msg(
+                ctx.rec,
+                ctx.topic,
+                ctx.ts,
+                ctx.stamp_base,
+                payload,
+                options.tf_buffer_seconds,
+                &options.root_frame,
+                &ctx.renames.frames,
+            )?;
+        }
+        Ok(Mapped::Logged(MessageKind::Other))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(detector) = self.detector.take() {
+            print!("{}", detector.finish().to_text());
+        }
+        Ok(())
+    }
+
+    fn standard_layout(&self) -> bool {
+        true
+    }
+
+    fn describe(&self, _tp: &str) -> Option<MappingInfo> {
+        Some(MappingInfo {
+            archetypes: "Transforms3D",
+            since: "v0.3.0",
+            options: &[
+                "root-frame",
+                "tf-mode",
+                "tf-buffer-seconds",
+                "tf-tolerance",
+                "tf-fallback",
+                "tf-authority",
+                "tf-axes",
+                "tf-axes-filter",
+                "tf-plots",
+                "map-frame",
+                "analyze-tf",
+            ],
+        })
+    }
+}
+
+/// Odometry (with the --odom-trajectory polyline), PoseStamped and Path
+struct NavMapper {
+    trajectory: Option<OdomTrajectory>,
+}
+
+impl MessageMapper for NavMapper {
+    fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> Result<Mapped> {
+        let options = ctx.options;
+        let tf_graph = Some(&*ctx.tf_graph);
+        match ctx.tp {
+            "nav_msgs/Odometry" => crate::mappings::nav::odometry_to_rerun(
+                ctx.rec,
+                ctx.topic,
+                ctx.ts,
+                payload,
+                &options.root_frame,
+                &ctx.renames.frames,
+                tf_graph,
+                options.tf_mode,
+                self.trajectory.as_mut(),
+                &ctx.topic_config.style,
+            )?,
+            "geometry_msgs/PoseStamped" => crate::mappings::nav::pose_stamped_to_rerun(
+                ctx.rec,
+                ctx.topic,
+                ctx.ts,
+                payload,
+                &options.root_frame,
+                &ctx.renames.topics,
+                &ctx.renames.frames,
+                tf_graph,
+                options.tf_mode,
+            )?,
+            _ => crate::mappings::nav::path_to_rerun(
+                ctx.rec,
+                ctx.topic,
+                ctx.ts,
+                ctx.stamp_base,
+                payload,
+                &options.root_frame,
+                &ctx.renames.topics,
+                &ctx.renames.frames,
+                tf_graph,
+                options.tf_mode,
+                &ctx.topic_config.style,
+            )?,
+        }
+        Ok(Mapped::Logged(MessageKind::Other))
+    }
+
+    fn standard_layout(&self) -> bool {
+        true
+    }
+
+    fn describe(&self, tp: &str) -> Option<MappingInfo> {
+        Some(match tp {
+            "nav_msgs/Odometry" => MappingInfo {
+                archetypes: "Transforms3D (+trajectory LineStrips3D)",
+                since: "v0.3.0",
+                options: &["odom-trajectory", "odom-trajectory-max-points", "odom-trajectory-every-nth", "tf-mode", "color", "line-width", "label"],
+            },
+            "geometry_msgs/PoseStamped" => {
+                MappingInfo { archetypes: "Transforms3D", since: "v0.3.0", options: &["topic-rename", "tf-mode"] }
+            }
+            _ => MappingInfo {
+                archetypes: "LineStrips3D",
+                since: "v0.3.0",
+                options: &["topic-rename", "tf-mode", "color", "line-width", "label"],
+            },
+        })
+    }
+}
+
+/// Decodes messages from the definition recorded in the bag, for mappers of
+/// types without a built-in layout
+#[derive(Default)]
+pub struct DefinitionDecoder {
+    schemas: SchemaCache,
+    /// Connections are numbered per bag, so schemas are cached by topic and type
+    ids: HashMap<(String, String), u32>,
+}
+
+impl DefinitionDecoder {
+    /// The decoded message, or `None` when its definition is missing or the payload does not decode
+    pub fn decode(&mut self, ctx: &MapperContext<'_>, payload: &[u8]) -> Option<Value> {
+        let next = self.ids.len() as u32;
+        let id = *self.ids.entry((ctx.topic.to_string(), ctx.tp.to_string())).or_insert(next);
+        let definition = ctx.type_info.map(|info| info.definition.as_str());
+        let schema = self.schemas.get(id, ctx.tp, definition)?;
+        schema
+            .decode(payload)
+            .inspect_err(|e| tracing::debug!("Failed to decode {} message on {}: {:#}", ctx.tp, ctx.topic, e))
+            .ok()
+    }
+}
+
+/// vision_msgs detection arrays, decoded from the definition in the bag
+struct DetectionMapper {
+    decoder: DefinitionDecoder,
+    classes: ClassMap,
+}
+
+impl MessageMapper for DetectionMapper {
+    fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> Result<Mapped> {
+        let Some(value) = self.decoder.decode(ctx, payload) else {
+            return Ok(Mapped::Skipped);
+        };
+        let is_3d = ctx.tp == "vision_msgs/Detection3DArray";
+        // 3D boxes are in their sensor frame; attached, the frame entity places them
+        let entity = if is_3d { ctx.attached_path.unwrap_or(ctx.entity) } else { ctx.entity };
+        crate::mappings::detection::detections_to_rerun(ctx.rec, entity, ctx.ts, &value, is_3d, &self.classes)?;
+        Ok(Mapped::Logged(MessageKind::Other))
+    }
+
+    fn describe(&self, tp: &str) -> Option<MappingInfo> {
+        Some(match tp {
+            "vision_msgs/Detection3DArray" => MappingInfo {
+                archetypes: "Boxes3D (+class ids)",
+                since: "v0.5.1",
+                options: &["class-map", "attach-to-frames"],
+            },
+            _ => MappingInfo { archetypes: "Boxes2D (+class ids)", since: "v0.5.1", options: &["class-map", "topic-rename"] },
+        })
+    }
+}
+
+/// Any type, decoded from the definition in the bag (--generic-fallback)
+#[derive(Default)]
+struct GenericMapper {
+    decoder: DefinitionDecoder,
+}
+
+impl MessageMapper for GenericMapper {
+    fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> Result<Mapped> {
+        let Some(value) = self.decoder.decode(ctx, payload) else {
+            return Ok(Mapped::Skipped);
+        };
+        crate::mappings::generic::generic_to_rerun(ctx.rec, ctx.entity, ctx.ts, &value, ctx.scalars)?;
+        Ok(Mapped::Logged(MessageKind::Generic))
+    }
+
+    fn describe(&self, _tp: &str) -> Option<MappingInfo> {
+        Some(MappingInfo { archetypes: "Scalars + TextLog", since: "v0.5.1", options: &["generic-fallback"] })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Named(&'static str);
+
+    impl MessageMapper for Named {
+        fn map(&mut self, _ctx: &mut MapperContext<'_>, _payload: &[u8]) -> Result<Mapped> {
+            Ok(Mapped::Skipped)
+        }
+
+        fn finish(&mut self) -> Result<()> {
+            anyhow::bail!("{}", self.0)
+        }
+    }
+
+    fn name(mapper: Option<&mut dyn MessageMapper>) -> Option<String> {
+        mapper.map(|m| m.finish().unwrap_err().to_string())
+    }
+
+    #[test]
+    fn test_registry_lookup_order() {
+        let mut registry = MapperRegistry::new();
+        registry.register(&["std_msgs/String", "std_msgs/Float64"], Box::new(Named("type")));
+        registry.register_topic("status", Box::new(Named("topic")));
+        assert_eq!(name(registry.get_mut("/status", "std_msgs/String")).as_deref(), Some("topic"));
+        assert_eq!(name(registry.get_mut("/speed", "std_msgs/Float64")).as_deref(), Some("type"));
+        assert!(registry.get("/speed", "std_msgs/Int32").is_none());
+
+        registry.set_fallback(Box::new(Named("fallback")));
+        assert_eq!(name(registry.get_mut("/speed", "std_msgs/Int32")).as_deref(), Some("fallback"));
+        // A later registration replaces the type's mapper
+        registry.register(&["std_msgs/Float64"], Box::new(Named("override")));
+        assert_eq!(name(registry.get_mut("/speed", "std_msgs/Float64")).as_deref(), Some("override"));
+    }
+}
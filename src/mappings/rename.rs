@@ -0,0 +1,145 @@
+//! PATTERN=REPLACEMENT rules for --topic-rename and --map-frame
+//!
+//! A pattern is matched against the whole name and is either:
+//! - a regex, when it contains any of `( [ \ ^ $ | +`; the replacement may use `$1`/`${name}`
+//! - a glob, when it contains `*` (any run of characters except `/`), `**` (anything)
+//!   or `?` (one character); each wildcard is a capture group `$1`, `$2`, ...
+//! - an exact name otherwise
+
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+
+use crate::convert::ConvertOptions;
+
+/// PATTERN=REPLACEMENT rules with their patterns compiled, built once per conversion
+#[derive(Clone, Debug, Default)]
+pub struct RenameRules {
+    rules: Vec<RenameRule>,
+}
+
+#[derive(Clone, Debug)]
+struct RenameRule {
+    pattern: String,
+    /// None for an exact name
+    regex: Option<Regex>,
+    replacement: String,
+}
+
+impl RenameRules {
+    /// Compile `rules`; fails on a rule without '=' or with an invalid pattern
+    pub fn new(rules: &[String]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let (pattern, replacement) = rule
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("Invalid rename rule '{}': expected PATTERN=REPLACEMENT", rule))?;
+                Ok(RenameRule {
+                    pattern: pattern.to_string(),
+                    regex: pattern_regex(pattern)?,
+                    replacement: replacement.to_string(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Rewrite `name` with the first matching rule
+    pub fn rename(&self, name: &str) -> Option<String> {
+        for rule in &self.rules {
+            if rule.pattern == name {
+                return Some(rule.replacement.clone());
+            }
+            if let Some(caps) = rule.regex.as_ref().and_then(|re| re.captures(name)) {
+                let mut out = String::new();
+                caps.expand(&rule.replacement, &mut out);
+                return Some(out);
+            }
+        }
+        None
+    }
+}
+
+/// The --map-frame and --topic-rename rules of a conversion
+#[derive(Clone, Debug, Default)]
+pub struct EntityRenames {
+    pub frames: RenameRules,
+    pub topics: RenameRules,
+}
+
+impl EntityRenames {
+    pub fn new(options: &ConvertOptions) -> Result<Self> {
+        Ok(Self {
+            frames: RenameRules::new(&options.frame_mappings).context("invalid --map-frame")?,
+            topics: RenameRules::new(&options.topic_renames).context("invalid --topic-rename")?,
+        })
+    }
+}
+
+/// Anchored regex for a regex or glob pattern; None for an exact name
+pub(crate) fn pattern_regex(pattern: &str) -> Result<Option<Regex>> {
+    let source = if pattern.contains(['(', '[', '\\', '^', '$', '|', '+']) {
+        pattern.to_string()
+    } else if pattern.contains(['*', '?']) {
+        glob_to_regex(pattern)
+    } else {
+        return Ok(None);
+    };
+    let anchored = format!("^(?:{})$", source.trim_start_matches('^').trim_end_matches('$'));
+    Regex::new(&anchored)
+        .map(Some)
+        .map_err(|e| anyhow!("Invalid rename pattern '{}': {}", pattern, e))
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut out = String::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                out.push_str("(.*)");
+            }
+            '*' => out.push_str("([^/]*)"),
+            '?' => out.push_str("([^/])"),
+            _ => out.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(rules: &[&str]) -> RenameRules {
+        RenameRules::new(&rules.iter().map(|r| r.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn test_rename_exact_glob_and_regex() {
+        let rules = rules(&[
+            "/odom=/world/odometry",
+            "/camera/(.*)/image_raw=/sensors/cam/$1",
+            "/lidar_*/points=/sensors/lidar/$1",
+            "/debug/**=/dbg/$1",
+        ]);
+        assert_eq!(rules.rename("/odom").as_deref(), Some("/world/odometry"));
+        assert_eq!(rules.rename("/camera/left/image_raw").as_deref(), Some("/sensors/cam/left"));
+        assert_eq!(rules.rename("/lidar_front/points").as_deref(), Some("/sensors/lidar/front"));
+        // A single * does not cross '/', ** does
+        assert_eq!(rules.rename("/lidar_a/b/points"), None);
+        assert_eq!(rules.rename("/debug/a/b").as_deref(), Some("/dbg/a/b"));
+        // Patterns match the whole name
+        assert_eq!(rules.rename("/odom/filtered"), None);
+        assert_eq!(rules.rename("/ns/camera/left/image_raw"), None);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let new = |rules: &[&str]| RenameRules::new(&rules.iter().map(|r| r.to_string()).collect::<Vec<_>>());
+        assert!(new(&["base_link=/world/base", "cam_(\\d+)=/cams/$1"]).is_ok());
+        assert!(new(&["no_separator"]).is_err());
+        assert!(new(&["/camera/(.*=/x"]).is_err());
+    }
+}
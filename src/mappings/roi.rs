@@ -0,0 +1,112 @@
+//! --roi: crop point clouds and laser scans to a box of the root frame
+//!
+//! Points are moved into the root frame with TF at the message time before
+//! the test, whatever frame they are logged in (--no-pointcloud-tf and
+//! --attach-to-frames keep the sensor frame). Messages whose frame TF cannot
+//! place are logged uncropped.
+
+use anyhow::{bail, Result};
+use nalgebra::{Isometry3, Point3};
+use serde::Serialize;
+
+use crate::mappings::tf::{TfGraph, TfMode};
+
+/// Axis-aligned box in the root frame, in meters
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct RegionOfInterest {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+impl RegionOfInterest {
+    pub fn contains(&self, p: &Point3<f64>) -> bool {
+        (0..3).all(|i| p[i] >= self.min[i] && p[i] <= self.max[i])
+    }
+}
+
+/// Parse "XMIN,YMIN,ZMIN,XMAX,YMAX,ZMAX", or "XMIN,YMIN,XMAX,YMAX" for a box of any height
+pub fn parse_roi(s: &str) -> Result<RegionOfInterest> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<f64>().map_err(|_| anyhow::anyhow!("Invalid ROI value '{}'", v.trim())))
+        .collect::<Result<Vec<f64>>>()?;
+    let (min, max) = match values[..] {
+        [x0, y0, x1, y1] => ([x0, y0, f64::NEG_INFINITY], [x1, y1, f64::INFINITY]),
+        [x0, y0, z0, x1, y1, z1] => ([x0, y0, z0], [x1, y1, z1]),
+        _ => bail!("Invalid ROI '{}' (expected XMIN,YMIN,ZMIN,XMAX,YMAX,ZMAX or XMIN,YMIN,XMAX,YMAX)", s),
+    };
+    if (0..3).any(|i| min[i] >= max[i] || min[i].is_nan() || max[i].is_nan()) {
+        bail!("Invalid ROI '{}': every min must be below its max", s);
+    }
+    Ok(RegionOfInterest { min, max })
+}
+
+/// The --roi box with what places a sensor frame in the root frame
+#[derive(Clone, Copy, Debug)]
+pub struct RoiCrop<'a> {
+    pub region: &'a RegionOfInterest,
+    pub tf: &'a TfGraph,
+    pub root_frame: &'a str,
+    pub tf_mode: TfMode,
+}
+
+impl RoiCrop<'_> {
+    /// Which of `points`, given in `frame`, lie inside the box at `ts`; None when TF cannot place the frame
+    pub fn mask(&self, frame: &str, ts: f64, points: impl Iterator<Item = Point3<f64>>) -> Option<Vec<bool>> {
+        // Frameless data is logged as if in the root frame
+        let to_root = if frame.is_empty() {
+            Isometry3::identity()
+        } else {
+            let Some(iso) = self.tf.resolve_pose(self.root_frame, frame, ts, self.tf_mode) else {
+                tracing::debug!(%frame, "no TF from the frame to {}; not cropping to the ROI", self.root_frame);
+                return None;
+            };
+            iso
+        };
+        Some(points.map(|p| self.region.contains(&(to_root * p))).collect())
+    }
+}
+
+/// The values whose `mask` entry is true
+pub fn retain<T>(values: Vec<T>, mask: &[bool]) -> Vec<T> {
+    values.into_iter().zip(mask).filter(|(_, keep)| **keep).map(|(v, _)| v).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappings::tf::{Header, RosQuaternion, Transform, TransformStamped, Vector3};
+
+    #[test]
+    fn test_parse_roi() {
+        let roi = parse_roi("0, -2, 0, 30, 2, 3").unwrap();
+        assert_eq!(roi, RegionOfInterest { min: [0.0, -2.0, 0.0], max: [30.0, 2.0, 3.0] });
+        assert!(roi.contains(&Point3::new(10.0, 1.0, 2.0)));
+        assert!(!roi.contains(&Point3::new(10.0, 3.0, 2.0)));
+        let aisle = parse_roi("0,-2,30,2").unwrap();
+        assert!(aisle.contains(&Point3::new(10.0, 1.0, 100.0)));
+        assert!(parse_roi("0,0,0,1,1").is_err());
+        assert!(parse_roi("5,0,1,1").is_err());
+        assert!(parse_roi("0,0,x,1").is_err());
+    }
+
+    #[test]
+    fn test_roi_mask_in_root_frame() {
+        let region = parse_roi("0,0,-1,10,10,1").unwrap();
+        let mut tf = TfGraph::new();
+        tf.add_static_transform(&TransformStamped {
+            header: Header { stamp: 0.0, frame_id: "map".to_string() },
+            child_frame_id: "lidar".to_string(),
+            transform: Transform {
+                translation: Vector3 { x: 5.0, y: 5.0, z: 0.0 },
+                rotation: RosQuaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+            },
+        });
+        let crop = RoiCrop { region: &region, tf: &tf, root_frame: "map", tf_mode: TfMode::Nearest };
+        let points = [Point3::new(0.0, 0.0, 0.0), Point3::new(-6.0, 0.0, 0.0), Point3::new(4.0, 4.0, 0.5)];
+        assert_eq!(crop.mask("lidar", 0.0, points.into_iter()), Some(vec![true, false, true]));
+        assert_eq!(crop.mask("", 0.0, points.into_iter()), Some(vec![true, false, true]));
+        assert_eq!(crop.mask("camera", 0.0, points.into_iter()), None);
+        assert_eq!(retain(vec!['a', 'b', 'c'], &[true, false, true]), ['a', 'c']);
+    }
+}
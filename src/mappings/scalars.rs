@@ -0,0 +1,120 @@
+//! Scalar series buffered per entity and sent as columns
+//!
+//! Logging a kHz IMU one `Scalars` row at a time makes one tiny chunk per row
+//! in the RRD. Mappers push their scalars here instead; each entity's rows are
+//! sent with `send_columns` on the three timelines once they span
+//! [`MAX_SPAN_SECS`] of ros_time or reach [`MAX_ROWS`], and when the
+//! conversion closes a recording. Low-rate series (a 1 Hz GPS status) still
+//! go out about once a second, so a live viewer lags them by at most that.
+
+use anyhow::Result;
+use std::collections::HashMap;
+
+use crate::timeline::{MessageTime, BAG_TIME, FRAME_INDEX, ROS_TIME};
+
+/// ros_time covered by the rows of an entity before they are sent
+pub const MAX_SPAN_SECS: f64 = 1.0;
+/// Rows of an entity before they are sent, whatever their span
+pub const MAX_ROWS: usize = 4096;
+
+/// Rows of one entity, with the recording they belong to
+struct Series {
+    rec: rerun::RecordingStream,
+    times: Vec<MessageTime>,
+    values: Vec<f64>,
+}
+
+impl Series {
+    fn full(&self) -> bool {
+        let span = match (self.times.first(), self.times.last()) {
+            (Some(first), Some(last)) => (last.ros_time - first.ros_time).abs(),
+            _ => 0.0,
+        };
+        self.values.len() >= MAX_ROWS || span >= MAX_SPAN_SECS
+    }
+
+    fn send(&mut self, entity: &str) -> Result<()> {
+        if self.values.is_empty() {
+            return Ok(());
+        }
+        let times = std::mem::take(&mut self.times);
+        let indexes = [
+            rerun::TimeColumn::new_timestamp_secs_since_epoch(ROS_TIME, times.iter().map(|t| t.ros_time)),
+            rerun::TimeColumn::new_timestamp_secs_since_epoch(BAG_TIME, times.iter().map(|t| t.bag_time)),
+            rerun::TimeColumn::new_sequence(FRAME_INDEX, times.iter().map(|t| t.frame_index)),
+        ];
+        let values = std::mem::take(&mut self.values);
+        self.rec.send_columns(entity, indexes, rerun::archetypes::Scalars::new(values).columns_of_unit_batches()?)?;
+        Ok(())
+    }
+}
+
+/// Scalar series of the conversion, buffered until sent as columns
+#[derive(Default)]
+pub struct ScalarColumns {
+    /// Timelines of the message being mapped
+    time: MessageTime,
+    series: HashMap<String, Series>,
+}
+
+impl ScalarColumns {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Timelines of the next rows, set with those of each message
+    pub fn set_time(&mut self, time: MessageTime) {
+        self.time = time;
+    }
+
+    /// One value of the series at `entity`, at the time of the current message
+    pub fn push(&mut self, rec: &rerun::RecordingStream, entity: &str, value: f64) -> Result<()> {
+        let series = self.series.entry(entity.to_string()).or_insert_with(|| Series {
+            rec: rec.clone(),
+            times: Vec::new(),
+            values: Vec::new(),
+        });
+        series.times.push(self.time);
+        series.values.push(value);
+        if series.full() {
+            series.send(entity)?;
+        }
+        Ok(())
+    }
+
+    /// Send every buffered row; before a recording is closed or replaced by the next segment
+    pub fn flush(&mut self) -> Result<()> {
+        for (entity, mut series) in self.series.drain() {
+            series.send(&entity)?;
+        }
+        Ok(())
+    }
+
+    /// Rows waiting to be sent
+    pub fn pending(&self) -> usize {
+        self.series.values().map(|series| series.values.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_series_sent_by_span() {
+        let (rec, _storage) = rerun::RecordingStreamBuilder::new("bag2rrd_test").memory().unwrap();
+        let mut scalars = ScalarColumns::new();
+        for i in 0..10 {
+            scalars.set_time(MessageTime { ros_time: i as f64 * 0.01, bag_time: i as f64 * 0.01, frame_index: i });
+            scalars.push(&rec, "/imu/accel", i as f64).unwrap();
+            scalars.push(&rec, "/gps/status", 0.0).unwrap();
+        }
+        assert_eq!(scalars.pending(), 20);
+        // The row reaching a second of ros_time sends its series
+        scalars.set_time(MessageTime { ros_time: 1.0, bag_time: 1.0, frame_index: 10 });
+        scalars.push(&rec, "/imu/accel", 10.0).unwrap();
+        assert_eq!(scalars.pending(), 10);
+        scalars.flush().unwrap();
+        assert_eq!(scalars.pending(), 0);
+    }
+}
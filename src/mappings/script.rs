@@ -0,0 +1,221 @@
+//! In-house message types → Scalars, TextLog and Points3D, mapped by a Rhai script (--mapper-script)
+//!
+//! The script lists the types it maps and receives each of their messages,
+//! decoded from the definition in the bag, as an object map:
+//!
+//! ```rhai
+//! fn types() { ["acme_msgs/Battery", "acme_msgs/Obstacles"] }
+//!
+//! fn map(topic, msg_type, msg) {
+//!     if msg_type == "acme_msgs/Battery" {
+//!         scalar("volts", msg.voltage);
+//!         text("state", msg.status);
+//!     } else {
+//!         points("obstacles", msg.obstacles.map(|o| [o.x, o.y, 0.0]));
+//!     }
+//! }
+//! ```
+//!
+//! Paths are relative to the topic entity. A message the script logs nothing for counts as skipped.
+
+use anyhow::{Context, Result};
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, FLOAT, INT};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::mappings::registry::{
+    DefinitionDecoder, Mapped, MapperContext, MappingInfo, MessageKind, MessageMapper, SCRIPT_MAPPING,
+};
+use crate::ros_msg::Value;
+
+/// Something a script asked to log, under a path relative to the topic entity
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScriptOutput {
+    Scalar(String, f64),
+    Text(String, String),
+    Points(String, Vec<[f32; 3]>),
+}
+
+pub struct ScriptMapper {
+    path: PathBuf,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    outputs: Rc<RefCell<Vec<ScriptOutput>>>,
+    decoder: DefinitionDecoder,
+}
+
+impl ScriptMapper {
+    /// Compile the script and run its top-level statements
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut engine = Engine::new();
+        // Rhai halves its nesting limits in debug builds; a script must load in both
+        engine.set_max_expr_depths(64, 32);
+        let outputs = Rc::new(RefCell::new(Vec::new()));
+        let sink = outputs.clone();
+        engine.register_fn("scalar", move |path: &str, value: FLOAT| {
+            sink.borrow_mut().push(ScriptOutput::Scalar(path.to_string(), value));
+        });
+        let sink = outputs.clone();
+        engine.register_fn("scalar", move |path: &str, value: INT| {
+            sink.borrow_mut().push(ScriptOutput::Scalar(path.to_string(), value as f64));
+        });
+        let sink = outputs.clone();
+        engine.register_fn("text", move |path: &str, text: &str| {
+            sink.borrow_mut().push(ScriptOutput::Text(path.to_string(), text.to_string()));
+        });
+        let sink = outputs.clone();
+        engine.register_fn("points", move |path: &str, points: Array| -> Result<(), Box<EvalAltResult>> {
+            let points = points
+                .into_iter()
+                .map(|point| {
+                    let xyz = point.try_cast::<Array>().unwrap_or_default();
+                    match xyz.iter().map(number).collect::<Option<Vec<f64>>>().as_deref() {
+                        Some([x, y, z]) => Ok([*x as f32, *y as f32, *z as f32]),
+                        _ => Err(format!("points(\"{}\"): each point must be an [x, y, z] array of numbers", path)),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            sink.borrow_mut().push(ScriptOutput::Points(path.to_string(), points));
+            Ok(())
+        });
+
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("failed to compile {}", path.display()))?;
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            engine,
+            ast,
+            scope,
+            outputs,
+            decoder: DefinitionDecoder::default(),
+        })
+    }
+
+    /// ROS types returned by the script's `types()`
+    pub fn types(&mut self) -> Result<Vec<String>> {
+        let types: Array = self.call("types", ())?;
+        types
+            .into_iter()
+            .map(|tp| tp.into_string().map_err(|found| anyhow::anyhow!("types() returned a {}, not a string", found)))
+            .collect()
+    }
+
+    /// Run the script's `map()` on a decoded message
+    pub fn run(&mut self, topic: &str, tp: &str, msg: &Value) -> Result<Vec<ScriptOutput>> {
+        self.outputs.borrow_mut().clear();
+        let _: Dynamic = self.call("map", (topic.to_string(), tp.to_string(), to_dynamic(msg)))?;
+        Ok(std::mem::take(&mut *self.outputs.borrow_mut()))
+    }
+
+    fn call<T: Clone + 'static>(&mut self, name: &str, args: impl rhai::FuncArgs) -> Result<T> {
+        // Top-level statements ran once at load
+        let options = CallFnOptions::new().eval_ast(false);
+        self.engine
+            .call_fn_with_options(options, &mut self.scope, &self.ast, name, args)
+            .map_err(|e| anyhow::anyhow!("{}: {}() failed: {}", self.path.display(), name, e))
+    }
+}
+
+impl MessageMapper for ScriptMapper {
+    fn map(&mut self, ctx: &mut MapperContext<'_>, payload: &[u8]) -> Result<Mapped> {
+        let Some(msg) = self.decoder.decode(ctx, payload) else {
+            return Ok(Mapped::Skipped);
+        };
+        let outputs = self.run(ctx.topic, ctx.tp, &msg)?;
+        if outputs.is_empty() {
+            return Ok(Mapped::Skipped);
+        }
+        ctx.rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ctx.ts);
+        let root = format!("/{}", ctx.entity.trim_matches('/'));
+        let entity = |path: &str| format!("{}/{}", root, path.trim_matches('/'));
+        for output in outputs {
+            match output {
+                ScriptOutput::Scalar(path, v) => ctx.scalars.push(ctx.rec, &entity(&path), v)?,
+                ScriptOutput::Text(path, text) => ctx.rec.log(entity(&path), &rerun::archetypes::TextLog::new(text))?,
+                ScriptOutput::Points(path, points) => ctx.rec.log(entity(&path), &rerun::archetypes::Points3D::new(points))?,
+            }
+        }
+        Ok(Mapped::Logged(MessageKind::Other))
+    }
+
+    fn describe(&self, _tp: &str) -> Option<MappingInfo> {
+        Some(SCRIPT_MAPPING)
+    }
+}
+
+fn number(value: &Dynamic) -> Option<f64> {
+    value.as_float().ok().or_else(|| value.as_int().ok().map(|i| i as f64))
+}
+
+/// Decoded message as Rhai values: nested messages become object maps, byte arrays blobs
+fn to_dynamic(value: &Value) -> Dynamic {
+    match value {
+        Value::Bool(b) => (*b).into(),
+        Value::Int(i) => (*i).into(),
+        Value::UInt(u) => INT::try_from(*u).map_or_else(|_| (*u as FLOAT).into(), Dynamic::from),
+        Value::Float(f) | Value::Time(f) => (*f).into(),
+        Value::String(s) => s.clone().into(),
+        Value::Bytes(bytes) => Dynamic::from_blob(bytes.clone()),
+        Value::Array(items) => items.iter().map(to_dynamic).collect::<Array>().into(),
+        Value::Struct(fields) => fields
+            .iter()
+            .map(|(name, field)| (name.as_str().into(), to_dynamic(field)))
+            .collect::<Map>()
+            .into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_maps_decoded_message() {
+        let path = std::env::temp_dir().join(format!("bag2rrd_mapper_{}.rhai", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+            fn types() { ["acme_msgs/Battery"] }
+            fn map(topic, msg_type, msg) {
+                if msg.voltage < 0.0 { return; }
+                scalar("volts", msg.voltage * 2.0);
+                scalar("cells", msg.cells.len());
+                text("state", `${topic}: ${msg.status}`);
+                points("probes", msg.probes.map(|p| [p.x, p.y, 0]));
+            }
+            "#,
+        )
+        .unwrap();
+        let mut mapper = ScriptMapper::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(mapper.types().unwrap(), ["acme_msgs/Battery"]);
+
+        let probe = |x: f64| Value::Struct(vec![("x".to_string(), Value::Float(x)), ("y".to_string(), Value::UInt(1))]);
+        let battery = |voltage: f64| {
+            Value::Struct(vec![
+                ("voltage".to_string(), Value::Float(voltage)),
+                ("cells".to_string(), Value::Bytes(vec![1, 2, 3])),
+                ("status".to_string(), Value::String("ok".to_string())),
+                ("probes".to_string(), Value::Array(vec![probe(0.5), probe(1.5)])),
+            ])
+        };
+        assert_eq!(
+            mapper.run("/battery", "acme_msgs/Battery", &battery(12.5)).unwrap(),
+            [
+                ScriptOutput::Scalar("volts".to_string(), 25.0),
+                ScriptOutput::Scalar("cells".to_string(), 3.0),
+                ScriptOutput::Text("state".to_string(), "/battery: ok".to_string()),
+                ScriptOutput::Points("probes".to_string(), vec![[0.5, 1.0, 0.0], [1.5, 1.0, 0.0]]),
+            ]
+        );
+        assert!(mapper.run("/battery", "acme_msgs/Battery", &battery(-1.0)).unwrap().is_empty());
+    }
+}
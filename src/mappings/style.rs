@@ -0,0 +1,104 @@
+//! Per-topic drawing style (--color, --radius, --line-width, --label)
+//!
+//! Applied by the mappers drawing points and lines: GPS fixes and paths, laser
+//! scans, point clouds, odometry trajectories and nav paths. A color given for a
+//! topic replaces the per-point colors of --scan-color and --pointcloud-color-field.
+
+use anyhow::{anyhow, Result};
+
+use crate::mappings::images::{parse_topic_setting, TopicSetting};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Style {
+    /// RGBA
+    pub color: Option<[u8; 4]>,
+    /// Point radius in meters
+    pub radius: Option<f32>,
+    /// Line width in meters
+    pub line_width: Option<f32>,
+    pub label: Option<String>,
+}
+
+/// Apply the style to an archetype; `$radius` gives the radius of its points or lines
+macro_rules! styled {
+    ($name:ident, $ty:ty, $radius:expr) => {
+        #[doc = concat!("`", stringify!($ty), "` with the style's color, size and label")]
+        pub fn $name(&self, mut archetype: $ty) -> $ty {
+            if let Some(color) = self.color {
+                archetype = archetype.with_colors([color]);
+            }
+            if let Some(radius) = $radius(self) {
+                archetype = archetype.with_radii([radius]);
+            }
+            if let Some(label) = &self.label {
+                archetype = archetype.with_labels([label.as_str()]).with_show_labels(true);
+            }
+            archetype
+        }
+    };
+}
+
+impl Style {
+    styled!(points3d, rerun::archetypes::Points3D, |s: &Style| s.radius);
+    styled!(points2d, rerun::archetypes::Points2D, |s: &Style| s.radius);
+    styled!(lines3d, rerun::archetypes::LineStrips3D, |s: &Style| s.line_width.map(|w| w / 2.0));
+    styled!(lines2d, rerun::archetypes::LineStrips2D, |s: &Style| s.line_width.map(|w| w / 2.0));
+}
+
+/// Parse "#RRGGBB", "#RRGGBBAA" or "R,G,B[,A]" (0-255)
+pub fn parse_color(s: &str) -> Result<[u8; 4]> {
+    let invalid = || anyhow!("Invalid color '{}' (expected #RRGGBB, #RRGGBBAA or R,G,B[,A])", s);
+    let channels: Vec<u8> = match s.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 || hex.len() == 8 => (0..hex.len())
+            .step_by(2)
+            .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?,
+        Some(_) => return Err(invalid()),
+        None => s.split(',').map(|c| c.trim().parse::<u8>()).collect::<Result<_, _>>().map_err(|_| invalid())?,
+    };
+    match channels.as_slice() {
+        [r, g, b] => Ok([*r, *g, *b, 255]),
+        [r, g, b, a] => Ok([*r, *g, *b, *a]),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parse "COLOR" or "TOPIC=COLOR" for --color
+pub fn parse_color_setting(s: &str) -> Result<TopicSetting<[u8; 4]>> {
+    parse_topic_setting(s, parse_color)
+}
+
+/// Parse "METERS" or "TOPIC=METERS" for --radius and --line-width
+pub fn parse_size_setting(s: &str) -> Result<TopicSetting<f32>> {
+    parse_topic_setting(s, |v| match v.parse::<f32>() {
+        Ok(size) if size > 0.0 && size.is_finite() => Ok(size),
+        _ => Err(anyhow!("Size must be a positive number of meters: '{}'", v)),
+    })
+}
+
+/// Parse "TEXT" or "TOPIC=TEXT" for --label
+pub fn parse_label_setting(s: &str) -> Result<TopicSetting<String>> {
+    parse_topic_setting(s, |v| Ok(v.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_style_settings() {
+        assert_eq!(parse_color("#ff8000").unwrap(), [255, 128, 0, 255]);
+        assert_eq!(parse_color("#FF800080").unwrap(), [255, 128, 0, 128]);
+        assert_eq!(parse_color("0, 160, 255").unwrap(), [0, 160, 255, 255]);
+        assert!(parse_color("#ff80").is_err());
+        assert!(parse_color("256,0,0").is_err());
+        assert!(parse_color("red").is_err());
+
+        let color = parse_color_setting("/gps/fix=#00ff00").unwrap();
+        assert_eq!((color.topic.as_deref(), color.value), (Some("/gps/fix"), [0, 255, 0, 255]));
+        assert_eq!(parse_size_setting("/scan=0.05").unwrap().value, 0.05);
+        assert!(parse_size_setting("0").is_err());
+        assert_eq!(parse_label_setting("/odom=wheel odometry").unwrap().value, "wheel odometry");
+    }
+}
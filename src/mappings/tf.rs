@@ -1,9 +1,20 @@
 //! /tf and /tf_static → Rerun Transforms3D (implemented in v0.3.0)
+//!
+//! Frame names are interned in a [`FrameRegistry`]: the graph's edges, parent
+//! bookkeeping and path cache are keyed by [`FrameId`], and /tf messages are
+//! parsed with the frame names borrowed from the payload, so ingesting a known
+//! edge allocates no strings.
 
 use anyhow::{anyhow, Result};
 use nalgebra::{Isometry3, Quaternion, Translation3, UnitQuaternion};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::collections::{BTreeMap, HashMap, HashSet};
 
+use crate::mappings::rename::RenameRules;
+use crate::ros_codec::Cursor;
+
 #[derive(Clone, Copy, Debug)]
 pub struct TfSample {
     pub t: f64,
@@ -11,250 +22,681 @@ pub struct TfSample {
     pub quat: [f64; 4], // [x, y, z, w]
 }
 
+/// Interned frame name, valid for the [`FrameRegistry`] that issued it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FrameId(u32);
+
+/// Frame names of a TF buffer, each stored once and referred to by id
+#[derive(Clone, Debug, Default)]
+pub struct FrameRegistry {
+    ids: HashMap<String, FrameId>,
+    names: Vec<String>,
+}
+
+impl FrameRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Id of `name`, registering it the first time it is seen
+    pub fn intern(&mut self, name: &str) -> FrameId {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+        let id = FrameId(self.names.len() as u32);
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    /// Id of a frame already registered
+    pub fn get(&self, name: &str) -> Option<FrameId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, id: FrameId) -> &str {
+        &self.names[id.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// (parent, child)
+type Edge = (FrameId, FrameId);
+
 #[derive(Clone, Debug)]
 pub struct TfGraph {
-    // key: (parent, child) in ROS names
-    dynamic: BTreeMap<(String, String), Vec<TfSample>>, // sorted by t
-    static_edges: BTreeMap<(String, String), TfSample>,
+    frames: FrameRegistry,
+    dynamic: BTreeMap<Edge, Vec<TfSample>>, // sorted by t
+    static_edges: BTreeMap<Edge, TfSample>,
     // For cycle detection in static graph
-    static_graph: HashMap<String, HashSet<String>>, // parent -> children
+    static_graph: HashMap<FrameId, HashSet<FrameId>>, // parent -> children
+    // Log each frame's pose relative to the root instead of its parent
+    root_relative: bool,
+    // Dynamic edges are only traversed with a sample this close to the query time
+    time_tolerance: f64,
+    // Without a path within time_tolerance, resolve through the nearest samples of any edge
+    tolerance_fallback: bool,
+    // Also log each dynamic edge's translation and yaw/pitch/roll as scalars
+    scalar_plots: bool,
+    // Coordinate axes drawn at frame entities, and the frames already given them
+    axes: Option<FrameAxes>,
+    axes_logged: HashSet<FrameId>,
+    // How to pick between parents when a child is published under several
+    authority: TfAuthority,
+    // Current parent of each child frame and the topic it came from
+    parent_of: HashMap<FrameId, (FrameId, String)>,
+    // child -> parent -> transforms received
+    parent_counts: HashMap<FrameId, HashMap<FrameId, u64>>,
+    // Re-parented children: (time, parent) sorted by time, starting with the
+    // first parent at -inf, so lookups before a change keep the old parent
+    parent_changes: HashMap<FrameId, Vec<(f64, FrameId)>>,
+    // Latest dynamic sample time, when static transforms re-parent a child
+    latest_t: f64,
+    // Last path found per (source, target); dropped whenever a new edge appears
+    path_cache: RefCell<HashMap<Edge, Vec<Hop>>>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+impl Default for TfGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Which parent wins when a child frame is published under more than one parent
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub enum TfAuthority {
+    /// Keep the first parent seen; later parents are ignored
+    FirstWins,
+    /// Re-parent the child to the latest parent, like tf2; lookups at earlier
+    /// times still go through the old parent
+    #[default]
+    LastWins,
+    /// Transforms from this topic win; among other topics the first parent wins
+    PreferTopic(String),
+}
+
+/// Axis gizmos for frames whose name matches `filter` (all frames when None)
+#[derive(Clone, Debug)]
+struct FrameAxes {
+    length: f32,
+    filter: Option<regex::Regex>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
 pub enum TfMode {
     Nearest,
     Interpolate,
     None,
+    /// Only static transforms; dynamic edges are never traversed
+    Static,
 }
 
 impl TfGraph {
     pub fn new() -> Self {
         Self {
+            frames: FrameRegistry::new(),
             dynamic: BTreeMap::new(),
             static_edges: BTreeMap::new(),
             static_graph: HashMap::new(),
+            root_relative: false,
+            time_tolerance: f64::INFINITY,
+            tolerance_fallback: false,
+            scalar_plots: false,
+            axes: None,
+            axes_logged: HashSet::new(),
+            authority: TfAuthority::LastWins,
+            parent_of: HashMap::new(),
+            parent_counts: HashMap::new(),
+            parent_changes: HashMap::new(),
+            latest_t: f64::NEG_INFINITY,
+            path_cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Maximum distance in seconds between the query time and the nearest sample
+    /// of a dynamic edge for that edge to be used when resolving (default: unlimited)
+    pub fn set_time_tolerance(&mut self, seconds: f64) {
+        self.time_tolerance = seconds;
+    }
+
+    /// When no path has data within the time tolerance, fall back to the
+    /// nearest samples of every edge instead of failing the lookup
+    pub fn set_tolerance_fallback(&mut self, enabled: bool) {
+        self.tolerance_fallback = enabled;
+    }
+
+    /// Log every frame's pose relative to the root frame rather than its parent.
+    ///
+    /// Frame entities are laid out flat under `/<root>/`, so this is what makes the
+    /// logged transforms compose correctly when sensors are attached to frames.
+    pub fn set_root_relative(&mut self, enabled: bool) {
+        self.root_relative = enabled;
+    }
+
+    /// Log each dynamic edge's x/y/z and yaw/pitch/roll as Scalars under
+    /// `/tf_plots/<parent>__<child>/` while ingesting /tf messages
+    pub fn set_scalar_plots(&mut self, enabled: bool) {
+        self.scalar_plots = enabled;
+    }
+
+    /// Draw RGB coordinate axes of `length` meters at each frame entity, like RViz's
+    /// TF display; `filter` restricts them to frame names matching the regex
+    pub fn set_frame_axes(&mut self, length: f32, filter: Option<regex::Regex>) {
+        self.axes = Some(FrameAxes { length, filter });
+    }
+
+    /// Names of the frames seen so far
+    pub fn frame_registry(&self) -> &FrameRegistry {
+        &self.frames
+    }
+
+    /// Log the axis length of `frame` once, as static data next to its transforms
+    fn log_frame_axes(&mut self, rec: &rerun::RecordingStream, frame: FrameId, root_frame: &str, map_frame: &RenameRules) -> Result<()> {
+        let Some(axes) = &self.axes else {
+            return Ok(());
+        };
+        let name = self.frames.name(frame);
+        if self.axes_logged.contains(&frame) || axes.filter.as_ref().is_some_and(|re| !re.is_match(name)) {
+            return Ok(());
+        }
+        let frame_path = map_frame_to_path(name, root_frame, map_frame);
+        rec.log_static(frame_path, &rerun::archetypes::Transform3D::update_fields().with_axis_length(axes.length))?;
+        self.axes_logged.insert(frame);
+        Ok(())
+    }
+
+    /// Policy for children published under several parents (default: last wins)
+    pub fn set_authority(&mut self, authority: TfAuthority) {
+        self.authority = authority;
+    }
+
+    /// Children received under more than one parent, with the number of
+    /// transforms seen per parent; both sorted by name
+    pub fn parent_conflicts(&self) -> impl Iterator<Item = (&str, Vec<(&str, u64)>)> {
+        let mut conflicts: Vec<(&str, Vec<(&str, u64)>)> = self
+            .parent_counts
+            .iter()
+            .filter(|(_, parents)| parents.len() > 1)
+            .map(|(child, parents)| {
+                let mut parents: Vec<(&str, u64)> = parents.iter().map(|(parent, n)| (self.frames.name(*parent), *n)).collect();
+                parents.sort();
+                (self.frames.name(*child), parents)
+            })
+            .collect();
+        conflicts.sort();
+        conflicts.into_iter()
+    }
+
+    /// Apply the authority policy to a transform parent -> child from `topic`
+    /// received at time `t`; false if it must be ignored
+    fn accept_parent(&mut self, parent: FrameId, child: FrameId, topic: &str, t: f64) -> bool {
+        let counts = self.parent_counts.entry(child).or_default();
+        *counts.entry(parent).or_default() += 1;
+        let Some((current, current_topic)) = self.parent_of.get(&child) else {
+            self.parent_of.insert(child, (parent, topic.to_string()));
+            return true;
+        };
+        let current = *current;
+        if current == parent {
+            return true;
+        }
+        let reparent = match &self.authority {
+            TfAuthority::FirstWins => false,
+            TfAuthority::LastWins => true,
+            TfAuthority::PreferTopic(preferred) => topic == preferred && current_topic != preferred,
+        };
+        if !reparent {
+            return false;
+        }
+        // Keep the old edge for lookups before `t`
+        let changes = self.parent_changes.entry(child).or_insert_with(|| vec![(f64::NEG_INFINITY, current)]);
+        let idx = changes.partition_point(|(at, _)| *at <= t);
+        changes.insert(idx, (t, parent));
+        self.path_cache.get_mut().clear();
+        self.parent_of.insert(child, (parent, topic.to_string()));
+        true
+    }
+
+    /// Add a dynamic transform to the buffer, sampled at time `t`
+    ///
+    /// Samples are kept sorted by time, so out-of-order stamps are fine. Nothing is
+    /// pruned here; see [`TfGraph::prune`]. Returns false if the authority policy
+    /// rejected the transform's parent.
+    pub fn add_transform(&mut self, tf: &TransformStamped, t: f64) -> bool {
+        self.add_transform_from(tf, t, "")
+    }
+
+    /// [`TfGraph::add_transform`] for a transform received on `topic`
+    pub fn add_transform_from(&mut self, tf: &TransformStamped, t: f64, topic: &str) -> bool {
+        self.add_dynamic_edge(&tf.header.frame_id, &tf.child_frame_id, &tf.transform, t, topic).is_some()
+    }
+
+    /// Insert a dynamic sample; the edge's ids, or None if the policy rejected it
+    fn add_dynamic_edge(&mut self, parent: &str, child: &str, transform: &Transform, t: f64, topic: &str) -> Option<Edge> {
+        let key = (self.frames.intern(parent), self.frames.intern(child));
+        if !self.accept_parent(key.0, key.1, topic, t) {
+            return None;
+        }
+        self.latest_t = self.latest_t.max(t);
+        let sample = to_sample(transform, t);
+        if !self.dynamic.contains_key(&key) {
+            self.path_cache.get_mut().clear();
+        }
+        let samples = self.dynamic.entry(key).or_default();
+        let idx = samples.partition_point(|s| s.t <= t);
+        samples.insert(idx, sample);
+        Some(key)
+    }
+
+    /// Add a static transform, valid at all times
+    ///
+    /// Returns false (and ignores the edge) if it would close a cycle in the static
+    /// tree or the authority policy rejected its parent.
+    pub fn add_static_transform(&mut self, tf: &TransformStamped) -> bool {
+        self.add_static_transform_from(tf, "")
+    }
+
+    /// [`TfGraph::add_static_transform`] for a transform received on `topic`
+    pub fn add_static_transform_from(&mut self, tf: &TransformStamped, topic: &str) -> bool {
+        self.add_static_edge(&tf.header.frame_id, &tf.child_frame_id, &tf.transform, topic).is_some()
+    }
+
+    fn add_static_edge(&mut self, parent: &str, child: &str, transform: &Transform, topic: &str) -> Option<Edge> {
+        let key = (self.frames.intern(parent), self.frames.intern(child));
+        if self.would_create_cycle(key.0, key.1) {
+            tracing::warn!("Static TF edge {parent} -> {child} would create a cycle, skipping");
+            return None;
+        }
+        if !self.accept_parent(key.0, key.1, topic, self.latest_t) {
+            return None;
         }
+        let sample = to_sample(transform, 0.0);
+        if self.static_edges.insert(key, sample).is_none() {
+            self.path_cache.get_mut().clear();
+        }
+        self.static_graph.entry(key.0).or_default().insert(key.1);
+        Some(key)
+    }
+
+    /// Drop dynamic samples older than `buffer_seconds` before `latest_ts`
+    pub fn prune(&mut self, latest_ts: f64, buffer_seconds: f64) {
+        let cutoff = latest_ts - buffer_seconds;
+        for samples in self.dynamic.values_mut() {
+            // Samples are time-sorted, so the expired ones form a prefix
+            let expired = samples.partition_point(|s| s.t < cutoff);
+            samples.drain(..expired);
+        }
+        // Keep the last parent change before the cutoff, it still holds there
+        for changes in self.parent_changes.values_mut() {
+            let expired = changes.partition_point(|(at, _)| *at < cutoff).saturating_sub(1);
+            changes.drain(..expired);
+        }
+    }
+
+    /// Pose of `source_frame` in `target_frame` at `time`, like tf2's `lookupTransform`
+    ///
+    /// Same as [`TfGraph::resolve_pose`], with an error naming the missing frames.
+    pub fn lookup_transform(&self, target_frame: &str, source_frame: &str, time: f64, mode: TfMode) -> Result<Isometry3<f64>> {
+        if let Some(iso) = self.resolve_pose(target_frame, source_frame, time, mode) {
+            return Ok(iso);
+        }
+        let frames = self.frames();
+        if let Some(missing) = [target_frame, source_frame].into_iter().find(|frame| !frames.iter().any(|f| f == frame)) {
+            return Err(anyhow!("frame \"{missing}\" does not exist in the TF buffer"));
+        }
+        Err(anyhow!("no transform from \"{source_frame}\" to \"{target_frame}\" at t={time:.6} ({mode:?})"))
+    }
+
+    /// Whether `lookup_transform(target_frame, source_frame, time, mode)` would succeed
+    pub fn can_transform(&self, target_frame: &str, source_frame: &str, time: f64, mode: TfMode) -> bool {
+        self.resolve_pose(target_frame, source_frame, time, mode).is_some()
+    }
+
+    /// Every frame name with an edge in the buffer, sorted
+    pub fn frames(&self) -> Vec<String> {
+        let mut frames: Vec<FrameId> = self.static_edges.keys().chain(self.dynamic.keys()).flat_map(|(p, c)| [*p, *c]).collect();
+        frames.sort();
+        frames.dedup();
+        let mut names: Vec<String> = frames.into_iter().map(|id| self.frames.name(id).to_string()).collect();
+        names.sort();
+        names
     }
 
     /// Ingest a /tf message
-    pub fn ingest_tf_msg(&mut self, rec: &rerun::RecordingStream, ts: f64, payload: &[u8], buffer_seconds: f64, root_frame: &str, map_frame: &[String]) -> Result<()> {
-        let transforms = parse_tf_message(payload)?;
-        for tf in transforms {
-            let parent = tf.header.frame_id;
-            let child = tf.child_frame_id;
-            let trans = tf.transform.translation;
-            let rot = tf.transform.rotation;
-            // Normalize quaternion
-            let quat = UnitQuaternion::from_quaternion(Quaternion::new(rot.w, rot.x, rot.y, rot.z));
-            let quat_normalized = quat.quaternion();
-            let sample = TfSample {
-                t: ts,
-                trans: [trans.x, trans.y, trans.z],
-                quat: [quat_normalized.i, quat_normalized.j, quat_normalized.k, quat_normalized.w],
+    ///
+    /// Each transform is sampled at its own header.stamp minus `stamp_base` (the
+    /// timeline origin); with no base, or a zero stamp, the message time `ts` is used.
+    #[allow(clippy::too_many_arguments)]
+    pub fn ingest_tf_msg(&mut self, rec: &rerun::RecordingStream, topic: &str, ts: f64, stamp_base: Option<f64>, payload: &[u8], buffer_seconds: f64, root_frame: &str, map_frame: &RenameRules) -> Result<()> {
+        let mut latest_ts = ts;
+        for tf in TfMessageReader::new(payload)? {
+            let tf = tf?;
+            let sample_ts = match stamp_base {
+                Some(base) if tf.stamp > 0.0 => tf.stamp - base,
+                _ => ts,
+            };
+            latest_ts = latest_ts.max(sample_ts);
+            let Some((_, child)) = self.add_dynamic_edge(&tf.parent, &tf.child, &tf.transform, sample_ts, topic) else {
+                continue;
             };
-            self.dynamic.entry((parent.clone(), child.clone())).or_default().push(sample);
+            if self.scalar_plots {
+                rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, sample_ts);
+                log_tf_plots(rec, &tf.parent, &tf.child, &to_sample(&tf.transform, sample_ts))?;
+            }
+            self.log_frame_axes(rec, child, root_frame, map_frame)?;
 
             // Log the transform
-            let parent_path = map_frame_to_path(&parent, root_frame, map_frame);
-            let child_path = map_frame_to_path(&child, root_frame, map_frame);
-            log_transform(rec, &parent_path, &child_path, &sample_to_isometry(&sample), ts)?;
+            if self.root_relative {
+                // The child and everything below it moved relative to the root
+                let Some(root) = self.frames.get(root_frame) else {
+                    continue;
+                };
+                for frame in self.descendants(child) {
+                    if frame == root {
+                        continue;
+                    }
+                    if let Some(iso) = self.resolve_ids(root, frame, sample_ts, TfMode::Nearest, true) {
+                        let frame_path = map_frame_to_path(self.frames.name(frame), root_frame, map_frame);
+                        log_transform(rec, root_frame, &frame_path, &iso, sample_ts)?;
+                    }
+                }
+                continue;
+            }
+            let parent_path = map_frame_to_path(&tf.parent, root_frame, map_frame);
+            let child_path = map_frame_to_path(&tf.child, root_frame, map_frame);
+            let iso = sample_to_isometry(&to_sample(&tf.transform, sample_ts));
+            log_transform(rec, &parent_path, &child_path, &iso, sample_ts)?;
         }
         // Prune old samples based on latest ts
-        self.prune_dynamic(ts, buffer_seconds);
+        self.prune(latest_ts, buffer_seconds);
         Ok(())
     }
 
     /// Ingest a /tf_static message
-    pub fn ingest_tf_static_msg(&mut self, rec: &rerun::RecordingStream, payload: &[u8], root_frame: &str, map_frame: &[String]) -> Result<()> {
-        let transforms = parse_tf_message(payload)?;
-        for tf in transforms {
-            let parent = tf.header.frame_id;
-            let child = tf.child_frame_id;
-            let trans = tf.transform.translation;
-            let rot = tf.transform.rotation;
-            // Normalize quaternion
-            let quat = UnitQuaternion::from_quaternion(Quaternion::new(rot.w, rot.x, rot.y, rot.z));
-            let quat_normalized = quat.quaternion();
-            let sample = TfSample {
-                t: 0.0,
-                trans: [trans.x, trans.y, trans.z],
-                quat: [quat_normalized.i, quat_normalized.j, quat_normalized.k, quat_normalized.w],
+    pub fn ingest_tf_static_msg(&mut self, rec: &rerun::RecordingStream, topic: &str, payload: &[u8], root_frame: &str, map_frame: &RenameRules) -> Result<()> {
+        for tf in TfMessageReader::new(payload)? {
+            let tf = tf?;
+            let Some((_, child)) = self.add_static_edge(&tf.parent, &tf.child, &tf.transform, topic) else {
+                continue;
             };
-            // Check for cycle
-            if self.would_create_cycle(&parent, &child) {
-                tracing::warn!("Static TF edge {parent} -> {child} would create a cycle, skipping");
+            self.log_frame_axes(rec, child, root_frame, map_frame)?;
+
+            // Log the static transform as static data so it applies on every timeline
+            if self.root_relative {
+                // Only subtrees hanging off the root through static edges have a fixed
+                // root pose; the others are logged whenever a dynamic ancestor moves
+                let Some(root) = self.frames.get(root_frame) else {
+                    continue;
+                };
+                for frame in self.descendants(child) {
+                    if frame == root {
+                        continue;
+                    }
+                    if let Some(iso) = self.resolve_ids(root, frame, 0.0, TfMode::Static, true) {
+                        let frame_path = map_frame_to_path(self.frames.name(frame), root_frame, map_frame);
+                        rec.log_static(frame_path, &to_rerun_transform(&iso))?;
+                    }
+                }
                 continue;
             }
-            self.static_edges.insert((parent.clone(), child.clone()), sample);
-            self.static_graph.entry(parent.clone()).or_default().insert(child.clone());
-
-            // Log the static transform
-            let parent_path = map_frame_to_path(&parent, root_frame, map_frame);
-            let child_path = map_frame_to_path(&child, root_frame, map_frame);
-            log_transform(rec, &parent_path, &child_path, &sample_to_isometry(&sample), 0.0)?;
+            let child_path = map_frame_to_path(&tf.child, root_frame, map_frame);
+            rec.log_static(child_path, &to_rerun_transform(&sample_to_isometry(&to_sample(&tf.transform, 0.0))))?;
         }
         Ok(())
     }
 
-    fn would_create_cycle(&self, parent: &str, child: &str) -> bool {
+    /// `frame` and every frame below it, following static and dynamic edges
+    fn descendants(&self, frame: FrameId) -> Vec<FrameId> {
+        let mut out = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![frame];
+        while let Some(node) = stack.pop() {
+            if !visited.insert(node) {
+                continue;
+            }
+            for (p, c) in self.static_edges.keys().chain(self.dynamic.keys()) {
+                if *p == node {
+                    stack.push(*c);
+                }
+            }
+            out.push(node);
+        }
+        out
+    }
+
+    fn would_create_cycle(&self, parent: FrameId, child: FrameId) -> bool {
         // Simple cycle detection: check if child can reach parent
         let mut visited = HashSet::new();
-        let mut stack = vec![child.to_string()];
+        let mut stack = vec![child];
         while let Some(node) = stack.pop() {
-            if !visited.insert(node.clone()) {
+            if !visited.insert(node) {
                 continue;
             }
             if node == parent {
                 return true;
             }
             if let Some(children) = self.static_graph.get(&node) {
-                for child in children {
-                    stack.push(child.clone());
-                }
+                stack.extend(children.iter().copied());
             }
         }
         false
     }
 
-    fn prune_dynamic(&mut self, latest_ts: f64, buffer_seconds: f64) {
-        let cutoff = latest_ts - buffer_seconds;
-        for samples in self.dynamic.values_mut() {
-            samples.retain(|s| s.t >= cutoff);
+    /// Resolve transform from source_frame to target_frame at time at_time
+    ///
+    /// Returns the pose of `target_frame` in `source_frame`, the inverse of
+    /// [`TfGraph::resolve_pose`] with the same arguments.
+    #[deprecated(note = "returns the inverse of lookupTransform(target, source); use resolve_pose")]
+    pub fn resolve(&self, target_frame: &str, source_frame: &str, at_time: f64, mode: TfMode) -> Option<Isometry3<f64>> {
+        // Compose transforms along the path, each as parent_to_child
+        self.compose_path(source_frame, target_frame, at_time, mode, false)
+    }
+
+    /// Pose of `source_frame` in `target_frame` at `at_time`
+    ///
+    /// The returned isometry maps points expressed in `source_frame` into
+    /// `target_frame` (the ROS `lookupTransform(target, source)` convention).
+    pub fn resolve_pose(&self, target_frame: &str, source_frame: &str, at_time: f64, mode: TfMode) -> Option<Isometry3<f64>> {
+        self.compose_path(source_frame, target_frame, at_time, mode, true)
+    }
+
+    /// Compose the edges on a path from source to target; see `compose`
+    fn compose_path(&self, source_frame: &str, target_frame: &str, at_time: f64, mode: TfMode, pose: bool) -> Option<Isometry3<f64>> {
+        if target_frame == source_frame {
+            return Some(Isometry3::identity());
         }
+        self.resolve_ids(self.frames.get(target_frame)?, self.frames.get(source_frame)?, at_time, mode, pose)
     }
 
-    /// Resolve transform from source_frame to target_frame at time at_time
-    pub fn resolve(&self, target_frame: &str, source_frame: &str, at_time: f64, mode: TfMode) -> Option<Isometry3<f64>> {
-        // Find path from source to target
-        let path = self.find_path(source_frame, target_frame)?;
-        // Compose transforms along the path
+    fn resolve_ids(&self, target: FrameId, source: FrameId, at_time: f64, mode: TfMode, pose: bool) -> Option<Isometry3<f64>> {
+        // Reuse the last path found between these frames while its edges have data at at_time
+        let cached = self
+            .path_cache
+            .borrow()
+            .get(&(source, target))
+            .filter(|path| path.iter().all(|hop| self.hop_valid(hop, at_time, mode)))
+            .map(|path| self.compose(path, at_time, mode, pose));
+        if let Some(iso) = cached {
+            return iso;
+        }
+        // Find path from source to target through edges with data at at_time
+        let path = self.find_path(source, target, at_time, mode, self.time_tolerance).or_else(|| {
+            // Stale edges rather than no transform at all
+            if !self.tolerance_fallback || !matches!(mode, TfMode::Nearest | TfMode::Interpolate) {
+                return None;
+            }
+            self.find_path(source, target, at_time, mode, f64::INFINITY)
+        })?;
+        let iso = self.compose(&path, at_time, mode, pose);
+        self.path_cache.borrow_mut().insert((source, target), path);
+        iso
+    }
+
+    /// With `pose`, each hop moves one frame closer to the target: T_to_source = T_to_from * T_from_source.
+    /// Without it, each edge is taken from the hop's start frame to its end frame, as `resolve` does.
+    fn compose(&self, path: &[Hop], at_time: f64, mode: TfMode, pose: bool) -> Option<Isometry3<f64>> {
         let mut iso = Isometry3::identity();
-        for (parent, child) in path {
-            let edge_iso = self.get_edge_transform(&parent, &child, at_time, mode)?;
-            iso = edge_iso * iso; // Compose: parent_to_child * current
+        for hop in path {
+            let edge_iso = match self.static_edges.get(&hop.edge) {
+                Some(sample) => sample_to_isometry(sample),
+                None => interpolate_samples(self.dynamic.get(&hop.edge)?, at_time, mode)?,
+            };
+            iso = if hop.inverse == pose { edge_iso.inverse() } else { edge_iso } * iso;
         }
         Some(iso)
     }
 
-    fn find_path(&self, source: &str, target: &str) -> Option<Vec<(String, String)>> {
+    fn hop_valid(&self, hop: &Hop, at_time: f64, mode: TfMode) -> bool {
+        if !self.parent_at(hop.edge, at_time) {
+            return false;
+        }
+        if self.static_edges.contains_key(&hop.edge) {
+            return true;
+        }
+        self.dynamic
+            .get(&hop.edge)
+            .is_some_and(|samples| dynamic_edge_valid(samples, at_time, mode, self.time_tolerance))
+    }
+
+    /// Whether the edge's parent is its child's parent at `at_time`; always
+    /// true for children that were never re-parented
+    fn parent_at(&self, (parent, child): Edge, at_time: f64) -> bool {
+        self.parent_changes.get(&child).is_none_or(|changes| {
+            let idx = changes.partition_point(|(at, _)| *at <= at_time);
+            changes[idx.saturating_sub(1)].1 == parent
+        })
+    }
+
+    fn find_path(&self, source: FrameId, target: FrameId, at_time: f64, mode: TfMode, tolerance: f64) -> Option<Vec<Hop>> {
         // BFS to find path from source to target
         let mut visited = HashSet::new();
         let mut queue = std::collections::VecDeque::new();
-        let mut parent_map: HashMap<String, (String, String)> = HashMap::new();
-        queue.push_back(source.to_string());
-        visited.insert(source.to_string());
+        let mut parent_map: HashMap<FrameId, (FrameId, Hop)> = HashMap::new();
+        queue.push_back(source);
+        visited.insert(source);
         while let Some(current) = queue.pop_front() {
             if current == target {
                 // Reconstruct path
                 let mut path = Vec::new();
                 let mut node = current;
-                while let Some((p, c)) = parent_map.get(&node) {
-                    path.push((p.clone(), c.clone()));
-                    node = p.clone();
+                while let Some((prev, hop)) = parent_map.remove(&node) {
+                    path.push(hop);
+                    node = prev;
                 }
                 path.reverse();
                 return Some(path);
             }
-            // Find neighbors: parents and children
-            for (p, c) in self.static_edges.keys() {
-                if p == &current && !visited.contains(c) {
-                    visited.insert(c.clone());
-                    parent_map.insert(c.clone(), (p.clone(), c.clone()));
-                    queue.push_back(c.clone());
+            // Find neighbors: parents and children; dynamic edges only when they have data near the query time
+            let static_keys = self.static_edges.keys();
+            let dynamic_keys = self
+                .dynamic
+                .iter()
+                .filter(|(_, samples)| dynamic_edge_valid(samples, at_time, mode, tolerance))
+                .map(|(key, _)| key);
+            for (p, c) in static_keys.chain(dynamic_keys).copied().filter(|edge| self.parent_at(*edge, at_time)) {
+                // Moving parent -> child needs T_child_parent, the inverse of the edge
+                if p == current && visited.insert(c) {
+                    parent_map.insert(c, (current, Hop { edge: (p, c), inverse: true }));
+                    queue.push_back(c);
                 }
-                if c == &current && !visited.contains(p) {
-                    visited.insert(p.clone());
-                    parent_map.insert(p.clone(), (current.clone(), p.clone()));
-                    queue.push_back(p.clone());
-                }
-            }
-            // Also dynamic edges
-            for (p, c) in self.dynamic.keys() {
-                if p == &current && !visited.contains(c) {
-                    visited.insert(c.clone());
-                    parent_map.insert(c.clone(), (p.clone(), c.clone()));
-                    queue.push_back(c.clone());
-                }
-                if c == &current && !visited.contains(p) {
-                    visited.insert(p.clone());
-                    parent_map.insert(p.clone(), (current.clone(), p.clone()));
-                    queue.push_back(p.clone());
+                if c == current && visited.insert(p) {
+                    parent_map.insert(p, (current, Hop { edge: (p, c), inverse: false }));
+                    queue.push_back(p);
                 }
             }
         }
         None
     }
+}
 
-    fn get_edge_transform(&self, parent: &str, child: &str, at_time: f64, mode: TfMode) -> Option<Isometry3<f64>> {
-        if let Some(sample) = self.static_edges.get(&(parent.to_string(), child.to_string())) {
-            return Some(sample_to_isometry(sample));
-        }
-        if let Some(sample) = self.static_edges.get(&(child.to_string(), parent.to_string())) {
-            // Inverse transform
-            let iso = sample_to_isometry(sample);
-            return Some(iso.inverse());
-        }
-        if let Some(samples) = self.dynamic.get(&(parent.to_string(), child.to_string())) {
-            return self.interpolate_samples(samples, at_time, mode);
-        }
-        if let Some(samples) = self.dynamic.get(&(child.to_string(), parent.to_string())) {
-            // Inverse
-            let iso = self.interpolate_samples(samples, at_time, mode)?;
-            return Some(iso.inverse());
-        }
-        None
+/// One step of a resolved frame path: the stored (parent, child) edge, used
+/// directly when stepping child -> parent and inverted when stepping down
+#[derive(Clone, Copy, Debug)]
+struct Hop {
+    edge: Edge,
+    inverse: bool,
+}
+
+/// Whether a dynamic edge has a sample usable at `at_time`, `tolerance` seconds
+/// away at most when sampling the nearest or interpolated transform
+fn dynamic_edge_valid(samples: &[TfSample], at_time: f64, mode: TfMode, tolerance: f64) -> bool {
+    let tolerance = match mode {
+        TfMode::Static => return false,
+        TfMode::None => EXACT_TIME_EPS,
+        TfMode::Nearest | TfMode::Interpolate => tolerance,
+    };
+    nearest_sample(samples, at_time).is_some_and(|s| (s.t - at_time).abs() <= tolerance)
+}
+
+/// Two stamps closer than this are the same instant for TfMode::None
+const EXACT_TIME_EPS: f64 = 1e-9;
+
+/// Sample closest to `at_time` in a time-sorted slice (earlier one on ties)
+fn nearest_sample(samples: &[TfSample], at_time: f64) -> Option<&TfSample> {
+    let idx = samples.partition_point(|s| s.t < at_time);
+    let before = idx.checked_sub(1).map(|i| &samples[i]);
+    let after = samples.get(idx);
+    match (before, after) {
+        (Some(b), Some(a)) if (a.t - at_time).abs() < (at_time - b.t).abs() => Some(a),
+        (Some(b), _) => Some(b),
+        (None, a) => a,
     }
+}
 
-    fn interpolate_samples(&self, samples: &[TfSample], at_time: f64, mode: TfMode) -> Option<Isometry3<f64>> {
-        match mode {
-            TfMode::None => samples.iter().find(|s| (s.t - at_time).abs() < 1e-9).map(sample_to_isometry),
-            TfMode::Nearest => {
-                let mut best: Option<&TfSample> = None;
-                let mut best_diff = f64::INFINITY;
-                for s in samples {
-                    let diff = (s.t - at_time).abs();
-                    if diff < best_diff {
-                        best_diff = diff;
-                        best = Some(s);
-                    }
-                }
-                best.map(sample_to_isometry)
-            }
-            TfMode::Interpolate => {
-                let mut before: Option<&TfSample> = None;
-                let mut after: Option<&TfSample> = None;
-                for s in samples {
-                    if s.t <= at_time && (before.is_none() || s.t > before.unwrap().t) {
-                        before = Some(s);
-                    }
-                    if s.t >= at_time && (after.is_none() || s.t < after.unwrap().t) {
-                        after = Some(s);
-                    }
-                }
-                match (before, after) {
-                    (Some(b), Some(a)) if (a.t - b.t).abs() > 1e-9 => {
-                        let t = (at_time - b.t) / (a.t - b.t);
-                        let trans = [
-                            b.trans[0] + t * (a.trans[0] - b.trans[0]),
-                            b.trans[1] + t * (a.trans[1] - b.trans[1]),
-                            b.trans[2] + t * (a.trans[2] - b.trans[2]),
-                        ];
-                        let quat_b = UnitQuaternion::from_quaternion(Quaternion::new(b.quat[3], b.quat[0], b.quat[1], b.quat[2]));
-                        let quat_a = UnitQuaternion::from_quaternion(Quaternion::new(a.quat[3], a.quat[0], a.quat[1], a.quat[2]));
-                        let quat = quat_b.slerp(&quat_a, t);
-                        let quat_arr = quat.quaternion();
-                        let sample = TfSample { t: at_time, trans, quat: [quat_arr.i, quat_arr.j, quat_arr.k, quat_arr.w] };
-                        Some(sample_to_isometry(&sample))
-                    }
-                    (Some(b), _) => Some(sample_to_isometry(b)),
-                    (_, Some(a)) => Some(sample_to_isometry(a)),
-                    _ => None,
+fn interpolate_samples(samples: &[TfSample], at_time: f64, mode: TfMode) -> Option<Isometry3<f64>> {
+    match mode {
+        TfMode::Static => None,
+        TfMode::None => nearest_sample(samples, at_time)
+            .filter(|s| (s.t - at_time).abs() < EXACT_TIME_EPS)
+            .map(sample_to_isometry),
+        TfMode::Nearest => nearest_sample(samples, at_time).map(sample_to_isometry),
+        TfMode::Interpolate => {
+            // Latest sample at or before at_time, earliest at or after it
+            let before = samples.partition_point(|s| s.t <= at_time).checked_sub(1).map(|i| &samples[i]);
+            let after = samples.get(samples.partition_point(|s| s.t < at_time));
+            match (before, after) {
+                (Some(b), Some(a)) if (a.t - b.t).abs() > EXACT_TIME_EPS => {
+                    let t = (at_time - b.t) / (a.t - b.t);
+                    let trans = [
+                        b.trans[0] + t * (a.trans[0] - b.trans[0]),
+                        b.trans[1] + t * (a.trans[1] - b.trans[1]),
+                        b.trans[2] + t * (a.trans[2] - b.trans[2]),
+                    ];
+                    let quat_b = UnitQuaternion::from_quaternion(Quaternion::new(b.quat[3], b.quat[0], b.quat[1], b.quat[2]));
+                    let quat_a = UnitQuaternion::from_quaternion(Quaternion::new(a.quat[3], a.quat[0], a.quat[1], a.quat[2]));
+                    let quat = quat_b.slerp(&quat_a, t);
+                    let quat_arr = quat.quaternion();
+                    let sample = TfSample { t: at_time, trans, quat: [quat_arr.i, quat_arr.j, quat_arr.k, quat_arr.w] };
+                    Some(sample_to_isometry(&sample))
                 }
+                (Some(b), _) => Some(sample_to_isometry(b)),
+                (_, Some(a)) => Some(sample_to_isometry(a)),
+                _ => None,
             }
         }
     }
 }
 
+/// Sample at time `t` with a normalized rotation
+fn to_sample(transform: &Transform, t: f64) -> TfSample {
+    let trans = transform.translation;
+    let rot = transform.rotation;
+    let quat = UnitQuaternion::from_quaternion(Quaternion::new(rot.w, rot.x, rot.y, rot.z));
+    let quat_normalized = quat.quaternion();
+    TfSample {
+        t,
+        trans: [trans.x, trans.y, trans.z],
+        quat: [quat_normalized.i, quat_normalized.j, quat_normalized.k, quat_normalized.w],
+    }
+}
+
 fn sample_to_isometry(sample: &TfSample) -> Isometry3<f64> {
     let trans = Translation3::new(sample.trans[0], sample.trans[1], sample.trans[2]);
     let quat = UnitQuaternion::from_quaternion(Quaternion::new(sample.quat[3], sample.quat[0], sample.quat[1], sample.quat[2]));
@@ -268,24 +710,58 @@ fn log_transform(
     iso: &Isometry3<f64>,
     ts: f64,
 ) -> Result<()> {
-    rec.set_timestamp_secs_since_epoch("ros_time", ts);
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
+    rec.log(child_path, &to_rerun_transform(iso))?;
+    Ok(())
+}
+
+/// Scalar series for one edge: translation in meters, yaw/pitch/roll in degrees
+fn log_tf_plots(rec: &rerun::RecordingStream, parent: &str, child: &str, sample: &TfSample) -> Result<()> {
+    let path = format!("/tf_plots/{}__{}", plot_name(parent), plot_name(child));
+    let quat = UnitQuaternion::from_quaternion(Quaternion::new(sample.quat[3], sample.quat[0], sample.quat[1], sample.quat[2]));
+    let (roll, pitch, yaw) = quat.euler_angles();
+    let channels = [
+        ("x", sample.trans[0]),
+        ("y", sample.trans[1]),
+        ("z", sample.trans[2]),
+        ("yaw", yaw.to_degrees()),
+        ("pitch", pitch.to_degrees()),
+        ("roll", roll.to_degrees()),
+    ];
+    for (name, value) in channels {
+        rec.log(format!("{path}/{name}"), &rerun::archetypes::Scalars::new([value]))?;
+    }
+    Ok(())
+}
+
+/// Frame name as a single entity path part
+fn plot_name(frame: &str) -> String {
+    frame.trim_start_matches('/').replace('/', "_")
+}
+
+fn to_rerun_transform(iso: &Isometry3<f64>) -> rerun::archetypes::Transform3D {
     let trans = iso.translation.vector;
     let quat = iso.rotation.quaternion();
-    let transform = rerun::archetypes::Transform3D::from_translation_rotation(
+    rerun::archetypes::Transform3D::from_translation_rotation(
         [trans.x as f32, trans.y as f32, trans.z as f32],
         rerun::datatypes::Quaternion::from_xyzw([quat.i as f32, quat.j as f32, quat.k as f32, quat.w as f32]),
-    );
-    rec.log(child_path, &transform)?;
-    Ok(())
+    )
 }
 
-fn map_frame_to_path(frame: &str, root_frame: &str, map_frame: &[String]) -> String {
-    for mapping in map_frame {
-        if let Some((ros_frame, rr_path)) = mapping.split_once('=') && ros_frame == frame {
-            return rr_path.to_string();
-        }
+pub(crate) fn map_frame_to_path(frame: &str, root_frame: &str, map_frame: &RenameRules) -> String {
+    map_frame.rename(frame).unwrap_or_else(|| format!("/{root_frame}/{frame}"))
+}
+
+/// first-wins | last-wins | prefer-topic=TOPIC
+pub fn parse_tf_authority(s: &str) -> Result<TfAuthority> {
+    match s {
+        "first-wins" => Ok(TfAuthority::FirstWins),
+        "last-wins" => Ok(TfAuthority::LastWins),
+        _ => match s.strip_prefix("prefer-topic=") {
+            Some(topic) if !topic.is_empty() => Ok(TfAuthority::PreferTopic(topic.to_string())),
+            _ => Err(anyhow!("Invalid tf-authority: {} (expected first-wins, last-wins or prefer-topic=TOPIC)", s)),
+        },
     }
-    format!("/{root_frame}/{frame}")
 }
 
 pub fn parse_tf_mode(s: &str) -> Result<TfMode> {
@@ -293,131 +769,150 @@ pub fn parse_tf_mode(s: &str) -> Result<TfMode> {
         "nearest" => Ok(TfMode::Nearest),
         "interpolate" => Ok(TfMode::Interpolate),
         "none" => Ok(TfMode::None),
+        "static" => Ok(TfMode::Static),
         _ => Err(anyhow!("Invalid tf-mode: {}", s)),
     }
 }
 
 // ROS message structs
-#[derive(Debug)]
-struct Header {
-    frame_id: String,
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    /// Seconds since epoch (0.0 when unset)
+    pub stamp: f64,
+    pub frame_id: String,
 }
 
-#[derive(Debug)]
-struct Vector3 {
-    x: f64,
-    y: f64,
-    z: f64,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vector3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
 }
 
-#[derive(Debug)]
-struct RosQuaternion {
-    x: f64,
-    y: f64,
-    z: f64,
-    w: f64,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RosQuaternion {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
 }
 
-#[derive(Debug)]
-struct Transform {
-    translation: Vector3,
-    rotation: RosQuaternion,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3,
+    pub rotation: RosQuaternion,
 }
 
-#[derive(Debug)]
-struct TransformStamped {
-    header: Header,
-    child_frame_id: String,
-    transform: Transform,
+/// geometry_msgs/TransformStamped: pose of `child_frame_id` in `header.frame_id`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransformStamped {
+    pub header: Header,
+    pub child_frame_id: String,
+    pub transform: Transform,
 }
 
-fn parse_tf_message(payload: &[u8]) -> Result<Vec<TransformStamped>> {
-    let mut cursor = 0;
-    // Skip ROS header if any, but for TF, it's an array of TransformStamped
-    // TF message is tf2_msgs/TFMessage which is std_msgs/Header + TransformStamped[]
-    // But in practice, it's just the array.
-    // Assume it's a sequence of TransformStamped
-    let mut transforms = Vec::new();
-    while cursor < payload.len() {
-        let tf = parse_transform_stamped(payload, &mut cursor)?;
-        transforms.push(tf);
-    }
-    Ok(transforms)
+/// (parent, child, header.stamp seconds) for each transform in a TFMessage
+pub fn parse_tf_edges(payload: &[u8]) -> Result<Vec<(String, String, f64)>> {
+    TfMessageReader::new(payload)?
+        .map(|tf| tf.map(|tf| (tf.parent.into_owned(), tf.child.into_owned(), tf.stamp)))
+        .collect()
 }
 
-fn parse_transform_stamped(payload: &[u8], cursor: &mut usize) -> Result<TransformStamped> {
-    // TransformStamped: header, child_frame_id, transform
-    let header = parse_header(payload, cursor)?;
-    let child_frame_id = parse_string(payload, cursor)?;
-    let transform = parse_transform(payload, cursor)?;
-    Ok(TransformStamped { header, child_frame_id, transform })
+/// Decode a tf2_msgs/TFMessage (or tf/tfMessage) payload
+pub fn parse_tf_message(payload: &[u8]) -> Result<Vec<TransformStamped>> {
+    TfMessageReader::new(payload)?.map(|tf| tf.map(TransformRef::into_owned)).collect()
 }
 
-fn parse_header(payload: &[u8], cursor: &mut usize) -> Result<Header> {
-    // Header: seq (u32), stamp (time), frame_id (string)
-    *cursor += 4; // seq
-    *cursor += 8; // stamp
-    let frame_id = parse_string(payload, cursor)?;
-    Ok(Header { frame_id })
+/// geometry_msgs/TransformStamped with its frame names borrowed from the payload
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransformRef<'a> {
+    /// header.stamp in seconds (0.0 when unset)
+    pub stamp: f64,
+    /// header.frame_id
+    pub parent: Cow<'a, str>,
+    /// child_frame_id
+    pub child: Cow<'a, str>,
+    pub transform: Transform,
 }
 
-fn parse_string(payload: &[u8], cursor: &mut usize) -> Result<String> {
-    let len = read_u32_le(payload, cursor)? as usize;
-    let bytes = &payload[*cursor..*cursor + len];
-    *cursor += len;
-    Ok(String::from_utf8_lossy(bytes).to_string())
+impl TransformRef<'_> {
+    pub fn into_owned(self) -> TransformStamped {
+        TransformStamped {
+            header: Header { stamp: self.stamp, frame_id: self.parent.into_owned() },
+            child_frame_id: self.child.into_owned(),
+            transform: self.transform,
+        }
+    }
 }
 
-fn parse_transform(payload: &[u8], cursor: &mut usize) -> Result<Transform> {
-    let translation = parse_vector3(payload, cursor)?;
-    let rotation = parse_quaternion(payload, cursor)?;
-    Ok(Transform { translation, rotation })
+/// Transforms of a tf2_msgs/TFMessage (or tf/tfMessage), decoded one at a time
+pub struct TfMessageReader<'a> {
+    cursor: Cursor<'a>,
+    remaining: usize,
 }
 
-fn parse_vector3(payload: &[u8], cursor: &mut usize) -> Result<Vector3> {
-    let x = read_f64_le(payload, cursor)?;
-    let y = read_f64_le(payload, cursor)?;
-    let z = read_f64_le(payload, cursor)?;
-    Ok(Vector3 { x, y, z })
-}
+impl<'a> TfMessageReader<'a> {
+    /// Read the element count of `payload`
+    ///
+    /// The message is geometry_msgs/TransformStamped[] transforms, serialized as
+    /// a uint32 element count followed by the elements.
+    pub fn new(payload: &'a [u8]) -> Result<Self> {
+        let mut cursor = Cursor::new(payload);
+        let count = cursor.u32()? as usize;
+        // Each TransformStamped is at least 76 bytes; reject counts the payload can't hold
+        if count > payload.len() / 76 {
+            return Err(anyhow!("TFMessage claims {count} transforms but payload is {} bytes", payload.len()));
+        }
+        Ok(Self { cursor, remaining: count })
+    }
 
-fn parse_quaternion(payload: &[u8], cursor: &mut usize) -> Result<RosQuaternion> {
-    let x = read_f64_le(payload, cursor)?;
-    let y = read_f64_le(payload, cursor)?;
-    let z = read_f64_le(payload, cursor)?;
-    let w = read_f64_le(payload, cursor)?;
-    Ok(RosQuaternion { x, y, z, w })
+    fn read(&mut self) -> Result<TransformRef<'a>> {
+        // TransformStamped: header, child_frame_id, transform
+        let header = self.cursor.header()?;
+        let child = self.cursor.str()?;
+        let c = &mut self.cursor;
+        let translation = Vector3 { x: c.f64()?, y: c.f64()?, z: c.f64()? };
+        let rotation = RosQuaternion { x: c.f64()?, y: c.f64()?, z: c.f64()?, w: c.f64()? };
+        Ok(TransformRef { stamp: header.stamp, parent: header.frame_id, child, transform: Transform { translation, rotation } })
+    }
 }
 
-fn read_u32_le(payload: &[u8], cursor: &mut usize) -> Result<u32> {
-    if *cursor + 4 > payload.len() {
-        return Err(anyhow!("Unexpected end of payload"));
+impl<'a> Iterator for TfMessageReader<'a> {
+    type Item = Result<TransformRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let tf = self.read();
+        if tf.is_err() {
+            // Nothing after a truncated element can be read
+            self.remaining = 0;
+        }
+        Some(tf)
     }
-    let val = u32::from_le_bytes(payload[*cursor..*cursor + 4].try_into().unwrap());
-    *cursor += 4;
-    Ok(val)
-}
 
-fn read_f64_le(payload: &[u8], cursor: &mut usize) -> Result<f64> {
-    if *cursor + 8 > payload.len() {
-        return Err(anyhow!("Unexpected end of payload"));
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
     }
-    let val = f64::from_le_bytes(payload[*cursor..*cursor + 8].try_into().unwrap());
-    *cursor += 8;
-    Ok(val)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn edge(graph: &TfGraph, parent: &str, child: &str) -> Edge {
+        (graph.frames.get(parent).unwrap(), graph.frames.get(child).unwrap())
+    }
+
     #[test]
     fn test_quaternion_normalization() {
         let (rec, _) = rerun::RecordingStreamBuilder::new("test").memory().unwrap();
         let mut graph = TfGraph::new();
         // Create a TF message with non-normalized quaternion
         let payload = create_tf_payload();
-        graph.ingest_tf_msg(&rec, 0.0, &payload, 30.0, "world", &[]).unwrap();
+        graph.ingest_tf_msg(&rec, "/tf", 0.0, None, &payload, 30.0, "world", &RenameRules::default()).unwrap();
         // Check that quaternions are normalized
         for samples in graph.dynamic.values() {
             for sample in samples {
@@ -433,10 +928,11 @@ mod tests {
         let mut graph = TfGraph::new();
         // Add static edges A -> B, B -> C
         let payload_ab = create_tf_static_payload("A", "B", [1.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
-        graph.ingest_tf_static_msg(&rec, &payload_ab, "world", &[]).unwrap();
+        graph.ingest_tf_static_msg(&rec, "/tf_static", &payload_ab, "world", &RenameRules::default()).unwrap();
         let payload_bc = create_tf_static_payload("B", "C", [0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
-        graph.ingest_tf_static_msg(&rec, &payload_bc, "world", &[]).unwrap();
+        graph.ingest_tf_static_msg(&rec, "/tf_static", &payload_bc, "world", &RenameRules::default()).unwrap();
         // Resolve A to C
+        #[allow(deprecated)]
         let iso = graph.resolve("C", "A", 0.0, TfMode::Nearest).unwrap();
         let trans = iso.translation.vector;
         assert!((trans.x - 1.0).abs() < 1e-6);
@@ -444,21 +940,373 @@ mod tests {
         assert!((trans.z - 0.0).abs() < 1e-6);
     }
 
+    #[test]
+    fn test_resolve_pose_simple_chain() {
+        let (rec, _) = rerun::RecordingStreamBuilder::new("test").memory().unwrap();
+        let mut graph = TfGraph::new();
+        let payload_ab = create_tf_static_payload("A", "B", [1.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
+        graph.ingest_tf_static_msg(&rec, "/tf_static", &payload_ab, "world", &RenameRules::default()).unwrap();
+        let payload_bc = create_tf_static_payload("B", "C", [0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
+        graph.ingest_tf_static_msg(&rec, "/tf_static", &payload_bc, "world", &RenameRules::default()).unwrap();
+        // Pose of C in A
+        let iso = graph.resolve_pose("A", "C", 0.0, TfMode::Nearest).unwrap();
+        let trans = iso.translation.vector;
+        assert!((trans.x - 1.0).abs() < 1e-6);
+        assert!((trans.y - 1.0).abs() < 1e-6);
+        assert!((trans.z - 0.0).abs() < 1e-6);
+        // And the inverse direction
+        let iso = graph.resolve_pose("C", "A", 0.0, TfMode::Nearest).unwrap();
+        let trans = iso.translation.vector;
+        assert!((trans.x + 1.0).abs() < 1e-6);
+        assert!((trans.y + 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_resolve_chain_with_rotation() {
+        let (rec, _) = rerun::RecordingStreamBuilder::new("test").memory().unwrap();
+        let mut graph = TfGraph::new();
+        // world -> base: 90° yaw, base -> sensor: 1m forward along base x
+        let s = std::f64::consts::FRAC_1_SQRT_2;
+        let payload = create_tf_static_payload("world", "base", [0.0, 0.0, 0.0], [0.0, 0.0, s, s]);
+        graph.ingest_tf_static_msg(&rec, "/tf_static", &payload, "world", &RenameRules::default()).unwrap();
+        let payload = create_tf_static_payload("base", "sensor", [1.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
+        graph.ingest_tf_static_msg(&rec, "/tf_static", &payload, "world", &RenameRules::default()).unwrap();
+        // A point at the sensor origin ends up 1m along world y
+        let iso = graph.resolve_pose("world", "sensor", 0.0, TfMode::Nearest).unwrap();
+        let p = iso * nalgebra::Point3::origin();
+        assert!(p.x.abs() < 1e-6);
+        assert!((p.y - 1.0).abs() < 1e-6);
+    }
+
     #[test]
     fn test_cycle_detection() {
         let (rec, _) = rerun::RecordingStreamBuilder::new("test").memory().unwrap();
         let mut graph = TfGraph::new();
         let payload_ab = create_tf_static_payload("A", "B", [1.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
-        graph.ingest_tf_static_msg(&rec, &payload_ab, "world", &[]).unwrap();
+        graph.ingest_tf_static_msg(&rec, "/tf_static", &payload_ab, "world", &RenameRules::default()).unwrap();
         let payload_ba = create_tf_static_payload("B", "A", [-1.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
         // Should not add cycle
-        assert!(graph.ingest_tf_static_msg(&rec, &payload_ba, "world", &[]).is_ok());
-        assert!(!graph.static_edges.contains_key(&("B".to_string(), "A".to_string())));
+        assert!(graph.ingest_tf_static_msg(&rec, "/tf_static", &payload_ba, "world", &RenameRules::default()).is_ok());
+        assert!(!graph.static_edges.contains_key(&edge(&graph, "B", "A")));
+    }
+
+    // tf2_msgs/TFMessage as recorded by rosbag: odom -> base_link (45° yaw) and
+    // base_link -> laser, both stamped 1700000000.25
+    const RECORDED_TF_MSG: &[u8] = &[
+        0x02, 0x00, 0x00, 0x00, 0xd2, 0x04, 0x00, 0x00, 0x00, 0xf1, 0x53, 0x65,
+        0x80, 0xb2, 0xe6, 0x0e, 0x04, 0x00, 0x00, 0x00, 0x6f, 0x64, 0x6f, 0x6d,
+        0x09, 0x00, 0x00, 0x00, 0x62, 0x61, 0x73, 0x65, 0x5f, 0x6c, 0x69, 0x6e,
+        0x6b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf8, 0x3f, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0xd0, 0xbf, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x64, 0xa9, 0xae, 0xa6, 0xe2, 0x7d, 0xd8,
+        0x3f, 0x46, 0x8d, 0x32, 0xcf, 0x6b, 0x90, 0xed, 0x3f, 0xd2, 0x04, 0x00,
+        0x00, 0x00, 0xf1, 0x53, 0x65, 0x80, 0xb2, 0xe6, 0x0e, 0x09, 0x00, 0x00,
+        0x00, 0x62, 0x61, 0x73, 0x65, 0x5f, 0x6c, 0x69, 0x6e, 0x6b, 0x05, 0x00,
+        0x00, 0x00, 0x6c, 0x61, 0x73, 0x65, 0x72, 0x9a, 0x99, 0x99, 0x99, 0x99,
+        0x99, 0xc9, 0x3f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33,
+        0x33, 0x33, 0x33, 0x33, 0x33, 0xd3, 0x3f, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0xf0, 0x3f,
+    ];
+
+    #[test]
+    fn test_parse_recorded_tf_message() {
+        let transforms = parse_tf_message(RECORDED_TF_MSG).unwrap();
+        assert_eq!(transforms.len(), 2);
+        assert_eq!(transforms[0].header.frame_id, "odom");
+        assert_eq!(transforms[0].child_frame_id, "base_link");
+        assert!((transforms[0].transform.translation.x - 1.5).abs() < 1e-12);
+        assert!((transforms[0].transform.rotation.w - 0.9238795325112867).abs() < 1e-12);
+        assert_eq!(transforms[1].header.frame_id, "base_link");
+        assert_eq!(transforms[1].child_frame_id, "laser");
+        assert!((transforms[1].transform.translation.z - 0.3).abs() < 1e-12);
+        assert!((transforms[0].header.stamp - 1_700_000_000.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_ingest_uses_per_transform_stamps() {
+        let (rec, _) = rerun::RecordingStreamBuilder::new("test").memory().unwrap();
+        let mut graph = TfGraph::new();
+        // Message received 5s after the timeline origin, stamped 2.25s after it
+        graph.ingest_tf_msg(&rec, "/tf", 5.0, Some(1_699_999_998.0), RECORDED_TF_MSG, 30.0, "world", &RenameRules::default()).unwrap();
+        let samples = &graph.dynamic[&edge(&graph, "odom", "base_link")];
+        assert!((samples[0].t - 2.25).abs() < 1e-6);
+        // Without a stamp base the receive time is used
+        let mut graph = TfGraph::new();
+        graph.ingest_tf_msg(&rec, "/tf", 5.0, None, RECORDED_TF_MSG, 30.0, "world", &RenameRules::default()).unwrap();
+        let samples = &graph.dynamic[&edge(&graph, "odom", "base_link")];
+        assert_eq!(samples[0].t, 5.0);
+    }
+
+    #[test]
+    fn test_root_relative_subtrees() {
+        let (rec, _) = rerun::RecordingStreamBuilder::new("test").memory().unwrap();
+        let mut graph = TfGraph::new();
+        graph.set_root_relative(true);
+        let payload = create_tf_static_payload("world", "odom", [1.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
+        graph.ingest_tf_static_msg(&rec, "/tf_static", &payload, "world", &RenameRules::default()).unwrap();
+        graph.ingest_tf_msg(&rec, "/tf", 5.0, None, RECORDED_TF_MSG, 30.0, "world", &RenameRules::default()).unwrap();
+        let odom = graph.frames.get("odom").unwrap();
+        let mut below_odom: Vec<&str> = graph.descendants(odom).into_iter().map(|id| graph.frames.name(id)).collect();
+        below_odom.sort();
+        assert_eq!(below_odom, ["base_link", "laser", "odom"]);
+        // odom hangs off the root statically, the laser only through odom -> base_link
+        assert!(graph.resolve_pose("world", "odom", 0.0, TfMode::Static).is_some());
+        assert!(graph.resolve_pose("world", "laser", 0.0, TfMode::Static).is_none());
+        assert!(graph.resolve_pose("world", "laser", 5.0, TfMode::Nearest).is_some());
+    }
+
+    #[test]
+    fn test_resolve_skips_stale_edges() {
+        let (rec, _) = rerun::RecordingStreamBuilder::new("test").memory().unwrap();
+        let mut graph = TfGraph::new();
+        graph.set_time_tolerance(1.0);
+        let payload = create_tf_static_payload("world", "odom", [1.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
+        graph.ingest_tf_msg(&rec, "/tf", 0.0, None, &payload, 30.0, "world", &RenameRules::default()).unwrap();
+        let payload = create_tf_static_payload("odom", "laser", [0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
+        graph.ingest_tf_static_msg(&rec, "/tf_static", &payload, "world", &RenameRules::default()).unwrap();
+        assert!(graph.resolve_pose("world", "laser", 0.5, TfMode::Nearest).is_some());
+        // The only world -> odom sample is 10s away from the query
+        assert!(graph.resolve_pose("world", "laser", 10.0, TfMode::Nearest).is_none());
+        assert!(graph.resolve_pose("world", "laser", 10.0, TfMode::Interpolate).is_none());
+        // Static-only lookups never use the dynamic edge
+        assert!(graph.resolve_pose("world", "laser", 0.0, TfMode::Static).is_none());
+        assert!(graph.resolve_pose("odom", "laser", 10.0, TfMode::Static).is_some());
+        // The fallback uses the stale sample rather than failing
+        graph.set_tolerance_fallback(true);
+        assert!(graph.resolve_pose("world", "laser", 10.0, TfMode::Nearest).is_some());
+        assert!(graph.resolve_pose("world", "laser", 10.0, TfMode::Static).is_none());
+    }
+
+    #[test]
+    fn test_sample_lookup_binary_search() {
+        let samples: Vec<TfSample> = (0..10)
+            .map(|i| TfSample { t: i as f64, trans: [i as f64, 0.0, 0.0], quat: [0.0, 0.0, 0.0, 1.0] })
+            .collect();
+        assert_eq!(nearest_sample(&samples, 3.4).unwrap().t, 3.0);
+        assert_eq!(nearest_sample(&samples, 3.6).unwrap().t, 4.0);
+        // Ties go to the earlier sample
+        assert_eq!(nearest_sample(&samples, 3.5).unwrap().t, 3.0);
+        assert_eq!(nearest_sample(&samples, -1.0).unwrap().t, 0.0);
+        assert_eq!(nearest_sample(&samples, 42.0).unwrap().t, 9.0);
+        assert!(nearest_sample(&[], 1.0).is_none());
+        let iso = interpolate_samples(&samples, 2.25, TfMode::Interpolate).unwrap();
+        assert!((iso.translation.vector.x - 2.25).abs() < 1e-9);
+        assert!(interpolate_samples(&samples, 2.25, TfMode::None).is_none());
+        assert!(interpolate_samples(&samples, 2.0, TfMode::None).is_some());
+    }
+
+    #[test]
+    fn test_cached_path_rerouted_when_stale() {
+        let (rec, _) = rerun::RecordingStreamBuilder::new("test").memory().unwrap();
+        let mut graph = TfGraph::new();
+        graph.set_time_tolerance(1.0);
+        // world -> base through odom (t=0) and through map (t=10)
+        let payload = create_tf_static_payload("world", "odom", [1.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
+        graph.ingest_tf_msg(&rec, "/tf", 0.0, None, &payload, 30.0, "world", &RenameRules::default()).unwrap();
+        let payload = create_tf_static_payload("odom", "base", [0.0; 3], [0.0, 0.0, 0.0, 1.0]);
+        graph.ingest_tf_static_msg(&rec, "/tf_static", &payload, "world", &RenameRules::default()).unwrap();
+        let payload = create_tf_static_payload("world", "map", [2.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
+        graph.ingest_tf_msg(&rec, "/tf", 10.0, None, &payload, 30.0, "world", &RenameRules::default()).unwrap();
+        let payload = create_tf_static_payload("map", "base", [0.0; 3], [0.0, 0.0, 0.0, 1.0]);
+        graph.ingest_tf_static_msg(&rec, "/tf_static", &payload, "world", &RenameRules::default()).unwrap();
+        let at_0 = graph.resolve_pose("world", "base", 0.0, TfMode::Nearest).unwrap();
+        assert!((at_0.translation.vector.x - 1.0).abs() < 1e-9);
+        // The cached path through odom has no data at t=10
+        let at_10 = graph.resolve_pose("world", "base", 10.0, TfMode::Nearest).unwrap();
+        assert!((at_10.translation.vector.x - 2.0).abs() < 1e-9);
+        let at_0 = graph.resolve_pose("world", "base", 0.0, TfMode::Nearest).unwrap();
+        assert!((at_0.translation.vector.x - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cached_path_revalidated() {
+        let (rec, _) = rerun::RecordingStreamBuilder::new("test").memory().unwrap();
+        let mut graph = TfGraph::new();
+        graph.set_time_tolerance(1.0);
+        let payload = create_tf_static_payload("world", "odom", [1.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
+        graph.ingest_tf_msg(&rec, "/tf", 0.0, None, &payload, 30.0, "world", &RenameRules::default()).unwrap();
+        let payload = create_tf_static_payload("odom", "base", [0.0; 3], [0.0, 0.0, 0.0, 1.0]);
+        graph.ingest_tf_static_msg(&rec, "/tf_static", &payload, "world", &RenameRules::default()).unwrap();
+        let at_0 = graph.resolve_pose("world", "base", 0.0, TfMode::Nearest).unwrap();
+        assert!((at_0.translation.vector.x - 1.0).abs() < 1e-9);
+        // The cached path has no world -> odom data at t=10
+        assert!(graph.resolve_pose("world", "base", 10.0, TfMode::Nearest).is_none());
+        let payload = create_tf_static_payload("world", "odom", [2.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]);
+        graph.ingest_tf_msg(&rec, "/tf", 10.0, None, &payload, 30.0, "world", &RenameRules::default()).unwrap();
+        let at_10 = graph.resolve_pose("world", "base", 10.0, TfMode::Nearest).unwrap();
+        assert!((at_10.translation.vector.x - 2.0).abs() < 1e-9);
+        let at_0 = graph.resolve_pose("world", "base", 0.0, TfMode::Nearest).unwrap();
+        assert!((at_0.translation.vector.x - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_buffer_api_without_recording() {
+        let mut graph = TfGraph::new();
+        for tf in parse_tf_message(RECORDED_TF_MSG).unwrap() {
+            graph.add_transform(&tf, 1.0);
+        }
+        let static_tf = TransformStamped {
+            header: Header { stamp: 0.0, frame_id: "map".to_string() },
+            child_frame_id: "odom".to_string(),
+            transform: Transform {
+                translation: Vector3 { x: 0.0, y: 0.0, z: 1.0 },
+                rotation: RosQuaternion { x: 0.0, y: 0.0, z: 0.0, w: 2.0 },
+            },
+        };
+        assert!(graph.add_static_transform(&static_tf));
+        assert_eq!(graph.frames(), ["base_link", "laser", "map", "odom"]);
+        assert!(graph.can_transform("map", "laser", 1.0, TfMode::Nearest));
+        let iso = graph.lookup_transform("map", "odom", 1.0, TfMode::Nearest).unwrap();
+        assert!((iso.translation.vector.z - 1.0).abs() < 1e-9);
+        let err = graph.lookup_transform("map", "camera", 1.0, TfMode::Nearest).unwrap_err();
+        assert!(err.to_string().contains("\"camera\" does not exist"));
+        assert!(graph.lookup_transform("map", "laser", 1.0, TfMode::Static).is_err());
+        // A static edge closing a cycle is refused
+        let mut cycle = static_tf.clone();
+        cycle.header.frame_id = "odom".to_string();
+        cycle.child_frame_id = "map".to_string();
+        assert!(!graph.add_static_transform(&cycle));
+        // Pruning drops samples older than the buffer
+        graph.prune(100.0, 30.0);
+        assert!(!graph.can_transform("map", "laser", 1.0, TfMode::Nearest));
+    }
+
+    #[test]
+    fn test_tf_plots() {
+        let (rec, storage) = rerun::RecordingStreamBuilder::new("test").memory().unwrap();
+        let mut graph = TfGraph::new();
+        graph.set_scalar_plots(true);
+        graph.ingest_tf_msg(&rec, "/tf", 1.0, None, RECORDED_TF_MSG, 30.0, "world", &RenameRules::default()).unwrap();
+        rec.flush_blocking().unwrap();
+        let mut plots: std::collections::BTreeMap<String, Vec<f64>> = Default::default();
+        for msg in storage.take() {
+            if let rerun::log::LogMsg::ArrowMsg(_, arrow_msg) = msg {
+                let chunk = rerun::log::Chunk::from_arrow_msg(&arrow_msg).unwrap();
+                for values in chunk.iter_slices::<f64>(rerun::archetypes::Scalars::descriptor_scalars()) {
+                    plots.entry(chunk.entity_path().to_string()).or_default().extend_from_slice(values);
+                }
+            }
+        }
+        let plot = |path: &str| plots[&format!("/tf_plots/{path}")].clone();
+        assert_eq!(plots.len(), 12);
+        assert_eq!(plot("odom__base_link/x"), [1.5]);
+        assert_eq!(plot("odom__base_link/y"), [-0.25]);
+        assert_eq!(plot("odom__base_link/z"), [0.0]);
+        assert!((plot("odom__base_link/yaw")[0] - 45.0).abs() < 1e-9);
+        assert!(plot("odom__base_link/pitch")[0].abs() < 1e-9);
+        assert!(plot("odom__base_link/roll")[0].abs() < 1e-9);
+        assert!((plot("base_link__laser/x")[0] - 0.2).abs() < 1e-12);
+        assert!((plot("base_link__laser/z")[0] - 0.3).abs() < 1e-12);
+        assert_eq!(plot("base_link__laser/yaw"), [0.0]);
+        assert_eq!(plot_name("/robot1/base_link"), "robot1_base_link");
+    }
+
+    #[test]
+    fn test_tf_authority_policies() {
+        let mut odom = parse_tf_message(RECORDED_TF_MSG).unwrap().remove(0);
+        let mut ekf = odom.clone();
+        ekf.header.frame_id = "odom_ekf".to_string();
+        odom.transform.translation.x = 1.0;
+        ekf.transform.translation.x = 2.0;
+        let x_at = |graph: &TfGraph, parent: &str| graph.resolve_pose(parent, "base_link", 0.0, TfMode::Nearest).map(|iso| iso.translation.vector.x);
+
+        let mut graph = TfGraph::new();
+        graph.set_authority(TfAuthority::FirstWins);
+        assert!(graph.add_transform_from(&odom, 0.0, "/tf"));
+        assert!(!graph.add_transform_from(&ekf, 0.0, "/ekf/tf"));
+        assert_eq!(x_at(&graph, "odom"), Some(1.0));
+        assert_eq!(x_at(&graph, "odom_ekf"), None);
+        let conflicts: Vec<_> = graph.parent_conflicts().collect();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].0, "base_link");
+        assert_eq!(conflicts[0].1.len(), 2);
+
+        let mut graph = TfGraph::new();
+        assert!(graph.add_transform_from(&odom, 0.0, "/tf"));
+        assert!(graph.add_transform_from(&ekf, 0.0, "/ekf/tf"));
+        assert_eq!(x_at(&graph, "odom"), None);
+        assert_eq!(x_at(&graph, "odom_ekf"), Some(2.0));
+
+        let mut graph = TfGraph::new();
+        graph.set_authority(parse_tf_authority("prefer-topic=/ekf/tf").unwrap());
+        assert!(graph.add_transform_from(&ekf, 0.0, "/ekf/tf"));
+        assert!(!graph.add_transform_from(&odom, 0.0, "/tf"));
+        assert_eq!(x_at(&graph, "odom_ekf"), Some(2.0));
+        assert!(parse_tf_authority("prefer-topic=").is_err());
+    }
+
+    #[test]
+    fn test_last_wins_keeps_earlier_parent() {
+        let mut odom = parse_tf_message(RECORDED_TF_MSG).unwrap().remove(0);
+        let mut ekf = odom.clone();
+        ekf.header.frame_id = "odom_ekf".to_string();
+        odom.transform.translation.x = 1.0;
+        ekf.transform.translation.x = 2.0;
+        let x_at = |graph: &TfGraph, parent: &str, t: f64| graph.resolve_pose(parent, "base_link", t, TfMode::Nearest).map(|iso| iso.translation.vector.x);
+
+        let mut graph = TfGraph::new();
+        assert!(graph.add_transform_from(&odom, 0.0, "/tf"));
+        assert!(graph.add_transform_from(&ekf, 5.0, "/ekf/tf"));
+        // Before the change base_link is still a child of odom
+        assert_eq!(x_at(&graph, "odom", 0.0), Some(1.0));
+        assert_eq!(x_at(&graph, "odom", 5.0), None);
+        assert_eq!(x_at(&graph, "odom_ekf", 5.0), Some(2.0));
+        assert_eq!(x_at(&graph, "odom_ekf", 0.0), None);
+        // Pruning keeps the parent that holds at the cutoff
+        let (base_link, odom_ekf) = (graph.frames.get("base_link").unwrap(), graph.frames.get("odom_ekf").unwrap());
+        graph.prune(10.0, 6.0);
+        assert_eq!(graph.parent_changes[&base_link].len(), 2);
+        graph.prune(10.0, 4.0);
+        assert_eq!(graph.parent_changes[&base_link], [(5.0, odom_ekf)]);
+    }
+
+    #[test]
+    fn test_frame_axes_filter() {
+        let (rec, _) = rerun::RecordingStreamBuilder::new("test").memory().unwrap();
+        let mut graph = TfGraph::new();
+        graph.set_frame_axes(0.2, Some(regex::Regex::new("^base").unwrap()));
+        graph.ingest_tf_msg(&rec, "/tf", 1.0, None, RECORDED_TF_MSG, 30.0, "world", &RenameRules::default()).unwrap();
+        graph.ingest_tf_msg(&rec, "/tf", 2.0, None, RECORDED_TF_MSG, 30.0, "world", &RenameRules::default()).unwrap();
+        let logged: Vec<&str> = graph.axes_logged.iter().map(|id| graph.frames.name(*id)).collect();
+        assert_eq!(logged, ["base_link"]);
+    }
+
+    #[test]
+    fn test_parse_tf_message_rejects_bad_count() {
+        let mut payload = RECORDED_TF_MSG.to_vec();
+        payload[..4].copy_from_slice(&3u32.to_le_bytes());
+        assert!(parse_tf_message(&payload).is_err());
+        payload[..4].copy_from_slice(&1000u32.to_le_bytes());
+        assert!(parse_tf_message(&payload).is_err());
+        // An empty TFMessage is valid
+        assert!(parse_tf_message(&0u32.to_le_bytes()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_frames_interned() {
+        let transforms: Vec<TransformRef> = TfMessageReader::new(RECORDED_TF_MSG).unwrap().collect::<Result<_>>().unwrap();
+        assert!(matches!(transforms[1].parent, Cow::Borrowed("base_link")));
+        let mut graph = TfGraph::new();
+        for _ in 0..3 {
+            for tf in parse_tf_message(RECORDED_TF_MSG).unwrap() {
+                graph.add_transform(&tf, 1.0);
+            }
+        }
+        // base_link is both a child and a parent, and is stored once
+        let frames = graph.frame_registry();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames.name(frames.get("base_link").unwrap()), "base_link");
+        assert!(graph.resolve_pose("unknown", "unknown", 1.0, TfMode::Nearest).is_some());
+        assert!(graph.resolve_pose("odom", "unknown", 1.0, TfMode::Nearest).is_none());
     }
 
     fn create_tf_payload() -> Vec<u8> {
-        // Simplified: just one transform
+        // TFMessage with a single transform
         let mut data = Vec::new();
+        data.extend_from_slice(&1u32.to_le_bytes()); // transforms length
         // TransformStamped
         // header: seq=0, stamp=0, frame_id="parent"
         data.extend_from_slice(&0u32.to_le_bytes()); // seq
@@ -483,6 +1331,7 @@ mod tests {
 
     fn create_tf_static_payload(parent: &str, child: &str, trans: [f64; 3], quat: [f64; 4]) -> Vec<u8> {
         let mut data = Vec::new();
+        data.extend_from_slice(&1u32.to_le_bytes()); // transforms length
         // header: seq=0, stamp=0, frame_id=parent
         data.extend_from_slice(&0u32.to_le_bytes());
         data.extend_from_slice(&0u64.to_le_bytes());
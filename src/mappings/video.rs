@@ -0,0 +1,135 @@
+//! Compressed video transports (ffmpeg_image_transport, foxglove CompressedVideo)
+//!
+//! H.264/H.265 packets are passed through to rerun's VideoStream without decoding.
+//! Theora packets need a decoder that isn't bundled, so those topics are skipped.
+
+use anyhow::Result;
+use std::borrow::Cow;
+
+use crate::ros_codec::Cursor;
+
+/// Codec from an encoding/format string such as "h264", "libx264" or "hevc_nvenc"
+pub fn video_codec(format: &str) -> Option<rerun::components::VideoCodec> {
+    let f = format.to_ascii_lowercase();
+    if f.contains("264") || f.contains("avc") {
+        Some(rerun::components::VideoCodec::H264)
+    } else if f.contains("265") || f.contains("hevc") {
+        Some(rerun::components::VideoCodec::H265)
+    } else {
+        None
+    }
+}
+
+/// Log one Annex B packet as a VideoStream sample
+pub fn log_video_sample(
+    rec: &rerun::RecordingStream,
+    rr_path: &str,
+    codec: rerun::components::VideoCodec,
+    data: &[u8],
+) -> Result<()> {
+    let stream = rerun::archetypes::VideoStream::new(codec).with_sample(data.to_vec());
+    rec.log(rr_path, &stream)?;
+    Ok(())
+}
+
+/// ffmpeg_image_transport_msgs/FFMPEGPacket
+pub fn ffmpeg_packet_to_rerun(
+    rec: &rerun::RecordingStream,
+    topic: &str,
+    ts: f64,
+    payload: &[u8],
+) -> Result<()> {
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
+    let (encoding, data) = parse_ffmpeg_packet(payload)?;
+    match video_codec(&encoding) {
+        Some(codec) => log_video_sample(rec, &normalize_path(topic), codec, data),
+        None => {
+            tracing::debug!(%encoding, "unsupported ffmpeg packet encoding; skipping message");
+            Ok(())
+        }
+    }
+}
+
+/// foxglove_msgs/CompressedVideo
+pub fn compressed_video_to_rerun(
+    rec: &rerun::RecordingStream,
+    topic: &str,
+    ts: f64,
+    payload: &[u8],
+) -> Result<()> {
+    rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
+    let (format, data) = parse_compressed_video(payload)?;
+    match video_codec(&format) {
+        Some(codec) => log_video_sample(rec, &normalize_path(topic), codec, data),
+        None => {
+            tracing::debug!(%format, "unsupported video format; skipping message");
+            Ok(())
+        }
+    }
+}
+
+/// theora_image_transport/Packet: no decoder available, warn once per conversion
+pub fn theora_packet_to_rerun(topic: &str, warned: &mut bool) -> Result<()> {
+    if !std::mem::replace(warned, true) {
+        tracing::warn!(%topic, "Theora packets are not supported (no decoder); skipping theora topics");
+    }
+    Ok(())
+}
+
+fn parse_ffmpeg_packet(payload: &[u8]) -> Result<(Cow<'_, str>, &[u8])> {
+    let mut cursor = Cursor::new(payload);
+    cursor.skip_header()?;
+    cursor.skip(8)?; // width, height (int32)
+    let encoding = cursor.str()?;
+    cursor.skip(8 + 1 + 1)?; // pts (uint64), flags (uint8), is_bigendian (bool)
+    let data = cursor.byte_array()?;
+    Ok((encoding, data))
+}
+
+fn parse_compressed_video(payload: &[u8]) -> Result<(Cow<'_, str>, &[u8])> {
+    let mut cursor = Cursor::new(payload);
+    cursor.skip(8)?; // timestamp (time)
+    let _frame_id = cursor.str()?;
+    let data = cursor.byte_array()?;
+    let format = cursor.str()?;
+    Ok((format, data))
+}
+
+fn normalize_path(topic: &str) -> String {
+    if topic.starts_with('/') {
+        topic.to_string()
+    } else {
+        format!("/{}", topic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_string(buf: &mut Vec<u8>, s: &str) {
+        buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        buf.extend_from_slice(s.as_bytes());
+    }
+
+    #[test]
+    fn test_parse_ffmpeg_packet() {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&[0u8; 12]); // seq, stamp
+        push_string(&mut payload, "camera");
+        payload.extend_from_slice(&640i32.to_le_bytes());
+        payload.extend_from_slice(&480i32.to_le_bytes());
+        push_string(&mut payload, "libx264");
+        payload.extend_from_slice(&7u64.to_le_bytes());
+        payload.extend_from_slice(&[1, 0]); // flags, is_bigendian
+        payload.extend_from_slice(&4u32.to_le_bytes());
+        payload.extend_from_slice(&[0, 0, 0, 1]);
+
+        let (encoding, data) = parse_ffmpeg_packet(&payload).unwrap();
+        assert_eq!(encoding, "libx264");
+        assert_eq!(data, &[0, 0, 0, 1]);
+        assert_eq!(video_codec(&encoding), Some(rerun::components::VideoCodec::H264));
+        assert_eq!(video_codec("hevc_nvenc"), Some(rerun::components::VideoCodec::H265));
+        assert_eq!(video_codec("vp9"), None);
+    }
+}
@@ -0,0 +1,126 @@
+//! --max-memory: a budget for the data the conversion pipeline holds at once
+//!
+//! Half of the budget holds decompressed chunks, which are then read a group at
+//! a time; a quarter holds the images decoded ahead of logging; the last quarter
+//! bounds the rerun batcher backlog, whose channels block logging once the file
+//! sink falls behind.
+
+use anyhow::{anyhow, Result};
+
+const MIB: u64 = 1024 * 1024;
+
+/// Byte size such as "16G", "512MiB", "1.5GB" or "1000000" (binary units)
+pub fn parse_byte_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid size '{}': expected a number with an optional K/M/G/T unit", s))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1,
+        "K" => 1024,
+        "M" => MIB,
+        "G" => 1024 * MIB,
+        "T" => 1024 * 1024 * MIB,
+        _ => return Err(anyhow!("Invalid size unit in '{}': expected K, M, G or T", s)),
+    };
+    let bytes = (number * multiplier as f64).round() as u64;
+    if bytes == 0 {
+        return Err(anyhow!("Invalid size '{}': must be > 0", s));
+    }
+    Ok(bytes)
+}
+
+/// Split of --max-memory between the pipeline stages
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryBudget {
+    pub total: u64,
+}
+
+impl MemoryBudget {
+    pub fn new(total: u64) -> Self {
+        Self { total }
+    }
+
+    /// Decompressed chunk data read per group
+    pub fn chunk_bytes(&self) -> u64 {
+        self.total / 2
+    }
+
+    /// Images decoded ahead of logging
+    pub fn image_bytes(&self) -> u64 {
+        self.total / 4
+    }
+
+    /// `base` batcher settings, bounding the rows and chunks waiting for the file sink
+    pub fn batcher_config(&self, base: rerun::log::ChunkBatcherConfig) -> rerun::log::ChunkBatcherConfig {
+        let backlog = self.total / 4;
+        rerun::log::ChunkBatcherConfig {
+            // Rows are counted as ~1 MiB (one camera image), chunks at their flush size
+            max_commands_in_flight: Some((backlog / MIB).max(16)),
+            max_chunks_in_flight: Some((backlog / base.flush_num_bytes.max(1)).max(4)),
+            ..base
+        }
+    }
+}
+
+/// Bytes held by the pipeline and their peak
+#[derive(Debug, Default)]
+pub struct MemoryTracker {
+    current: u64,
+    peak: u64,
+}
+
+impl MemoryTracker {
+    pub fn hold(&mut self, bytes: u64) {
+        self.current += bytes;
+        self.peak = self.peak.max(self.current);
+    }
+
+    pub fn release(&mut self, bytes: u64) {
+        self.current = self.current.saturating_sub(bytes);
+    }
+
+    pub fn peak(&self) -> u64 {
+        self.peak
+    }
+}
+
+/// Peak resident set size of this process (VmHWM; Linux only)
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kib: u64 = line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("1000000").unwrap(), 1_000_000);
+        assert_eq!(parse_byte_size("512M").unwrap(), 512 * MIB);
+        assert_eq!(parse_byte_size("16GiB").unwrap(), 16 * 1024 * MIB);
+        assert_eq!(parse_byte_size("1.5g").unwrap(), 1536 * MIB);
+        assert_eq!(parse_byte_size("64kb").unwrap(), 64 * 1024);
+        assert!(parse_byte_size("0").is_err());
+        assert!(parse_byte_size("12X").is_err());
+        assert!(parse_byte_size("lots").is_err());
+    }
+
+    #[test]
+    fn test_memory_tracker_peak() {
+        let mut tracker = MemoryTracker::default();
+        tracker.hold(100);
+        tracker.hold(50);
+        tracker.release(100);
+        tracker.hold(20);
+        assert_eq!(tracker.peak(), 150);
+        let config = MemoryBudget::new(1024 * MIB).batcher_config(rerun::log::ChunkBatcherConfig::DEFAULT);
+        assert_eq!(config.max_commands_in_flight, Some(256));
+        assert_eq!(config.max_chunks_in_flight, Some(256));
+    }
+}
@@ -0,0 +1,161 @@
+//! Several bags converted into one recording
+//!
+//! Inputs are bag files, directories (every .bag inside) or globs on the file
+//! name (`runs/day1_*.bag`), so the parts written by `rosbag record --split`
+//! can be passed as one. Connections are numbered per bag, so they are mapped to
+//! ids shared by every bag recording the same topic and type.
+
+use anyhow::{anyhow, bail, Result};
+use rosbag::{ChunkRecord, MessageRecord};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use crate::ros_msg::TypeInfo;
+
+/// Bag files named by `inputs`, with directories and file-name globs expanded
+///
+/// Expanded names are sorted; the order does not matter otherwise since
+/// messages of all bags are merged by record time.
+pub fn expand_bag_paths(inputs: &[String]) -> Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        let mut found: Vec<String> = if path.is_dir() {
+            list_dir(path, |name| name.ends_with(".bag"))?
+        } else if input.contains(['*', '?']) {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            let regex = crate::mappings::rename::pattern_regex(name)?
+                .ok_or_else(|| anyhow!("Invalid bag pattern '{}'", input))?;
+            let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            list_dir(dir, |name| regex.is_match(name))?
+        } else {
+            paths.push(input.clone());
+            continue;
+        };
+        if found.is_empty() {
+            bail!("No .bag files match '{}'", input);
+        }
+        found.sort();
+        paths.extend(found);
+    }
+    Ok(paths)
+}
+
+fn list_dir(dir: &Path, keep: impl Fn(&str) -> bool) -> Result<Vec<String>> {
+    let entries = std::fs::read_dir(dir).map_err(|e| anyhow!("failed to list {}: {}", dir.display(), e))?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && entry.file_name().to_str().is_some_and(&keep) {
+            paths.push(entry.path().to_string_lossy().into_owned());
+        }
+    }
+    Ok(paths)
+}
+
+/// Connections of all input bags, under ids shared across bags
+#[derive(Debug, Default)]
+pub struct ConnectionMap {
+    /// Connection id → (topic, type)
+    pub connections: BTreeMap<u32, (String, String)>,
+    /// Shared id → md5sum and message_definition text, from the first bag defining it
+    pub definitions: HashMap<u32, TypeInfo>,
+    pub latched: HashSet<u32>,
+    /// (bag, connection id in that bag) → shared id
+    ids: HashMap<(usize, u32), u32>,
+    by_topic: HashMap<(String, String, bool), u32>,
+}
+
+impl ConnectionMap {
+    /// Map connection `id` of bag number `bag`; connections with the same topic,
+    /// type and latching share an id
+    pub fn insert(&mut self, bag: usize, id: u32, topic: &str, tp: &str, latching: bool) -> u32 {
+        if let Some(shared) = self.ids.get(&(bag, id)) {
+            return *shared;
+        }
+        let next = self.by_topic.len() as u32;
+        let shared = *self
+            .by_topic
+            .entry((topic.to_string(), tp.to_string(), latching))
+            .or_insert(next);
+        self.connections.insert(shared, (topic.to_string(), tp.to_string()));
+        if latching {
+            self.latched.insert(shared);
+        }
+        self.ids.insert((bag, id), shared);
+        shared
+    }
+
+    /// Keep the md5sum and message definition of shared connection `id`, unless known
+    pub fn define(&mut self, id: u32, info: TypeInfo) {
+        self.definitions.entry(id).or_insert(info);
+    }
+
+    /// Whether some connection has type `tp`
+    pub fn has_type(&self, tp: &str) -> bool {
+        self.connections.values().any(|(_, t)| t == tp)
+    }
+
+    /// Shared id of connection `id` of bag number `bag`
+    pub fn get(&self, bag: usize, id: u32) -> Option<u32> {
+        self.ids.get(&(bag, id)).copied()
+    }
+
+    /// Map the connection records of a chunk read from bag number `bag`, up to
+    /// its first damaged record
+    pub fn register_chunk(&mut self, bag: usize, record: &ChunkRecord) {
+        if let ChunkRecord::Chunk(chunk) = record {
+            for msg in chunk.messages().map_while(Result::ok) {
+                if let MessageRecord::Connection(conn) = msg {
+                    let id = self.insert(bag, conn.id, conn.topic, conn.tp, conn.latching);
+                    if !self.definitions.contains_key(&id) {
+                        self.define(id, TypeInfo::new(&conn.md5sum, conn.message_definition));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_map_shares_ids_across_bags() {
+        let mut map = ConnectionMap::default();
+        let camera = map.insert(0, 0, "/camera", "sensor_msgs/Image", false);
+        let tf_static = map.insert(0, 1, "/tf_static", "tf2_msgs/TFMessage", true);
+        // The second bag numbers its connections differently
+        assert_eq!(map.insert(1, 0, "/tf_static", "tf2_msgs/TFMessage", true), tf_static);
+        assert_eq!(map.insert(1, 3, "/camera", "sensor_msgs/Image", false), camera);
+        let imu = map.insert(1, 4, "/imu", "sensor_msgs/Imu", false);
+        assert_eq!(map.get(1, 3), Some(camera));
+        assert_eq!(map.get(0, 4), None);
+        assert_eq!(map.connections.len(), 3);
+        assert_eq!(map.connections[&imu], ("/imu".to_string(), "sensor_msgs/Imu".to_string()));
+        assert_eq!(map.latched, HashSet::from([tf_static]));
+    }
+
+    #[test]
+    fn test_expand_bag_paths() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_expand_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["run_1.bag", "run_0.bag", "other.bag", "notes.txt"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        let d = dir.to_string_lossy();
+        let names = |inputs: &[String]| -> Vec<String> {
+            expand_bag_paths(inputs)
+                .unwrap()
+                .iter()
+                .map(|p| Path::new(p).file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(names(&[d.to_string()]), ["other.bag", "run_0.bag", "run_1.bag"]);
+        assert_eq!(names(&[format!("{d}/run_*.bag")]), ["run_0.bag", "run_1.bag"]);
+        assert_eq!(names(&[format!("{d}/other.bag"), format!("{d}/run_?.bag")]), ["other.bag", "run_0.bag", "run_1.bag"]);
+        assert!(expand_bag_paths(&[format!("{d}/missing_*.bag")]).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,261 @@
+//! What a --dry-run conversion would write
+//!
+//! The dry run reads the bag like a conversion but maps nothing. Each message
+//! kept by the filters, --max-rate and the --start/--end window is counted here under its
+//! topic, with the mapping that would log it. The estimated output size
+//! scales the payload bytes by the image encoding, --image-scale,
+//! --image-every-nth and --pointcloud-downsample. Segments are counted with
+//! the same rotation rules as the conversion.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::convert::{ConvertOptions, SegmentRotation, TopicConfig};
+use crate::mappings::images::ImageEncoding;
+use crate::mappings::registry::{MessageKind, MessageMapper};
+
+/// Decoded size of a JPEG/PNG CompressedImage relative to its payload, as the
+/// image decoding budget assumes
+const DECODED_RATIO: f64 = 8.0;
+
+/// One topic of a [`ConversionPlan`]
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct TopicPlan {
+    pub topic: String,
+    pub tp: String,
+    /// Rerun archetypes of the mapper; `None` for unsupported types
+    pub mapping: Option<String>,
+    /// Messages in the window
+    pub messages: u64,
+    /// Messages that would be logged, after --image-every-nth
+    pub logged: u64,
+    pub payload_bytes: u64,
+    pub estimated_bytes: u64,
+}
+
+/// Counts of a dry run: what each topic would become and how many parts it makes
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct ConversionPlan {
+    pub output: String,
+    /// Messages of the bag, kept or not
+    pub total_msgs: u64,
+    /// Messages of topics left out by the filters
+    pub filtered_out: u64,
+    /// Topics with messages in the window, sorted by name
+    pub topics: Vec<TopicPlan>,
+    /// Output files: parts when segmenting, split outputs, else 1 (0 when nothing is kept)
+    pub outputs: u64,
+    #[serde(skip)]
+    by_topic: BTreeMap<String, TopicPlan>,
+    #[serde(skip)]
+    segments: SegmentCounter,
+}
+
+/// Replays the segment rotation of the conversion on the kept messages
+#[derive(Clone, Debug, Default, PartialEq)]
+struct SegmentCounter {
+    rotation: SegmentRotation,
+    open: bool,
+    parts: u64,
+    /// Split output groups, "" for the main output
+    groups: BTreeSet<String>,
+}
+
+impl SegmentCounter {
+    fn record(&mut self, bag_s: f64, fills: Option<u64>) {
+        if self.open && self.rotation.window_ended(bag_s) {
+            self.close();
+        }
+        if !self.open {
+            self.rotation.start(bag_s);
+            self.open = true;
+            self.parts += 1;
+        }
+        if let Some(payload_len) = fills {
+            self.rotation.fill(payload_len);
+            if self.rotation.full() {
+                self.close();
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        self.open = false;
+        self.rotation.close();
+    }
+}
+
+impl ConversionPlan {
+    /// An empty plan for `options`; `bag_start_s` is the origin of unaligned --segment-seconds windows
+    pub fn new(options: &ConvertOptions, bag_start_s: f64) -> Self {
+        Self {
+            output: options.output_path.clone(),
+            segments: SegmentCounter { rotation: SegmentRotation::new(options, bag_start_s), ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    /// Count a message kept by the filters and the window; `bag_s` is its bag time
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &mut self,
+        options: &ConvertOptions,
+        topic: &str,
+        tp: &str,
+        mapper: Option<&dyn MessageMapper>,
+        topic_config: &TopicConfig,
+        bag_s: f64,
+        payload_len: u64,
+    ) {
+        let entry = self.by_topic.entry(topic.to_string()).or_insert_with(|| TopicPlan {
+            topic: topic.to_string(),
+            tp: tp.to_string(),
+            // Mappers that do not describe themselves come from downstream crates
+            mapping: mapper.map(|m| m.describe(tp).map_or("custom", |info| info.archetypes).to_string()),
+            ..Default::default()
+        });
+        let index = entry.messages;
+        entry.messages += 1;
+        entry.payload_bytes += payload_len;
+        let kind = MessageKind::of_type(tp);
+        let decimated = matches!(kind, Some(MessageKind::Image | MessageKind::CompressedImage))
+            && !index.is_multiple_of(topic_config.image_every_nth);
+        let logged = entry.mapping.is_some() && !decimated;
+        if logged {
+            entry.logged += 1;
+            entry.estimated_bytes += (payload_len as f64 * output_ratio(tp, topic_config, options)).round() as u64;
+        }
+        self.segments.groups.insert(topic_config.output_group.clone().unwrap_or_default());
+        let fills = kind.filter(|kind| logged && kind.fills_segment()).map(|_| payload_len);
+        self.segments.record(bag_s, fills);
+    }
+
+    /// Close the plan once the bag is read
+    pub fn finish(&mut self, total_msgs: u64, filtered_out: u64) {
+        self.total_msgs = total_msgs;
+        self.filtered_out = filtered_out;
+        self.topics = self.by_topic.values().cloned().collect();
+        self.outputs = if self.segments.rotation.enabled() {
+            self.segments.parts
+        } else {
+            self.segments.groups.len() as u64
+        };
+    }
+
+    /// Messages in the window
+    pub fn kept_msgs(&self) -> u64 {
+        self.topics.iter().map(|t| t.messages).sum()
+    }
+
+    pub fn estimated_bytes(&self) -> u64 {
+        self.topics.iter().map(|t| t.estimated_bytes).sum()
+    }
+
+    /// Summary lines and a topic table
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "Plan: {} messages, {} kept after filters, {} topics → output: {}\n",
+            self.total_msgs,
+            self.kept_msgs(),
+            self.topics.len(),
+            self.output
+        );
+        out.push_str(&format!(
+            "{:<35} {:<35} {:<28} {:>9} {:>9} {:>12} {:>12}\n",
+            "Topic", "Type", "Mapping", "Messages", "Logged", "Payload(MB)", "Est.(MB)"
+        ));
+        out.push_str(&format!("{}\n", "-".repeat(146)));
+        for topic in &self.topics {
+            out.push_str(&format!(
+                "{:<35} {:<35} {:<28} {:>9} {:>9} {:>12.1} {:>12.1}\n",
+                topic.topic,
+                topic.tp,
+                topic.mapping.as_deref().unwrap_or("unsupported"),
+                topic.messages,
+                topic.logged,
+                topic.payload_bytes as f64 / 1e6,
+                topic.estimated_bytes as f64 / 1e6
+            ));
+        }
+        let unsupported: Vec<&TopicPlan> = self.topics.iter().filter(|t| t.mapping.is_none()).collect();
+        if !unsupported.is_empty() {
+            out.push_str(&format!(
+                "\n{} unsupported topics ({} messages) would be skipped; --generic-fallback logs them as scalars and text\n",
+                unsupported.len(),
+                unsupported.iter().map(|t| t.messages).sum::<u64>()
+            ));
+        }
+        out.push_str(&format!(
+            "\nEstimated output: {:.1} MB in {} file{}\n",
+            self.estimated_bytes() as f64 / 1e6,
+            self.outputs,
+            if self.outputs == 1 { "" } else { "s" }
+        ));
+        out
+    }
+}
+
+/// Rough size of the logged data relative to the payload
+fn output_ratio(tp: &str, topic_config: &TopicConfig, options: &ConvertOptions) -> f64 {
+    let scale = topic_config.image_scale.unwrap_or(1.0).powi(2);
+    let encoding = match options.image_encoding {
+        ImageEncoding::Raw => 1.0,
+        ImageEncoding::Png => 0.5,
+        // About 1:20 at quality 0 to 1:3 at quality 100
+        ImageEncoding::Jpeg { quality } => 0.05 + 0.25 * quality as f64 / 100.0,
+    };
+    match tp {
+        "sensor_msgs/Image" => scale * encoding,
+        "sensor_msgs/CompressedImage" if options.compressed_passthrough => 1.0,
+        // Decoded, but never re-encoded
+        "sensor_msgs/CompressedImage" => DECODED_RATIO * scale,
+        "sensor_msgs/PointCloud2" => 1.0 / topic_config.pointcloud_downsample.max(1) as f64,
+        _ => 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappings::registry::MapperRegistry;
+    use crate::mappings::rename::RenameRules;
+
+    #[test]
+    fn test_plan_topics_and_segments() {
+        let options = ConvertOptions::new("in.bag", "out.rrd")
+            .segment_size(2)
+            .image_every_nth(vec![crate::mappings::images::parse_image_every_nth("/camera=2").unwrap()]);
+        let mappers = MapperRegistry::builtin(&options);
+        let mut plan = ConversionPlan::new(&options, 0.0);
+        for (topic, tp) in [("/camera", "sensor_msgs/Image"), ("/points", "sensor_msgs/PointCloud2"), ("/acme", "acme_msgs/Battery")] {
+            let config = TopicConfig::resolve(&options, &RenameRules::default(), topic);
+            for i in 0..4 {
+                plan.record(&options, topic, tp, mappers.get(topic, tp), &config, i as f64, 1000);
+            }
+        }
+        plan.finish(20, 8);
+
+        let names: Vec<&str> = plan.topics.iter().map(|t| t.topic.as_str()).collect();
+        assert_eq!(names, ["/acme", "/camera", "/points"]);
+        // Every other image is logged, at full size
+        let camera = &plan.topics[1];
+        assert_eq!((camera.messages, camera.logged, camera.estimated_bytes), (4, 2, 2000));
+        assert_eq!(plan.topics[0].mapping, None);
+        assert_eq!(plan.topics[0].estimated_bytes, 0);
+        assert_eq!(plan.kept_msgs(), 12);
+        // 2 images + 4 clouds fill 3 parts of 2, and the unsupported messages open a 4th
+        assert_eq!(plan.outputs, 4);
+        assert!(plan.to_text().contains("1 unsupported topics (4 messages)"));
+    }
+
+    #[test]
+    fn test_output_ratio() {
+        let options = ConvertOptions::new("in.bag", "out.rrd")
+            .image_encoding(ImageEncoding::Jpeg { quality: 100 })
+            .image_scale(vec![crate::mappings::images::parse_image_scale("0.5").unwrap()]);
+        let config = TopicConfig::resolve(&options, &RenameRules::default(), "/camera");
+        assert!((output_ratio("sensor_msgs/Image", &config, &options) - 0.3 * 0.25).abs() < 1e-9);
+        assert!((output_ratio("sensor_msgs/CompressedImage", &config, &options) - 8.0 * 0.25).abs() < 1e-9);
+        assert_eq!(output_ratio("sensor_msgs/Imu", &config, &options), 1.0);
+    }
+}
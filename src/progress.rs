@@ -0,0 +1,70 @@
+//! Terminal progress of a conversion: a bar with percentage and ETA when the
+//! message count is known, a spinner otherwise
+//!
+//! The count comes from the ChunkInfo records of indexed bags, or from the first
+//! pass when every chunk is already in memory. The bar is only advanced by the
+//! loop logging messages in order, whatever decodes them ahead of it.
+
+use indicatif::{ProgressBar, ProgressStyle};
+use std::time::Instant;
+
+/// Messages between two refreshes of the read rate and topic
+const REFRESH_EVERY: u64 = 64;
+
+pub(crate) struct ConvertProgress {
+    bar: ProgressBar,
+    started: Instant,
+    /// Payload bytes read so far
+    bytes: u64,
+}
+
+impl ConvertProgress {
+    /// Progress over `total` messages, when known
+    pub(crate) fn new(total: Option<u64>) -> Self {
+        let bar = match total {
+            Some(total) => {
+                let bar = ProgressBar::new(total);
+                bar.set_style(
+                    ProgressStyle::with_template("{wide_bar} {percent:>3}% {pos}/{len} msgs  ETA {eta}  {msg}").unwrap(),
+                );
+                bar
+            }
+            None => {
+                let bar = ProgressBar::new_spinner();
+                bar.set_style(ProgressStyle::with_template("{spinner} {pos} msgs  {elapsed}  {msg}").unwrap());
+                bar
+            }
+        };
+        Self { bar, started: Instant::now(), bytes: 0 }
+    }
+
+    /// One message of `bytes` read from `topic`
+    pub(crate) fn message(&mut self, topic: &str, bytes: u64) {
+        self.bytes += bytes;
+        self.bar.inc(1);
+        if self.bar.position() % REFRESH_EVERY == 1 {
+            self.bar.set_message(status(self.bytes, self.started.elapsed().as_secs_f64(), topic));
+        }
+    }
+
+    pub(crate) fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Read rate and current topic, e.g. `12.5 MB/s  /camera/image_raw`
+fn status(bytes: u64, secs: f64, topic: &str) -> String {
+    let rate = if secs > 0.0 { bytes as f64 / secs / 1e6 } else { 0.0 };
+    format!("{:.1} MB/s  {}", rate, topic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        assert_eq!(status(25_000_000, 2.0, "/camera/image_raw"), "12.5 MB/s  /camera/image_raw");
+        assert_eq!(status(1000, 0.0, "/imu"), "0.0 MB/s  /imu");
+    }
+}
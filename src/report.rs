@@ -0,0 +1,373 @@
+//! What a conversion left out, and the --report JSON summary
+//!
+//! Messages a mapper did not log are counted per ROS type with the reason and
+//! a few of their topics. The table printed after converting tells why a topic
+//! is missing from the RRD, with the flag that would map it when the mapping
+//! exists but is off.
+//!
+//! Messages a mapper failed on (truncated payloads, undecodable images, md5
+//! mismatches) are skipped with a warning. [`MappingFailures`] counts them per
+//! [`MessageKind`] so --strict and --max-failure-rate can fail the conversion.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::convert::ConvertOptions;
+use crate::drops::{drops_to_text, SeqTracker, TopicDrops};
+use crate::events::ConvertStats;
+use crate::mappings::registry::MessageKind;
+
+/// Topics listed per type
+const EXAMPLE_TOPICS: usize = 3;
+
+/// Why messages of a type were not logged
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnmappedReason {
+    /// No mapper for the type
+    Unsupported,
+    /// The bag's definition differs from the standard one the mapper parses
+    Md5Mismatch,
+    /// The mapper could not decode the payload
+    Undecodable,
+}
+
+impl UnmappedReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            UnmappedReason::Unsupported => "unsupported",
+            UnmappedReason::Md5Mismatch => "md5 mismatch",
+            UnmappedReason::Undecodable => "undecodable",
+        }
+    }
+}
+
+/// A ROS type whose messages were not logged
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct UnmappedType {
+    pub tp: String,
+    pub reason: UnmappedReason,
+    pub messages: u64,
+    /// The first topics seen, at most three
+    pub topics: Vec<String>,
+    /// Flag that maps the type, when its mapping is turned off
+    pub hint: Option<String>,
+}
+
+/// Unmapped messages of a conversion, by type and reason, with the failure counts of --strict
+#[derive(Debug, Default)]
+pub struct UnmappedTypes {
+    types: BTreeMap<(String, UnmappedReason), (u64, Vec<String>)>,
+    failures: MappingFailures,
+}
+
+impl UnmappedTypes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a message of `tp` on `topic` that was not logged
+    pub fn record(&mut self, topic: &str, tp: &str, reason: UnmappedReason) {
+        let (messages, topics) = self.types.entry((tp.to_string(), reason)).or_default();
+        *messages += 1;
+        if topics.len() < EXAMPLE_TOPICS && !topics.iter().any(|t| t == topic) {
+            topics.push(topic.to_string());
+        }
+    }
+
+    /// A message of `tp` was logged
+    pub fn mapped(&mut self, tp: &str) {
+        self.failures.mapped(tp);
+    }
+
+    /// A message the mapping step skipped, with the error of its mapper if it failed;
+    /// messages of unsupported types are not failures
+    pub fn skipped(
+        &mut self,
+        options: &ConvertOptions,
+        topic: &str,
+        tp: &str,
+        reason: UnmappedReason,
+        error: Option<String>,
+    ) -> Result<()> {
+        self.record(topic, tp, reason);
+        let error = match (reason, error) {
+            (_, Some(error)) => error,
+            (UnmappedReason::Unsupported, None) => return Ok(()),
+            (UnmappedReason::Md5Mismatch, None) => "its definition differs from the standard one".to_string(),
+            (UnmappedReason::Undecodable, None) => "the payload did not decode".to_string(),
+        };
+        self.failures.failed(options, topic, tp, &error)
+    }
+
+    /// Fail when a kind went over its --max-failure-rate
+    pub fn check_failures(&self, options: &ConvertOptions) -> Result<()> {
+        self.failures.check(options)
+    }
+
+    /// Messages a mapper failed on
+    pub fn failed_msgs(&self) -> u64 {
+        self.failures.counts.values().map(|(_, failed)| failed).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    /// The counted types, most messages first, with the hints of `options`
+    pub fn report(&self, options: &ConvertOptions) -> Vec<UnmappedType> {
+        let mut types: Vec<UnmappedType> = self
+            .types
+            .iter()
+            .map(|((tp, reason), (messages, topics))| UnmappedType {
+                tp: tp.clone(),
+                reason: *reason,
+                messages: *messages,
+                topics: topics.clone(),
+                hint: hint(tp, *reason, options).map(str::to_string),
+            })
+            .collect();
+        types.sort_by(|a, b| b.messages.cmp(&a.messages).then_with(|| a.tp.cmp(&b.tp)));
+        types
+    }
+}
+
+/// Flag turning on the mapping of `tp`
+fn hint(tp: &str, reason: UnmappedReason, options: &ConvertOptions) -> Option<&'static str> {
+    match reason {
+        UnmappedReason::Unsupported if tp == "sensor_msgs/CameraInfo" => {
+            Some("mapped with --depth-to-points or --camera-group")
+        }
+        UnmappedReason::Unsupported if !options.generic_fallback => {
+            Some("--generic-fallback logs its fields from the bag's definition")
+        }
+        UnmappedReason::Md5Mismatch if !options.ignore_md5_mismatch => {
+            Some("--ignore-md5-mismatch parses it as the standard definition")
+        }
+        _ => None,
+    }
+}
+
+/// Table of the types that were not logged
+pub fn unmapped_to_text(types: &[UnmappedType]) -> String {
+    let mut out = format!(
+        "\nNot converted: {} messages of {} types\n",
+        types.iter().map(|t| t.messages).sum::<u64>(),
+        types.len()
+    );
+    out.push_str(&format!("{:<40} {:<14} {:>9}  {}\n", "Type", "Reason", "Messages", "Topics"));
+    out.push_str(&format!("{}\n", "-".repeat(100)));
+    for t in types {
+        out.push_str(&format!("{:<40} {:<14} {:>9}  {}\n", t.tp, t.reason.as_str(), t.messages, t.topics.join(", ")));
+        if let Some(hint) = &t.hint {
+            out.push_str(&format!("{:<40} hint: {}\n", "", hint));
+        }
+    }
+    out
+}
+
+/// The --report file: counters of the conversion and what it left out
+#[derive(Clone, Debug, Serialize)]
+pub struct ConvertReport {
+    pub bag: String,
+    pub output: String,
+    pub stats: ConvertStats,
+    pub unmapped: Vec<UnmappedType>,
+    /// Topics with gaps in their header.seq
+    pub drops: Vec<TopicDrops>,
+}
+
+impl ConvertReport {
+    pub fn write(&self, path: &str) -> Result<()> {
+        let file = std::fs::File::create(path).with_context(|| format!("failed to create {}", path))?;
+        serde_json::to_writer_pretty(file, self)?;
+        tracing::info!(%path, "wrote conversion report");
+        Ok(())
+    }
+}
+
+/// Share of the messages of a kind allowed to fail mapping; `kind` is `None` for every kind
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct FailureRate {
+    pub kind: Option<MessageKind>,
+    pub rate: f64,
+}
+
+/// Parse "RATE" or "KIND=RATE" with 0 <= RATE <= 1, e.g. "image=0.3"
+pub fn parse_failure_rate(s: &str) -> Result<FailureRate> {
+    let (kind, rate) = match s.split_once('=') {
+        Some((kind, rate)) => {
+            // Types without a built-in mapper all count as "other"
+            let kind = MessageKind::from_name(kind.trim()).filter(|k| *k != MessageKind::Generic).ok_or_else(|| {
+                anyhow!(
+                    "unknown message kind '{}' (image, compressed_image, pointcloud, laserscan, gps, imu, other)",
+                    kind.trim()
+                )
+            })?;
+            (Some(kind), rate)
+        }
+        None => (None, s),
+    };
+    let rate: f64 = rate.trim().parse().map_err(|_| anyhow!("invalid failure rate: '{}'", rate))?;
+    if !(0.0..=1.0).contains(&rate) {
+        bail!("failure rate must be in [0, 1]: {}", rate);
+    }
+    Ok(FailureRate { kind, rate })
+}
+
+/// Failures allowed for `kind`: its own rate, else the global one, else none under --strict
+fn allowed_rate(options: &ConvertOptions, kind: MessageKind) -> Option<f64> {
+    let rate = |k: Option<MessageKind>| options.max_failure_rates.iter().find(|r| r.kind == k).map(|r| r.rate);
+    rate(Some(kind)).or_else(|| rate(None)).or(options.strict.then_some(0.0))
+}
+
+fn failure_kind(tp: &str) -> MessageKind {
+    MessageKind::of_type(tp).unwrap_or(MessageKind::Other)
+}
+
+/// Messages given to a mapper and those it failed on, per kind
+#[derive(Debug, Default)]
+pub struct MappingFailures {
+    counts: HashMap<MessageKind, (u64, u64)>,
+    /// Topics already warned about; later failures are logged at debug level
+    warned: HashSet<String>,
+}
+
+impl MappingFailures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A message of `tp` was logged
+    pub fn mapped(&mut self, tp: &str) {
+        self.counts.entry(failure_kind(tp)).or_default().0 += 1;
+    }
+
+    /// A message of `tp` on `topic` could not be mapped; fails right away when no failure is allowed
+    pub fn failed(&mut self, options: &ConvertOptions, topic: &str, tp: &str, error: &str) -> Result<()> {
+        let kind = failure_kind(tp);
+        let counts = self.counts.entry(kind).or_default();
+        counts.0 += 1;
+        counts.1 += 1;
+        if allowed_rate(options, kind) == Some(0.0) {
+            bail!("--strict: failed to map a {} message on {}: {}", tp, topic, error);
+        }
+        if self.warned.insert(topic.to_string()) {
+            options.warn(format!("failed to map a {} message on {}: {}; skipping (further failures on this topic at debug level)", tp, topic, error));
+        } else {
+            tracing::debug!(%topic, %tp, "failed to map message: {}", error);
+        }
+        Ok(())
+    }
+
+    /// Fail when a kind went over its allowed rate
+    pub fn check(&self, options: &ConvertOptions) -> Result<()> {
+        let mut over: Vec<String> = self
+            .counts
+            .iter()
+            .filter(|(_, (_, failed))| *failed > 0)
+            .filter_map(|(kind, (total, failed))| {
+                let rate = *failed as f64 / *total as f64;
+                let allowed = allowed_rate(options, *kind)?;
+                (rate > allowed).then(|| {
+                    format!("{}: {} of {} ({:.1}% > {:.1}%)", kind.name(), failed, total, rate * 100.0, allowed * 100.0)
+                })
+            })
+            .collect();
+        if over.is_empty() {
+            return Ok(());
+        }
+        over.sort();
+        bail!("too many messages failed to map: {}", over.join(", "))
+    }
+}
+
+/// After a conversion: print the types that were not logged and the sequence gaps, and write the --report file
+pub fn finish_report(
+    options: &ConvertOptions,
+    stats: &ConvertStats,
+    unmapped: &UnmappedTypes,
+    drops: &SeqTracker,
+) -> Result<()> {
+    let unmapped = unmapped.report(options);
+    if !unmapped.is_empty() {
+        print!("{}", unmapped_to_text(&unmapped));
+    }
+    let drops = drops.report();
+    if !drops.is_empty() {
+        print!("{}", drops_to_text(&drops));
+    }
+    if let Some(path) = &options.report_path {
+        let report = ConvertReport {
+            bag: options.bag_path.clone(),
+            output: options.output_path.clone(),
+            stats: stats.clone(),
+            unmapped,
+            drops,
+        };
+        report.write(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unmapped_types() {
+        let mut unmapped = UnmappedTypes::new();
+        for i in 0..5 {
+            unmapped.record(&format!("/acme/battery_{}", i % 4), "acme_msgs/Battery", UnmappedReason::Unsupported);
+        }
+        unmapped.record("/camera/camera_info", "sensor_msgs/CameraInfo", UnmappedReason::Unsupported);
+        unmapped.record("/imu", "sensor_msgs/Imu", UnmappedReason::Md5Mismatch);
+        unmapped.record("/imu", "sensor_msgs/Imu", UnmappedReason::Md5Mismatch);
+
+        let types = unmapped.report(&ConvertOptions::new("in.bag", "out.rrd"));
+        let rows: Vec<(&str, u64, usize)> = types.iter().map(|t| (t.tp.as_str(), t.messages, t.topics.len())).collect();
+        assert_eq!(rows, [("acme_msgs/Battery", 5, 3), ("sensor_msgs/Imu", 2, 1), ("sensor_msgs/CameraInfo", 1, 1)]);
+        assert_eq!(types[0].hint.as_deref(), Some("--generic-fallback logs its fields from the bag's definition"));
+        assert!(types[1].hint.as_deref().unwrap().starts_with("--ignore-md5-mismatch"));
+        assert!(types[2].hint.as_deref().unwrap().contains("--depth-to-points"));
+        let text = unmapped_to_text(&types);
+        assert!(text.contains("Not converted: 8 messages of 3 types"));
+        assert!(text.contains("/acme/battery_0, /acme/battery_1, /acme/battery_2"));
+
+        // With the fallback on, nothing is left to suggest for unknown types
+        let types = unmapped.report(&ConvertOptions::new("in.bag", "out.rrd").generic_fallback(true));
+        assert_eq!(types[0].hint, None);
+    }
+
+    #[test]
+    fn test_failure_rates() {
+        assert_eq!(parse_failure_rate("image=0.3").unwrap(), FailureRate { kind: Some(MessageKind::Image), rate: 0.3 });
+        assert_eq!(parse_failure_rate("0.05").unwrap(), FailureRate { kind: None, rate: 0.05 });
+        assert!(parse_failure_rate("images=0.3").is_err());
+        assert!(parse_failure_rate("generic=0.3").is_err());
+        assert!(parse_failure_rate("pointcloud=2").is_err());
+
+        // Forgiving by default
+        let mut failures = MappingFailures::new();
+        let lenient = ConvertOptions::new("in.bag", "out.rrd");
+        failures.failed(&lenient, "/scan", "sensor_msgs/LaserScan", "truncated").unwrap();
+        failures.check(&lenient).unwrap();
+
+        // 30% of images may fail, nothing else under --strict
+        let strict = ConvertOptions::new("in.bag", "out.rrd").strict(true).max_failure_rates(vec![parse_failure_rate("image=0.3").unwrap()]);
+        assert!(failures.failed(&strict, "/scan", "sensor_msgs/LaserScan", "truncated").is_err());
+        let mut failures = MappingFailures::new();
+        for i in 0..10 {
+            if i < 3 {
+                failures.failed(&strict, "/camera", "sensor_msgs/Image", "truncated").unwrap();
+            } else {
+                failures.mapped("sensor_msgs/Image");
+            }
+        }
+        failures.check(&strict).unwrap();
+        failures.failed(&strict, "/camera", "sensor_msgs/Image", "truncated").unwrap();
+        let error = failures.check(&strict).unwrap_err().to_string();
+        assert!(error.contains("image: 4 of 11 (36.4% > 30.0%)"), "{error}");
+    }
+}
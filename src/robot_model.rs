@@ -0,0 +1,297 @@
+//! Robot model shown at its TF frames (--urdf, --mesh)
+//!
+//! The visuals of a URDF (meshes, boxes, cylinders, spheres) and the meshes of
+//! --mesh FRAME=PATH are logged once, as static data, under the entities of their
+//! frames, so they move with the transforms logged from /tf. Meshes must be glTF,
+//! GLB, OBJ or STL, the formats Rerun renders; others are skipped with a warning.
+//! `package://` URIs are looked up in ROS_PACKAGE_PATH, then in the folders
+//! above the URDF named after the package.
+
+use anyhow::{bail, Context, Result};
+use nalgebra::UnitQuaternion;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::convert::ConvertOptions;
+use crate::mappings::rename::RenameRules;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Geometry {
+    Mesh { path: PathBuf, scale: [f32; 3] },
+    Box { size: [f32; 3] },
+    Cylinder { radius: f32, length: f32 },
+    Sphere { radius: f32 },
+}
+
+/// One shape attached to a frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct Visual {
+    pub frame: String,
+    pub geometry: Geometry,
+    /// Pose in the frame: xyz in meters, roll/pitch/yaw in radians
+    pub xyz: [f32; 3],
+    pub rpy: [f32; 3],
+    /// RGBA from the URDF material, 0-1
+    pub color: Option<[f32; 4]>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RobotModel {
+    pub visuals: Vec<Visual>,
+}
+
+impl RobotModel {
+    /// Visuals of --urdf and --mesh, empty when neither is given
+    pub fn load(options: &ConvertOptions) -> Result<Self> {
+        let mut visuals = Vec::new();
+        if let Some(urdf) = &options.urdf {
+            let text = std::fs::read_to_string(urdf).with_context(|| format!("failed to read {}", urdf))?;
+            let base_dir = Path::new(urdf).parent().unwrap_or(Path::new("."));
+            visuals = parse_urdf(&text, base_dir).with_context(|| format!("invalid URDF {}", urdf))?;
+        }
+        for mesh in &options.meshes {
+            let (frame, path) = parse_mesh_arg(mesh)?;
+            visuals.push(Visual {
+                frame,
+                geometry: Geometry::Mesh { path, scale: [1.0; 3] },
+                xyz: [0.0; 3],
+                rpy: [0.0; 3],
+                color: None,
+            });
+        }
+        Ok(Self { visuals })
+    }
+
+    /// Log the visuals as static data under `<frame entity>/visual_<n>`
+    pub fn log(&self, rec: &rerun::RecordingStream, options: &ConvertOptions, frame_mappings: &RenameRules) -> Result<()> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for visual in &self.visuals {
+            let frame_path = if visual.frame == options.root_frame {
+                format!("/{}", options.root_frame)
+            } else {
+                crate::mappings::tf::map_frame_to_path(&visual.frame, &options.root_frame, frame_mappings)
+            };
+            let n = counts.entry(visual.frame.as_str()).or_default();
+            let path = format!("{}/visual_{}", frame_path.trim_end_matches('/'), n);
+            *n += 1;
+
+            let [roll, pitch, yaw] = visual.rpy;
+            let quat = UnitQuaternion::from_euler_angles(roll, pitch, yaw);
+            let rotation = rerun::datatypes::Quaternion::from_xyzw([quat.i, quat.j, quat.k, quat.w]);
+            let scale = match &visual.geometry {
+                Geometry::Mesh { scale, .. } => *scale,
+                _ => [1.0; 3],
+            };
+            rec.log_static(
+                path.as_str(),
+                &rerun::archetypes::Transform3D::from_translation_rotation_scale(visual.xyz, rotation, scale),
+            )?;
+            let color = visual.color.map(|[r, g, b, a]| rerun::datatypes::Rgba32::from_unmultiplied_rgba(
+                (r * 255.0) as u8,
+                (g * 255.0) as u8,
+                (b * 255.0) as u8,
+                (a * 255.0) as u8,
+            ));
+            match &visual.geometry {
+                Geometry::Mesh { path: mesh, .. } => {
+                    let supported = mesh
+                        .extension()
+                        .is_some_and(|ext| ["glb", "gltf", "obj", "stl"].contains(&ext.to_string_lossy().to_lowercase().as_str()));
+                    if !supported {
+                        options.warn(format!(
+                            "mesh {} of frame {} skipped: Rerun renders glTF, GLB, OBJ and STL",
+                            mesh.display(),
+                            visual.frame
+                        ));
+                        continue;
+                    }
+                    let mut asset = rerun::archetypes::Asset3D::from_file_path(mesh)
+                        .with_context(|| format!("failed to read mesh {}", mesh.display()))?;
+                    if let Some(color) = color {
+                        asset = asset.with_albedo_factor(color);
+                    }
+                    rec.log_static(path.as_str(), &asset)?;
+                }
+                Geometry::Box { size } => {
+                    let mut boxes = rerun::archetypes::Boxes3D::from_sizes([*size]);
+                    if let Some(color) = color {
+                        boxes = boxes.with_colors([color]);
+                    }
+                    rec.log_static(path.as_str(), &boxes)?;
+                }
+                Geometry::Cylinder { radius, length } => {
+                    let mut cylinders = rerun::archetypes::Cylinders3D::from_lengths_and_radii([*length], [*radius]);
+                    if let Some(color) = color {
+                        cylinders = cylinders.with_colors([color]);
+                    }
+                    rec.log_static(path.as_str(), &cylinders)?;
+                }
+                Geometry::Sphere { radius } => {
+                    let mut spheres = rerun::archetypes::Ellipsoids3D::from_radii([*radius]);
+                    if let Some(color) = color {
+                        spheres = spheres.with_colors([color]);
+                    }
+                    rec.log_static(path.as_str(), &spheres)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse "FRAME=PATH" for --mesh
+pub fn parse_mesh_arg(s: &str) -> Result<(String, PathBuf)> {
+    match s.split_once('=') {
+        Some((frame, path)) if !frame.trim().is_empty() && !path.trim().is_empty() => {
+            Ok((frame.trim().to_string(), PathBuf::from(path.trim())))
+        }
+        _ => bail!("Invalid mesh '{}' (expected FRAME=PATH)", s),
+    }
+}
+
+/// Visuals of the links of a URDF whose relative and `package://` mesh paths
+/// are resolved from `base_dir`, the folder of the URDF
+pub fn parse_urdf(text: &str, base_dir: &Path) -> Result<Vec<Visual>> {
+    let doc = roxmltree::Document::parse(text)?;
+    let robot = doc.root_element();
+    if !robot.has_tag_name("robot") {
+        bail!("root element is <{}>, not <robot>", robot.tag_name().name());
+    }
+    // Materials named at the top level and referenced by name in the links
+    let materials: HashMap<&str, [f32; 4]> = robot
+        .children()
+        .filter(|n| n.has_tag_name("material"))
+        .filter_map(|m| Some((m.attribute("name")?, material_color(m)?)))
+        .collect();
+
+    let mut visuals = Vec::new();
+    for link in robot.children().filter(|n| n.has_tag_name("link")) {
+        let frame = link.attribute("name").context("<link> without name")?;
+        for visual in link.children().filter(|n| n.has_tag_name("visual")) {
+            let Some(shape) = child(visual, "geometry").and_then(|g| g.children().find(|n| n.is_element())) else {
+                continue;
+            };
+            let geometry = match shape.tag_name().name() {
+                "mesh" => {
+                    let uri = shape.attribute("filename").context("<mesh> without filename")?;
+                    let scale = match shape.attribute("scale") {
+                        Some(scale) => floats(scale)?,
+                        None => [1.0; 3],
+                    };
+                    Geometry::Mesh { path: resolve_uri(uri, base_dir)?, scale }
+                }
+                "box" => Geometry::Box { size: floats(shape.attribute("size").context("<box> without size")?)? },
+                "cylinder" => Geometry::Cylinder {
+                    radius: float(shape, "radius")?,
+                    length: float(shape, "length")?,
+                },
+                "sphere" => Geometry::Sphere { radius: float(shape, "radius")? },
+                other => bail!("unsupported geometry <{}> in link {}", other, frame),
+            };
+            let origin = child(visual, "origin");
+            let pose = |name| origin.and_then(|o| o.attribute(name)).map_or(Ok([0.0; 3]), floats);
+            let color = child(visual, "material").and_then(|m| {
+                material_color(m).or_else(|| m.attribute("name").and_then(|name| materials.get(name).copied()))
+            });
+            visuals.push(Visual { frame: frame.to_string(), geometry, xyz: pose("xyz")?, rpy: pose("rpy")?, color });
+        }
+    }
+    Ok(visuals)
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, tag: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(tag))
+}
+
+fn material_color(material: roxmltree::Node) -> Option<[f32; 4]> {
+    let rgba: Vec<f32> = child(material, "color")?
+        .attribute("rgba")?
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .ok()?;
+    rgba.try_into().ok()
+}
+
+fn float(node: roxmltree::Node, name: &str) -> Result<f32> {
+    let value = node.attribute(name).with_context(|| format!("<{}> without {}", node.tag_name().name(), name))?;
+    value.trim().parse().with_context(|| format!("invalid {} '{}'", name, value))
+}
+
+/// Three space-separated numbers, e.g. an origin xyz
+fn floats(s: &str) -> Result<[f32; 3]> {
+    let values: Vec<f32> = s
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("invalid numbers '{}'", s))?;
+    values.try_into().map_err(|_| anyhow::anyhow!("expected 3 numbers, got '{}'", s))
+}
+
+/// File of a mesh URI: `package://PKG/...`, `file://...` or a path relative to the URDF
+fn resolve_uri(uri: &str, base_dir: &Path) -> Result<PathBuf> {
+    if let Some(path) = uri.strip_prefix("file://") {
+        return Ok(PathBuf::from(path));
+    }
+    let Some(rest) = uri.strip_prefix("package://") else {
+        return Ok(base_dir.join(uri));
+    };
+    let (package, file) = rest.split_once('/').with_context(|| format!("invalid package URI {}", uri))?;
+    let search_path = std::env::var("ROS_PACKAGE_PATH").unwrap_or_default();
+    let roots = std::env::split_paths(&search_path).map(|root| root.join(package));
+    // A URDF usually sits in PKG/urdf/ of its own package
+    let ancestors = base_dir.ancestors().filter(|dir| dir.file_name().is_some_and(|name| name == package)).map(Path::to_path_buf);
+    roots
+        .chain(ancestors)
+        .map(|dir| dir.join(file))
+        .find(|path| path.exists())
+        .with_context(|| format!("{} not found in ROS_PACKAGE_PATH or above {}", uri, base_dir.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_urdf_visuals() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_urdf_{}", std::process::id()));
+        let package = dir.join("my_robot");
+        std::fs::create_dir_all(package.join("meshes")).unwrap();
+        std::fs::create_dir_all(package.join("urdf")).unwrap();
+        std::fs::write(package.join("meshes/base.stl"), b"solid base\nendsolid base\n").unwrap();
+        let urdf = r#"<?xml version="1.0"?>
+            <robot name="rover">
+              <material name="orange"><color rgba="1 0.5 0 1"/></material>
+              <link name="base_link">
+                <visual>
+                  <origin xyz="0 0 0.1" rpy="0 0 1.5708"/>
+                  <geometry><mesh filename="package://my_robot/meshes/base.stl" scale="0.001 0.001 0.001"/></geometry>
+                  <material name="orange"/>
+                </visual>
+              </link>
+              <link name="lidar">
+                <visual><geometry><cylinder radius="0.05" length="0.07"/></geometry></visual>
+                <visual><geometry><box size="0.1 0.1 0.02"/></geometry></visual>
+              </link>
+              <link name="base_footprint"/>
+            </robot>"#;
+        let visuals = parse_urdf(urdf, &package.join("urdf")).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(visuals.len(), 3);
+        assert_eq!(visuals[0].frame, "base_link");
+        assert_eq!(
+            visuals[0].geometry,
+            Geometry::Mesh { path: package.join("meshes/base.stl"), scale: [0.001; 3] }
+        );
+        assert_eq!(visuals[0].xyz, [0.0, 0.0, 0.1]);
+        assert_eq!(visuals[0].color, Some([1.0, 0.5, 0.0, 1.0]));
+        assert_eq!(visuals[1].geometry, Geometry::Cylinder { radius: 0.05, length: 0.07 });
+        assert_eq!(visuals[2].geometry, Geometry::Box { size: [0.1, 0.1, 0.02] });
+        assert_eq!(visuals[2].color, None);
+
+        assert!(parse_urdf("<robot><link name=\"a\"><visual><geometry><mesh filename=\"package://missing/a.stl\"/></geometry></visual></link></robot>", &dir).is_err());
+        assert_eq!(parse_mesh_arg("base_link=robot.glb").unwrap(), ("base_link".to_string(), PathBuf::from("robot.glb")));
+        assert!(parse_mesh_arg("robot.glb").is_err());
+    }
+}
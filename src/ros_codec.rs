@@ -0,0 +1,279 @@
+//! ROS1 message serialization shared by the hand-written parsers
+//!
+//! [`Cursor`] reads the little-endian primitives, strings, arrays and
+//! std_msgs/Header of a payload with bounds checks, so a truncated or corrupt
+//! message is an error rather than a panic. Strings and byte fields are borrowed
+//! from the payload (a `Cow::Borrowed` unless the bytes are not UTF-8), so a
+//! message is parsed without allocating anything the caller does not keep.
+//!
+//! The parsers of the mapped types (TF, LaserScan, NavSatFix, Odometry, images,
+//! PointCloud2, CameraInfo, CompressedVideo) all read through it; types without
+//! a hand-written parser are decoded by [`crate::ros_msg`].
+
+use anyhow::{anyhow, Result};
+use std::borrow::Cow;
+
+/// Read position in a payload
+#[derive(Clone, Copy, Debug)]
+pub struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+/// std_msgs/Header with its frame id borrowed from the payload
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header<'a> {
+    /// Seconds since epoch (0.0 when unset)
+    pub stamp: f64,
+    pub frame_id: Cow<'a, str>,
+}
+
+/// Seconds of a ROS time; stamps are never negative in ROS1
+pub fn time_to_secs(secs: u32, nsecs: u32) -> f64 {
+    secs as f64 + nsecs as f64 * 1e-9
+}
+
+macro_rules! read_le {
+    ($($name:ident: $ty:ty),* $(,)?) => {
+        $(
+            #[doc = concat!("Read a little-endian `", stringify!($ty), "`")]
+            pub fn $name(&mut self) -> Result<$ty> {
+                let bytes = self.bytes(std::mem::size_of::<$ty>())?;
+                Ok(<$ty>::from_le_bytes(bytes.try_into().unwrap()))
+            }
+        )*
+    };
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Cursor at byte `pos` of `data`
+    pub fn at(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Bytes left after the read position
+    pub fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.pos)
+    }
+
+    /// The next `len` bytes, borrowed from the payload
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.data.len());
+        let Some(end) = end else {
+            return Err(anyhow!("Unexpected end of payload: {len} bytes at offset {} of {}", self.pos, self.data.len()));
+        };
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn skip(&mut self, len: usize) -> Result<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    read_le!(u8: u8, i8: i8, u16: u16, u32: u32, i32: i32, u64: u64, f32: f32, f64: f64);
+
+    pub fn bool(&mut self) -> Result<bool> {
+        Ok(self.u8()? != 0)
+    }
+
+    /// ROS1 time (uint32 secs + uint32 nsecs) as seconds
+    pub fn time(&mut self) -> Result<f64> {
+        let secs = self.u32()?;
+        let nsecs = self.u32()?;
+        Ok(time_to_secs(secs, nsecs))
+    }
+
+    /// ROS1 duration (int32 secs + int32 nsecs) as seconds
+    pub fn duration(&mut self) -> Result<f64> {
+        let secs = self.i32()?;
+        let nsecs = self.i32()?;
+        Ok(secs as f64 + nsecs as f64 * 1e-9)
+    }
+
+    /// Length-prefixed string, borrowed when it is valid UTF-8
+    pub fn str(&mut self) -> Result<Cow<'a, str>> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?))
+    }
+
+    /// Length-prefixed uint8[] field
+    pub fn byte_array(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    /// Header: seq, stamp, frame_id
+    pub fn header(&mut self) -> Result<Header<'a>> {
+        self.skip(4)?;
+        let stamp = self.time()?;
+        let frame_id = self.str()?;
+        Ok(Header { stamp, frame_id })
+    }
+
+    /// Skip a Header whose stamp and frame id are not needed
+    pub fn skip_header(&mut self) -> Result<()> {
+        self.skip(12)?;
+        self.byte_array().map(|_| ())
+    }
+
+    /// Length-prefixed float32[] into `out`, which is cleared first so a
+    /// buffer can be reused from one message to the next
+    pub fn f32_array_into(&mut self, out: &mut Vec<f32>) -> Result<()> {
+        let len = self.u32()? as usize;
+        let bytes = self.bytes(len.checked_mul(4).ok_or_else(|| anyhow!("float32[] length {len} overflows"))?)?;
+        out.clear();
+        out.extend(bytes.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())));
+        Ok(())
+    }
+
+    /// Length-prefixed float32[]
+    pub fn f32_array(&mut self) -> Result<Vec<f32>> {
+        let mut values = Vec::new();
+        self.f32_array_into(&mut values)?;
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_cursor_reads() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&7u32.to_le_bytes()); // seq
+        data.extend_from_slice(&10u32.to_le_bytes());
+        data.extend_from_slice(&500_000_000u32.to_le_bytes());
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(b"odom");
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&1.5f32.to_le_bytes());
+        data.extend_from_slice(&(-2.0f32).to_le_bytes());
+
+        let mut cursor = Cursor::new(&data);
+        let header = cursor.header().unwrap();
+        assert_eq!(header.stamp, 10.5);
+        assert!(matches!(header.frame_id, Cow::Borrowed("odom")));
+        let mut scratch = vec![9.0; 16];
+        cursor.f32_array_into(&mut scratch).unwrap();
+        assert_eq!(scratch, [1.5, -2.0]);
+        assert_eq!(cursor.remaining(), 0);
+        assert!(cursor.u8().is_err());
+
+        // A length running past the payload is an error, not a panic
+        let mut cursor = Cursor::new(&[0xff, 0xff, 0xff, 0xff, 0x00]);
+        assert!(cursor.f32_array().is_err());
+        assert!(Cursor::at(&data, data.len() - 2).u32().is_err());
+    }
+
+    /// std_msgs/Header as rosbag serializes it
+    fn encode_header(seq: u32, secs: u32, nsecs: u32, frame_id: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&seq.to_le_bytes());
+        data.extend_from_slice(&secs.to_le_bytes());
+        data.extend_from_slice(&nsecs.to_le_bytes());
+        data.extend_from_slice(&(frame_id.len() as u32).to_le_bytes());
+        data.extend_from_slice(frame_id.as_bytes());
+        data
+    }
+
+    /// Synthetic payloads of the types with a hand-written parser
+    fn tf_message(frames: &[(String, String)]) -> Vec<u8> {
+        let mut data = (frames.len() as u32).to_le_bytes().to_vec();
+        for (parent, child) in frames {
+            data.extend(encode_header(0, 1, 2, parent));
+            data.extend_from_slice(&(child.len() as u32).to_le_bytes());
+            data.extend_from_slice(child.as_bytes());
+            for v in [0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0f64] {
+                data.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    fn laser_scan(ranges: &[f32]) -> Vec<u8> {
+        let mut data = encode_header(0, 1, 2, "laser");
+        for v in [-1.0f32, 1.0, 0.01, 0.0, 0.1, 0.05, 30.0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        for _ in 0..2 {
+            data.extend_from_slice(&(ranges.len() as u32).to_le_bytes());
+            ranges.iter().for_each(|r| data.extend_from_slice(&r.to_le_bytes()));
+        }
+        data
+    }
+
+    proptest! {
+        #[test]
+        fn prop_header_roundtrip(seq in any::<u32>(), secs in any::<u32>(), nsecs in 0u32..1_000_000_000, frame_id in "[a-z_/]{0,32}") {
+            let data = encode_header(seq, secs, nsecs, &frame_id);
+            let mut cursor = Cursor::new(&data);
+            let header = cursor.header().unwrap();
+            prop_assert_eq!(header.stamp, time_to_secs(secs, nsecs));
+            prop_assert_eq!(header.frame_id, frame_id.as_str());
+            prop_assert_eq!(cursor.remaining(), 0);
+            prop_assert!(Cursor::new(&data).skip_header().is_ok());
+        }
+
+        #[test]
+        fn prop_reads_never_overrun(data in prop::collection::vec(any::<u8>(), 0..64), ops in prop::collection::vec(0u8..8, 0..16)) {
+            let mut cursor = Cursor::new(&data);
+            for op in ops {
+                let ok = match op {
+                    0 => cursor.u8().is_ok(),
+                    1 => cursor.u32().is_ok(),
+                    2 => cursor.f64().is_ok(),
+                    3 => cursor.str().is_ok(),
+                    4 => cursor.byte_array().is_ok(),
+                    5 => cursor.f32_array().is_ok(),
+                    6 => cursor.header().is_ok(),
+                    _ => cursor.time().is_ok(),
+                };
+                prop_assert!(cursor.position() <= data.len());
+                if !ok {
+                    break;
+                }
+            }
+        }
+
+        #[test]
+        fn prop_f32_array_roundtrip(values in prop::collection::vec(-1e6f32..1e6, 0..256)) {
+            let mut data = (values.len() as u32).to_le_bytes().to_vec();
+            values.iter().for_each(|v| data.extend_from_slice(&v.to_le_bytes()));
+            prop_assert_eq!(Cursor::new(&data).f32_array().unwrap(), values);
+        }
+
+        #[test]
+        fn prop_truncated_messages_are_errors(
+            frames in prop::collection::vec(("[a-z]{1,12}", "[a-z]{1,12}"), 1..4),
+            ranges in prop::collection::vec(0.0f32..40.0, 1..64),
+            cut in 0.0f64..1.0,
+        ) {
+            use crate::mappings::laserscan::{parse_laserscan_msg, parse_multi_echo_laserscan};
+            use crate::mappings::tf::parse_tf_message;
+
+            let tf = tf_message(&frames);
+            prop_assert_eq!(parse_tf_message(&tf).unwrap().len(), frames.len());
+            prop_assert!(parse_tf_message(&tf[..(tf.len() as f64 * cut) as usize]).is_err());
+
+            let scan = laser_scan(&ranges);
+            prop_assert_eq!(parse_laserscan_msg(&scan).unwrap().ranges, ranges);
+            let truncated = &scan[..(scan.len() as f64 * cut) as usize];
+            prop_assert!(parse_laserscan_msg(truncated).is_err());
+            // Not a MultiEchoLaserScan, but it must not panic either
+            let _ = parse_multi_echo_laserscan(truncated);
+            let _ = crate::mappings::depth::parse_camera_info(truncated);
+            let _ = crate::mappings::pointcloud::pointcloud_frame_id(truncated);
+        }
+    }
+}
@@ -0,0 +1,811 @@
+//! Generic ROS1 message decoding from the definitions embedded in connection records
+//!
+//! A connection's `message_definition` holds the text of its type followed by
+//! every type it depends on, each after a `===` separator line and a
+//! `MSG: pkg/Type` line. [`MessageSchema`] parses that text once, then decodes any
+//! payload of the type into a [`Value`] tree.
+//!
+//! The hand-written parsers of the mapped types assume the standard layout of
+//! their type; [`Md5Check`] compares a connection's md5sum with the standard one
+//! so a patched definition is reported instead of mis-parsed.
+//!
+//! ROS2 payloads are CDR encoded; [`MessageSchema::cdr_to_ros1`] re-encodes them
+//! in the ROS1 layout of the same schema so they go through the same parsers.
+//! Messages received as rosbridge JSON are encoded the same way by
+//! [`MessageSchema::json_to_ros1`].
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::HashMap;
+
+/// A decoded message or field
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(String),
+    /// `time` or `duration`, in seconds
+    Time(f64),
+    /// `uint8[]`, `byte[]` and `char[]` arrays, kept as raw bytes
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    /// Fields of a nested message, in definition order
+    Struct(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Field at a dotted path, e.g. `header.stamp` or `orientation.w`
+    pub fn get(&self, path: &str) -> Option<&Value> {
+        path.split('.').try_fold(self, |value, name| match value {
+            Value::Struct(fields) => fields.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        })
+    }
+
+    /// Numeric, boolean and time values as f64
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Bool(b) => Some(*b as u8 as f64),
+            Value::Int(i) => Some(*i as f64),
+            Value::UInt(u) => Some(*u as f64),
+            Value::Float(f) | Value::Time(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// f64 at a dotted path
+    pub fn f64_at(&self, path: &str) -> Result<f64> {
+        self.get(path)
+            .and_then(Value::as_f64)
+            .ok_or_else(|| anyhow!("no numeric field '{}'", path))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Primitive {
+    Bool,
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Int64,
+    UInt64,
+    Float32,
+    Float64,
+    String,
+    Time,
+    Duration,
+}
+
+impl Primitive {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "bool" => Self::Bool,
+            // byte and char are the deprecated aliases of int8 and uint8
+            "int8" | "byte" => Self::Int8,
+            "uint8" | "char" => Self::UInt8,
+            "int16" => Self::Int16,
+            "uint16" => Self::UInt16,
+            "int32" => Self::Int32,
+            "uint32" => Self::UInt32,
+            "int64" => Self::Int64,
+            "uint64" => Self::UInt64,
+            "float32" => Self::Float32,
+            "float64" => Self::Float64,
+            "string" => Self::String,
+            "time" => Self::Time,
+            "duration" => Self::Duration,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum FieldType {
+    Primitive(Primitive),
+    /// Fully qualified message type, `pkg/Type`
+    Message(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Field {
+    name: String,
+    ty: FieldType,
+    /// `None` for a single value, `Some(None)` for `T[]`, `Some(Some(n))` for `T[n]`
+    array: Option<Option<usize>>,
+}
+
+/// Parsed definition of a message type and every type it nests
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageSchema {
+    root: String,
+    types: HashMap<String, Vec<Field>>,
+}
+
+impl MessageSchema {
+    /// Parse the `message_definition` of a connection of type `tp`
+    pub fn parse(tp: &str, definition: &str) -> Result<Self> {
+        let mut types: HashMap<String, Vec<Field>> = HashMap::new();
+        let mut current = tp.to_string();
+        let mut fields = Vec::new();
+        for line in definition.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with("===") {
+                types.insert(std::mem::take(&mut current), std::mem::take(&mut fields));
+                continue;
+            }
+            if let Some(name) = line.strip_prefix("MSG:") {
+                current = name.trim().to_string();
+                continue;
+            }
+            // Constants (`uint8 OK=0`) take no space in the payload
+            if line.contains('=') {
+                continue;
+            }
+            let (ty, name) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| anyhow!("invalid field '{}' in {}", line, current))?;
+            fields.push(parse_field(ty, name.trim(), &current)?);
+        }
+        types.insert(current, fields);
+        types.retain(|name, _| !name.is_empty());
+
+        let schema = Self { root: tp.to_string(), types };
+        for (name, fields) in &schema.types {
+            for field in fields {
+                if let FieldType::Message(nested) = &field.ty
+                    && !schema.types.contains_key(nested)
+                {
+                    bail!("definition of {} (field '{}' of {}) is missing", nested, field.name, name);
+                }
+            }
+        }
+        if !schema.types.contains_key(tp) {
+            bail!("definition of {} is missing", tp);
+        }
+        Ok(schema)
+    }
+
+    /// Type the schema decodes
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /// Top-level field names of the root type, in definition order
+    pub fn field_names(&self) -> Vec<&str> {
+        self.types[&self.root].iter().map(|field| field.name.as_str()).collect()
+    }
+
+    /// Decode a payload of the root type
+    pub fn decode(&self, payload: &[u8]) -> Result<Value> {
+        let mut reader = Reader { data: payload, pos: 0 };
+        self.decode_struct(&self.root, &mut reader)
+    }
+
+    fn decode_struct(&self, tp: &str, reader: &mut Reader) -> Result<Value> {
+        let fields = &self.types[tp];
+        let mut values = Vec::with_capacity(fields.len());
+        for field in fields {
+            let value = self
+                .decode_field(field, reader)
+                .with_context(|| format!("failed to decode {}.{}", tp, field.name))?;
+            values.push((field.name.clone(), value));
+        }
+        Ok(Value::Struct(values))
+    }
+
+    fn decode_field(&self, field: &Field, reader: &mut Reader) -> Result<Value> {
+        let Some(len) = field.array else {
+            return self.decode_value(&field.ty, reader);
+        };
+        let len = match len {
+            Some(n) => n,
+            None => reader.u32()? as usize,
+        };
+        if let FieldType::Primitive(Primitive::UInt8 | Primitive::Int8) = field.ty {
+            return Ok(Value::Bytes(reader.take(len)?.to_vec()));
+        }
+        // A damaged length must not size the array past what the payload can hold
+        let remaining = reader.data.len() - reader.pos;
+        if len.saturating_mul(self.min_size(&field.ty)) > remaining {
+            bail!("array of {} elements does not fit in the {} bytes left", len, remaining);
+        }
+        (0..len)
+            .map(|_| self.decode_value(&field.ty, reader))
+            .collect::<Result<_>>()
+            .map(Value::Array)
+    }
+
+    /// Fewest bytes a ROS1 value of `ty` takes, with its variable-length arrays and strings empty
+    fn min_size(&self, ty: &FieldType) -> usize {
+        let primitive = match ty {
+            FieldType::Message(nested) => {
+                return self.types[nested]
+                    .iter()
+                    .map(|field| match field.array {
+                        None => self.min_size(&field.ty),
+                        Some(None) => 4,
+                        Some(Some(n)) => n * self.min_size(&field.ty),
+                    })
+                    .sum();
+            }
+            FieldType::Primitive(primitive) => *primitive,
+        };
+        match primitive {
+            Primitive::Bool | Primitive::Int8 | Primitive::UInt8 => 1,
+            Primitive::Int16 | Primitive::UInt16 => 2,
+            Primitive::Int32 | Primitive::UInt32 | Primitive::Float32 | Primitive::String => 4,
+            Primitive::Int64 | Primitive::UInt64 | Primitive::Float64 | Primitive::Time | Primitive::Duration => 8,
+        }
+    }
+
+    fn decode_value(&self, ty: &FieldType, reader: &mut Reader) -> Result<Value> {
+        let primitive = match ty {
+            FieldType::Message(nested) => return self.decode_struct(nested, reader),
+            FieldType::Primitive(primitive) => *primitive,
+        };
+        Ok(match primitive {
+            Primitive::Bool => Value::Bool(reader.array::<1>()?[0] != 0),
+            Primitive::Int8 => Value::Int(i8::from_le_bytes(reader.array()?) as i64),
+            Primitive::UInt8 => Value::UInt(reader.array::<1>()?[0] as u64),
+            Primitive::Int16 => Value::Int(i16::from_le_bytes(reader.array()?) as i64),
+            Primitive::UInt16 => Value::UInt(u16::from_le_bytes(reader.array()?) as u64),
+            Primitive::Int32 => Value::Int(i32::from_le_bytes(reader.array()?) as i64),
+            Primitive::UInt32 => Value::UInt(reader.u32()? as u64),
+            Primitive::Int64 => Value::Int(i64::from_le_bytes(reader.array()?)),
+            Primitive::UInt64 => Value::UInt(u64::from_le_bytes(reader.array()?)),
+            Primitive::Float32 => Value::Float(f32::from_le_bytes(reader.array()?) as f64),
+            Primitive::Float64 => Value::Float(f64::from_le_bytes(reader.array()?)),
+            Primitive::String => {
+                let len = reader.u32()? as usize;
+                Value::String(String::from_utf8_lossy(reader.take(len)?).into_owned())
+            }
+            Primitive::Time => {
+                let secs = reader.u32()?;
+                let nsecs = reader.u32()?;
+                Value::Time(secs as f64 + nsecs as f64 * 1e-9)
+            }
+            Primitive::Duration => {
+                let secs = i32::from_le_bytes(reader.array()?);
+                let nsecs = i32::from_le_bytes(reader.array()?);
+                Value::Time(secs as f64 + nsecs as f64 * 1e-9)
+            }
+        })
+    }
+}
+
+impl MessageSchema {
+    /// Re-encode a CDR (ROS2) payload of the root type in the ROS1 layout. ROS2
+    /// headers have no `seq`, which is written as 0
+    pub fn cdr_to_ros1(&self, cdr: &[u8]) -> Result<Vec<u8>> {
+        let (encapsulation, data) = cdr
+            .split_at_checked(4)
+            .ok_or_else(|| anyhow!("payload too short for a CDR header"))?;
+        let little_endian = match encapsulation[1] {
+            0x00 => false,
+            0x01 => true,
+            kind => bail!("unsupported CDR encapsulation {:#04x}", kind),
+        };
+        let mut reader = CdrReader { data, pos: 0, little_endian };
+        let mut out = Vec::with_capacity(data.len());
+        self.transcode_struct(&self.root, &mut reader, &mut out)?;
+        Ok(out)
+    }
+
+    fn transcode_struct(&self, tp: &str, reader: &mut CdrReader, out: &mut Vec<u8>) -> Result<()> {
+        for field in &self.types[tp] {
+            if tp == "std_msgs/Header" && field.name == "seq" {
+                out.extend_from_slice(&0u32.to_le_bytes());
+                continue;
+            }
+            self.transcode_field(field, reader, out)
+                .with_context(|| format!("failed to transcode {}.{}", tp, field.name))?;
+        }
+        Ok(())
+    }
+
+    fn transcode_field(&self, field: &Field, reader: &mut CdrReader, out: &mut Vec<u8>) -> Result<()> {
+        let Some(len) = field.array else {
+            return self.transcode_value(&field.ty, reader, out);
+        };
+        let len = match len {
+            Some(n) => n,
+            None => {
+                let n = reader.u32()?;
+                out.extend_from_slice(&n.to_le_bytes());
+                n as usize
+            }
+        };
+        if let FieldType::Primitive(Primitive::UInt8 | Primitive::Int8 | Primitive::Bool) = field.ty {
+            out.extend_from_slice(reader.take(len)?);
+            return Ok(());
+        }
+        (0..len).try_for_each(|_| self.transcode_value(&field.ty, reader, out))
+    }
+
+    fn transcode_value(&self, ty: &FieldType, reader: &mut CdrReader, out: &mut Vec<u8>) -> Result<()> {
+        let primitive = match ty {
+            FieldType::Message(nested) => return self.transcode_struct(nested, reader, out),
+            FieldType::Primitive(primitive) => *primitive,
+        };
+        match primitive {
+            Primitive::Bool | Primitive::Int8 | Primitive::UInt8 => out.extend_from_slice(reader.take(1)?),
+            Primitive::Int16 | Primitive::UInt16 => out.extend_from_slice(&reader.primitive::<2>()?),
+            Primitive::Int32 | Primitive::UInt32 | Primitive::Float32 => out.extend_from_slice(&reader.primitive::<4>()?),
+            Primitive::Int64 | Primitive::UInt64 | Primitive::Float64 => out.extend_from_slice(&reader.primitive::<8>()?),
+            Primitive::String => {
+                // CDR lengths count the terminating NUL
+                let len = reader.u32()? as usize;
+                let bytes = reader.take(len)?;
+                let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+                out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                out.extend_from_slice(bytes);
+            }
+            // builtin_interfaces/Time and Duration: int32 sec, uint32 nanosec
+            Primitive::Time | Primitive::Duration => {
+                out.extend_from_slice(&reader.primitive::<4>()?);
+                out.extend_from_slice(&reader.primitive::<4>()?);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MessageSchema {
+    /// Encode a rosbridge JSON message of the root type in the ROS1 layout. Missing
+    /// fields are zero (NaN for floats, which rosbridge sends as `null`); `uint8[]`
+    /// may be base64 text; times are `{secs, nsecs}` (ROS1) or `{sec, nanosec}` (ROS2)
+    pub fn json_to_ros1(&self, msg: &serde_json::Value) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.encode_json_struct(&self.root, msg, &mut out)?;
+        Ok(out)
+    }
+
+    fn encode_json_struct(&self, tp: &str, msg: &serde_json::Value, out: &mut Vec<u8>) -> Result<()> {
+        for field in &self.types[tp] {
+            let value = msg.get(&field.name).unwrap_or(&serde_json::Value::Null);
+            self.encode_json_field(field, value, out)
+                .with_context(|| format!("failed to encode {}.{}", tp, field.name))?;
+        }
+        Ok(())
+    }
+
+    fn encode_json_field(&self, field: &Field, value: &serde_json::Value, out: &mut Vec<u8>) -> Result<()> {
+        let Some(len) = field.array else {
+            return self.encode_json_value(&field.ty, value, out);
+        };
+        if let FieldType::Primitive(Primitive::UInt8 | Primitive::Int8) = field.ty
+            && let Some(text) = value.as_str()
+        {
+            use base64::Engine;
+            let bytes = base64::engine::general_purpose::STANDARD.decode(text).context("invalid base64 array")?;
+            match len {
+                None => out.extend_from_slice(&(bytes.len() as u32).to_le_bytes()),
+                Some(n) if n != bytes.len() => bail!("expected {} bytes, got {}", n, bytes.len()),
+                Some(_) => {}
+            }
+            out.extend_from_slice(&bytes);
+            return Ok(());
+        }
+        let items: &[serde_json::Value] = match value {
+            serde_json::Value::Array(items) => items,
+            serde_json::Value::Null => &[],
+            other => bail!("expected an array, got {}", other),
+        };
+        let count = match len {
+            Some(n) if !items.is_empty() && items.len() != n => bail!("expected {} items, got {}", n, items.len()),
+            Some(n) => n,
+            None => {
+                out.extend_from_slice(&(items.len() as u32).to_le_bytes());
+                items.len()
+            }
+        };
+        (0..count).try_for_each(|i| self.encode_json_value(&field.ty, items.get(i).unwrap_or(&serde_json::Value::Null), out))
+    }
+
+    fn encode_json_value(&self, ty: &FieldType, value: &serde_json::Value, out: &mut Vec<u8>) -> Result<()> {
+        let primitive = match ty {
+            FieldType::Message(nested) => return self.encode_json_struct(nested, value, out),
+            FieldType::Primitive(primitive) => *primitive,
+        };
+        let int = |value: &serde_json::Value| -> Result<i64> {
+            match value {
+                serde_json::Value::Null => Ok(0),
+                serde_json::Value::Bool(b) => Ok(*b as i64),
+                _ => value
+                    .as_i64()
+                    .or_else(|| value.as_u64().map(|v| v as i64))
+                    .ok_or_else(|| anyhow!("expected an integer, got {}", value)),
+            }
+        };
+        let float = |value: &serde_json::Value| -> Result<f64> {
+            match value {
+                serde_json::Value::Null => Ok(f64::NAN),
+                _ => value.as_f64().ok_or_else(|| anyhow!("expected a number, got {}", value)),
+            }
+        };
+        match primitive {
+            Primitive::Bool | Primitive::Int8 | Primitive::UInt8 => out.push(int(value)? as u8),
+            Primitive::Int16 | Primitive::UInt16 => out.extend_from_slice(&(int(value)? as u16).to_le_bytes()),
+            Primitive::Int32 | Primitive::UInt32 => out.extend_from_slice(&(int(value)? as u32).to_le_bytes()),
+            Primitive::Int64 => out.extend_from_slice(&int(value)?.to_le_bytes()),
+            Primitive::UInt64 => {
+                let v = match value {
+                    serde_json::Value::Null => 0,
+                    _ => value.as_u64().ok_or_else(|| anyhow!("expected a uint64, got {}", value))?,
+                };
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+            Primitive::Float32 => out.extend_from_slice(&(float(value)? as f32).to_le_bytes()),
+            Primitive::Float64 => out.extend_from_slice(&float(value)?.to_le_bytes()),
+            Primitive::String => {
+                let text = value.as_str().unwrap_or_default();
+                out.extend_from_slice(&(text.len() as u32).to_le_bytes());
+                out.extend_from_slice(text.as_bytes());
+            }
+            Primitive::Time | Primitive::Duration => {
+                let part = |names: [&str; 2]| names.iter().find_map(|name| value.get(name)).map_or(Ok(0), int);
+                out.extend_from_slice(&(part(["secs", "sec"])? as u32).to_le_bytes());
+                out.extend_from_slice(&(part(["nsecs", "nanosec"])? as u32).to_le_bytes());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Field `name` of type `ty` (`float64`, `Header`, `geometry_msgs/Point[]`, `float64[9]`)
+/// declared in message type `owner`
+fn parse_field(ty: &str, name: &str, owner: &str) -> Result<Field> {
+    let (base, array) = match ty.split_once('[') {
+        Some((base, rest)) => {
+            let len = rest.strip_suffix(']').ok_or_else(|| anyhow!("invalid array type '{}' in {}", ty, owner))?;
+            let len = if len.is_empty() {
+                None
+            } else {
+                Some(len.parse().map_err(|_| anyhow!("invalid array length '{}' in {}", ty, owner))?)
+            };
+            (base, Some(len))
+        }
+        None => (ty, None),
+    };
+    let ty = match Primitive::parse(base) {
+        Some(primitive) => FieldType::Primitive(primitive),
+        None if base == "Header" => FieldType::Message("std_msgs/Header".to_string()),
+        // Unqualified names refer to the package of the declaring type
+        None if !base.contains('/') => {
+            let package = owner.split_once('/').map_or("", |(package, _)| package);
+            FieldType::Message(format!("{}/{}", package, base))
+        }
+        None => FieldType::Message(base.to_string()),
+    };
+    Ok(Field { name: name.to_string(), ty, array })
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| anyhow!("payload too short: {} bytes needed at offset {}", len, self.pos))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+}
+
+/// Reader of CDR data, where values are aligned to their size from the end of
+/// the encapsulation header
+struct CdrReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    little_endian: bool,
+}
+
+impl<'a> CdrReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| anyhow!("payload too short: {} bytes needed at offset {}", len, self.pos))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Aligned value of `N` bytes, little-endian
+    fn primitive<const N: usize>(&mut self) -> Result<[u8; N]> {
+        self.pos = self.pos.next_multiple_of(N);
+        let mut bytes: [u8; N] = self.take(N)?.try_into().unwrap();
+        if !self.little_endian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.primitive()?))
+    }
+}
+
+/// md5sum and message definition of a connection, as recorded in the bag
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TypeInfo {
+    /// Lowercase hex
+    pub md5sum: String,
+    pub definition: String,
+}
+
+impl TypeInfo {
+    pub fn new(md5sum: &[u8], definition: &str) -> Self {
+        Self {
+            md5sum: md5sum.iter().map(|b| format!("{:02x}", b)).collect(),
+            definition: definition.to_string(),
+        }
+    }
+
+    /// Whether the first field of the definition is a std_msgs/Header, as in every
+    /// stamped type; `None` without a definition
+    pub fn starts_with_header(&self) -> Option<bool> {
+        let mut fields = self
+            .definition
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty() && !line.contains('='));
+        let tp = fields.next()?.split_whitespace().next()?;
+        Some(matches!(tp, "Header" | "std_msgs/Header"))
+    }
+}
+
+/// md5sums of the standard definitions decoded by the hand-written parsers
+const KNOWN_MD5SUMS: &[(&str, &str)] = &[
+    ("sensor_msgs/Image", "060021388200f6f0f447d0fcd9c64743"),
+    ("sensor_msgs/CompressedImage", "8f7a12909da2c9d3332d540a0977563f"),
+    ("sensor_msgs/CameraInfo", "c9a58c1b0b154e0e6da7578cb991d214"),
+    ("sensor_msgs/PointCloud2", "1158d486dd51d683ce2f1be655c3c181"),
+    ("sensor_msgs/LaserScan", "90c7ef2dc6895d81024acba2ac42f369"),
+    ("sensor_msgs/NavSatFix", "2d3a8cd499b9b4a0249fb98fd05cfa48"),
+    ("sensor_msgs/Imu", "6a62c6daae103f4ff57a132d6f95cec2"),
+    ("tf2_msgs/TFMessage", "94810edda583a504dfda3829e70d7eec"),
+    ("tf/tfMessage", "94810edda583a504dfda3829e70d7eec"),
+    ("nav_msgs/Odometry", "cd5e73d190d741a2f92e81eda573aca7"),
+    ("geometry_msgs/PoseStamped", "d3812c3cbc69362b77dc0b19b345f8f5"),
+    ("nav_msgs/Path", "6227e2b7e9cce15051f669a5e197bbf7"),
+    ("rosgraph_msgs/Clock", "a9c97c1d230cfc112e270351a944ee47"),
+];
+
+/// md5sum of the standard definition of `tp`, for the types with a hand-written parser
+pub fn known_md5sum(tp: &str) -> Option<&'static str> {
+    KNOWN_MD5SUMS.iter().find(|(known, _)| *known == tp).map(|(_, md5sum)| *md5sum)
+}
+
+/// Connections of mapped types checked against the standard md5sum, by connection id
+#[derive(Debug, Default)]
+pub struct Md5Check {
+    matches: HashMap<u32, bool>,
+}
+
+impl Md5Check {
+    /// Whether the hand-written parser of `tp` can decode connection `conn`. A
+    /// connection whose md5sum differs from the standard one is reported once
+    pub fn matches(&mut self, conn: u32, topic: &str, tp: &str, info: Option<&TypeInfo>) -> bool {
+        *self.matches.entry(conn).or_insert_with(|| {
+            let (Some(expected), Some(info)) = (known_md5sum(tp), info) else {
+                return true;
+            };
+            if info.md5sum == expected {
+                return true;
+            }
+            let fields = MessageSchema::parse(tp, &info.definition)
+                .map(|schema| format!("fields: {}", schema.field_names().join(", ")))
+                .unwrap_or_else(|_| "definition not readable".to_string());
+            tracing::warn!(
+                "{}: {} has md5sum {} instead of the standard {} ({}); its messages are skipped rather than \
+                 mis-parsed (--generic-fallback decodes them from the bag's definition, \
+                 --ignore-md5-mismatch parses them as the standard type)",
+                topic,
+                tp,
+                info.md5sum,
+                expected,
+                fields
+            );
+            false
+        })
+    }
+}
+
+/// Parsed schemas of connections, by connection id; `None` when a definition is
+/// missing or does not parse, reported once
+#[derive(Debug, Default)]
+pub struct SchemaCache {
+    schemas: HashMap<u32, Option<MessageSchema>>,
+}
+
+impl SchemaCache {
+    pub fn get(&mut self, conn: u32, tp: &str, definition: Option<&str>) -> Option<&MessageSchema> {
+        self.schemas
+            .entry(conn)
+            .or_insert_with(|| {
+                let definition = definition.filter(|d| !d.trim().is_empty());
+                let Some(definition) = definition else {
+                    tracing::warn!("No message definition for {}; its messages are skipped", tp);
+                    return None;
+                };
+                MessageSchema::parse(tp, definition)
+                    .inspect_err(|e| tracing::warn!("Cannot decode {}: {:#}", tp, e))
+                    .ok()
+            })
+            .as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFINITION: &str = "\
+# A reading with a status
+Header header
+Status status
+float32[] values
+uint8[4] raw
+string note
+================================================================================
+MSG: std_msgs/Header
+uint32 seq
+time stamp
+string frame_id
+================================================================================
+MSG: my_msgs/Status
+uint8 OK=0  # constants take no space
+int8 level
+bool valid
+";
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut out = (s.len() as u32).to_le_bytes().to_vec();
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    fn payload() -> Vec<u8> {
+        let mut payload = 7u32.to_le_bytes().to_vec();
+        payload.extend_from_slice(&10u32.to_le_bytes());
+        payload.extend_from_slice(&500_000_000u32.to_le_bytes());
+        payload.extend(string("base_link"));
+        payload.extend_from_slice(&[0xfe, 1]);
+        payload.extend_from_slice(&2u32.to_le_bytes());
+        payload.extend_from_slice(&1.5f32.to_le_bytes());
+        payload.extend_from_slice(&(-2.0f32).to_le_bytes());
+        payload.extend_from_slice(&[1, 2, 3, 4]);
+        payload.extend(string("ok"));
+        payload
+    }
+
+    #[test]
+    fn test_decode_nested_definition() {
+        let schema = MessageSchema::parse("my_msgs/Reading", DEFINITION).unwrap();
+        let value = schema.decode(&payload()).unwrap();
+        assert_eq!(value.get("header.seq"), Some(&Value::UInt(7)));
+        assert_eq!(value.f64_at("header.stamp").unwrap(), 10.5);
+        assert_eq!(value.get("header.frame_id").and_then(Value::as_str), Some("base_link"));
+        assert_eq!(value.get("status.level"), Some(&Value::Int(-2)));
+        assert_eq!(value.get("status.valid"), Some(&Value::Bool(true)));
+        assert_eq!(value.get("values"), Some(&Value::Array(vec![Value::Float(1.5), Value::Float(-2.0)])));
+        assert_eq!(value.get("raw"), Some(&Value::Bytes(vec![1, 2, 3, 4])));
+        assert_eq!(value.get("note").and_then(Value::as_str), Some("ok"));
+        assert_eq!(value.get("status.missing"), None);
+
+        assert!(schema.decode(&payload()[..20]).is_err());
+        // A damaged array length fails instead of sizing the array from it
+        let mut damaged = payload();
+        damaged[27..31].copy_from_slice(&u32::MAX.to_le_bytes());
+        let error = format!("{:#}", schema.decode(&damaged).unwrap_err());
+        assert!(error.contains("does not fit"), "{error}");
+    }
+
+    #[test]
+    fn test_md5_check_reports_patched_types() {
+        let standard = TypeInfo { md5sum: known_md5sum("sensor_msgs/NavSatFix").unwrap().to_string(), ..Default::default() };
+        let patched = TypeInfo {
+            md5sum: "0123456789abcdef0123456789abcdef".to_string(),
+            definition: "Header header\nfloat64 latitude\nfloat64 hdop\n".to_string(),
+        };
+        let mut check = Md5Check::default();
+        assert!(check.matches(0, "/fix", "sensor_msgs/NavSatFix", Some(&standard)));
+        assert!(!check.matches(1, "/fix_patched", "sensor_msgs/NavSatFix", Some(&patched)));
+        // Types without a hand-written parser and connections without a header are not checked
+        assert!(check.matches(2, "/status", "my_msgs/Status", Some(&patched)));
+        assert!(check.matches(3, "/imu", "sensor_msgs/Imu", None));
+        assert_eq!(TypeInfo::new(&[0x0a, 0xff], "").md5sum, "0aff");
+    }
+
+    #[test]
+    fn test_cdr_to_ros1() {
+        let definition = "Header header\nfloat64 value\nstring label\n\
+                          ===\nMSG: std_msgs/Header\nuint32 seq\ntime stamp\nstring frame_id\n";
+        let schema = MessageSchema::parse("test_msgs/Reading", definition).unwrap();
+        let mut cdr = vec![0, 1, 0, 0];
+        cdr.extend_from_slice(&10i32.to_le_bytes());
+        cdr.extend_from_slice(&500_000_000u32.to_le_bytes());
+        cdr.extend_from_slice(&5u32.to_le_bytes());
+        cdr.extend_from_slice(b"base\0");
+        // float64 aligned to 8 bytes
+        cdr.extend_from_slice(&[0; 7]);
+        cdr.extend_from_slice(&2.5f64.to_le_bytes());
+        cdr.extend_from_slice(&3u32.to_le_bytes());
+        cdr.extend_from_slice(b"ok\0");
+
+        let ros1 = schema.cdr_to_ros1(&cdr).unwrap();
+        let value = schema.decode(&ros1).unwrap();
+        assert_eq!(value.get("header.seq"), Some(&Value::UInt(0)));
+        assert_eq!(value.f64_at("header.stamp").unwrap(), 10.5);
+        assert_eq!(value.get("header.frame_id").and_then(Value::as_str), Some("base"));
+        assert_eq!(value.f64_at("value").unwrap(), 2.5);
+        assert_eq!(value.get("label").and_then(Value::as_str), Some("ok"));
+
+        assert!(schema.cdr_to_ros1(&cdr[..30]).is_err());
+    }
+
+    #[test]
+    fn test_json_to_ros1() {
+        let definition = "Header header\nfloat32[3] gains\nuint8[] raw\nbool ok\n\
+                          ===\nMSG: std_msgs/Header\nuint32 seq\ntime stamp\nstring frame_id\n";
+        let schema = MessageSchema::parse("test_msgs/Gains", definition).unwrap();
+        // ROS2 rosbridge: no seq, sec/nanosec stamp, uint8[] as base64
+        let msg = serde_json::json!({
+            "header": {"stamp": {"sec": 10, "nanosec": 500_000_000}, "frame_id": "base"},
+            "gains": [1.0, 2.0, null],
+            "raw": "AQID",
+            "ok": true
+        });
+        let value = schema.decode(&schema.json_to_ros1(&msg).unwrap()).unwrap();
+        assert_eq!(value.get("header.seq"), Some(&Value::UInt(0)));
+        assert_eq!(value.f64_at("header.stamp").unwrap(), 10.5);
+        assert_eq!(value.get("header.frame_id").and_then(Value::as_str), Some("base"));
+        let Some(Value::Array(gains)) = value.get("gains") else { panic!("{value:?}") };
+        assert_eq!(gains[1], Value::Float(2.0));
+        assert!(gains[2].as_f64().unwrap().is_nan());
+        assert_eq!(value.get("raw"), Some(&Value::Bytes(vec![1, 2, 3])));
+        assert_eq!(value.get("ok"), Some(&Value::Bool(true)));
+
+        // ROS1 rosbridge: secs/nsecs, arrays of numbers
+        let msg = serde_json::json!({"header": {"seq": 7, "stamp": {"secs": 3, "nsecs": 0}}, "raw": [4, 5]});
+        let value = schema.decode(&schema.json_to_ros1(&msg).unwrap()).unwrap();
+        assert_eq!(value.get("header.seq"), Some(&Value::UInt(7)));
+        assert_eq!(value.get("raw"), Some(&Value::Bytes(vec![4, 5])));
+        assert!(schema.json_to_ros1(&serde_json::json!({"gains": [1.0]})).is_err());
+    }
+
+    #[test]
+    fn test_missing_nested_definition() {
+        let definition = DEFINITION.split("====").next().unwrap();
+        let err = MessageSchema::parse("my_msgs/Reading", definition).unwrap_err();
+        assert!(err.to_string().contains("std_msgs/Header"), "{err}");
+    }
+}
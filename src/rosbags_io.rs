@@ -1,195 +1,395 @@
 use anyhow::{Context, Result};
-use rosbag::{ChunkRecord, MessageRecord, RosBag};
-use std::collections::BTreeMap;
-
-/// Diagnose bag file issues
-pub fn diagnose_bag(path: &str) -> Result<()> {
-    tracing::info!("Starting bag diagnosis for: {}", path);
-
-    // Try to open the bag
-    tracing::debug!("Opening bag file...");
-    let bag = match RosBag::new(path) {
-        Ok(bag) => {
-            tracing::info!("Bag file opened successfully");
-            bag
-        }
-        Err(e) => {
-            tracing::error!("Failed to open bag file: {}", e);
-            return Err(e.into());
-        }
-    };
+use rosbag::{ChunkRecord, IndexRecord, RosBag};
+use std::collections::{BTreeMap, HashSet};
 
-    // Count total chunks without reading them
-    tracing::debug!("Counting chunks...");
-    let mut total_chunks = 0;
-    let mut successful_chunks = 0;
+use crate::ros_msg::TypeInfo;
 
-    for record in bag.chunk_records() {
-        total_chunks += 1;
+/// Position, record-time span and message count of one chunk, from its ChunkInfo index record
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkSpan {
+    pub pos: u64,
+    pub start_ns: u64,
+    pub end_ns: u64,
+    pub messages: u64,
+}
 
-        if record.is_ok() {
-            successful_chunks += 1;
-        } else {
-            tracing::error!("Failed to read chunk #{}: {:?}", total_chunks, record.err());
-            break;
-        }
+/// Index section of a bag 2.0 file: what it holds without decompressing any chunk
+#[derive(Debug, Default)]
+pub struct BagIndex {
+    pub chunks: Vec<ChunkSpan>,
+    /// Connection id → (topic, type)
+    pub connections: BTreeMap<u32, (String, String)>,
+    /// Connection id → md5sum and message_definition text
+    pub definitions: BTreeMap<u32, TypeInfo>,
+    pub latched: HashSet<u32>,
+    /// Chunk position → ids of the connections with messages in it
+    pub chunk_connections: BTreeMap<u64, Vec<u32>>,
+}
 
-        // Log progress every 1000 chunks
-        if total_chunks % 1000 == 0 {
-            tracing::info!("Processed {} chunks successfully", total_chunks);
+impl BagIndex {
+    /// Read the index section; `None` for bags without ChunkInfo records (unindexed)
+    pub fn read(bag: &RosBag) -> Result<Option<Self>> {
+        let mut index = Self::default();
+        for record in bag.index_records() {
+            match record.context("failed to read bag index record")? {
+                IndexRecord::ChunkInfo(info) => {
+                    index.chunks.push(ChunkSpan {
+                        pos: info.chunk_pos,
+                        start_ns: info.start_time,
+                        end_ns: info.end_time,
+                        messages: info.entries().map(|entry| entry.count as u64).sum(),
+                    });
+                    index.chunk_connections.insert(info.chunk_pos, info.entries().map(|entry| entry.conn_id).collect());
+                }
+                IndexRecord::Connection(conn) => {
+                    index.connections.insert(conn.id, (conn.topic.to_string(), conn.tp.to_string()));
+                    index.definitions.insert(conn.id, TypeInfo::new(&conn.md5sum, conn.message_definition));
+                    if conn.latching {
+                        index.latched.insert(conn.id);
+                    }
+                }
+                IndexRecord::IndexData(_) => {}
+            }
         }
+        if index.chunks.is_empty() {
+            return Ok(None);
+        }
+        index.chunks.sort_by_key(|c| c.pos);
+        Ok(Some(index))
+    }
+
+    /// Record time of the first message, in nanoseconds
+    pub fn start_ns(&self) -> Option<u64> {
+        self.chunks.iter().map(|c| c.start_ns).min()
     }
 
-    tracing::info!("Diagnosis complete:");
-    tracing::info!("  Total chunks attempted: {}", total_chunks);
-    tracing::info!("  Successful chunks: {}", successful_chunks);
-    tracing::info!("  Failed chunks: {}", total_chunks - successful_chunks);
+    /// Record time of the last message, in nanoseconds
+    pub fn end_ns(&self) -> Option<u64> {
+        self.chunks.iter().map(|c| c.end_ns).max()
+    }
 
-    if successful_chunks < total_chunks {
-        tracing::error!("Bag file appears to be corrupted or truncated");
-        tracing::info!("File is likely incomplete - missing data after chunk {}", successful_chunks);
-    } else {
-        tracing::info!("Bag file structure appears intact");
+    /// Chunks holding messages between `start` and `end` seconds from the bag start
+    pub fn chunks_in_window(&self, start: Option<f64>, end: Option<f64>) -> Vec<ChunkSpan> {
+        self.chunks_in_range(self.start_ns().unwrap_or(0), start, end)
     }
 
-    Ok(())
+    /// Chunks holding messages between `start` and `end` seconds from `origin_ns`
+    pub fn chunks_in_range(&self, origin_ns: u64, start: Option<f64>, end: Option<f64>) -> Vec<ChunkSpan> {
+        let origin = origin_ns as f64;
+        let from = start.map_or(f64::NEG_INFINITY, |s| origin + s * 1e9);
+        let to = end.map_or(f64::INFINITY, |e| origin + e * 1e9);
+        self.chunks
+            .iter()
+            .filter(|c| c.end_ns as f64 >= from && c.start_ns as f64 <= to)
+            .copied()
+            .collect()
+    }
 }
 
-#[allow(clippy::collapsible_if)]
-pub fn inspect_bag(path: &str) -> Result<()> {
-    tracing::info!("Starting bag inspection for: {}", path);
-
-    // 1) open bag
-    tracing::debug!("Opening bag file...");
-    let bag = RosBag::new(path).with_context(|| format!("failed to open bag: {}", path))?;
-    tracing::info!("Bag file opened successfully");
-
-    // 2) collect connections first
-    tracing::debug!("Collecting connections...");
-    let mut connections = BTreeMap::new();
-    let mut chunk_count = 0;
-    let mut connection_count = 0;
-
-    for record in bag.chunk_records() {
-        chunk_count += 1;
-        tracing::debug!("Processing chunk record #{}", chunk_count);
-
-        let record = match record.with_context(|| format!("failed to read chunk record #{}", chunk_count)) {
-            Ok(record) => record,
-            Err(e) => {
-                tracing::error!("Error reading chunk #{}: {}", chunk_count, e);
-                tracing::info!("Total chunks processed before error: {}", chunk_count - 1);
-                return Err(e);
-            }
-        };
+/// Read only the chunk records at `spans`, skipping everything in between
+pub fn read_chunks_at<'a>(
+    bag: &'a RosBag,
+    spans: &'a [ChunkSpan],
+) -> impl Iterator<Item = rosbag::Result<ChunkRecord<'a>>> + 'a {
+    spans.iter().filter_map(move |span| read_chunk_at(bag, span.pos))
+}
 
-        if let ChunkRecord::Chunk(chunk) = record {
-            tracing::debug!("Found chunk with {} messages", chunk.messages().count());
+/// Read the chunk record starting at `pos`
+pub fn read_chunk_at(bag: &RosBag, pos: u64) -> Option<rosbag::Result<ChunkRecord<'_>>> {
+    let mut records = bag.chunk_records();
+    match records.seek(pos) {
+        Ok(()) => records.next(),
+        Err(e) => Some(Err(e)),
+    }
+}
 
-            for msg in chunk.messages() {
-                let msg = msg.with_context(|| format!("failed to read message in chunk #{}", chunk_count))?;
+pub(crate) const VERSION_LINE: &[u8] = b"#ROSBAG V2.0\n";
+pub(crate) const CHUNK_OP: u8 = 0x05;
+pub(crate) const INDEX_DATA_OP: u8 = 0x04;
+// "op" field of a chunk header: length 4, then "op=" and the opcode
+const CHUNK_OP_FIELD: &[u8] = b"\x04\x00\x00\x00op=\x05";
+// Record headers are a few fields; anything larger is a damaged length
+const MAX_HEADER_LEN: usize = 64 * 1024;
 
-                if let MessageRecord::Connection(conn) = msg {
-                    connection_count += 1;
-                    connections.insert(conn.id, (conn.topic.to_string(), conn.tp.to_string()));
-                    tracing::debug!("Found connection: {} -> {}", conn.topic, conn.tp);
-                }
-            }
+/// Where the chunk and index sections of a bag file start, from its header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BagLayout {
+    /// First chunk record
+    pub start_pos: u64,
+    /// Index section; 0 when the recorder never wrote it
+    pub index_pos: u64,
+    pub file_len: u64,
+}
+
+impl BagLayout {
+    pub fn read(path: &str) -> Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("failed to open bag: {}", path))?;
+        // SAFETY: the bag is opened read-only and only read while mapped; like the bag
+        // reader's own mapping, this assumes no other process truncates it meanwhile
+        let data = unsafe { memmap2::Mmap::map(&file) }.with_context(|| format!("failed to map bag: {}", path))?;
+        Self::parse(&data).with_context(|| format!("invalid bag header: {}", path))
+    }
+
+    pub(crate) fn parse(data: &[u8]) -> Result<Self> {
+        if !data.starts_with(VERSION_LINE) {
+            anyhow::bail!("not a ROS bag 2.0 file");
         }
+        let (header, data_len) = record_parts(data, VERSION_LINE.len(), data.len())
+            .ok_or_else(|| anyhow::anyhow!("truncated bag header record"))?;
+        let index_pos = header_field(header, "index_pos")
+            .filter(|v| v.len() == 8)
+            .map(|v| u64::from_le_bytes(v.try_into().unwrap()))
+            .ok_or_else(|| anyhow::anyhow!("bag header has no index_pos"))?;
+        Ok(Self {
+            start_pos: (VERSION_LINE.len() + 8 + header.len() + data_len) as u64,
+            index_pos,
+            file_len: data.len() as u64,
+        })
+    }
+
+    /// Whether the index section exists; bags of a crashed recorder have none, or
+    /// point past the end of a truncated file
+    pub fn has_index(&self) -> bool {
+        self.index_pos >= self.start_pos && self.index_pos <= self.file_len
+    }
+}
+
+/// Chunk records of a bag found by stepping over record lengths, for bags whose
+/// index is missing or damaged
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ChunkScan {
+    pub positions: Vec<u64>,
+    /// Damaged stretches stepped over while looking for the next chunk
+    pub damaged_regions: usize,
+    pub skipped_bytes: u64,
+}
+
+impl ChunkScan {
+    pub fn read(path: &str) -> Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("failed to open bag: {}", path))?;
+        // SAFETY: see `BagLayout::read`
+        let data = unsafe { memmap2::Mmap::map(&file) }.with_context(|| format!("failed to map bag: {}", path))?;
+        let layout = BagLayout::parse(&data).with_context(|| format!("invalid bag header: {}", path))?;
+        let end = if layout.has_index() { layout.index_pos } else { layout.file_len };
+        Ok(Self::scan(&data, layout.start_pos as usize, end as usize))
     }
 
-    tracing::info!("Collected {} connections from {} chunk records", connection_count, chunk_count);
-
-    // 3) gather stats from messages
-    tracing::debug!("Gathering message statistics...");
-    #[derive(Default)]
-    struct Stat {
-        ty: String,
-        count: u64,
-        first: f64,
-        last: f64,
-    }
-    let mut stats: BTreeMap<String, Stat> = BTreeMap::new();
-
-    let mut bag_start_ns = f64::INFINITY;
-    let mut global_first = f64::INFINITY;
-    let mut global_last = 0.0_f64;
-    let mut total: u64 = 0;
-    let mut message_chunk_count = 0;
-
-    for record in bag.chunk_records() {
-        message_chunk_count += 1;
-        tracing::debug!("Processing message chunk #{}", message_chunk_count);
-
-        let record = record.with_context(|| format!("failed to read message chunk record #{}", message_chunk_count))?;
-
-        if let ChunkRecord::Chunk(chunk) = record {
-            tracing::debug!("Message chunk #{} has {} messages", message_chunk_count, chunk.messages().count());
-
-            for msg in chunk.messages() {
-                let msg = msg.with_context(|| format!("failed to read message in chunk #{}", message_chunk_count))?;
-
-                if let MessageRecord::MessageData(msg_data) = msg {
-                    total += 1;
-
-                    if let Some((topic, tp)) = connections.get(&msg_data.conn_id) {
-                        let ts_ns = msg_data.time as f64;
-                        bag_start_ns = bag_start_ns.min(ts_ns);
-                        let ts = ts_ns / 1_000_000_000.0; // convert to seconds
-                        let entry = stats.entry(topic.clone()).or_insert_with(|| Stat {
-                            ty: tp.clone(),
-                            count: 0,
-                            first: ts,
-                            last: ts,
-                        });
-                        entry.count += 1;
-                        entry.first = entry.first.min(ts);
-                        entry.last = entry.last.max(ts);
-                        global_first = global_first.min(ts);
-                        global_last = global_last.max(ts);
-                        total += 1;
+    pub(crate) fn scan(data: &[u8], start: usize, end: usize) -> Self {
+        let mut scan = Self::default();
+        let mut pos = start;
+        while pos < end {
+            match record_at(data, pos, end) {
+                Some((op, next)) if op == CHUNK_OP || op == INDEX_DATA_OP => {
+                    if op == CHUNK_OP {
+                        scan.positions.push(pos as u64);
                     }
+                    pos = next;
+                }
+                _ => {
+                    // Resume at the next chunk header that parses as a whole record
+                    let next = next_chunk_start(data, pos + 1, end).unwrap_or(end);
+                    scan.damaged_regions += 1;
+                    scan.skipped_bytes += (next - pos) as u64;
+                    pos = next;
                 }
             }
         }
+        scan
     }
+}
 
-    // Calculate relative times from bag start
-    if bag_start_ns.is_finite() {
-        let bag_start_s = bag_start_ns / 1_000_000_000.0;
-        global_first -= bag_start_s;
-        global_last -= bag_start_s;
-        for stat in stats.values_mut() {
-            stat.first -= bag_start_s;
-            stat.last -= bag_start_s;
-        }
+/// Chunk count per compression (`none`, `bz2`, `lz4`), from the chunk record headers
+pub fn chunk_compressions(path: &str) -> Result<BTreeMap<String, u64>> {
+    let file = std::fs::File::open(path).with_context(|| format!("failed to open bag: {}", path))?;
+    // SAFETY: see `BagLayout::read`
+    let data = unsafe { memmap2::Mmap::map(&file) }.with_context(|| format!("failed to map bag: {}", path))?;
+    let layout = BagLayout::parse(&data).with_context(|| format!("invalid bag header: {}", path))?;
+    let end = if layout.has_index() { layout.index_pos } else { layout.file_len } as usize;
+    let mut compressions = BTreeMap::new();
+    for pos in ChunkScan::scan(&data, layout.start_pos as usize, end).positions {
+        let compression = record_parts(&data, pos as usize, end)
+            .and_then(|(header, _)| header_field(header, "compression"))
+            .map(|value| String::from_utf8_lossy(value).into_owned())
+            .unwrap_or_else(|| "unknown".to_string());
+        *compressions.entry(compression).or_insert(0) += 1;
     }
+    Ok(compressions)
+}
 
-    let duration = if global_last.is_finite() {
-        global_last - global_first
-    } else {
-        0.0
+/// Header bytes and data length of the record at `pos`, if both fit before `end`
+pub(crate) fn record_parts(data: &[u8], pos: usize, end: usize) -> Option<(&[u8], usize)> {
+    let read_u32 = |at: usize| -> Option<usize> {
+        (at + 4 <= end).then(|| u32::from_le_bytes(data[at..at + 4].try_into().unwrap()) as usize)
     };
+    let header_len = read_u32(pos)?;
+    if header_len > MAX_HEADER_LEN {
+        return None;
+    }
+    let header = data.get(pos + 4..pos + 4 + header_len)?;
+    let data_len = read_u32(pos + 4 + header_len)?;
+    (pos + 8 + header_len + data_len <= end).then_some((header, data_len))
+}
+
+/// Value of field `name` in a record header whose fields exactly fill it
+pub(crate) fn header_field<'a>(header: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let mut value = None;
+    let mut rest = header;
+    while !rest.is_empty() {
+        let len = u32::from_le_bytes(rest.get(..4)?.try_into().unwrap()) as usize;
+        let field = rest.get(4..4 + len)?;
+        let eq = field.iter().position(|b| *b == b'=')?;
+        if &field[..eq] == name.as_bytes() {
+            value = Some(&field[eq + 1..]);
+        }
+        rest = &rest[4 + len..];
+    }
+    value
+}
+
+/// Opcode of the well-formed record at `pos` and the position following it
+pub(crate) fn record_at(data: &[u8], pos: usize, end: usize) -> Option<(u8, usize)> {
+    let (header, data_len) = record_parts(data, pos, end)?;
+    match header_field(header, "op")? {
+        [op] => Some((*op, pos + 8 + header.len() + data_len)),
+        _ => None,
+    }
+}
 
-    // 4) print summary
-    println!("Bag: {}", path);
-    println!(
-        "Start (s): {:.6}, End (s): {:.6}, Duration (s): {:.6}, Total messages: {}\n",
-        global_first, global_last, duration, total
-    );
-
-    // 5) table
-    println!(
-        "{:<35} {:<35} {:>7} {:>10} {:>10}",
-        "Topic", "Type", "Count", "Start(s)", "End(s)"
-    );
-    println!("{}", "-".repeat(97));
-    for (topic, st) in stats.iter() {
-        println!(
-            "{:<35} {:<35} {:>7} {:>10.6} {:>10.6}",
-            topic, st.ty, st.count, st.first, st.last
-        );
-    }
-
-    Ok(())
+/// Start of the first well-formed chunk record from `from` on
+fn next_chunk_start(data: &[u8], from: usize, end: usize) -> Option<usize> {
+    let mut search = from;
+    while search < end {
+        let hit = search + data[search..end].windows(CHUNK_OP_FIELD.len()).position(|w| w == CHUNK_OP_FIELD)?;
+        // The op field sits among the first few header fields, after the header length
+        let earliest = hit.saturating_sub(64).max(from);
+        let start = (earliest..=hit.saturating_sub(4))
+            .rev()
+            .find(|s| record_at(data, *s, end).is_some_and(|(op, _)| op == CHUNK_OP));
+        if start.is_some() {
+            return start;
+        }
+        search = hit + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bag::{write_bag, write_bag_compressed, TestCompression, TestConnection, TestMessage};
+    use rosbag::MessageRecord;
+
+    #[test]
+    fn test_index_window_reads_only_overlapping_chunks() {
+        let path = std::env::temp_dir().join(format!("bag2rrd_index_{}.bag", std::process::id()));
+        let connections = [
+            TestConnection { id: 0, topic: "/tf_static", tp: "tf2_msgs/TFMessage", latching: true },
+            TestConnection { id: 1, topic: "/imu", tp: "sensor_msgs/Imu", latching: false },
+        ];
+        // Three chunks covering 100-101 s, 102-103 s and 104-105 s
+        let chunks: Vec<Vec<TestMessage>> = (0..3)
+            .map(|i| {
+                let t = 100.0 + 2.0 * i as f64;
+                let mut msgs = vec![TestMessage::new(1, t, vec![i]), TestMessage::new(1, t + 1.0, vec![i])];
+                if i == 0 {
+                    msgs.insert(0, TestMessage::new(0, t, vec![]));
+                }
+                msgs
+            })
+            .collect();
+        write_bag(&path, &connections, &chunks);
+
+        let bag = RosBag::new(&path).unwrap();
+        let index = BagIndex::read(&bag).unwrap().unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(index.chunks.len(), 3);
+        let counts: Vec<u64> = index.chunks.iter().map(|c| c.messages).collect();
+        assert_eq!(counts, [3, 2, 2]);
+        assert_eq!(index.start_ns(), Some(100_000_000_000));
+        assert_eq!(index.end_ns(), Some(105_000_000_000));
+        assert_eq!(index.connections[&1], ("/imu".to_string(), "sensor_msgs/Imu".to_string()));
+        assert_eq!(index.latched, HashSet::from([0]));
+
+        let window = index.chunks_in_window(Some(2.5), Some(3.5));
+        assert_eq!(window, [index.chunks[1]]);
+        assert_eq!(index.chunks_in_window(Some(2.5), None).len(), 2);
+        assert_eq!(index.chunks_in_window(None, Some(1.0)).len(), 1);
+
+        // The seeked chunk only holds its own messages
+        let payloads: Vec<Vec<u8>> = read_chunks_at(&bag, &window)
+            .flat_map(|record| match record.unwrap() {
+                ChunkRecord::Chunk(chunk) => chunk
+                    .messages()
+                    .filter_map(|m| match m.unwrap() {
+                        MessageRecord::MessageData(data) => Some(data.data.to_vec()),
+                        MessageRecord::Connection(_) => None,
+                    })
+                    .collect(),
+                ChunkRecord::IndexData(_) => vec![],
+            })
+            .collect();
+        assert_eq!(payloads, [vec![1], vec![1]]);
+    }
+
+    #[test]
+    fn test_chunk_scan_steps_over_damaged_records() {
+        let path = std::env::temp_dir().join(format!("bag2rrd_scan_{}.bag", std::process::id()));
+        let connections = [TestConnection { id: 0, topic: "/imu", tp: "sensor_msgs/Imu", latching: false }];
+        let chunks: Vec<Vec<TestMessage>> =
+            (0..3).map(|i| vec![TestMessage::new(0, i as f64, vec![i; 50])]).collect();
+        write_bag(&path, &connections, &chunks);
+        let p = path.to_str().unwrap();
+        let bag = RosBag::new(&path).unwrap();
+        let index = BagIndex::read(&bag).unwrap().unwrap();
+        let positions: Vec<u64> = index.chunks.iter().map(|c| c.pos).collect();
+        let layout = BagLayout::read(p).unwrap();
+        assert!(layout.has_index());
+        assert_eq!(layout.start_pos, positions[0]);
+        assert_eq!(ChunkScan::read(p).unwrap(), ChunkScan { positions: positions.clone(), ..Default::default() });
+
+        // A garbage header length in the second chunk loses that chunk only
+        let mut data = std::fs::read(&path).unwrap();
+        let second = positions[1] as usize;
+        data[second..second + 4].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+        let scan = ChunkScan::read(p).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(scan.positions, [positions[0], positions[2]]);
+        assert_eq!(scan.damaged_regions, 1);
+        assert_eq!(scan.skipped_bytes, positions[2] - positions[1]);
+    }
+
+    #[test]
+    fn test_compressed_chunks_read_by_index_and_scan() {
+        let connections = [TestConnection { id: 0, topic: "/imu", tp: "sensor_msgs/Imu", latching: false }];
+        let chunks: Vec<Vec<TestMessage>> = (0..3)
+            .map(|i| vec![TestMessage::new(0, i as f64, vec![i; 200]), TestMessage::new(0, i as f64 + 0.5, vec![i; 200])])
+            .collect();
+        for compression in [TestCompression::Bz2, TestCompression::Lz4] {
+            let path = std::env::temp_dir().join(format!("bag2rrd_{:?}_{}.bag", compression, std::process::id()));
+            write_bag_compressed(&path, &connections, &chunks, compression);
+            let p = path.to_str().unwrap();
+            let bag = RosBag::new(&path).unwrap();
+            let index = BagIndex::read(&bag).unwrap().unwrap();
+            let positions: Vec<u64> = index.chunks.iter().map(|c| c.pos).collect();
+            // Chunk records hold compressed data, so the scan steps over their stored length
+            assert_eq!(ChunkScan::read(p).unwrap().positions, positions);
+
+            let window = index.chunks_in_window(Some(1.0), Some(1.5));
+            let payloads: Vec<Vec<u8>> = read_chunks_at(&bag, &window)
+                .flat_map(|record| match record.unwrap() {
+                    ChunkRecord::Chunk(chunk) => chunk
+                        .messages()
+                        .filter_map(|m| match m.unwrap() {
+                            MessageRecord::MessageData(data) => Some(data.data.to_vec()),
+                            MessageRecord::Connection(_) => None,
+                        })
+                        .collect(),
+                    ChunkRecord::IndexData(_) => vec![],
+                })
+                .collect();
+            std::fs::remove_file(&path).ok();
+            assert_eq!(payloads, [vec![1; 200], vec![1; 200]], "{:?}", compression);
+        }
+    }
 }
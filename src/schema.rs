@@ -1,28 +1,97 @@
 //! Schema command - Print supported ROS → Rerun mappings
+//!
+//! The list comes from the builtin mapper registry, so it is the one the
+//! converter uses. Options are the long flags of `bag2rrd convert`, which are
+//! also the keys of `--config` files.
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+
+use crate::convert::ConvertOptions;
+use crate::mappings::registry::{MapperRegistry, MappingEntry, SCRIPT_MAPPING};
+
+/// Output of the schema command
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SchemaFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+pub fn parse_schema_format(s: &str) -> Result<SchemaFormat> {
+    match s.to_ascii_lowercase().as_str() {
+        "text" => Ok(SchemaFormat::Text),
+        "json" => Ok(SchemaFormat::Json),
+        _ => bail!("Invalid schema format '{}' (expected text or json)", s),
+    }
+}
+
+/// Every mapping the converter can use, including the optional ones
+pub fn mappings() -> Vec<MappingEntry> {
+    // Camera info and the generic fallback are only registered when enabled
+    let options = ConvertOptions { depth_to_points: true, generic_fallback: true, ..Default::default() };
+    let mut entries = MapperRegistry::builtin(&options).mappings();
+    entries.push(MappingEntry {
+        ros_type: "types of --mapper-script".to_string(),
+        topic: None,
+        mapping: Some(SCRIPT_MAPPING),
+    });
+    entries
+}
 
 /// Print all supported ROS → Rerun mappings with version introduced
 pub fn print_schema() -> Result<()> {
+    print_schema_as(SchemaFormat::Text)
+}
+
+/// Print the mappings as a table or as JSON
+pub fn print_schema_as(format: SchemaFormat) -> Result<()> {
+    let entries = mappings();
+    if format == SchemaFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
     println!("Supported ROS → Rerun mappings:");
     println!("---------------------------------------------------------------");
-
-    let mappings = vec![
-        ("sensor_msgs/Image", "Image/DepthImage", "v0.1.0"),
-        ("sensor_msgs/CompressedImage", "Image", "v0.1.0"),
-        ("sensor_msgs/PointCloud2", "Points3D", "v0.2.0"),
-        ("sensor_msgs/LaserScan", "Points2D/LineStrips2D", "v0.2.0"),
-        ("sensor_msgs/NavSatFix", "Points3D (+path optional)", "v0.2.0"),
-        ("sensor_msgs/Imu", "Transform3D + Arrows3D + Scalar", "v0.5.0"),
-        ("/tf, /tf_static", "Transforms3D", "v0.3.0"),
-        ("nav_msgs/Odometry", "Transforms3D", "v0.3.0"),
-        ("geometry_msgs/PoseStamped", "Transforms3D", "v0.3.0"),
-        ("nav_msgs/Path", "LineStrips3D", "v0.3.0"),
-    ];
-
-    for (ros_type, rerun_archetype, version) in mappings {
-        println!("{:<30} → {:<25} {}", ros_type, rerun_archetype, version);
+    for entry in entries {
+        let ros_type = match (&entry.topic, entry.ros_type.as_str()) {
+            (Some(topic), _) => format!("topic {}", topic),
+            (None, "*") => "other types (--generic-fallback)".to_string(),
+            (None, tp) => tp.to_string(),
+        };
+        let Some(mapping) = entry.mapping else {
+            println!("{:<40} → (custom mapper)", ros_type);
+            continue;
+        };
+        println!("{:<40} → {:<40} {}", ros_type, mapping.archetypes, mapping.since);
+        if !mapping.options.is_empty() {
+            println!("{:<43}options: --{}", "", mapping.options.join(", --"));
+        }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mappings() {
+        let entries = mappings();
+        let image = entries.iter().find(|e| e.ros_type == "sensor_msgs/Image").unwrap();
+        let mapping = image.mapping.as_ref().unwrap();
+        assert_eq!(mapping.since, "v0.1.0");
+        assert!(mapping.options.contains(&"image-scale"));
+        assert!(entries.iter().any(|e| e.ros_type == "sensor_msgs/CameraInfo"));
+        // Every builtin mapper describes its types
+        assert!(entries.iter().all(|e| e.mapping.is_some()));
+        // The fallback comes after the named types
+        let fallback = entries.iter().position(|e| e.ros_type == "*").unwrap();
+        assert!(entries[..fallback].iter().all(|e| e.ros_type != "*"));
+
+        let json = serde_json::to_value(&entries).unwrap();
+        assert_eq!(json[0]["ros_type"], entries[0].ros_type.as_str());
+        assert!(json[0]["mapping"]["options"].is_array());
+    }
+}
@@ -0,0 +1,187 @@
+//! Minimal ROS bag 2.0 writer for staged inputs: uncompressed chunks followed by
+//! the index section the reader needs. Its record helpers also serve `diagnose --repair`
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::SourceTopic;
+use crate::rosbags_io::VERSION_LINE;
+
+/// Uncompressed chunks of this many bytes
+const CHUNK_BYTES: usize = 4 << 20;
+/// Length of the bag header record, padded like `rosbag` does so it can be rewritten in place
+const BAG_HEADER_LEN: usize = 4096;
+
+/// Writes the chunks as messages come, then the index and its position in the header
+pub(super) struct BagWriter {
+    file: BufWriter<File>,
+    pos: u64,
+    /// Connection records, by connection id
+    connections: Vec<Vec<u8>>,
+    chunk: Vec<u8>,
+    /// Connections of the current chunk and their message counts
+    chunk_counts: Vec<(u32, u32)>,
+    chunk_times: Option<(u64, u64)>,
+    chunk_infos: Vec<Vec<u8>>,
+}
+
+impl BagWriter {
+    pub(super) fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut file = BufWriter::new(file);
+        // Rewritten with the index position once the chunks are written
+        let header = bag_header(0, 0, 0);
+        file.write_all(VERSION_LINE)?;
+        file.write_all(&header)?;
+        Ok(Self {
+            file,
+            pos: (VERSION_LINE.len() + header.len()) as u64,
+            connections: Vec::new(),
+            chunk: Vec::new(),
+            chunk_counts: Vec::new(),
+            chunk_times: None,
+            chunk_infos: Vec::new(),
+        })
+    }
+
+    /// Add a connection for `topic`; its id
+    pub(super) fn connection(&mut self, topic: &SourceTopic) -> u32 {
+        let id = self.connections.len() as u32;
+        // Bag readers expect 32 hex digits; an unknown md5sum is written as zeros
+        let md5sum = if topic.md5sum.len() == 32 && topic.md5sum.bytes().all(|b| b.is_ascii_hexdigit()) {
+            topic.md5sum.to_ascii_lowercase()
+        } else {
+            "0".repeat(32)
+        };
+        let data = [
+            field("topic", topic.name.as_bytes()),
+            field("type", topic.tp.as_bytes()),
+            field("md5sum", md5sum.as_bytes()),
+            field("message_definition", topic.definition.as_bytes()),
+            field("latching", if topic.latching { b"1" } else { b"0" }),
+        ]
+        .concat();
+        self.connections.push(record(
+            &[field("op", &[0x07]), field("conn", &id.to_le_bytes()), field("topic", topic.name.as_bytes())],
+            &data,
+        ));
+        id
+    }
+
+    pub(super) fn write(&mut self, conn: u32, time_ns: u64, data: &[u8]) -> Result<()> {
+        match self.chunk_counts.iter_mut().find(|(id, _)| *id == conn) {
+            Some((_, count)) => *count += 1,
+            None => {
+                self.chunk.extend_from_slice(&self.connections[conn as usize]);
+                self.chunk_counts.push((conn, 1));
+            }
+        }
+        let (start, end) = self.chunk_times.get_or_insert((time_ns, time_ns));
+        *start = (*start).min(time_ns);
+        *end = (*end).max(time_ns);
+        self.chunk.extend(record(
+            &[field("op", &[0x02]), field("conn", &conn.to_le_bytes()), time_field("time", time_ns)],
+            data,
+        ));
+        if self.chunk.len() >= CHUNK_BYTES {
+            self.flush_chunk()?;
+        }
+        Ok(())
+    }
+
+    fn flush_chunk(&mut self) -> Result<()> {
+        let Some((start, end)) = self.chunk_times.take() else {
+            return Ok(());
+        };
+        let chunk = record(
+            &[
+                field("op", &[0x05]),
+                field("compression", b"none"),
+                field("size", &(self.chunk.len() as u32).to_le_bytes()),
+            ],
+            &self.chunk,
+        );
+        let entries: Vec<u8> = self
+            .chunk_counts
+            .iter()
+            .flat_map(|(conn, count)| [conn.to_le_bytes(), count.to_le_bytes()].concat())
+            .collect();
+        self.chunk_infos.push(record(
+            &[
+                field("op", &[0x06]),
+                field("ver", &1u32.to_le_bytes()),
+                field("chunk_pos", &self.pos.to_le_bytes()),
+                time_field("start_time", start),
+                time_field("end_time", end),
+                field("count", &(self.chunk_counts.len() as u32).to_le_bytes()),
+            ],
+            &entries,
+        ));
+        self.file.write_all(&chunk)?;
+        self.pos += chunk.len() as u64;
+        self.chunk.clear();
+        self.chunk_counts.clear();
+        Ok(())
+    }
+
+    pub(super) fn finish(mut self) -> Result<()> {
+        self.flush_chunk()?;
+        let index_pos = self.pos;
+        for conn in &self.connections {
+            self.file.write_all(conn)?;
+        }
+        for info in &self.chunk_infos {
+            self.file.write_all(info)?;
+        }
+        self.file.seek(SeekFrom::Start(VERSION_LINE.len() as u64))?;
+        self.file
+            .write_all(&bag_header(index_pos, self.connections.len(), self.chunk_infos.len()))?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Bag header record pointing at the index section
+pub(crate) fn bag_header(index_pos: u64, conn_count: usize, chunk_count: usize) -> Vec<u8> {
+    let header = [
+        field("op", &[0x03]),
+        field("index_pos", &index_pos.to_le_bytes()),
+        field("conn_count", &(conn_count as u32).to_le_bytes()),
+        field("chunk_count", &(chunk_count as u32).to_le_bytes()),
+    ];
+    let padding = BAG_HEADER_LEN - 8 - header.iter().map(Vec::len).sum::<usize>();
+    record(&header, &vec![b' '; padding])
+}
+
+/// `name=value` header field, length-prefixed
+pub(crate) fn field(name: &str, value: &[u8]) -> Vec<u8> {
+    let mut out = ((name.len() + 1 + value.len()) as u32).to_le_bytes().to_vec();
+    out.extend_from_slice(name.as_bytes());
+    out.push(b'=');
+    out.extend_from_slice(value);
+    out
+}
+
+/// ROS `time` value: seconds then nanoseconds, both u32
+pub(crate) fn time_value(time_ns: u64) -> Vec<u8> {
+    let mut value = ((time_ns / 1_000_000_000) as u32).to_le_bytes().to_vec();
+    value.extend_from_slice(&((time_ns % 1_000_000_000) as u32).to_le_bytes());
+    value
+}
+
+fn time_field(name: &str, time_ns: u64) -> Vec<u8> {
+    field(name, &time_value(time_ns))
+}
+
+/// Length-prefixed header fields then length-prefixed data
+pub(crate) fn record(header: &[Vec<u8>], data: &[u8]) -> Vec<u8> {
+    let header: Vec<u8> = header.concat();
+    let mut out = (header.len() as u32).to_le_bytes().to_vec();
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
@@ -0,0 +1,151 @@
+//! Standard ROS1 definitions of the common ROS2 types, and ros2msg definitions
+//! in the syntax [`MessageSchema`](crate::ros_msg::MessageSchema) reads
+//!
+//! The common sensor_msgs, nav_msgs, geometry_msgs and tf2_msgs types have the
+//! same layout in ROS1 and ROS2 apart from the header `seq`, so their ROS2
+//! messages are re-encoded with the ROS1 definition and reach the hand-written parsers.
+
+/// `pkg/Type` for a ROS2 `pkg/msg/Type`
+pub fn ros1_type(tp: &str) -> String {
+    tp.replacen("/msg/", "/", 1)
+}
+
+/// A ros2msg definition in the syntax [`MessageSchema`] reads: no default
+/// values, no string or array bounds, `pkg/Type` names
+pub fn ros1_syntax(definition: &str) -> String {
+    let mut out = String::new();
+    for line in definition.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if let Some(name) = line.strip_prefix("MSG:") {
+            out += &format!("MSG: {}\n", ros1_type(name.trim()));
+            continue;
+        }
+        let Some((ty, rest)) = line.split_once(char::is_whitespace) else {
+            out += line;
+            out.push('\n');
+            continue;
+        };
+        let mut ty = ros1_type(ty);
+        if let Some(bounded) = ty.find("[<=") {
+            ty = format!("{}[]", &ty[..bounded]);
+        }
+        // string<=10, string<=10[3]
+        if let Some(bound) = ty.find("<=") {
+            let array = ty[bound..].find('[').map_or("", |i| &ty[bound + i..]);
+            ty = format!("{}{}", &ty[..bound], array);
+        }
+        let rest = rest.trim();
+        // Constants keep their value; fields drop their default
+        let rest = if rest.contains('=') { rest } else { rest.split_whitespace().next().unwrap_or_default() };
+        out += &format!("{} {}\n", ty, rest);
+    }
+    out
+}
+
+/// Fields of the ROS1 types whose ROS2 messages are re-encoded with their
+/// standard definition, so their md5sum matches the hand-written parsers
+const ROS1_DEFINITIONS: &[(&str, &str)] = &[
+    ("std_msgs/Header", "uint32 seq\ntime stamp\nstring frame_id\n"),
+    ("std_msgs/String", "string data\n"),
+    ("rosgraph_msgs/Clock", "time clock\n"),
+    (
+        "sensor_msgs/Image",
+        "Header header\nuint32 height\nuint32 width\nstring encoding\nuint8 is_bigendian\nuint32 step\nuint8[] data\n",
+    ),
+    ("sensor_msgs/CompressedImage", "Header header\nstring format\nuint8[] data\n"),
+    (
+        "sensor_msgs/CameraInfo",
+        "Header header\nuint32 height\nuint32 width\nstring distortion_model\nfloat64[] D\nfloat64[9] K\n\
+         float64[9] R\nfloat64[12] P\nuint32 binning_x\nuint32 binning_y\nsensor_msgs/RegionOfInterest roi\n",
+    ),
+    (
+        "sensor_msgs/RegionOfInterest",
+        "uint32 x_offset\nuint32 y_offset\nuint32 height\nuint32 width\nbool do_rectify\n",
+    ),
+    (
+        "sensor_msgs/PointCloud2",
+        "Header header\nuint32 height\nuint32 width\nsensor_msgs/PointField[] fields\nbool is_bigendian\n\
+         uint32 point_step\nuint32 row_step\nuint8[] data\nbool is_dense\n",
+    ),
+    ("sensor_msgs/PointField", "string name\nuint32 offset\nuint8 datatype\nuint32 count\n"),
+    (
+        "sensor_msgs/LaserScan",
+        "Header header\nfloat32 angle_min\nfloat32 angle_max\nfloat32 angle_increment\nfloat32 time_increment\n\
+         float32 scan_time\nfloat32 range_min\nfloat32 range_max\nfloat32[] ranges\nfloat32[] intensities\n",
+    ),
+    (
+        "sensor_msgs/NavSatFix",
+        "Header header\nsensor_msgs/NavSatStatus status\nfloat64 latitude\nfloat64 longitude\nfloat64 altitude\n\
+         float64[9] position_covariance\nuint8 position_covariance_type\n",
+    ),
+    ("sensor_msgs/NavSatStatus", "int8 status\nuint16 service\n"),
+    (
+        "sensor_msgs/Imu",
+        "Header header\ngeometry_msgs/Quaternion orientation\nfloat64[9] orientation_covariance\n\
+         geometry_msgs/Vector3 angular_velocity\nfloat64[9] angular_velocity_covariance\n\
+         geometry_msgs/Vector3 linear_acceleration\nfloat64[9] linear_acceleration_covariance\n",
+    ),
+    ("tf2_msgs/TFMessage", "geometry_msgs/TransformStamped[] transforms\n"),
+    (
+        "geometry_msgs/TransformStamped",
+        "Header header\nstring child_frame_id\ngeometry_msgs/Transform transform\n",
+    ),
+    ("geometry_msgs/Transform", "geometry_msgs/Vector3 translation\ngeometry_msgs/Quaternion rotation\n"),
+    ("geometry_msgs/Vector3", "float64 x\nfloat64 y\nfloat64 z\n"),
+    ("geometry_msgs/Point", "float64 x\nfloat64 y\nfloat64 z\n"),
+    ("geometry_msgs/Quaternion", "float64 x\nfloat64 y\nfloat64 z\nfloat64 w\n"),
+    ("geometry_msgs/Pose", "geometry_msgs/Point position\ngeometry_msgs/Quaternion orientation\n"),
+    ("geometry_msgs/PoseStamped", "Header header\ngeometry_msgs/Pose pose\n"),
+    ("geometry_msgs/PoseWithCovariance", "geometry_msgs/Pose pose\nfloat64[36] covariance\n"),
+    ("geometry_msgs/Twist", "geometry_msgs/Vector3 linear\ngeometry_msgs/Vector3 angular\n"),
+    ("geometry_msgs/TwistWithCovariance", "geometry_msgs/Twist twist\nfloat64[36] covariance\n"),
+    (
+        "nav_msgs/Odometry",
+        "Header header\nstring child_frame_id\ngeometry_msgs/PoseWithCovariance pose\n\
+         geometry_msgs/TwistWithCovariance twist\n",
+    ),
+    ("nav_msgs/Path", "Header header\ngeometry_msgs/PoseStamped[] poses\n"),
+];
+
+/// Standard ROS1 definition of `tp` followed by the types it nests, as ROS1 bags record it
+pub fn builtin_definition(tp: &str) -> Option<String> {
+    let fields = |tp: &str| ROS1_DEFINITIONS.iter().find(|(name, _)| *name == tp).map(|(_, fields)| *fields);
+    let mut definition = fields(tp)?.to_string();
+    let mut seen = vec![tp.to_string()];
+    let mut pending = vec![tp.to_string()];
+    while let Some(current) = pending.pop() {
+        for line in fields(&current).unwrap_or_default().lines() {
+            let base = line.split(['[', ' ']).next().unwrap_or_default();
+            let nested = if base == "Header" { "std_msgs/Header" } else { base };
+            if nested.contains('/') && !seen.iter().any(|name| name == nested) {
+                definition += &format!("{}\nMSG: {}\n{}", "=".repeat(80), nested, fields(nested)?);
+                seen.push(nested.to_string());
+                pending.push(nested.to_string());
+            }
+        }
+    }
+    Some(definition)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ros1_syntax() {
+        let definition = "int32 count 5  # defaulted\nstring<=8 name\nfloat64[<=3] values\nuint8 OK=0\n\
+                          builtin_interfaces/msg/Time stamp\n===\nMSG: builtin_interfaces/Time\nint32 sec\n";
+        assert_eq!(
+            ros1_syntax(definition),
+            "int32 count\nstring name\nfloat64[] values\nuint8 OK=0\nbuiltin_interfaces/Time stamp\n===\n\
+             MSG: builtin_interfaces/Time\nint32 sec\n"
+        );
+        assert_eq!(ros1_type("sensor_msgs/msg/Imu"), "sensor_msgs/Imu");
+        let odometry = builtin_definition("nav_msgs/Odometry").unwrap();
+        for nested in ["std_msgs/Header", "geometry_msgs/PoseWithCovariance", "geometry_msgs/Vector3"] {
+            assert_eq!(odometry.matches(&format!("MSG: {nested}\n")).count(), 1, "{nested}");
+        }
+        assert!(builtin_definition("acme_msgs/Battery").is_none());
+    }
+}
@@ -0,0 +1,497 @@
+//! MCAP files as a [`RecordingSource`]
+//!
+//! Messages are visited in log-time order: chunks are decompressed (lz4 or zstd)
+//! by the log time of their first message, their messages held until no chunk
+//! left can hold an earlier one. The summary section, when the writer added one, gives the channels and
+//! time range without reading the data. `ros1` messages are kept as they are,
+//! `cdr` (ROS2) and `json` messages of ROS types re-encoded; other encodings
+//! (protobuf, flatbuffer) are skipped.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::io::Read;
+use std::path::PathBuf;
+
+use super::definitions::ros1_type;
+use super::rosbag2::transient_local;
+use super::{RecordingSource, SourceMessage, SourceTopic, TopicTable};
+
+/// First (and last) bytes of an MCAP file
+pub const MAGIC: &[u8] = b"\x89MCAP0\r\n";
+
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_CHUNK: u8 = 0x06;
+const OP_STATISTICS: u8 = 0x0B;
+const OP_DATA_END: u8 = 0x0F;
+
+/// One or more MCAP files read as one source, e.g. the parts of a rosbag2 with
+/// mcap storage
+pub struct McapFile {
+    files: Vec<memmap2::Mmap>,
+    table: TopicTable,
+    /// (file, channel id) → shared topic id; `None` when skipped
+    ids: HashMap<(usize, u16), Option<u32>>,
+    time_range: Option<(u64, u64)>,
+}
+
+/// Log time, read order, topic id and ROS1 payload of a message not visited yet
+type PendingMessage = (u64, usize, u32, Vec<u8>);
+
+struct Schema {
+    name: String,
+    encoding: String,
+    data: Vec<u8>,
+}
+
+struct Channel {
+    schema_id: u16,
+    topic: String,
+    encoding: String,
+    metadata: HashMap<String, String>,
+}
+
+impl McapFile {
+    pub fn open(paths: Vec<PathBuf>) -> Result<Self> {
+        let mut source = Self { files: Vec::new(), table: TopicTable::default(), ids: HashMap::new(), time_range: None };
+        for (index, path) in paths.iter().enumerate() {
+            let file = std::fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+            // SAFETY: the file is opened read-only and only read while mapped; this assumes
+            // no other process truncates it during the conversion
+            let data = unsafe { memmap2::Mmap::map(&file) }.with_context(|| format!("failed to map {}", path.display()))?;
+            if !data.starts_with(MAGIC) {
+                bail!("{} is not an MCAP file", path.display());
+            }
+            let mut schemas: HashMap<u16, Schema> = HashMap::new();
+            let mut channels: BTreeMap<u16, Channel> = BTreeMap::new();
+            let mut time_range: Option<(u64, u64)> = None;
+            let mut collect = |op: u8, body: &[u8]| -> Result<()> {
+                let mut fields = Fields::new(body);
+                match op {
+                    OP_SCHEMA => {
+                        let id = fields.u16()?;
+                        let schema = Schema {
+                            name: fields.string()?.to_string(),
+                            encoding: fields.string()?.to_string(),
+                            data: fields.bytes()?.to_vec(),
+                        };
+                        schemas.entry(id).or_insert(schema);
+                    }
+                    OP_CHANNEL => {
+                        let id = fields.u16()?;
+                        let channel = Channel {
+                            schema_id: fields.u16()?,
+                            topic: fields.string()?.to_string(),
+                            encoding: fields.string()?.to_string(),
+                            metadata: fields.map()?,
+                        };
+                        channels.entry(id).or_insert(channel);
+                    }
+                    OP_MESSAGE => {
+                        fields.take(2 + 4)?;
+                        let time = fields.u64()?;
+                        time_range = Some(time_range.map_or((time, time), |(start, end)| (start.min(time), end.max(time))));
+                    }
+                    OP_STATISTICS => {
+                        fields.take(8 + 2 + 4 * 4)?;
+                        let (start, end) = (fields.u64()?, fields.u64()?);
+                        time_range = (start <= end).then_some((start, end));
+                    }
+                    _ => {}
+                }
+                Ok(())
+            };
+            match summary(&data)? {
+                Some(summary) => visit_records(summary, &mut collect)?,
+                // Without a summary the channels are wherever the writer put them
+                None => visit_records(&data[MAGIC.len()..], &mut collect)?,
+            }
+            if let Some((first, last)) = time_range {
+                source.time_range =
+                    Some(source.time_range.map_or((first, last), |(start, end)| (start.min(first), end.max(last))));
+            }
+            for (id, channel) in channels {
+                let topic = source.register(&channel, schemas.get(&channel.schema_id));
+                source.ids.insert((index, id), topic);
+            }
+            source.files.push(data);
+        }
+        Ok(source)
+    }
+
+    fn register(&mut self, channel: &Channel, schema: Option<&Schema>) -> Option<u32> {
+        let Some(schema) = schema else {
+            tracing::warn!("{}: no schema; its messages are skipped", channel.topic);
+            return None;
+        };
+        let tp = ros1_type(&schema.name);
+        // ros2idl schemas are not read; the common types do not need them
+        let recorded = matches!(schema.encoding.as_str(), "ros1msg" | "ros2msg")
+            .then(|| String::from_utf8_lossy(&schema.data).into_owned());
+        let latching = channel.metadata.get("latching").is_some_and(|v| v == "1")
+            || channel.metadata.get("offered_qos_profiles").is_some_and(|qos| transient_local(qos));
+        self.table.insert(
+            &channel.topic,
+            &tp,
+            &channel.encoding,
+            recorded.as_deref(),
+            channel.metadata.get("md5sum").map(String::as_str),
+            latching,
+        )
+    }
+}
+
+impl RecordingSource for McapFile {
+    fn topics(&self) -> &[SourceTopic] {
+        &self.table.topics
+    }
+
+    fn time_range(&self) -> Option<(u64, u64)> {
+        self.time_range
+    }
+
+    fn messages(&mut self, visit: &mut dyn FnMut(SourceMessage<'_>) -> Result<()>) -> Result<()> {
+        // Chunks of every file, and messages outside chunks, by the log time of their
+        // first message; MCAP writers need not keep records in log-time order
+        let mut blocks: Vec<(u64, usize, u8, &[u8])> = Vec::new();
+        for (index, data) in self.files.iter().enumerate() {
+            for (op, body) in records(&data[MAGIC.len()..])? {
+                let mut fields = Fields::new(body);
+                match op {
+                    // message_start_time
+                    OP_CHUNK => blocks.push((fields.u64()?, index, op, body)),
+                    OP_MESSAGE => {
+                        fields.take(2 + 4)?;
+                        blocks.push((fields.u64()?, index, op, body));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        blocks.sort_by_key(|(start, ..)| *start);
+
+        // Messages read but not visited yet, until no block left can hold an earlier one;
+        // ties keep the file order
+        let mut pending: BinaryHeap<Reverse<PendingMessage>> = BinaryHeap::new();
+        let mut read_count = 0;
+        let (table, ids) = (&mut self.table, &self.ids);
+        for (start, index, op, body) in blocks {
+            while let Some(Reverse((time_ns, ..))) = pending.peek()
+                && *time_ns < start
+            {
+                let Reverse((time_ns, _, topic, data)) = pending.pop().unwrap();
+                visit(SourceMessage { topic, time_ns, data: &data })?;
+            }
+            let mut read = |op: u8, body: &[u8]| -> Result<()> {
+                if op != OP_MESSAGE {
+                    return Ok(());
+                }
+                let mut fields = Fields::new(body);
+                let channel = fields.u16()?;
+                fields.take(4)?;
+                let time_ns = fields.u64()?;
+                fields.take(8)?;
+                let id = ids.get(&(index, channel)).copied().flatten();
+                if let Some(data) = table.decode(id, fields.rest()) {
+                    pending.push(Reverse((time_ns, read_count, id.unwrap_or_default(), data.into_owned())));
+                    read_count += 1;
+                }
+                Ok(())
+            };
+            match op {
+                OP_CHUNK => visit_records(&chunk_records(body)?, &mut read)?,
+                _ => read(op, body)?,
+            }
+        }
+        while let Some(Reverse((time_ns, _, topic, data))) = pending.pop() {
+            visit(SourceMessage { topic, time_ns, data: &data })?;
+        }
+        Ok(())
+    }
+
+    fn skipped(&self) -> u64 {
+        self.table.skipped
+    }
+}
+
+/// Records of the summary section, located by the footer; `None` when there is none
+fn summary(data: &[u8]) -> Result<Option<&[u8]>> {
+    // Footer record: opcode, length, summary_start, summary_offset_start, summary_crc
+    let footer_len = 1 + 8 + 8 + 8 + 4;
+    let Some(footer_pos) = data.len().checked_sub(MAGIC.len() + footer_len) else {
+        return Ok(None);
+    };
+    if !data.ends_with(MAGIC) || data[footer_pos] != OP_FOOTER {
+        tracing::warn!("MCAP file without a footer (truncated recording?); reading it to the end");
+        return Ok(None);
+    }
+    let summary_start = Fields::new(&data[footer_pos + 9..]).u64()? as usize;
+    if summary_start == 0 {
+        return Ok(None);
+    }
+    data.get(summary_start..footer_pos)
+        .map(Some)
+        .ok_or_else(|| anyhow!("MCAP summary offset {} is out of the file", summary_start))
+}
+
+/// Records of `data` as (opcode, body), up to the end of the data section
+fn records(data: &[u8]) -> Result<Vec<(u8, &[u8])>> {
+    let mut fields = Fields::new(data);
+    let mut records = Vec::new();
+    while !fields.rest().is_empty() {
+        let op = fields.take(1)?[0];
+        let len = fields.u64()?;
+        let body = fields.take(usize::try_from(len)?)?;
+        if matches!(op, OP_DATA_END | OP_FOOTER) {
+            break;
+        }
+        records.push((op, body));
+    }
+    Ok(records)
+}
+
+/// Pass the records of `data` to `visit`, those of chunks included, up to the
+/// end of the data section
+fn visit_records(data: &[u8], visit: &mut dyn FnMut(u8, &[u8]) -> Result<()>) -> Result<()> {
+    for (op, body) in records(data)? {
+        match op {
+            OP_CHUNK => visit_records(&chunk_records(body)?, visit)?,
+            _ => visit(op, body)?,
+        }
+    }
+    Ok(())
+}
+
+/// Decompressed records of a chunk record body
+fn chunk_records(body: &[u8]) -> Result<Cow<'_, [u8]>> {
+    let mut chunk = Fields::new(body);
+    chunk.take(8 + 8)?;
+    let size = chunk.u64()?;
+    chunk.take(4)?;
+    let compression = chunk.string()?;
+    let records_len = chunk.u64()?;
+    let records = chunk.take(usize::try_from(records_len)?)?;
+    decompress(compression, records, size)
+}
+
+fn decompress<'a>(compression: &str, data: &'a [u8], size: u64) -> Result<Cow<'a, [u8]>> {
+    Ok(match compression {
+        "" => Cow::Borrowed(data),
+        "lz4" => {
+            // LZ4 expands data at most 255 times, whatever size the chunk claims
+            let mut out = Vec::with_capacity(size.min(data.len() as u64 * 255) as usize);
+            lz4::Decoder::new(data)?.read_to_end(&mut out)?;
+            Cow::Owned(out)
+        }
+        "zstd" => Cow::Owned(zstd::decode_all(data)?),
+        other => bail!("unsupported MCAP chunk compression '{}'", other),
+    })
+}
+
+/// Little-endian fields of a record body
+struct Fields<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| anyhow!("MCAP record too short: {} bytes needed at offset {}", len, self.pos))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<&'a str> {
+        std::str::from_utf8(self.bytes()?).context("MCAP string is not UTF-8")
+    }
+
+    fn map(&mut self) -> Result<HashMap<String, String>> {
+        let mut entries = Fields::new(self.bytes()?);
+        let mut map = HashMap::new();
+        while !entries.rest().is_empty() {
+            map.insert(entries.string()?.to_string(), entries.string()?.to_string());
+        }
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::ros1::Ros1Bag;
+    use crate::source::{stage_as_ros1, StageStats};
+
+    fn record(op: u8, body: &[u8]) -> Vec<u8> {
+        [&[op][..], &(body.len() as u64).to_le_bytes(), body].concat()
+    }
+
+    fn string(s: &str) -> Vec<u8> {
+        [&(s.len() as u32).to_le_bytes()[..], s.as_bytes()].concat()
+    }
+
+    fn message(channel: u16, time_ns: u64, data: &[u8]) -> Vec<u8> {
+        let body = [&channel.to_le_bytes()[..], &0u32.to_le_bytes(), &time_ns.to_le_bytes(), &time_ns.to_le_bytes(), data];
+        record(OP_MESSAGE, &body.concat())
+    }
+
+    /// Two channels (ros1 and protobuf) with messages inside and outside a chunk
+    fn write_mcap(with_summary: bool) -> Vec<u8> {
+        let schema = record(
+            OP_SCHEMA,
+            &[&1u16.to_le_bytes()[..], &string("std_msgs/String"), &string("ros1msg"), &string("string data\n")].concat(),
+        );
+        let channel = |id: u16, topic: &str, encoding: &str| {
+            record(
+                OP_CHANNEL,
+                &[&id.to_le_bytes()[..], &1u16.to_le_bytes(), &string(topic), &string(encoding), &0u32.to_le_bytes()]
+                    .concat(),
+            )
+        };
+        let chunk_records = [message(1, 2_000_000_000, &string("a")), message(2, 2_100_000_000, b"{}")].concat();
+        let chunk = record(
+            OP_CHUNK,
+            &[
+                &2_000_000_000u64.to_le_bytes()[..],
+                &2_100_000_000u64.to_le_bytes(),
+                &(chunk_records.len() as u64).to_le_bytes(),
+                &0u32.to_le_bytes(),
+                &string(""),
+                &(chunk_records.len() as u64).to_le_bytes(),
+                &chunk_records,
+            ]
+            .concat(),
+        );
+        let mut file = MAGIC.to_vec();
+        file.extend(record(0x01, &[string("ros1"), string("test")].concat()));
+        file.extend(&schema);
+        file.extend(channel(1, "/status", "ros1"));
+        file.extend(channel(2, "/proto", "protobuf"));
+        file.extend(chunk);
+        file.extend(message(1, 2_200_000_000, &string("b")));
+        file.extend(record(OP_DATA_END, &0u32.to_le_bytes()));
+        let mut summary_start = 0u64;
+        if with_summary {
+            summary_start = file.len() as u64;
+            file.extend(&schema);
+            file.extend(channel(1, "/status", "ros1"));
+            file.extend(channel(2, "/proto", "protobuf"));
+            let statistics = [
+                &3u64.to_le_bytes()[..],
+                &1u16.to_le_bytes(),
+                &[0; 4 * 4],
+                &2_000_000_000u64.to_le_bytes(),
+                &2_200_000_000u64.to_le_bytes(),
+                &0u32.to_le_bytes(),
+            ];
+            file.extend(record(OP_STATISTICS, &statistics.concat()));
+        }
+        file.extend(record(OP_FOOTER, &[&summary_start.to_le_bytes()[..], &0u64.to_le_bytes(), &0u32.to_le_bytes()].concat()));
+        file.extend(MAGIC);
+        file
+    }
+
+    #[test]
+    fn test_mcap_source_stages_ros1_messages() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_mcap_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for with_summary in [false, true] {
+            let path = dir.join("run.mcap");
+            std::fs::write(&path, write_mcap(with_summary)).unwrap();
+            let mut source = McapFile::open(vec![path]).unwrap();
+            let topics: Vec<(&str, &str)> =
+                source.topics().iter().map(|topic| (topic.name.as_str(), topic.tp.as_str())).collect();
+            assert_eq!(topics, [("/status", "std_msgs/String")]);
+            assert_eq!(source.time_range(), Some((2_000_000_000, 2_200_000_000)));
+
+            let staged = dir.join("staged.bag");
+            let stats = stage_as_ros1(&mut source, &staged).unwrap();
+            assert_eq!(stats, StageStats { messages: 2, skipped: 1 });
+
+            let mut bag = Ros1Bag::open(&staged).unwrap();
+            assert_eq!(bag.topics()[0].definition, "string data\n");
+            assert_eq!(bag.time_range(), Some((2_000_000_000, 2_200_000_000)));
+            let mut messages = Vec::new();
+            bag.messages(&mut |msg| {
+                messages.push((msg.time_ns, msg.data.to_vec()));
+                Ok(())
+            })
+            .unwrap();
+            assert_eq!(messages, [(2_000_000_000, string("a")), (2_200_000_000, string("b"))]);
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_mcap_messages_are_visited_in_log_time_order() {
+        let schema = [&1u16.to_le_bytes()[..], &string("std_msgs/String"), &string("ros1msg"), &string("string data\n")];
+        let channel = [&1u16.to_le_bytes()[..], &1u16.to_le_bytes(), &string("/status"), &string("ros1"), &0u32.to_le_bytes()];
+        // The chunk holds its messages out of order, and a message after it is older than its last one
+        let chunk_records = [message(1, 2_000_000_000, &string("a")), message(1, 1_500_000_000, &string("b"))].concat();
+        let chunk = [
+            &1_500_000_000u64.to_le_bytes()[..],
+            &2_000_000_000u64.to_le_bytes(),
+            &(chunk_records.len() as u64).to_le_bytes(),
+            &0u32.to_le_bytes(),
+            &string(""),
+            &(chunk_records.len() as u64).to_le_bytes(),
+            &chunk_records,
+        ];
+        let mut file = MAGIC.to_vec();
+        file.extend(record(OP_SCHEMA, &schema.concat()));
+        file.extend(record(OP_CHANNEL, &channel.concat()));
+        file.extend(record(OP_CHUNK, &chunk.concat()));
+        file.extend(message(1, 1_800_000_000, &string("c")));
+        file.extend(record(OP_DATA_END, &0u32.to_le_bytes()));
+        file.extend(record(OP_FOOTER, &[0u8; 8 + 8 + 4]));
+        file.extend(MAGIC);
+        let path = std::env::temp_dir().join(format!("bag2rrd_mcap_order_{}.mcap", std::process::id()));
+        std::fs::write(&path, file).unwrap();
+
+        let mut source = McapFile::open(vec![path.clone()]).unwrap();
+        let mut messages = Vec::new();
+        source
+            .messages(&mut |msg| {
+                messages.push((msg.time_ns, msg.data.to_vec()));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            messages,
+            [(1_500_000_000, string("b")), (1_800_000_000, string("c")), (2_000_000_000, string("a"))]
+        );
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,374 @@
+//! Recorded message logs the converter reads: ROS1 bags, ROS2 bags and MCAP files
+//!
+//! [`InputFormat::detect`] recognizes an input by its first bytes, or a rosbag2
+//! directory by its metadata.yaml, and [`open_source`] opens it as a
+//! [`RecordingSource`]. ROS1 bags are converted straight from their chunks; the
+//! other sources are staged as a ROS1 bag first, their messages re-encoded in
+//! the ROS1 layout of their type, so every mapper, filter and output option
+//! applies unchanged. A new format only needs a [`RecordingSource`].
+//!
+//! Running systems are [`LiveSource`]s: a rosbridge server, or a ROS1 master
+//! with the `ros1-live` feature. Their messages are mapped as they arrive.
+
+pub(crate) mod bag_writer;
+pub mod definitions;
+pub mod mcap;
+pub mod ros1;
+pub mod rosbag2;
+pub mod rosbridge;
+#[cfg(feature = "ros1-live")]
+pub mod tcpros;
+
+use anyhow::{bail, Context, Result};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::ros_msg::{known_md5sum, MessageSchema};
+
+/// A topic of a source, with its ROS1 type and definition
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceTopic {
+    /// Id the source's messages refer to
+    pub id: u32,
+    pub name: String,
+    /// ROS1 type name, `pkg/Type`
+    pub tp: String,
+    /// ROS1 message definition, with the types it nests
+    pub definition: String,
+    /// Lowercase hex
+    pub md5sum: String,
+    pub latching: bool,
+}
+
+/// A message of a source, as a ROS1 payload
+#[derive(Clone, Copy, Debug)]
+pub struct SourceMessage<'a> {
+    pub topic: u32,
+    /// Record time, in nanoseconds since the epoch
+    pub time_ns: u64,
+    pub data: &'a [u8],
+}
+
+/// A log of ROS messages the converter can read
+pub trait RecordingSource {
+    /// Topics whose messages the source yields
+    fn topics(&self) -> &[SourceTopic];
+
+    /// Record times of the first and last message, in nanoseconds since the
+    /// epoch, when known without reading the messages
+    fn time_range(&self) -> Option<(u64, u64)>;
+
+    /// Pass every message to `visit`, in record order within each storage file
+    fn messages(&mut self, visit: &mut dyn FnMut(SourceMessage<'_>) -> Result<()>) -> Result<()>;
+
+    /// Messages left out so far: without a definition, in an unsupported
+    /// encoding or that did not decode
+    fn skipped(&self) -> u64 {
+        0
+    }
+}
+
+/// A message received from a live source, as a ROS1 payload
+#[derive(Clone, Debug, PartialEq)]
+pub struct LiveMessage {
+    pub topic: u32,
+    /// Receive time, in nanoseconds since the epoch
+    pub time_ns: u64,
+    pub data: Vec<u8>,
+}
+
+/// A running ROS system the converter subscribes to
+pub trait LiveSource {
+    /// Topics currently published, with their type as the system reports it
+    /// (`pkg/Type` or ROS2 `pkg/msg/Type`)
+    fn list_topics(&mut self) -> Result<Vec<(String, String)>>;
+
+    /// Subscribe to topic `name` of type `ros_type`; `None` when its messages
+    /// cannot be decoded
+    fn subscribe(&mut self, name: &str, ros_type: &str) -> Result<Option<SourceTopic>>;
+
+    /// Next message of a subscribed topic; `None` when none arrived within the read
+    /// timeout or the message was skipped
+    fn next_message(&mut self) -> Result<Option<LiveMessage>>;
+
+    /// Messages left out so far: of topics without a definition or that did not decode
+    fn skipped(&self) -> u64 {
+        0
+    }
+}
+
+/// Whether a convert input names a running ROS system, a rosbridge server or a
+/// ROS1 master, rather than a file
+pub fn is_live_input(input: &str) -> bool {
+    rosbridge::is_rosbridge_url(input) || input.starts_with("ros1://")
+}
+
+/// Now, in nanoseconds since the epoch
+fn now_ns() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+/// Input formats recognized by [`InputFormat::detect`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputFormat {
+    Ros1,
+    /// A rosbag2 directory or one of its sqlite3 .db3 files
+    Rosbag2,
+    Mcap,
+}
+
+impl InputFormat {
+    /// Format of the input at `path` from its first bytes; `None` when not recognized
+    pub fn detect(path: &Path) -> Result<Option<Self>> {
+        if path.is_dir() {
+            return Ok(path.join("metadata.yaml").is_file().then_some(Self::Rosbag2));
+        }
+        let mut magic = Vec::new();
+        std::fs::File::open(path)
+            .with_context(|| format!("failed to open {}", path.display()))?
+            .take(16)
+            .read_to_end(&mut magic)?;
+        Ok(if magic.starts_with(b"#ROSBAG V2.0") {
+            Some(Self::Ros1)
+        } else if magic.starts_with(mcap::MAGIC) {
+            Some(Self::Mcap)
+        } else if magic.starts_with(b"SQLite format 3\0") {
+            Some(Self::Rosbag2)
+        } else {
+            None
+        })
+    }
+}
+
+/// Open the input at `path` as the source of its detected format
+pub fn open_source(path: &Path) -> Result<Box<dyn RecordingSource>> {
+    Ok(match InputFormat::detect(path)? {
+        Some(InputFormat::Ros1) => Box::new(ros1::Ros1Bag::open(path)?),
+        Some(InputFormat::Rosbag2) => rosbag2::open(path)?,
+        Some(InputFormat::Mcap) => Box::new(mcap::McapFile::open(vec![path.to_path_buf()])?),
+        None => bail!("{}: not a ROS1 bag, ROS2 bag or MCAP file", path.display()),
+    })
+}
+
+/// Messages written to a staged bag and messages left out
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StageStats {
+    pub messages: u64,
+    pub skipped: u64,
+}
+
+/// Write the messages of `source` to a ROS1 bag at `dest`
+pub fn stage_as_ros1(source: &mut dyn RecordingSource, dest: &Path) -> Result<StageStats> {
+    let mut writer = bag_writer::BagWriter::create(dest)?;
+    let conns: HashMap<u32, u32> = source.topics().iter().map(|topic| (topic.id, writer.connection(topic))).collect();
+    let mut messages = 0;
+    source.messages(&mut |msg| {
+        let Some(conn) = conns.get(&msg.topic) else {
+            return Ok(());
+        };
+        messages += 1;
+        writer.write(*conn, msg.time_ns, msg.data)
+    })?;
+    writer.finish()?;
+    Ok(StageStats { messages, skipped: source.skipped() })
+}
+
+/// Inputs of a conversion with their ROS2 bags and MCAP files staged as ROS1 bags,
+/// and their bags without an index section reindexed; staged files are removed on drop
+#[derive(Debug, Default)]
+pub struct StagedInputs {
+    /// Inputs to read, staged ones replaced by their ROS1 bag
+    pub paths: Vec<String>,
+    staged: Vec<PathBuf>,
+    /// Input each staged ROS1 bag was made from
+    origins: HashMap<String, String>,
+}
+
+impl StagedInputs {
+    /// Stage the inputs that are not ROS1 bags in the temporary directory; globs
+    /// and directories of bags are left to expand
+    pub fn new(inputs: &[String]) -> Result<Self> {
+        let mut staged_inputs = Self::default();
+        for input in inputs {
+            let path = Path::new(input);
+            let format = if path.exists() { InputFormat::detect(path)? } else { None };
+            if !matches!(format, Some(InputFormat::Rosbag2 | InputFormat::Mcap)) {
+                staged_inputs.paths.push(input.clone());
+                continue;
+            }
+            let dest = staged_inputs.next_dest();
+            let stats = stage_as_ros1(&mut *open_source(path)?, &dest)?;
+            tracing::info!("Staged {} ({} messages, {} skipped)", input, stats.messages, stats.skipped);
+            let dest = dest.to_string_lossy().into_owned();
+            staged_inputs.origins.insert(dest.clone(), input.clone());
+            staged_inputs.paths.push(dest);
+        }
+        Ok(staged_inputs)
+    }
+
+    /// Stage a reindexed copy of the ROS1 bag at `path`, for bags without an index section
+    pub fn stage_reindexed(&mut self, path: &str) -> Result<(String, crate::diagnose::RepairSummary)> {
+        let dest = self.next_dest().to_string_lossy().into_owned();
+        let summary = crate::diagnose::repair_bag(path, &dest)?;
+        self.origins.insert(dest.clone(), self.origin(path).to_string());
+        Ok((dest, summary))
+    }
+
+    /// Input given for `path`: the ROS2 bag or MCAP file a staged bag was made from,
+    /// else `path` itself
+    pub fn origin<'a>(&'a self, path: &'a str) -> &'a str {
+        self.origins.get(path).map_or(path, String::as_str)
+    }
+
+    /// Temporary file for the next staged bag, removed with the inputs
+    fn next_dest(&mut self) -> PathBuf {
+        let dest = std::env::temp_dir().join(format!("bag2rrd_staged_{}_{}.bag", std::process::id(), self.staged.len()));
+        self.staged.push(dest.clone());
+        dest
+    }
+}
+
+impl Drop for StagedInputs {
+    fn drop(&mut self) {
+        for path in &self.staged {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
+
+/// How the payloads of a topic become ROS1 payloads
+#[derive(Debug)]
+enum Decoder {
+    Ros1,
+    Cdr(MessageSchema),
+    /// rosbridge JSON messages
+    Json(MessageSchema),
+}
+
+/// Topics of a source under shared ids, with the decoders of their payloads
+#[derive(Debug, Default)]
+struct TopicTable {
+    topics: Vec<SourceTopic>,
+    decoders: Vec<Decoder>,
+    ids: HashMap<(String, String), u32>,
+    skipped: u64,
+    /// Topics whose undecodable messages were reported
+    reported: HashSet<u32>,
+}
+
+impl TopicTable {
+    /// Shared id of topic `name`, added on first use; `None` when its messages are
+    /// skipped. `recorded` is the definition stored with the messages: ROS1 text for
+    /// the `ros1` encoding, ros2msg text for `cdr` and `json`
+    fn insert(
+        &mut self,
+        name: &str,
+        tp: &str,
+        encoding: &str,
+        recorded: Option<&str>,
+        md5sum: Option<&str>,
+        latching: bool,
+    ) -> Option<u32> {
+        if let Some(id) = self.ids.get(&(name.to_string(), tp.to_string())) {
+            return Some(*id);
+        }
+        let reencoded = match encoding {
+            "ros1" => false,
+            "cdr" | "json" => true,
+            _ => {
+                tracing::warn!("{}: {} encoding is not supported; its messages are skipped", name, encoding);
+                return None;
+            }
+        };
+        let definition = if reencoded {
+            definitions::builtin_definition(tp).or_else(|| recorded.map(definitions::ros1_syntax))
+        } else {
+            recorded.map(str::to_string)
+        };
+        let Some(definition) = definition.filter(|d| !d.trim().is_empty()) else {
+            tracing::warn!("No message definition for {} ({}); its messages are skipped", tp, name);
+            return None;
+        };
+        let decoder = if reencoded {
+            match MessageSchema::parse(tp, &definition) {
+                Ok(schema) if encoding == "cdr" => Decoder::Cdr(schema),
+                Ok(schema) => Decoder::Json(schema),
+                Err(e) => {
+                    tracing::warn!("Cannot decode {} ({}): {:#}", tp, name, e);
+                    return None;
+                }
+            }
+        } else {
+            Decoder::Ros1
+        };
+        let id = self.topics.len() as u32;
+        self.topics.push(SourceTopic {
+            id,
+            name: name.to_string(),
+            tp: tp.to_string(),
+            definition,
+            // Re-encoded messages have the standard layout of their type
+            md5sum: md5sum.or(known_md5sum(tp)).unwrap_or("*").to_string(),
+            latching,
+        });
+        self.decoders.push(decoder);
+        self.ids.insert((name.to_string(), tp.to_string()), id);
+        Some(id)
+    }
+
+    /// ROS1 payload of a message of topic `id`; `None` when skipped
+    fn decode<'a>(&mut self, id: Option<u32>, payload: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        let Some(id) = id else {
+            self.skipped += 1;
+            return None;
+        };
+        let reencoded = match &self.decoders[id as usize] {
+            Decoder::Ros1 => return Some(Cow::Borrowed(payload)),
+            Decoder::Cdr(schema) => schema.cdr_to_ros1(payload),
+            Decoder::Json(schema) => serde_json::from_slice(payload)
+                .map_err(anyhow::Error::from)
+                .and_then(|msg| schema.json_to_ros1(&msg)),
+        };
+        match reencoded {
+            Ok(ros1) => Some(Cow::Owned(ros1)),
+            Err(e) => {
+                if self.reported.insert(id) {
+                    tracing::warn!("Skipping {} messages that do not decode: {:#}", self.topics[id as usize].name, e);
+                }
+                self.skipped += 1;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_input_format() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_detect_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, data: &[u8]| {
+            let path = dir.join(name);
+            std::fs::write(&path, data).unwrap();
+            path
+        };
+        let detect = |path: &Path| InputFormat::detect(path).unwrap();
+        // The extension does not matter
+        assert_eq!(detect(&file("a.mcap", b"#ROSBAG V2.0\n")), Some(InputFormat::Ros1));
+        assert_eq!(detect(&file("b.bag", b"\x89MCAP0\r\n\x01")), Some(InputFormat::Mcap));
+        assert_eq!(detect(&file("c.db3", b"SQLite format 3\0...")), Some(InputFormat::Rosbag2));
+        assert_eq!(detect(&file("d.txt", b"notes")), None);
+        assert_eq!(detect(&dir), None);
+        file("metadata.yaml", b"rosbag2_bagfile_information: {}\n");
+        assert_eq!(detect(&dir), Some(InputFormat::Rosbag2));
+        assert!(open_source(&dir.join("d.txt")).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
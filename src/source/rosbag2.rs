@@ -0,0 +1,362 @@
+//! ROS2 bags: rosbag2 directories with sqlite3 (.db3) or MCAP storage
+//!
+//! Messages are CDR encoded. The common types are re-encoded with their standard
+//! ROS1 definition; other types with the definitions recorded by ROS2 Iron and
+//! later, and are skipped without one.
+
+use anyhow::{bail, Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::definitions::ros1_type;
+use super::mcap::McapFile;
+use super::{RecordingSource, SourceMessage, SourceTopic, TopicTable};
+
+/// Open a rosbag2 directory, or a lone .db3 file
+pub fn open(input: &Path) -> Result<Box<dyn RecordingSource>> {
+    let (storage, files) = storage_files(input)?;
+    Ok(match storage.as_str() {
+        "sqlite3" => Box::new(Rosbag2::open(files)?),
+        "mcap" => Box::new(McapFile::open(files)?),
+        _ => bail!("{}: {} storage is not supported, only sqlite3 and mcap", input.display(), storage),
+    })
+}
+
+/// sqlite3 storage files of a rosbag2
+pub struct Rosbag2 {
+    dbs: Vec<Connection>,
+    table: TopicTable,
+    /// (storage file, topic id in it) → shared topic id; `None` when skipped
+    ids: HashMap<(usize, i64), Option<u32>>,
+    time_range: Option<(u64, u64)>,
+}
+
+impl Rosbag2 {
+    /// Open the .db3 files of a bag, in recording order
+    pub fn open(files: Vec<PathBuf>) -> Result<Self> {
+        let mut table = TopicTable::default();
+        let mut ids = HashMap::new();
+        let mut time_range: Option<(u64, u64)> = None;
+        let mut dbs = Vec::new();
+        for (index, file) in files.iter().enumerate() {
+            let db = Connection::open_with_flags(file, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .with_context(|| format!("failed to open {}", file.display()))?;
+            let recorded = recorded_definitions(&db)?;
+            for TopicRow { id, name, ros2_type, format, latching } in read_topics(&db)? {
+                let tp = ros1_type(&ros2_type);
+                let shared = table.insert(&name, &tp, &format, recorded.get(&tp).map(String::as_str), None, latching);
+                ids.insert((index, id), shared);
+            }
+            let (first, last): (Option<i64>, Option<i64>) =
+                db.query_row("SELECT MIN(timestamp), MAX(timestamp) FROM messages", [], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?;
+            if let (Some(first), Some(last)) = (first, last) {
+                let (first, last) = (first.max(0) as u64, last.max(0) as u64);
+                time_range = Some(time_range.map_or((first, last), |(start, end)| (start.min(first), end.max(last))));
+            }
+            dbs.push(db);
+        }
+        Ok(Self { dbs, table, ids, time_range })
+    }
+}
+
+impl RecordingSource for Rosbag2 {
+    fn topics(&self) -> &[SourceTopic] {
+        &self.table.topics
+    }
+
+    fn time_range(&self) -> Option<(u64, u64)> {
+        self.time_range
+    }
+
+    fn messages(&mut self, visit: &mut dyn FnMut(SourceMessage<'_>) -> Result<()>) -> Result<()> {
+        for (index, db) in self.dbs.iter().enumerate() {
+            let mut query = db.prepare("SELECT topic_id, timestamp, data FROM messages ORDER BY timestamp")?;
+            let mut rows = query.query([])?;
+            while let Some(row) = rows.next()? {
+                let id = self.ids.get(&(index, row.get::<_, i64>(0)?)).copied().flatten();
+                let Some(data) = self.table.decode(id, row.get_ref(2)?.as_blob()?) else {
+                    continue;
+                };
+                let time_ns = row.get::<_, i64>(1)?.max(0) as u64;
+                visit(SourceMessage { topic: id.unwrap_or_default(), time_ns, data: &data })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn skipped(&self) -> u64 {
+        self.table.skipped
+    }
+}
+
+/// Storage identifier and files of a rosbag2 directory or a lone .db3 file, in
+/// recording order
+fn storage_files(input: &Path) -> Result<(String, Vec<PathBuf>)> {
+    if !input.is_dir() {
+        return Ok(("sqlite3".to_string(), vec![input.to_path_buf()]));
+    }
+    let metadata_path = input.join("metadata.yaml");
+    let text = std::fs::read_to_string(&metadata_path)
+        .with_context(|| format!("failed to read {}", metadata_path.display()))?;
+    let metadata: serde_yaml::Value =
+        serde_yaml::from_str(&text).with_context(|| format!("invalid {}", metadata_path.display()))?;
+    let info = &metadata["rosbag2_bagfile_information"];
+    let storage = info["storage_identifier"].as_str().unwrap_or("sqlite3").to_string();
+    let files: Vec<PathBuf> = info["relative_file_paths"]
+        .as_sequence()
+        .into_iter()
+        .flatten()
+        .filter_map(serde_yaml::Value::as_str)
+        .map(|file| {
+            // Early rosbag2 versions list the files under the bag directory name
+            let path = input.join(file);
+            match Path::new(file).file_name() {
+                Some(name) if !path.exists() => input.join(name),
+                _ => path,
+            }
+        })
+        .collect();
+    if files.is_empty() {
+        bail!("{} lists no storage files", metadata_path.display());
+    }
+    Ok((storage, files))
+}
+
+/// A row of a storage file's topics table
+struct TopicRow {
+    id: i64,
+    name: String,
+    ros2_type: String,
+    /// Serialization format, `cdr` for every bag recorded by ROS2 itself
+    format: String,
+    /// Transient local durability: the last message is kept for late subscribers
+    latching: bool,
+}
+
+/// Topics of a storage file
+fn read_topics(db: &Connection) -> Result<Vec<TopicRow>> {
+    // Bags recorded before Foxy have no QoS profiles
+    let with_qos = db.prepare("SELECT offered_qos_profiles FROM topics LIMIT 0").is_ok();
+    let sql = if with_qos {
+        "SELECT id, name, type, serialization_format, offered_qos_profiles FROM topics"
+    } else {
+        "SELECT id, name, type, serialization_format, '' FROM topics"
+    };
+    let mut query = db.prepare(sql)?;
+    let topics = query
+        .query_map([], |row| {
+            let qos: String = row.get(4)?;
+            Ok(TopicRow {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                ros2_type: row.get(2)?,
+                format: row.get(3)?,
+                latching: transient_local(&qos),
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(topics)
+}
+
+/// Whether a topic's offered QoS profiles (YAML) keep the last message for late subscribers
+pub(super) fn transient_local(qos: &str) -> bool {
+    let Ok(serde_yaml::Value::Sequence(profiles)) = serde_yaml::from_str(qos) else {
+        return false;
+    };
+    profiles.iter().any(|profile| match &profile["durability"] {
+        serde_yaml::Value::Number(n) => n.as_u64() == Some(1),
+        serde_yaml::Value::String(s) => s == "transient_local",
+        _ => false,
+    })
+}
+
+/// ros2msg definitions recorded in a storage file (Iron and later), by ROS1 type name
+fn recorded_definitions(db: &Connection) -> Result<HashMap<String, String>> {
+    let Ok(mut query) =
+        db.prepare("SELECT topic_type, encoded_message_definition FROM message_definitions WHERE encoding = 'ros2msg'")
+    else {
+        return Ok(HashMap::new());
+    };
+    let definitions = query
+        .query_map([], |row| Ok((ros1_type(&row.get::<_, String>(0)?), row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(definitions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::definitions::builtin_definition;
+    use crate::source::{open_source, stage_as_ros1, StageStats};
+    use std::sync::{Arc, Mutex};
+
+    /// CDR encoder for the test payloads
+    #[derive(Default)]
+    struct Cdr(Vec<u8>);
+
+    impl Cdr {
+        fn align(&mut self, n: usize) {
+            while !self.0.len().is_multiple_of(n) {
+                self.0.push(0);
+            }
+        }
+        fn u32(&mut self, v: u32) {
+            self.align(4);
+            self.0.extend_from_slice(&v.to_le_bytes());
+        }
+        fn f64s(&mut self, values: &[f64]) {
+            for v in values {
+                self.align(8);
+                self.0.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        fn string(&mut self, s: &str) {
+            self.u32(s.len() as u32 + 1);
+            self.0.extend_from_slice(s.as_bytes());
+            self.0.push(0);
+        }
+        fn payload(&self) -> Vec<u8> {
+            [&[0, 1, 0, 0][..], &self.0].concat()
+        }
+    }
+
+    fn imu(sec: u32) -> Vec<u8> {
+        let mut cdr = Cdr::default();
+        cdr.u32(sec);
+        cdr.u32(0);
+        cdr.string("imu_link");
+        cdr.f64s(&[0.0, 0.0, 0.0, 1.0]);
+        cdr.f64s(&[0.0; 9]);
+        cdr.f64s(&[0.1, 0.2, 0.3]);
+        cdr.f64s(&[0.0; 9]);
+        cdr.f64s(&[0.0, 0.0, 9.81]);
+        cdr.f64s(&[0.0; 9]);
+        cdr.payload()
+    }
+
+    #[test]
+    fn test_qos_and_builtin_definitions() {
+        let imu = builtin_definition("sensor_msgs/Imu").unwrap();
+        let schema = crate::ros_msg::MessageSchema::parse("sensor_msgs/Imu", &imu).unwrap();
+        assert_eq!(schema.field_names()[0], "header");
+        assert!(transient_local("- history: 3\n  depth: 0\n  reliability: 1\n  durability: 1\n"));
+        assert!(!transient_local("- durability: 2\n"));
+    }
+
+    #[test]
+    fn test_convert_rosbag2_directory() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_rosbag2_{}", std::process::id()));
+        let bag = dir.join("run");
+        std::fs::create_dir_all(&bag).unwrap();
+        std::fs::write(
+            bag.join("metadata.yaml"),
+            "rosbag2_bagfile_information:\n  version: 5\n  storage_identifier: sqlite3\n  \
+             relative_file_paths:\n    - run_0.db3\n",
+        )
+        .unwrap();
+        let db = Connection::open(bag.join("run_0.db3")).unwrap();
+        db.execute_batch(
+            "CREATE TABLE topics (id INTEGER PRIMARY KEY, name TEXT, type TEXT, serialization_format TEXT, \
+             offered_qos_profiles TEXT);
+             CREATE TABLE messages (id INTEGER PRIMARY KEY, topic_id INTEGER, timestamp INTEGER, data BLOB);
+             INSERT INTO topics VALUES (1, '/imu', 'sensor_msgs/msg/Imu', 'cdr', '');
+             INSERT INTO topics VALUES (2, '/battery', 'acme_msgs/msg/Battery', 'cdr', '');",
+        )
+        .unwrap();
+        for (i, topic) in [1, 1, 2].into_iter().enumerate() {
+            let time_ns = 1_700_000_000_000_000_000i64 + i as i64 * 100_000_000;
+            db.execute(
+                "INSERT INTO messages (topic_id, timestamp, data) VALUES (?1, ?2, ?3)",
+                rusqlite::params![topic, time_ns, imu(1_700_000_000)],
+            )
+            .unwrap();
+        }
+        drop(db);
+
+        let mut source = open_source(&bag).unwrap();
+        assert_eq!(source.topics().len(), 1);
+        assert_eq!(
+            source.time_range(),
+            Some((1_700_000_000_000_000_000, 1_700_000_000_200_000_000))
+        );
+        let stats = stage_as_ros1(&mut *source, &dir.join("staged.bag")).unwrap();
+        // No definition recorded for the in-house type
+        assert_eq!(stats, StageStats { messages: 2, skipped: 1 });
+
+        let finished = Arc::new(Mutex::new(None));
+        let sink = finished.clone();
+        let options = crate::ConvertOptions::new(bag.to_str().unwrap(), dir.join("out.rrd").to_str().unwrap())
+            .show_progress(false)
+            .progress_hook(crate::ProgressHook::new(move |event| {
+                if let crate::ConvertEvent::Finished(stats) = event {
+                    *sink.lock().unwrap() = Some(stats.clone());
+                }
+            }));
+        crate::convert_bag(&options).unwrap();
+        assert_eq!(finished.lock().unwrap().as_ref().map(|stats| stats.imu_msgs), Some(2));
+        assert!(dir.join("out.rrd").exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+        fn test_stage_custom_type_with_recorded_definition() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_rosbag2_custom_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("run_0.db3");
+        let db = Connection::open(&db_path).unwrap();
+        db.execute_batch(
+            "CREATE TABLE topics (id INTEGER PRIMARY KEY, name TEXT, type TEXT, serialization_format TEXT, \
+             offered_qos_profiles TEXT);
+             CREATE TABLE messages (id INTEGER PRIMARY KEY, topic_id INTEGER, timestamp INTEGER, data BLOB);
+             CREATE TABLE message_definitions (id INTEGER PRIMARY KEY, topic_type TEXT, encoding TEXT, \
+             encoded_message_definition TEXT, type_description_hash TEXT);
+             INSERT INTO topics VALUES (1, '/battery', 'acme_msgs/msg/Battery', 'cdr', '');
+             INSERT INTO message_definitions VALUES (1, 'acme_msgs/msg/Battery', 'ros2msg', \
+             'uint32 cell_count\nfloat64 voltage 0.0\nstring<=16 label\n', '');",
+        )
+        .unwrap();
+        let mut cdr = Cdr::default();
+        cdr.u32(6);
+        cdr.f64s(&[24.5]);
+        cdr.string("main");
+        db.execute(
+            "INSERT INTO messages (topic_id, timestamp, data) VALUES (1, 1700000000000000000, ?1)",
+            rusqlite::params![cdr.payload()],
+        )
+        .unwrap();
+        drop(db);
+
+        let staged = dir.join("staged.bag");
+        let mut source = open_source(&db_path).unwrap();
+        assert_eq!(stage_as_ros1(&mut *source, &staged).unwrap(), StageStats { messages: 1, skipped: 0 });
+        let bag = rosbag::RosBag::new(&staged).unwrap();
+        let mut connection = None;
+        let mut payloads = vec![];
+        for record in bag.chunk_records() {
+            let rosbag::ChunkRecord::Chunk(chunk) = record.unwrap() else {
+                continue;
+            };
+            for msg in chunk.messages() {
+                match msg.unwrap() {
+                    rosbag::MessageRecord::Connection(conn) => {
+                        connection = Some((conn.tp.to_string(), conn.md5sum, conn.message_definition.to_string()))
+                    }
+                    rosbag::MessageRecord::MessageData(msg) => payloads.push(msg.data.to_vec()),
+                }
+            }
+        }
+        std::fs::remove_dir_all(&dir).ok();
+
+        let (tp, md5sum, definition) = connection.unwrap();
+        assert_eq!(tp, "acme_msgs/Battery");
+        assert_eq!(md5sum, [0u8; 16]);
+        let schema = crate::ros_msg::MessageSchema::parse(&tp, &definition).unwrap();
+        let value = schema.decode(&payloads[0]).unwrap();
+        assert_eq!(value.get("cell_count"), Some(&crate::ros_msg::Value::UInt(6)));
+        assert_eq!(value.get("voltage"), Some(&crate::ros_msg::Value::Float(24.5)));
+        assert_eq!(value.get("label"), Some(&crate::ros_msg::Value::String("main".to_string())));
+    }
+}
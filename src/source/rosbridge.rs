@@ -0,0 +1,372 @@
+//! Live messages of a running ROS system, subscribed through rosbridge
+//!
+//! rosbridge speaks JSON over a WebSocket. Topics and their types come from the
+//! `/rosapi/topics` service, definitions of the types without a standard one from
+//! `/rosapi/message_details`. With `cbor-raw` (the default) rosbridge forwards
+//! the serialized messages, ROS1 payloads or CDR (ROS2) re-encoded like a ROS2
+//! bag's; with `json` every message is encoded from its JSON fields.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use super::definitions::{builtin_definition, ros1_syntax, ros1_type};
+use super::{now_ns, LiveMessage, LiveSource, SourceTopic, TopicTable};
+
+/// Socket reads give up after this long so the caller can check for Ctrl-C
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+/// Time allowed for a rosapi service to answer
+const SERVICE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How rosbridge sends the messages of subscribed topics
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum RosbridgeEncoding {
+    /// Serialized messages wrapped in CBOR, as recorded in a bag
+    #[default]
+    CborRaw,
+    /// Messages as JSON objects; slower, for rosbridge servers without CBOR
+    Json,
+}
+
+/// Parse "cbor-raw" or "json" for --rosbridge-encoding
+pub fn parse_rosbridge_encoding(s: &str) -> Result<RosbridgeEncoding> {
+    match s {
+        "cbor-raw" => Ok(RosbridgeEncoding::CborRaw),
+        "json" => Ok(RosbridgeEncoding::Json),
+        _ => bail!("Invalid rosbridge encoding '{}' (expected cbor-raw or json)", s),
+    }
+}
+
+/// Whether a convert input names a rosbridge server rather than a file
+pub fn is_rosbridge_url(input: &str) -> bool {
+    input.starts_with("ws://") || input.starts_with("wss://")
+}
+
+/// A `publish` operation: topic name, receive time and message (CBOR-wrapped
+/// bytes or JSON text)
+struct Publish {
+    topic: String,
+    time_ns: u64,
+    payload: Vec<u8>,
+}
+
+/// Connection to a rosbridge server
+pub struct Rosbridge {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    encoding: RosbridgeEncoding,
+    table: TopicTable,
+    /// Topic name → shared topic id; `None` when skipped
+    ids: HashMap<String, Option<u32>>,
+    /// Messages received while waiting for a service response
+    pending: VecDeque<Publish>,
+    calls: u64,
+}
+
+impl Rosbridge {
+    /// Connect to the rosbridge server at `url` (`ws://host:9090`)
+    pub fn connect(url: &str, encoding: RosbridgeEncoding) -> Result<Self> {
+        let (socket, _) = tungstenite::connect(url).with_context(|| format!("failed to connect to rosbridge at {}", url))?;
+        match socket.get_ref() {
+            MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(READ_TIMEOUT))?,
+            _ => bail!("{}: only ws:// rosbridge URLs are supported", url),
+        }
+        Ok(Self {
+            socket,
+            encoding,
+            table: TopicTable::default(),
+            ids: HashMap::new(),
+            pending: VecDeque::new(),
+            calls: 0,
+        })
+    }
+
+    /// Definition of `ros_type` from its rosapi typedefs, in ROS1 syntax
+    fn message_details(&mut self, ros_type: &str) -> Result<String> {
+        let values = self.call_service("/rosapi/message_details", json!({"type": ros_type}))?;
+        typedefs_definition(&ros1_type(ros_type), &values["typedefs"])
+    }
+
+    fn call_service(&mut self, service: &str, args: serde_json::Value) -> Result<serde_json::Value> {
+        self.calls += 1;
+        let id = format!("bag2rrd_{}", self.calls);
+        self.send(json!({"op": "call_service", "id": id, "service": service, "args": args}))?;
+        let deadline = Instant::now() + SERVICE_TIMEOUT;
+        while Instant::now() < deadline {
+            let Some(message) = self.read()? else {
+                continue;
+            };
+            match message {
+                Frame::Publish(publish) => self.pending.push_back(publish),
+                Frame::ServiceResponse { id: answered, result, values } if answered == id => {
+                    if !result {
+                        bail!("{} failed: {}", service, values);
+                    }
+                    return Ok(values);
+                }
+                _ => {}
+            }
+        }
+        bail!("no response from {} (is rosapi running next to rosbridge?)", service)
+    }
+
+    fn read_publish(&mut self) -> Result<Option<Publish>> {
+        Ok(match self.read()? {
+            Some(Frame::Publish(publish)) => Some(publish),
+            _ => None,
+        })
+    }
+
+    /// Next frame; `None` on read timeout
+    fn read(&mut self) -> Result<Option<Frame>> {
+        let message = match self.socket.read() {
+            Ok(message) => message,
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) =>
+            {
+                return Ok(None);
+            }
+            Err(e) => return Err(e).context("rosbridge connection lost"),
+        };
+        Ok(Some(match message {
+            Message::Text(text) => parse_json_frame(text.as_str())?,
+            Message::Binary(data) => parse_cbor_frame(&data)?,
+            Message::Close(_) => bail!("rosbridge closed the connection"),
+            _ => Frame::Other,
+        }))
+    }
+
+    fn send(&mut self, op: serde_json::Value) -> Result<()> {
+        self.socket
+            .send(Message::text(op.to_string()))
+            .context("failed to send to rosbridge")
+    }
+}
+
+impl LiveSource for Rosbridge {
+    fn list_topics(&mut self) -> Result<Vec<(String, String)>> {
+        let values = self.call_service("/rosapi/topics", json!({}))?;
+        let strings = |key: &str| -> Vec<String> {
+            values[key]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|v| v.as_str().map(str::to_string))
+                .collect()
+        };
+        Ok(strings("topics").into_iter().zip(strings("types")).collect())
+    }
+
+    fn subscribe(&mut self, name: &str, ros_type: &str) -> Result<Option<SourceTopic>> {
+        if let Some(id) = self.ids.get(name) {
+            return Ok(id.map(|id| self.table.topics[id as usize].clone()));
+        }
+        let tp = ros1_type(ros_type);
+        let ros2 = ros_type.contains("/msg/");
+        let encoding = match self.encoding {
+            RosbridgeEncoding::Json => "json",
+            RosbridgeEncoding::CborRaw if ros2 => "cdr",
+            RosbridgeEncoding::CborRaw => "ros1",
+        };
+        let recorded = match builtin_definition(&tp) {
+            Some(definition) => Some(definition),
+            None => self
+                .message_details(ros_type)
+                .inspect_err(|e| tracing::warn!("No definition of {} from rosapi: {:#}", ros_type, e))
+                .ok(),
+        };
+        let id = self.table.insert(name, &tp, encoding, recorded.as_deref(), None, false);
+        self.ids.insert(name.to_string(), id);
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        let compression = match self.encoding {
+            RosbridgeEncoding::CborRaw => "cbor-raw",
+            RosbridgeEncoding::Json => "none",
+        };
+        self.send(json!({"op": "subscribe", "topic": name, "type": ros_type, "compression": compression}))?;
+        Ok(Some(self.table.topics[id as usize].clone()))
+    }
+
+    fn next_message(&mut self) -> Result<Option<LiveMessage>> {
+        let publish = match self.pending.pop_front() {
+            Some(publish) => publish,
+            None => match self.read_publish()? {
+                Some(publish) => publish,
+                None => return Ok(None),
+            },
+        };
+        let id = self.ids.get(&publish.topic).copied().flatten();
+        Ok(self.table.decode(id, &publish.payload).map(|data| LiveMessage {
+            topic: id.unwrap_or_default(),
+            time_ns: publish.time_ns,
+            data: data.into_owned(),
+        }))
+    }
+
+    fn skipped(&self) -> u64 {
+        self.table.skipped
+    }
+}
+
+enum Frame {
+    Publish(Publish),
+    ServiceResponse { id: String, result: bool, values: serde_json::Value },
+    Other,
+}
+
+fn parse_json_frame(text: &str) -> Result<Frame> {
+    let op: serde_json::Value = serde_json::from_str(text).context("invalid rosbridge JSON")?;
+    Ok(match op["op"].as_str() {
+        Some("publish") => Frame::Publish(Publish {
+            topic: op["topic"].as_str().unwrap_or_default().to_string(),
+            time_ns: now_ns(),
+            payload: serde_json::to_vec(&op["msg"])?,
+        }),
+        Some("service_response") => Frame::ServiceResponse {
+            id: op["id"].as_str().unwrap_or_default().to_string(),
+            result: op["result"].as_bool().unwrap_or(true),
+            values: op["values"].clone(),
+        },
+        Some("status") => {
+            tracing::warn!("rosbridge: {}", op["msg"].as_str().unwrap_or(text));
+            Frame::Other
+        }
+        _ => Frame::Other,
+    })
+}
+
+/// A `cbor-raw` publish: `{op, topic, msg: {secs, nsecs, bytes}}`
+fn parse_cbor_frame(data: &[u8]) -> Result<Frame> {
+    let op: ciborium::Value = ciborium::from_reader(data).map_err(|e| anyhow!("invalid rosbridge CBOR: {}", e))?;
+    let field = |map: &ciborium::Value, name: &str| -> Option<ciborium::Value> {
+        map.as_map()?
+            .iter()
+            .find(|(key, _)| key.as_text() == Some(name))
+            .map(|(_, value)| value.clone())
+    };
+    if field(&op, "op").as_ref().and_then(ciborium::Value::as_text) != Some("publish") {
+        return Ok(Frame::Other);
+    }
+    let topic = field(&op, "topic").and_then(|v| v.into_text().ok()).unwrap_or_default();
+    let msg = field(&op, "msg").ok_or_else(|| anyhow!("{}: CBOR publish without msg", topic))?;
+    let int = |name: &str| -> u64 {
+        field(&msg, name)
+            .and_then(|v| v.as_integer())
+            .and_then(|i| u64::try_from(i).ok())
+            .unwrap_or(0)
+    };
+    let time_ns = int("secs") * 1_000_000_000 + int("nsecs");
+    let payload = field(&msg, "bytes")
+        .and_then(|v| v.into_bytes().ok())
+        .ok_or_else(|| anyhow!("{}: CBOR publish without bytes", topic))?;
+    Ok(Frame::Publish(Publish { topic, time_ns, payload }))
+}
+
+/// Definition text of `tp` from rosapi typedefs (type, fieldnames, fieldtypes,
+/// fieldarraylen per type), `tp` first
+fn typedefs_definition(tp: &str, typedefs: &serde_json::Value) -> Result<String> {
+    let typedefs = typedefs.as_array().ok_or_else(|| anyhow!("no typedefs for {}", tp))?;
+    let mut sorted: Vec<&serde_json::Value> = typedefs.iter().collect();
+    sorted.sort_by_key(|typedef| ros1_type(typedef["type"].as_str().unwrap_or_default()) != tp);
+    let mut out = String::new();
+    for (i, typedef) in sorted.into_iter().enumerate() {
+        if i > 0 {
+            out += &format!("{}\nMSG: {}\n", "=".repeat(80), typedef["type"].as_str().unwrap_or_default());
+        }
+        let list = |key: &str| typedef[key].as_array().cloned().unwrap_or_default();
+        for ((name, ty), len) in list("fieldnames").iter().zip(list("fieldtypes")).zip(list("fieldarraylen")) {
+            let ty = idl_type(ty.as_str().unwrap_or_default());
+            let array = match len.as_i64() {
+                Some(0) => "[]".to_string(),
+                Some(n) if n > 0 => format!("[{}]", n),
+                _ => String::new(),
+            };
+            out += &format!("{}{} {}\n", ty, array, name.as_str().unwrap_or_default());
+        }
+    }
+    Ok(ros1_syntax(&out))
+}
+
+/// msg name of the IDL primitive names ROS2 rosapi reports
+fn idl_type(ty: &str) -> &str {
+    match ty {
+        "double" => "float64",
+        "float" => "float32",
+        "boolean" => "bool",
+        "octet" => "byte",
+        "int8_t" | "char" => "int8",
+        "long" => "int32",
+        "long long" => "int64",
+        "unsigned long" => "uint32",
+        "unsigned long long" => "uint64",
+        "short" => "int16",
+        "unsigned short" => "uint16",
+        _ => ty,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ros_msg::MessageSchema;
+
+    #[test]
+    fn test_parse_frames() {
+        let Frame::ServiceResponse { id, result, values } = parse_json_frame(
+            r#"{"op": "service_response", "id": "bag2rrd_1", "result": true, "values": {"topics": ["/imu"]}}"#,
+        )
+        .unwrap() else {
+            panic!("not a service response");
+        };
+        assert_eq!((id.as_str(), result), ("bag2rrd_1", true));
+        assert_eq!(values["topics"][0], "/imu");
+
+        let cbor = ciborium::Value::Map(vec![
+            (ciborium::Value::Text("op".into()), ciborium::Value::Text("publish".into())),
+            (ciborium::Value::Text("topic".into()), ciborium::Value::Text("/status".into())),
+            (
+                ciborium::Value::Text("msg".into()),
+                ciborium::Value::Map(vec![
+                    (ciborium::Value::Text("secs".into()), ciborium::Value::Integer(12.into())),
+                    (ciborium::Value::Text("nsecs".into()), ciborium::Value::Integer(5.into())),
+                    (ciborium::Value::Text("bytes".into()), ciborium::Value::Bytes(vec![2, 0, 0, 0, b'o', b'k'])),
+                ]),
+            ),
+        ]);
+        let mut data = Vec::new();
+        ciborium::into_writer(&cbor, &mut data).unwrap();
+        let Frame::Publish(publish) = parse_cbor_frame(&data).unwrap() else {
+            panic!("not a publish");
+        };
+        assert_eq!(publish.topic, "/status");
+        assert_eq!(publish.time_ns, 12_000_000_005);
+        assert_eq!(publish.payload, [2, 0, 0, 0, b'o', b'k']);
+        assert_eq!(parse_rosbridge_encoding("json").unwrap(), RosbridgeEncoding::Json);
+        assert!(parse_rosbridge_encoding("cbor").is_err());
+        assert!(is_rosbridge_url("ws://robot:9090") && !is_rosbridge_url("run.bag"));
+    }
+
+    #[test]
+    fn test_typedefs_definition() {
+        let typedefs = json!([
+            {"type": "std_msgs/msg/Header", "fieldnames": ["stamp", "frame_id"],
+             "fieldtypes": ["builtin_interfaces/Time", "string"], "fieldarraylen": [-1, -1]},
+            {"type": "acme_msgs/msg/Battery", "fieldnames": ["header", "cells", "ok"],
+             "fieldtypes": ["std_msgs/Header", "double", "boolean"], "fieldarraylen": [-1, 0, -1]},
+            {"type": "builtin_interfaces/msg/Time", "fieldnames": ["sec", "nanosec"],
+             "fieldtypes": ["int32", "uint32"], "fieldarraylen": [-1, -1]}
+        ]);
+        let definition = typedefs_definition("acme_msgs/Battery", &typedefs).unwrap();
+        assert!(definition.starts_with("std_msgs/Header header\nfloat64[] cells\nbool ok\n"), "{definition}");
+        let schema = MessageSchema::parse("acme_msgs/Battery", &definition).unwrap();
+        let msg = json!({"header": {"stamp": {"sec": 1, "nanosec": 0}, "frame_id": "bms"}, "cells": [3.7], "ok": true});
+        let value = schema.decode(&schema.json_to_ros1(&msg).unwrap()).unwrap();
+        assert_eq!(value.get("header.frame_id").and_then(|v| v.as_str()), Some("bms"));
+        assert_eq!(value.f64_at("ok").unwrap(), 1.0);
+    }
+}
@@ -0,0 +1,578 @@
+//! Live messages of a running ROS1 system, subscribed over TCPROS
+//!
+//! A `ros1://host:11311` input (bare `ros1://` for `ROS_MASTER_URI`) registers
+//! bag2rrd as a node with the ROS master over XML-RPC, asks every publisher of a
+//! subscribed topic for a TCPROS connection and reads its messages, serialized
+//! exactly as in a bag. Definitions and md5sums come from the publishers'
+//! connection headers. Publishers that appear later are connected when the topic
+//! list is polled again; the `publisherUpdate` calls of the master are only
+//! acknowledged.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use super::{now_ns, LiveMessage, LiveSource, SourceTopic};
+
+/// How long [`LiveSource::next_message`] waits for a message
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+/// How long an XML-RPC call or TCPROS handshake may take
+const CALL_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest TCPROS frame accepted, against corrupt length prefixes
+const MAX_FRAME: usize = 256 << 20;
+/// Messages queued from the publisher connections before their readers block
+const QUEUE_LEN: usize = 1024;
+
+/// A node subscribed to topics of a ROS1 master
+pub struct Ros1Node {
+    /// Master XML-RPC URI, `http://host:11311`
+    master: String,
+    caller_id: String,
+    /// XML-RPC URI of this node, answered by an acknowledging thread
+    caller_api: String,
+    topics: Vec<SourceTopic>,
+    /// Topic name → topic id
+    ids: HashMap<String, u32>,
+    /// XML-RPC URIs of the publishers connected, per topic id
+    publishers: HashMap<u32, HashSet<String>>,
+    sender: flume::Sender<Received>,
+    receiver: flume::Receiver<Received>,
+}
+
+/// What a publisher connection thread reports
+enum Received {
+    Message(LiveMessage),
+    Closed { topic: u32, publisher: String },
+}
+
+impl Ros1Node {
+    /// Register with the master of a `ros1://host:port` input
+    pub fn connect(input: &str) -> Result<Self> {
+        let master = match input.strip_prefix("ros1://").unwrap_or(input).trim_end_matches('/') {
+            "" => std::env::var("ROS_MASTER_URI").context("a bare ros1:// input needs ROS_MASTER_URI")?,
+            host => format!("http://{}", host),
+        };
+        let master_addr = socket_addr(&master)?;
+        // The address the master reaches this machine at, unless ROS_HOSTNAME or ROS_IP say otherwise
+        let host = match std::env::var("ROS_HOSTNAME").or_else(|_| std::env::var("ROS_IP")) {
+            Ok(host) => host,
+            Err(_) => TcpStream::connect_timeout(&master_addr, CALL_TIMEOUT)
+                .with_context(|| format!("failed to connect to the ROS master at {}", master))?
+                .local_addr()?
+                .ip()
+                .to_string(),
+        };
+        let listener = TcpListener::bind("0.0.0.0:0")?;
+        let caller_api = format!("http://{}:{}/", host, listener.local_addr()?.port());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = acknowledge(stream) {
+                    tracing::debug!("XML-RPC call not acknowledged: {:#}", e);
+                }
+            }
+        });
+        let (sender, receiver) = flume::bounded(QUEUE_LEN);
+        let node = Self {
+            master,
+            caller_id: format!("/bag2rrd_{}", std::process::id()),
+            caller_api,
+            topics: Vec::new(),
+            ids: HashMap::new(),
+            publishers: HashMap::new(),
+            sender,
+            receiver,
+        };
+        node.call_master("getUri", vec![])
+            .with_context(|| format!("no ROS master at {}", node.master))?;
+        Ok(node)
+    }
+
+    /// URI of the master registered with
+    pub fn master_uri(&self) -> &str {
+        &self.master
+    }
+
+    fn call_master(&self, method: &str, params: Vec<Value>) -> Result<Value> {
+        let params = std::iter::once(Value::Str(self.caller_id.clone())).chain(params).collect::<Vec<_>>();
+        xmlrpc_call(&self.master, method, &params)
+    }
+
+    /// Register as a subscriber of `name`; XML-RPC URIs of its current publishers
+    fn register(&self, name: &str, ros_type: &str) -> Result<Vec<String>> {
+        let publishers = self.call_master(
+            "registerSubscriber",
+            vec![Value::Str(name.to_string()), Value::Str(ros_type.to_string()), Value::Str(self.caller_api.clone())],
+        )?;
+        Ok(publishers.strings())
+    }
+
+    /// Open a TCPROS connection to `publisher` for topic `name`; the connection and
+    /// the publisher's header
+    fn request_topic(&self, publisher: &str, name: &str, ros_type: &str) -> Result<(TcpStream, HashMap<String, String>)> {
+        let protocols = Value::Array(vec![Value::Array(vec![Value::Str("TCPROS".to_string())])]);
+        let params = [Value::Str(self.caller_id.clone()), Value::Str(name.to_string()), protocols];
+        let params = match xmlrpc_call(publisher, "requestTopic", &params)? {
+            Value::Array(params) => params,
+            other => bail!("{}: unexpected requestTopic answer {:?}", publisher, other),
+        };
+        let (host, port) = match params.as_slice() {
+            [Value::Str(protocol), Value::Str(host), Value::Int(port)] if protocol == "TCPROS" => (host, *port),
+            _ => bail!("{}: no TCPROS connection offered for {}", publisher, name),
+        };
+        let addr = format!("{}:{}", host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("{}: cannot resolve {}", publisher, host))?;
+        let mut stream = TcpStream::connect_timeout(&addr, CALL_TIMEOUT)?;
+        stream.set_read_timeout(Some(CALL_TIMEOUT))?;
+        stream.write_all(&encode_header(&[
+            ("callerid", &self.caller_id),
+            ("topic", name),
+            ("type", ros_type),
+            ("md5sum", "*"),
+            ("tcp_nodelay", "1"),
+        ]))?;
+        let header = decode_header(&read_frame(&mut stream)?)?;
+        if let Some(error) = header.get("error") {
+            bail!("{} refused {}: {}", publisher, name, error);
+        }
+        stream.set_read_timeout(None)?;
+        Ok((stream, header))
+    }
+
+    /// Read the messages of a connected publisher on a thread of their own
+    fn spawn_reader(&mut self, topic: u32, publisher: String, mut stream: TcpStream) {
+        self.publishers.entry(topic).or_default().insert(publisher.clone());
+        let sender = self.sender.clone();
+        std::thread::spawn(move || {
+            loop {
+                match read_frame(&mut stream) {
+                    Ok(data) => {
+                        if sender.send(Received::Message(LiveMessage { topic, time_ns: now_ns(), data })).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::info!("{}: connection closed: {:#}", publisher, e);
+                        sender.send(Received::Closed { topic, publisher }).ok();
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Connect the publishers of a subscribed topic not connected yet
+    fn connect_publishers(&mut self, topic: u32) -> Result<()> {
+        let (name, tp) = (self.topics[topic as usize].name.clone(), self.topics[topic as usize].tp.clone());
+        for publisher in self.register(&name, &tp)? {
+            if self.publishers.get(&topic).is_some_and(|connected| connected.contains(&publisher)) {
+                continue;
+            }
+            match self.request_topic(&publisher, &name, &tp) {
+                Ok((stream, _)) => self.spawn_reader(topic, publisher, stream),
+                Err(e) => tracing::warn!("{:#}", e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LiveSource for Ros1Node {
+    fn list_topics(&mut self) -> Result<Vec<(String, String)>> {
+        for topic in self.ids.values().copied().collect::<Vec<_>>() {
+            self.connect_publishers(topic)?;
+        }
+        let published = self.call_master("getPublishedTopics", vec![Value::Str(String::new())])?;
+        let Value::Array(published) = published else {
+            bail!("unexpected getPublishedTopics answer from {}", self.master);
+        };
+        Ok(published
+            .iter()
+            .filter_map(|pair| match pair.strings().as_slice() {
+                [name, tp] => Some((name.clone(), tp.clone())),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn subscribe(&mut self, name: &str, ros_type: &str) -> Result<Option<SourceTopic>> {
+        if let Some(id) = self.ids.get(name) {
+            return Ok(Some(self.topics[*id as usize].clone()));
+        }
+        // The first publisher that answers tells the definition
+        let mut refused = None;
+        for publisher in self.register(name, ros_type)? {
+            let (stream, header) = match self.request_topic(&publisher, name, ros_type) {
+                Ok(connection) => connection,
+                Err(e) => {
+                    refused = Some(e);
+                    continue;
+                }
+            };
+            let id = self.topics.len() as u32;
+            let topic = SourceTopic {
+                id,
+                name: name.to_string(),
+                tp: header.get("type").cloned().unwrap_or_else(|| ros_type.to_string()),
+                definition: header.get("message_definition").cloned().unwrap_or_default(),
+                md5sum: header.get("md5sum").cloned().unwrap_or_else(|| "*".to_string()),
+                latching: header.get("latching").is_some_and(|latching| latching == "1"),
+            };
+            self.topics.push(topic.clone());
+            self.ids.insert(name.to_string(), id);
+            self.spawn_reader(id, publisher, stream);
+            self.connect_publishers(id)?;
+            return Ok(Some(topic));
+        }
+        // Tried again on the next poll, once a publisher is reachable or accepts
+        if let Some(e) = refused {
+            tracing::warn!("Not subscribed to {} yet: {:#}", name, e);
+        }
+        Ok(None)
+    }
+
+    fn next_message(&mut self) -> Result<Option<LiveMessage>> {
+        match self.receiver.recv_timeout(READ_TIMEOUT) {
+            Ok(Received::Message(message)) => Ok(Some(message)),
+            Ok(Received::Closed { topic, publisher }) => {
+                if let Some(connected) = self.publishers.get_mut(&topic) {
+                    connected.remove(&publisher);
+                }
+                Ok(None)
+            }
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl Drop for Ros1Node {
+    fn drop(&mut self) {
+        for topic in &self.topics {
+            let params = vec![Value::Str(topic.name.clone()), Value::Str(self.caller_api.clone())];
+            if let Err(e) = self.call_master("unregisterSubscriber", params) {
+                tracing::debug!("Failed to unregister from {}: {:#}", topic.name, e);
+            }
+        }
+    }
+}
+
+/// XML-RPC values of the ROS master and slave APIs
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Int(i64),
+    Bool(bool),
+    Double(f64),
+    Str(String),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// Strings of an array, others left out
+    fn strings(&self) -> Vec<String> {
+        match self {
+            Value::Array(values) => values
+                .iter()
+                .filter_map(|value| match value {
+                    Value::Str(s) => Some(s.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn encode(&self, out: &mut String) {
+        out.push_str("<value>");
+        match self {
+            Value::Int(i) => out.push_str(&format!("<i4>{}</i4>", i)),
+            Value::Bool(b) => out.push_str(&format!("<boolean>{}</boolean>", u8::from(*b))),
+            Value::Double(d) => out.push_str(&format!("<double>{}</double>", d)),
+            Value::Str(s) => {
+                let escaped = s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+                out.push_str(&format!("<string>{}</string>", escaped));
+            }
+            Value::Array(values) => {
+                out.push_str("<array><data>");
+                for value in values {
+                    value.encode(out);
+                }
+                out.push_str("</data></array>");
+            }
+        }
+        out.push_str("</value>");
+    }
+}
+
+fn encode_params(params: &[Value]) -> String {
+    let mut out = String::from("<params>");
+    for param in params {
+        out.push_str("<param>");
+        param.encode(&mut out);
+        out.push_str("</param>");
+    }
+    out + "</params>"
+}
+
+/// Call `method` of the XML-RPC server at `uri`; the value of its ROS
+/// `[code, status, value]` answer
+fn xmlrpc_call(uri: &str, method: &str, params: &[Value]) -> Result<Value> {
+    let mut stream = TcpStream::connect_timeout(&socket_addr(uri)?, CALL_TIMEOUT)
+        .with_context(|| format!("failed to connect to {}", uri))?;
+    stream.set_read_timeout(Some(CALL_TIMEOUT))?;
+    let body = format!(
+        "<?xml version=\"1.0\"?><methodCall><methodName>{}</methodName>{}</methodCall>",
+        method,
+        encode_params(params)
+    );
+    write!(
+        stream,
+        "POST / HTTP/1.0\r\nContent-Type: text/xml\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| anyhow!("{}: malformed {} response", uri, method))?;
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        bail!("{}: {} answered {}", uri, method, status);
+    }
+    match parse_response(body).with_context(|| format!("{}: invalid {} response", uri, method))? {
+        Value::Array(answer) => match <[Value; 3]>::try_from(answer) {
+            Ok([Value::Int(1), _, value]) => Ok(value),
+            Ok([_, Value::Str(status), _]) => bail!("{}: {} failed: {}", uri, method, status),
+            _ => bail!("{}: unexpected {} response", uri, method),
+        },
+        _ => bail!("{}: unexpected {} response", uri, method),
+    }
+}
+
+/// Address of an `http://host:port/` URI
+fn socket_addr(uri: &str) -> Result<SocketAddr> {
+    let host_port = uri
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow!("{}: expected an http:// URI", uri))?
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    host_port
+        .to_socket_addrs()
+        .with_context(|| format!("cannot resolve {}", uri))?
+        .next()
+        .ok_or_else(|| anyhow!("cannot resolve {}", uri))
+}
+
+/// Answer an XML-RPC call to this node with success: the master's
+/// `publisherUpdate` and the pings of `rosnode`
+fn acknowledge(mut stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(CALL_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':')
+            && key.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse()?;
+        }
+    }
+    reader.read_exact(&mut vec![0; length])?;
+    let answer = Value::Array(vec![Value::Int(1), Value::Str(String::new()), Value::Int(0)]);
+    let body = format!(
+        "<?xml version=\"1.0\"?><methodResponse>{}</methodResponse>",
+        encode_params(&[answer])
+    );
+    write!(
+        stream,
+        "HTTP/1.0 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// The value of an XML-RPC `methodResponse`
+fn parse_response(body: &str) -> Result<Value> {
+    if body.contains("<fault>") {
+        bail!("XML-RPC fault: {}", body);
+    }
+    let start = body.find("<params>").ok_or_else(|| anyhow!("no params"))?;
+    let mut parser = XmlParser { rest: &body[start..] };
+    parser.expect("params")?;
+    parser.expect("param")?;
+    parser.value()
+}
+
+/// Reader of the XML-RPC subset: tags without attributes and their text
+#[derive(Clone, Copy)]
+struct XmlParser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> XmlParser<'a> {
+    /// Next tag, without its brackets (`value`, `/value`, `string/`)
+    fn tag(&mut self) -> Result<&'a str> {
+        self.rest = self.rest.trim_start();
+        let end = self.rest.find('>').filter(|_| self.rest.starts_with('<'));
+        let end = end.ok_or_else(|| anyhow!("expected a tag at '{:.20}'", self.rest))?;
+        let tag = &self.rest[1..end];
+        self.rest = &self.rest[end + 1..];
+        Ok(tag)
+    }
+
+    fn peek_tag(&self) -> Result<&'a str> {
+        let mut ahead = *self;
+        ahead.tag()
+    }
+
+    fn expect(&mut self, tag: &str) -> Result<()> {
+        let found = self.tag()?;
+        if found != tag {
+            bail!("expected <{}>, found <{}>", tag, found);
+        }
+        Ok(())
+    }
+
+    /// Text up to the next tag, unescaped
+    fn text(&mut self) -> String {
+        let end = self.rest.find('<').unwrap_or(self.rest.len());
+        let text = &self.rest[..end];
+        self.rest = &self.rest[end..];
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+    }
+
+    /// Text of a scalar element, up to its closing tag
+    fn scalar(&mut self, tag: &str) -> Result<String> {
+        let text = self.text();
+        self.expect(&format!("/{}", tag))?;
+        Ok(text)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.expect("value")?;
+        // A bare string keeps its whitespace
+        let bare = self.text();
+        let value = match self.tag()? {
+            "/value" => return Ok(Value::Str(bare)),
+            "string/" => Value::Str(String::new()),
+            "string" => Value::Str(self.scalar("string")?),
+            tag @ ("i4" | "int") => Value::Int(self.scalar(tag)?.trim().parse()?),
+            "boolean" => Value::Bool(self.scalar("boolean")?.trim() == "1"),
+            "double" => Value::Double(self.scalar("double")?.trim().parse()?),
+            "array" => {
+                let mut values = Vec::new();
+                match self.tag()? {
+                    "data/" => {}
+                    "data" => {
+                        while self.peek_tag()? != "/data" {
+                            values.push(self.value()?);
+                        }
+                        self.expect("/data")?;
+                    }
+                    tag => bail!("expected <data>, found <{}>", tag),
+                }
+                self.expect("/array")?;
+                Value::Array(values)
+            }
+            tag => bail!("unsupported XML-RPC value <{}>", tag),
+        };
+        self.expect("/value")?;
+        Ok(value)
+    }
+}
+
+/// A TCPROS connection header: length-prefixed `key=value` fields, with the
+/// length of them all first
+fn encode_header(fields: &[(&str, &str)]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (key, value) in fields {
+        let field = format!("{}={}", key, value);
+        body.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        body.extend_from_slice(field.as_bytes());
+    }
+    let mut out = (body.len() as u32).to_le_bytes().to_vec();
+    out.extend(body);
+    out
+}
+
+fn decode_header(mut data: &[u8]) -> Result<HashMap<String, String>> {
+    let mut fields = HashMap::new();
+    while data.len() >= 4 {
+        let len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let field = data.get(4..4 + len).ok_or_else(|| anyhow!("truncated TCPROS header"))?;
+        let field = String::from_utf8_lossy(field);
+        if let Some((key, value)) = field.split_once('=') {
+            fields.insert(key.to_string(), value.to_string());
+        }
+        data = &data[4 + len..];
+    }
+    Ok(fields)
+}
+
+/// A length-prefixed TCPROS frame: a header or a message
+fn read_frame(stream: &mut impl Read) -> Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME {
+        bail!("TCPROS frame of {} bytes", len);
+    }
+    let mut data = vec![0; len];
+    stream.read_exact(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xmlrpc_values() {
+        let value = Value::Array(vec![
+            Value::Int(1),
+            Value::Str("a <b> & c".to_string()),
+            Value::Array(vec![Value::Array(vec![Value::Str("/tf".into()), Value::Str("tf2_msgs/TFMessage".into())])]),
+            Value::Bool(true),
+            Value::Double(0.5),
+            Value::Array(vec![]),
+        ]);
+        let body = format!("<?xml version=\"1.0\"?>\n<methodResponse>{}</methodResponse>", encode_params(std::slice::from_ref(&value)));
+        assert_eq!(parse_response(&body).unwrap(), value);
+
+        // Bare strings, <int>, <data/> and whitespace between tags, as rosmaster answers
+        let body = "<methodResponse><params><param>\n<value><array><data>\n<value><int>1</int></value>\n\
+                    <value>current topics</value>\n<value><array><data/></array></value>\n\
+                    </data></array></value>\n</param></params></methodResponse>";
+        assert_eq!(
+            parse_response(body).unwrap(),
+            Value::Array(vec![Value::Int(1), Value::Str("current topics".into()), Value::Array(vec![])])
+        );
+        assert!(parse_response("<methodResponse><fault></fault></methodResponse>").is_err());
+    }
+
+    #[test]
+    fn test_tcpros_frames() {
+        let header = encode_header(&[("topic", "/chatter"), ("md5sum", "*"), ("message_definition", "string data\n")]);
+        let mut reader = header.as_slice();
+        let fields = decode_header(&read_frame(&mut reader).unwrap()).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(fields["topic"], "/chatter");
+        assert_eq!(fields["md5sum"], "*");
+        assert_eq!(fields["message_definition"], "string data\n");
+
+        let mut truncated: &[u8] = &[8, 0, 0, 0, 1, 2];
+        assert!(read_frame(&mut truncated).is_err());
+    }
+}
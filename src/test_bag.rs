@@ -0,0 +1,214 @@
+//! Minimal ROS bag 2.0 writer for tests and benches: chunks (optionally bz2 or lz4 compressed) plus the index section
+
+use std::io::Write;
+use std::path::Path;
+
+/// Chunk compression, as `rosbag record --bz2` / `--lz4` write it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TestCompression {
+    None,
+    Bz2,
+    Lz4,
+}
+
+impl TestCompression {
+    fn name(self) -> &'static str {
+        match self {
+            TestCompression::None => "none",
+            TestCompression::Bz2 => "bz2",
+            TestCompression::Lz4 => "lz4",
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            TestCompression::None => data.to_vec(),
+            TestCompression::Bz2 => {
+                let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::best());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            TestCompression::Lz4 => {
+                let mut encoder = lz4::EncoderBuilder::new().build(Vec::new()).unwrap();
+                encoder.write_all(data).unwrap();
+                let (out, result) = encoder.finish();
+                result.unwrap();
+                out
+            }
+        }
+    }
+}
+
+pub struct TestConnection {
+    pub id: u32,
+    pub topic: &'static str,
+    pub tp: &'static str,
+    pub latching: bool,
+}
+
+pub struct TestMessage {
+    pub conn: u32,
+    pub time_ns: u64,
+    pub data: Vec<u8>,
+}
+
+impl TestMessage {
+    pub fn new(conn: u32, time_s: f64, data: Vec<u8>) -> Self {
+        Self { conn, time_ns: (time_s * 1e9).round() as u64, data }
+    }
+}
+
+fn field(name: &str, value: &[u8]) -> Vec<u8> {
+    let mut out = ((name.len() + 1 + value.len()) as u32).to_le_bytes().to_vec();
+    out.extend_from_slice(name.as_bytes());
+    out.push(b'=');
+    out.extend_from_slice(value);
+    out
+}
+
+fn time_field(name: &str, time_ns: u64) -> Vec<u8> {
+    let mut value = ((time_ns / 1_000_000_000) as u32).to_le_bytes().to_vec();
+    value.extend_from_slice(&((time_ns % 1_000_000_000) as u32).to_le_bytes());
+    field(name, &value)
+}
+
+fn record(header: &[Vec<u8>], data: &[u8]) -> Vec<u8> {
+    let header: Vec<u8> = header.concat();
+    let mut out = (header.len() as u32).to_le_bytes().to_vec();
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+/// Message definition stored for `tp`; empty for the types tests only count
+fn definition(tp: &str) -> &'static str {
+    match tp {
+        "std_msgs/String" => "string data\n",
+        "test_msgs/Reading" => "Header header\nfloat64 value\nstring label\n\
+                                ================================================================================\n\
+                                MSG: std_msgs/Header\nuint32 seq\ntime stamp\nstring frame_id\n",
+        _ => "",
+    }
+}
+
+fn connection_record(conn: &TestConnection) -> Vec<u8> {
+    let data = [
+        field("topic", conn.topic.as_bytes()),
+        field("type", conn.tp.as_bytes()),
+        // The standard md5sum of mapped types, so they are parsed
+        field("md5sum", crate::ros_msg::known_md5sum(conn.tp).unwrap_or("00000000000000000000000000000000").as_bytes()),
+        field("message_definition", definition(conn.tp).as_bytes()),
+        // One publisher node per connection
+        field("callerid", format!("/publisher_{}", conn.id).as_bytes()),
+        field("latching", if conn.latching { b"1" } else { b"0" }),
+    ]
+    .concat();
+    record(
+        &[field("op", &[0x07]), field("conn", &conn.id.to_le_bytes()), field("topic", conn.topic.as_bytes())],
+        &data,
+    )
+}
+
+/// Options of `bag2rrd convert ARGS`, parsed as on the command line
+pub fn convert_options(args: &[&str]) -> anyhow::Result<crate::convert::ConvertOptions> {
+    use clap::Parser;
+
+    let cli = crate::cli::Cli::try_parse_from(["bag2rrd", "convert"].iter().chain(args))?;
+    let crate::cli::Commands::Convert(args) = cli.command else {
+        unreachable!()
+    };
+    args.into_options()
+}
+
+/// Run `bag2rrd convert ARGS`, e.g. `run_convert(&[bag, out, "--split-topics"])`
+pub fn run_convert(args: &[&str]) -> anyhow::Result<()> {
+    crate::convert::convert_bag(&convert_options(args)?)
+}
+
+/// Write a bag with one chunk per entry of `chunks`, each defining the connections it uses
+pub fn write_bag(path: &Path, connections: &[TestConnection], chunks: &[Vec<TestMessage>]) {
+    write_bag_compressed(path, connections, chunks, TestCompression::None);
+}
+
+/// Like [`write_bag`], with every chunk's data compressed by `compression`
+pub fn write_bag_compressed(
+    path: &Path,
+    connections: &[TestConnection],
+    chunks: &[Vec<TestMessage>],
+    compression: TestCompression,
+) {
+    let mut body = Vec::new();
+    let mut chunk_infos = Vec::new();
+    let header_len = bag_header(0, connections.len(), chunks.len()).len();
+    let data_start = b"#ROSBAG V2.0\n".len() + header_len;
+    for messages in chunks {
+        let mut data = Vec::new();
+        let mut counts: Vec<(u32, u32)> = Vec::new();
+        for msg in messages {
+            match counts.iter_mut().find(|(conn, _)| *conn == msg.conn) {
+                Some((_, count)) => *count += 1,
+                None => {
+                    let conn = connections.iter().find(|c| c.id == msg.conn).expect("unknown connection");
+                    data.extend(connection_record(conn));
+                    counts.push((msg.conn, 1));
+                }
+            }
+            data.extend(record(
+                &[field("op", &[0x02]), field("conn", &msg.conn.to_le_bytes()), time_field("time", msg.time_ns)],
+                &msg.data,
+            ));
+        }
+        let chunk_pos = (data_start + body.len()) as u64;
+        // "size" is the uncompressed length of the chunk data
+        body.extend(record(
+            &[
+                field("op", &[0x05]),
+                field("compression", compression.name().as_bytes()),
+                field("size", &(data.len() as u32).to_le_bytes()),
+            ],
+            &compression.compress(&data),
+        ));
+        let start = messages.iter().map(|m| m.time_ns).min().unwrap_or(0);
+        let end = messages.iter().map(|m| m.time_ns).max().unwrap_or(0);
+        let entries: Vec<u8> = counts
+            .iter()
+            .flat_map(|(conn, count)| [conn.to_le_bytes(), count.to_le_bytes()].concat())
+            .collect();
+        chunk_infos.push(record(
+            &[
+                field("op", &[0x06]),
+                field("ver", &1u32.to_le_bytes()),
+                field("chunk_pos", &chunk_pos.to_le_bytes()),
+                time_field("start_time", start),
+                time_field("end_time", end),
+                field("count", &(counts.len() as u32).to_le_bytes()),
+            ],
+            &entries,
+        ));
+    }
+    let index_pos = (data_start + body.len()) as u64;
+
+    let mut bag = b"#ROSBAG V2.0\n".to_vec();
+    bag.extend(bag_header(index_pos, connections.len(), chunks.len()));
+    bag.extend(body);
+    for conn in connections {
+        bag.extend(connection_record(conn));
+    }
+    for info in chunk_infos {
+        bag.extend(info);
+    }
+    std::fs::write(path, bag).unwrap();
+}
+
+fn bag_header(index_pos: u64, conn_count: usize, chunk_count: usize) -> Vec<u8> {
+    record(
+        &[
+            field("op", &[0x03]),
+            field("index_pos", &index_pos.to_le_bytes()),
+            field("conn_count", &(conn_count as u32).to_le_bytes()),
+            field("chunk_count", &(chunk_count as u32).to_le_bytes()),
+        ],
+        &[],
+    )
+}
@@ -0,0 +1,250 @@
+//! analyze-tf command - Detect jumps and discontinuities in the TF edges of a bag
+
+use anyhow::{Context, Result};
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+use rosbag::{ChunkRecord, MessageRecord, RosBag};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::mappings::tf::{parse_tf_message, TransformStamped};
+
+/// Limits between two consecutive samples of the same edge
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct TfThresholds {
+    /// Translation change in meters
+    pub max_translation: f64,
+    /// Rotation change in degrees
+    pub max_rotation_deg: f64,
+}
+
+impl Default for TfThresholds {
+    fn default() -> Self {
+        Self { max_translation: 0.5, max_rotation_deg: 30.0 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TfAnomalyKind {
+    /// Translation changed by more than the threshold (magnitude in meters)
+    PositionJump,
+    /// Rotation changed by more than the threshold (magnitude in degrees)
+    RotationJump,
+    /// Quaternion sign flipped while describing (almost) the same rotation (magnitude in degrees)
+    QuaternionFlip,
+    /// header.stamp earlier than the previous sample (magnitude in seconds)
+    TimeBackwards,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct TfAnomaly {
+    pub parent: String,
+    pub child: String,
+    pub kind: TfAnomalyKind,
+    /// header.stamp of the offending sample (bag time when unstamped), seconds since epoch
+    pub stamp: f64,
+    /// Bag record time of the message, seconds since epoch
+    pub bag_time: f64,
+    pub magnitude: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TfReport {
+    /// Number of dynamic transforms checked
+    pub transforms: u64,
+    /// Number of dynamic parent → child edges
+    pub edges: usize,
+    pub anomalies: Vec<TfAnomaly>,
+}
+
+impl TfReport {
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "TF analysis: {} transforms on {} edges, {} anomalies\n",
+            self.transforms,
+            self.edges,
+            self.anomalies.len()
+        );
+        for a in &self.anomalies {
+            let magnitude = match a.kind {
+                TfAnomalyKind::PositionJump => format!("{:.3} m", a.magnitude),
+                TfAnomalyKind::RotationJump | TfAnomalyKind::QuaternionFlip => format!("{:.1} deg", a.magnitude),
+                TfAnomalyKind::TimeBackwards => format!("{:.6} s", a.magnitude),
+            };
+            out.push_str(&format!(
+                "  {:.6}  {} -> {}  {:?}  {}\n",
+                a.stamp, a.parent, a.child, a.kind, magnitude
+            ));
+        }
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct LastSample {
+    stamp: f64,
+    trans: Vector3<f64>,
+    quat: Quaternion<f64>,
+}
+
+/// Compares each dynamic transform with the previous sample of the same edge
+#[derive(Debug, Default)]
+pub struct TfJumpDetector {
+    thresholds: TfThresholds,
+    last: BTreeMap<(String, String), LastSample>,
+    report: TfReport,
+}
+
+impl TfJumpDetector {
+    pub fn new(thresholds: TfThresholds) -> Self {
+        Self { thresholds, ..Default::default() }
+    }
+
+    /// Check one /tf transform received at `bag_time`
+    pub fn push(&mut self, tf: &TransformStamped, bag_time: f64) {
+        let stamp = if tf.header.stamp > 0.0 { tf.header.stamp } else { bag_time };
+        let t = tf.transform.translation;
+        let r = tf.transform.rotation;
+        let sample = LastSample {
+            stamp,
+            trans: Vector3::new(t.x, t.y, t.z),
+            quat: Quaternion::new(r.w, r.x, r.y, r.z).normalize(),
+        };
+        self.report.transforms += 1;
+        let key = (tf.header.frame_id.clone(), tf.child_frame_id.clone());
+        let Some(prev) = self.last.insert(key, sample) else {
+            return;
+        };
+
+        let mut found = Vec::new();
+        if stamp < prev.stamp {
+            found.push((TfAnomalyKind::TimeBackwards, prev.stamp - stamp));
+        }
+        let distance = (sample.trans - prev.trans).norm();
+        if distance > self.thresholds.max_translation {
+            found.push((TfAnomalyKind::PositionJump, distance));
+        }
+        let angle = UnitQuaternion::new_unchecked(prev.quat)
+            .angle_to(&UnitQuaternion::new_unchecked(sample.quat))
+            .to_degrees();
+        if angle > self.thresholds.max_rotation_deg {
+            found.push((TfAnomalyKind::RotationJump, angle));
+        } else if prev.quat.dot(&sample.quat) < 0.0 {
+            found.push((TfAnomalyKind::QuaternionFlip, angle));
+        }
+        for (kind, magnitude) in found {
+            self.report.anomalies.push(TfAnomaly {
+                parent: tf.header.frame_id.clone(),
+                child: tf.child_frame_id.clone(),
+                kind,
+                stamp,
+                bag_time,
+                magnitude,
+            });
+        }
+    }
+
+    pub fn finish(mut self) -> TfReport {
+        self.report.edges = self.last.len();
+        self.report
+    }
+}
+
+/// Scan the dynamic TF topics of a bag for discontinuities
+pub fn analyze_tf(path: &str, thresholds: TfThresholds) -> Result<TfReport> {
+    let bag = RosBag::new(path).with_context(|| format!("failed to open bag: {}", path))?;
+
+    // Dynamic TF connections only; static transforms never change
+    let mut tf_conns: HashSet<u32> = HashSet::new();
+    for record in bag.chunk_records() {
+        if let ChunkRecord::Chunk(chunk) = record? {
+            for msg in chunk.messages() {
+                if let MessageRecord::Connection(conn) = msg?
+                    && matches!(conn.tp, "tf2_msgs/TFMessage" | "tf/tfMessage")
+                    && conn.topic.trim_start_matches('/') != "tf_static"
+                    && !conn.latching
+                {
+                    tf_conns.insert(conn.id);
+                }
+            }
+        }
+    }
+
+    let mut detector = TfJumpDetector::new(thresholds);
+    for record in bag.chunk_records() {
+        if let ChunkRecord::Chunk(chunk) = record? {
+            for msg in chunk.messages() {
+                if let MessageRecord::MessageData(msg_data) = msg?
+                    && tf_conns.contains(&msg_data.conn_id)
+                {
+                    let bag_time = msg_data.time as f64 / 1_000_000_000.0;
+                    match parse_tf_message(msg_data.data) {
+                        Ok(transforms) => transforms.iter().for_each(|tf| detector.push(tf, bag_time)),
+                        Err(e) => tracing::warn!("Failed to parse TF message: {}; skipping", e),
+                    }
+                }
+            }
+        }
+    }
+    Ok(detector.finish())
+}
+
+/// Print the TF anomaly report and optionally write it as JSON
+pub fn print_tf_analysis(path: &str, thresholds: TfThresholds, json: Option<&str>) -> Result<()> {
+    let report = analyze_tf(path, thresholds)?;
+    print!("{}", report.to_text());
+    if let Some(json_path) = json {
+        let file = std::fs::File::create(json_path).with_context(|| format!("failed to create {}", json_path))?;
+        serde_json::to_writer_pretty(file, &report)?;
+        println!("Wrote JSON report to {}", json_path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mappings::tf::{Header, RosQuaternion, Transform, Vector3 as RosVector3};
+
+    fn tf(stamp: f64, x: f64, quat: [f64; 4]) -> TransformStamped {
+        TransformStamped {
+            header: Header { stamp, frame_id: "odom".to_string() },
+            child_frame_id: "base_link".to_string(),
+            transform: Transform {
+                translation: RosVector3 { x, y: 0.0, z: 0.0 },
+                rotation: RosQuaternion { x: quat[0], y: quat[1], z: quat[2], w: quat[3] },
+            },
+        }
+    }
+
+    #[test]
+    fn test_detects_jumps_flips_and_time_reversal() {
+        let identity = [0.0, 0.0, 0.0, 1.0];
+        let mut detector = TfJumpDetector::new(TfThresholds::default());
+        detector.push(&tf(10.0, 0.0, identity), 10.0);
+        detector.push(&tf(10.1, 0.1, identity), 10.1);
+        // Teleport 5 m forward
+        detector.push(&tf(10.2, 5.1, identity), 10.2);
+        // Same rotation, negated quaternion
+        detector.push(&tf(10.3, 5.1, [0.0, 0.0, 0.0, -1.0]), 10.3);
+        // 90° yaw in one step, stamped in the past
+        let s = std::f64::consts::FRAC_1_SQRT_2;
+        detector.push(&tf(10.25, 5.1, [0.0, 0.0, s, s]), 10.4);
+        let report = detector.finish();
+        assert_eq!(report.transforms, 5);
+        assert_eq!(report.edges, 1);
+        let kinds: Vec<TfAnomalyKind> = report.anomalies.iter().map(|a| a.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                TfAnomalyKind::PositionJump,
+                TfAnomalyKind::QuaternionFlip,
+                TfAnomalyKind::TimeBackwards,
+                TfAnomalyKind::RotationJump,
+            ]
+        );
+        assert!((report.anomalies[0].magnitude - 5.0).abs() < 1e-9);
+        assert!((report.anomalies[3].magnitude - 90.0).abs() < 1e-6);
+        assert!(report.to_text().contains("odom -> base_link  PositionJump  5.000 m"));
+    }
+}
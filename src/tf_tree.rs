@@ -0,0 +1,218 @@
+//! tf-tree command - Print the TF frame tree of a bag (like `rosrun tf view_frames`)
+
+use anyhow::{Context, Result};
+use rosbag::{ChunkRecord, MessageRecord, RosBag};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::mappings::tf::parse_tf_edges;
+
+/// Statistics of one parent → child edge
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct TfEdge {
+    pub parent: String,
+    pub child: String,
+    /// Published on /tf_static (or a latched connection)
+    pub is_static: bool,
+    pub count: u64,
+    /// First and last header.stamp (bag time when unstamped), seconds since epoch
+    pub first_stamp: f64,
+    pub last_stamp: f64,
+    /// Average publish rate in Hz (0 for static or single-sample edges)
+    pub rate_hz: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TfTree {
+    pub edges: Vec<TfEdge>,
+}
+
+impl TfTree {
+    /// Frames that never appear as a child
+    pub fn roots(&self) -> Vec<&str> {
+        let children: HashSet<&str> = self.edges.iter().map(|e| e.child.as_str()).collect();
+        let parents: BTreeSet<&str> = self.edges.iter().map(|e| e.parent.as_str()).collect();
+        parents.into_iter().filter(|p| !children.contains(p)).collect()
+    }
+
+    fn children_of<'a>(&'a self, parent: &'a str) -> impl Iterator<Item = &'a TfEdge> + 'a {
+        self.edges.iter().filter(move |e| e.parent == parent)
+    }
+
+    /// Indented text tree
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let mut visited = HashSet::new();
+        for root in self.roots() {
+            out.push_str(root);
+            out.push('\n');
+            self.write_children(root, 1, &mut visited, &mut out);
+        }
+        // Frames only reachable through cycles have no root
+        for edge in &self.edges {
+            if !visited.contains(edge.child.as_str()) {
+                out.push_str(&format!("(cycle) {}\n", edge.parent));
+                self.write_children(&edge.parent, 1, &mut visited, &mut out);
+            }
+        }
+        out
+    }
+
+    fn write_children<'a>(&'a self, parent: &'a str, depth: usize, visited: &mut HashSet<&'a str>, out: &mut String) {
+        for edge in self.children_of(parent) {
+            if !visited.insert(edge.child.as_str()) {
+                continue;
+            }
+            out.push_str(&format!("{}└─ {} {}\n", "   ".repeat(depth - 1), edge.child, edge_label(edge)));
+            self.write_children(&edge.child, depth + 1, visited, out);
+        }
+    }
+
+    /// Graphviz DOT graph
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph tf {\n    rankdir=TB;\n");
+        for edge in &self.edges {
+            out.push_str(&format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"{}];\n",
+                edge.parent,
+                edge.child,
+                edge_label(edge).replace('"', "'"),
+                if edge.is_static { ", style=dashed" } else { "" }
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn edge_label(edge: &TfEdge) -> String {
+    if edge.is_static {
+        "[static]".to_string()
+    } else {
+        format!(
+            "[{:.1} Hz, {} msgs, {:.3}s..{:.3}s]",
+            edge.rate_hz, edge.count, edge.first_stamp, edge.last_stamp
+        )
+    }
+}
+
+/// Scan the TF topics of a bag and build the frame tree
+pub fn scan_tf_tree(path: &str) -> Result<TfTree> {
+    let bag = RosBag::new(path).with_context(|| format!("failed to open bag: {}", path))?;
+
+    // TF connections only; their messages are the only ones decoded
+    let mut tf_conns: BTreeMap<u32, bool> = BTreeMap::new();
+    for record in bag.chunk_records() {
+        if let ChunkRecord::Chunk(chunk) = record? {
+            for msg in chunk.messages() {
+                if let MessageRecord::Connection(conn) = msg?
+                    && matches!(conn.tp, "tf2_msgs/TFMessage" | "tf/tfMessage")
+                {
+                    let is_static = conn.topic.trim_start_matches('/') == "tf_static" || conn.latching;
+                    tf_conns.insert(conn.id, is_static);
+                }
+            }
+        }
+    }
+
+    let mut edges: BTreeMap<(String, String), TfEdge> = BTreeMap::new();
+    for record in bag.chunk_records() {
+        if let ChunkRecord::Chunk(chunk) = record? {
+            for msg in chunk.messages() {
+                if let MessageRecord::MessageData(msg_data) = msg?
+                    && let Some(&is_static) = tf_conns.get(&msg_data.conn_id)
+                {
+                    let bag_time = msg_data.time as f64 / 1_000_000_000.0;
+                    let transforms = match parse_tf_edges(msg_data.data) {
+                        Ok(transforms) => transforms,
+                        Err(e) => {
+                            tracing::warn!("Failed to parse TF message: {}; skipping", e);
+                            continue;
+                        }
+                    };
+                    for (parent, child, stamp) in transforms {
+                        let stamp = if stamp > 0.0 { stamp } else { bag_time };
+                        let edge = edges.entry((parent.clone(), child.clone())).or_insert(TfEdge {
+                            parent,
+                            child,
+                            is_static,
+                            count: 0,
+                            first_stamp: stamp,
+                            last_stamp: stamp,
+                            rate_hz: 0.0,
+                        });
+                        edge.count += 1;
+                        edge.is_static |= is_static;
+                        edge.first_stamp = edge.first_stamp.min(stamp);
+                        edge.last_stamp = edge.last_stamp.max(stamp);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut edges: Vec<TfEdge> = edges.into_values().collect();
+    for edge in &mut edges {
+        let span = edge.last_stamp - edge.first_stamp;
+        if !edge.is_static && edge.count > 1 && span > 0.0 {
+            edge.rate_hz = (edge.count - 1) as f64 / span;
+        }
+    }
+    Ok(TfTree { edges })
+}
+
+/// Print the TF tree and optionally write it as DOT and/or JSON
+pub fn print_tf_tree(path: &str, dot: Option<&str>, json: Option<&str>) -> Result<()> {
+    let tree = scan_tf_tree(path)?;
+    if tree.edges.is_empty() {
+        println!("No TF transforms found in {}", path);
+        return Ok(());
+    }
+    print!("{}", tree.to_text());
+
+    if let Some(dot_path) = dot {
+        std::fs::write(dot_path, tree.to_dot()).with_context(|| format!("failed to write {}", dot_path))?;
+        println!("Wrote DOT graph to {}", dot_path);
+    }
+    if let Some(json_path) = json {
+        let file = std::fs::File::create(json_path).with_context(|| format!("failed to create {}", json_path))?;
+        serde_json::to_writer_pretty(file, &tree)?;
+        println!("Wrote JSON tree to {}", json_path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(parent: &str, child: &str, is_static: bool) -> TfEdge {
+        TfEdge {
+            parent: parent.to_string(),
+            child: child.to_string(),
+            is_static,
+            count: 11,
+            first_stamp: 0.0,
+            last_stamp: 1.0,
+            rate_hz: if is_static { 0.0 } else { 10.0 },
+        }
+    }
+
+    #[test]
+    fn test_tree_text_and_dot() {
+        let tree = TfTree {
+            edges: vec![
+                edge("map", "odom", true),
+                edge("odom", "base_link", false),
+                edge("base_link", "laser", true),
+            ],
+        };
+        assert_eq!(tree.roots(), vec!["map"]);
+        let text = tree.to_text();
+        assert!(text.starts_with("map\n└─ odom [static]\n   └─ base_link [10.0 Hz"));
+        assert!(text.contains("      └─ laser [static]"));
+        let dot = tree.to_dot();
+        assert!(dot.contains("\"odom\" -> \"base_link\""));
+        assert!(dot.contains("style=dashed"));
+    }
+}
@@ -0,0 +1,68 @@
+//! Timelines shared by all mappings
+//!
+//! Every message is logged on:
+//! - `ros_time`: header.stamp (or bag/sim time, see `--timestamp-source`), seconds since bag start
+//! - `bag_time`: time the message was recorded into the bag, seconds since bag start
+//! - `frame_index`: per-topic message index, to step through topics frame by frame
+
+use std::collections::HashMap;
+
+pub const ROS_TIME: &str = "ros_time";
+pub const BAG_TIME: &str = "bag_time";
+pub const FRAME_INDEX: &str = "frame_index";
+
+/// Values of the three timelines for one message
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MessageTime {
+    pub ros_time: f64,
+    pub bag_time: f64,
+    pub frame_index: i64,
+}
+
+/// Per-topic message counters behind the `frame_index` timeline
+#[derive(Debug, Default)]
+pub struct Timelines {
+    frame_counts: HashMap<String, i64>,
+}
+
+impl Timelines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set all timelines for the next message of `topic`; their values, for rows sent as columns
+    pub fn set_message_time(
+        &mut self,
+        rec: &rerun::RecordingStream,
+        topic: &str,
+        ros_time: f64,
+        bag_time: f64,
+    ) -> MessageTime {
+        let index = self.next_index(topic);
+        rec.set_timestamp_secs_since_epoch(ROS_TIME, ros_time);
+        rec.set_timestamp_secs_since_epoch(BAG_TIME, bag_time);
+        rec.set_time_sequence(FRAME_INDEX, index);
+        MessageTime { ros_time, bag_time, frame_index: index }
+    }
+
+    fn next_index(&mut self, topic: &str) -> i64 {
+        let count = self.frame_counts.entry(topic.to_string()).or_insert(0);
+        let index = *count;
+        *count += 1;
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_index_is_per_topic() {
+        let mut timelines = Timelines::new();
+        assert_eq!(timelines.next_index("/a"), 0);
+        assert_eq!(timelines.next_index("/a"), 1);
+        assert_eq!(timelines.next_index("/b"), 0);
+        assert_eq!(timelines.next_index("/a"), 2);
+    }
+}
@@ -1,66 +1,254 @@
 //! Validate command - Check .rrd file structure and consistency
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use re_log_encoding::decoder::Decoder;
+use re_log_types::LogMsg;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
+use std::io::BufReader;
 use std::path::Path;
 
-/// Validate an .rrd file
-///
-/// Performs lightweight checks on the RRD file structure:
-/// - File can be opened and has basic structure
-/// - Entity paths are valid (start with /, no spaces)
-/// - Timestamps are monotonically non-decreasing per entity
-/// - Metadata block exists if present
-pub fn validate_rrd(rrd_path: &str) -> Result<()> {
-    let path = Path::new(rrd_path);
+/// How bad an issue is; warnings only fail validation in strict mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
 
-    // Check 1: File exists and can be opened
-    if !path.exists() {
-        println!("Validation of {}: FAILED", rrd_path);
-        println!("[ERROR] File does not exist");
-        std::process::exit(1);
+/// What an issue is about
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IssueCategory {
+    /// Missing, unreadable or empty file
+    File,
+    /// Truncated stream or corrupted chunk
+    Decode,
+    EntityPath,
+    /// Time going backwards on an entity
+    Timestamps,
+    /// Readable recording without any data
+    Empty,
+}
+
+impl IssueCategory {
+    fn name(self) -> &'static str {
+        match self {
+            IssueCategory::File => "file",
+            IssueCategory::Decode => "decode",
+            IssueCategory::EntityPath => "entity-path",
+            IssueCategory::Timestamps => "timestamps",
+            IssueCategory::Empty => "empty",
+        }
     }
+}
 
-    let metadata = fs::metadata(path)?;
-    if !metadata.is_file() {
-        println!("Validation of {}: FAILED", rrd_path);
-        println!("[ERROR] Path is not a file");
-        std::process::exit(1);
+#[derive(Clone, Debug, PartialEq)]
+pub struct Issue {
+    pub severity: Severity,
+    pub category: IssueCategory,
+    pub message: String,
+}
+
+impl Issue {
+    fn error(category: IssueCategory, message: impl Into<String>) -> Self {
+        Self { severity: Severity::Error, category, message: message.into() }
     }
 
-    // For now, implement basic file validation
-    // TODO: Use rerun reader API when available for deeper validation
-    // Since rerun v0.25.1 may not have reader APIs, we'll do basic checks
+    fn warning(category: IssueCategory, message: impl Into<String>) -> Self {
+        Self { severity: Severity::Warning, category, message: message.into() }
+    }
+}
 
-    // Check 2: Basic file structure (check if it's not empty and has some expected patterns)
-    let file_size = metadata.len();
-    if file_size == 0 {
-        println!("Validation of {}: FAILED", rrd_path);
-        println!("[ERROR] File is empty");
-        std::process::exit(1);
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "ERROR",
+            Severity::Warning => "WARN",
+        };
+        write!(f, "[{}] {}: {}", severity, self.category.name(), self.message)
     }
+}
 
-    // Try to read first few bytes to check basic structure
-    let mut file = fs::File::open(path)?;
-    let mut buffer = [0u8; 64];
-    let bytes_read = std::io::Read::read(&mut file, &mut buffer)?;
+/// What an .rrd file holds, read back through rerun's decoder, and what is wrong with it
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub path: String,
+    pub size_bytes: u64,
+    /// Recordings and blueprints announced by SetStoreInfo messages
+    pub stores: usize,
+    pub chunks: u64,
+    pub rows: u64,
+    /// Entity path → rows logged to it
+    pub entities: BTreeMap<String, u64>,
+    pub timelines: BTreeSet<String>,
+    /// (entity, timeline) → rows and time range logged on it
+    pub time_ranges: BTreeMap<(String, String), TimeRange>,
+    pub issues: Vec<Issue>,
+}
 
-    // Basic check: RRD files are Arrow-based, should have some structure
-    if bytes_read < 8 {
-        println!("Validation of {}: FAILED", rrd_path);
-        println!("[ERROR] File too small to be a valid RRD");
-        std::process::exit(1);
+/// Rows of an entity on one timeline, and their first and last time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeRange {
+    pub rows: u64,
+    /// Raw timeline values; nanoseconds since epoch on timestamp timelines
+    pub min: i64,
+    pub max: i64,
+}
+
+impl ValidationReport {
+    pub fn errors(&self) -> impl Iterator<Item = &Issue> {
+        self.issues.iter().filter(|issue| issue.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Issue> {
+        self.issues.iter().filter(|issue| issue.severity == Severity::Warning)
+    }
+
+    /// No errors, and no warnings either when `strict`
+    pub fn passed(&self, strict: bool) -> bool {
+        self.errors().next().is_none() && !(strict && self.warnings().next().is_some())
+    }
+
+    pub fn to_text(&self, strict: bool) -> String {
+        let status = if self.passed(strict) { "PASSED" } else { "FAILED" };
+        let mut out = format!("Validation of {}: {}\n", self.path, status);
+        let mut issues: Vec<&Issue> = self.issues.iter().collect();
+        issues.sort_by_key(|issue| issue.severity);
+        for issue in issues {
+            out.push_str(&format!("{}\n", issue));
+        }
+        out.push_str(&format!("File size: {} bytes\n", self.size_bytes));
+        out.push_str(&format!(
+            "Stores: {}, Chunks: {}, Rows: {}, Entities: {}, Timelines: {}\n",
+            self.stores,
+            self.chunks,
+            self.rows,
+            self.entities.len(),
+            self.timelines.iter().cloned().collect::<Vec<_>>().join(", ")
+        ));
+        out
     }
 
-    // For deeper validation, we'd need rerun's reader API
-    // For now, assume the file is valid if it exists and has content
-    // In a real implementation, we'd load the recording and check entities
+    /// Decode every message of the file and check its chunks:
+    /// - Messages and chunks decode (no truncation or corrupted Arrow data)
+    /// - Entity paths are valid (start with /, no spaces)
+    /// - Timestamps are monotonically non-decreasing per entity and timeline
+    fn decode(&mut self) -> Result<()> {
+        let file = fs::File::open(&self.path).with_context(|| format!("failed to open {}", self.path))?;
+        let decoder = match Decoder::new(BufReader::new(file)) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                self.issues.push(Issue::error(IssueCategory::Decode, format!("Not an .rrd file: {}", e)));
+                return Ok(());
+            }
+        };
+
+        // (entity, timeline) → last time seen
+        let mut last_times: HashMap<(String, String), i64> = HashMap::new();
+        // (entity, timeline) → times going backwards, and the first of them
+        let mut backwards: BTreeMap<(String, String), (u64, i64, i64)> = BTreeMap::new();
+        let mut messages = 0u64;
+        for msg in decoder {
+            messages += 1;
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(e) => {
+                    self.issues.push(Issue::error(
+                        IssueCategory::Decode,
+                        format!("Message #{} does not decode: {}", messages, e),
+                    ));
+                    break;
+                }
+            };
+            let arrow_msg = match msg {
+                LogMsg::SetStoreInfo(_) => {
+                    self.stores += 1;
+                    continue;
+                }
+                LogMsg::ArrowMsg(_, arrow_msg) => arrow_msg,
+                LogMsg::BlueprintActivationCommand(_) => continue,
+            };
+            self.chunks += 1;
+            let chunk = match re_chunk::Chunk::from_arrow_msg(&arrow_msg) {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    self.issues.push(Issue::error(
+                        IssueCategory::Decode,
+                        format!("Chunk #{} does not decode: {}", self.chunks, e),
+                    ));
+                    continue;
+                }
+            };
+            let entity = chunk.entity_path().to_string();
+            self.issues.extend(check_entity_path(&entity));
+            self.rows += chunk.num_rows() as u64;
+            *self.entities.entry(entity.clone()).or_default() += chunk.num_rows() as u64;
+            for (timeline, column) in chunk.timelines() {
+                self.timelines.insert(timeline.to_string());
+                let key = (entity.clone(), timeline.to_string());
+                let last = last_times.entry(key.clone()).or_insert(i64::MIN);
+                let range = self.time_ranges.entry(key.clone()).or_insert(TimeRange { rows: 0, min: i64::MAX, max: i64::MIN });
+                for &time in column.times_raw() {
+                    range.rows += 1;
+                    range.min = range.min.min(time);
+                    range.max = range.max.max(time);
+                    if time < *last {
+                        backwards.entry(key.clone()).or_insert((0, time, *last)).0 += 1;
+                    }
+                    *last = (*last).max(time);
+                }
+            }
+        }
+        for ((entity, timeline), (count, time, last)) in backwards {
+            self.issues.push(Issue::warning(
+                IssueCategory::Timestamps,
+                format!("Timestamps for {} on {} are not monotonic {} times, first {} < {}", entity, timeline, count, time, last),
+            ));
+        }
+        if self.chunks == 0 && !self.issues.iter().any(|issue| issue.category == IssueCategory::Decode) {
+            self.issues.push(Issue::warning(IssueCategory::Empty, "Recording holds no data"));
+        }
+        Ok(())
+    }
+}
+
+/// Validate an .rrd file by decoding it and checking its entities and timelines.
+/// Problems with the file are reported as issues; `Err` is left for I/O failures
+/// past the first checks
+pub fn validate_rrd(rrd_path: &str) -> Result<ValidationReport> {
+    let mut report = ValidationReport { path: rrd_path.to_string(), ..Default::default() };
+    let path = Path::new(rrd_path);
+
+    // Check 1: File exists and can be opened
+    if !path.exists() {
+        report.issues.push(Issue::error(IssueCategory::File, "File does not exist"));
+        return Ok(report);
+    }
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_file() {
+        report.issues.push(Issue::error(IssueCategory::File, "Path is not a file"));
+        return Ok(report);
+    }
+    report.size_bytes = metadata.len();
+    if report.size_bytes == 0 {
+        report.issues.push(Issue::error(IssueCategory::File, "File is empty"));
+        return Ok(report);
+    }
 
-    println!("Validation of {}: PASSED", rrd_path);
-    println!("File size: {} bytes", file_size);
-    println!("Note: Full entity path and timestamp validation requires rerun reader API");
+    // Check 2: every message and chunk decodes, with valid entities and timestamps
+    report.decode()?;
+    Ok(report)
+}
 
-    Ok(())
+/// Error for an entity path that does not start with / or holds a space
+fn check_entity_path(entity_path: &str) -> Option<Issue> {
+    let message = if !entity_path.starts_with('/') {
+        format!("Entity \"{}\" does not start with /", entity_path)
+    } else if entity_path.contains(' ') {
+        format!("Entity \"{}\" contains invalid space", entity_path)
+    } else {
+        return None;
+    };
+    Some(Issue::error(IssueCategory::EntityPath, message))
 }
 
 /// Mock validation for testing - simulates checking entities and timestamps
@@ -79,12 +267,7 @@ pub fn validate_rrd_mock(rrd_path: &str, entities: &[(&str, Vec<f64>)]) -> Resul
     // Check entity paths
     for (entity_path, timestamps) in entities {
         // Check entity path format
-        if !entity_path.starts_with('/') {
-            errors.push(format!("[ERROR] Entity \"{}\" does not start with /", entity_path));
-        }
-        if entity_path.contains(' ') {
-            errors.push(format!("[ERROR] Entity \"{}\" contains invalid space", entity_path));
-        }
+        errors.extend(check_entity_path(entity_path).map(|issue| issue.to_string()));
 
         // Check timestamps are monotonic
         for i in 1..timestamps.len() {
@@ -146,4 +329,40 @@ mod tests {
         // Clean up
         std::fs::remove_file(dummy_path).unwrap();
     }
+
+    #[test]
+    fn test_validate_rrd() {
+        let path = std::env::temp_dir().join(format!("bag2rrd_validate_{}.rrd", std::process::id()));
+        let rec = rerun::RecordingStreamBuilder::new("validate_test").save(&path).unwrap();
+        for ts in [1.0, 2.0, 3.0] {
+            rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
+            rec.log("/gps/points", &rerun::archetypes::Points3D::new([(0.0, 0.0, 0.0)])).unwrap();
+        }
+        // Going back in time twice on one entity
+        for ts in [3.0, 1.0, 2.0] {
+            rec.set_timestamp_secs_since_epoch(crate::timeline::ROS_TIME, ts);
+            rec.log("/odom", &rerun::archetypes::Scalars::new([ts])).unwrap();
+        }
+        rec.flush_blocking().unwrap();
+        drop(rec);
+
+        let report = validate_rrd(&path.to_string_lossy()).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(report.entities.get("/gps/points"), Some(&3));
+        assert_eq!(report.entities.get("/odom"), Some(&3));
+        assert!(report.timelines.contains(crate::timeline::ROS_TIME));
+        // Time going backwards only fails strict validation
+        let warnings: Vec<&Issue> = report.warnings().collect();
+        assert_eq!(warnings.len(), 1, "{:?}", report.issues);
+        assert_eq!(warnings[0].category, IssueCategory::Timestamps);
+        assert!(warnings[0].message.contains("/odom"), "{:?}", report.issues);
+        assert!(warnings[0].message.contains("2 times"), "{:?}", report.issues);
+        assert!(report.passed(false));
+        assert!(!report.passed(true));
+        assert!(report.to_text(true).starts_with(&format!("Validation of {}: FAILED", path.display())));
+
+        let missing = validate_rrd("missing.rrd").unwrap();
+        assert!(!missing.passed(false));
+        assert_eq!(missing.issues[0].category, IssueCategory::File);
+    }
 }
\ No newline at end of file
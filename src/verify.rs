@@ -0,0 +1,422 @@
+//! verify command - Cross-check an .rrd against the bag it was converted from
+//!
+//! The bag is read again with the conversion's options: each topic of a mapped
+//! type is expected under the entity --topic-rename gives it, with the messages
+//! its filters, --start/--end, --max-rate and --image-every-nth keep. The rows
+//! logged on the `ros_time` timeline of that entity are compared with them, and
+//! the time span they cover with the span of the kept messages on the same
+//! clock: header stamps, or record times with `--timestamp-source bag`. The
+//! entity of a mapper that logs several sub-entities per message (IMU, GPS path)
+//! counts its fullest one. Segment parts listed in the manifest of a segmented
+//! conversion and the files of split topics are read as one recording.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::Path;
+
+use crate::convert::{header_stamp, ConvertOptions, RateLimiter, TimestampSource, TopicConfig};
+use crate::extract::ExtractInput;
+use crate::filter::MessageFilter;
+use crate::manifest::SegmentManifest;
+use crate::mappings::rename::EntityRenames;
+use crate::timeline::ROS_TIME;
+use crate::validate::{validate_rrd, ValidationReport};
+
+/// Fraction of a topic's time span in the bag its entity must cover
+pub const MIN_COVERAGE: f64 = 0.9;
+
+/// Types logged once per message under the topic's entity
+const MAPPED_TYPES: &[&str] = &[
+    "sensor_msgs/Image",
+    "sensor_msgs/CompressedImage",
+    "sensor_msgs/PointCloud2",
+    "sensor_msgs/LaserScan",
+    "sensor_msgs/MultiEchoLaserScan",
+    "sensor_msgs/NavSatFix",
+    "sensor_msgs/Imu",
+    "nav_msgs/Odometry",
+    "geometry_msgs/PoseStamped",
+    "nav_msgs/Path",
+    "ffmpeg_image_transport_msgs/FFMPEGPacket",
+    "foxglove_msgs/CompressedVideo",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TopicStatus {
+    Ok,
+    /// No entity under the topic
+    Missing,
+    /// Fewer rows than messages
+    Dropped,
+    /// The rows cover less than [`MIN_COVERAGE`] of the topic's span
+    ShortCoverage,
+    /// Not logged per message under its own entity (TF, CameraInfo, other types)
+    NotChecked,
+}
+
+/// One topic of the bag against its entity in the recording
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct TopicCheck {
+    pub topic: String,
+    #[serde(rename = "type")]
+    pub tp: String,
+    pub entity: String,
+    /// Messages in the bag
+    pub expected: u64,
+    /// Rows on the ros_time timeline of the fullest entity under the topic
+    pub logged: u64,
+    /// Logged time span over the topic's span in the bag
+    pub coverage: f64,
+    pub status: TopicStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifyReport {
+    pub bag: String,
+    pub rrd: String,
+    /// Errors of the recording itself (decode errors, truncation)
+    pub rrd_errors: Vec<String>,
+    pub topics: Vec<TopicCheck>,
+}
+
+impl VerifyReport {
+    /// No recording error and every checked topic complete
+    pub fn passed(&self) -> bool {
+        self.rrd_errors.is_empty()
+            && self.topics.iter().all(|t| matches!(t.status, TopicStatus::Ok | TopicStatus::NotChecked))
+    }
+
+    pub fn to_text(&self) -> String {
+        let status = if self.passed() { "PASSED" } else { "FAILED" };
+        let mut out = format!("Verification of {} against {}: {}\n", self.rrd, self.bag, status);
+        for error in &self.rrd_errors {
+            out.push_str(&format!("[ERROR] {}\n", error));
+        }
+        out.push_str(&format!(
+            "\n{:<35} {:<35} {:>9} {:>9} {:>9}  {}\n",
+            "Topic", "Type", "Expected", "Logged", "Coverage", "Status"
+        ));
+        out.push_str(&format!("{}\n", "-".repeat(115)));
+        for topic in &self.topics {
+            let status = match topic.status {
+                TopicStatus::Ok => "ok",
+                TopicStatus::Missing => "MISSING",
+                TopicStatus::Dropped => "DROPPED",
+                TopicStatus::ShortCoverage => "SHORT",
+                TopicStatus::NotChecked => "-",
+            };
+            out.push_str(&format!(
+                "{:<35} {:<35} {:>9} {:>9} {:>8.1}%  {}\n",
+                topic.topic,
+                topic.tp,
+                topic.expected,
+                topic.logged,
+                topic.coverage * 100.0,
+                status
+            ));
+        }
+        out
+    }
+}
+
+/// What the conversion of one topic should have logged
+#[derive(Clone, Debug, PartialEq)]
+struct ExpectedTopic {
+    topic: String,
+    tp: String,
+    /// Entity of the topic, after --topic-rename
+    entity: String,
+    /// Messages in the window; for checked topics, those the conversion logs
+    count: u64,
+    /// Times of the first and last of them on the ros_time clock, in seconds
+    first: f64,
+    last: f64,
+    /// Of a mapped type and kept by the filters
+    checked: bool,
+}
+
+/// Compare the .rrd at `rrd` with the bag at `bag` it was converted from with `options`
+pub fn verify(bag: &str, rrd: &str, options: &ConvertOptions) -> Result<VerifyReport> {
+    let expected = expected_topics(bag, options)?;
+    let groups: BTreeSet<String> = expected
+        .iter()
+        .filter_map(|topic| TopicConfig::resolve(options, &Default::default(), &topic.topic).output_group)
+        .collect();
+    let mut rrd_report = ValidationReport { path: rrd.to_string(), ..Default::default() };
+    for file in recording_files(rrd, &groups)? {
+        let report = validate_rrd(&file)?;
+        rrd_report.issues.extend(report.issues);
+        for (key, range) in report.time_ranges {
+            rrd_report
+                .time_ranges
+                .entry(key)
+                .and_modify(|merged| {
+                    merged.rows += range.rows;
+                    merged.min = merged.min.min(range.min);
+                    merged.max = merged.max.max(range.max);
+                })
+                .or_insert(range);
+        }
+    }
+    Ok(compare(bag, &expected, &rrd_report))
+}
+
+/// Read the bag like the conversion with `options` and count what each topic logs
+fn expected_topics(bag: &str, options: &ConvertOptions) -> Result<Vec<ExpectedTopic>> {
+    let input = ExtractInput::open(bag)?;
+    let conns = input.connections(&[], &[])?;
+    let filter = MessageFilter::new(
+        &options.include_topics,
+        &options.exclude_topics,
+        &options.include_types,
+        &options.exclude_types,
+    )?;
+    let renames = EntityRenames::new(options)?;
+    let latched: HashSet<&str> =
+        input.index.latched.iter().filter_map(|id| conns.get(id)).map(|(topic, _)| topic.as_str()).collect();
+
+    // Topic → type and the record and ros_time times of its messages in the window
+    let mut times: BTreeMap<String, (String, Vec<(f64, f64)>)> = BTreeMap::new();
+    input.messages(&conns, options.start_time, options.end_time, |topic, tp, bag_time, data| {
+        let time = match options.timestamp_source {
+            TimestampSource::Header => header_stamp(tp, data).unwrap_or(bag_time),
+            TimestampSource::Bag => bag_time,
+        };
+        times.entry(topic.to_string()).or_insert_with(|| (tp.to_string(), Vec::new())).1.push((bag_time, time));
+        Ok(())
+    })?;
+
+    Ok(times
+        .into_iter()
+        .map(|(topic, (tp, mut times))| {
+            let config = TopicConfig::resolve(options, &renames.topics, &topic);
+            let checked = MAPPED_TYPES.contains(&tp.as_str()) && filter.allows(&topic, &tp);
+            if checked {
+                times.sort_by(|a, b| a.0.total_cmp(&b.0));
+                // Thinned as the conversion does: --max-rate, then --image-every-nth
+                let mut limiter = RateLimiter::default();
+                times.retain(|(bag_time, _)| latched.contains(topic.as_str()) || limiter.keep(&topic, config.max_rate, *bag_time));
+                if matches!(tp.as_str(), "sensor_msgs/Image" | "sensor_msgs/CompressedImage") {
+                    let mut frame = 0u64;
+                    times.retain(|_| {
+                        frame += 1;
+                        (frame - 1).is_multiple_of(config.image_every_nth)
+                    });
+                }
+            }
+            let first = times.iter().map(|(_, time)| *time).reduce(f64::min).unwrap_or_default();
+            let last = times.iter().map(|(_, time)| *time).reduce(f64::max).unwrap_or_default();
+            ExpectedTopic {
+                entity: config.entity(&topic).to_string(),
+                count: times.len() as u64,
+                first,
+                last,
+                checked,
+                topic,
+                tp,
+            }
+        })
+        .collect())
+}
+
+/// The files a conversion to `rrd` wrote: `rrd` itself, the parts listed in its
+/// segment manifest and the files of the split output `groups`
+fn recording_files(rrd: &str, groups: &BTreeSet<String>) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    if Path::new(rrd).exists() {
+        files.push(rrd.to_string());
+    }
+    let manifest_path = SegmentManifest::path_for(rrd);
+    if manifest_path.exists() {
+        let manifest = SegmentManifest::read(&manifest_path)?;
+        let dir = manifest_path.parent().unwrap_or(Path::new(""));
+        files.extend(manifest.segments.iter().map(|part| dir.join(&part.file).to_string_lossy().into_owned()));
+    }
+    for group in groups {
+        let path = crate::convert::group_output_path(rrd, group);
+        if Path::new(&path).exists() {
+            files.push(path);
+        }
+    }
+    // Reported as a missing file
+    if files.is_empty() {
+        files.push(rrd.to_string());
+    }
+    Ok(files)
+}
+
+fn compare(bag: &str, expected: &[ExpectedTopic], rrd: &ValidationReport) -> VerifyReport {
+    let topics = expected
+        .iter()
+        .map(|topic| {
+            let mut check = TopicCheck {
+                topic: topic.topic.clone(),
+                tp: topic.tp.clone(),
+                entity: topic.entity.clone(),
+                expected: topic.count,
+                logged: 0,
+                coverage: 0.0,
+                status: TopicStatus::NotChecked,
+            };
+            if !topic.checked || topic.count == 0 {
+                return check;
+            }
+            // Fullest entity at or under the topic's path
+            let prefix = format!("{}/", check.entity.trim_end_matches('/'));
+            let range = rrd
+                .time_ranges
+                .iter()
+                .filter(|((entity, timeline), _)| {
+                    timeline == ROS_TIME && (*entity == check.entity || entity.starts_with(&prefix))
+                })
+                .map(|(_, range)| range)
+                .max_by_key(|range| range.rows);
+            let Some(range) = range else {
+                check.status = TopicStatus::Missing;
+                return check;
+            };
+            check.logged = range.rows;
+            let span = topic.last - topic.first;
+            let logged_span = (range.max - range.min) as f64 / 1e9;
+            check.coverage = if span > 0.0 { (logged_span / span).min(1.0) } else { 1.0 };
+            check.status = if check.logged < check.expected {
+                TopicStatus::Dropped
+            } else if check.coverage < MIN_COVERAGE {
+                TopicStatus::ShortCoverage
+            } else {
+                TopicStatus::Ok
+            };
+            check
+        })
+        .collect();
+    VerifyReport {
+        bag: bag.to_string(),
+        rrd: rrd.path.clone(),
+        rrd_errors: rrd.errors().map(|issue| issue.to_string()).collect(),
+        topics,
+    }
+}
+
+/// Print the verification of `rrd` against `bag`, and also write it as JSON
+pub fn print_verify(bag: &str, rrd: &str, options: &ConvertOptions, json: Option<&str>) -> Result<VerifyReport> {
+    let report = verify(bag, rrd, options)?;
+    print!("{}", report.to_text());
+    if let Some(json) = json {
+        let file = std::fs::File::create(json)?;
+        serde_json::to_writer_pretty(file, &report)?;
+        println!("Wrote {}", json);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_bag::{convert_options, run_convert, write_bag, TestConnection, TestMessage};
+    use crate::validate::TimeRange;
+
+    fn topic(name: &str, tp: &str, count: u64, first: f64, last: f64) -> ExpectedTopic {
+        ExpectedTopic {
+            topic: name.to_string(),
+            tp: tp.to_string(),
+            entity: name.to_string(),
+            count,
+            first,
+            last,
+            checked: MAPPED_TYPES.contains(&tp),
+        }
+    }
+
+    fn range(entity: &str, rows: u64, min: f64, max: f64) -> ((String, String), TimeRange) {
+        let key = (entity.to_string(), ROS_TIME.to_string());
+        (key, TimeRange { rows, min: (min * 1e9) as i64, max: (max * 1e9) as i64 })
+    }
+
+    #[test]
+    fn test_compare() {
+        let expected = [
+            topic("/camera/image_raw", "sensor_msgs/Image", 10, 100.0, 101.0),
+            topic("/gps/fix", "sensor_msgs/NavSatFix", 5, 100.0, 104.0),
+            topic("/imu", "sensor_msgs/Imu", 100, 100.0, 101.0),
+            topic("/lidar", "sensor_msgs/PointCloud2", 10, 100.0, 110.0),
+            topic("/tf", "tf2_msgs/TFMessage", 50, 100.0, 101.0),
+        ];
+        let rrd = ValidationReport {
+            path: "run.rrd".to_string(),
+            time_ranges: [
+                range("/camera/image_raw", 10, 100.0, 101.0),
+                // IMU sub-entities, one dropped message on one of them
+                range("/imu/orientation", 100, 100.0, 101.0),
+                range("/imu/angular_velocity", 99, 100.0, 101.0),
+                range("/gps/fix", 4, 100.0, 103.0),
+                range("/lidar", 10, 100.0, 102.0),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let report = compare("run.bag", &expected, &rrd);
+        let statuses: Vec<(&str, TopicStatus)> = report.topics.iter().map(|t| (t.topic.as_str(), t.status)).collect();
+        assert_eq!(
+            statuses,
+            [
+                ("/camera/image_raw", TopicStatus::Ok),
+                ("/gps/fix", TopicStatus::Dropped),
+                ("/imu", TopicStatus::Ok),
+                ("/lidar", TopicStatus::ShortCoverage),
+                ("/tf", TopicStatus::NotChecked),
+            ]
+        );
+        assert_eq!(report.topics[2].logged, 100);
+        assert!((report.topics[3].coverage - 0.2).abs() < 1e-9);
+        assert!(!report.passed());
+
+        let missing = ValidationReport { path: "empty.rrd".to_string(), ..Default::default() };
+        let report = compare("run.bag", &expected, &missing);
+        assert_eq!(report.topics[0].status, TopicStatus::Missing);
+    }
+
+    /// sensor_msgs/NavSatFix stamped `stamp`
+    fn fix_payload(stamp: f64) -> Vec<u8> {
+        let mut payload = 0u32.to_le_bytes().to_vec();
+        payload.extend_from_slice(&(stamp.trunc() as u32).to_le_bytes());
+        payload.extend_from_slice(&((stamp.fract() * 1e9).round() as u32).to_le_bytes());
+        payload.extend_from_slice(&0u32.to_le_bytes()); // frame_id
+        payload.extend_from_slice(&[0, 0, 0, 1]); // status, service
+        for v in [45.5, -73.6, 30.0] {
+            payload.extend_from_slice(&f64::to_le_bytes(v));
+        }
+        payload.extend_from_slice(&[0; 72]); // covariance
+        payload.push(0); // covariance type
+        payload
+    }
+
+    #[test]
+    fn test_verify_converted_bag() {
+        let dir = std::env::temp_dir().join(format!("bag2rrd_verify_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bag = dir.join("run.bag");
+        let connections = [TestConnection { id: 0, topic: "/gps/fix", tp: "sensor_msgs/NavSatFix", latching: false }];
+        // 10 Hz fixes recorded 2 s after their stamps
+        let messages: Vec<TestMessage> = (0..20)
+            .map(|i| TestMessage::new(0, 102.0 + i as f64 / 10.0, fix_payload(100.0 + i as f64 / 10.0)))
+            .collect();
+        write_bag(&bag, &connections, &[messages]);
+        let (bag, rrd) = (bag.to_string_lossy().into_owned(), dir.join("run.rrd").to_string_lossy().into_owned());
+
+        // Renamed and thinned to 5 Hz: 10 rows under /gnss
+        let flags = ["--topic-rename", "/gps/fix=/gnss", "--max-rate", "5"];
+        let args: Vec<&str> = [bag.as_str(), rrd.as_str()].into_iter().chain(flags).collect();
+        run_convert(&args).unwrap();
+        let report = verify(&bag, &rrd, &convert_options(&args).unwrap()).unwrap();
+        assert!(report.passed(), "{}", report.to_text());
+        assert_eq!((report.topics[0].entity.as_str(), report.topics[0].expected, report.topics[0].logged), ("/gnss", 10, 10));
+
+        // Without the conversion's options the topic is looked for at its own path
+        let report = verify(&bag, &rrd, &convert_options(&[&bag, &rrd]).unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(report.topics[0].status, TopicStatus::Missing);
+    }
+}
@@ -0,0 +1,225 @@
+//! watch command - Convert bags as they are finished in a drop folder
+//!
+//! `rosbag record` writes `name.bag.active` and renames it to `name.bag` once
+//! closed, so a rename to .bag means a finished bag. Bags copied in by other
+//! means count as finished once their size has stayed the same for the settle
+//! time. Bags already in the folder are converted at start, except those whose
+//! .rrd exists, and each bag is converted with the same options as `batch`.
+
+use anyhow::{Context, Result};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::batch::{convert_one, output_path, BagResult, BagStatus};
+use crate::convert::ConvertOptions;
+
+/// How often pending bags are checked when no event arrives
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Debug)]
+pub struct WatchOptions {
+    /// Folder watched for .bag files (not its subfolders)
+    pub dir: String,
+    /// Directory receiving one .rrd per bag
+    pub out_dir: String,
+    /// Time a bag's size must stay unchanged before it counts as finished
+    pub settle: Duration,
+}
+
+/// Bags seen in the folder and not converted yet
+#[derive(Debug, Default)]
+struct PendingBags {
+    /// Path → size when last seen, and since when it has that size
+    bags: BTreeMap<PathBuf, (u64, Instant)>,
+    /// Bags renamed from .bag.active, finished by definition
+    closed: Vec<PathBuf>,
+}
+
+impl PendingBags {
+    /// Note a bag whose file changed or appeared
+    fn touch(&mut self, path: PathBuf, size: u64, now: Instant) {
+        match self.bags.get_mut(&path) {
+            Some((known, since)) if *known != size => (*known, *since) = (size, now),
+            Some(_) => {}
+            None => {
+                self.bags.insert(path, (size, now));
+            }
+        }
+    }
+
+    fn closed(&mut self, path: PathBuf) {
+        self.bags.remove(&path);
+        if !self.closed.contains(&path) {
+            self.closed.push(path);
+        }
+    }
+
+    /// Bags finished by `now`, given their current size; they leave the pending set
+    fn take_ready(&mut self, now: Instant, settle: Duration, size_of: impl Fn(&Path) -> Option<u64>) -> Vec<PathBuf> {
+        let mut ready = std::mem::take(&mut self.closed);
+        self.bags.retain(|path, (known, since)| match size_of(path) {
+            // Gone (moved away or deleted) before it settled
+            None => false,
+            Some(size) if size != *known => {
+                (*known, *since) = (size, now);
+                true
+            }
+            Some(_) if now.duration_since(*since) >= settle => {
+                ready.push(path.clone());
+                false
+            }
+            Some(_) => true,
+        });
+        ready
+    }
+}
+
+fn is_bag(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "bag")
+}
+
+fn file_size(path: &Path) -> Option<u64> {
+    std::fs::metadata(path).ok().filter(|m| m.is_file()).map(|m| m.len())
+}
+
+/// Record the .bag files an event is about
+fn on_event(pending: &mut PendingBags, event: Event, now: Instant) {
+    match event.kind {
+        // `.bag.active` → `.bag`: the recorder closed the bag
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            event.paths.into_iter().filter(|p| is_bag(p)).for_each(|p| pending.closed(p));
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            if let [from, to] = event.paths.as_slice()
+                && is_bag(to)
+            {
+                // A .bag renamed from another .bag may still be written to
+                if from.to_string_lossy().ends_with(".bag.active") {
+                    pending.closed(to.clone());
+                } else if let Some(size) = file_size(to) {
+                    pending.touch(to.clone(), size, now);
+                }
+            }
+        }
+        EventKind::Create(_) | EventKind::Modify(_) => {
+            for path in event.paths.into_iter().filter(|p| is_bag(p)) {
+                if let Some(size) = file_size(&path) {
+                    pending.touch(path, size, now);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn report(result: &BagResult) {
+    match result.status {
+        BagStatus::Converted => tracing::info!(
+            bag = %result.bag,
+            output = %result.output,
+            messages = result.messages,
+            seconds = result.seconds,
+            "converted"
+        ),
+        BagStatus::Skipped => tracing::info!(bag = %result.bag, output = %result.output, "output exists, skipped"),
+        BagStatus::Failed | BagStatus::Interrupted => {
+            tracing::error!(bag = %result.bag, "conversion failed: {}", result.error.as_deref().unwrap_or_default())
+        }
+    }
+}
+
+/// Convert the bags finished in `watch.dir`, then each bag finished there until
+/// Ctrl-C or the cancellation token of `template`
+pub fn watch_dir(watch: &WatchOptions, template: &ConvertOptions) -> Result<Vec<BagResult>> {
+    std::fs::create_dir_all(&watch.out_dir).with_context(|| format!("failed to create {}", watch.out_dir))?;
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("failed to start the file watcher")?;
+    watcher
+        .watch(Path::new(&watch.dir), RecursiveMode::NonRecursive)
+        .with_context(|| format!("failed to watch {}", watch.dir))?;
+    tracing::info!(dir = %watch.dir, "watching for finished bags (Ctrl-C to stop)");
+
+    // Bags already there settle like new ones, in case they are still being copied
+    let mut pending = PendingBags::default();
+    let now = Instant::now();
+    for entry in std::fs::read_dir(&watch.dir).with_context(|| format!("failed to list {}", watch.dir))? {
+        let path = entry?.path();
+        if let (true, Some(size)) = (is_bag(&path), file_size(&path)) {
+            pending.touch(path, size, now);
+        }
+    }
+
+    let mut results = Vec::new();
+    while !template.cancelled() {
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) => on_event(&mut pending, event, Instant::now()),
+            Ok(Err(e)) => tracing::warn!("file watcher error: {}", e),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        for bag in pending.take_ready(Instant::now(), watch.settle, file_size) {
+            let bag = bag.to_string_lossy().into_owned();
+            let result = convert_one(&bag, &output_path(&bag, &watch.out_dir), false, template);
+            report(&result);
+            results.push(result);
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::CreateKind;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_bags_settle_or_close() {
+        let settle = Duration::from_secs(5);
+        let start = Instant::now();
+        let at = |s: u64| start + Duration::from_secs(s);
+        let mut pending = PendingBags::default();
+        let sizes: HashMap<PathBuf, u64> =
+            [("/drop/copied.bag", 100), ("/drop/closed.bag", 50)].map(|(p, s)| (PathBuf::from(p), s)).into();
+
+        on_event(
+            &mut pending,
+            Event::new(EventKind::Create(CreateKind::File)).add_path("/drop/notes.txt".into()),
+            start,
+        );
+        // A bag being copied: 10 bytes at first, 100 two seconds later
+        pending.touch("/drop/copied.bag".into(), 10, at(0));
+        assert!(pending.take_ready(at(2), settle, |p| sizes.get(p).copied()).is_empty());
+        // Renamed from .bag.active: finished at once
+        on_event(
+            &mut pending,
+            Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+                .add_path("/drop/closed.bag.active".into())
+                .add_path("/drop/closed.bag".into()),
+            at(3),
+        );
+        assert_eq!(pending.take_ready(at(3), settle, |p| sizes.get(p).copied()), [PathBuf::from("/drop/closed.bag")]);
+        // Unchanged since 2 s: settled at 7 s
+        assert!(pending.take_ready(at(6), settle, |p| sizes.get(p).copied()).is_empty());
+        assert_eq!(pending.take_ready(at(7), settle, |p| sizes.get(p).copied()), [PathBuf::from("/drop/copied.bag")]);
+        assert!(pending.bags.is_empty());
+
+        // A bag deleted before it settled is forgotten
+        pending.touch("/drop/gone.bag".into(), 10, at(8));
+        assert!(pending.take_ready(at(20), settle, |p| sizes.get(p).copied()).is_empty());
+        assert!(pending.bags.is_empty());
+    }
+
+    #[test]
+    fn test_settle_seconds_out_of_range() {
+        use clap::Parser;
+        for settle in ["inf", "NaN", "-1", "1e300"] {
+            let cli = crate::cli::Cli::try_parse_from(["bag2rrd", "watch", "/drop", "--out-dir", "/out", &format!("--settle-seconds={settle}")]).unwrap();
+            let crate::cli::Commands::Watch(args) = cli.command else { panic!("not watch") };
+            assert!(args.into_watch().unwrap_err().to_string().contains("--settle-seconds"), "{settle}");
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! World annotations logged once as static data: a ground grid (--ground-grid),
+//! a north arrow for bags with GPS (--north-arrow), named landmarks from a
+//! YAML file (--landmarks) and the legend of semantic classes (--class-map)
+//!
+//! The class legend is the AnnotationContext of the root entity; everything
+//! else goes under /annotations, in the same space as the ENU points of
+//! the GPS topics: x east, y north, z up, in meters. A landmarks file is a list
+//! of points:
+//!
+//! ```yaml
+//! - name: dock
+//!   position: [12.0, -3.5, 0.0]
+//!   color: [0, 160, 255]
+//! ```
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::convert::ConvertOptions;
+use crate::mappings::classes::ClassMap;
+
+/// Half-width of the grid and spacing of its lines, in meters
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct GroundGrid {
+    pub size: f32,
+    pub spacing: f32,
+}
+
+/// Parse "SIZE[,SPACING]" for --ground-grid (spacing 1 m by default)
+pub fn parse_ground_grid(s: &str) -> Result<GroundGrid> {
+    let (size, spacing) = s.split_once(',').unwrap_or((s, "1"));
+    match (size.trim().parse::<f32>(), spacing.trim().parse::<f32>()) {
+        (Ok(size), Ok(spacing)) if size > 0.0 && spacing > 0.0 && size / spacing <= 1000.0 => {
+            Ok(GroundGrid { size, spacing })
+        }
+        _ => bail!("Invalid ground grid '{}' (expected SIZE[,SPACING] in meters, at most 1000 lines per side)", s),
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Landmark {
+    pub name: String,
+    pub position: [f32; 3],
+    /// RGB, 0-255
+    #[serde(default)]
+    pub color: Option<[u8; 3]>,
+}
+
+/// The annotations asked for by the options
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorldAnnotations {
+    pub grid: Option<GroundGrid>,
+    pub north_arrow: bool,
+    pub landmarks: Vec<Landmark>,
+    pub classes: ClassMap,
+}
+
+impl WorldAnnotations {
+    pub fn load(options: &ConvertOptions) -> Result<Self> {
+        let landmarks = match &options.landmarks {
+            Some(path) => {
+                let text = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path))?;
+                serde_yaml::from_str(&text).with_context(|| format!("invalid landmarks file {}", path))?
+            }
+            None => Vec::new(),
+        };
+        let classes = match &options.class_map {
+            Some(path) => ClassMap::load(path)?,
+            None => ClassMap::default(),
+        };
+        Ok(Self { grid: options.ground_grid, north_arrow: options.north_arrow, landmarks, classes })
+    }
+
+    /// Log the annotations; the north arrow only when the recording has GPS fixes
+    pub fn log(&self, rec: &rerun::RecordingStream, has_gps: bool) -> Result<()> {
+        self.classes.log(rec)?;
+        if let Some(grid) = self.grid {
+            rec.log_static("/annotations/ground_grid", &grid_lines(grid).with_colors([[128, 128, 128, 96]]))?;
+        }
+        if self.north_arrow && has_gps {
+            let length = self.grid.map_or(5.0, |grid| grid.size / 2.0);
+            rec.log_static(
+                "/annotations/north",
+                &rerun::archetypes::Arrows3D::from_vectors([[0.0, length, 0.0]])
+                    .with_colors([[220, 40, 40]])
+                    .with_labels(["N"])
+                    .with_show_labels(true),
+            )?;
+        }
+        if !self.landmarks.is_empty() {
+            let points = rerun::archetypes::Points3D::new(self.landmarks.iter().map(|l| l.position))
+                .with_labels(self.landmarks.iter().map(|l| l.name.as_str()))
+                .with_colors(self.landmarks.iter().map(|l| l.color.unwrap_or([255, 200, 0])))
+                .with_radii([0.3])
+                .with_show_labels(true);
+            rec.log_static("/annotations/landmarks", &points)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lines of the grid in the z = 0 plane, centered on the origin
+fn grid_lines(grid: GroundGrid) -> rerun::archetypes::LineStrips3D {
+    let n = (grid.size / grid.spacing).floor() as i32;
+    let extent = n as f32 * grid.spacing;
+    let strips = (-n..=n).flat_map(|i| {
+        let offset = i as f32 * grid.spacing;
+        [[[offset, -extent, 0.0], [offset, extent, 0.0]], [[-extent, offset, 0.0], [extent, offset, 0.0]]]
+    });
+    rerun::archetypes::LineStrips3D::new(strips.collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_annotations() {
+        assert_eq!(parse_ground_grid("50").unwrap(), GroundGrid { size: 50.0, spacing: 1.0 });
+        assert_eq!(parse_ground_grid("20, 0.5").unwrap(), GroundGrid { size: 20.0, spacing: 0.5 });
+        assert!(parse_ground_grid("0").is_err());
+        assert!(parse_ground_grid("10000,1").is_err());
+
+        let landmarks: Vec<Landmark> =
+            serde_yaml::from_str("- name: dock\n  position: [12.0, -3.5, 0.0]\n  color: [0, 160, 255]\n- name: gate\n  position: [0, 40, 0]\n")
+                .unwrap();
+        assert_eq!(landmarks[0].name, "dock");
+        assert_eq!(landmarks[0].color, Some([0, 160, 255]));
+        assert_eq!(landmarks[1].position, [0.0, 40.0, 0.0]);
+        assert!(serde_yaml::from_str::<Vec<Landmark>>("- name: dock\n  pos: [1, 2, 3]\n").is_err());
+    }
+}
@@ -1,8 +1,11 @@
+// Needs tests/data/race_1.bag: run tests/download_test_bag.sh, then
+// `cargo test --features integration-tests`
+#![cfg(feature = "integration-tests")]
+
 use std::fs;
 use std::path::Path;
 use std::process::Command;
 
-#[cfg(feature = "integration_tests")]
 #[test]
 fn test_inspect_command() {
     // Use the downloaded test bag file
@@ -15,7 +18,7 @@ fn test_inspect_command() {
 
     // Run inspect command
     let output = Command::new("cargo")
-        .args(&["run", "--", "inspect", test_bag_path])
+        .args(["run", "--", "inspect", test_bag_path])
         .output()
         .expect("Failed to run inspect command");
 
@@ -38,7 +41,6 @@ fn test_inspect_command() {
     );
 }
 
-#[cfg(feature = "integration_tests")]
 #[test]
 fn test_convert_command() {
     // Use the downloaded test bag file
@@ -52,7 +54,7 @@ fn test_convert_command() {
 
     // Run convert command
     let output = Command::new("cargo")
-        .args(&["run", "--", "convert", test_bag_path, test_rrd_path])
+        .args(["run", "--", "convert", test_bag_path, test_rrd_path])
         .output()
         .expect("Failed to run convert command");
 
@@ -69,7 +71,6 @@ fn test_convert_command() {
     let _ = fs::remove_file(test_rrd_path);
 }
 
-#[cfg(feature = "integration_tests")]
 #[test]
 fn test_convert_with_filters() {
     // Use the downloaded test bag file
@@ -83,7 +84,7 @@ fn test_convert_with_filters() {
 
     // Run convert command with filters
     let output = Command::new("cargo")
-        .args(&[
+        .args([
             "run",
             "--",
             "convert",
@@ -110,7 +111,6 @@ fn test_convert_with_filters() {
     let _ = fs::remove_file(test_rrd_path);
 }
 
-#[cfg(feature = "integration_tests")]
 #[test]
 fn test_dry_run() {
     // Use the downloaded test bag file
@@ -127,7 +127,7 @@ fn test_dry_run() {
 
     // Run convert command with dry-run
     let output = Command::new("cargo")
-        .args(&[
+        .args([
             "run",
             "--",
             "convert",
@@ -151,12 +151,11 @@ fn test_dry_run() {
     );
 }
 
-#[cfg(feature = "integration_tests")]
 #[test]
 fn test_schema_command() {
     // Run schema command
     let output = Command::new("cargo")
-        .args(&["run", "--", "schema"])
+        .args(["run", "--", "schema"])
         .output()
         .expect("Failed to run schema command");
 
@@ -179,12 +178,11 @@ fn test_schema_command() {
     );
 }
 
-#[cfg(feature = "integration_tests")]
 #[test]
 fn test_validate_command_nonexistent_file() {
     // Run validate command on nonexistent file
     let output = Command::new("cargo")
-        .args(&["run", "--", "validate", "nonexistent.rrd"])
+        .args(["run", "--", "validate", "nonexistent.rrd"])
         .output()
         .expect("Failed to run validate command");
 